@@ -0,0 +1,5 @@
+// 迁移脚本通过 `sqlx::migrate!` 在编译时嵌入二进制文件，
+// 脚本变化时需要重新编译。
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,8 @@
+-- 任务状态变化的历史记录，用于事件流断线重连后的回放
+CREATE TABLE IF NOT EXISTS task_events (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    task_id CHAR(36) NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    created_at DATETIME(3) NOT NULL,
+    INDEX idx_task_events_task_id (task_id)
+);
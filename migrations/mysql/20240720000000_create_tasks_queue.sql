@@ -0,0 +1,9 @@
+-- 尚未处理完成的任务，入队时写入、处理结束后删除，启动时据此恢复队列
+CREATE TABLE IF NOT EXISTS tasks_queue (
+    id CHAR(36) NOT NULL PRIMARY KEY,
+    payload JSON NOT NULL,
+    priority INT NOT NULL,
+    retry_count INT NOT NULL,
+    enqueued_at DATETIME(3) NOT NULL,
+    INDEX idx_tasks_queue_enqueued_at (enqueued_at)
+);
@@ -0,0 +1,11 @@
+-- 死信队列：重试次数用尽后最终失败的任务及其最后一次错误，可通过管理 API 查看并重新入队
+CREATE TABLE IF NOT EXISTS dead_tasks (
+    id CHAR(36) NOT NULL PRIMARY KEY,
+    tenant VARCHAR(64) NOT NULL,
+    payload JSON NOT NULL,
+    priority INT NOT NULL,
+    retry_count INT NOT NULL,
+    error TEXT NOT NULL,
+    failed_at DATETIME(3) NOT NULL,
+    INDEX idx_dead_tasks_failed_at (failed_at)
+);
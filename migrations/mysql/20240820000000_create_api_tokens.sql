@@ -0,0 +1,11 @@
+-- 公开 API 与管理 API 的访问令牌，只保存令牌的 SHA-256 摘要；吊销的令牌保留记录，作为吊销列表
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id CHAR(36) NOT NULL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL,
+    token_hash CHAR(64) NOT NULL,
+    scope VARCHAR(16) NOT NULL,
+    created_at DATETIME(3) NOT NULL,
+    expires_at DATETIME(3) NULL,
+    revoked_at DATETIME(3) NULL,
+    UNIQUE INDEX idx_api_tokens_token_hash (token_hash)
+);
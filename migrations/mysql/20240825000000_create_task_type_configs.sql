@@ -0,0 +1,9 @@
+-- 按任务类型在运行时覆盖的调度配置（通过管理 API 修改），为 NULL 的列沿用默认值
+CREATE TABLE IF NOT EXISTS task_type_configs (
+    task_type VARCHAR(128) NOT NULL PRIMARY KEY,
+    max_retries INT NULL,
+    timeout_ms BIGINT NULL,
+    max_concurrency INT NULL,
+    rate_per_minute INT NULL,
+    updated_at DATETIME(3) NOT NULL
+);
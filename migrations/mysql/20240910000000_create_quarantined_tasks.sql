@@ -0,0 +1,13 @@
+-- 隔离区：提交时被内容扫描、宽松模式下的 Schema 校验或可疑规则拦下的任务，
+-- 不会被调度，等待运维人员通过管理 API 放行或拒绝
+CREATE TABLE IF NOT EXISTS quarantined_tasks (
+    id CHAR(36) NOT NULL PRIMARY KEY,
+    tenant VARCHAR(64) NOT NULL,
+    payload JSON NOT NULL,
+    priority INT NOT NULL,
+    run_at DATETIME(3) NULL,
+    context JSON NULL,
+    reasons JSON NOT NULL,
+    quarantined_at DATETIME(3) NOT NULL,
+    INDEX idx_quarantined_tasks_quarantined_at (quarantined_at)
+);
@@ -0,0 +1,13 @@
+-- 审计日志：通过管理 API 执行的变更操作，按时间倒序分页查询，
+-- 索引覆盖按时间、调用方、操作类型过滤后按 (created_at, id) 翻页
+CREATE TABLE IF NOT EXISTS audit_log (
+    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    actor VARCHAR(128) NOT NULL,
+    action VARCHAR(64) NOT NULL,
+    target VARCHAR(128) NULL,
+    detail JSON NOT NULL,
+    created_at DATETIME(3) NOT NULL,
+    INDEX idx_audit_log_created_at (created_at, id),
+    INDEX idx_audit_log_actor (actor, created_at, id),
+    INDEX idx_audit_log_action (action, created_at, id)
+);
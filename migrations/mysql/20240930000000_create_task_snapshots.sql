@@ -0,0 +1,7 @@
+-- 任务最终失败时最后一次执行的输入快照（载荷、生效的配置与处理器版本），供 POST /tasks/:id/replay 重放
+CREATE TABLE IF NOT EXISTS task_snapshots (
+    id CHAR(36) NOT NULL PRIMARY KEY,
+    snapshot JSON NOT NULL,
+    captured_at DATETIME(3) NOT NULL,
+    INDEX idx_task_snapshots_captured_at (captured_at)
+);
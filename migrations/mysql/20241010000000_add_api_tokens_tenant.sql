@@ -0,0 +1,2 @@
+-- 令牌绑定的租户，以该令牌访问时只能使用这个租户；为空表示未绑定，由 X-Tenant-ID 声明租户
+ALTER TABLE api_tokens ADD COLUMN tenant VARCHAR(64) NULL;
@@ -0,0 +1,9 @@
+-- 尚未处理完成的任务，入队时写入、处理结束后删除，启动时据此恢复队列
+CREATE TABLE IF NOT EXISTS tasks_queue (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    payload JSONB NOT NULL,
+    priority INTEGER NOT NULL,
+    retry_count INTEGER NOT NULL,
+    enqueued_at TIMESTAMPTZ(3) NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_tasks_queue_enqueued_at ON tasks_queue (enqueued_at);
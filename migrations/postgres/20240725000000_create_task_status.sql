@@ -0,0 +1,13 @@
+-- 每个任务的最新状态，由事件记录器随状态变化写入，
+-- 进程重启或内存索引淘汰记录后，状态查询接口据此返回任务状态
+CREATE TABLE IF NOT EXISTS task_status (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    tenant VARCHAR(64) NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    priority INTEGER NOT NULL,
+    retry_count INTEGER NOT NULL,
+    last_error TEXT NULL,
+    version BIGINT NOT NULL,
+    created_at TIMESTAMPTZ(3) NOT NULL,
+    updated_at TIMESTAMPTZ(3) NOT NULL
+);
@@ -0,0 +1,2 @@
+-- 延迟任务的执行时间，为空表示入队后立即可以处理
+ALTER TABLE tasks_queue ADD COLUMN run_at TIMESTAMPTZ(3) NULL;
@@ -0,0 +1,9 @@
+-- 运维人员为任务添加的批注，通过管理 API 写入，在死信队列等列表中展示
+CREATE TABLE IF NOT EXISTS task_annotations (
+    id BIGSERIAL PRIMARY KEY,
+    task_id VARCHAR(36) NOT NULL,
+    author VARCHAR(64) NOT NULL,
+    text TEXT NOT NULL,
+    created_at TIMESTAMPTZ(3) NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_task_annotations_task_id ON task_annotations (task_id);
@@ -0,0 +1,6 @@
+-- 停机时保存的累计计数器，启动时加载，使长期计数器在重启之间保持连续；name 为带标签的指标键
+CREATE TABLE IF NOT EXISTS metric_snapshots (
+    name VARCHAR(255) NOT NULL PRIMARY KEY,
+    value BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ(3) NOT NULL
+);
@@ -0,0 +1,2 @@
+-- 提交时捕获的请求头（PROPAGATE_HEADERS），为空表示没有需要传递的请求头
+ALTER TABLE tasks_queue ADD COLUMN context JSONB NULL;
@@ -0,0 +1,13 @@
+-- 隔离区：提交时被内容扫描、宽松模式下的 Schema 校验或可疑规则拦下的任务，
+-- 不会被调度，等待运维人员通过管理 API 放行或拒绝
+CREATE TABLE IF NOT EXISTS quarantined_tasks (
+    id VARCHAR(36) NOT NULL PRIMARY KEY,
+    tenant VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL,
+    priority INTEGER NOT NULL,
+    run_at TIMESTAMPTZ(3) NULL,
+    context JSONB NULL,
+    reasons JSONB NOT NULL,
+    quarantined_at TIMESTAMPTZ(3) NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_quarantined_tasks_quarantined_at ON quarantined_tasks (quarantined_at);
@@ -0,0 +1,3 @@
+-- 提交时指定的执行方式（quick、slow 或自定义处理器名称），为空表示按历史耗时自动分类
+ALTER TABLE tasks_queue ADD COLUMN kind VARCHAR(64) NULL;
+ALTER TABLE quarantined_tasks ADD COLUMN kind VARCHAR(64) NULL;
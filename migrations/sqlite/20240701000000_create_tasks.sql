@@ -0,0 +1,5 @@
+-- 保存任务处理结果的 `tasks` 表
+CREATE TABLE IF NOT EXISTS tasks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    data TEXT NOT NULL
+);
@@ -0,0 +1,8 @@
+-- 任务状态变化的历史记录，用于事件流断线重连后的回放
+CREATE TABLE IF NOT EXISTS task_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_task_events_task_id ON task_events (task_id);
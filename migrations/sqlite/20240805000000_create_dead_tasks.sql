@@ -0,0 +1,11 @@
+-- 死信队列：重试次数用尽后最终失败的任务及其最后一次错误，可通过管理 API 查看并重新入队
+CREATE TABLE IF NOT EXISTS dead_tasks (
+    id TEXT NOT NULL PRIMARY KEY,
+    tenant TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    priority INTEGER NOT NULL,
+    retry_count INTEGER NOT NULL,
+    error TEXT NOT NULL,
+    failed_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_dead_tasks_failed_at ON dead_tasks (failed_at);
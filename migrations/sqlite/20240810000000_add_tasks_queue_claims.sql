@@ -0,0 +1,4 @@
+-- 认领任务的实例与认领（续约）时间；多个实例共用一个数据库时，每个任务只由认领它的实例处理
+ALTER TABLE tasks_queue ADD COLUMN claimed_by TEXT;
+ALTER TABLE tasks_queue ADD COLUMN claimed_at TEXT;
+CREATE INDEX IF NOT EXISTS idx_tasks_queue_claimed_by ON tasks_queue (claimed_by);
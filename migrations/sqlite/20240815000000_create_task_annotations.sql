@@ -0,0 +1,9 @@
+-- 运维人员为任务添加的批注，通过管理 API 写入，在死信队列等列表中展示
+CREATE TABLE IF NOT EXISTS task_annotations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id TEXT NOT NULL,
+    author TEXT NOT NULL,
+    text TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_task_annotations_task_id ON task_annotations (task_id);
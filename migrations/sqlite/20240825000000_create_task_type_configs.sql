@@ -0,0 +1,9 @@
+-- 按任务类型在运行时覆盖的调度配置（通过管理 API 修改），为 NULL 的列沿用默认值
+CREATE TABLE IF NOT EXISTS task_type_configs (
+    task_type TEXT NOT NULL PRIMARY KEY,
+    max_retries INTEGER,
+    timeout_ms INTEGER,
+    max_concurrency INTEGER,
+    rate_per_minute INTEGER,
+    updated_at TEXT NOT NULL
+);
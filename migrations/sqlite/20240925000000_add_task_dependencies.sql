@@ -0,0 +1,2 @@
+-- 事务提交时指定的依赖任务 ID（JSON 数组），为空表示没有依赖
+ALTER TABLE tasks_queue ADD COLUMN depends_on TEXT;
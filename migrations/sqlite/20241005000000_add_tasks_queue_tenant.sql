@@ -0,0 +1,2 @@
+-- 提交任务的租户，重启或被其他实例接管时据此恢复任务的归属；为空表示默认租户
+ALTER TABLE tasks_queue ADD COLUMN tenant TEXT;
@@ -0,0 +1,8 @@
+-- 按租户的累计任务统计（成功、失败数与已结束任务的总耗时），各实例定期把增量累加进来，重启后从这里继续累计
+CREATE TABLE IF NOT EXISTS tenant_stats (
+    tenant TEXT NOT NULL PRIMARY KEY,
+    succeeded INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    total_latency_ms INTEGER NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL
+);
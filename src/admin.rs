@@ -0,0 +1,1245 @@
+use crate::annotations::{self, Annotation, NewAnnotation};
+use crate::audit::{self, Actor};
+use crate::db::filter::{AuditFilter, AuditOrder, TaskFilter};
+use crate::db::{self, check_schema};
+use crate::dlq;
+use crate::error::AppError;
+use crate::i18n::Message;
+use crate::jwt;
+use crate::lifecycle::RESTART_EXIT_CODE;
+use crate::log_tail::{self, LogFilter, TailEvent};
+use crate::logging;
+use crate::metrics;
+use crate::quarantine;
+use crate::queue::RebalanceFilter;
+use crate::queue_migration::MigratingQueue;
+use crate::replay::{self, ExecutionSnapshot, ReplayOutcome};
+use crate::response::Fields;
+use crate::runtime_metrics;
+use crate::status::TaskState;
+use crate::task_types::{TaskTypeConfig, TaskTypeOverride};
+use crate::tokens::{self, ApiToken, Scope, TOKEN_PREFIX};
+use crate::web::{with_common_layers, with_estimated_retry_after, AppState};
+use axum::{
+    body::Body,
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
+    },
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// `GET /admin/status` 的 handler。
+///
+/// 返回队列长度和数据库连接池的概况，供运维人员快速查看服务状态。
+async fn admin_status(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+    let pending = state.queue.len().await;
+    let by_class: serde_json::Map<String, Value> = state
+        .queue
+        .len_by_class()
+        .await
+        .into_iter()
+        .map(|(class, len)| (class.as_str().to_string(), len.into()))
+        .collect();
+    let schema = state
+        .schema_check
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    Ok(Json(json!({
+        "queue": { "pending": pending, "pending_by_class": by_class },
+        "db": state.db.describe(),
+        "db_circuit": db::circuit_breaker().map(|breaker| breaker.state()),
+        "log_file": logging::file_sink(),
+        "schema": schema,
+        "scheduler": {
+            "heartbeat_age_ms": state.heartbeat.age().as_millis() as u64,
+            "paused": state.lifecycle.is_paused(),
+            "task_types": state.classifier.snapshot(),
+        },
+        "background_tasks": state.supervisor.status(),
+    })))
+}
+
+/// `GET /stats/tenants` 的 handler。
+///
+/// 返回所有租户的任务统计（排队、处理中、成功、失败、失败率、平均耗时）。
+async fn tenant_stats(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+    Ok(Json(json!({ "tenants": state.tasks.tenant_stats() })))
+}
+
+/// `GET /admin/db` 的 handler。
+///
+/// 返回连接池的详细状况，并实时探测一次数据库的可用性。
+async fn admin_db(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+    let ping = match state.db.ping().await {
+        Ok(latency) => json!({ "ok": true, "latency_ms": latency.as_millis() as u64 }),
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    };
+    Ok(Json(json!({
+        "db": state.db.describe(),
+        "ping": ping,
+    })))
+}
+
+/// `GET /admin/db/migrations` 的 handler。
+///
+/// 返回已应用与待应用的迁移列表。
+async fn admin_migrations(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+    let status = state.db.migration_status().await?;
+    Ok(Json(json!({
+        "backend": state.db.backend_name(),
+        "migrations": status,
+    })))
+}
+
+/// `POST /admin/db/migrate` 的查询参数。
+#[derive(Deserialize)]
+pub struct MigrateQuery {
+    /// 为 `true` 时只报告将要应用的迁移，不修改数据库。
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// `POST /admin/db/migrate` 的 handler。
+///
+/// 应用所有待执行的迁移。这是一个变更操作，要求服务配置了 `ADMIN_TOKEN`，
+/// 请求已经过 `require_admin_token` 中间件的校验。
+async fn admin_migrate(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Query(query): Query<MigrateQuery>,
+) -> Result<Json<Value>, AppError> {
+    require_configured_token(&state, "执行数据库迁移")?;
+
+    let migrations = state.db.run_migrations(query.dry_run).await?;
+    tracing::info!(
+        dry_run = query.dry_run,
+        count = migrations.as_ref().map_or(0, Vec::len),
+        "通过管理 API 执行数据库迁移"
+    );
+    if !query.dry_run {
+        let applied: Vec<i64> = migrations
+            .iter()
+            .flatten()
+            .map(|migration| migration.version)
+            .collect();
+        audit::record(
+            &state.db,
+            &actor,
+            "db.migrate",
+            None,
+            json!({ "applied": applied }),
+        )
+        .await;
+    }
+
+    // 迁移后重新检查表结构，使就绪状态随之更新
+    let schema = check_schema(&state.db).await;
+    *state
+        .schema_check
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = schema.clone();
+
+    Ok(Json(json!({
+        "backend": state.db.backend_name(),
+        "dry_run": query.dry_run,
+        "migrations": migrations,
+        "schema": schema,
+    })))
+}
+
+/// `POST /admin/restart-intent` 的 handler。
+///
+/// 通知本实例准备滚动重启：停止接收新任务、调度器不再取出新任务，
+/// 等待正在处理的任务完成后以 `RESTART_EXIT_CODE` 退出，由编排系统拉起新实例。
+/// 调度器目前只在本进程内运行，没有需要释放的跨实例领导权。
+async fn admin_restart_intent(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    require_configured_token(&state, "请求重启")?;
+
+    let accepted = state.lifecycle.request_restart();
+    if accepted {
+        tracing::warn!(
+            in_flight = state.lifecycle.in_flight(),
+            "通过管理 API 请求滚动重启"
+        );
+        audit::record(
+            &state.db,
+            &actor,
+            "lifecycle.restart",
+            None,
+            json!({ "in_flight": state.lifecycle.in_flight() }),
+        )
+        .await;
+    }
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "accepted": accepted,
+            "draining": true,
+            "in_flight": state.lifecycle.in_flight(),
+            "exit_code": state.lifecycle.exit_code(),
+            "expected_exit_code": RESTART_EXIT_CODE,
+        })),
+    ))
+}
+
+/// `POST /admin/pause` 的 handler。
+///
+/// 暂停调度器的分发：不再取出新任务，任务留在队列中，仍然接收新任务；正在处理的任务继续完成。
+/// 与 `restart-intent` 不同，暂停不会使进程退出，之后通过 `POST /admin/resume` 恢复。
+async fn admin_pause(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+) -> Result<Json<Value>, AppError> {
+    require_configured_token(&state, "暂停调度器")?;
+
+    let changed = state.lifecycle.pause();
+    if changed {
+        tracing::warn!(
+            in_flight = state.lifecycle.in_flight(),
+            "通过管理 API 暂停调度器"
+        );
+        audit::record(&state.db, &actor, "scheduler.pause", None, json!({})).await;
+    }
+    Ok(Json(json!({
+        "paused": true,
+        "changed": changed,
+        "in_flight": state.lifecycle.in_flight(),
+    })))
+}
+
+/// `POST /admin/resume` 的 handler，恢复被 `POST /admin/pause` 暂停的分发。
+async fn admin_resume(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+) -> Result<Json<Value>, AppError> {
+    require_configured_token(&state, "恢复调度器")?;
+
+    let changed = state.lifecycle.resume();
+    if changed {
+        tracing::info!("通过管理 API 恢复调度器");
+        audit::record(&state.db, &actor, "scheduler.resume", None, json!({})).await;
+    }
+    Ok(Json(json!({
+        "paused": false,
+        "changed": changed,
+        "in_flight": state.lifecycle.in_flight(),
+    })))
+}
+
+/// `POST /admin/queue/rebalance` 的请求体。
+#[derive(Deserialize)]
+pub struct RebalanceRequest {
+    /// 选择任务的条件，不能为空。
+    filter: RebalanceFilter,
+    /// 新的优先级。
+    priority: u8,
+    /// 为 `true` 时只报告会被修改的任务，不修改队列。
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// `POST /admin/queue/rebalance` 的 handler。
+///
+/// 将满足条件的排队任务整体调整到新的优先级，例如在故障期间把营销类任务降到低优先级档位。
+/// 修改在队列锁内一次完成，并记录审计日志。
+async fn admin_queue_rebalance(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Json(request): Json<RebalanceRequest>,
+) -> Result<Json<Value>, AppError> {
+    require_configured_token(&state, "调整队列优先级")?;
+    if request.filter.is_empty() {
+        return Err(AppError::Validation(Message::new("EMPTY_FILTER")));
+    }
+
+    let outcome = state
+        .queue
+        .rebalance(&request.filter, request.priority, request.dry_run)
+        .await?;
+    if !request.dry_run {
+        for id in &outcome.changed {
+            state.tasks.set_priority(id, request.priority);
+        }
+    }
+    tracing::warn!(
+        audit = true,
+        filter = ?request.filter,
+        priority = request.priority,
+        dry_run = request.dry_run,
+        matched = outcome.matched,
+        changed = outcome.changed.len(),
+        "通过管理 API 调整队列优先级"
+    );
+    if !request.dry_run {
+        audit::record(
+            &state.db,
+            &actor,
+            "queue.rebalance",
+            None,
+            json!({
+                "filter": request.filter,
+                "priority": request.priority,
+                "matched": outcome.matched,
+                "task_ids": outcome.changed,
+            }),
+        )
+        .await;
+    }
+
+    Ok(Json(json!({
+        "dry_run": request.dry_run,
+        "priority": request.priority,
+        "matched": outcome.matched,
+        "changed": outcome.changed.len(),
+        "task_ids": outcome.changed,
+    })))
+}
+
+/// 切换队列后端时的迁移窗口，未设置 `QUEUE_MIGRATE_FROM` 时返回 404。
+fn queue_migration(state: &AppState) -> Result<&Arc<MigratingQueue>, AppError> {
+    state
+        .queue_migration
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound(Message::new("QUEUE_MIGRATION_NOT_CONFIGURED")))
+}
+
+/// `GET /admin/queue/migration`：查看旧队列后端迁移到新后端的进度与计数校验结果。
+async fn admin_queue_migration(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+    let report = queue_migration(&state)?.report().await;
+    Ok(Json(json!(report)))
+}
+
+/// `POST /admin/queue/migration`：在后台把旧队列后端中的任务迁移到新后端。
+///
+/// 迁移期间 API 照常接收任务（写入新后端），调度器继续处理两个后端中的任务；
+/// 已经在迁移时不会重复开始，返回当前进度。
+async fn admin_queue_migrate(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    require_configured_token(&state, "迁移队列")?;
+    let migration = queue_migration(&state)?;
+    let started = migration.start().await;
+    let report = migration.report().await;
+    if started {
+        tracing::warn!(
+            audit = true,
+            source = report.source,
+            target = report.target,
+            source_before = report.source_before,
+            "通过管理 API 开始迁移队列"
+        );
+        audit::record(
+            &state.db,
+            &actor,
+            "queue.migrate",
+            None,
+            json!({
+                "source": report.source,
+                "target": report.target,
+                "source_before": report.source_before,
+            }),
+        )
+        .await;
+    }
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({ "started": started, "migration": report })),
+    ))
+}
+
+/// 死信队列列表默认返回的任务数。
+const DLQ_DEFAULT_LIMIT: i64 = 100;
+/// 死信队列列表单次最多返回的任务数。
+const DLQ_MAX_LIMIT: i64 = 1000;
+
+/// `GET /dlq` 的查询参数。
+#[derive(Deserialize)]
+pub struct DlqQuery {
+    /// 最多返回的任务数，默认 `DLQ_DEFAULT_LIMIT`，不超过 `DLQ_MAX_LIMIT`。
+    limit: Option<i64>,
+    /// 只返回该租户的任务。
+    tenant: Option<String>,
+    /// 只返回优先级在该范围内（含两端）的任务。
+    min_priority: Option<u8>,
+    max_priority: Option<u8>,
+    /// 只返回在该时间之后（含）失败的任务。
+    from: Option<DateTime<Utc>>,
+    /// 只返回在该时间之前（不含）失败的任务。
+    to: Option<DateTime<Utc>>,
+    /// 逗号分隔的字段列表，每个任务只返回这些字段（包括 `annotations`）。
+    fields: Option<String>,
+}
+
+impl DlqQuery {
+    fn filter(&self) -> TaskFilter {
+        let mut filter = TaskFilter::new();
+        if let Some(tenant) = &self.tenant {
+            filter = filter.tenant(tenant.clone());
+        }
+        if let Some(priority) = self.min_priority {
+            filter = filter.min_priority(priority);
+        }
+        if let Some(priority) = self.max_priority {
+            filter = filter.max_priority(priority);
+        }
+        if let Some(time) = self.from {
+            filter = filter.from(time);
+        }
+        if let Some(time) = self.to {
+            filter = filter.to(time);
+        }
+        filter
+    }
+}
+
+/// `GET /dlq` 的 handler。
+///
+/// 按失败时间从新到旧返回死信队列中满足过滤条件的任务及其最后一次错误，每个任务附带运维人员添加的批注；
+/// `size` 始终为死信任务总数。
+async fn dlq_list(
+    State(state): State<AppState>,
+    Query(query): Query<DlqQuery>,
+) -> Result<Json<Value>, AppError> {
+    let limit = query
+        .limit
+        .unwrap_or(DLQ_DEFAULT_LIMIT)
+        .clamp(1, DLQ_MAX_LIMIT);
+    let size = state.db.dead_task_count().await?;
+    let dead = state.db.dead_tasks(&query.filter(), limit).await?;
+    let ids: Vec<Uuid> = dead.iter().map(|dead| dead.id).collect();
+    let mut notes = annotations::group_by_task(state.db.annotations(&ids).await?);
+    let tasks: Vec<Value> = dead
+        .into_iter()
+        .map(|dead| {
+            let mut task = json!(dead);
+            task["annotations"] = json!(notes.remove(&dead.id).unwrap_or_default());
+            task
+        })
+        .collect();
+    let tasks = Fields::parse(query.fields.as_deref()).apply(json!(tasks));
+    Ok(Json(json!({ "size": size, "tasks": tasks })))
+}
+
+/// `POST /dlq/:id/requeue` 的 handler。
+///
+/// 将死信队列中的任务以原 ID 和优先级重新入队，重试次数清零。
+/// 入队失败时任务被放回死信队列，不会丢失。
+async fn dlq_requeue(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<Uuid>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    require_configured_token(&state, "重新入队死信任务")?;
+    let dead = dlq::take(&state.db, &id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(Message::new("DEAD_TASK_NOT_FOUND").arg("id", id)))?;
+
+    let task = dead.to_task();
+    state
+        .tasks
+        .insert_queued(task.id, task.priority, &dead.tenant);
+    // 队列已满时不等待，任务放回死信队列，由调用方稍后重试
+    if let Err(e) = state.queue.try_push(task).await {
+        state
+            .tasks
+            .set_state(&id, TaskState::Failed, 0, Some(e.to_string()));
+        if let Err(db_error) = state.db.insert_dead_task(&dead).await {
+            tracing::error!(task_id = %id, "放回死信队列失败: {}", db_error);
+        }
+        return Err(with_estimated_retry_after(&state, e).into());
+    }
+    tracing::warn!(
+        audit = true,
+        task_id = %id,
+        tenant = %dead.tenant,
+        "通过管理 API 将死信任务重新入队"
+    );
+    audit::record(
+        &state.db,
+        &actor,
+        "dlq.requeue",
+        Some(id.to_string()),
+        json!({ "tenant": dead.tenant }),
+    )
+    .await;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "id": id,
+            "tenant": dead.tenant,
+            "priority": dead.priority,
+        })),
+    ))
+}
+
+/// 隔离区列表默认返回的任务数。
+const QUARANTINE_DEFAULT_LIMIT: i64 = 100;
+/// 隔离区列表单次最多返回的任务数。
+const QUARANTINE_MAX_LIMIT: i64 = 1000;
+
+/// `GET /admin/quarantine` 的查询参数。
+#[derive(Deserialize)]
+pub struct QuarantineQuery {
+    /// 最多返回的任务数，默认 `QUARANTINE_DEFAULT_LIMIT`，不超过 `QUARANTINE_MAX_LIMIT`。
+    limit: Option<i64>,
+    /// 逗号分隔的字段列表，每个任务只返回这些字段（包括 `annotations`）。
+    fields: Option<String>,
+}
+
+/// `GET /admin/quarantine` 的 handler。
+///
+/// 按隔离时间从旧到新返回隔离区中等待审核的任务及其隔离原因，每个任务附带运维人员添加的批注。
+async fn quarantine_list(
+    State(state): State<AppState>,
+    Query(query): Query<QuarantineQuery>,
+) -> Result<Json<Value>, AppError> {
+    let limit = query
+        .limit
+        .unwrap_or(QUARANTINE_DEFAULT_LIMIT)
+        .clamp(1, QUARANTINE_MAX_LIMIT);
+    let size = state.db.quarantined_task_count().await?;
+    let held = state.db.quarantined_tasks(limit).await?;
+    let ids: Vec<Uuid> = held.iter().map(|held| held.id).collect();
+    let mut notes = annotations::group_by_task(state.db.annotations(&ids).await?);
+    let tasks: Vec<Value> = held
+        .into_iter()
+        .map(|held| {
+            let mut task = json!(held);
+            task["annotations"] = json!(notes.remove(&held.id).unwrap_or_default());
+            task
+        })
+        .collect();
+    let tasks = Fields::parse(query.fields.as_deref()).apply(json!(tasks));
+    Ok(Json(json!({ "size": size, "tasks": tasks })))
+}
+
+/// 放行或拒绝隔离任务的请求体。
+#[derive(Deserialize)]
+pub struct ReviewRequest {
+    /// 审核人，通常是运维人员的用户名。
+    reviewer: String,
+    /// 审核说明，与审核结论一起记录为任务批注。
+    #[serde(default)]
+    note: Option<String>,
+}
+
+impl ReviewRequest {
+    /// 记录审核结论的批注；同时校验审核人与说明的长度。
+    fn annotation(&self, id: Uuid, decision: &str) -> Result<NewAnnotation, AppError> {
+        let text = match self.note.as_deref().map(str::trim) {
+            Some(note) if !note.is_empty() => format!("隔离审核：{}（{}）", decision, note),
+            _ => format!("隔离审核：{}", decision),
+        };
+        NewAnnotation::new(id, &self.reviewer, &text)
+    }
+}
+
+/// 从隔离区取出任务，不存在时返回 404。
+async fn take_quarantined(
+    state: &AppState,
+    id: Uuid,
+) -> Result<quarantine::QuarantinedTask, AppError> {
+    quarantine::take(&state.db, &id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(Message::new("QUARANTINED_TASK_NOT_FOUND").arg("id", id)))
+}
+
+/// 写入审核批注；任务已经放行或拒绝，写入失败只记录日志。
+async fn record_review(state: &AppState, annotation: &NewAnnotation) {
+    if let Err(e) = state.db.insert_annotation(annotation).await {
+        tracing::error!(task_id = %annotation.task_id, "写入隔离审核批注失败: {}", e);
+    }
+}
+
+/// `POST /admin/quarantine/:id/approve` 的 handler。
+///
+/// 放行隔离任务：以提交时的 ID、优先级与请求上下文入队。
+/// 入队失败时任务被放回隔离区，不会丢失。
+async fn quarantine_approve(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ReviewRequest>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    require_configured_token(&state, "放行隔离任务")?;
+    let annotation = request.annotation(id, "放行")?;
+    let held = take_quarantined(&state, id).await?;
+
+    // 进程重启后内存索引中没有该任务，先补上记录，使状态变化能写入历史
+    if state.tasks.get(&id).is_none() {
+        state
+            .tasks
+            .insert_quarantined(id, held.priority, &held.tenant);
+    }
+    state.tasks.set_state(&id, TaskState::Queued, 0, None);
+    // 队列已满时不等待，任务放回隔离区，由审核人稍后重试
+    if let Err(e) = state.queue.try_push(held.to_task()).await {
+        state.tasks.set_state(&id, TaskState::Quarantined, 0, None);
+        if let Err(db_error) = state.db.insert_quarantined_task(&held).await {
+            tracing::error!(task_id = %id, "放回隔离区失败: {}", db_error);
+        }
+        if let Err(db_error) = quarantine::refresh_size(&state.db).await {
+            tracing::warn!("更新隔离区大小失败: {}", db_error);
+        }
+        return Err(with_estimated_retry_after(&state, e).into());
+    }
+    record_review(&state, &annotation).await;
+    metrics::counter_with_labels("quarantine_reviews_total", &[("decision", "approved")]).inc();
+    tracing::warn!(
+        audit = true,
+        task_id = %id,
+        tenant = %held.tenant,
+        reviewer = %annotation.author,
+        reasons = ?held.reasons,
+        "通过管理 API 放行隔离任务"
+    );
+    audit::record(
+        &state.db,
+        &actor,
+        "quarantine.approve",
+        Some(id.to_string()),
+        json!({
+            "tenant": held.tenant,
+            "reviewer": annotation.author,
+            "reasons": held.reasons,
+        }),
+    )
+    .await;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "id": id,
+            "tenant": held.tenant,
+            "priority": held.priority,
+            "status": TaskState::Queued,
+        })),
+    ))
+}
+
+/// `POST /admin/quarantine/:id/reject` 的 handler。
+///
+/// 拒绝隔离任务：从隔离区删除，任务以失败结束，不会被处理。
+async fn quarantine_reject(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<ReviewRequest>,
+) -> Result<Json<Value>, AppError> {
+    require_configured_token(&state, "拒绝隔离任务")?;
+    let annotation = request.annotation(id, "拒绝")?;
+    let held = take_quarantined(&state, id).await?;
+
+    if state.tasks.get(&id).is_none() {
+        state
+            .tasks
+            .insert_quarantined(id, held.priority, &held.tenant);
+    }
+    state.tasks.set_state(
+        &id,
+        TaskState::Failed,
+        0,
+        Some(format!("隔离审核未通过（审核人 {}）", annotation.author)),
+    );
+    record_review(&state, &annotation).await;
+    metrics::counter_with_labels("quarantine_reviews_total", &[("decision", "rejected")]).inc();
+    tracing::warn!(
+        audit = true,
+        task_id = %id,
+        tenant = %held.tenant,
+        reviewer = %annotation.author,
+        reasons = ?held.reasons,
+        "通过管理 API 拒绝隔离任务"
+    );
+    audit::record(
+        &state.db,
+        &actor,
+        "quarantine.reject",
+        Some(id.to_string()),
+        json!({
+            "tenant": held.tenant,
+            "reviewer": annotation.author,
+            "reasons": held.reasons,
+        }),
+    )
+    .await;
+
+    Ok(Json(json!({
+        "id": id,
+        "tenant": held.tenant,
+        "status": TaskState::Failed,
+    })))
+}
+
+/// `POST /tasks/:id/annotations` 的请求体。
+#[derive(Deserialize)]
+pub struct AnnotationRequest {
+    /// 批注的作者，通常是运维人员的用户名。
+    author: String,
+    text: String,
+}
+
+/// `POST /tasks/:id/annotations` 的 handler。
+///
+/// 为任务添加一条批注（作者、时间与内容），记录排查结论或处理决定。
+/// 任务必须存在于内存索引或 `task_status` 表中；批注只追加，不能修改或删除。
+async fn add_annotation(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<AnnotationRequest>,
+) -> Result<(StatusCode, Json<Annotation>), AppError> {
+    require_configured_token(&state, "添加任务批注")?;
+    let annotation = NewAnnotation::new(id, &request.author, &request.text)?;
+    if state.tasks.get(&id).is_none() && state.db.task_status(&id).await?.is_none() {
+        return Err(AppError::NotFound(
+            Message::new("TASK_NOT_FOUND").arg("id", id),
+        ));
+    }
+
+    let annotation = state.db.insert_annotation(&annotation).await?;
+    metrics::counter("task_annotations_total").inc();
+    tracing::warn!(
+        audit = true,
+        task_id = %id,
+        author = %annotation.author,
+        annotation_id = annotation.id,
+        "通过管理 API 添加任务批注"
+    );
+    audit::record(
+        &state.db,
+        &actor,
+        "task.annotate",
+        Some(id.to_string()),
+        json!({ "annotation_id": annotation.id, "author": annotation.author }),
+    )
+    .await;
+    Ok((StatusCode::CREATED, Json(annotation)))
+}
+
+/// `GET /tasks/:id/annotations` 的 handler，按添加时间从旧到新返回任务的批注。
+async fn list_annotations(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, AppError> {
+    let annotations = state.db.annotations(&[id]).await?;
+    Ok(Json(json!({ "task_id": id, "annotations": annotations })))
+}
+
+/// `GET /tasks/:id/snapshot` 的 handler。
+///
+/// 返回任务最终失败时记录的执行快照：载荷及其摘要、生效的任务类型配置、处理器与服务版本和失败的错误。
+/// 快照包含完整的载荷，读取会记入审计日志。任务没有失败过时返回 404。
+async fn task_snapshot(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ExecutionSnapshot>, AppError> {
+    let snapshot = state
+        .db
+        .task_snapshot(&id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(Message::new("SNAPSHOT_NOT_FOUND").arg("id", id)))?;
+    audit::record(
+        &state.db,
+        &actor,
+        "task.snapshot",
+        Some(id.to_string()),
+        json!({ "tenant": snapshot.tenant }),
+    )
+    .await;
+    Ok(Json(snapshot))
+}
+
+/// `POST /tasks/:id/replay` 的 handler。
+///
+/// 按任务的执行快照在隔离的空跑环境中重新执行一次（见 `replay::replay`），返回执行结果与期间的全部日志，
+/// 不影响任务的状态、重试次数与生产数据。需要配置 `TASK_REPLAY=true`，否则返回 403。
+async fn replay_task(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ReplayOutcome>, AppError> {
+    require_configured_token(&state, "重放任务")?;
+    if !state.config.task_replay {
+        return Err(AppError::Forbidden(Message::new("REPLAY_DISABLED")));
+    }
+    let snapshot = state
+        .db
+        .task_snapshot(&id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(Message::new("SNAPSHOT_NOT_FOUND").arg("id", id)))?;
+    let outcome = replay::replay(&state.handlers, &snapshot).await;
+    tracing::warn!(
+        audit = true,
+        task_id = %id,
+        tenant = %snapshot.tenant,
+        succeeded = outcome.succeeded,
+        handler_changed = outcome.handler_changed,
+        duration_ms = outcome.duration_ms,
+        "通过管理 API 重放任务"
+    );
+    audit::record(
+        &state.db,
+        &actor,
+        "task.replay",
+        Some(id.to_string()),
+        json!({
+            "tenant": snapshot.tenant,
+            "succeeded": outcome.succeeded,
+            "handler_version": outcome.handler_version,
+        }),
+    )
+    .await;
+    Ok(Json(outcome))
+}
+
+/// `POST /admin/tokens` 的请求体。
+#[derive(Deserialize)]
+pub struct CreateTokenRequest {
+    /// 令牌的用途说明，例如调用方的服务名。
+    name: String,
+    scope: Scope,
+    /// 令牌绑定的租户，省略时不绑定（由 `X-Tenant-ID` 声明租户）。
+    #[serde(default)]
+    tenant: Option<String>,
+    /// 过期时间（RFC 3339），省略时永不过期。
+    #[serde(default)]
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// `POST /admin/tokens` 的 handler。
+///
+/// 创建一个 API 令牌，令牌明文只在响应中返回这一次，数据库中只保存其摘要。
+async fn create_token(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Json(request): Json<CreateTokenRequest>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    require_configured_token(&state, "创建 API 令牌")?;
+    let (token, secret) = ApiToken::generate(
+        &request.name,
+        request.scope,
+        request.tenant.as_deref(),
+        request.expires_at,
+    )?;
+    state
+        .db
+        .insert_api_token(&token, &tokens::hash_secret(&secret))
+        .await?;
+    tracing::warn!(
+        audit = true,
+        token_id = %token.id,
+        token_name = %token.name,
+        scope = token.scope.as_str(),
+        tenant = ?token.tenant,
+        expires_at = ?token.expires_at,
+        "通过管理 API 创建 API 令牌"
+    );
+    audit::record(
+        &state.db,
+        &actor,
+        "token.create",
+        Some(token.id.to_string()),
+        json!({
+            "name": token.name,
+            "scope": token.scope.as_str(),
+            "tenant": token.tenant,
+            "expires_at": token.expires_at,
+        }),
+    )
+    .await;
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "token": token, "secret": secret })),
+    ))
+}
+
+/// `GET /admin/tokens` 的 handler。
+///
+/// 按创建时间从新到旧返回所有 API 令牌（不含令牌明文与摘要），包括已吊销与已过期的。
+async fn list_tokens(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+    let tokens = state.db.api_tokens().await?;
+    Ok(Json(json!({ "tokens": tokens })))
+}
+
+/// `DELETE /admin/tokens/:id` 的 handler。
+///
+/// 吊销 API 令牌：记录保留在 `api_tokens` 表中作为吊销列表，并清空本实例的校验缓存，
+/// 吊销立即在本实例生效，其他实例在 `API_TOKEN_CACHE_TTL_SECS` 之内生效。重复吊销不会报错。
+async fn revoke_token(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ApiToken>, AppError> {
+    require_configured_token(&state, "吊销 API 令牌")?;
+    let token = state
+        .db
+        .revoke_api_token(&id, chrono::Utc::now())
+        .await?
+        .ok_or_else(|| AppError::NotFound(Message::new("API_TOKEN_NOT_FOUND").arg("id", id)))?;
+    state.api_tokens.invalidate();
+    tracing::warn!(
+        audit = true,
+        token_id = %id,
+        token_name = %token.name,
+        "通过管理 API 吊销 API 令牌"
+    );
+    audit::record(
+        &state.db,
+        &actor,
+        "token.revoke",
+        Some(id.to_string()),
+        json!({ "name": token.name }),
+    )
+    .await;
+    Ok(Json(token))
+}
+
+/// `GET /admin/task-types` 的 handler。
+///
+/// 按名称返回所有保存过配置的任务类型及其配置。
+async fn list_task_type_configs(State(state): State<AppState>) -> Result<Json<Value>, AppError> {
+    let task_types = state.db.task_type_configs().await?;
+    Ok(Json(json!({ "task_types": task_types })))
+}
+
+/// `GET /admin/task-types/:name/config` 的 handler。
+///
+/// 返回任务类型当前保存的配置；没有保存过配置时各项均为 `null`（使用默认值），且没有 `updated_at`。
+async fn get_task_type_config(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    let saved = state
+        .db
+        .task_type_configs()
+        .await?
+        .into_iter()
+        .find(|o| o.task_type == name);
+    Ok(Json(match saved {
+        Some(saved) => json!(saved),
+        None => {
+            let mut defaults = json!(TaskTypeConfig::default());
+            defaults["task_type"] = json!(name);
+            defaults
+        }
+    }))
+}
+
+/// `PUT /admin/task-types/:name/config` 的 handler。
+///
+/// 整体覆盖任务类型的重试次数、超时时间、并发与速率限制，省略的字段恢复为默认值。
+/// 配置写入 `task_type_configs` 表，立即在本实例生效，
+/// 其他实例在 `TASK_TYPE_CONFIG_CACHE_TTL_SECS` 之内生效，调度器不需要重启。
+async fn put_task_type_config(
+    State(state): State<AppState>,
+    Extension(actor): Extension<Actor>,
+    Path(name): Path<String>,
+    Json(config): Json<TaskTypeConfig>,
+) -> Result<Json<TaskTypeOverride>, AppError> {
+    require_configured_token(&state, "修改任务类型配置")?;
+    let saved = state.task_types.set(&name, config).await?;
+    tracing::warn!(
+        audit = true,
+        task_type = %saved.task_type,
+        config = ?saved.config,
+        "通过管理 API 修改任务类型配置"
+    );
+    audit::record(
+        &state.db,
+        &actor,
+        "task_type.update",
+        Some(saved.task_type.clone()),
+        json!({ "config": saved.config }),
+    )
+    .await;
+    Ok(Json(saved))
+}
+
+/// 审计日志每页默认返回的记录数。
+const AUDIT_DEFAULT_LIMIT: i64 = 100;
+/// 审计日志每页最多返回的记录数。
+const AUDIT_MAX_LIMIT: i64 = 1000;
+
+/// `GET /admin/audit` 与 `GET /admin/audit/export` 的查询参数，各条件之间是“与”的关系。
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    /// 调用方：`admin`、API 令牌 ID 或 JWT 的 `sub`。
+    actor: Option<String>,
+    /// 操作类型，例如 `dlq.requeue`。
+    action: Option<String>,
+    /// 操作的对象，例如任务 ID。
+    target: Option<String>,
+    /// 起始时间（RFC 3339，含）。
+    from: Option<DateTime<Utc>>,
+    /// 结束时间（RFC 3339，不含）。
+    to: Option<DateTime<Utc>>,
+    /// `desc`（默认，从新到旧）或 `asc`。
+    #[serde(default)]
+    order: AuditOrder,
+    /// 上一页响应中的 `next_cursor`。
+    cursor: Option<String>,
+    /// 每页最多返回的记录数，默认 `AUDIT_DEFAULT_LIMIT`，不超过 `AUDIT_MAX_LIMIT`；导出时忽略。
+    limit: Option<i64>,
+}
+
+impl AuditQuery {
+    fn filter(self) -> Result<AuditFilter, AppError> {
+        let after = self
+            .cursor
+            .map(|cursor| {
+                cursor.parse().map_err(|_| {
+                    AppError::Validation(Message::new("INVALID_AUDIT_CURSOR").arg("cursor", cursor))
+                })
+            })
+            .transpose()?;
+        Ok(AuditFilter {
+            actor: self.actor,
+            action: self.action,
+            target: self.target,
+            from: self.from,
+            to: self.to,
+            order: self.order,
+            after,
+        })
+    }
+}
+
+/// `GET /admin/audit` 的 handler。
+///
+/// 按时间返回一页审计记录（默认从新到旧）；还有更多记录时 `next_cursor` 不为 `null`，
+/// 将其作为 `cursor` 参数（其他条件不变）请求下一页。
+async fn audit_list(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Value>, AppError> {
+    let limit = query
+        .limit
+        .unwrap_or(AUDIT_DEFAULT_LIMIT)
+        .clamp(1, AUDIT_MAX_LIMIT);
+    let filter = query.filter()?;
+    let (entries, next) = audit::page(&state.db, &filter, limit).await?;
+    Ok(Json(json!({
+        "entries": entries,
+        "next_cursor": next.map(|cursor| cursor.to_string()),
+    })))
+}
+
+/// `GET /admin/audit/export` 的 handler。
+///
+/// 以 NDJSON 流式导出满足条件的所有审计记录，条件与 `GET /admin/audit` 相同。
+async fn audit_export(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<([(header::HeaderName, &'static str); 1], Body), AppError> {
+    let filter = query.filter()?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(audit::export(state.db.clone(), filter)),
+    ))
+}
+
+/// `GET /admin/logs/tail` 的查询参数。
+#[derive(Deserialize)]
+pub struct LogTailQuery {
+    /// 最低日志级别（`trace`/`debug`/`info`/`warn`/`error`），默认不过滤。
+    level: Option<String>,
+    /// 模块路径，例如 `scheduler` 匹配 `web_server::scheduler` 及其子模块。
+    target: Option<String>,
+    /// 连接后先回放的最近日志条数，默认 100。
+    limit: Option<usize>,
+}
+
+/// `GET /admin/logs/tail` 的 handler。
+///
+/// 以 SSE 推送结构化日志：先回放内存中最近的日志，再接续实时日志，运维人员不需要登录主机即可查看日志。
+/// 只能订阅到通过 `RUST_LOG` 过滤的日志；重连时携带 `Last-Event-ID` 可以补齐缓冲中断线期间的日志。
+/// 订阅者处理过慢时会收到 `lagged` 事件，其中是跳过的日志条数。
+async fn tail_logs(
+    Query(query): Query<LogTailQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, AppError> {
+    let level = query
+        .level
+        .as_deref()
+        .map(|level| {
+            level.trim().parse::<tracing::Level>().map_err(|_| {
+                AppError::BadRequest(Message::new("INVALID_LOG_LEVEL").arg("level", level))
+            })
+        })
+        .transpose()?;
+    let after = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .ok_or_else(|| AppError::BadRequest(Message::new("INVALID_LAST_EVENT_ID")))?,
+        ),
+        None => None,
+    };
+    let filter = LogFilter {
+        level,
+        target: query.target.filter(|t| !t.trim().is_empty()),
+    };
+    let limit = query.limit.unwrap_or(100).min(1000);
+    let stream = log_tail::handle()
+        .tail(filter, limit, after)
+        .map(|event| match event {
+            TailEvent::Record(record) => Event::default()
+                .id(record.id.to_string())
+                .event("log")
+                .json_data(&record),
+            TailEvent::Lagged(skipped) => Event::default()
+                .event("lagged")
+                .json_data(json!({ "skipped": skipped })),
+        });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// 变更类操作要求服务配置了 `ADMIN_TOKEN` 或 JWT 密钥（请求已经过 `require_admin_token` 的校验）。
+fn require_configured_token(state: &AppState, action: &str) -> Result<(), AppError> {
+    if state.config.admin_token.is_none() && state.jwt.is_none() {
+        tracing::warn!("未配置 ADMIN_TOKEN 或 JWT 密钥，拒绝通过 API {}", action);
+        return Err(AppError::Unauthorized(Message::new(
+            "ADMIN_TOKEN_NOT_CONFIGURED",
+        )));
+    }
+    Ok(())
+}
+
+/// `GET /admin/metrics` 的 handler，以 Prometheus 文本格式导出指标。
+///
+/// Tokio 运行时的指标与各优先级档位的排队任务数（`queue_pending{class}`）在导出时采集。
+async fn admin_metrics(
+    State(state): State<AppState>,
+) -> ([(header::HeaderName, &'static str); 1], String) {
+    runtime_metrics::record();
+    logging::record_metrics();
+    for (class, len) in state.queue.len_by_class().await {
+        metrics::gauge_with_labels("queue_pending", &[("class", class.as_str())]).set(len as f64);
+    }
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(),
+    )
+}
+
+/// 管理 API 的鉴权中间件。
+///
+/// 配置了 `ADMIN_TOKEN` 或 JWT 密钥时，请求必须携带 `Authorization: Bearer <token>`，
+/// 令牌可以是 `ADMIN_TOKEN` 本身、权限范围为 `admin` 的 API 令牌，或 `scope` 包含 `admin` 的 JWT
+/// （JWT 的声明记录在请求的日志 span 中）；
+/// 都未配置时管理 API 仅依赖内部监听地址隔离，只读接口可以直接访问。
+/// 校验通过后将调用方（`Actor`）放入请求扩展，供审计日志记录。
+async fn require_admin_token(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let expected = state.config.admin_token.as_deref();
+    if expected.is_none() && state.jwt.is_none() {
+        request.extensions_mut().insert(Actor::anonymous());
+        return Ok(next.run(request).await);
+    }
+    let provided = tokens::bearer_token(request.headers()).unwrap_or_default();
+    let actor = if expected
+        .is_some_and(|expected| constant_time_eq(provided.as_bytes(), expected.as_bytes()))
+    {
+        Actor("admin".to_string())
+    } else {
+        match &state.jwt {
+            _ if provided.starts_with(TOKEN_PREFIX) => {
+                let token = state
+                    .api_tokens
+                    .authenticate(provided, Scope::Admin)
+                    .await?;
+                tracing::debug!(token_id = %token.id, "管理 API 令牌校验通过");
+                Actor(token.id.to_string())
+            }
+            Some(jwt) if !provided.is_empty() => {
+                let claims = jwt.verify(provided, Scope::Admin)?;
+                jwt::record_claims(&claims);
+                tracing::debug!("管理 API 的 JWT 校验通过");
+                Actor(claims.sub.unwrap_or_else(|| "jwt".to_string()))
+            }
+            _ => return Err(AppError::Unauthorized(Message::new("INVALID_ADMIN_TOKEN"))),
+        }
+    };
+    request.extensions_mut().insert(actor);
+    Ok(next.run(request).await)
+}
+
+/// 以恒定时间比较两个字节串，避免通过响应时间推测令牌内容。
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 创建并配置管理 API 路由。
+///
+/// 管理路由只应挂载在内部监听地址上，不能与公开 API 共用端口。
+pub fn admin_router(app_state: AppState) -> Router {
+    let router = Router::new()
+        .route("/admin/status", get(admin_status))
+        .route("/admin/db", get(admin_db))
+        .route("/admin/db/migrations", get(admin_migrations))
+        .route("/admin/db/migrate", post(admin_migrate))
+        .route("/admin/queue/rebalance", post(admin_queue_rebalance))
+        .route(
+            "/admin/queue/migration",
+            get(admin_queue_migration).post(admin_queue_migrate),
+        )
+        .route("/admin/restart-intent", post(admin_restart_intent))
+        .route("/admin/pause", post(admin_pause))
+        .route("/admin/resume", post(admin_resume))
+        .route("/admin/metrics", get(admin_metrics))
+        .route("/admin/logs/tail", get(tail_logs))
+        .route("/admin/audit", get(audit_list))
+        .route("/admin/audit/export", get(audit_export))
+        .route("/stats/tenants", get(tenant_stats))
+        .route("/dlq", get(dlq_list))
+        .route("/dlq/:id/requeue", post(dlq_requeue))
+        .route("/admin/quarantine", get(quarantine_list))
+        .route("/admin/quarantine/:id/approve", post(quarantine_approve))
+        .route("/admin/quarantine/:id/reject", post(quarantine_reject))
+        .route("/admin/tokens", get(list_tokens).post(create_token))
+        .route("/admin/tokens/:id", delete(revoke_token))
+        .route("/admin/task-types", get(list_task_type_configs))
+        .route(
+            "/admin/task-types/:name/config",
+            get(get_task_type_config).put(put_task_type_config),
+        )
+        .route(
+            "/tasks/:id/annotations",
+            get(list_annotations).post(add_annotation),
+        )
+        .route("/tasks/:id/snapshot", get(task_snapshot))
+        .route("/tasks/:id/replay", post(replay_task))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            require_admin_token,
+        ))
+        .with_state(app_state);
+    with_common_layers(router)
+}
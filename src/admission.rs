@@ -0,0 +1,224 @@
+use crate::metrics;
+use crate::units;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// 慢速任务准入控制的配置，`None` 表示不限制。
+#[derive(Debug, Clone)]
+pub struct AdmissionSettings {
+    /// 单个租户正在处理的慢速任务的预计耗时之和的上限。
+    pub per_tenant: Option<Duration>,
+    /// 单个任务类型正在处理的慢速任务的预计耗时之和的默认上限。
+    pub per_type: Option<Duration>,
+    /// 按任务类型单独设置的上限，优先于 `per_type`。
+    pub types: HashMap<String, Duration>,
+    /// 超出预算的任务被推迟的时长。
+    pub defer: Duration,
+}
+
+impl Default for AdmissionSettings {
+    fn default() -> Self {
+        Self {
+            per_tenant: None,
+            per_type: None,
+            types: HashMap::new(),
+            defer: Duration::from_secs(5),
+        }
+    }
+}
+
+/// 解析按任务类型的预算，格式为逗号分隔的 `type=duration`，例如 `report=60s,export=2m`。
+pub fn parse_type_budgets(list: &str) -> Result<HashMap<String, Duration>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|rule| {
+            let (task_type, budget) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("{}: 格式应为 type=duration", rule))?;
+            let budget = units::parse_duration(budget, Duration::from_secs(1))?;
+            Ok((task_type.trim().to_string(), budget))
+        })
+        .collect()
+}
+
+/// 准入被拒绝的原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetExceeded {
+    /// 租户的预算已用完。
+    Tenant,
+    /// 任务类型的预算已用完。
+    TaskType,
+}
+
+impl BudgetExceeded {
+    /// 用于指标标签和日志。
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BudgetExceeded::Tenant => "tenant",
+            BudgetExceeded::TaskType => "type",
+        }
+    }
+}
+
+/// 正在处理的慢速任务占用的预算。
+#[derive(Default)]
+struct InUse {
+    tenants: HashMap<String, Duration>,
+    types: HashMap<String, Duration>,
+}
+
+/// 慢速任务的准入控制。
+///
+/// 每个慢速任务按其类型的预计耗时占用租户与任务类型的预算，处理结束后归还。
+/// 开始处理一个任务会使任一预算超出上限时，任务被推迟 `defer` 后重新入队，
+/// 避免单个租户或单个任务类型占满慢速任务的处理能力。
+/// 预算为空时总是放行一个任务，即使它的预计耗时本身就超过上限，否则这样的任务永远无法执行。
+pub struct SlowAdmission {
+    settings: AdmissionSettings,
+    in_use: Mutex<InUse>,
+}
+
+impl SlowAdmission {
+    pub fn new(settings: AdmissionSettings) -> Self {
+        Self {
+            settings,
+            in_use: Mutex::new(InUse::default()),
+        }
+    }
+
+    /// 超出预算的任务需要推迟的时长。
+    pub fn defer(&self) -> Duration {
+        self.settings.defer
+    }
+
+    /// 为一个预计耗时 `cost` 的慢速任务申请预算；成功时返回许可，许可被丢弃时归还预算。
+    pub fn try_admit(
+        self: &Arc<Self>,
+        tenant: &str,
+        task_type: &str,
+        cost: Duration,
+    ) -> Result<AdmissionPermit, BudgetExceeded> {
+        let type_limit = self
+            .settings
+            .types
+            .get(task_type)
+            .copied()
+            .or(self.settings.per_type);
+        let mut in_use = self.lock();
+        let fits = |used: Option<&Duration>, limit: Option<Duration>| {
+            let used = used.copied().unwrap_or_default();
+            limit.is_none_or(|limit| used.is_zero() || used + cost <= limit)
+        };
+        let denied = if !fits(in_use.tenants.get(tenant), self.settings.per_tenant) {
+            Some(BudgetExceeded::Tenant)
+        } else if !fits(in_use.types.get(task_type), type_limit) {
+            Some(BudgetExceeded::TaskType)
+        } else {
+            None
+        };
+        if let Some(reason) = denied {
+            metrics::counter_with_labels(
+                "slow_tasks_deferred_total",
+                &[("reason", reason.as_str())],
+            )
+            .inc();
+            return Err(reason);
+        }
+        *in_use.tenants.entry(tenant.to_string()).or_default() += cost;
+        *in_use.types.entry(task_type.to_string()).or_default() += cost;
+        Ok(AdmissionPermit {
+            admission: self.clone(),
+            tenant: tenant.to_string(),
+            task_type: task_type.to_string(),
+            cost,
+        })
+    }
+
+    fn release(&self, tenant: &str, task_type: &str, cost: Duration) {
+        let mut guard = self.lock();
+        let in_use = &mut *guard;
+        for (map, key) in [
+            (&mut in_use.tenants, tenant),
+            (&mut in_use.types, task_type),
+        ] {
+            if let Some(used) = map.get_mut(key) {
+                *used = used.saturating_sub(cost);
+                if used.is_zero() {
+                    map.remove(key);
+                }
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, InUse> {
+        self.in_use.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 一个慢速任务占用的预算，丢弃时归还。
+pub struct AdmissionPermit {
+    admission: Arc<SlowAdmission>,
+    tenant: String,
+    task_type: String,
+    cost: Duration,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.admission
+            .release(&self.tenant, &self.task_type, self.cost);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试租户与任务类型的预算，以及许可丢弃后归还预算。
+    #[test]
+    fn test_admission_budgets() {
+        let admission = Arc::new(SlowAdmission::new(AdmissionSettings {
+            per_tenant: Some(Duration::from_secs(10)),
+            per_type: Some(Duration::from_secs(15)),
+            types: parse_type_budgets("report=5s").unwrap(),
+            ..Default::default()
+        }));
+        let cost = Duration::from_secs(5);
+
+        let first = admission.try_admit("acme", "export", cost).unwrap();
+        let _second = admission.try_admit("acme", "export", cost).unwrap();
+        assert_eq!(
+            admission.try_admit("acme", "export", cost).err(),
+            Some(BudgetExceeded::Tenant)
+        );
+        // 其他租户不受影响，但同一类型的预算是共享的
+        let _third = admission.try_admit("globex", "export", cost).unwrap();
+        assert_eq!(
+            admission.try_admit("initech", "export", cost).err(),
+            Some(BudgetExceeded::TaskType)
+        );
+        drop(first);
+        assert!(admission.try_admit("initech", "export", cost).is_ok());
+
+        // 单独设置的类型预算；预算为空时即使超过上限也放行一个任务
+        let _report = admission
+            .try_admit("initech", "report", Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(
+            admission.try_admit("umbrella", "report", cost).err(),
+            Some(BudgetExceeded::TaskType)
+        );
+    }
+
+    /// 测试按任务类型的预算的解析。
+    #[test]
+    fn test_parse_type_budgets() {
+        let budgets = parse_type_budgets("report=60s, export=2m").unwrap();
+        assert_eq!(budgets["report"], Duration::from_secs(60));
+        assert_eq!(budgets["export"], Duration::from_secs(120));
+        assert!(parse_type_budgets("report").is_err());
+        assert!(parse_type_budgets("report=fast").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::error::AppError;
+use crate::i18n::Message;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// 批注作者的最大长度（字符数）。
+pub const MAX_AUTHOR_LEN: usize = 64;
+/// 批注内容的最大长度（字符数）。
+pub const MAX_TEXT_LEN: usize = 2000;
+
+/// 运维人员为任务添加的一条批注，保存在 `task_annotations` 表中。
+///
+/// 批注只追加不修改，用于记录排查结论、处理决定等，与任务本身的状态无关。
+#[derive(Debug, Clone, Serialize)]
+pub struct Annotation {
+    pub id: i64,
+    pub task_id: Uuid,
+    pub author: String,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 一条尚未写入数据库的批注。
+#[derive(Debug, Clone)]
+pub struct NewAnnotation {
+    pub task_id: Uuid,
+    pub author: String,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl NewAnnotation {
+    /// 校验并创建批注：作者与内容去掉首尾空白后不能为空，且不能超过长度上限。
+    pub fn new(task_id: Uuid, author: &str, text: &str) -> Result<Self, AppError> {
+        let author = author.trim();
+        if author.is_empty() || author.chars().count() > MAX_AUTHOR_LEN {
+            return Err(AppError::Validation(
+                Message::new("INVALID_ANNOTATION_AUTHOR").arg("max", MAX_AUTHOR_LEN),
+            ));
+        }
+        let text = text.trim();
+        if text.is_empty() || text.chars().count() > MAX_TEXT_LEN {
+            return Err(AppError::Validation(
+                Message::new("INVALID_ANNOTATION_TEXT").arg("max", MAX_TEXT_LEN),
+            ));
+        }
+        Ok(Self {
+            task_id,
+            author: author.to_string(),
+            text: text.to_string(),
+            created_at: Utc::now(),
+        })
+    }
+}
+
+/// 按任务分组批注，每个任务的批注保持原有顺序（从旧到新）。
+pub fn group_by_task(annotations: Vec<Annotation>) -> HashMap<Uuid, Vec<Annotation>> {
+    let mut grouped: HashMap<Uuid, Vec<Annotation>> = HashMap::new();
+    for annotation in annotations {
+        grouped
+            .entry(annotation.task_id)
+            .or_default()
+            .push(annotation);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试批注的校验。
+    #[test]
+    fn test_validate() {
+        let id = Uuid::new_v4();
+        let annotation = NewAnnotation::new(id, " alice ", " 下游故障，已联系对方 ").unwrap();
+        assert_eq!(annotation.author, "alice");
+        assert_eq!(annotation.text, "下游故障，已联系对方");
+        assert!(NewAnnotation::new(id, "", "text").is_err());
+        assert!(NewAnnotation::new(id, "alice", "  ").is_err());
+        assert!(NewAnnotation::new(id, &"a".repeat(MAX_AUTHOR_LEN + 1), "text").is_err());
+        assert!(NewAnnotation::new(id, "alice", &"批".repeat(MAX_TEXT_LEN)).is_ok());
+        assert!(NewAnnotation::new(id, "alice", &"批".repeat(MAX_TEXT_LEN + 1)).is_err());
+    }
+
+    /// 测试批注写入数据库后按任务读取，且按写入顺序排列。
+    #[tokio::test]
+    async fn test_insert_and_list() {
+        let db = crate::db::test_database().await;
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        for (task_id, text) in [(first, "一"), (second, "二"), (first, "三")] {
+            let annotation = NewAnnotation::new(task_id, "alice", text).unwrap();
+            let stored = db.insert_annotation(&annotation).await.unwrap();
+            assert_eq!(stored.task_id, task_id);
+            assert_eq!(stored.text, text);
+        }
+
+        let grouped = group_by_task(db.annotations(&[first, second]).await.unwrap());
+        let texts =
+            |id: &Uuid| -> Vec<String> { grouped[id].iter().map(|a| a.text.clone()).collect() };
+        assert_eq!(texts(&first), ["一", "三"]);
+        assert_eq!(texts(&second), ["二"]);
+        assert!(db.annotations(&[Uuid::new_v4()]).await.unwrap().is_empty());
+        assert!(db.annotations(&[]).await.unwrap().is_empty());
+    }
+}
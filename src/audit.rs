@@ -0,0 +1,206 @@
+use crate::db::filter::{AuditCursor, AuditFilter};
+use crate::db::Database;
+use crate::metrics;
+use bytes::Bytes;
+use chrono::{DateTime, SubsecRound, Utc};
+use futures::Stream;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::Error as SqlxError;
+use std::fmt;
+
+/// 导出审计日志时每次从数据库读取的记录数。
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// 执行管理操作的调用方，由管理 API 的鉴权中间件根据令牌确定并放入请求扩展：
+/// `ADMIN_TOKEN` 为 `admin`，API 令牌为令牌 ID，JWT 为 `sub` 声明（缺省时为 `jwt`），
+/// 未配置任何令牌时为 `anonymous`。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Actor(pub String);
+
+impl Actor {
+    pub fn anonymous() -> Self {
+        Actor("anonymous".to_string())
+    }
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// 审计日志中的一条记录，保存在 `audit_log` 表中，只追加不修改。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor: String,
+    /// 操作类型，例如 `dlq.requeue`、`token.revoke`。
+    pub action: String,
+    /// 操作的对象（任务 ID、令牌 ID、任务类型等），没有单一对象的操作为 `None`。
+    pub target: Option<String>,
+    /// 操作的参数与结果。
+    pub detail: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 一条尚未写入数据库的审计记录。
+#[derive(Debug, Clone)]
+pub struct NewAuditEntry {
+    pub actor: String,
+    pub action: &'static str,
+    pub target: Option<String>,
+    pub detail: Value,
+    /// 精确到毫秒，与数据库中保存的精度以及翻页游标一致。
+    pub created_at: DateTime<Utc>,
+}
+
+/// 写入一条审计记录。操作本身已经完成，写入失败时只记录错误日志与 `audit_write_failures_total`，
+/// 不影响操作的响应。
+pub async fn record(
+    db: &Database,
+    actor: &Actor,
+    action: &'static str,
+    target: Option<String>,
+    detail: Value,
+) {
+    let entry = NewAuditEntry {
+        actor: actor.0.clone(),
+        action,
+        target,
+        detail,
+        created_at: Utc::now().trunc_subsecs(3),
+    };
+    if let Err(e) = db.insert_audit_entry(&entry).await {
+        metrics::counter("audit_write_failures_total").inc();
+        tracing::error!(action, actor = %actor, "写入审计日志失败: {}", e);
+    }
+}
+
+/// 按过滤条件返回一页审计记录，以及还有下一页时指向下一页的游标。
+pub async fn page(
+    db: &Database,
+    filter: &AuditFilter,
+    limit: i64,
+) -> Result<(Vec<AuditEntry>, Option<AuditCursor>), SqlxError> {
+    // 多取一条判断是否还有下一页
+    let mut entries = db.audit_entries(filter, limit + 1).await?;
+    let next = if entries.len() as i64 > limit {
+        entries.truncate(limit as usize);
+        entries.last().map(AuditCursor::after)
+    } else {
+        None
+    };
+    Ok((entries, next))
+}
+
+/// 以 NDJSON（每行一条 JSON 记录）导出满足过滤条件的所有审计记录。
+///
+/// 按游标分批读取数据库，导出大量记录时内存占用不随记录数增长；读取失败时流以错误结束。
+pub fn export(db: Database, filter: AuditFilter) -> impl Stream<Item = Result<Bytes, SqlxError>> {
+    futures::stream::try_unfold(Some(filter), move |filter| {
+        let db = db.clone();
+        async move {
+            let Some(mut filter) = filter else {
+                return Ok(None);
+            };
+            let (entries, next) = page(&db, &filter, EXPORT_PAGE_SIZE).await?;
+            let mut chunk = Vec::new();
+            for entry in &entries {
+                serde_json::to_writer(&mut chunk, entry)
+                    .map_err(|e| SqlxError::Decode(Box::new(e)))?;
+                chunk.push(b'\n');
+            }
+            let rest = next.map(|cursor| {
+                filter.after = Some(cursor);
+                filter
+            });
+            Ok(Some((Bytes::from(chunk), rest)))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::filter::AuditOrder;
+    use chrono::Duration;
+    use futures::TryStreamExt;
+    use serde_json::json;
+
+    async fn write(db: &Database, actor: &str, action: &'static str, at: DateTime<Utc>) {
+        let entry = NewAuditEntry {
+            actor: actor.to_string(),
+            action,
+            target: Some("t".to_string()),
+            detail: json!({ "n": 1 }),
+            created_at: at,
+        };
+        db.insert_audit_entry(&entry).await.unwrap();
+    }
+
+    /// 测试按调用方、操作类型与时间过滤，以及按游标翻页与导出。
+    #[tokio::test]
+    async fn test_page_and_export() {
+        let db = crate::db::test_database().await;
+        let start = Utc::now().trunc_subsecs(3);
+        for i in 0..5 {
+            write(&db, "key123", "dlq.requeue", start + Duration::seconds(i)).await;
+        }
+        // 同一毫秒内的记录按 ID 区分先后
+        write(&db, "key123", "token.revoke", start + Duration::seconds(4)).await;
+        write(&db, "admin", "dlq.requeue", start + Duration::seconds(2)).await;
+
+        let filter = AuditFilter {
+            actor: Some("key123".to_string()),
+            ..Default::default()
+        };
+        let (first, next) = page(&db, &filter, 4).await.unwrap();
+        assert_eq!(first.len(), 4);
+        assert_eq!(first[0].action, "token.revoke");
+        assert!(first
+            .windows(2)
+            .all(|w| (w[0].created_at, w[0].id) > (w[1].created_at, w[1].id)));
+        let cursor = next.unwrap();
+        assert_eq!(cursor.to_string().parse::<AuditCursor>().unwrap(), cursor);
+        let (second, next) = page(
+            &db,
+            &AuditFilter {
+                after: Some(cursor),
+                ..filter.clone()
+            },
+            4,
+        )
+        .await
+        .unwrap();
+        assert_eq!(second.len(), 2);
+        assert!(next.is_none());
+        assert_eq!(second[1].created_at, start);
+
+        let filter = AuditFilter {
+            action: Some("dlq.requeue".to_string()),
+            from: Some(start + Duration::seconds(1)),
+            to: Some(start + Duration::seconds(3)),
+            order: AuditOrder::Asc,
+            ..Default::default()
+        };
+        let (entries, _) = page(&db, &filter, 10).await.unwrap();
+        let actors: Vec<&str> = entries.iter().map(|e| e.actor.as_str()).collect();
+        assert_eq!(actors, ["key123", "key123", "admin"]);
+        assert_eq!(entries[0].detail, json!({ "n": 1 }));
+
+        let chunks: Vec<Bytes> = export(db.clone(), AuditFilter::default())
+            .try_collect()
+            .await
+            .unwrap();
+        let lines: Vec<Value> = chunks
+            .concat()
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0]["action"], "token.revoke");
+        assert!("123".parse::<AuditCursor>().is_err());
+    }
+}
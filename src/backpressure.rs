@@ -0,0 +1,104 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 无法估算处理速度时建议客户端等待的时长。
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(30);
+/// `Retry-After` 的上限，避免处理停滞时给出过长的等待时间。
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// 调度器的出队速度统计，按秒分桶的滑动窗口。
+pub struct Throughput {
+    window: Duration,
+    origin: Instant,
+    /// `(秒序号, 出队数)`。
+    buckets: Mutex<VecDeque<(u64, u64)>>,
+}
+
+impl Throughput {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window: window.max(Duration::from_secs(1)),
+            origin: Instant::now(),
+            buckets: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// 记录调度器取出了一个任务。
+    pub fn record(&self) {
+        let now = self.origin.elapsed().as_secs();
+        let mut buckets = self.lock(now);
+        match buckets.back_mut() {
+            Some((sec, count)) if *sec == now => *count += 1,
+            _ => buckets.push_back((now, 1)),
+        }
+    }
+
+    /// 窗口内平均每秒取出的任务数。启动不满一个窗口时按已运行的秒数计算。
+    pub fn per_second(&self) -> f64 {
+        let now = self.origin.elapsed().as_secs();
+        let buckets = self.lock(now);
+        let total: u64 = buckets.iter().map(|(_, count)| count).sum();
+        // 当前这一秒也算一个桶
+        let span = (now + 1).min(self.window.as_secs());
+        total as f64 / span as f64
+    }
+
+    /// 丢弃窗口外的桶并返回持有锁的桶列表。
+    fn lock(&self, now: u64) -> std::sync::MutexGuard<'_, VecDeque<(u64, u64)>> {
+        let window = self.window.as_secs();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        while buckets
+            .front()
+            .is_some_and(|(sec, _)| now.saturating_sub(*sec) >= window)
+        {
+            buckets.pop_front();
+        }
+        buckets
+    }
+
+    /// 排在前面的 `ahead` 个任务处理完所需的时间；还没有处理速度数据时返回 `None`。
+    pub fn estimate(&self, ahead: usize) -> Option<Duration> {
+        let rate = self.per_second();
+        (rate > 0.0).then(|| Duration::from_secs_f64(ahead as f64 / rate))
+    }
+
+    /// 队列超出容量 `excess` 个任务时，建议客户端重试前等待的时长（整秒，1–300 秒）。
+    pub fn retry_after(&self, excess: usize) -> Duration {
+        let wait = self.estimate(excess).unwrap_or(DEFAULT_RETRY_AFTER);
+        Duration::from_secs(wait.as_secs_f64().ceil() as u64)
+            .clamp(Duration::from_secs(1), MAX_RETRY_AFTER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试根据出队速度估算等待时间与 `Retry-After`。
+    #[tokio::test(start_paused = true)]
+    async fn test_throughput_estimate() {
+        let throughput = Throughput::new(Duration::from_secs(10));
+        assert_eq!(throughput.estimate(100), None);
+        assert_eq!(throughput.retry_after(100), DEFAULT_RETRY_AFTER);
+
+        // 10 秒内每秒取出 5 个任务
+        for second in 0..10 {
+            if second > 0 {
+                tokio::time::advance(Duration::from_secs(1)).await;
+            }
+            for _ in 0..5 {
+                throughput.record();
+            }
+        }
+        assert_eq!(throughput.per_second(), 5.0);
+        assert_eq!(throughput.estimate(50), Some(Duration::from_secs(10)));
+        assert_eq!(throughput.retry_after(1), Duration::from_secs(1));
+        assert_eq!(throughput.retry_after(100_000), MAX_RETRY_AFTER);
+
+        // 窗口滑过后没有新数据
+        tokio::time::advance(Duration::from_secs(11)).await;
+        assert_eq!(throughput.estimate(1), None);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::config::Config;
+use crate::db::{self, check_schema, Database};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+
+/// 连接数据库的最长等待时间，超时视为数据库不可达。
+const DB_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 一项检查的结果。
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn passed(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: true,
+            detail: detail.into(),
+        }
+    }
+
+    fn failed(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// `check-config` 的检查报告，`ok` 为所有检查都通过。
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub ok: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn new(checks: Vec<Check>) -> Self {
+        Self {
+            ok: checks.iter().all(|check| check.ok),
+            checks,
+        }
+    }
+}
+
+/// 执行 `check-config`：加载并校验完整的配置，检查日志目录与结果目录可写，
+/// 以只读方式连接数据库并检查表结构。
+///
+/// 不启动监听器与后台任务，不执行迁移，也不写入数据库，适合在部署流水线中于滚动发布之前运行。
+/// 配置无法加载时其余检查无从进行，报告中只有这一项。
+pub async fn run(log_directory: &Path) -> Report {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return Report::new(vec![Check::failed("config", e.to_string())]),
+    };
+    let mut checks = vec![Check::passed(
+        "config",
+        format!("配置有效，监听 {} 个地址", config.listeners().len()),
+    )];
+    if config.log_file.enabled {
+        checks.push(check_writable_dir("log_directory", log_directory).await);
+    }
+    checks.push(check_writable_dir("result_store_dir", &config.result_store_dir).await);
+    // 表名前缀必须在执行任何查询之前设置
+    db::set_table_prefix(&config.table_prefix);
+    checks.extend(check_database(&config).await);
+    Report::new(checks)
+}
+
+/// 检查目录可写；目录还不存在时（启动时会创建）检查最近的已存在的上级目录。
+///
+/// 通过创建并删除一个临时文件判断是否可写，不会留下文件。
+async fn check_writable_dir(name: &'static str, dir: &Path) -> Check {
+    let existing = dir
+        .ancestors()
+        .map(|path| {
+            if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path
+            }
+        })
+        .find(|path| path.exists())
+        .unwrap_or(Path::new("."));
+    if !existing.is_dir() {
+        return Check::failed(name, format!("{} 不是目录", existing.display()));
+    }
+    let probe: PathBuf = existing.join(format!(".check-config-{}", uuid::Uuid::new_v4()));
+    if let Err(e) = fs::write(&probe, b"").await {
+        return Check::failed(name, format!("{} 不可写: {}", existing.display(), e));
+    }
+    let _ = fs::remove_file(&probe).await;
+    if existing == dir {
+        Check::passed(name, format!("{} 可写", dir.display()))
+    } else {
+        Check::passed(
+            name,
+            format!(
+                "{} 不存在，启动时将在可写的 {} 下创建",
+                dir.display(),
+                existing.display()
+            ),
+        )
+    }
+}
+
+/// 以只读方式连接数据库并检查表结构，返回 `database` 与 `schema` 两项检查。
+async fn check_database(config: &Config) -> Vec<Check> {
+    let settings = db::PoolSettings {
+        read_only: true,
+        ..config.pool_settings()
+    };
+    let connect = Database::connect(config.db_mode, &config.database_url, &settings);
+    let db = match tokio::time::timeout(DB_CHECK_TIMEOUT, connect).await {
+        Ok(Ok(db)) => db,
+        Ok(Err(e)) => return vec![Check::failed("database", format!("无法连接数据库: {}", e))],
+        Err(_) => {
+            return vec![Check::failed(
+                "database",
+                format!("连接数据库超时（{:?}）", DB_CHECK_TIMEOUT),
+            )]
+        }
+    };
+    let database = match db.ping().await {
+        Ok(latency) => Check::passed(
+            "database",
+            format!("{} 可以连接，延迟 {:?}", db.backend_name(), latency),
+        ),
+        Err(e) => return vec![Check::failed("database", format!("数据库探活失败: {}", e))],
+    };
+    let check = check_schema(&db).await;
+    let schema = Check {
+        name: "schema",
+        ok: check.ok,
+        detail: check.message,
+    };
+    vec![database, schema]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DbMode;
+
+    /// 测试已存在、尚不存在与不是目录的路径。
+    #[tokio::test]
+    async fn test_check_writable_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let check = check_writable_dir("logs", dir.path()).await;
+        assert!(check.ok, "{}", check.detail);
+
+        let missing = dir.path().join("a/b");
+        let check = check_writable_dir("logs", &missing).await;
+        assert!(check.ok, "{}", check.detail);
+        assert!(check.detail.contains("不存在"));
+        assert!(!missing.exists());
+
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+        assert!(!check_writable_dir("logs", &file.join("logs")).await.ok);
+        // 探测文件已被删除
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    /// 测试数据库检查：内存数据库总是通过，只读模式不会创建不存在的 SQLite 数据库文件。
+    #[tokio::test]
+    async fn test_check_database() {
+        let config = Config {
+            db_mode: DbMode::Memory,
+            ..Default::default()
+        };
+        let checks = check_database(&config).await;
+        assert!(checks.iter().all(|check| check.ok));
+
+        #[cfg(feature = "sqlite")]
+        {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("app.db");
+            let config = Config {
+                db_mode: DbMode::Sqlite,
+                database_url: format!("sqlite://{}?mode=rwc", path.display()),
+                ..Default::default()
+            };
+            let checks = check_database(&config).await;
+            assert_eq!(checks.len(), 1);
+            assert_eq!((checks[0].name, checks[0].ok), ("database", false));
+            assert!(!path.exists());
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use crate::metrics;
+use crate::queue::QueueBackend;
+use crate::status::TaskIndex;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 每次轮询最多认领的任务数，避免一次接管过多任务。
+const CLAIM_BATCH: i64 = 100;
+
+/// 定期续约本实例认领的排队任务，并接管失联实例留下的任务。
+///
+/// 间隔应明显短于认领租约，否则存活实例的任务可能在续约之前被其他实例认领。
+/// 接管的任务与启动时恢复的任务一样归入默认租户。
+pub async fn run_claim_poller(queue: Arc<dyn QueueBackend>, tasks: TaskIndex, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match queue.claim_orphans(CLAIM_BATCH).await {
+            Ok(claimed) if !claimed.is_empty() => {
+                for task in &claimed {
+                    tasks.insert_queued(task.id, task.priority, task.tenant_or_default());
+                }
+                metrics::counter("queue_tasks_taken_over_total").add(claimed.len() as u64);
+                tracing::warn!(count = claimed.len(), "已接管失联实例留下的排队任务");
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("续约或认领排队任务失败: {}", e),
+        }
+    }
+}
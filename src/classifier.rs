@@ -0,0 +1,278 @@
+use crate::metrics;
+use crate::queue::{Task, TaskKind};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// 每种任务类型保留的最近执行耗时样本数。
+const MAX_SAMPLES: usize = 256;
+/// 载荷中没有 `type` 字段的任务归入的类型。
+pub const DEFAULT_TASK_TYPE: &str = "default";
+
+/// 慢速任务分类的配置。
+#[derive(Debug, Clone)]
+pub struct ClassifierSettings {
+    /// p95 执行耗时超过该值的任务类型被归为慢速任务。
+    pub threshold: Duration,
+    /// 样本数达到该值后才根据耗时分类，之前按快速任务处理。
+    pub min_samples: usize,
+    /// 手动指定的分类，`true` 表示慢速，优先于自动分类。
+    pub overrides: HashMap<String, bool>,
+}
+
+impl Default for ClassifierSettings {
+    fn default() -> Self {
+        Self {
+            threshold: Duration::from_secs(2),
+            min_samples: 20,
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+/// 解析手动分类，格式为逗号分隔的 `type=slow` 或 `type=fast`。
+pub fn parse_overrides(list: &str) -> Result<HashMap<String, bool>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|rule| {
+            let (task_type, class) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("{}: 格式应为 type=slow 或 type=fast", rule))?;
+            let slow = match class.trim() {
+                "slow" => true,
+                "fast" => false,
+                other => return Err(format!("{}: 未知的分类 {}（可选 slow/fast）", rule, other)),
+            };
+            Ok((task_type.trim().to_string(), slow))
+        })
+        .collect()
+}
+
+/// 任务的类型：自定义执行方式的名称，否则取自载荷顶层的 `type` 字符串字段。
+pub fn task_type(task: &Task) -> &str {
+    kind_type(&task.kind, &task.payload)
+}
+
+/// 以 `kind` 方式执行 `payload` 时的任务类型。
+pub fn kind_type<'a>(kind: &'a TaskKind, payload: &'a Value) -> &'a str {
+    match kind {
+        TaskKind::Custom(name) => name,
+        _ => payload_type(payload),
+    }
+}
+
+/// 载荷对应的任务类型，没有 `type` 字段时为 `DEFAULT_TASK_TYPE`。
+pub fn payload_type(payload: &Value) -> &str {
+    payload
+        .get("type")
+        .and_then(|v| v.as_str())
+        .unwrap_or(DEFAULT_TASK_TYPE)
+}
+
+/// 单个任务类型的统计。
+#[derive(Default)]
+struct TypeStats {
+    samples: VecDeque<Duration>,
+    /// 最近一次计算出的 p95。
+    p95: Duration,
+}
+
+/// 单个任务类型的分类结果，用于管理接口展示。
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeClassification {
+    pub task_type: String,
+    pub samples: usize,
+    pub p95_ms: u64,
+    /// 样本不足时为 `None`，按快速任务处理。
+    pub slow: Option<bool>,
+    /// 分类来源：`override`、`measured` 或 `unmeasured`（样本不足）。
+    pub source: &'static str,
+}
+
+/// 根据各任务类型的执行耗时自动区分快速任务与慢速任务。
+///
+/// 调度器在每个任务完成后调用 `record` 记录耗时，分发任务前调用 `is_slow` 决定
+/// 在调度循环内处理还是交给独立的 Tokio 任务处理。提交时指定了 `quick` 或 `slow` 的任务不参与自动分类；
+/// 其余任务中配置的手动分类优先，样本不足的类型按快速任务处理。
+pub struct SlowClassifier {
+    settings: ClassifierSettings,
+    stats: Mutex<HashMap<String, TypeStats>>,
+}
+
+impl SlowClassifier {
+    pub fn new(settings: ClassifierSettings) -> Self {
+        Self {
+            settings,
+            stats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 任务是否应按慢速任务处理。
+    pub fn is_slow(&self, task: &Task) -> bool {
+        match task.kind {
+            TaskKind::Quick => return false,
+            TaskKind::Slow => return true,
+            TaskKind::Auto | TaskKind::Custom(_) => {}
+        }
+        let task_type = task_type(task);
+        if let Some(slow) = self.settings.overrides.get(task_type) {
+            return *slow;
+        }
+        let stats = self.lock();
+        match stats.get(task_type) {
+            Some(s) if s.samples.len() >= self.settings.min_samples => {
+                s.p95 > self.settings.threshold
+            }
+            _ => false,
+        }
+    }
+
+    /// 任务类型的预计耗时，用于慢速任务的准入控制：样本足够时取 p95，否则取慢速任务的阈值。
+    pub fn estimated_cost(&self, task_type: &str) -> Duration {
+        match self.lock().get(task_type) {
+            Some(s) if s.samples.len() >= self.settings.min_samples => s.p95,
+            _ => self.settings.threshold,
+        }
+    }
+
+    /// 记录一次执行耗时并更新该类型的 p95。
+    pub fn record(&self, task_type: &str, elapsed: Duration) {
+        let mut stats = self.lock();
+        let entry = stats.entry(task_type.to_string()).or_default();
+        if entry.samples.len() == MAX_SAMPLES {
+            entry.samples.pop_front();
+        }
+        entry.samples.push_back(elapsed);
+
+        let mut sorted: Vec<Duration> = entry.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        entry.p95 = sorted[(sorted.len() * 95).div_ceil(100) - 1];
+        metrics::gauge_with_labels("task_type_duration_p95_ms", &[("type", task_type)])
+            .set(entry.p95.as_secs_f64() * 1000.0);
+    }
+
+    /// 所有已知任务类型（有样本或有手动分类）的当前分类。
+    pub fn snapshot(&self) -> Vec<TypeClassification> {
+        let stats = self.lock();
+        let mut types: Vec<&String> = stats.keys().chain(self.settings.overrides.keys()).collect();
+        types.sort();
+        types.dedup();
+        types
+            .into_iter()
+            .map(|task_type| {
+                let (samples, p95) = stats
+                    .get(task_type)
+                    .map_or((0, Duration::ZERO), |s| (s.samples.len(), s.p95));
+                let (slow, source) = match self.settings.overrides.get(task_type) {
+                    Some(slow) => (Some(*slow), "override"),
+                    None if samples >= self.settings.min_samples => {
+                        (Some(p95 > self.settings.threshold), "measured")
+                    }
+                    None => (None, "unmeasured"),
+                };
+                TypeClassification {
+                    task_type: task_type.clone(),
+                    samples,
+                    p95_ms: p95.as_millis() as u64,
+                    slow,
+                    source,
+                }
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, TypeStats>> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn task(task_type: &str, kind: TaskKind) -> Task {
+        Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "type": task_type }).into(),
+            priority: 10,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind,
+            depends_on: Default::default(),
+            tenant: None,
+        }
+    }
+
+    /// 测试样本足够后按 p95 自动分类，手动分类优先，提交时指定的执行方式优先于自动分类。
+    #[test]
+    fn test_classification() {
+        let classifier = SlowClassifier::new(ClassifierSettings {
+            threshold: Duration::from_millis(100),
+            min_samples: 10,
+            overrides: parse_overrides("ping=slow").unwrap(),
+        });
+
+        // 样本不足时按快速任务处理，不受优先级影响
+        assert!(!classifier.is_slow(&task("report", TaskKind::Auto)));
+        assert!(classifier.is_slow(&task("report", TaskKind::Slow)));
+
+        // 少数几次快速执行不会把 p95 拉到阈值以下
+        for _ in 0..18 {
+            classifier.record("report", Duration::from_millis(500));
+        }
+        for _ in 0..2 {
+            classifier.record("report", Duration::from_millis(5));
+        }
+        assert!(classifier.is_slow(&task("report", TaskKind::Auto)));
+        assert!(!classifier.is_slow(&task("report", TaskKind::Quick)));
+        // 自定义执行方式按其名称分类
+        let custom = task("email", TaskKind::Custom("report".to_string()));
+        assert_eq!(task_type(&custom), "report");
+        assert!(classifier.is_slow(&custom));
+        // 预计耗时：样本足够时取 p95，否则取阈值
+        assert_eq!(
+            classifier.estimated_cost("report"),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            classifier.estimated_cost("export"),
+            Duration::from_millis(100)
+        );
+
+        for _ in 0..20 {
+            classifier.record("email", Duration::from_millis(5));
+        }
+        assert!(!classifier.is_slow(&task("email", TaskKind::Auto)));
+
+        classifier.record("ping", Duration::from_millis(1));
+        assert!(classifier.is_slow(&task("ping", TaskKind::Auto)));
+
+        let snapshot = classifier.snapshot();
+        let sources: Vec<_> = snapshot
+            .iter()
+            .map(|c| (c.task_type.as_str(), c.source))
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                ("email", "measured"),
+                ("ping", "override"),
+                ("report", "measured")
+            ]
+        );
+    }
+
+    /// 测试无效的手动分类会被拒绝。
+    #[test]
+    fn test_parse_overrides() {
+        assert!(parse_overrides("a=slow, b=fast").is_ok());
+        assert!(parse_overrides("a=medium").is_err());
+        assert!(parse_overrides("a").is_err());
+    }
+}
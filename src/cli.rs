@@ -0,0 +1,292 @@
+use anyhow::{anyhow, bail, Context};
+use reqwest::{Method, Url};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// 命令行帮助。
+const USAGE: &str = "\
+用法: web_server [子命令]
+
+不带子命令时启动服务。
+  check-config                       校验配置、日志目录与数据库（只读连接、检查表结构）后退出，
+                                     输出检查报告，有问题时退出码为 1
+  simulate <负载描述> [--workers 4,8]  在模拟时钟上用调度器与队列回放负载，输出各工作者数量下
+                                     预测的队列深度与延迟分位数（需要以 fixtures feature 编译）
+
+以下子命令通过管理 API 操作正在运行的实例：
+  stats                              队列、数据库、后台任务概况与各租户的任务统计
+  pause                              暂停分发任务（仍然接收新任务，正在处理的任务继续完成）
+  resume                             恢复分发任务
+  drain                              排空后以退出码 75 退出（滚动重启）
+  dead-letter list [--limit N]       列出死信队列中的任务
+  dead-letter requeue <id>...        将死信任务重新入队
+
+管理 API 的地址取自 ADMIN_URL（例如 http://127.0.0.1:9000），未设置时使用 ADMIN_ADDRESS 中的第一个地址；
+配置了 ADMIN_TOKEN 时随请求携带。";
+
+/// 通过管理 API 执行的子命令。
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Stats,
+    Pause,
+    Resume,
+    Drain,
+    DeadLetterList { limit: Option<u32> },
+    DeadLetterRequeue { ids: Vec<Uuid> },
+}
+
+/// 解析命令行参数（不含程序名）；不是管理子命令（启动服务、`check-config`、`simulate` 或 `bench`）时返回 `None`。
+pub fn parse(args: &[String]) -> Result<Option<Command>, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let command = match args.as_slice() {
+        [] | ["check-config"] | ["simulate", ..] | ["bench", ..] => return Ok(None),
+        ["help" | "-h" | "--help"] => Command::Help,
+        ["stats"] => Command::Stats,
+        ["drain"] => Command::Drain,
+        ["pause"] => Command::Pause,
+        ["resume"] => Command::Resume,
+        ["dead-letter", "list"] => Command::DeadLetterList { limit: None },
+        ["dead-letter", "list", "--limit", limit] => Command::DeadLetterList {
+            limit: Some(
+                limit
+                    .parse()
+                    .map_err(|_| format!("--limit 必须是正整数，当前值: {}", limit))?,
+            ),
+        },
+        ["dead-letter", "requeue", ids @ ..] if !ids.is_empty() => Command::DeadLetterRequeue {
+            ids: ids
+                .iter()
+                .map(|id| id.parse().map_err(|_| format!("无效的任务 ID: {}", id)))
+                .collect::<Result<_, _>>()?,
+        },
+        _ => return Err(format!("无法识别的参数: {}\n\n{}", args.join(" "), USAGE)),
+    };
+    Ok(Some(command))
+}
+
+/// 管理 API 的客户端。
+pub struct AdminClient {
+    http: reqwest::Client,
+    base: Url,
+    token: Option<String>,
+}
+
+impl AdminClient {
+    pub fn new(base: &str, token: Option<String>) -> anyhow::Result<Self> {
+        let base = Url::parse(base).with_context(|| format!("管理 API 地址无效: {}", base))?;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            base,
+            token,
+        })
+    }
+
+    /// 根据 `ADMIN_URL`（或 `ADMIN_ADDRESS`）与 `ADMIN_TOKEN` 创建客户端，会先读取 `.env`。
+    pub fn from_env() -> anyhow::Result<Self> {
+        dotenvy::dotenv().ok();
+        let base = match std::env::var("ADMIN_URL") {
+            Ok(url) if !url.trim().is_empty() => url.trim().to_string(),
+            _ => std::env::var("ADMIN_ADDRESS")
+                .ok()
+                .and_then(|list| {
+                    list.split(',')
+                        .map(str::trim)
+                        .find(|address| !address.is_empty())
+                        .map(|address| format!("http://{}", address))
+                })
+                .ok_or_else(|| anyhow!("必须设置 ADMIN_URL 或 ADMIN_ADDRESS"))?,
+        };
+        let token = std::env::var("ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.trim().is_empty());
+        Self::new(&base, token)
+    }
+
+    /// 发送请求并返回 JSON 响应体；非 2xx 的响应按服务端返回的错误码与消息报错。
+    async fn request(&self, method: Method, path: &str) -> anyhow::Result<Value> {
+        let url = self
+            .base
+            .join(path)
+            .with_context(|| format!("无效的路径: {}", path))?;
+        let mut request = self.http.request(method, url.clone());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("无法连接管理 API {}", url))?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            bail!(
+                "{} {}: [{}] {}",
+                status.as_u16(),
+                url.path(),
+                body["code"].as_str().unwrap_or("-"),
+                body["error"].as_str().unwrap_or("")
+            );
+        }
+        Ok(body)
+    }
+
+    /// 执行子命令，返回要输出的结果。
+    pub async fn run(&self, command: &Command) -> anyhow::Result<Value> {
+        match command {
+            Command::Help => Ok(Value::String(USAGE.to_string())),
+            Command::Stats => {
+                let status = self.request(Method::GET, "/admin/status").await?;
+                let tenants = self.request(Method::GET, "/stats/tenants").await?;
+                Ok(json!({ "status": status, "tenants": tenants["tenants"] }))
+            }
+            Command::Pause => self.request(Method::POST, "/admin/pause").await,
+            Command::Resume => self.request(Method::POST, "/admin/resume").await,
+            Command::Drain => self.request(Method::POST, "/admin/restart-intent").await,
+            Command::DeadLetterList { limit } => {
+                let path = match limit {
+                    Some(limit) => format!("/dlq?limit={}", limit),
+                    None => "/dlq".to_string(),
+                };
+                self.request(Method::GET, &path).await
+            }
+            Command::DeadLetterRequeue { ids } => {
+                // 逐个重新入队，某个任务失败时记录错误并继续处理其余的任务
+                let mut results = Vec::with_capacity(ids.len());
+                for id in ids {
+                    let path = format!("/dlq/{}/requeue", id);
+                    results.push(match self.request(Method::POST, &path).await {
+                        Ok(body) => json!({ "id": id, "ok": true, "task": body }),
+                        Err(e) => json!({ "id": id, "ok": false, "error": e.to_string() }),
+                    });
+                }
+                Ok(json!({ "results": results }))
+            }
+        }
+    }
+}
+
+/// 执行管理子命令并将结果输出到标准输出；有任何操作失败时返回错误。
+pub async fn run(command: Command) -> anyhow::Result<()> {
+    if command == Command::Help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let client = AdminClient::from_env()?;
+    let output = client.run(&command).await?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    if output["results"]
+        .as_array()
+        .is_some_and(|results| results.iter().any(|r| r["ok"] == false))
+    {
+        bail!("部分死信任务重新入队失败");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        extract::Path,
+        http::{HeaderMap, StatusCode},
+        routing::{get, post},
+        Json, Router,
+    };
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// 测试子命令的解析。
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(&args("")), Ok(None));
+        assert_eq!(parse(&args("bench")), Ok(None));
+        assert_eq!(parse(&args("simulate load.toml")), Ok(None));
+        assert_eq!(parse(&args("check-config")), Ok(None));
+        assert_eq!(parse(&args("stats")), Ok(Some(Command::Stats)));
+        assert_eq!(parse(&args("drain")), Ok(Some(Command::Drain)));
+        assert_eq!(
+            parse(&args("dead-letter list --limit 5")),
+            Ok(Some(Command::DeadLetterList { limit: Some(5) }))
+        );
+        let id = Uuid::new_v4();
+        assert_eq!(
+            parse(&args(&format!("dead-letter requeue {}", id))),
+            Ok(Some(Command::DeadLetterRequeue { ids: vec![id] }))
+        );
+        assert!(parse(&args("dead-letter requeue")).is_err());
+        assert!(parse(&args("dead-letter requeue abc")).is_err());
+        assert!(parse(&args("dead-letter list --limit x")).is_err());
+        assert_eq!(parse(&args("pause")), Ok(Some(Command::Pause)));
+        assert_eq!(parse(&args("resume")), Ok(Some(Command::Resume)));
+        assert!(parse(&args("pause now")).is_err());
+        assert!(parse(&args("restart")).is_err());
+    }
+
+    /// 测试客户端携带令牌访问管理 API，并把服务端的错误码带到错误信息中。
+    #[tokio::test]
+    async fn test_requeue_over_admin_api() {
+        let known = Uuid::new_v4();
+        let router = Router::new()
+            .route(
+                "/dlq",
+                get(|| async { Json(json!({ "size": 0, "tasks": [] })) }),
+            )
+            .route(
+                "/dlq/:id/requeue",
+                post(move |Path(id): Path<Uuid>, headers: HeaderMap| async move {
+                    if headers.get("authorization").and_then(|v| v.to_str().ok())
+                        != Some("Bearer secret")
+                    {
+                        return (
+                            StatusCode::UNAUTHORIZED,
+                            Json(json!({ "code": "INVALID_ADMIN_TOKEN", "error": "令牌无效" })),
+                        );
+                    }
+                    if id == known {
+                        (StatusCode::ACCEPTED, Json(json!({ "id": id })))
+                    } else {
+                        (
+                            StatusCode::NOT_FOUND,
+                            Json(
+                                json!({ "code": "DEAD_TASK_NOT_FOUND", "error": "不在死信队列中" }),
+                            ),
+                        )
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let client = AdminClient::new(&base, Some("secret".to_string())).unwrap();
+        let list = client
+            .run(&Command::DeadLetterList { limit: Some(10) })
+            .await
+            .unwrap();
+        assert_eq!(list["size"], 0);
+
+        let missing = Uuid::new_v4();
+        let output = client
+            .run(&Command::DeadLetterRequeue {
+                ids: vec![known, missing],
+            })
+            .await
+            .unwrap();
+        assert_eq!(output["results"][0]["ok"], true);
+        assert_eq!(output["results"][1]["ok"], false);
+        assert!(output["results"][1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("DEAD_TASK_NOT_FOUND"));
+
+        let anonymous = AdminClient::new(&base, None).unwrap();
+        let error = anonymous
+            .request(Method::POST, &format!("/dlq/{}/requeue", known))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("INVALID_ADMIN_TOKEN"));
+    }
+}
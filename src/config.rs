@@ -1,43 +1,1289 @@
+use crate::admission::{self, AdmissionSettings};
+use crate::classifier::{self, ClassifierSettings};
+use crate::config_file;
+use crate::context::HeaderPropagation;
+use crate::db::{self, CircuitSettings, ConnectRetry, DbMode, PoolSettings};
+use crate::decompress::RequestBodySettings;
 use crate::error::AppError;
+#[cfg(feature = "fixtures")]
+use crate::fixtures::WorkloadSpec;
+use crate::handler::BuiltinMiddleware;
+use crate::ids::{self, IdFormat};
+use crate::jwt::{JwtKey, JwtSettings, JwtVerifier};
+use crate::limits::{self, PayloadLimits};
+use crate::logging::{LogFormat, SinkSettings};
+use crate::outbound::{self, ProxySettings};
+use crate::policy::{PolicyFailureMode, PolicySettings};
+use crate::quarantine::QuarantineSettings;
+use crate::queue::{BandCapacities, CompressionSettings, QueueBackendKind, StarvationThresholds};
+use crate::rate_limit::RateLimitSettings;
+use crate::retry_budget::RetryBudgetSettings;
+use crate::slo::{self, SloSettings};
+use crate::units;
+use crate::validation::{SchemaMode, SubmissionRules};
+use crate::watchdog::WatchdogSettings;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// 慢查询阈值的默认值。
+const DEFAULT_DB_SLOW_QUERY: Duration = Duration::from_millis(500);
+/// 数据库查询超时的默认值。
+const DEFAULT_DB_STATEMENT_TIMEOUT: Duration = Duration::from_secs(30);
+/// 为关键任务预留的连接比例的默认值（百分比）。
+const DEFAULT_DB_CRITICAL_RESERVED_PERCENT: u64 = 20;
+/// 日志订阅缓冲保留的最近日志条数的默认值。
+const DEFAULT_LOG_TAIL_BUFFER: u64 = 1000;
+/// 写入日志文件失败后重试的默认间隔。
+const DEFAULT_LOG_FILE_RETRY: Duration = Duration::from_secs(30);
+/// 数据库连接最长存活时间的默认值。
+const DEFAULT_DB_MAX_LIFETIME: Duration = Duration::from_secs(30 * 60);
+/// 数据库空闲连接超时的默认值。
+const DEFAULT_DB_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// 数据库连接池最大连接数的默认值（与 sqlx 的默认值相同）。
+const DEFAULT_DB_MAX_CONNECTIONS: u64 = 10;
+/// 从数据库连接池借出连接的最长等待时间的默认值（与 sqlx 的默认值相同）。
+const DEFAULT_DB_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(30);
+/// 启动时单次连接数据库的超时时间的默认值。
+const DEFAULT_DB_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// 启动时连接数据库两次重试之间最长等待时间的默认值。
+const DEFAULT_DB_CONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// 数据库熔断器打开所需的连续失败次数的默认值。
+const DEFAULT_DB_CIRCUIT_FAILURE_THRESHOLD: u64 = 5;
+/// 数据库熔断器打开后到下一次探测的等待时间的默认值。
+const DEFAULT_DB_CIRCUIT_OPEN: Duration = Duration::from_secs(10);
+/// 数据库连接池健康检查间隔的默认值。
+const DEFAULT_DB_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// 后台任务在时间窗口内允许的最大重启次数的默认值。
+const DEFAULT_SUPERVISOR_MAX_RESTARTS: u64 = 5;
+/// 调度器心跳过期阈值的默认值。
+const DEFAULT_SCHEDULER_STALL_THRESHOLD: Duration = Duration::from_secs(30);
+/// 计算调度器处理速度的时间窗口的默认值。
+const DEFAULT_THROUGHPUT_WINDOW: Duration = Duration::from_secs(60);
+/// 饥饿检测扫描间隔的默认值。
+const DEFAULT_STARVATION_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// 排队任务认领租约的默认值。
+const DEFAULT_QUEUE_CLAIM_LEASE: Duration = Duration::from_secs(60);
+/// 任务类型配置的默认缓存时间。
+/// 默认同时处理的慢速任务数量上限。
+const DEFAULT_MAX_SLOW_TASKS: u64 = 32;
+const DEFAULT_TASK_TYPE_CONFIG_TTL: Duration = Duration::from_secs(10);
+/// 未单独配置超时时间的任务类型默认不限时。
+const DEFAULT_TASK_TIMEOUT: Duration = Duration::ZERO;
+/// 校验 JWT 的过期时间时默认允许的时钟偏差。
+const DEFAULT_JWT_LEEWAY: Duration = Duration::from_secs(60);
+/// 策略服务单次决策请求默认的超时时间。
+const DEFAULT_POLICY_TIMEOUT: Duration = Duration::from_millis(500);
+/// 策略决策默认的缓存时间。
+const DEFAULT_POLICY_CACHE_TTL: Duration = Duration::from_secs(10);
+/// API 令牌校验结果的默认缓存时间。
+const DEFAULT_API_TOKEN_CACHE_TTL: Duration = Duration::from_secs(30);
+/// 实例标识的最大长度，与 `tasks_queue.claimed_by` 列的长度一致。
+const MAX_INSTANCE_ID_LEN: usize = 64;
+/// 任务结果保存目录的默认值（相对于工作目录）。
+const DEFAULT_RESULT_STORE_DIR: &str = "results";
+/// 默认在重启之间保持连续的计数器：处理完成（按租户与结果）、处理器执行与失败、进入死信队列的任务数。
+const DEFAULT_PERSISTED_METRICS: &[&str] = &[
+    "tasks_completed_total",
+    "task_handler_runs_total",
+    "task_handler_failures_total",
+    "dlq_tasks_total",
+];
+/// Redis 队列默认的键前缀。
+const DEFAULT_REDIS_QUEUE_PREFIX: &str = "webserver:queue:";
+/// 以 `_MS` 结尾的配置项不带单位时的单位。
+const MILLIS: Duration = Duration::from_millis(1);
+/// 以 `_SECS` 结尾的配置项不带单位时的单位。
+const SECS: Duration = Duration::from_secs(1);
+
+/// 监听器的角色，决定该监听地址上挂载哪一组路由。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerRole {
+    /// 对外公开的业务 API。
+    Public,
+    /// 仅供内部运维使用的管理 API。
+    Admin,
+}
+
+/// 单个监听器的配置：监听地址及其角色。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerConfig {
+    pub role: ListenerRole,
+    pub address: String,
+}
 
 /// 应用配置结构体，存储从环境变量加载的配置项。
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Config {
     /// 服务器监听地址，例如 "127.0.0.1:3000"。
+    /// 可以用逗号分隔多个地址，以便同时监听多个地址/端口。
     pub server_address: String,
-    /// 数据库连接字符串。
+    /// 管理 API 的监听地址（可选），同样支持逗号分隔的多个地址。
+    /// 未设置时不启动管理 API，管理路由也不会暴露在公开端口上。
+    pub admin_address: Option<String>,
+    /// 公开 API 的路径前缀，例如 `/jobs`；空字符串表示挂在根路径下。
+    /// 部署在网关的某个路径之后时使用，管理 API 不受影响。
+    pub base_path: String,
+    /// 是否将所有 JSON 响应包装为 `{"data", "meta", "errors"}` 信封，默认不包装。
+    pub response_envelope: bool,
+    /// 管理 API 的访问令牌（可选）。
+    /// 设置后所有管理接口都需要携带 `Authorization: Bearer <token>`；
+    /// 执行数据库迁移等变更操作时必须设置。
+    pub admin_token: Option<String>,
+    /// 公开 API 是否要求携带 API 令牌（`Authorization: Bearer <token>`），默认不要求。
+    pub api_auth_required: bool,
+    /// API 令牌校验结果的缓存时间；其他实例吊销的令牌最迟在这段时间后失效。
+    pub api_token_cache_ttl: Duration,
+    /// 公开 API 按客户端（API 令牌或 IP 地址）的限流。
+    pub rate_limit: RateLimitSettings,
+    /// JWT 校验的配置，未配置密钥时为 `None`；配置后公开 API 要求鉴权。
+    pub jwt: Option<JwtSettings>,
+    /// 外部策略服务（OPA）的配置，未设置 `POLICY_URL` 时为 `None`，此时只按令牌的权限范围授权。
+    pub policy: Option<PolicySettings>,
+    /// 任务 ID 与请求 ID 的格式。
+    pub id_format: IdFormat,
+    /// Snowflake 格式的工作节点 ID（0-1023），同时运行的实例必须各不相同。
+    pub snowflake_worker_id: u16,
+    /// 数据库后端模式，`mysql`（默认）或 `memory`。
+    pub db_mode: DbMode,
+    /// 数据库连接字符串，`memory` 模式下可以不设置。
     pub database_url: String,
+    /// 表名前缀，例如 `webq_`；与其他应用共用一个数据库时用于避免表名冲突，默认为空。
+    pub table_prefix: String,
     /// 日志级别，例如 "info", "debug"。
     pub rust_log: String,
+    /// 标准输出日志的开关与格式。
+    pub log_stdout: SinkSettings,
+    /// 文件日志的开关与格式。
+    pub log_file: SinkSettings,
+    /// 写入日志文件失败后重新尝试写入文件的间隔，期间只输出到标准输出。
+    pub log_file_retry: Duration,
+    /// 内存中保留的最近日志条数，供 `GET /admin/logs/tail` 回放，0 表示只推送实时日志。
+    pub log_tail_buffer: usize,
+    /// 慢查询阈值，耗时超过该值的数据库查询会以 WARN 级别记录。
+    pub db_slow_query_threshold: Duration,
+    /// 数据库查询的默认超时，`None` 表示不限制。
+    /// 超时的查询会被取消，以免异常的查询长期占用连接池中的连接。
+    pub db_statement_timeout: Option<Duration>,
+    /// 为 `Critical` 档位的操作预留的连接比例（百分比），0 表示不预留。
+    pub db_critical_reserved_percent: u32,
+    /// 单个数据库连接的最长存活时间。
+    /// 到期的连接会被回收并重新建立，重新建立时会重新解析 DNS，
+    /// 从而在数据库故障切换后不再使用已失效的 IP。
+    pub db_max_lifetime: Duration,
+    /// 空闲连接的超时时间，超时的空闲连接会被关闭。
+    pub db_idle_timeout: Duration,
+    /// 连接池健康检查（探活并更新连接池指标）的间隔。
+    pub db_health_check_interval: Duration,
+    /// 连接池的最大连接数。
+    pub db_max_connections: u32,
+    /// 连接池始终保持的最少连接数，启动时即建立这些连接。
+    pub db_min_connections: u32,
+    /// 从连接池借出连接的最长等待时间，超时的操作按数据库超时处理。
+    pub db_acquire_timeout: Duration,
+    /// 启动时不等待数据库连接：立即开始服务并报告未就绪，在后台重试连接，连接成功后完成启动检查。
+    pub db_connect_lazy: bool,
+    /// 启动时立即连接数据库最多尝试的次数，1 表示不重试，全部失败时退出。
+    pub db_connect_attempts: u32,
+    /// 启动时单次连接数据库（延迟连接时为单次探活）的超时时间。
+    pub db_connect_timeout: Duration,
+    /// 启动时连接数据库两次重试之间的最长等待时间，等待时间从 1 秒起每次翻倍。
+    pub db_connect_backoff_max: Duration,
+    /// 连续多少次查询因数据库不可用而失败后打开熔断器、暂停分发任务，0 表示不启用熔断器。
+    pub db_circuit_failure_threshold: u32,
+    /// 熔断器打开后到下一次探测数据库是否恢复的等待时间。
+    pub db_circuit_open: Duration,
+    /// 启动时（表结构检查之前）应用待执行的迁移；关闭时需要通过管理 API 或部署流程执行迁移。
+    pub run_migrations: bool,
+    /// 队列后端：进程内（默认）或多个实例共享的 Redis。
+    pub queue_backend: QueueBackendKind,
+    /// Redis 的连接 URL，`QUEUE_BACKEND=redis` 时必须设置。
+    pub redis_url: String,
+    /// Redis 队列的键前缀，共享同一个队列的实例必须相同。
+    pub redis_queue_prefix: String,
+    /// 切换队列后端期间的旧后端：设置后新提交的任务写入 `queue_backend`，
+    /// 调度器同时从两个后端取任务，旧后端中的任务通过管理 API 迁移到新后端。
+    pub queue_migrate_from: Option<QueueBackendKind>,
+    /// 队列容量，排队任务数达到该值后新的提交返回 429，0 表示不限制。
+    pub queue_capacity: usize,
+    /// 各优先级档位的容量，某个档位的排队任务数达到容量后该档位的新提交返回 429。
+    pub queue_band_capacities: BandCapacities,
+    /// 计算调度器处理速度（用于估算开始时间与 `Retry-After`）的时间窗口。
+    pub throughput_window: Duration,
+    /// 任务载荷的大小上限。
+    pub payload_limits: PayloadLimits,
+    /// 提交任务时的优先级范围与载荷的 JSON Schema 校验。
+    pub submission_rules: SubmissionRules,
+    /// 提交时的内容扫描与可疑规则，命中的任务被隔离而不是入队。
+    pub quarantine: QuarantineSettings,
+    /// 停机时保存、启动时加载的计数器名称，使长期计数器在重启之间保持连续。
+    pub persisted_metrics: Vec<String>,
+    /// 任务提交接口的请求体大小限制（含压缩请求体的解压限制）。
+    pub request_body: RequestBodySettings,
+    /// 提交任务时捕获、处理期间恢复的请求头。
+    pub header_propagation: HeaderPropagation,
+    /// 队列中任务载荷的压缩设置。
+    pub queue_compression: CompressionSettings,
+    /// 当前实例的标识，用于认领持久化的排队任务，共用一个数据库的实例必须各不相同。
+    pub instance_id: String,
+    /// 排队任务的认领租约，实例超过这个时长没有续约时，它认领的任务可以被其他实例接管。
+    pub queue_claim_lease: Duration,
+    /// 并发处理快速任务的工作者数量，至少为 1。
+    pub scheduler_workers: usize,
+    /// 同时处理的慢速任务数量上限，0 表示不限制。
+    pub max_slow_tasks: usize,
+    /// 后台任务在时间窗口内允许的最大重启次数，超过后服务被标记为不健康。
+    pub supervisor_max_restarts: usize,
+    /// 调度器心跳超过该时长未更新即视为调度循环卡住。
+    pub scheduler_stall_threshold: Duration,
+    /// 检测到调度循环卡住时是否终止进程。
+    pub watchdog_abort: bool,
+    /// 各优先级档位允许的最长排队时间。
+    pub starvation_thresholds: StarvationThresholds,
+    /// 饥饿检测的扫描间隔。
+    pub starvation_check_interval: Duration,
+    /// 包装任务处理器的中间件，靠前的位于外层。
+    pub handler_middleware: Vec<BuiltinMiddleware>,
+    /// 慢速任务自动分类的设置。
+    pub slow_tasks: ClassifierSettings,
+    /// 慢速任务按租户与任务类型的准入预算。
+    pub slow_admission: AdmissionSettings,
+    /// 全局重试预算的设置。
+    pub retry_budget: RetryBudgetSettings,
+    /// 任务类型配置（`task_type_configs` 表）的缓存时间，其他实例的修改最迟在这之后生效。
+    pub task_type_config_ttl: Duration,
+    /// 未配置 `timeout_ms` 的任务类型的单次执行超时时间，`None` 表示不限时。
+    pub task_timeout: Option<Duration>,
+    /// 出站 HTTP 请求（webhook、回调等）的代理设置。
+    pub outbound_proxy: ProxySettings,
+    /// 按任务类型的端到端延迟目标（SLO）与快速消耗告警。
+    pub slo: SloSettings,
+    /// 任务结果（处理器返回的流式结果）的保存目录。
+    pub result_store_dir: PathBuf,
+    /// 是否允许通过管理 API 的 `POST /tasks/:id/replay` 重放失败任务，默认关闭，建议只在本地或预发环境开启。
+    pub task_replay: bool,
+    /// `bench` 子命令使用的合成负载描述。
+    #[cfg(feature = "fixtures")]
+    pub fixtures: WorkloadSpec,
+}
+
+thread_local! {
+    /// 正在加载的配置文件中的配置项，只在 `Config::from_file` 执行期间不为空。
+    static FILE_VALUES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+/// 读取一个配置项：环境变量优先，其次是正在加载的配置文件。
+fn var(name: &str) -> Result<String, env::VarError> {
+    env::var(name).or_else(|e| {
+        FILE_VALUES
+            .with(|file| file.borrow().get(name).cloned())
+            .ok_or(e)
+    })
 }
 
 impl Config {
+    /// 加载配置：设置了 `CONFIG_PATH`（可以写在 `.env` 中）时从该文件加载，否则只读取环境变量。
+    pub fn load() -> Result<Self, AppError> {
+        dotenvy::dotenv().ok();
+        match env::var_os("CONFIG_PATH").filter(|path| !path.is_empty()) {
+            Some(path) => Self::from_file(Path::new(&path)),
+            None => Self::from_env(),
+        }
+    }
+
+    /// 从 TOML 或 YAML 配置文件加载配置，按扩展名（`.toml`、`.yaml`、`.yml`）判断格式。
+    ///
+    /// 文件中的配置项与环境变量同名（见 `config_file::load` 的映射规则），
+    /// 同时设置时环境变量（包括 `.env` 中的）优先，文件中没有的配置项使用默认值。
+    pub fn from_file(path: &Path) -> Result<Self, AppError> {
+        let values = config_file::load(path).map_err(AppError::Config)?;
+        FILE_VALUES.with(|file| *file.borrow_mut() = values);
+        let config = Self::from_env();
+        FILE_VALUES.with(|file| file.borrow_mut().clear());
+        config
+    }
+
     /// 从环境变量中加载配置。
     ///
     /// 这个函数会：
     /// 1. 使用 `dotenvy::dotenv().ok()` 尝试从项目根目录的 `.env` 文件加载环境变量。
     ///    这在本地开发时非常有用。如果 `.env` 文件不存在，此操作会被安全地忽略。
     /// 2. 逐一读取必要的环境变量 (`SERVER_ADDRESS`, `DATABASE_URL`, `RUST_LOG`)。
+    ///    `DB_MODE=memory` 时不要求设置 `DATABASE_URL`；未设置 `DB_MODE` 时根据 `DATABASE_URL` 的协议推断。
     /// 3. 如果任何一个环境变量未设置，它将返回一个 `AppError::Config` 错误。
+    /// 4. 读取可选的环境变量 (`ADMIN_ADDRESS`, `BASE_PATH`, `RESPONSE_ENVELOPE`, `ADMIN_TOKEN`, `API_AUTH_REQUIRED`,
+    ///    `API_TOKEN_CACHE_TTL_SECS`, `RATE_LIMIT_PER_SECOND`, `RATE_LIMIT_BURST`, `JWT_HS256_SECRET`, `JWT_RS256_PUBLIC_KEY`, `JWT_ISSUER`,
+    ///    `JWT_AUDIENCE`, `JWT_LEEWAY_SECS`, `POLICY_URL`, `POLICY_TIMEOUT_MS`, `POLICY_CACHE_TTL_SECS`,
+    ///    `POLICY_FAIL_MODE`, `ID_FORMAT`, `SNOWFLAKE_WORKER_ID`,
+    ///    `TABLE_PREFIX`, `DB_SLOW_QUERY_MS`, `DB_STATEMENT_TIMEOUT_MS`,
+    ///    `DB_CRITICAL_RESERVED_PERCENT`, `DB_MAX_LIFETIME_SECS`, `DB_IDLE_TIMEOUT_SECS`, `DB_HEALTH_CHECK_INTERVAL_SECS`,
+    ///    `DB_MAX_CONNECTIONS`, `DB_MIN_CONNECTIONS`, `DB_ACQUIRE_TIMEOUT_MS`, `DB_CONNECT_LAZY`,
+    ///    `DB_CONNECT_ATTEMPTS`, `DB_CONNECT_TIMEOUT_MS`, `DB_CONNECT_BACKOFF_MAX_MS`,
+    ///    `DB_CIRCUIT_FAILURE_THRESHOLD`, `DB_CIRCUIT_OPEN_MS`, `RUN_MIGRATIONS`,
+    ///    `QUEUE_BACKEND`, `REDIS_URL`, `REDIS_QUEUE_PREFIX`, `QUEUE_MIGRATE_FROM`, `QUEUE_CAPACITY`,
+    ///    `QUEUE_CAPACITY_{LOW,NORMAL,CRITICAL}`,
+    ///    `THROUGHPUT_WINDOW_SECS`, `PAYLOAD_LIMIT_{LOW,NORMAL,CRITICAL}_BYTES`, `PAYLOAD_LIMIT_TYPES`,
+    ///    `TASK_PRIORITY_MIN`, `TASK_PRIORITY_MAX`, `PAYLOAD_SCHEMA_VALIDATION`, `PAYLOAD_SCHEMA_MODE`,
+    ///    `QUARANTINE_PATTERNS`, `QUARANTINE_RULES`, `METRICS_PERSISTED`,
+    ///    `REQUEST_BODY_LIMIT_BYTES`, `REQUEST_DECOMPRESSION_MAX_RATIO`, `PROPAGATE_HEADERS`, `QUEUE_COMPRESSION`,
+    ///    `QUEUE_COMPRESSION_THRESHOLD_BYTES`, `QUEUE_COMPRESSION_LEVEL`, `INSTANCE_ID`, `QUEUE_CLAIM_LEASE_SECS`,
+    ///    `SCHEDULER_WORKERS`, `MAX_SLOW_TASKS`, `SUPERVISOR_MAX_RESTARTS`, `SCHEDULER_STALL_THRESHOLD_SECS`, `WATCHDOG_ABORT`,
+    ///    `STARVATION_THRESHOLD_{LOW,NORMAL,CRITICAL}_SECS`, `STARVATION_CHECK_INTERVAL_SECS`,
+    ///    `LOG_STDOUT`, `LOG_STDOUT_FORMAT`, `LOG_FILE`, `LOG_FILE_FORMAT`, `LOG_FILE_RETRY_SECS`,
+    ///    `LOG_TAIL_BUFFER`,
+    ///    `OUTBOUND_HTTP_PROXY`, `OUTBOUND_HTTPS_PROXY`, `OUTBOUND_ALL_PROXY`, `OUTBOUND_NO_PROXY`,
+    ///    `OUTBOUND_PROXY_OVERRIDES`, `RETRY_BUDGET_PERCENT`, `RETRY_BUDGET_WINDOW_SECS`,
+    ///    `RETRY_BUDGET_MIN_RETRIES`, `RETRY_BUDGET_DELAY_SECS`, `TASK_TYPE_CONFIG_CACHE_TTL_SECS`,
+    ///    `TASK_TIMEOUT_MS`, `SLOW_TASK_THRESHOLD_MS`,
+    ///    `SLOW_TASK_MIN_SAMPLES`, `SLOW_TASK_OVERRIDES`, `SLOW_BUDGET_PER_TENANT_SECS`, `SLOW_BUDGET_PER_TYPE_SECS`,
+    ///    `SLOW_BUDGET_TYPES`, `SLOW_BUDGET_DEFER_SECS`, `HANDLER_MIDDLEWARE`, `SLO_TARGETS`,
+    ///    `SLO_WINDOW_SECS`, `SLO_FAST_BURN_WINDOW_SECS`, `SLO_FAST_BURN_RATE`, `SLO_CHECK_INTERVAL_SECS`,
+    ///    `SLO_ALERT_WEBHOOK_URL`, `RESULT_STORE_DIR`, `TASK_REPLAY`；启用 `fixtures` feature 时还有
+    ///    `FIXTURES_SEED`, `FIXTURES_COUNT`, `FIXTURES_PRIORITY_WEIGHTS`, `FIXTURES_PAYLOAD_BYTES`,
+    ///    `FIXTURES_FAILURE_PERCENT`)，未设置时使用默认值。
+    ///
+    /// 时长类配置项接受 `500ms`、`30s`、`5m`、`1h` 这样的写法，不带单位的整数按变量名的后缀
+    /// （`_MS`/`_SECS`）解释；大小类配置项（`_BYTES`）接受 `4096`、`64KiB`、`10MiB`。
+    /// 监听地址必须是 `host:port` 格式。
     pub fn from_env() -> Result<Self, AppError> {
         // 尝试从 .env 文件加载环境变量，这对于本地开发很方便
         dotenvy::dotenv().ok();
 
         // 读取服务器地址
-        let server_address = env::var("SERVER_ADDRESS")
+        let server_address = var("SERVER_ADDRESS")
             .map_err(|_| AppError::Config("必须设置 SERVER_ADDRESS".to_string()))?;
-        // 读取数据库连接 URL
-        let database_url = env::var("DATABASE_URL")
-            .map_err(|_| AppError::Config("必须设置 DATABASE_URL".to_string()))?;
+        // 读取管理 API 地址（可选）
+        let admin_address = var("ADMIN_ADDRESS").ok().filter(|v| !v.trim().is_empty());
+        // 读取公开 API 的路径前缀（可选）
+        let base_path = match var("BASE_PATH") {
+            Ok(v) => normalize_base_path(&v)
+                .map_err(|e| AppError::Config(format!("BASE_PATH 无效: {}", e)))?,
+            Err(_) => String::new(),
+        };
+        let response_envelope = env_bool("RESPONSE_ENVELOPE", false)?;
+        // 读取管理 API 访问令牌（可选）
+        let admin_token = var("ADMIN_TOKEN").ok().filter(|v| !v.trim().is_empty());
+        // 读取公开 API 的令牌校验设置
+        let api_auth_required = env_bool("API_AUTH_REQUIRED", false)?;
+        let api_token_cache_ttl = env_duration(
+            "API_TOKEN_CACHE_TTL_SECS",
+            DEFAULT_API_TOKEN_CACHE_TTL,
+            SECS,
+        )?;
+        let jwt = env_jwt_settings()?;
+        let policy = env_policy_settings()?;
+        // 读取任务 ID 与请求 ID 的格式
+        let id_format = match var("ID_FORMAT") {
+            Ok(v) => v
+                .parse()
+                .map_err(|e| AppError::Config(format!("ID_FORMAT 无效: {}", e)))?,
+            Err(_) => IdFormat::default(),
+        };
+        let snowflake_worker_id = env_u64("SNOWFLAKE_WORKER_ID", 0)?;
+        if snowflake_worker_id > u64::from(ids::MAX_WORKER_ID) {
+            return Err(AppError::Config(format!(
+                "SNOWFLAKE_WORKER_ID 无效: 必须在 0-{} 之间",
+                ids::MAX_WORKER_ID
+            )));
+        }
+        let snowflake_worker_id = snowflake_worker_id as u16;
+        // 读取数据库模式与连接 URL，内存模式不需要连接 URL；
+        // 未设置 DB_MODE 时根据 DATABASE_URL 的协议推断
+        let db_mode = match var("DB_MODE") {
+            Ok(v) => v
+                .parse()
+                .map_err(|e| AppError::Config(format!("DB_MODE 无效: {}", e)))?,
+            Err(_) => var("DATABASE_URL")
+                .ok()
+                .and_then(|url| DbMode::from_url(&url))
+                .unwrap_or_default(),
+        };
+        let database_url = match (var("DATABASE_URL"), db_mode) {
+            (Ok(url), _) => url,
+            (Err(_), DbMode::Memory) => String::new(),
+            (Err(_), _) => return Err(AppError::Config("必须设置 DATABASE_URL".to_string())),
+        };
+        // 读取表名前缀（可选）
+        let table_prefix = var("TABLE_PREFIX")
+            .map(|v| v.trim().to_string())
+            .unwrap_or_default();
+        db::validate_prefix(&table_prefix)
+            .map_err(|e| AppError::Config(format!("TABLE_PREFIX 无效: {}", e)))?;
         // 读取日志级别
         let rust_log =
-            env::var("RUST_LOG").map_err(|_| AppError::Config("必须设置 RUST_LOG".to_string()))?;
+            var("RUST_LOG").map_err(|_| AppError::Config("必须设置 RUST_LOG".to_string()))?;
+        // 读取日志输出目标的配置，两个目标可以分别开关
+        let log_stdout = SinkSettings {
+            enabled: env_bool("LOG_STDOUT", true)?,
+            format: env_log_format("LOG_STDOUT_FORMAT")?,
+        };
+        let log_file = SinkSettings {
+            enabled: env_bool("LOG_FILE", true)?,
+            format: env_log_format("LOG_FILE_FORMAT")?,
+        };
+        if !log_stdout.enabled && !log_file.enabled {
+            return Err(AppError::Config(
+                "LOG_STDOUT 和 LOG_FILE 不能同时关闭".to_string(),
+            ));
+        }
+        let log_file_retry = env_duration("LOG_FILE_RETRY_SECS", DEFAULT_LOG_FILE_RETRY, SECS)?;
+        let log_tail_buffer = env_u64("LOG_TAIL_BUFFER", DEFAULT_LOG_TAIL_BUFFER)? as usize;
+        // 读取数据库相关的可选配置
+        let db_slow_query_threshold =
+            env_duration("DB_SLOW_QUERY_MS", DEFAULT_DB_SLOW_QUERY, MILLIS)?;
+        // 查询超时为 0 表示不限制
+        let db_statement_timeout = env_duration(
+            "DB_STATEMENT_TIMEOUT_MS",
+            DEFAULT_DB_STATEMENT_TIMEOUT,
+            MILLIS,
+        )?;
+        let db_critical_reserved_percent = env_u64(
+            "DB_CRITICAL_RESERVED_PERCENT",
+            DEFAULT_DB_CRITICAL_RESERVED_PERCENT,
+        )?;
+        if db_critical_reserved_percent > 100 {
+            return Err(AppError::Config(format!(
+                "DB_CRITICAL_RESERVED_PERCENT 不能超过 100，当前值: {}",
+                db_critical_reserved_percent
+            )));
+        }
+        let db_max_lifetime = env_duration("DB_MAX_LIFETIME_SECS", DEFAULT_DB_MAX_LIFETIME, SECS)?;
+        let db_idle_timeout = env_duration("DB_IDLE_TIMEOUT_SECS", DEFAULT_DB_IDLE_TIMEOUT, SECS)?;
+        let db_health_check_interval = env_duration(
+            "DB_HEALTH_CHECK_INTERVAL_SECS",
+            DEFAULT_DB_HEALTH_CHECK_INTERVAL,
+            SECS,
+        )?;
+        let db_max_connections = env_u64("DB_MAX_CONNECTIONS", DEFAULT_DB_MAX_CONNECTIONS)?;
+        let db_min_connections = env_u64("DB_MIN_CONNECTIONS", 0)?;
+        if db_max_connections == 0 || db_max_connections > u64::from(u32::MAX) {
+            return Err(AppError::Config(format!(
+                "DB_MAX_CONNECTIONS 必须在 1-{} 之间，当前值: {}",
+                u32::MAX,
+                db_max_connections
+            )));
+        }
+        if db_min_connections > db_max_connections {
+            return Err(AppError::Config(format!(
+                "DB_MIN_CONNECTIONS ({}) 不能大于 DB_MAX_CONNECTIONS ({})",
+                db_min_connections, db_max_connections
+            )));
+        }
+        let db_acquire_timeout =
+            env_duration("DB_ACQUIRE_TIMEOUT_MS", DEFAULT_DB_ACQUIRE_TIMEOUT, MILLIS)?;
+        let db_connect_lazy = env_bool("DB_CONNECT_LAZY", false)?;
+        let db_connect_attempts = env_u64("DB_CONNECT_ATTEMPTS", 1)?;
+        if db_connect_attempts == 0 || db_connect_attempts > u64::from(u32::MAX) {
+            return Err(AppError::Config(format!(
+                "DB_CONNECT_ATTEMPTS 必须在 1-{} 之间，当前值: {}",
+                u32::MAX,
+                db_connect_attempts
+            )));
+        }
+        let db_connect_timeout =
+            env_duration("DB_CONNECT_TIMEOUT_MS", DEFAULT_DB_CONNECT_TIMEOUT, MILLIS)?;
+        let db_connect_backoff_max = env_duration(
+            "DB_CONNECT_BACKOFF_MAX_MS",
+            DEFAULT_DB_CONNECT_BACKOFF_MAX,
+            MILLIS,
+        )?;
+        let db_circuit_failure_threshold = env_u64(
+            "DB_CIRCUIT_FAILURE_THRESHOLD",
+            DEFAULT_DB_CIRCUIT_FAILURE_THRESHOLD,
+        )?;
+        if db_circuit_failure_threshold > u64::from(u32::MAX) {
+            return Err(AppError::Config(format!(
+                "DB_CIRCUIT_FAILURE_THRESHOLD 必须在 0-{} 之间，当前值: {}",
+                u32::MAX,
+                db_circuit_failure_threshold
+            )));
+        }
+        let db_circuit_open = env_duration("DB_CIRCUIT_OPEN_MS", DEFAULT_DB_CIRCUIT_OPEN, MILLIS)?;
+        let run_migrations = env_bool("RUN_MIGRATIONS", false)?;
+        // 读取队列后端，Redis 后端需要连接 URL
+        let queue_backend = match var("QUEUE_BACKEND") {
+            Ok(v) => v
+                .parse()
+                .map_err(|e| AppError::Config(format!("QUEUE_BACKEND 无效: {}", e)))?,
+            Err(_) => QueueBackendKind::default(),
+        };
+        let redis_url = match (var("REDIS_URL"), queue_backend) {
+            (Ok(url), _) => url,
+            (Err(_), QueueBackendKind::Local) => String::new(),
+            (Err(_), QueueBackendKind::Redis) => {
+                return Err(AppError::Config(
+                    "QUEUE_BACKEND=redis 时必须设置 REDIS_URL".to_string(),
+                ))
+            }
+        };
+        let redis_queue_prefix =
+            var("REDIS_QUEUE_PREFIX").unwrap_or_else(|_| DEFAULT_REDIS_QUEUE_PREFIX.to_string());
+        // 读取迁移窗口的旧队列后端，目前只支持从进程内队列迁移到 Redis
+        let queue_migrate_from = match var("QUEUE_MIGRATE_FROM") {
+            Ok(v) if v.trim().is_empty() => None,
+            Ok(v) => Some(
+                v.parse()
+                    .map_err(|e| AppError::Config(format!("QUEUE_MIGRATE_FROM 无效: {}", e)))?,
+            ),
+            Err(_) => None,
+        };
+        match (queue_migrate_from, queue_backend) {
+            (Some(from), to) if from == to => {
+                return Err(AppError::Config(
+                    "QUEUE_MIGRATE_FROM 不能与 QUEUE_BACKEND 相同".to_string(),
+                ))
+            }
+            (Some(QueueBackendKind::Redis), _) => {
+                return Err(AppError::Config(
+                    "QUEUE_MIGRATE_FROM 目前只支持 local（迁移到 QUEUE_BACKEND=redis）".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        // 读取队列容量与处理速度统计窗口
+        let queue_capacity = env_u64("QUEUE_CAPACITY", 0)? as usize;
+        let queue_band_capacities = BandCapacities {
+            low: env_u64("QUEUE_CAPACITY_LOW", 0)? as usize,
+            normal: env_u64("QUEUE_CAPACITY_NORMAL", 0)? as usize,
+            critical: env_u64("QUEUE_CAPACITY_CRITICAL", 0)? as usize,
+        };
+        let throughput_window =
+            env_duration("THROUGHPUT_WINDOW_SECS", DEFAULT_THROUGHPUT_WINDOW, SECS)?;
+        // 读取任务载荷的大小上限，0 表示不限制
+        let payload_limits = PayloadLimits {
+            low: env_limit("PAYLOAD_LIMIT_LOW_BYTES")?,
+            normal: env_limit("PAYLOAD_LIMIT_NORMAL_BYTES")?,
+            critical: env_limit("PAYLOAD_LIMIT_CRITICAL_BYTES")?,
+            by_type: match var("PAYLOAD_LIMIT_TYPES") {
+                Ok(list) => limits::parse_type_limits(&list)
+                    .map_err(|e| AppError::Config(format!("PAYLOAD_LIMIT_TYPES 无效: {}", e)))?,
+                Err(_) => Default::default(),
+            },
+        };
+        // 读取允许的优先级范围与需要按 JSON Schema 校验载荷的任务类型
+        let min_priority = env_priority("TASK_PRIORITY_MIN", u8::MIN)?;
+        let max_priority = env_priority("TASK_PRIORITY_MAX", u8::MAX)?;
+        if min_priority > max_priority {
+            return Err(AppError::Config(format!(
+                "TASK_PRIORITY_MIN ({}) 不能大于 TASK_PRIORITY_MAX ({})",
+                min_priority, max_priority
+            )));
+        }
+        let submission_rules = SubmissionRules {
+            min_priority,
+            max_priority,
+            schema_types: var("PAYLOAD_SCHEMA_VALIDATION")
+                .map(|list| split_addresses(&list).collect())
+                .unwrap_or_default(),
+            schema_mode: match var("PAYLOAD_SCHEMA_MODE") {
+                Ok(v) => v
+                    .parse()
+                    .map_err(|e| AppError::Config(format!("PAYLOAD_SCHEMA_MODE 无效: {}", e)))?,
+                Err(_) => SchemaMode::default(),
+            },
+        };
+        // 读取提交时的隔离检查，都未设置时不隔离任何任务
+        let quarantine = QuarantineSettings::parse(
+            &var("QUARANTINE_PATTERNS").unwrap_or_default(),
+            &var("QUARANTINE_RULES").unwrap_or_default(),
+        )
+        .map_err(|e| AppError::Config(format!("QUARANTINE_RULES 无效: {}", e)))?;
+        // 读取需要在重启之间保持连续的计数器，设置为空字符串时不保存
+        let persisted_metrics = match var("METRICS_PERSISTED") {
+            Ok(list) => split_addresses(&list).collect(),
+            Err(_) => DEFAULT_PERSISTED_METRICS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        };
+        // 读取请求体的大小上限与解压时允许的最大压缩比
+        let body_defaults = RequestBodySettings::default();
+        let request_body = RequestBodySettings {
+            limit: env_size("REQUEST_BODY_LIMIT_BYTES", body_defaults.limit as u64)? as usize,
+            max_ratio: env_u64(
+                "REQUEST_DECOMPRESSION_MAX_RATIO",
+                body_defaults.max_ratio as u64,
+            )?
+            .max(1) as usize,
+        };
+        // 读取需要随任务传递的请求头，默认不传递
+        let header_propagation = match var("PROPAGATE_HEADERS") {
+            Ok(v) => HeaderPropagation::parse(&v)
+                .map_err(|e| AppError::Config(format!("PROPAGATE_HEADERS 无效: {}", e)))?,
+            Err(_) => HeaderPropagation::default(),
+        };
+        // 读取队列压缩相关的可选配置
+        let compression_defaults = CompressionSettings::default();
+        let queue_compression = CompressionSettings {
+            enabled: env_bool("QUEUE_COMPRESSION", compression_defaults.enabled)?,
+            threshold_bytes: env_size(
+                "QUEUE_COMPRESSION_THRESHOLD_BYTES",
+                compression_defaults.threshold_bytes as u64,
+            )? as usize,
+            level: env_u64("QUEUE_COMPRESSION_LEVEL", compression_defaults.level as u64)? as i32,
+        };
+        // 读取实例标识，未设置时依次使用主机名与随机值
+        let instance_id = var("INSTANCE_ID")
+            .or_else(|_| var("HOSTNAME"))
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        if instance_id.len() > MAX_INSTANCE_ID_LEN {
+            return Err(AppError::Config(format!(
+                "INSTANCE_ID 无效: 不能超过 {} 个字节",
+                MAX_INSTANCE_ID_LEN
+            )));
+        }
+        let queue_claim_lease =
+            env_duration("QUEUE_CLAIM_LEASE_SECS", DEFAULT_QUEUE_CLAIM_LEASE, SECS)?;
+        if queue_claim_lease.is_zero() {
+            return Err(AppError::Config(
+                "QUEUE_CLAIM_LEASE_SECS 无效: 必须大于 0".to_string(),
+            ));
+        }
+        // 读取调度器的工作者数量，为 1 时快速任务依次处理（默认）
+        let scheduler_workers = env_u64("SCHEDULER_WORKERS", 1)?.max(1) as usize;
+        let max_slow_tasks = env_u64("MAX_SLOW_TASKS", DEFAULT_MAX_SLOW_TASKS)? as usize;
+        let supervisor_max_restarts =
+            env_u64("SUPERVISOR_MAX_RESTARTS", DEFAULT_SUPERVISOR_MAX_RESTARTS)? as usize;
+        let scheduler_stall_threshold = env_duration(
+            "SCHEDULER_STALL_THRESHOLD_SECS",
+            DEFAULT_SCHEDULER_STALL_THRESHOLD,
+            SECS,
+        )?;
+        let watchdog_abort = env_bool("WATCHDOG_ABORT", false)?;
+        // 读取饥饿检测相关的可选配置
+        let starvation_defaults = StarvationThresholds::default();
+        let starvation_thresholds = StarvationThresholds {
+            low: env_duration(
+                "STARVATION_THRESHOLD_LOW_SECS",
+                starvation_defaults.low,
+                SECS,
+            )?,
+            normal: env_duration(
+                "STARVATION_THRESHOLD_NORMAL_SECS",
+                starvation_defaults.normal,
+                SECS,
+            )?,
+            critical: env_duration(
+                "STARVATION_THRESHOLD_CRITICAL_SECS",
+                starvation_defaults.critical,
+                SECS,
+            )?,
+        };
+        let starvation_check_interval = env_duration(
+            "STARVATION_CHECK_INTERVAL_SECS",
+            DEFAULT_STARVATION_CHECK_INTERVAL,
+            SECS,
+        )?;
+        // 读取重试预算相关的可选配置
+        let budget_defaults = RetryBudgetSettings::default();
+        let retry_budget_percent = env_u64(
+            "RETRY_BUDGET_PERCENT",
+            (budget_defaults.ratio * 100.0) as u64,
+        )?;
+        if retry_budget_percent > 100 {
+            return Err(AppError::Config(format!(
+                "RETRY_BUDGET_PERCENT 不能超过 100，当前值: {}",
+                retry_budget_percent
+            )));
+        }
+        let retry_budget = RetryBudgetSettings {
+            window: env_duration("RETRY_BUDGET_WINDOW_SECS", budget_defaults.window, SECS)?
+                .max(SECS),
+            ratio: retry_budget_percent as f64 / 100.0,
+            min_retries: env_u64("RETRY_BUDGET_MIN_RETRIES", budget_defaults.min_retries)?,
+            delay: env_duration("RETRY_BUDGET_DELAY_SECS", budget_defaults.delay, SECS)?,
+        };
+        let task_type_config_ttl = env_duration(
+            "TASK_TYPE_CONFIG_CACHE_TTL_SECS",
+            DEFAULT_TASK_TYPE_CONFIG_TTL,
+            SECS,
+        )?;
+        let task_timeout = env_duration("TASK_TIMEOUT_MS", DEFAULT_TASK_TIMEOUT, MILLIS)?;
+        // 读取慢速任务分类相关的可选配置
+        let slow_defaults = ClassifierSettings::default();
+        let slow_tasks = ClassifierSettings {
+            threshold: env_duration("SLOW_TASK_THRESHOLD_MS", slow_defaults.threshold, MILLIS)?,
+            min_samples: env_u64("SLOW_TASK_MIN_SAMPLES", slow_defaults.min_samples as u64)?.max(1)
+                as usize,
+            overrides: match var("SLOW_TASK_OVERRIDES") {
+                Ok(list) => classifier::parse_overrides(&list)
+                    .map_err(|e| AppError::Config(format!("SLOW_TASK_OVERRIDES 无效: {}", e)))?,
+                Err(_) => slow_defaults.overrides,
+            },
+        };
+        // 读取慢速任务的准入预算，0 表示不限制
+        let admission_defaults = AdmissionSettings::default();
+        let slow_admission = AdmissionSettings {
+            per_tenant: env_budget("SLOW_BUDGET_PER_TENANT_SECS")?,
+            per_type: env_budget("SLOW_BUDGET_PER_TYPE_SECS")?,
+            types: match var("SLOW_BUDGET_TYPES") {
+                Ok(list) => admission::parse_type_budgets(&list)
+                    .map_err(|e| AppError::Config(format!("SLOW_BUDGET_TYPES 无效: {}", e)))?,
+                Err(_) => admission_defaults.types,
+            },
+            defer: env_duration("SLOW_BUDGET_DEFER_SECS", admission_defaults.defer, SECS)?
+                .max(Duration::from_millis(100)),
+        };
+        // 读取任务处理器中间件，设置为空字符串时不使用任何中间件
+        let handler_middleware = match var("HANDLER_MIDDLEWARE") {
+            Ok(list) => split_addresses(&list)
+                .map(|name| name.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|e| AppError::Config(format!("HANDLER_MIDDLEWARE 无效: {}", e)))?,
+            Err(_) => BuiltinMiddleware::DEFAULT.to_vec(),
+        };
+        // 读取出站代理相关的可选配置
+        let outbound_proxy = ProxySettings {
+            http: env_proxy_url("OUTBOUND_HTTP_PROXY")?,
+            https: env_proxy_url("OUTBOUND_HTTPS_PROXY")?,
+            all: env_proxy_url("OUTBOUND_ALL_PROXY")?,
+            no_proxy: var("OUTBOUND_NO_PROXY")
+                .map(|list| split_addresses(&list).collect())
+                .unwrap_or_default(),
+            overrides: match var("OUTBOUND_PROXY_OVERRIDES") {
+                Ok(list) => outbound::parse_overrides(&list).map_err(|e| {
+                    AppError::Config(format!("OUTBOUND_PROXY_OVERRIDES 无效: {}", e))
+                })?,
+                Err(_) => Vec::new(),
+            },
+        };
+        // 读取按客户端限流的配置，突发请求数默认与每秒请求数相同
+        let rate_limit_per_second = env_u64("RATE_LIMIT_PER_SECOND", 0)?;
+        let rate_limit = RateLimitSettings {
+            per_second: u32::try_from(rate_limit_per_second).unwrap_or(u32::MAX),
+            burst: u32::try_from(env_u64("RATE_LIMIT_BURST", rate_limit_per_second)?)
+                .unwrap_or(u32::MAX),
+        };
+        // 读取按任务类型的延迟目标与快速消耗告警的配置
+        let slo_defaults = SloSettings::default();
+        let slo = SloSettings {
+            targets: match var("SLO_TARGETS") {
+                Ok(list) => slo::parse_targets(&list)
+                    .map_err(|e| AppError::Config(format!("SLO_TARGETS 无效: {}", e)))?,
+                Err(_) => slo_defaults.targets,
+            },
+            window: env_duration("SLO_WINDOW_SECS", slo_defaults.window, SECS)?.max(SECS),
+            fast_window: env_duration("SLO_FAST_BURN_WINDOW_SECS", slo_defaults.fast_window, SECS)?
+                .max(SECS),
+            fast_burn_rate: match var("SLO_FAST_BURN_RATE") {
+                Ok(v) => v
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|rate| *rate > 0.0)
+                    .ok_or_else(|| {
+                        AppError::Config(format!("SLO_FAST_BURN_RATE 必须是正数，当前值: {}", v))
+                    })?,
+                Err(_) => slo_defaults.fast_burn_rate,
+            },
+            check_interval: env_duration(
+                "SLO_CHECK_INTERVAL_SECS",
+                slo_defaults.check_interval,
+                SECS,
+            )?
+            .max(SECS),
+            alert_webhook: match var("SLO_ALERT_WEBHOOK_URL") {
+                Ok(v) if !v.trim().is_empty() => {
+                    Some(reqwest::Url::parse(v.trim()).map_err(|e| {
+                        AppError::Config(format!("SLO_ALERT_WEBHOOK_URL 无效: {}: {}", v, e))
+                    })?)
+                }
+                _ => None,
+            },
+        };
+        // 读取任务结果的保存目录
+        let result_store_dir = var("RESULT_STORE_DIR")
+            .map(|v| v.trim().to_string())
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map_or_else(|| PathBuf::from(DEFAULT_RESULT_STORE_DIR), PathBuf::from);
+        let task_replay = env_bool("TASK_REPLAY", false)?;
 
-        Ok(Self {
+        let config = Self {
             server_address,
+            admin_address,
+            base_path,
+            response_envelope,
+            admin_token,
+            api_auth_required,
+            api_token_cache_ttl,
+            rate_limit,
+            jwt,
+            policy,
+            id_format,
+            snowflake_worker_id,
+            db_mode,
             database_url,
+            table_prefix,
             rust_log,
-        })
+            log_stdout,
+            log_file,
+            log_file_retry,
+            log_tail_buffer,
+            db_slow_query_threshold,
+            db_statement_timeout: (!db_statement_timeout.is_zero()).then_some(db_statement_timeout),
+            db_critical_reserved_percent: db_critical_reserved_percent as u32,
+            db_max_lifetime,
+            db_idle_timeout,
+            db_health_check_interval: db_health_check_interval.max(SECS),
+            db_max_connections: db_max_connections as u32,
+            db_min_connections: db_min_connections as u32,
+            db_acquire_timeout: db_acquire_timeout.max(MILLIS),
+            db_connect_lazy,
+            db_connect_attempts: db_connect_attempts as u32,
+            db_connect_timeout,
+            db_connect_backoff_max,
+            db_circuit_failure_threshold: db_circuit_failure_threshold as u32,
+            db_circuit_open: db_circuit_open.max(MILLIS),
+            run_migrations,
+            queue_backend,
+            redis_url,
+            redis_queue_prefix,
+            queue_migrate_from,
+            queue_capacity,
+            queue_band_capacities,
+            throughput_window: throughput_window.max(SECS),
+            payload_limits,
+            submission_rules,
+            quarantine,
+            persisted_metrics,
+            request_body,
+            header_propagation,
+            queue_compression,
+            instance_id,
+            queue_claim_lease,
+            scheduler_workers,
+            max_slow_tasks,
+            supervisor_max_restarts,
+            scheduler_stall_threshold: scheduler_stall_threshold.max(SECS),
+            watchdog_abort,
+            starvation_thresholds,
+            starvation_check_interval: starvation_check_interval.max(SECS),
+            handler_middleware,
+            slow_tasks,
+            slow_admission,
+            retry_budget,
+            task_type_config_ttl,
+            task_timeout: (!task_timeout.is_zero()).then_some(task_timeout),
+            outbound_proxy,
+            slo,
+            result_store_dir,
+            task_replay,
+            #[cfg(feature = "fixtures")]
+            fixtures: env_workload_spec()?,
+        };
+        // 提前校验监听地址，避免在启动到一半时才发现配置错误
+        let listeners = config.listeners();
+        if listeners.is_empty() {
+            return Err(AppError::Config(
+                "SERVER_ADDRESS 至少需要包含一个地址".to_string(),
+            ));
+        }
+        for listener in &listeners {
+            let name = match listener.role {
+                ListenerRole::Public => "SERVER_ADDRESS",
+                ListenerRole::Admin => "ADMIN_ADDRESS",
+            };
+            units::validate_address(&listener.address)
+                .map_err(|e| AppError::Config(format!("{} 无效: {}", name, e)))?;
+        }
+        Ok(config)
+    }
+
+    /// 返回构建数据库连接池所需的参数。
+    pub fn pool_settings(&self) -> PoolSettings {
+        PoolSettings {
+            max_lifetime: Some(self.db_max_lifetime),
+            idle_timeout: Some(self.db_idle_timeout),
+            statement_timeout: self.db_statement_timeout,
+            read_only: false,
+            max_connections: Some(self.db_max_connections),
+            min_connections: Some(self.db_min_connections),
+            acquire_timeout: Some(self.db_acquire_timeout),
+        }
+    }
+
+    /// 返回启动时连接数据库的重试策略。
+    pub fn connect_retry(&self) -> ConnectRetry {
+        ConnectRetry {
+            attempts: self.db_connect_attempts,
+            attempt_timeout: self.db_connect_timeout,
+            max_backoff: self.db_connect_backoff_max,
+            ..Default::default()
+        }
+    }
+
+    /// 返回数据库熔断器的参数。
+    pub fn circuit_settings(&self) -> CircuitSettings {
+        CircuitSettings {
+            failure_threshold: self.db_circuit_failure_threshold,
+            open_duration: self.db_circuit_open,
+        }
+    }
+
+    /// 返回看门狗所需的参数。
+    pub fn watchdog_settings(&self) -> WatchdogSettings {
+        WatchdogSettings {
+            stall_threshold: self.scheduler_stall_threshold,
+            abort_on_stall: self.watchdog_abort,
+        }
+    }
+
+    /// 返回所有需要启动的监听器。
+    ///
+    /// 公开地址在前，管理地址在后；空白项会被忽略。
+    pub fn listeners(&self) -> Vec<ListenerConfig> {
+        let public = split_addresses(&self.server_address).map(|address| ListenerConfig {
+            role: ListenerRole::Public,
+            address,
+        });
+        let admin = self
+            .admin_address
+            .iter()
+            .flat_map(|list| split_addresses(list))
+            .map(|address| ListenerConfig {
+                role: ListenerRole::Admin,
+                address,
+            });
+        public.chain(admin).collect()
+    }
+}
+
+/// 读取一个可选的非负整数环境变量，未设置时返回默认值。
+fn env_u64(name: &str, default: u64) -> Result<u64, AppError> {
+    match var(name) {
+        Ok(v) => v
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| AppError::Config(format!("{} 必须是非负整数，当前值: {}", name, v))),
+        Err(_) => Ok(default),
+    }
+}
+
+/// 读取一个优先级（0-255）。
+fn env_priority(name: &str, default: u8) -> Result<u8, AppError> {
+    match var(name) {
+        Ok(v) => v.trim().parse::<u8>().map_err(|_| {
+            AppError::Config(format!(
+                "{} 必须是 0 到 255 之间的整数，当前值: {}",
+                name, v
+            ))
+        }),
+        Err(_) => Ok(default),
+    }
+}
+
+/// 读取 JWT 校验的配置：`JWT_HS256_SECRET` 与 `JWT_RS256_PUBLIC_KEY` 最多设置一个，都未设置时返回 `None`。
+///
+/// `JWT_RS256_PUBLIC_KEY` 可以是 PEM 内容本身，也可以是 PEM 文件的路径。
+fn env_jwt_settings() -> Result<Option<JwtSettings>, AppError> {
+    let non_empty = |name| var(name).ok().filter(|v| !v.trim().is_empty());
+    let key = match (
+        non_empty("JWT_HS256_SECRET"),
+        non_empty("JWT_RS256_PUBLIC_KEY"),
+    ) {
+        (None, None) => return Ok(None),
+        (Some(_), Some(_)) => {
+            return Err(AppError::Config(
+                "JWT_HS256_SECRET 与 JWT_RS256_PUBLIC_KEY 只能设置一个".to_string(),
+            ))
+        }
+        (Some(secret), None) => JwtKey::Hs256(secret),
+        (None, Some(key)) if key.trim_start().starts_with("-----BEGIN") => JwtKey::Rs256(key),
+        (None, Some(path)) => JwtKey::Rs256(std::fs::read_to_string(path.trim()).map_err(|e| {
+            AppError::Config(format!(
+                "无法读取 JWT_RS256_PUBLIC_KEY 文件 {}: {}",
+                path, e
+            ))
+        })?),
+    };
+    let settings = JwtSettings {
+        key,
+        issuer: non_empty("JWT_ISSUER"),
+        audience: non_empty("JWT_AUDIENCE"),
+        leeway: env_duration("JWT_LEEWAY_SECS", DEFAULT_JWT_LEEWAY, SECS)?,
+    };
+    // 提前解析密钥，避免在第一个请求到达时才发现配置错误
+    JwtVerifier::new(&settings).map_err(AppError::Config)?;
+    Ok(Some(settings))
+}
+
+/// 读取外部策略服务的配置，未设置 `POLICY_URL` 时返回 `None`。
+fn env_policy_settings() -> Result<Option<PolicySettings>, AppError> {
+    let Some(url) = var("POLICY_URL").ok().filter(|v| !v.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let url = url.trim().to_string();
+    reqwest::Url::parse(&url).map_err(|e| AppError::Config(format!("POLICY_URL 无效: {}", e)))?;
+    let failure_mode = match var("POLICY_FAIL_MODE") {
+        Ok(v) => v
+            .parse()
+            .map_err(|e| AppError::Config(format!("POLICY_FAIL_MODE 无效: {}", e)))?,
+        Err(_) => PolicyFailureMode::default(),
+    };
+    Ok(Some(PolicySettings {
+        url,
+        timeout: env_duration("POLICY_TIMEOUT_MS", DEFAULT_POLICY_TIMEOUT, MILLIS)?,
+        cache_ttl: env_duration("POLICY_CACHE_TTL_SECS", DEFAULT_POLICY_CACHE_TTL, SECS)?,
+        failure_mode,
+    }))
+}
+
+/// 读取一个可选的时长环境变量，未设置时返回默认值。
+///
+/// 接受 `500ms`、`30s`、`5m`、`1h30m` 等写法；不带单位的整数按 `bare_unit` 解释。
+fn env_duration(name: &str, default: Duration, bare_unit: Duration) -> Result<Duration, AppError> {
+    match var(name) {
+        Ok(v) => units::parse_duration(&v, bare_unit).map_err(|e| {
+            AppError::Config(format!("{} 无效: {}（示例: 500ms、30s、5m、1h）", name, e))
+        }),
+        Err(_) => Ok(default),
+    }
+}
+
+/// 读取一个可选的字节数环境变量，未设置时返回默认值。接受 `4096`、`64KiB`、`10MiB` 等写法。
+fn env_size(name: &str, default: u64) -> Result<u64, AppError> {
+    match var(name) {
+        Ok(v) => units::parse_size(&v).map_err(|e| {
+            AppError::Config(format!("{} 无效: {}（示例: 4096、64KiB、10MiB）", name, e))
+        }),
+        Err(_) => Ok(default),
+    }
+}
+
+/// 读取一个可选的字节数上限，未设置或为 0 时表示不限制。
+fn env_limit(name: &str) -> Result<Option<usize>, AppError> {
+    Ok(Some(env_size(name, 0)? as usize).filter(|limit| *limit > 0))
+}
+
+/// 读取一个可选的时长预算，未设置或为 0 时返回 `None`（不限制）。
+fn env_budget(name: &str) -> Result<Option<Duration>, AppError> {
+    Ok(Some(env_duration(name, Duration::ZERO, SECS)?).filter(|budget| !budget.is_zero()))
+}
+
+/// 读取一个可选的布尔环境变量，接受 `true/false/1/0/yes/no/on/off`。
+fn env_bool(name: &str, default: bool) -> Result<bool, AppError> {
+    match var(name) {
+        Ok(v) => match v.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(true),
+            "false" | "0" | "no" | "off" => Ok(false),
+            _ => Err(AppError::Config(format!(
+                "{} 必须是布尔值 (true/false)，当前值: {}",
+                name, v
+            ))),
+        },
+        Err(_) => Ok(default),
+    }
+}
+
+/// 读取一个可选的日志格式环境变量，未设置时使用 JSON。
+fn env_log_format(name: &str) -> Result<LogFormat, AppError> {
+    match var(name) {
+        Ok(v) => v
+            .parse()
+            .map_err(|e| AppError::Config(format!("{} 无效: {}", name, e))),
+        Err(_) => Ok(LogFormat::default()),
+    }
+}
+
+/// 读取一个可选的代理地址环境变量，空值视为未设置。
+fn env_proxy_url(name: &str) -> Result<Option<reqwest::Url>, AppError> {
+    match var(name) {
+        Ok(v) if !v.trim().is_empty() => outbound::parse_proxy_url(&v)
+            .map(Some)
+            .map_err(|e| AppError::Config(format!("{} 无效: {}", name, e))),
+        _ => Ok(None),
+    }
+}
+
+/// 读取合成负载的描述，未设置的项使用默认值。
+#[cfg(feature = "fixtures")]
+fn env_workload_spec() -> Result<WorkloadSpec, AppError> {
+    let defaults = WorkloadSpec::default();
+    let priority_weights = match var("FIXTURES_PRIORITY_WEIGHTS") {
+        Ok(v) => {
+            let weights: Vec<u32> = v
+                .split(',')
+                .map(|w| w.trim().parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| AppError::Config(format!("FIXTURES_PRIORITY_WEIGHTS 无效: {}", v)))?;
+            weights.try_into().map_err(|_| {
+                AppError::Config(format!(
+                    "FIXTURES_PRIORITY_WEIGHTS 必须是三个以逗号分隔的权重 (low,normal,critical)，当前值: {}",
+                    v
+                ))
+            })?
+        }
+        Err(_) => defaults.priority_weights,
+    };
+    let payload_bytes = match var("FIXTURES_PAYLOAD_BYTES") {
+        Ok(v) => v
+            .split_once('-')
+            .and_then(|(min, max)| Some((min.trim().parse().ok()?, max.trim().parse().ok()?)))
+            .filter(|(min, max)| min <= max)
+            .ok_or_else(|| {
+                AppError::Config(format!(
+                    "FIXTURES_PAYLOAD_BYTES 格式应为 min-max，当前值: {}",
+                    v
+                ))
+            })?,
+        Err(_) => defaults.payload_bytes,
+    };
+    Ok(WorkloadSpec {
+        seed: env_u64("FIXTURES_SEED", defaults.seed)?,
+        count: env_u64("FIXTURES_COUNT", defaults.count as u64)? as usize,
+        priority_weights,
+        payload_bytes,
+        failure_rate: env_u64("FIXTURES_FAILURE_PERCENT", 0)?.min(100) as f64 / 100.0,
+    })
+}
+
+/// 规范化路径前缀：补上开头的 `/`，去掉结尾的 `/`，`/` 与空字符串都表示根路径。
+///
+/// 只允许普通的路径字符，不允许空的路径段和 `:`/`*` 这样会被路由当作参数的写法。
+fn normalize_base_path(value: &str) -> Result<String, String> {
+    let trimmed = value.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    if let Some(c) = trimmed
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || "-._~/".contains(*c)))
+    {
+        return Err(format!("{}: 不允许的字符 {:?}", value, c));
+    }
+    if trimmed.split('/').any(str::is_empty) {
+        return Err(format!("{}: 不能包含空的路径段", value));
+    }
+    Ok(format!("/{}", trimmed))
+}
+
+/// 将逗号分隔的地址列表拆分为单个地址。
+fn split_addresses(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(server_address: &str, admin_address: Option<&str>) -> Config {
+        Config {
+            server_address: server_address.to_string(),
+            admin_address: admin_address.map(str::to_string),
+            rust_log: "info".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// 测试多个公开地址与管理地址能被正确拆分并标记角色。
+    #[test]
+    fn test_listeners_multiple_addresses() {
+        let config = config_with("0.0.0.0:3000, [::]:3000", Some("127.0.0.1:9000"));
+        let listeners = config.listeners();
+        assert_eq!(
+            listeners,
+            vec![
+                ListenerConfig {
+                    role: ListenerRole::Public,
+                    address: "0.0.0.0:3000".to_string(),
+                },
+                ListenerConfig {
+                    role: ListenerRole::Public,
+                    address: "[::]:3000".to_string(),
+                },
+                ListenerConfig {
+                    role: ListenerRole::Admin,
+                    address: "127.0.0.1:9000".to_string(),
+                },
+            ]
+        );
+    }
+
+    /// 测试从配置文件加载配置，且环境变量优先于文件中的同名配置项。
+    #[test]
+    fn test_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+server_address = "0.0.0.0:8080"
+rust_log = "info"
+queue_capacity = 42
+snowflake_worker_id = 3
+
+[db]
+mode = "memory"
+max_connections = 20
+min_connections = 2
+acquire_timeout_ms = "5s"
+connect_attempts = 5
+connect_backoff_max_ms = "10s"
+circuit_open_ms = "2s"
+
+[run]
+migrations = true
+
+[api]
+auth_required = true
+"#,
+        )
+        .unwrap();
+        env::set_var("SNOWFLAKE_WORKER_ID", "7");
+        let config = Config::from_file(&path);
+        env::remove_var("SNOWFLAKE_WORKER_ID");
+        let config = config.unwrap();
+        assert_eq!(config.db_mode, DbMode::Memory);
+        assert_eq!(config.queue_capacity, 42);
+        assert!(config.api_auth_required);
+        assert_eq!(config.snowflake_worker_id, 7);
+        assert!(config.run_migrations);
+        let pool = config.pool_settings();
+        assert_eq!(
+            (pool.max_connections, pool.min_connections),
+            (Some(20), Some(2))
+        );
+        assert_eq!(pool.acquire_timeout, Some(Duration::from_secs(5)));
+        let retry = config.connect_retry();
+        assert_eq!(retry.attempts, 5);
+        assert_eq!(retry.max_backoff, Duration::from_secs(10));
+        assert_eq!(retry.attempt_timeout, Duration::from_secs(5));
+        let circuit = config.circuit_settings();
+        assert_eq!(circuit.failure_threshold, 5);
+        assert_eq!(circuit.open_duration, Duration::from_secs(2));
+        // 文件中的配置项只在加载期间可见
+        assert!(var("QUEUE_CAPACITY").is_err());
+
+        assert!(Config::from_file(&dir.path().join("missing.toml")).is_err());
+    }
+
+    /// 测试路径前缀的规范化与校验。
+    #[test]
+    fn test_normalize_base_path() {
+        assert_eq!(normalize_base_path("").unwrap(), "");
+        assert_eq!(normalize_base_path("/").unwrap(), "");
+        assert_eq!(normalize_base_path("jobs").unwrap(), "/jobs");
+        assert_eq!(normalize_base_path(" /jobs/v1/ ").unwrap(), "/jobs/v1");
+        assert!(normalize_base_path("/jobs//v1").is_err());
+        assert!(normalize_base_path("/jobs/:id").is_err());
+        assert!(normalize_base_path("/jobs?x=1").is_err());
+    }
+
+    /// 测试未配置管理地址时只有公开监听器。
+    #[test]
+    fn test_listeners_without_admin() {
+        let config = config_with("127.0.0.1:3000,", None);
+        let listeners = config.listeners();
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].role, ListenerRole::Public);
     }
 }
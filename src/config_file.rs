@@ -0,0 +1,157 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// 配置文件的格式，按扩展名判断。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Result<Self, String> {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("toml") => Ok(Format::Toml),
+            Some("yaml" | "yml") => Ok(Format::Yaml),
+            _ => Err(format!(
+                "无法识别配置文件的格式: {}（扩展名应为 .toml、.yaml 或 .yml）",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// 读取配置文件，返回与环境变量同名的配置项。
+///
+/// 文件中的键与环境变量一一对应：嵌套的表按 `_` 拼接后转为大写，
+/// 例如 `[db] slow_query_ms = 500` 对应 `DB_SLOW_QUERY_MS`；
+/// 数字与布尔值转为字符串，数组按逗号拼接（例如多个监听地址），`null` 视为未设置。
+pub fn load(path: &Path) -> Result<HashMap<String, String>, String> {
+    let format = Format::from_path(path)?;
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("无法读取配置文件 {}: {}", path.display(), e))?;
+    parse(&content, format).map_err(|e| format!("配置文件 {} 无效: {}", path.display(), e))
+}
+
+fn parse(content: &str, format: Format) -> Result<HashMap<String, String>, String> {
+    let value: Value = match format {
+        Format::Toml => toml::from_str(content).map_err(|e| e.to_string())?,
+        Format::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string())?,
+    };
+    let mut values = HashMap::new();
+    match value {
+        Value::Object(table) => flatten("", table, &mut values)?,
+        // 空的 YAML 文件解析为 null
+        Value::Null => {}
+        _ => return Err("顶层必须是键值表".to_string()),
+    }
+    Ok(values)
+}
+
+/// 将嵌套的表展开为环境变量名到值的映射。
+fn flatten(
+    prefix: &str,
+    table: serde_json::Map<String, Value>,
+    values: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    for (key, value) in table {
+        let name = if prefix.is_empty() {
+            key.to_ascii_uppercase()
+        } else {
+            format!("{}_{}", prefix, key.to_ascii_uppercase())
+        };
+        let value = match value {
+            Value::Object(table) => {
+                flatten(&name, table, values)?;
+                continue;
+            }
+            Value::Null => continue,
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| scalar(&name, item))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            value => scalar(&name, value)?,
+        };
+        if values.insert(name.clone(), value).is_some() {
+            return Err(format!("配置项 {} 重复", name));
+        }
+    }
+    Ok(())
+}
+
+/// 将单个值转为字符串，嵌套的表或数组不能作为数组的元素。
+fn scalar(name: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(format!(
+            "配置项 {} 的数组元素只能是字符串、数字或布尔值",
+            name
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试 TOML 与 YAML 展开为同样的环境变量。
+    #[test]
+    fn test_parse() {
+        let toml = r#"
+server_address = ["0.0.0.0:3000", "[::]:3000"]
+rust_log = "info"
+
+[db]
+slow_query_ms = 500
+statement_timeout_ms = "30s"
+
+[api]
+auth_required = true
+"#;
+        let yaml = r#"
+server_address: ["0.0.0.0:3000", "[::]:3000"]
+rust_log: info
+db:
+  slow_query_ms: 500
+  statement_timeout_ms: 30s
+api:
+  auth_required: true
+"#;
+        for values in [
+            parse(toml, Format::Toml).unwrap(),
+            parse(yaml, Format::Yaml).unwrap(),
+        ] {
+            assert_eq!(values["SERVER_ADDRESS"], "0.0.0.0:3000,[::]:3000");
+            assert_eq!(values["RUST_LOG"], "info");
+            assert_eq!(values["DB_SLOW_QUERY_MS"], "500");
+            assert_eq!(values["DB_STATEMENT_TIMEOUT_MS"], "30s");
+            assert_eq!(values["API_AUTH_REQUIRED"], "true");
+            assert_eq!(values.len(), 5);
+        }
+        assert!(parse("", Format::Yaml).unwrap().is_empty());
+    }
+
+    /// 测试无效的配置文件。
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("rust_log = ", Format::Toml).is_err());
+        assert!(parse("- a\n- b\n", Format::Yaml).is_err());
+        assert!(parse("items = [{ a = 1 }]", Format::Toml).is_err());
+        // `db_mode` 与 `[db] mode` 展开后是同一个配置项
+        assert!(parse("db_mode = \"memory\"\n[db]\nmode = \"mysql\"", Format::Toml).is_err());
+        assert!(Format::from_path(Path::new("config.json")).is_err());
+        assert_eq!(
+            Format::from_path(Path::new("/etc/web/config.YML")),
+            Ok(Format::Yaml)
+        );
+    }
+}
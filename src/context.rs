@@ -0,0 +1,158 @@
+use axum::http::{HeaderMap, HeaderName};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// 单个请求头的值超过该长度时不传递，避免任务记录被异常大的请求头撑大。
+const MAX_HEADER_VALUE_LEN: usize = 1024;
+/// 每个任务最多保存的请求头数。
+const MAX_HEADERS: usize = 32;
+
+/// 提交任务时需要传递给处理过程的请求头，配置在 `PROPAGATE_HEADERS` 中。
+///
+/// 每一项是一个请求头名称，或以 `*` 结尾的前缀（例如 `x-b3-*`），不区分大小写。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderPropagation {
+    /// 小写的完整名称。
+    exact: Vec<String>,
+    /// 小写的前缀（不含 `*`）。
+    prefixes: Vec<String>,
+}
+
+impl HeaderPropagation {
+    /// 解析逗号分隔的请求头列表。
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let mut propagation = Self::default();
+        for item in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let item = item.to_ascii_lowercase();
+            let (name, prefix) = match item.strip_suffix('*') {
+                Some(prefix) => (prefix, true),
+                None => (item.as_str(), false),
+            };
+            if name.is_empty() || HeaderName::from_bytes(name.as_bytes()).is_err() {
+                return Err(format!("无效的请求头名称: {}", item));
+            }
+            if prefix {
+                propagation.prefixes.push(name.to_string());
+            } else {
+                propagation.exact.push(name.to_string());
+            }
+        }
+        Ok(propagation)
+    }
+
+    /// 是否配置了需要传递的请求头。
+    pub fn is_enabled(&self) -> bool {
+        !self.exact.is_empty() || !self.prefixes.is_empty()
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.exact.iter().any(|exact| exact == name)
+            || self.prefixes.iter().any(|prefix| name.starts_with(prefix))
+    }
+
+    /// 从提交请求中选出需要传递的请求头。
+    ///
+    /// 同名的多个值以 `, ` 连接；不是合法 UTF-8 或超过长度上限的值被忽略，
+    /// 超过数量上限的请求头按名称排序后截断。
+    pub fn capture(&self, headers: &HeaderMap) -> TaskContext {
+        let mut context = TaskContext::default();
+        if !self.is_enabled() {
+            return context;
+        }
+        for name in headers.keys().filter(|name| self.matches(name.as_str())) {
+            let values: Vec<&str> = headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            let value = values.join(", ");
+            if values.is_empty() || value.len() > MAX_HEADER_VALUE_LEN {
+                continue;
+            }
+            context.headers.insert(name.as_str().to_string(), value);
+        }
+        while context.headers.len() > MAX_HEADERS {
+            context.headers.pop_last();
+        }
+        context
+    }
+}
+
+/// 提交任务时捕获的请求上下文，随任务一起保存，在处理期间恢复：
+/// 记录为处理过程 span 的 `headers` 字段，处理器调用下游服务时作为出站请求头。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskContext {
+    /// 小写的请求头名称到值。
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl TaskContext {
+    /// 是否没有捕获任何请求头。
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// 将捕获的请求头加入出站请求，例如发给策略服务的决策请求。
+    pub fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        self.headers.iter().fold(request, |request, (name, value)| {
+            request.header(name, value)
+        })
+    }
+}
+
+/// 以 `name=value` 空格分隔的形式输出，用于日志字段。
+impl fmt::Display for TaskContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.headers.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    /// 测试请求头列表的解析与匹配。
+    #[test]
+    fn test_capture() {
+        let propagation = HeaderPropagation::parse("x-b3-*, X-Tenant-Id,x-correlation-id").unwrap();
+        assert!(propagation.is_enabled());
+        assert!(!HeaderPropagation::default().is_enabled());
+        assert!(HeaderPropagation::parse("bad header").is_err());
+        assert!(HeaderPropagation::parse("*").is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-b3-traceid", HeaderValue::from_static("abc"));
+        headers.insert("x-b3-spanid", HeaderValue::from_static("def"));
+        headers.insert("x-tenant-id", HeaderValue::from_static("acme"));
+        headers.insert("authorization", HeaderValue::from_static("Bearer secret"));
+        headers.append("x-correlation-id", HeaderValue::from_static("1"));
+        headers.append("x-correlation-id", HeaderValue::from_static("2"));
+        headers.insert(
+            "x-b3-sampled",
+            HeaderValue::from_str(&"1".repeat(MAX_HEADER_VALUE_LEN + 1)).unwrap(),
+        );
+
+        let context = propagation.capture(&headers);
+        assert_eq!(
+            context.to_string(),
+            "x-b3-spanid=def x-b3-traceid=abc x-correlation-id=1, 2 x-tenant-id=acme"
+        );
+        assert!(HeaderPropagation::default().capture(&headers).is_empty());
+
+        let request = context
+            .apply(reqwest::Client::new().get("http://localhost/"))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["x-tenant-id"], "acme");
+        assert!(request.headers().get("authorization").is_none());
+    }
+}
@@ -0,0 +1,483 @@
+use crate::audit::AuditEntry;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Deserialize;
+use sqlx::database::HasArguments;
+use sqlx::query::QueryAs;
+use sqlx::{Encode, Type};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// SQL 占位符风格。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    /// `?`，MySQL 与 SQLite 使用。
+    Question,
+    /// `$1, $2, ...`，PostgreSQL 使用。
+    #[cfg_attr(not(feature = "postgres"), allow(dead_code))]
+    Dollar,
+}
+
+/// 绑定到 SQL 参数上的值。
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Text(String),
+    Int(i64),
+    Timestamp(DateTime<Utc>),
+}
+
+/// 一段带参数的 SQL，`sql` 中的占位符与 `params` 一一对应。
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlFragment {
+    pub sql: String,
+    pub params: Vec<FilterValue>,
+}
+
+impl SqlFragment {
+    /// 按顺序将所有参数绑定到查询上。
+    pub fn bind<'q, DB, O>(
+        self,
+        mut query: QueryAs<'q, DB, O, <DB as HasArguments<'q>>::Arguments>,
+    ) -> QueryAs<'q, DB, O, <DB as HasArguments<'q>>::Arguments>
+    where
+        DB: sqlx::Database,
+        String: Encode<'q, DB> + Type<DB>,
+        i64: Encode<'q, DB> + Type<DB>,
+        DateTime<Utc>: Encode<'q, DB> + Type<DB>,
+    {
+        for param in self.params {
+            query = match param {
+                FilterValue::Text(v) => query.bind(v),
+                FilterValue::Int(v) => query.bind(v),
+                FilterValue::Timestamp(v) => query.bind(v),
+            };
+        }
+        query
+    }
+}
+
+/// 任务的过滤条件，死信队列等按任务保存的表共用。
+///
+/// 所有条件之间是“与”的关系；未设置的条件不参与过滤。
+/// 列名全部来自代码中的常量，用户输入只会作为参数绑定，不会拼接进 SQL。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaskFilter {
+    pub ids: Vec<Uuid>,
+    pub tenants: Vec<String>,
+    pub min_priority: Option<u8>,
+    pub max_priority: Option<u8>,
+    /// 只匹配时间列在该时间之后（含）的任务，时间列由调用方指定。
+    pub from: Option<DateTime<Utc>>,
+    /// 只匹配时间列在该时间之前（不含）的任务。
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl TaskFilter {
+    /// 创建一个不含任何条件的过滤器。
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 只匹配指定租户的任务，可多次调用。
+    pub fn tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenants.push(tenant.into());
+        self
+    }
+
+    /// 只匹配优先级不低于 `priority` 的任务。
+    pub fn min_priority(mut self, priority: u8) -> Self {
+        self.min_priority = Some(priority);
+        self
+    }
+
+    /// 只匹配优先级不高于 `priority` 的任务。
+    pub fn max_priority(mut self, priority: u8) -> Self {
+        self.max_priority = Some(priority);
+        self
+    }
+
+    /// 只匹配时间列在 `time` 之后（含）的任务。
+    pub fn from(mut self, time: DateTime<Utc>) -> Self {
+        self.from = Some(time);
+        self
+    }
+
+    /// 只匹配时间列在 `time` 之前（不含）的任务。
+    pub fn to(mut self, time: DateTime<Utc>) -> Self {
+        self.to = Some(time);
+        self
+    }
+
+    /// 生成参数化的 `WHERE` 子句（包含 `WHERE` 关键字）；没有条件时返回空字符串。
+    ///
+    /// `time_column` 是 `from`/`to` 作用的列（例如死信队列的 `failed_at`）；
+    /// `first_param` 是第一个占位符的序号（从 1 开始），仅对 `Placeholder::Dollar` 有意义，
+    /// 便于在已有参数的查询后追加过滤条件。
+    pub fn to_where_clause(
+        &self,
+        time_column: &'static str,
+        style: Placeholder,
+        first_param: usize,
+    ) -> SqlFragment {
+        let mut builder = ClauseBuilder {
+            style,
+            next: first_param,
+            conditions: Vec::new(),
+            params: Vec::new(),
+        };
+
+        builder.in_list(
+            "id",
+            self.ids
+                .iter()
+                .map(|id| FilterValue::Text(id.to_string()))
+                .collect(),
+        );
+        builder.in_list(
+            "tenant",
+            self.tenants
+                .iter()
+                .map(|t| FilterValue::Text(t.clone()))
+                .collect(),
+        );
+        if let Some(p) = self.min_priority {
+            builder.compare("priority", ">=", FilterValue::Int(p.into()));
+        }
+        if let Some(p) = self.max_priority {
+            builder.compare("priority", "<=", FilterValue::Int(p.into()));
+        }
+        if let Some(t) = self.from {
+            builder.compare(time_column, ">=", FilterValue::Timestamp(t));
+        }
+        if let Some(t) = self.to {
+            builder.compare(time_column, "<", FilterValue::Timestamp(t));
+        }
+
+        let sql = if builder.conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", builder.conditions.join(" AND "))
+        };
+        SqlFragment {
+            sql,
+            params: builder.params,
+        }
+    }
+
+    /// 任务是否满足过滤条件，供内存数据库使用；`time` 为时间列的值。
+    pub fn matches(&self, id: &Uuid, tenant: &str, priority: u8, time: DateTime<Utc>) -> bool {
+        (self.ids.is_empty() || self.ids.contains(id))
+            && (self.tenants.is_empty() || self.tenants.iter().any(|t| t == tenant))
+            && self.min_priority.is_none_or(|p| priority >= p)
+            && self.max_priority.is_none_or(|p| priority <= p)
+            && self.from.is_none_or(|t| time >= t)
+            && self.to.is_none_or(|t| time < t)
+    }
+}
+
+/// 审计日志的排序方向，按 `(created_at, id)` 排序。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOrder {
+    /// 从旧到新。
+    Asc,
+    /// 从新到旧（默认）。
+    #[default]
+    Desc,
+}
+
+impl AuditOrder {
+    fn as_sql(self) -> &'static str {
+        match self {
+            AuditOrder::Asc => "ASC",
+            AuditOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// 审计日志的翻页游标：上一页最后一条记录的时间与 ID，以 `毫秒时间戳-ID` 的形式返回给客户端。
+///
+/// 按 `(created_at, id)` 定位而不是按偏移量，翻页期间写入的新记录不会导致重复或遗漏。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: i64,
+}
+
+impl AuditCursor {
+    /// 指向给定记录之后的游标。
+    pub fn after(entry: &AuditEntry) -> Self {
+        Self {
+            created_at: entry.created_at,
+            id: entry.id,
+        }
+    }
+}
+
+impl fmt::Display for AuditCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.created_at.timestamp_millis(), self.id)
+    }
+}
+
+impl FromStr for AuditCursor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (millis, id) = s.split_once('-').ok_or(())?;
+        let created_at = Utc
+            .timestamp_millis_opt(millis.parse().map_err(|_| ())?)
+            .single()
+            .ok_or(())?;
+        Ok(Self {
+            created_at,
+            id: id.parse().map_err(|_| ())?,
+        })
+    }
+}
+
+/// 审计日志的过滤条件与翻页位置。
+///
+/// 与 `TaskFilter` 一样，所有条件之间是“与”的关系，用户输入只会作为参数绑定。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    /// 只匹配在该时间之后（含）的记录。
+    pub from: Option<DateTime<Utc>>,
+    /// 只匹配在该时间之前（不含）的记录。
+    pub to: Option<DateTime<Utc>>,
+    pub order: AuditOrder,
+    /// 只返回按 `order` 排在游标之后的记录。
+    pub after: Option<AuditCursor>,
+}
+
+impl AuditFilter {
+    /// 生成参数化的 `WHERE` 子句（包含 `WHERE` 关键字）与 `ORDER BY` 子句。
+    pub fn to_clauses(&self, style: Placeholder) -> SqlFragment {
+        let mut builder = ClauseBuilder {
+            style,
+            next: 1,
+            conditions: Vec::new(),
+            params: Vec::new(),
+        };
+        if let Some(actor) = &self.actor {
+            builder.compare("actor", "=", FilterValue::Text(actor.clone()));
+        }
+        if let Some(action) = &self.action {
+            builder.compare("action", "=", FilterValue::Text(action.clone()));
+        }
+        if let Some(target) = &self.target {
+            builder.compare("target", "=", FilterValue::Text(target.clone()));
+        }
+        if let Some(t) = self.from {
+            builder.compare("created_at", ">=", FilterValue::Timestamp(t));
+        }
+        if let Some(t) = self.to {
+            builder.compare("created_at", "<", FilterValue::Timestamp(t));
+        }
+        if let Some(cursor) = self.after {
+            let op = match self.order {
+                AuditOrder::Asc => ">",
+                AuditOrder::Desc => "<",
+            };
+            // 展开为 `created_at < ? OR (created_at = ? AND id < ?)`，各数据库都能用上 (created_at, id) 索引
+            let before = builder.placeholder(FilterValue::Timestamp(cursor.created_at));
+            let at = builder.placeholder(FilterValue::Timestamp(cursor.created_at));
+            let id = builder.placeholder(FilterValue::Int(cursor.id));
+            builder.conditions.push(format!(
+                "(created_at {op} {before} OR (created_at = {at} AND id {op} {id}))"
+            ));
+        }
+        let order = self.order.as_sql();
+        let mut sql = format!("ORDER BY created_at {order}, id {order}");
+        if !builder.conditions.is_empty() {
+            sql = format!("WHERE {} {}", builder.conditions.join(" AND "), sql);
+        }
+        SqlFragment {
+            sql,
+            params: builder.params,
+        }
+    }
+
+    /// 记录是否满足过滤条件且排在翻页位置之后，供内存数据库使用。
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        let key = (entry.created_at, entry.id);
+        self.actor.as_ref().is_none_or(|a| *a == entry.actor)
+            && self.action.as_ref().is_none_or(|a| *a == entry.action)
+            && self
+                .target
+                .as_ref()
+                .is_none_or(|t| entry.target.as_ref() == Some(t))
+            && self.from.is_none_or(|t| entry.created_at >= t)
+            && self.to.is_none_or(|t| entry.created_at < t)
+            && self.after.is_none_or(|c| match self.order {
+                AuditOrder::Asc => key > (c.created_at, c.id),
+                AuditOrder::Desc => key < (c.created_at, c.id),
+            })
+    }
+}
+
+/// 逐条累积条件与参数，负责生成正确编号的占位符。
+struct ClauseBuilder {
+    style: Placeholder,
+    next: usize,
+    conditions: Vec<String>,
+    params: Vec<FilterValue>,
+}
+
+impl ClauseBuilder {
+    fn placeholder(&mut self, value: FilterValue) -> String {
+        self.params.push(value);
+        let placeholder = match self.style {
+            Placeholder::Question => "?".to_string(),
+            Placeholder::Dollar => format!("${}", self.next),
+        };
+        self.next += 1;
+        placeholder
+    }
+
+    fn compare(&mut self, column: &'static str, op: &'static str, value: FilterValue) {
+        let placeholder = self.placeholder(value);
+        self.conditions
+            .push(format!("{} {} {}", column, op, placeholder));
+    }
+
+    fn in_list(&mut self, column: &'static str, values: Vec<FilterValue>) {
+        if values.is_empty() {
+            return;
+        }
+        let placeholders: Vec<String> = values.into_iter().map(|v| self.placeholder(v)).collect();
+        self.conditions
+            .push(format!("{} IN ({})", column, placeholders.join(", ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试空过滤器不生成任何条件。
+    #[test]
+    fn test_empty_filter() {
+        let filter = TaskFilter::new();
+        let clause = filter.to_where_clause("failed_at", Placeholder::Question, 1);
+        assert_eq!(clause.sql, "");
+        assert!(clause.params.is_empty());
+    }
+
+    /// 测试组合条件生成的 SQL 与参数，以及两种占位符风格。
+    #[test]
+    fn test_where_clause_generation() {
+        let id = Uuid::new_v4();
+        let from = Utc.timestamp_millis_opt(1_726_000_000_000).unwrap();
+        let filter = TaskFilter {
+            ids: vec![id],
+            ..TaskFilter::new()
+        }
+        .tenant("acme")
+        .tenant("globex")
+        .min_priority(10)
+        .max_priority(200)
+        .from(from)
+        .to(from + chrono::Duration::hours(1));
+
+        let clause = filter.to_where_clause("failed_at", Placeholder::Question, 1);
+        assert_eq!(
+            clause.sql,
+            "WHERE id IN (?) AND tenant IN (?, ?) AND priority >= ? AND priority <= ? \
+             AND failed_at >= ? AND failed_at < ?"
+        );
+        assert_eq!(
+            clause.params,
+            vec![
+                FilterValue::Text(id.to_string()),
+                FilterValue::Text("acme".to_string()),
+                FilterValue::Text("globex".to_string()),
+                FilterValue::Int(10),
+                FilterValue::Int(200),
+                FilterValue::Timestamp(from),
+                FilterValue::Timestamp(from + chrono::Duration::hours(1)),
+            ]
+        );
+        assert!(filter.matches(&id, "acme", 10, from));
+        assert!(!filter.matches(&id, "initech", 10, from));
+        assert!(!filter.matches(&id, "acme", 201, from));
+        assert!(!filter.matches(&id, "acme", 10, from - chrono::Duration::seconds(1)));
+        assert!(!filter.matches(&id, "acme", 10, from + chrono::Duration::hours(1)));
+
+        let clause = filter.to_where_clause("failed_at", Placeholder::Dollar, 3);
+        assert_eq!(
+            clause.sql,
+            "WHERE id IN ($3) AND tenant IN ($4, $5) AND priority >= $6 AND priority <= $7 \
+             AND failed_at >= $8 AND failed_at < $9"
+        );
+    }
+
+    /// 测试审计日志的过滤条件、翻页游标与排序生成的 SQL。
+    #[test]
+    fn test_audit_clauses() {
+        assert_eq!(
+            AuditFilter::default().to_clauses(Placeholder::Question).sql,
+            "ORDER BY created_at DESC, id DESC"
+        );
+        let cursor: AuditCursor = "1726000000000-42".parse().unwrap();
+        assert_eq!(cursor.id, 42);
+        assert!("abc-1".parse::<AuditCursor>().is_err());
+        let filter = AuditFilter {
+            actor: Some("key123".to_string()),
+            order: AuditOrder::Asc,
+            after: Some(cursor),
+            ..Default::default()
+        };
+        let clauses = filter.to_clauses(Placeholder::Dollar);
+        assert_eq!(
+            clauses.sql,
+            "WHERE actor = $1 AND (created_at > $2 OR (created_at = $3 AND id > $4)) \
+             ORDER BY created_at ASC, id ASC"
+        );
+        assert_eq!(clauses.params.len(), 4);
+    }
+
+    /// 测试恶意输入只会作为参数绑定，不会改变 SQL 结构。
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_filter_is_injection_safe() {
+        let pool = super::super::sqlite::create_sqlite_pool("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE items (id TEXT, tenant TEXT, priority INTEGER)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for (tenant, priority) in [("acme", 10), ("globex", 50)] {
+            sqlx::query("INSERT INTO items VALUES (?, ?, ?)")
+                .bind(Uuid::new_v4().to_string())
+                .bind(tenant)
+                .bind(priority)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let count = |filter: TaskFilter| {
+            let pool = pool.clone();
+            async move {
+                let clause = filter.to_where_clause("created_at", Placeholder::Question, 1);
+                let sql = format!("SELECT COUNT(*) FROM items {}", clause.sql);
+                let (count,): (i64,) = clause
+                    .bind(sqlx::query_as(&sql))
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+                count
+            }
+        };
+
+        assert_eq!(count(TaskFilter::new()).await, 2);
+        assert_eq!(count(TaskFilter::new().tenant("acme")).await, 1);
+        assert_eq!(count(TaskFilter::new().min_priority(20)).await, 1);
+        assert_eq!(count(TaskFilter::new().tenant("acme' OR '1'='1")).await, 0);
+    }
+}
@@ -0,0 +1,321 @@
+use super::filter::{AuditFilter, AuditOrder, TaskFilter};
+use crate::annotations::{Annotation, NewAnnotation};
+use crate::audit::{AuditEntry, NewAuditEntry};
+use crate::dlq::DeadTask;
+use crate::events::{NewTaskEvent, TaskEvent};
+use crate::quarantine::QuarantinedTask;
+use crate::replay::ExecutionSnapshot;
+use crate::status::{TaskRecord, TenantTotals};
+use crate::task_types::TaskTypeOverride;
+use crate::tokens::ApiToken;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use uuid::Uuid;
+
+/// 内存中的“表”，与 MySQL 中的表结构一一对应。
+#[derive(Default)]
+struct Tables {
+    /// 对应 `tasks` 表的 `data` 字段。
+    tasks: Vec<Value>,
+    /// 对应 `task_events` 表，按 ID 递增排列。
+    task_events: Vec<TaskEvent>,
+    /// 对应 `task_status` 表。
+    task_status: HashMap<Uuid, TaskRecord>,
+    /// 对应 `dead_tasks` 表。
+    dead_tasks: HashMap<Uuid, DeadTask>,
+    /// 对应 `task_annotations` 表，按 ID 递增排列。
+    task_annotations: Vec<Annotation>,
+    /// 对应 `api_tokens` 表：令牌摘要与令牌记录。
+    api_tokens: Vec<(String, ApiToken)>,
+    /// 对应 `task_type_configs` 表。
+    task_type_configs: HashMap<String, TaskTypeOverride>,
+    /// 对应 `metric_snapshots` 表：指标键与计数器的值。
+    metric_snapshots: HashMap<String, u64>,
+    /// 对应 `tenant_stats` 表：按租户的累计任务统计。
+    tenant_stats: HashMap<String, TenantTotals>,
+    /// 对应 `quarantined_tasks` 表。
+    quarantined_tasks: HashMap<Uuid, QuarantinedTask>,
+    /// 对应 `audit_log` 表，按 ID 递增排列。
+    audit_log: Vec<AuditEntry>,
+    /// 对应 `task_snapshots` 表。
+    task_snapshots: HashMap<Uuid, ExecutionSnapshot>,
+}
+
+/// 仅用于本地开发的内存数据库。
+///
+/// 所有数据保存在进程内存中，进程退出后全部丢失，不具备持久性。
+/// 它实现了与 MySQL 后端相同的操作，使没有 MySQL 的开发者也能运行完整的服务。
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    tables: Arc<Mutex<Tables>>,
+}
+
+impl MemoryStore {
+    /// 创建一个空的内存数据库。
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 获取表的锁；锁被毒化时继续使用其中的数据，内存数据库不值得让调用方 panic。
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 将数据保存到 `tasks` 表。
+    pub fn save_data(&self, data: &Value) {
+        self.tables().tasks.push(data.clone());
+    }
+
+    /// 写入一条任务事件，返回分配的事件 ID。
+    pub fn insert_task_event(&self, event: &NewTaskEvent) -> i64 {
+        let mut tables = self.tables();
+        let id = tables.task_events.last().map_or(1, |e| e.id + 1);
+        tables.task_events.push(TaskEvent {
+            id,
+            task_id: event.task_id,
+            status: event.status,
+            created_at: event.created_at,
+        });
+        id
+    }
+
+    /// 返回 ID 大于 `after` 的前 `limit` 条任务事件。
+    pub fn task_events_after(&self, after: i64, limit: usize) -> Vec<TaskEvent> {
+        let tables = self.tables();
+        let start = tables.task_events.partition_point(|e| e.id <= after);
+        tables.task_events[start..]
+            .iter()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// 写入任务的最新状态。
+    pub fn upsert_task_status(&self, record: &TaskRecord) {
+        self.tables().task_status.insert(record.id, record.clone());
+    }
+
+    /// 读取任务的最新状态。
+    pub fn task_status(&self, id: &Uuid) -> Option<TaskRecord> {
+        self.tables().task_status.get(id).cloned()
+    }
+
+    /// 写入一个死信任务。
+    pub fn insert_dead_task(&self, dead: &DeadTask) {
+        self.tables().dead_tasks.insert(dead.id, dead.clone());
+    }
+
+    /// 按失败时间从新到旧返回满足过滤条件的前 `limit` 个死信任务。
+    pub fn dead_tasks(&self, filter: &TaskFilter, limit: usize) -> Vec<DeadTask> {
+        let mut dead: Vec<DeadTask> = self
+            .tables()
+            .dead_tasks
+            .values()
+            .filter(|dead| filter.matches(&dead.id, &dead.tenant, dead.priority, dead.failed_at))
+            .cloned()
+            .collect();
+        dead.sort_by_key(|dead| std::cmp::Reverse(dead.failed_at));
+        dead.truncate(limit);
+        dead
+    }
+
+    /// 读取并删除一个死信任务。
+    pub fn take_dead_task(&self, id: &Uuid) -> Option<DeadTask> {
+        self.tables().dead_tasks.remove(id)
+    }
+
+    /// 返回死信任务数。
+    pub fn dead_task_count(&self) -> usize {
+        self.tables().dead_tasks.len()
+    }
+
+    /// 写入一个任务的执行快照，覆盖原有快照。
+    pub fn insert_task_snapshot(&self, snapshot: &ExecutionSnapshot) {
+        self.tables()
+            .task_snapshots
+            .insert(snapshot.task.id, snapshot.clone());
+    }
+
+    /// 读取一个任务的执行快照。
+    pub fn task_snapshot(&self, id: &Uuid) -> Option<ExecutionSnapshot> {
+        self.tables().task_snapshots.get(id).cloned()
+    }
+
+    /// 写入一个隔离任务。
+    pub fn insert_quarantined_task(&self, held: &QuarantinedTask) {
+        self.tables()
+            .quarantined_tasks
+            .insert(held.id, held.clone());
+    }
+
+    /// 按隔离时间从旧到新返回前 `limit` 个隔离任务。
+    pub fn quarantined_tasks(&self, limit: usize) -> Vec<QuarantinedTask> {
+        let mut held: Vec<QuarantinedTask> =
+            self.tables().quarantined_tasks.values().cloned().collect();
+        held.sort_by_key(|held| held.quarantined_at);
+        held.truncate(limit);
+        held
+    }
+
+    /// 读取并删除一个隔离任务。
+    pub fn take_quarantined_task(&self, id: &Uuid) -> Option<QuarantinedTask> {
+        self.tables().quarantined_tasks.remove(id)
+    }
+
+    /// 返回隔离任务数。
+    pub fn quarantined_task_count(&self) -> usize {
+        self.tables().quarantined_tasks.len()
+    }
+
+    /// 写入一条审计记录。
+    pub fn insert_audit_entry(&self, entry: &NewAuditEntry) {
+        let mut tables = self.tables();
+        let id = tables.audit_log.last().map_or(1, |e| e.id + 1);
+        tables.audit_log.push(AuditEntry {
+            id,
+            actor: entry.actor.clone(),
+            action: entry.action.to_string(),
+            target: entry.target.clone(),
+            detail: entry.detail.clone(),
+            created_at: entry.created_at,
+        });
+    }
+
+    /// 按过滤条件与排序方向返回从翻页位置开始的前 `limit` 条审计记录。
+    pub fn audit_entries(&self, filter: &AuditFilter, limit: usize) -> Vec<AuditEntry> {
+        let mut entries: Vec<AuditEntry> = self
+            .tables()
+            .audit_log
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .cloned()
+            .collect();
+        entries.sort_by_key(|entry| (entry.created_at, entry.id));
+        if filter.order == AuditOrder::Desc {
+            entries.reverse();
+        }
+        entries.truncate(limit);
+        entries
+    }
+
+    /// 写入一条批注，返回写入后的批注。
+    pub fn insert_annotation(&self, annotation: &NewAnnotation) -> Annotation {
+        let mut tables = self.tables();
+        let id = tables.task_annotations.last().map_or(1, |a| a.id + 1);
+        let stored = Annotation {
+            id,
+            task_id: annotation.task_id,
+            author: annotation.author.clone(),
+            text: annotation.text.clone(),
+            created_at: annotation.created_at,
+        };
+        tables.task_annotations.push(stored.clone());
+        stored
+    }
+
+    /// 按写入顺序返回属于 `task_ids` 的所有批注。
+    pub fn annotations(&self, task_ids: &[Uuid]) -> Vec<Annotation> {
+        self.tables()
+            .task_annotations
+            .iter()
+            .filter(|a| task_ids.contains(&a.task_id))
+            .cloned()
+            .collect()
+    }
+
+    /// 写入一个 API 令牌。
+    pub fn insert_api_token(&self, token: &ApiToken, hash: &str) {
+        self.tables()
+            .api_tokens
+            .push((hash.to_string(), token.clone()));
+    }
+
+    /// 按摘要查找 API 令牌。
+    pub fn api_token_by_hash(&self, hash: &str) -> Option<ApiToken> {
+        self.tables()
+            .api_tokens
+            .iter()
+            .find(|(h, _)| h == hash)
+            .map(|(_, token)| token.clone())
+    }
+
+    /// 按创建时间从新到旧返回所有 API 令牌。
+    pub fn api_tokens(&self) -> Vec<ApiToken> {
+        let mut tokens: Vec<ApiToken> = self
+            .tables()
+            .api_tokens
+            .iter()
+            .map(|(_, token)| token.clone())
+            .collect();
+        tokens.sort_by_key(|token| std::cmp::Reverse(token.created_at));
+        tokens
+    }
+
+    /// 吊销 API 令牌，已吊销的令牌保持原有的吊销时间；令牌不存在时返回 `None`。
+    pub fn revoke_api_token(&self, id: &Uuid, at: DateTime<Utc>) -> Option<ApiToken> {
+        let mut tables = self.tables();
+        let (_, token) = tables.api_tokens.iter_mut().find(|(_, t)| t.id == *id)?;
+        token.revoked_at.get_or_insert(at);
+        Some(token.clone())
+    }
+
+    /// 写入任务类型的配置，覆盖同一任务类型原有的配置。
+    pub fn upsert_task_type_config(&self, record: &TaskTypeOverride) {
+        self.tables()
+            .task_type_configs
+            .insert(record.task_type.clone(), record.clone());
+    }
+
+    /// 按任务类型名称排序返回所有任务类型的配置。
+    pub fn task_type_configs(&self) -> Vec<TaskTypeOverride> {
+        let mut configs: Vec<TaskTypeOverride> =
+            self.tables().task_type_configs.values().cloned().collect();
+        configs.sort_by(|a, b| a.task_type.cmp(&b.task_type));
+        configs
+    }
+
+    /// 保存计数器的值，覆盖同名计数器原有的值。
+    pub fn save_metric_snapshots(&self, counters: &[(String, u64)]) {
+        self.tables()
+            .metric_snapshots
+            .extend(counters.iter().cloned());
+    }
+
+    /// 返回所有保存的计数器。
+    pub fn metric_snapshots(&self) -> Vec<(String, u64)> {
+        self.tables()
+            .metric_snapshots
+            .iter()
+            .map(|(name, value)| (name.clone(), *value))
+            .collect()
+    }
+
+    /// 把按租户统计的增量累加到对应租户的累计值上。
+    pub fn add_tenant_stats(&self, totals: &[TenantTotals]) {
+        let mut tables = self.tables();
+        for totals in totals {
+            let saved = tables
+                .tenant_stats
+                .entry(totals.tenant.clone())
+                .or_insert_with(|| TenantTotals {
+                    tenant: totals.tenant.clone(),
+                    ..Default::default()
+                });
+            saved.succeeded += totals.succeeded;
+            saved.failed += totals.failed;
+            saved.total_latency_ms += totals.total_latency_ms;
+        }
+    }
+
+    /// 返回所有租户的累计统计。
+    pub fn tenant_stats(&self) -> Vec<TenantTotals> {
+        self.tables().tenant_stats.values().cloned().collect()
+    }
+
+    /// 返回 `tasks` 表中的记录数。
+    pub fn task_count(&self) -> usize {
+        self.tables().tasks.len()
+    }
+}
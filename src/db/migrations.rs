@@ -0,0 +1,155 @@
+use super::tables;
+use serde::Serialize;
+use sqlx::migrate::{Migrate, MigrateError, Migration, Migrator};
+use sqlx::pool::PoolConnection;
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+/// MySQL 的迁移脚本，编译时嵌入二进制文件。
+pub static MYSQL_MIGRATOR: Migrator = sqlx::migrate!("./migrations/mysql");
+
+/// SQLite 的迁移脚本，编译时嵌入二进制文件。
+#[cfg(feature = "sqlite")]
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// PostgreSQL 的迁移脚本，编译时嵌入二进制文件。
+#[cfg(feature = "postgres")]
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// 按表名前缀改写后的 MySQL 迁移脚本。
+pub fn mysql_migrator() -> &'static Migrator {
+    static PREFIXED: OnceLock<Migrator> = OnceLock::new();
+    prefixed(&MYSQL_MIGRATOR, &PREFIXED)
+}
+
+/// 按表名前缀改写后的 SQLite 迁移脚本。
+#[cfg(feature = "sqlite")]
+pub fn sqlite_migrator() -> &'static Migrator {
+    static PREFIXED: OnceLock<Migrator> = OnceLock::new();
+    prefixed(&SQLITE_MIGRATOR, &PREFIXED)
+}
+
+/// 按表名前缀改写后的 PostgreSQL 迁移脚本。
+#[cfg(feature = "postgres")]
+pub fn postgres_migrator() -> &'static Migrator {
+    static PREFIXED: OnceLock<Migrator> = OnceLock::new();
+    prefixed(&POSTGRES_MIGRATOR, &PREFIXED)
+}
+
+/// 没有设置表名前缀时直接使用嵌入的迁移脚本，校验和与之前应用的记录一致。
+fn prefixed(base: &'static Migrator, cache: &'static OnceLock<Migrator>) -> &'static Migrator {
+    let prefix = tables::table_prefix();
+    if prefix.is_empty() {
+        return base;
+    }
+    cache.get_or_init(|| with_table_prefix(base, prefix))
+}
+
+/// 为迁移脚本中的表名与索引名加上前缀，并重新计算校验和。
+///
+/// 迁移记录表 `_sqlx_migrations` 的名字由 sqlx 固定，不受前缀影响。
+pub fn with_table_prefix(base: &Migrator, prefix: &str) -> Migrator {
+    let migrations = base
+        .iter()
+        .map(|m| {
+            Migration::new(
+                m.version,
+                m.description.clone(),
+                m.migration_type,
+                Cow::Owned(tables::with_prefix(&m.sql, prefix)),
+            )
+        })
+        .collect::<Vec<_>>();
+    Migrator {
+        migrations: Cow::Owned(migrations),
+        ignore_missing: base.ignore_missing,
+        locking: base.locking,
+    }
+}
+
+/// 单个迁移脚本的状态。
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationInfo {
+    /// 迁移版本号（文件名前缀）。
+    pub version: i64,
+    /// 迁移描述（文件名的其余部分）。
+    pub description: String,
+    /// 已应用的迁移脚本与当前二进制中的内容是否不一致。
+    pub checksum_mismatch: bool,
+}
+
+/// 数据库当前的表结构状态。
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    /// 已应用的迁移。
+    pub applied: Vec<MigrationInfo>,
+    /// 尚未应用的迁移。
+    pub pending: Vec<MigrationInfo>,
+    /// 执行失败、处于“半应用”状态的迁移版本。
+    pub dirty_version: Option<i64>,
+    /// 数据库中已应用、但当前二进制不认识的迁移版本（通常说明数据库被更新版本的服务迁移过）。
+    pub unknown_applied: Vec<i64>,
+}
+
+impl MigrationStatus {
+    /// 当前二进制期望的最新迁移版本。
+    pub fn expected_version(&self) -> Option<i64> {
+        self.applied
+            .iter()
+            .chain(&self.pending)
+            .map(|m| m.version)
+            .max()
+    }
+
+    /// 数据库中已应用的最新迁移版本。
+    pub fn applied_version(&self) -> Option<i64> {
+        self.applied
+            .iter()
+            .map(|m| m.version)
+            .chain(self.unknown_applied.iter().copied())
+            .max()
+    }
+}
+
+/// 对比迁移脚本与数据库中的记录，返回已应用与待应用的迁移。
+pub async fn status<DB>(
+    conn: &mut PoolConnection<DB>,
+    migrator: &Migrator,
+) -> Result<MigrationStatus, MigrateError>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    let conn: &mut DB::Connection = conn;
+    conn.ensure_migrations_table().await?;
+    let dirty_version = conn.dirty_version().await?;
+    let applied = conn.list_applied_migrations().await?;
+
+    let mut status = MigrationStatus {
+        applied: Vec::new(),
+        pending: Vec::new(),
+        dirty_version,
+        unknown_applied: applied
+            .iter()
+            .map(|a| a.version)
+            .filter(|v| migrator.iter().all(|m| m.version != *v))
+            .collect(),
+    };
+    // 只统计“升级”脚本，回滚脚本不参与版本比较
+    for migration in migrator
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+    {
+        let record = applied.iter().find(|a| a.version == migration.version);
+        let info = MigrationInfo {
+            version: migration.version,
+            description: migration.description.to_string(),
+            checksum_mismatch: record.is_some_and(|a| a.checksum != migration.checksum),
+        };
+        match record {
+            Some(_) => status.applied.push(info),
+            None => status.pending.push(info),
+        }
+    }
+    Ok(status)
+}
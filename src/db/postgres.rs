@@ -0,0 +1,143 @@
+use super::PoolSettings;
+use serde_json::Value;
+use sqlx::postgres::{PgDatabaseError, PgPoolOptions};
+use sqlx::{Error as SqlxError, PgPool};
+
+/// PostgreSQL 因超过 `statement_timeout` 而取消查询时返回的 SQLSTATE（`query_canceled`）。
+const PG_QUERY_CANCELED: &str = "57014";
+
+/// 根据提供的数据库 URL 创建一个 `PgPool` 连接池。
+pub async fn create_postgres_pool(
+    database_url: &str,
+    settings: &PoolSettings,
+) -> Result<PgPool, SqlxError> {
+    postgres_pool_options(settings).connect(database_url).await
+}
+
+/// PostgreSQL 连接池的参数，立即连接与延迟连接共用。
+///
+/// 与 MySQL 相同，服务端的查询超时与只读模式在每个新连接上以会话参数设置；
+/// PostgreSQL 的 `statement_timeout` 对所有语句生效，而不只是 SELECT。
+pub fn postgres_pool_options(settings: &PoolSettings) -> PgPoolOptions {
+    let statement_timeout_ms = settings.statement_timeout.map(|t| t.as_millis() as u64);
+    let read_only = settings.read_only;
+    settings
+        .pool_options::<sqlx::Postgres>()
+        .after_connect(move |conn, _meta| {
+            Box::pin(async move {
+                if let Some(ms) = statement_timeout_ms {
+                    sqlx::query(&format!("SET statement_timeout = {}", ms))
+                        .execute(&mut *conn)
+                        .await?;
+                }
+                if read_only {
+                    sqlx::query("SET default_transaction_read_only = on")
+                        .execute(&mut *conn)
+                        .await?;
+                }
+                Ok(())
+            })
+        })
+}
+
+/// 判断一个数据库错误是否为服务端因 `statement_timeout` 取消的查询。
+pub fn is_statement_timeout(e: &(dyn sqlx::error::DatabaseError + 'static)) -> bool {
+    e.try_downcast_ref::<PgDatabaseError>()
+        .is_some_and(|e| e.code() == PG_QUERY_CANCELED)
+}
+
+/// 将数据保存到 PostgreSQL 的 `tasks` 表。
+pub async fn save_data_to_postgres(pool: &PgPool, data: &Value) -> Result<(), SqlxError> {
+    sqlx::query(super::tables::postgres(
+        "INSERT INTO tasks (data) VALUES (?)",
+    ))
+    .bind(data)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{migrations, Database};
+    use crate::dlq::DeadTask;
+    use crate::queue::Task;
+    use crate::status::{TaskRecord, TaskState};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    /// 连接 `POSTGRES_URL` 指向的数据库并应用全部迁移。
+    async fn test_database() -> Database {
+        let url = std::env::var("POSTGRES_URL").expect("需要设置 POSTGRES_URL");
+        let pool = super::create_postgres_pool(&url, &Default::default())
+            .await
+            .unwrap();
+        migrations::POSTGRES_MIGRATOR.run(&pool).await.unwrap();
+        Database::Postgres(pool, None)
+    }
+
+    /// 测试改写后的查询在 PostgreSQL 上的读写，包括 `REPLACE INTO` 改写成的 upsert 与认领。
+    /// 需要设置 `POSTGRES_URL` 后以 `--features postgres -- --ignored` 运行。
+    #[tokio::test]
+    #[ignore]
+    async fn test_postgres_roundtrip() {
+        let db = test_database().await;
+        db.ping().await.unwrap();
+        let status = db.migration_status().await.unwrap().unwrap();
+        assert!(status.pending.is_empty());
+
+        let now = chrono::Utc::now();
+        let mut record = TaskRecord {
+            id: Uuid::new_v4(),
+            tenant: "default".to_string(),
+            status: TaskState::Queued,
+            priority: 10,
+            retry_count: 0,
+            created_at: now,
+            updated_at: now,
+            last_error: None,
+            version: 1,
+        };
+        db.upsert_task_status(&record).await.unwrap();
+        record.status = TaskState::Running;
+        record.version += 1;
+        db.upsert_task_status(&record).await.unwrap();
+        let stored = db.task_status(&record.id).await.unwrap().unwrap();
+        assert_eq!((stored.status, stored.version), (TaskState::Running, 2));
+
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "type": "quick" }).into(),
+            priority: 7,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let owner = format!("pg-test-{}", Uuid::new_v4());
+        db.journal_task(&task, &owner).await.unwrap();
+        db.journal_task(&task, &owner).await.unwrap();
+        let claimed = db
+            .claim_journaled_tasks(&owner, Some(&owner), chrono::Utc::now(), 1000)
+            .await
+            .unwrap();
+        assert!(claimed.iter().any(|t| t.id == task.id));
+        db.remove_journaled_task(&task.id).await.unwrap();
+
+        let dead = DeadTask {
+            id: Uuid::new_v4(),
+            tenant: "default".to_string(),
+            payload: json!({ "type": "quick" }),
+            priority: 1,
+            retry_count: 3,
+            error: "boom".to_string(),
+            failed_at: chrono::Utc::now(),
+        };
+        db.insert_dead_task(&dead).await.unwrap();
+        let taken = db.take_dead_task(&dead.id).await.unwrap().unwrap();
+        assert_eq!((taken.id, taken.error.as_str()), (dead.id, "boom"));
+        assert!(db.take_dead_task(&dead.id).await.unwrap().is_none());
+    }
+}
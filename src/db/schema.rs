@@ -0,0 +1,291 @@
+use super::Database;
+use serde::Serialize;
+
+/// 服务运行所依赖的表与列。
+///
+/// 新增表或列时需要同步更新这里，启动检查会逐一确认它们存在。
+pub const REQUIRED_COLUMNS: &[(&str, &[&str])] = &[
+    ("tasks", &["id", "data"]),
+    ("task_events", &["id", "task_id", "status", "created_at"]),
+    (
+        "tasks_queue",
+        &[
+            "id",
+            "payload",
+            "priority",
+            "retry_count",
+            "enqueued_at",
+            "run_at",
+            "claimed_by",
+            "claimed_at",
+            "context",
+            "kind",
+            "depends_on",
+            "tenant",
+        ],
+    ),
+    (
+        "task_status",
+        &[
+            "id",
+            "tenant",
+            "status",
+            "priority",
+            "retry_count",
+            "last_error",
+            "version",
+            "created_at",
+            "updated_at",
+        ],
+    ),
+    (
+        "dead_tasks",
+        &[
+            "id",
+            "tenant",
+            "payload",
+            "priority",
+            "retry_count",
+            "error",
+            "failed_at",
+        ],
+    ),
+    (
+        "task_annotations",
+        &["id", "task_id", "author", "text", "created_at"],
+    ),
+    (
+        "api_tokens",
+        &[
+            "id",
+            "token_hash",
+            "name",
+            "scope",
+            "tenant",
+            "created_at",
+            "expires_at",
+            "revoked_at",
+        ],
+    ),
+    (
+        "task_type_configs",
+        &[
+            "task_type",
+            "max_retries",
+            "timeout_ms",
+            "max_concurrency",
+            "rate_per_minute",
+            "updated_at",
+        ],
+    ),
+    ("metric_snapshots", &["name", "value", "updated_at"]),
+    (
+        "tenant_stats",
+        &[
+            "tenant",
+            "succeeded",
+            "failed",
+            "total_latency_ms",
+            "updated_at",
+        ],
+    ),
+    (
+        "quarantined_tasks",
+        &[
+            "id",
+            "tenant",
+            "payload",
+            "priority",
+            "run_at",
+            "context",
+            "reasons",
+            "quarantined_at",
+            "kind",
+        ],
+    ),
+    (
+        "audit_log",
+        &["id", "actor", "action", "target", "detail", "created_at"],
+    ),
+    ("task_snapshots", &["id", "snapshot", "captured_at"]),
+];
+
+/// 表结构检查失败的原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SchemaErrorCode {
+    /// 数据库尚未连接（`DB_CONNECT_LAZY`），还没有检查表结构。
+    #[serde(rename = "SCHEMA_CHECK_PENDING")]
+    Pending,
+    /// 无法读取迁移记录（数据库不可用或权限不足）。
+    #[serde(rename = "SCHEMA_CHECK_FAILED")]
+    CheckFailed,
+    /// 存在执行失败、处于半应用状态的迁移。
+    #[serde(rename = "SCHEMA_DIRTY")]
+    Dirty,
+    /// 存在尚未应用的迁移。
+    #[serde(rename = "SCHEMA_MIGRATIONS_PENDING")]
+    MigrationsPending,
+    /// 数据库已被更新版本的服务迁移过，当前二进制不认识其中的迁移。
+    #[serde(rename = "SCHEMA_TOO_NEW")]
+    TooNew,
+    /// 已应用的迁移脚本与当前二进制中的内容不一致。
+    #[serde(rename = "SCHEMA_CHECKSUM_MISMATCH")]
+    ChecksumMismatch,
+    /// 缺少必需的表或列。
+    #[serde(rename = "SCHEMA_MISSING_COLUMNS")]
+    MissingColumns,
+}
+
+/// 表结构兼容性检查的结果。
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaCheck {
+    /// 表结构是否与当前二进制兼容。
+    pub ok: bool,
+    /// 不兼容时的错误码。
+    pub code: Option<SchemaErrorCode>,
+    /// 便于排查的详细说明。
+    pub message: String,
+    /// 当前二进制期望的迁移版本。
+    pub expected_version: Option<i64>,
+    /// 数据库中已应用的最新迁移版本。
+    pub applied_version: Option<i64>,
+}
+
+impl SchemaCheck {
+    /// 数据库尚未连接时的检查结果，连接成功后由启动流程替换为实际的检查结果。
+    pub fn pending() -> Self {
+        Self::failed(
+            SchemaErrorCode::Pending,
+            "数据库尚未连接，表结构尚未检查".to_string(),
+        )
+    }
+
+    fn failed(code: SchemaErrorCode, message: String) -> Self {
+        Self {
+            ok: false,
+            code: Some(code),
+            message,
+            expected_version: None,
+            applied_version: None,
+        }
+    }
+}
+
+/// 检查数据库表结构是否与当前二进制兼容。
+///
+/// 依次检查迁移记录（半应用、待应用、未知版本、校验和）以及必需的表和列，
+/// 返回第一个发现的问题。没有表结构的后端（内存数据库）总是兼容的。
+pub async fn check_schema(db: &Database) -> SchemaCheck {
+    let status = match db.migration_status().await {
+        Ok(Some(status)) => status,
+        Ok(None) => {
+            return SchemaCheck {
+                ok: true,
+                code: None,
+                message: format!("{} 后端没有表结构", db.backend_name()),
+                expected_version: None,
+                applied_version: None,
+            }
+        }
+        Err(e) => {
+            return SchemaCheck::failed(
+                SchemaErrorCode::CheckFailed,
+                format!("无法读取迁移记录: {}", e),
+            )
+        }
+    };
+
+    let problem = if let Some(version) = status.dirty_version {
+        Some((
+            SchemaErrorCode::Dirty,
+            format!("迁移 {} 执行失败，数据库处于半应用状态", version),
+        ))
+    } else if !status.unknown_applied.is_empty() {
+        Some((
+            SchemaErrorCode::TooNew,
+            format!(
+                "数据库包含当前版本不认识的迁移: {:?}",
+                status.unknown_applied
+            ),
+        ))
+    } else if let Some(m) = status.applied.iter().find(|m| m.checksum_mismatch) {
+        Some((
+            SchemaErrorCode::ChecksumMismatch,
+            format!("迁移 {} 的内容与已应用的版本不一致", m.version),
+        ))
+    } else if !status.pending.is_empty() {
+        let versions: Vec<i64> = status.pending.iter().map(|m| m.version).collect();
+        Some((
+            SchemaErrorCode::MigrationsPending,
+            format!("存在尚未应用的迁移: {:?}", versions),
+        ))
+    } else {
+        missing_columns(db).await.map(|missing| {
+            (
+                SchemaErrorCode::MissingColumns,
+                format!("缺少必需的表或列: {}", missing.join(", ")),
+            )
+        })
+    };
+
+    let (code, message) = match problem {
+        Some((code, message)) => (Some(code), message),
+        None => (None, "表结构与当前版本兼容".to_string()),
+    };
+    SchemaCheck {
+        ok: code.is_none(),
+        code,
+        message,
+        expected_version: status.expected_version(),
+        applied_version: status.applied_version(),
+    }
+}
+
+/// 逐一探测必需的表和列，返回缺失的 `表(列...)` 列表；全部存在时返回 `None`。
+async fn missing_columns(db: &Database) -> Option<Vec<String>> {
+    let mut missing = Vec::new();
+    for (table, columns) in REQUIRED_COLUMNS {
+        if let Err(e) = db.probe_columns(table, columns).await {
+            tracing::debug!(table, "表结构探测失败: {}", e);
+            missing.push(format!("{}({})", table, columns.join(", ")));
+        }
+    }
+    (!missing.is_empty()).then_some(missing)
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+
+    /// 测试已完成迁移的数据库通过检查。
+    #[tokio::test]
+    async fn test_check_schema_ok() {
+        let db = Database::Sqlite(super::super::sqlite::test_pool().await, None);
+        let check = check_schema(&db).await;
+        assert!(check.ok, "{}", check.message);
+        assert_eq!(check.expected_version, check.applied_version);
+    }
+
+    /// 测试未迁移的数据库被报告为存在待应用的迁移。
+    #[tokio::test]
+    async fn test_check_schema_pending() {
+        let pool = super::super::sqlite::create_sqlite_pool("sqlite::memory:")
+            .await
+            .unwrap();
+        let check = check_schema(&Database::Sqlite(pool, None)).await;
+        assert!(!check.ok);
+        assert_eq!(check.code, Some(SchemaErrorCode::MigrationsPending));
+    }
+
+    /// 测试迁移记录完整但缺少列时被识别出来。
+    #[tokio::test]
+    async fn test_check_schema_missing_columns() {
+        let pool = super::super::sqlite::test_pool().await;
+        sqlx::query("ALTER TABLE tasks RENAME COLUMN data TO payload")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let check = check_schema(&Database::Sqlite(pool, None)).await;
+        assert_eq!(check.code, Some(SchemaErrorCode::MissingColumns));
+    }
+}
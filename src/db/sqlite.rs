@@ -0,0 +1,59 @@
+use serde_json::Value;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Error as SqlxError, SqlitePool};
+use std::str::FromStr;
+
+/// 根据提供的数据库 URL 创建一个 `SqlitePool` 连接池。
+///
+/// 内存数据库（`sqlite::memory:`）的每个连接都是一个独立的数据库，
+/// 因此此时连接池只保留一个永不过期的连接，保证所有查询看到同一份数据。
+pub async fn create_sqlite_pool(database_url: &str) -> Result<SqlitePool, SqlxError> {
+    sqlite_pool_options(database_url)
+        .connect(database_url)
+        .await
+}
+
+/// 以只读模式打开 SQLite 数据库，数据库文件不存在时报错而不是创建。
+pub async fn create_read_only_sqlite_pool(database_url: &str) -> Result<SqlitePool, SqlxError> {
+    let options = SqliteConnectOptions::from_str(database_url)?
+        .read_only(true)
+        .create_if_missing(false);
+    sqlite_pool_options(database_url)
+        .connect_with(options)
+        .await
+}
+
+/// SQLite 连接池的参数，立即连接与延迟连接共用。
+pub fn sqlite_pool_options(database_url: &str) -> SqlitePoolOptions {
+    if database_url.contains(":memory:") {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        SqlitePoolOptions::new()
+    }
+}
+
+/// 将数据保存到 SQLite 的 `tasks` 表。
+pub async fn save_data_to_sqlite(pool: &SqlitePool, data: &Value) -> Result<(), SqlxError> {
+    sqlx::query(super::tables::sql("INSERT INTO tasks (data) VALUES (?)"))
+        .bind(data)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// 创建一个已应用全部迁移的 SQLite 内存数据库，供测试使用。
+#[cfg(test)]
+pub async fn test_pool() -> SqlitePool {
+    let pool = create_sqlite_pool("sqlite::memory:")
+        .await
+        .expect("failed to open in-memory sqlite database");
+    super::migrations::SQLITE_MIGRATOR
+        .run(&pool)
+        .await
+        .expect("failed to run sqlite migrations");
+    pool
+}
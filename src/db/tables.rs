@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// 服务使用的所有表，设置了表名前缀时这些表名都会加上前缀。
+pub const TABLES: &[&str] = &[
+    "tasks",
+    "task_events",
+    "tasks_queue",
+    "task_status",
+    "dead_tasks",
+    "task_annotations",
+    "api_tokens",
+    "task_type_configs",
+    "metric_snapshots",
+    "quarantined_tasks",
+    "audit_log",
+    "task_snapshots",
+    "tenant_stats",
+];
+
+/// 表名前缀的最大长度，加上最长的表名与索引名后仍在 MySQL 的 64 字符限制之内。
+pub const MAX_PREFIX_LEN: usize = 16;
+
+/// 表名前缀，由 `set_table_prefix` 在启动时设置，未设置时为空。
+static PREFIX: OnceLock<String> = OnceLock::new();
+
+/// 加上前缀之后的查询语句，每条查询只改写一次。
+static REWRITTEN: OnceLock<Mutex<HashMap<&'static str, &'static str>>> = OnceLock::new();
+/// 加上前缀并改写为 PostgreSQL 语法之后的查询语句，每条查询只改写一次。
+#[cfg(feature = "postgres")]
+static POSTGRES: OnceLock<Mutex<HashMap<&'static str, &'static str>>> = OnceLock::new();
+
+/// 检查表名前缀是否合法：只能包含字母、数字和下划线，且不能以数字开头。
+pub fn validate_prefix(prefix: &str) -> Result<(), String> {
+    if prefix.len() > MAX_PREFIX_LEN {
+        return Err(format!("不能超过 {} 个字符", MAX_PREFIX_LEN));
+    }
+    if !prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err("只能包含字母、数字和下划线".to_string());
+    }
+    if prefix.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err("不能以数字开头".to_string());
+    }
+    Ok(())
+}
+
+/// 设置表名前缀，只能在启动时、执行任何查询之前调用一次，重复调用会被忽略。
+pub fn set_table_prefix(prefix: &str) {
+    let _ = PREFIX.set(prefix.to_string());
+}
+
+/// 当前的表名前缀。
+pub fn table_prefix() -> &'static str {
+    PREFIX.get().map_or("", String::as_str)
+}
+
+/// 加上前缀的表名。
+pub fn table(name: &str) -> String {
+    format!("{}{}", table_prefix(), name)
+}
+
+/// 为代码中的查询语句加上表名前缀；没有设置前缀时原样返回。
+///
+/// 改写结果会被缓存并在进程生命周期内保留，`query` 只能是代码中的常量。
+pub fn sql(query: &'static str) -> &'static str {
+    let prefix = table_prefix();
+    if prefix.is_empty() {
+        return query;
+    }
+    let cache = REWRITTEN.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    cache
+        .entry(query)
+        .or_insert_with(|| Box::leak(with_prefix(query, prefix).into_boxed_str()))
+}
+
+/// 为代码中的查询语句加上表名前缀，并改写为 PostgreSQL 的语法（见 `to_postgres`）。
+///
+/// 改写结果会被缓存并在进程生命周期内保留，`query` 只能是代码中的常量。
+#[cfg(feature = "postgres")]
+pub fn postgres(query: &'static str) -> &'static str {
+    let cache = POSTGRES.get_or_init(|| Mutex::new(HashMap::new()));
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    cache.entry(query).or_insert_with(|| {
+        let prefixed = with_prefix(query, table_prefix());
+        Box::leak(to_postgres(&prefixed).into_boxed_str())
+    })
+}
+
+/// 将以 MySQL 语法编写的查询改写为 PostgreSQL 的语法。
+///
+/// - `?` 占位符按顺序改写为 `$1, $2, ...`，字符串字面量中的 `?` 保持不变；
+/// - `REPLACE INTO t (c1, c2, ...) VALUES (...)` 改写为
+///   `INSERT INTO ... ON CONFLICT (c1) DO UPDATE SET c2 = EXCLUDED.c2, ...`，
+///   即以第一列作为冲突键，因此使用 `REPLACE INTO` 的查询必须把主键列写在第一位。
+#[cfg(feature = "postgres")]
+pub fn to_postgres(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len() + 16);
+    let mut n = 0;
+    let mut in_string = false;
+    for c in sql.chars() {
+        match c {
+            '\'' => {
+                in_string = !in_string;
+                out.push(c);
+            }
+            '?' if !in_string => {
+                n += 1;
+                out.push_str(&format!("${}", n));
+            }
+            _ => out.push(c),
+        }
+    }
+    match out.strip_prefix("REPLACE INTO ") {
+        Some(rest) => {
+            let columns: Vec<&str> = rest
+                .find('(')
+                .zip(rest.find(')'))
+                .map(|(start, end)| rest[start + 1..end].split(',').map(str::trim).collect())
+                .unwrap_or_default();
+            let Some((key, others)) = columns.split_first() else {
+                return out;
+            };
+            let updates: Vec<String> = others
+                .iter()
+                .map(|column| format!("{} = EXCLUDED.{}", column, column))
+                .collect();
+            format!(
+                "INSERT INTO {} ON CONFLICT ({}) DO UPDATE SET {}",
+                rest,
+                key,
+                updates.join(", ")
+            )
+        }
+        None => out,
+    }
+}
+
+/// 为 SQL 中的表名与索引名加上前缀。
+///
+/// 逐个扫描标识符：属于 `TABLES` 的表名以及以 `idx_` 开头的索引名（SQLite 的索引名在整个数据库内唯一）
+/// 加上前缀，字符串字面量与注释中的内容保持不变。
+pub fn with_prefix(sql: &str, prefix: &str) -> String {
+    if prefix.is_empty() {
+        return sql.to_string();
+    }
+    let mut out = String::with_capacity(sql.len() + prefix.len() * 4);
+    let mut rest = sql;
+    while let Some(c) = rest.chars().next() {
+        if c == '\'' {
+            // 字符串字面量原样保留（`''` 表示转义的单引号，同样适用）
+            let end = rest[1..].find('\'').map_or(rest.len(), |i| i + 2);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if rest.starts_with("--") {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let ident = &rest[..end];
+            if TABLES.contains(&ident) || ident.starts_with("idx_") {
+                out.push_str(prefix);
+            }
+            out.push_str(ident);
+            rest = &rest[end..];
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试只有表名与索引名会加上前缀。
+    #[test]
+    fn test_with_prefix() {
+        assert_eq!(
+            with_prefix(
+                "SELECT id, status FROM task_status WHERE tenant = 'tasks' -- tasks\n",
+                "webq_"
+            ),
+            "SELECT id, status FROM webq_task_status WHERE tenant = 'tasks' -- tasks\n"
+        );
+        assert_eq!(
+            with_prefix(
+                "CREATE INDEX idx_tasks_queue_enqueued_at ON tasks_queue (enqueued_at);",
+                "webq_"
+            ),
+            "CREATE INDEX webq_idx_tasks_queue_enqueued_at ON webq_tasks_queue (enqueued_at);"
+        );
+        // `tasks_queue` 不会被当作 `tasks` 再加一次前缀
+        assert_eq!(
+            with_prefix("REPLACE INTO tasks_queue (id) VALUES (?)", "p_"),
+            "REPLACE INTO p_tasks_queue (id) VALUES (?)"
+        );
+        assert_eq!(with_prefix("DELETE FROM tasks", ""), "DELETE FROM tasks");
+    }
+
+    /// 测试占位符与 `REPLACE INTO` 改写为 PostgreSQL 语法。
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn test_to_postgres() {
+        assert_eq!(
+            to_postgres("SELECT id FROM task_status WHERE tenant = '?' AND id = ? LIMIT ?"),
+            "SELECT id FROM task_status WHERE tenant = '?' AND id = $1 LIMIT $2"
+        );
+        assert_eq!(
+            to_postgres("REPLACE INTO metric_snapshots (name, value, updated_at) VALUES (?, ?, ?)"),
+            "INSERT INTO metric_snapshots (name, value, updated_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (name) DO UPDATE SET value = EXCLUDED.value, updated_at = EXCLUDED.updated_at"
+        );
+        assert_eq!(
+            with_prefix(
+                &to_postgres("REPLACE INTO tasks_queue (id, payload) VALUES (?, ?)"),
+                "p_"
+            ),
+            "INSERT INTO p_tasks_queue (id, payload) VALUES ($1, $2) \
+             ON CONFLICT (id) DO UPDATE SET payload = EXCLUDED.payload"
+        );
+    }
+
+    /// 测试加上前缀的迁移脚本只创建带前缀的表，且能通过带前缀的查询访问。
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_prefixed_migrations_sqlite() {
+        use crate::db::{migrations, sqlite};
+
+        let pool = sqlite::create_sqlite_pool("sqlite::memory:").await.unwrap();
+        let migrator = migrations::with_table_prefix(&migrations::SQLITE_MIGRATOR, "webq_");
+        migrator.run(&pool).await.unwrap();
+
+        let names: Vec<(String,)> = sqlx::query_as(
+            "SELECT name FROM sqlite_master WHERE type IN ('table', 'index') \
+             AND name NOT LIKE 'sqlite_%' AND name <> '_sqlx_migrations' ORDER BY name",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert!(!names.is_empty());
+        assert!(
+            names.iter().all(|(name,)| name.starts_with("webq_")),
+            "{:?}",
+            names
+        );
+        for table in TABLES {
+            sqlx::query(&with_prefix(
+                &format!("SELECT COUNT(*) FROM {}", table),
+                "webq_",
+            ))
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+    }
+
+    /// 测试表名前缀的校验。
+    #[test]
+    fn test_validate_prefix() {
+        assert!(validate_prefix("").is_ok());
+        assert!(validate_prefix("webq_").is_ok());
+        assert!(validate_prefix("web-q").is_err());
+        assert!(validate_prefix("1q_").is_err());
+        assert!(validate_prefix(&"x".repeat(MAX_PREFIX_LEN + 1)).is_err());
+    }
+}
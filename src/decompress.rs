@@ -0,0 +1,253 @@
+use crate::error::AppError;
+use crate::i18n::Message;
+use crate::metrics;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::io::Read;
+
+/// 请求体的大小限制，同时约束压缩与未压缩的请求体。
+#[derive(Debug, Clone, Copy)]
+pub struct RequestBodySettings {
+    /// 请求体（压缩的请求体按解压后计算）的最大字节数。
+    pub limit: usize,
+    /// 解压后与压缩前的最大大小比例，超过即视为压缩炸弹。
+    pub max_ratio: usize,
+}
+
+impl Default for RequestBodySettings {
+    fn default() -> Self {
+        Self {
+            limit: 2 * 1024 * 1024,
+            max_ratio: 100,
+        }
+    }
+}
+
+/// 支持的 `Content-Encoding`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Gzip,
+    Zstd,
+}
+
+impl Encoding {
+    /// 解析 `Content-Encoding`；`identity` 或未设置时返回 `None`，不支持的编码返回错误。
+    fn parse(value: &str) -> Result<Option<Self>, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => Ok(None),
+            "gzip" | "x-gzip" => Ok(Some(Encoding::Gzip)),
+            "zstd" => Ok(Some(Encoding::Zstd)),
+            other => Err(other.to_string()),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+        }
+    }
+}
+
+/// 解压失败的原因。
+#[derive(Debug)]
+enum DecodeError {
+    /// 解压后的大小超过了 `limit`。
+    TooLarge,
+    /// 解压后的大小超过了压缩前的 `max_ratio` 倍。
+    RatioExceeded,
+    /// 数据不是合法的压缩格式。
+    Corrupt(std::io::Error),
+}
+
+/// 按 `Content-Encoding` 解压请求体的中间件。
+///
+/// 支持 `gzip` 与 `zstd`，解压后去掉 `Content-Encoding` 并更新 `Content-Length`，
+/// 后续的 handler 看到的是普通的请求体。解压以流的方式进行，一旦输出超过 `limit`
+/// 或压缩前大小的 `max_ratio` 倍就立即停止，防止小请求解压出巨大的数据（压缩炸弹）。
+pub async fn decompress_request(
+    State(settings): State<RequestBodySettings>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    Ok(next.run(decompress(settings, request).await?).await)
+}
+
+/// 按 `Content-Encoding` 解压请求体，返回去掉 `Content-Encoding` 的请求；没有压缩的请求原样返回。
+///
+/// 除了中间件之外，鉴权中间件在需要读取请求体做授权决策时也通过它解压，
+/// 此后路由上的解压中间件看到的是未压缩的请求，不会重复解压。
+pub async fn decompress(
+    settings: RequestBodySettings,
+    request: Request,
+) -> Result<Request, AppError> {
+    let encoding = request
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .map(|value| value.to_str().unwrap_or("?"))
+        .map(Encoding::parse)
+        .transpose()
+        .map_err(|encoding| {
+            reject("unsupported");
+            AppError::UnsupportedMediaType(
+                Message::new("UNSUPPORTED_CONTENT_ENCODING").arg("encoding", encoding),
+            )
+        })?
+        .flatten();
+    let Some(encoding) = encoding else {
+        return Ok(request);
+    };
+
+    let (mut parts, body) = request.into_parts();
+    // 压缩后的请求体不会比解压后的更大，同样受 `limit` 约束
+    let compressed = to_bytes(body, settings.limit).await.map_err(|_| {
+        reject("too_large");
+        AppError::PayloadTooLarge(
+            Message::new("REQUEST_BODY_TOO_LARGE").arg("limit", settings.limit),
+        )
+    })?;
+    let decoded = decode(encoding, &compressed, settings).map_err(|e| match e {
+        DecodeError::TooLarge => {
+            reject("too_large");
+            AppError::PayloadTooLarge(
+                Message::new("REQUEST_BODY_TOO_LARGE").arg("limit", settings.limit),
+            )
+        }
+        DecodeError::RatioExceeded => {
+            reject("ratio");
+            tracing::warn!(
+                encoding = encoding.as_str(),
+                compressed = compressed.len(),
+                "请求体的压缩比超过 {}，疑似压缩炸弹",
+                settings.max_ratio
+            );
+            AppError::PayloadTooLarge(
+                Message::new("COMPRESSION_RATIO_TOO_HIGH").arg("ratio", settings.max_ratio),
+            )
+        }
+        DecodeError::Corrupt(e) => {
+            reject("corrupt");
+            AppError::BadRequest(
+                Message::new("INVALID_COMPRESSED_BODY")
+                    .arg("encoding", encoding.as_str())
+                    .arg("error", e),
+            )
+        }
+    })?;
+    metrics::counter_with_labels(
+        "request_bodies_decompressed_total",
+        &[("encoding", encoding.as_str())],
+    )
+    .inc();
+
+    parts.headers.remove(header::CONTENT_ENCODING);
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(decoded.len()));
+    Ok(Request::from_parts(parts, Body::from(decoded)))
+}
+
+/// 记录一次被拒绝的压缩请求体。
+fn reject(reason: &str) {
+    metrics::counter_with_labels(
+        "request_decompression_rejected_total",
+        &[("reason", reason)],
+    )
+    .inc();
+}
+
+/// 解压请求体，输出最多 `min(limit, 压缩前大小 × max_ratio)` 字节。
+fn decode(
+    encoding: Encoding,
+    compressed: &[u8],
+    settings: RequestBodySettings,
+) -> Result<Vec<u8>, DecodeError> {
+    let ratio_limit = compressed.len().saturating_mul(settings.max_ratio);
+    let cap = settings.limit.min(ratio_limit);
+    let reader: Box<dyn Read + '_> = match encoding {
+        Encoding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(compressed)),
+        Encoding::Zstd => {
+            Box::new(zstd::stream::read::Decoder::new(compressed).map_err(DecodeError::Corrupt)?)
+        }
+    };
+    let mut decoded = Vec::new();
+    // 多读一个字节以判断是否超出上限
+    reader
+        .take(cap as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(DecodeError::Corrupt)?;
+    if decoded.len() > cap {
+        return Err(if cap < settings.limit {
+            DecodeError::RatioExceeded
+        } else {
+            DecodeError::TooLarge
+        });
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// 测试 gzip 与 zstd 的解压，以及大小上限与压缩比的限制。
+    #[test]
+    fn test_decode() {
+        let settings = RequestBodySettings {
+            limit: 1024,
+            max_ratio: 10,
+        };
+        let json = br#"{"priority": 1, "payload": {"type": "report"}}"#;
+        assert_eq!(
+            decode(Encoding::Gzip, &gzip(json), settings).unwrap(),
+            json.to_vec()
+        );
+        let zstd = zstd::encode_all(&json[..], 3).unwrap();
+        assert_eq!(
+            decode(Encoding::Zstd, &zstd, settings).unwrap(),
+            json.to_vec()
+        );
+
+        // 高度重复的数据压缩比很高
+        let zeros = vec![b'0'; 4096];
+        assert!(matches!(
+            decode(Encoding::Gzip, &gzip(&zeros), settings),
+            Err(DecodeError::RatioExceeded)
+        ));
+        let relaxed = RequestBodySettings {
+            limit: 1024,
+            max_ratio: 10_000,
+        };
+        assert!(matches!(
+            decode(Encoding::Gzip, &gzip(&zeros), relaxed),
+            Err(DecodeError::TooLarge)
+        ));
+        assert!(matches!(
+            decode(Encoding::Gzip, b"not gzip", settings),
+            Err(DecodeError::Corrupt(_))
+        ));
+    }
+
+    /// 测试 `Content-Encoding` 的解析。
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!(Encoding::parse("gzip"), Ok(Some(Encoding::Gzip)));
+        assert_eq!(Encoding::parse("X-GZIP"), Ok(Some(Encoding::Gzip)));
+        assert_eq!(Encoding::parse("zstd"), Ok(Some(Encoding::Zstd)));
+        assert_eq!(Encoding::parse("identity"), Ok(None));
+        assert_eq!(Encoding::parse("br"), Err("br".to_string()));
+        assert_eq!(Encoding::parse("gzip, zstd"), Err("gzip, zstd".to_string()));
+    }
+}
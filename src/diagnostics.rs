@@ -0,0 +1,123 @@
+use crate::web::AppState;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// 保留的最近错误条数。
+const MAX_RECENT_ERRORS: usize = 50;
+
+/// 最近发生的错误，供诊断快照使用。
+static RECENT_ERRORS: Mutex<VecDeque<(DateTime<Utc>, String)>> = Mutex::new(VecDeque::new());
+
+/// 记录一条错误，超过 `MAX_RECENT_ERRORS` 条时丢弃最早的记录。
+pub fn record_error(message: impl Into<String>) {
+    let mut errors = RECENT_ERRORS.lock().unwrap_or_else(|e| e.into_inner());
+    if errors.len() == MAX_RECENT_ERRORS {
+        errors.pop_front();
+    }
+    errors.push_back((Utc::now(), message.into()));
+}
+
+fn recent_errors() -> Vec<Value> {
+    RECENT_ERRORS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(at, message)| json!({ "at": at, "message": message }))
+        .collect()
+}
+
+/// 收集一份诊断快照：队列、正在处理的任务、连接池、后台任务、Tokio 运行时与最近的错误。
+///
+/// 只读取进程内的状态，不访问数据库，管理 API 或数据库无响应时也能完成。
+pub async fn snapshot(state: &AppState) -> Value {
+    let runtime = tokio::runtime::Handle::current().metrics();
+    json!({
+        "at": Utc::now(),
+        "pid": std::process::id(),
+        "queue": { "pending": state.queue.len().await },
+        "in_flight": state.lifecycle.in_flight(),
+        "draining": state.lifecycle.is_draining(),
+        "db": state.db.describe(),
+        "scheduler": { "heartbeat_age_ms": state.heartbeat.age().as_millis() as u64 },
+        "background_tasks": state.supervisor.status(),
+        "tokio": {
+            "workers": runtime.num_workers(),
+            "alive_tasks": runtime.num_alive_tasks(),
+            "global_queue_depth": runtime.global_queue_depth(),
+        },
+        "recent_errors": recent_errors(),
+    })
+}
+
+/// 将快照写入 `directory` 下带时间戳的文件，返回文件路径。
+fn write_snapshot(directory: &Path, snapshot: &Value) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(directory)?;
+    let path = directory.join(format!(
+        "diagnostics-{}.json",
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    ));
+    std::fs::write(&path, serde_json::to_vec_pretty(snapshot)?)?;
+    Ok(path)
+}
+
+/// 监听 `SIGUSR1`，每收到一次就把诊断快照写入日志和 `directory` 下的文件。
+///
+/// 用于 HTTP 管理接口本身无响应时排查问题：`kill -USR1 <pid>`。
+/// 非 Unix 平台上不做任何事。
+pub async fn run_diagnostics_signal(state: AppState, directory: PathBuf) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut signals = match signal(SignalKind::user_defined1()) {
+            Ok(signals) => signals,
+            Err(e) => {
+                tracing::error!("无法监听 SIGUSR1，诊断快照不可用: {}", e);
+                return;
+            }
+        };
+        while signals.recv().await.is_some() {
+            let snapshot = snapshot(&state).await;
+            tracing::warn!(diagnostics = %snapshot, "收到 SIGUSR1，输出诊断快照");
+            // 写文件是阻塞操作，放到阻塞线程池中执行
+            let directory = directory.clone();
+            match tokio::task::spawn_blocking(move || write_snapshot(&directory, &snapshot)).await {
+                Ok(Ok(path)) => tracing::info!(path = %path.display(), "诊断快照已写入文件"),
+                Ok(Err(e)) => tracing::error!("写入诊断快照失败: {}", e),
+                Err(e) => tracing::error!("写入诊断快照的任务异常退出: {}", e),
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (state, directory);
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试最近错误只保留固定条数，快照文件写入指定目录。
+    #[test]
+    fn test_recent_errors_and_write() {
+        for i in 0..MAX_RECENT_ERRORS + 5 {
+            record_error(format!("error {}", i));
+        }
+        let errors = recent_errors();
+        assert_eq!(errors.len(), MAX_RECENT_ERRORS);
+        assert_eq!(
+            errors.last().unwrap()["message"],
+            format!("error {}", MAX_RECENT_ERRORS + 4)
+        );
+
+        let directory = std::env::temp_dir().join(format!("diag-{}", uuid::Uuid::new_v4()));
+        let path = write_snapshot(&directory, &json!({ "ok": true })).unwrap();
+        let written: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written["ok"], true);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use crate::db::Database;
+use crate::metrics;
+use crate::queue::Task;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::Error as SqlxError;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// 死信队列中的一个任务：重试次数用尽后最终失败，保存在 `dead_tasks` 表中。
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadTask {
+    pub id: Uuid,
+    /// 提交任务的租户。
+    pub tenant: String,
+    pub payload: Value,
+    pub priority: u8,
+    /// 进入死信队列时的重试次数。
+    pub retry_count: u8,
+    /// 最后一次失败的错误。
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+impl DeadTask {
+    /// 重新入队时使用的任务：保留 ID 与优先级，重试次数清零，立即可以执行。
+    pub fn to_task(&self) -> Task {
+        Task {
+            id: self.id,
+            payload: Arc::new(self.payload.clone()),
+            priority: self.priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: Some(self.tenant.clone()),
+        }
+    }
+}
+
+/// 将重试次数用尽的任务写入死信队列，并更新死信队列大小的指标。
+pub async fn bury(db: &Database, task: &Task, tenant: &str, error: &str) -> Result<(), SqlxError> {
+    let dead = DeadTask {
+        id: task.id,
+        tenant: tenant.to_string(),
+        payload: task.payload.as_ref().clone(),
+        priority: task.priority,
+        retry_count: task.retry_count,
+        error: error.to_string(),
+        failed_at: Utc::now(),
+    };
+    db.insert_dead_task(&dead).await?;
+    metrics::counter("dlq_tasks_total").inc();
+    refresh_size(db).await
+}
+
+/// 从死信队列取出一个任务（删除其记录），任务不存在或已被并发取出时返回 `None`。
+pub async fn take(db: &Database, id: &Uuid) -> Result<Option<DeadTask>, SqlxError> {
+    let dead = db.take_dead_task(id).await?;
+    if dead.is_some() {
+        metrics::counter("dlq_requeued_total").inc();
+        refresh_size(db).await?;
+    }
+    Ok(dead)
+}
+
+/// 按数据库中的记录数更新 `dlq_size` 指标；启动时调用一次，使指标包含之前进程写入的任务。
+pub async fn refresh_size(db: &Database) -> Result<(), SqlxError> {
+    let size = db.dead_task_count().await?;
+    metrics::gauge("dlq_size").set(size as f64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::filter::TaskFilter;
+    use serde_json::json;
+
+    /// 测试任务写入死信队列、列出与取出。
+    #[tokio::test]
+    async fn test_bury_and_take() {
+        let db = crate::db::test_database().await;
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "type": "report" }).into(),
+            priority: 80,
+            retry_count: 3,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        bury(&db, &task, "acme", "下游超时").await.unwrap();
+
+        let dead = db.dead_tasks(&TaskFilter::new(), 10).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].id, task.id);
+        assert_eq!(dead[0].tenant, "acme");
+        assert_eq!(dead[0].payload, json!({ "type": "report" }));
+        assert_eq!(dead[0].retry_count, 3);
+        assert_eq!(dead[0].error, "下游超时");
+        assert_eq!(db.dead_task_count().await.unwrap(), 1);
+        // 过滤条件在数据库中执行，时间范围作用于失败时间
+        let failed_at = dead[0].failed_at;
+        let matching = TaskFilter::new()
+            .tenant("acme")
+            .min_priority(80)
+            .from(failed_at - chrono::Duration::seconds(1))
+            .to(failed_at + chrono::Duration::seconds(1));
+        assert_eq!(db.dead_tasks(&matching, 10).await.unwrap().len(), 1);
+        for filter in [
+            TaskFilter::new().tenant("globex"),
+            TaskFilter::new().max_priority(79),
+            TaskFilter::new().from(failed_at + chrono::Duration::seconds(1)),
+        ] {
+            assert!(db.dead_tasks(&filter, 10).await.unwrap().is_empty());
+        }
+
+        let taken = take(&db, &task.id).await.unwrap().unwrap();
+        let requeued = taken.to_task();
+        assert_eq!(requeued.id, task.id);
+        assert_eq!(requeued.priority, 80);
+        assert_eq!(requeued.retry_count, 0);
+        // 已被取出的任务不能再次取出
+        assert!(take(&db, &task.id).await.unwrap().is_none());
+        assert_eq!(db.dead_task_count().await.unwrap(), 0);
+    }
+}
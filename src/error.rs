@@ -1,5 +1,8 @@
+use crate::i18n::{self, Message};
+use crate::queue::QueueError;
+use crate::validation::FieldError;
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -11,16 +14,66 @@ use thiserror::Error;
 /// 使用 `thiserror` 宏可以方便地为枚举的每个变体实现 `std::error::Error` trait。
 /// - `#[error(...)]`: 定义了 `Display` trait 的实现，用于生成错误的文本描述。
 /// - `#[from]`: 实现了 `From` trait，允许将源错误类型自动转换为 `AppError`。
+///
+/// 返回给客户端的错误都带有稳定的错误码，消息按请求的 `Accept-Language` 从 `locales/` 中的模板渲染。
 #[derive(Error, Debug)]
 pub enum AppError {
     /// 表示数据库操作相关的错误。
     #[error("数据库错误: {0}")]
     Database(#[from] sqlx::Error),
 
+    /// 表示数据库迁移相关的错误。
+    #[error("数据库迁移错误: {0}")]
+    Migration(#[from] sqlx::migrate::MigrateError),
+
     /// 表示应用配置相关的错误。
     #[error("配置错误: {0}")]
     Config(String),
 
+    /// 表示请求未通过身份验证或无权执行该操作。
+    #[error("未授权: {0}")]
+    Unauthorized(Message),
+
+    /// 表示请求已通过身份验证，但凭据的权限范围不允许该操作。
+    #[error("权限不足: {0}")]
+    Forbidden(Message),
+
+    /// 表示请求格式不合法（无法解析的请求头、请求体或参数）。
+    #[error("请求参数错误: {0}")]
+    BadRequest(Message),
+
+    /// 表示请求格式正确，但内容未通过业务校验（字段超出范围、缺少必需的值等）。
+    #[error("请求校验失败: {0}")]
+    Validation(Message),
+
+    /// 表示请求中的一个或多个字段未通过校验，响应体的 `details` 逐一列出出错的字段。
+    #[error("请求校验失败: {} 个字段有误", .0.len())]
+    InvalidFields(Vec<FieldError>),
+
+    /// 表示请求的资源不存在。
+    #[error("资源不存在: {0}")]
+    NotFound(Message),
+
+    /// 表示任务载荷超过了所属优先级档位或任务类型的大小上限。
+    #[error("任务载荷过大: {0}")]
+    PayloadTooLarge(Message),
+
+    /// 表示请求体使用了不支持的编码。
+    #[error("不支持的请求体编码: {0}")]
+    UnsupportedMediaType(Message),
+
+    /// 表示客户端的请求速度超过了限流配置，附带建议的重试等待时间。
+    #[error("请求过于频繁: {0}")]
+    RateLimited(Message, std::time::Duration),
+
+    /// 表示服务依赖的外部组件（例如策略服务）暂时不可用，调用方可以稍后重试。
+    #[error("服务暂时不可用: {0}")]
+    Unavailable(Message),
+
+    /// 表示队列操作失败，是否可以重试由 `QueueError::is_retryable` 决定。
+    #[error("队列错误: {0}")]
+    Queue(#[from] QueueError),
+
     /// 表示其他所有未被明确分类的内部服务器错误。
     #[error("内部服务器错误: {0}")]
     Internal(#[from] anyhow::Error),
@@ -32,37 +85,149 @@ pub enum AppError {
 /// axum 会调用这个 `into_response` 方法将 `AppError` 转换为一个 HTTP 响应。
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        // 过载时通过 `Retry-After` 告诉客户端何时重试
+        let mut retry_after = None;
+        // 队列错误告诉客户端是否值得重试
+        let mut retryable = None;
+        // 字段校验错误逐一列出出错的字段
+        let mut details = None;
         // 根据错误类型匹配，决定返回的 HTTP 状态码和错误信息
-        let (status, error_message) = match self {
+        let (status, message) = match self {
+            AppError::Database(e) if crate::db::circuit_open(&e).is_some() => {
+                // 熔断期间不记录错误日志，避免数据库宕机时刷屏
+                let retry_in = crate::db::circuit_open(&e)
+                    .map(|open| open.retry_in)
+                    .unwrap_or_default();
+                retry_after = Some(std::time::Duration::from_secs(
+                    retry_in.as_secs() + u64::from(retry_in.subsec_nanos() > 0),
+                ));
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Message::new("DB_UNAVAILABLE"),
+                )
+            }
+            AppError::Database(e) if crate::db::is_timeout(&e) => {
+                tracing::warn!("数据库查询超时: {}", e);
+                // 超时通常是暂时性的，调用方可以稍后重试
+                (StatusCode::GATEWAY_TIMEOUT, Message::new("DB_TIMEOUT"))
+            }
             AppError::Database(e) => {
                 // 对于数据库错误，记录详细的错误日志
                 tracing::error!("数据库错误: {}", e);
+                crate::diagnostics::record_error(format!("数据库错误: {}", e));
                 // 但为了安全，向客户端返回一个通用的错误信息
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    "数据库错误".to_string(),
+                    Message::new("DATABASE_ERROR"),
+                )
+            }
+            AppError::Migration(e) => {
+                tracing::error!("数据库迁移错误: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Message::new("MIGRATION_ERROR"),
                 )
             }
             AppError::Config(e) => {
                 tracing::error!("配置错误: {}", e);
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    "配置错误".to_string(),
+                    Message::new("CONFIG_ERROR"),
                 )
             }
+            AppError::Unauthorized(e) => {
+                tracing::warn!("未授权的请求: {}", e);
+                // 未授权的原因可以直接返回给调用方，便于排查配置问题
+                (StatusCode::UNAUTHORIZED, e)
+            }
+            AppError::Forbidden(e) => {
+                tracing::warn!("权限不足的请求: {}", e);
+                (StatusCode::FORBIDDEN, e)
+            }
+            AppError::BadRequest(e) => (StatusCode::BAD_REQUEST, e),
+            AppError::Validation(e) => (StatusCode::UNPROCESSABLE_ENTITY, e),
+            AppError::InvalidFields(errors) => {
+                let message = Message::new("INVALID_FIELDS").arg("count", errors.len());
+                details = Some(errors);
+                (StatusCode::UNPROCESSABLE_ENTITY, message)
+            }
+            AppError::NotFound(e) => (StatusCode::NOT_FOUND, e),
+            AppError::PayloadTooLarge(e) => (StatusCode::PAYLOAD_TOO_LARGE, e),
+            AppError::UnsupportedMediaType(e) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, e),
+            AppError::RateLimited(e, after) => {
+                // `Retry-After` 只能是整数秒，向上取整避免客户端过早重试
+                retry_after = Some(std::time::Duration::from_secs(
+                    after.as_secs() + u64::from(after.subsec_nanos() > 0),
+                ));
+                (StatusCode::TOO_MANY_REQUESTS, e)
+            }
+            AppError::Unavailable(e) => (StatusCode::SERVICE_UNAVAILABLE, e),
+            AppError::Queue(e) => {
+                let status = match &e {
+                    QueueError::Full { .. } | QueueError::BandFull { .. } => {
+                        StatusCode::TOO_MANY_REQUESTS
+                    }
+                    QueueError::Closed => StatusCode::SERVICE_UNAVAILABLE,
+                    QueueError::DuplicateKey(_) => StatusCode::CONFLICT,
+                    QueueError::NotFound(_) => StatusCode::NOT_FOUND,
+                    QueueError::Serialization(_) => {
+                        tracing::error!("队列错误: {}", e);
+                        crate::diagnostics::record_error(format!("队列错误: {}", e));
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    }
+                    QueueError::Storage(_) => {
+                        tracing::error!("队列错误: {}", e);
+                        crate::diagnostics::record_error(format!("队列错误: {}", e));
+                        StatusCode::SERVICE_UNAVAILABLE
+                    }
+                };
+                retryable = Some(e.is_retryable());
+                retry_after = e.retry_after();
+                (status, e.message())
+            }
             AppError::Internal(e) => {
                 tracing::error!("内部服务器错误: {}", e);
+                crate::diagnostics::record_error(format!("内部服务器错误: {}", e));
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    "内部服务器错误".to_string(),
+                    Message::new("INTERNAL_ERROR"),
                 )
             }
         };
 
-        // 将错误信息包装在 JSON 对象中作为响应体
-        let body = Json(json!({ "error": error_message }));
+        // 将错误信息和错误码包装在 JSON 对象中作为响应体，消息使用请求协商出的语言
+        let locale = i18n::current_locale();
+        let mut body = json!({
+            "error": message.render(locale),
+            "code": message.code(),
+        });
+        if let Some(details) = details {
+            body["details"] = details
+                .iter()
+                .map(|e| {
+                    json!({
+                        "field": e.field,
+                        "code": e.message.code(),
+                        "error": e.message.render(locale),
+                    })
+                })
+                .collect();
+        }
+        // 回显请求 ID，便于调用方反馈问题时与服务端日志对应
+        if let Some(request_id) = crate::web::current_request_id() {
+            body["request_id"] = request_id.into();
+        }
+        if let Some(retryable) = retryable {
+            body["retryable"] = retryable.into();
+        }
 
         // 构建并返回最终的 HTTP 响应
-        (status, body).into_response()
+        let mut response = (status, Json(body)).into_response();
+        if let Some(after) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(after.as_secs()));
+        }
+        response
     }
 }
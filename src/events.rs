@@ -0,0 +1,231 @@
+use crate::db::Database;
+use crate::metrics;
+use crate::status::{TaskRecord, TaskState};
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// 每次从历史表回放的最大事件数。
+const REPLAY_BATCH: i64 = 500;
+/// 广播通道的容量，订阅者落后超过该数量时改为从历史表补齐。
+const BROADCAST_CAPACITY: usize = 1024;
+
+/// 一条已写入历史表的任务事件。
+///
+/// `id` 由数据库分配、单调递增，同时作为 SSE 的事件 ID，
+/// 客户端重连时通过 `Last-Event-ID` 带回，服务端据此回放错过的事件。
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskEvent {
+    pub id: i64,
+    pub task_id: Uuid,
+    pub status: TaskState,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 一条尚未写入历史表的任务事件。
+#[derive(Debug, Clone)]
+pub struct NewTaskEvent {
+    pub task_id: Uuid,
+    pub status: TaskState,
+    pub created_at: DateTime<Utc>,
+    /// 状态变化后的完整记录，写入 `task_status` 表。
+    pub record: TaskRecord,
+}
+
+/// 任务事件的广播通道，每个事件流连接持有一个订阅者。
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<TaskEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
+        Self { sender }
+    }
+
+    /// 订阅之后发布的事件。
+    pub fn subscribe(&self) -> broadcast::Receiver<TaskEvent> {
+        self.sender.subscribe()
+    }
+
+    /// 广播一条事件；没有订阅者时直接丢弃。
+    fn publish(&self, event: TaskEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+/// 运行事件记录器：将状态变化事件依次写入 `task_events` 表，再广播给事件流的订阅者，
+/// 并用事件携带的记录更新 `task_status` 表。
+///
+/// 只有这一个写入者，保证广播顺序与数据库分配的 ID 顺序一致，`task_status` 也不会被旧状态覆盖。
+/// 接收端放在 `Mutex` 中，以便监督者重启记录器后继续消费同一个通道。
+pub async fn run_event_recorder(
+    db: Database,
+    receiver: Arc<Mutex<UnboundedReceiver<NewTaskEvent>>>,
+    bus: EventBus,
+) {
+    let mut receiver = receiver.lock().await;
+    while let Some(event) = receiver.recv().await {
+        if let Err(e) = db.upsert_task_status(&event.record).await {
+            metrics::counter("task_status_writes_failed_total").inc();
+            tracing::warn!(task_id = %event.task_id, "写入任务状态失败: {}", e);
+        }
+        match db.insert_task_event(&event).await {
+            Ok(id) => bus.publish(TaskEvent {
+                id,
+                task_id: event.task_id,
+                status: event.status,
+                created_at: event.created_at,
+            }),
+            Err(e) => {
+                metrics::counter("task_events_dropped_total").inc();
+                tracing::warn!(task_id = %event.task_id, "写入任务事件失败: {}", e);
+            }
+        }
+    }
+}
+
+/// 事件流的内部状态。
+struct ReplayState {
+    db: Database,
+    receiver: broadcast::Receiver<TaskEvent>,
+    last_id: i64,
+    backlog: VecDeque<TaskEvent>,
+    /// 是否还需要从历史表读取（首次回放或订阅者落后时）。
+    catching_up: bool,
+}
+
+/// 创建一个任务事件流。
+///
+/// `last_event_id` 为客户端上次收到的事件 ID：先从历史表回放之后的所有事件，再切换到实时广播；
+/// 为 `None` 时只推送实时事件。订阅在回放之前建立，回放与实时事件之间不会有遗漏，
+/// 重叠部分按事件 ID 去重。
+pub fn task_event_stream(
+    db: Database,
+    bus: &EventBus,
+    last_event_id: Option<i64>,
+) -> impl Stream<Item = TaskEvent> {
+    let state = ReplayState {
+        db,
+        receiver: bus.subscribe(),
+        last_id: last_event_id.unwrap_or(i64::MIN),
+        backlog: VecDeque::new(),
+        catching_up: last_event_id.is_some(),
+    };
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.backlog.pop_front() {
+                if event.id > state.last_id {
+                    state.last_id = event.id;
+                    return Some((event, state));
+                }
+                continue;
+            }
+            if state.catching_up {
+                match state
+                    .db
+                    .task_events_after(state.last_id.max(0), REPLAY_BATCH)
+                    .await
+                {
+                    Ok(events) => {
+                        state.catching_up = events.len() as i64 == REPLAY_BATCH;
+                        state.backlog.extend(events);
+                    }
+                    Err(e) => {
+                        tracing::warn!("回放任务事件失败: {}", e);
+                        state.catching_up = false;
+                    }
+                }
+                continue;
+            }
+            match state.receiver.recv().await {
+                Ok(event) => {
+                    if event.id > state.last_id {
+                        state.last_id = event.id;
+                        return Some((event, state));
+                    }
+                }
+                // 落后太多时广播中的旧事件已被覆盖，从历史表补齐
+                Err(RecvError::Lagged(_)) => state.catching_up = true,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    /// 测试重连时先回放历史事件，再接续实时事件，且不会重复。
+    #[tokio::test]
+    async fn test_replay_then_live() {
+        let db = crate::db::test_database().await;
+        let bus = EventBus::new();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let recorder = tokio::spawn(run_event_recorder(
+            db.clone(),
+            Arc::new(Mutex::new(rx)),
+            bus.clone(),
+        ));
+
+        let task_id = Uuid::new_v4();
+        let send = |status| {
+            let now = Utc::now();
+            tx.send(NewTaskEvent {
+                task_id,
+                status,
+                created_at: now,
+                record: TaskRecord {
+                    id: task_id,
+                    tenant: "default".to_string(),
+                    status,
+                    priority: 10,
+                    retry_count: 0,
+                    created_at: now,
+                    updated_at: now,
+                    last_error: None,
+                    version: 1,
+                },
+            })
+            .unwrap()
+        };
+        send(TaskState::Queued);
+        send(TaskState::Running);
+        // 等待记录器写入前两条事件
+        while db.task_events_after(0, 10).await.unwrap().len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        let first = db.task_events_after(0, 1).await.unwrap()[0].id;
+
+        // 客户端上次收到第一条事件后断线
+        let mut stream = Box::pin(task_event_stream(db.clone(), &bus, Some(first)));
+        send(TaskState::Succeeded);
+
+        let replayed = stream.next().await.unwrap();
+        assert_eq!(replayed.status, TaskState::Running);
+        let live = stream.next().await.unwrap();
+        assert_eq!(live.status, TaskState::Succeeded);
+        assert!(live.id > replayed.id);
+
+        // 记录器随事件更新任务的最新状态
+        let record = db.task_status(&task_id).await.unwrap().unwrap();
+        assert_eq!(record.status, TaskState::Succeeded);
+
+        recorder.abort();
+    }
+}
@@ -0,0 +1,249 @@
+use crate::queue::{CompressionSettings, PriorityQueue, Task};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// 合成负载的描述。相同的描述（包括 `seed`）总是生成完全相同的任务序列。
+#[derive(Debug, Clone)]
+pub struct WorkloadSpec {
+    /// 随机数种子。
+    pub seed: u64,
+    /// 生成的任务数量。
+    pub count: usize,
+    /// Low/Normal/Critical 三个档位的权重。
+    pub priority_weights: [u32; 3],
+    /// 载荷大小（字节）的范围，闭区间。
+    pub payload_bytes: (usize, usize),
+    /// 注入失败的任务比例，0.0–1.0。
+    pub failure_rate: f64,
+}
+
+impl Default for WorkloadSpec {
+    fn default() -> Self {
+        Self {
+            seed: 42,
+            count: 10_000,
+            priority_weights: [70, 25, 5],
+            payload_bytes: (64, 4096),
+            failure_rate: 0.0,
+        }
+    }
+}
+
+/// 一个合成任务。
+#[derive(Debug, Clone)]
+pub struct SyntheticTask {
+    pub task: Task,
+    /// 是否应当让该任务处理失败（载荷中同时带有 `"inject_failure": true`）。
+    pub should_fail: bool,
+}
+
+/// SplitMix64 伪随机数生成器。
+///
+/// 算法固定、实现只有几行，保证同一个种子在任何版本、任何平台上生成相同的序列，
+/// 不受第三方随机数库升级的影响。
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// `[low, high]` 闭区间内的整数。
+    pub(crate) fn range(&mut self, low: u64, high: u64) -> u64 {
+        low + self.next_u64() % (high - low + 1)
+    }
+
+    /// `[0, 1)` 区间内的浮点数。
+    pub(crate) fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// 按描述逐个生成合成任务的迭代器。
+pub struct Workload {
+    spec: WorkloadSpec,
+    rng: SplitMix64,
+    generated: usize,
+}
+
+/// 根据描述创建合成负载。
+pub fn generate(spec: &WorkloadSpec) -> Workload {
+    Workload {
+        rng: SplitMix64::new(spec.seed),
+        spec: spec.clone(),
+        generated: 0,
+    }
+}
+
+/// 按 Low/Normal/Critical 三个档位的权重随机选择档位，再在档位的范围内随机选择优先级。
+pub(crate) fn pick_priority(rng: &mut SplitMix64, weights: [u32; 3]) -> u8 {
+    // 各档位对应的优先级范围，与 `PriorityClass::from_priority` 一致
+    const RANGES: [(u64, u64); 3] = [(0, 49), (50, 199), (200, 255)];
+    let total: u32 = weights.iter().sum();
+    let mut pick = rng.range(0, u64::from(total.max(1)) - 1);
+    for (weight, (low, high)) in weights.iter().zip(RANGES) {
+        if pick < u64::from(*weight) {
+            return rng.range(low, high) as u8;
+        }
+        pick -= u64::from(*weight);
+    }
+    rng.range(RANGES[1].0, RANGES[1].1) as u8
+}
+
+impl Iterator for Workload {
+    type Item = SyntheticTask;
+
+    fn next(&mut self) -> Option<SyntheticTask> {
+        if self.generated >= self.spec.count {
+            return None;
+        }
+        self.generated += 1;
+
+        let bytes = self.rng.next_u64().to_le_bytes();
+        let bytes = [bytes, self.rng.next_u64().to_le_bytes()].concat();
+        let id = uuid::Builder::from_random_bytes(bytes.try_into().expect("16 字节")).into_uuid();
+        let priority = pick_priority(&mut self.rng, self.spec.priority_weights);
+        let (min, max) = self.spec.payload_bytes;
+        let size = self.rng.range(min as u64, max.max(min) as u64) as usize;
+        let should_fail = self.rng.unit() < self.spec.failure_rate;
+
+        Some(SyntheticTask {
+            task: Task {
+                id,
+                payload: Arc::new(json!({
+                    "sequence": self.generated,
+                    "inject_failure": should_fail,
+                    "data": "x".repeat(size),
+                })),
+                priority,
+                retry_count: 0,
+                run_at: None,
+                context: Default::default(),
+                kind: Default::default(),
+                depends_on: Default::default(),
+                tenant: None,
+            },
+            should_fail,
+        })
+    }
+}
+
+/// 压测结果。
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub seed: u64,
+    pub tasks: usize,
+    /// 负载中被注入失败的任务数。
+    pub injected_failures: usize,
+    pub push_per_sec: f64,
+    pub pop_per_sec: f64,
+    /// 出队顺序是否满足优先级不递增。
+    pub ordered: bool,
+}
+
+/// 用合成负载压测优先级队列：先全部入队，再全部出队。
+pub async fn run_bench(spec: &WorkloadSpec, compression: CompressionSettings) -> BenchReport {
+    let workload: Vec<SyntheticTask> = generate(spec).collect();
+    let injected_failures = workload.iter().filter(|t| t.should_fail).count();
+    let tasks: Vec<Task> = workload.into_iter().map(|t| t.task).collect();
+    let queue = PriorityQueue::with_compression(compression);
+
+    let start = Instant::now();
+    for task in tasks.iter().cloned() {
+        queue.push(task).await.expect("合成任务的 ID 不会重复");
+    }
+    let push_elapsed = start.elapsed();
+
+    let start = Instant::now();
+    let mut last = u8::MAX;
+    let mut ordered = true;
+    while let Some(task) = queue.pop().await {
+        ordered &= task.priority <= last;
+        last = task.priority;
+    }
+    let pop_elapsed = start.elapsed();
+
+    let rate = |elapsed: std::time::Duration| tasks.len() as f64 / elapsed.as_secs_f64().max(1e-9);
+    BenchReport {
+        seed: spec.seed,
+        tasks: tasks.len(),
+        injected_failures,
+        push_per_sec: rate(push_elapsed),
+        pop_per_sec: rate(pop_elapsed),
+        ordered,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::PriorityClass;
+
+    /// 测试相同的种子生成完全相同的负载，不同的种子生成不同的负载。
+    #[test]
+    fn test_workload_is_reproducible() {
+        let spec = WorkloadSpec {
+            count: 100,
+            ..Default::default()
+        };
+        let a: Vec<_> = generate(&spec)
+            .map(|t| (t.task.id, t.task.priority))
+            .collect();
+        let b: Vec<_> = generate(&spec)
+            .map(|t| (t.task.id, t.task.priority))
+            .collect();
+        assert_eq!(a, b);
+
+        let other = WorkloadSpec { seed: 7, ..spec };
+        let c: Vec<_> = generate(&other)
+            .map(|t| (t.task.id, t.task.priority))
+            .collect();
+        assert_ne!(a, c);
+    }
+
+    /// 测试优先级分布与失败注入比例大致符合描述。
+    #[test]
+    fn test_workload_distribution() {
+        let spec = WorkloadSpec {
+            count: 10_000,
+            priority_weights: [0, 50, 50],
+            payload_bytes: (10, 20),
+            failure_rate: 0.1,
+            ..Default::default()
+        };
+        let tasks: Vec<_> = generate(&spec).collect();
+        assert_eq!(tasks.len(), 10_000);
+        assert!(tasks
+            .iter()
+            .all(|t| PriorityClass::from_priority(t.task.priority) != PriorityClass::Low));
+        let critical = tasks
+            .iter()
+            .filter(|t| PriorityClass::from_priority(t.task.priority) == PriorityClass::Critical)
+            .count();
+        assert!((4_000..6_000).contains(&critical));
+        let failing = tasks.iter().filter(|t| t.should_fail).count();
+        assert!((700..1_300).contains(&failing));
+    }
+
+    /// 测试压测按优先级顺序出队。
+    #[tokio::test]
+    async fn test_run_bench() {
+        let spec = WorkloadSpec {
+            count: 500,
+            ..Default::default()
+        };
+        let report = run_bench(&spec, CompressionSettings::default()).await;
+        assert_eq!(report.tasks, 500);
+        assert!(report.ordered);
+    }
+}
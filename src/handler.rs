@@ -0,0 +1,556 @@
+use crate::db::Database;
+use crate::metrics;
+use crate::queue::Task;
+use crate::results::ResultStream;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde_json::Value;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::Instrument;
+
+/// 处理器成功处理任务后的输出。
+pub enum TaskOutput {
+    /// 没有需要单独保存的结果，例如结果已经由处理器写入数据库。
+    Empty,
+    /// 流式结果：调度器边读边写入结果存储，不在内存中整体缓冲，之后通过 `GET /tasks/:id/result` 下载。
+    Stream {
+        content_type: String,
+        body: ResultStream,
+    },
+}
+
+impl TaskOutput {
+    /// 检查输出能否被保存与下载：流式结果的 `content_type` 必须是 `type/subtype` 形式（可以带参数）、
+    /// 能作为响应头返回的媒体类型，否则结果在 `GET /tasks/:id/result` 时无法返回。
+    pub fn validate(&self) -> Result<(), String> {
+        let TaskOutput::Stream { content_type, .. } = self else {
+            return Ok(());
+        };
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        let is_token = |s: &str| {
+            !s.is_empty()
+                && s.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&b))
+        };
+        let valid = essence
+            .split_once('/')
+            .is_some_and(|(kind, subtype)| is_token(kind) && is_token(subtype))
+            && axum::http::HeaderValue::from_str(content_type).is_ok();
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("无效的结果类型: {:?}", content_type))
+        }
+    }
+}
+
+impl fmt::Debug for TaskOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskOutput::Empty => f.write_str("Empty"),
+            TaskOutput::Stream { content_type, .. } => f
+                .debug_struct("Stream")
+                .field("content_type", content_type)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// 任务处理器：执行一个任务的业务逻辑。
+///
+/// 计时、panic 捕获、日志上下文等横切逻辑不写在处理器里，而是由 `HandlerMiddleware` 统一包装。
+pub trait TaskHandler: Send + Sync {
+    /// 处理器名称，用于指标标签和日志。
+    fn name(&self) -> &'static str;
+
+    /// 处理任务，返回错误时由调度器决定是否重试；返回流式结果时由调度器保存到结果存储。
+    fn run<'a>(
+        &'a self,
+        task: &'a Task,
+        db: &'a Database,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>>;
+
+    /// 处理器的版本，记录在失败任务的执行快照中，重放时据此提示处理器是否已经变化；默认为服务的版本号。
+    fn version(&self) -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    /// 任务类型的说明，在 `GET /task-types` 中展示。
+    fn description(&self) -> &'static str {
+        ""
+    }
+
+    /// 载荷的 JSON Schema，在 `GET /task-types` 中展示；`None` 表示没有约定。
+    fn payload_schema(&self) -> Option<Value> {
+        None
+    }
+
+    /// 提交任务时省略 `priority` 使用的默认优先级；`None` 表示必须显式指定。
+    fn default_priority(&self) -> Option<u8> {
+        None
+    }
+
+    /// 载荷示例，在 `GET /task-types` 中展示；默认按 `payload_schema` 生成。
+    fn example_payload(&self) -> Option<Value> {
+        self.payload_schema()
+            .map(|schema| example_from_schema(&schema))
+    }
+}
+
+/// 按 JSON Schema 生成一个示例值。
+///
+/// 依次使用 `const`、`examples` 的第一项、`default` 与 `enum` 的第一项；对象只填写必填字段，
+/// `oneOf`/`anyOf` 取第一个分支的必填字段；其余按 `type` 填写占位值。
+pub fn example_from_schema(schema: &Value) -> Value {
+    let given = schema
+        .get("const")
+        .or_else(|| schema.pointer("/examples/0"))
+        .or_else(|| schema.get("default"))
+        .or_else(|| schema.pointer("/enum/0"));
+    if let Some(value) = given {
+        return value.clone();
+    }
+    let schema_type = match schema.get("type") {
+        Some(Value::String(name)) => Some(name.as_str()),
+        // 多个类型时取第一个非 null 的类型
+        Some(Value::Array(names)) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|name| *name != "null"),
+        _ => None,
+    };
+    match schema_type {
+        Some("object") => {
+            let branch = schema
+                .pointer("/oneOf/0")
+                .or_else(|| schema.pointer("/anyOf/0"));
+            let required = [Some(schema), branch]
+                .into_iter()
+                .flatten()
+                .filter_map(|s| s.get("required").and_then(Value::as_array))
+                .flatten()
+                .filter_map(Value::as_str);
+            let mut example = serde_json::Map::new();
+            for name in required {
+                let property = schema
+                    .get("properties")
+                    .and_then(|properties| properties.get(name))
+                    .unwrap_or(&Value::Null);
+                example.insert(name.to_string(), example_from_schema(property));
+            }
+            Value::Object(example)
+        }
+        Some("array") => match schema.get("items") {
+            Some(items) => Value::Array(vec![example_from_schema(items)]),
+            None => Value::Array(Vec::new()),
+        },
+        Some("string") => Value::from("string"),
+        Some("integer") | Some("number") => Value::from(0),
+        Some("boolean") => Value::Bool(false),
+        _ => Value::Null,
+    }
+}
+
+/// 包装 `TaskHandler::run` 的中间件。
+///
+/// 实现通过 `next.run(task, db)` 调用链中的下一环，可以在调用前后插入逻辑或改写结果。
+pub trait HandlerMiddleware: Send + Sync {
+    fn call<'a>(
+        &'a self,
+        task: &'a Task,
+        db: &'a Database,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>>;
+}
+
+/// 中间件链中剩余的部分。
+pub struct Next<'a> {
+    handler: &'a dyn TaskHandler,
+    middleware: &'a [Arc<dyn HandlerMiddleware>],
+}
+
+impl<'a> Next<'a> {
+    /// 处理器名称，供中间件使用。
+    pub fn handler_name(&self) -> &'static str {
+        self.handler.name()
+    }
+
+    /// 调用下一个中间件；没有剩余中间件时调用处理器本身。
+    pub fn run(
+        self,
+        task: &'a Task,
+        db: &'a Database,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.call(
+                task,
+                db,
+                Next {
+                    handler: self.handler,
+                    middleware: rest,
+                },
+            ),
+            None => self.handler.run(task, db),
+        }
+    }
+}
+
+/// 一个处理器及包装它的中间件，靠前的中间件位于外层、先执行。
+#[derive(Clone)]
+pub struct HandlerChain {
+    handler: Arc<dyn TaskHandler>,
+    middleware: Arc<[Arc<dyn HandlerMiddleware>]>,
+}
+
+impl HandlerChain {
+    pub fn new(handler: Arc<dyn TaskHandler>, middleware: &[BuiltinMiddleware]) -> Self {
+        Self {
+            handler,
+            middleware: middleware.iter().map(|m| m.build()).collect(),
+        }
+    }
+
+    /// 链末端的处理器。
+    pub fn handler(&self) -> &dyn TaskHandler {
+        self.handler.as_ref()
+    }
+
+    /// 经过所有中间件处理任务。
+    pub async fn run(&self, task: &Task, db: &Database) -> anyhow::Result<TaskOutput> {
+        Next {
+            handler: self.handler.as_ref(),
+            middleware: &self.middleware,
+        }
+        .run(task, db)
+        .await
+    }
+}
+
+/// 为处理过程创建带有任务信息的 span，处理器内的日志都会带上这些字段。
+/// 提交时捕获的请求头（`PROPAGATE_HEADERS`）记录在 `headers` 字段中。
+struct TracingMiddleware;
+
+impl HandlerMiddleware for TracingMiddleware {
+    fn call<'a>(
+        &'a self,
+        task: &'a Task,
+        db: &'a Database,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+        let span = tracing::info_span!(
+            "task",
+            task_id = %task.id,
+            handler = next.handler_name(),
+            priority = task.priority,
+            retry_count = task.retry_count,
+            headers = tracing::field::Empty,
+        );
+        // 提交时捕获的请求头（例如追踪与关联 ID），便于把处理日志与上游请求对应起来
+        if !task.context.is_empty() {
+            span.record("headers", tracing::field::display(&task.context));
+        }
+        next.run(task, db).instrument(span).boxed()
+    }
+}
+
+/// 记录每个处理器的执行次数、失败次数与累计耗时。
+struct TimingMiddleware;
+
+impl HandlerMiddleware for TimingMiddleware {
+    fn call<'a>(
+        &'a self,
+        task: &'a Task,
+        db: &'a Database,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+        let handler = next.handler_name();
+        async move {
+            let start = Instant::now();
+            let result = next.run(task, db).await;
+            let labels = [("handler", handler)];
+            metrics::counter_with_labels("task_handler_runs_total", &labels).inc();
+            metrics::counter_with_labels("task_handler_duration_us_total", &labels)
+                .add(start.elapsed().as_micros() as u64);
+            if result.is_err() {
+                metrics::counter_with_labels("task_handler_failures_total", &labels).inc();
+            }
+            result
+        }
+        .boxed()
+    }
+}
+
+/// 将处理器中的 panic 转换为普通的处理失败，由调度器按失败任务重试，而不是让调度器崩溃重启。
+struct CatchPanicMiddleware;
+
+impl HandlerMiddleware for CatchPanicMiddleware {
+    fn call<'a>(
+        &'a self,
+        task: &'a Task,
+        db: &'a Database,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+        let handler = next.handler_name();
+        async move {
+            match AssertUnwindSafe(next.run(task, db)).catch_unwind().await {
+                Ok(result) => result,
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "未知的 panic".to_string());
+                    metrics::counter_with_labels(
+                        "task_handler_panics_total",
+                        &[("handler", handler)],
+                    )
+                    .inc();
+                    Err(anyhow::anyhow!("处理器 {} panic: {}", handler, message))
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+/// 检查处理器成功返回的输出（见 `TaskOutput::validate`），不合格的输出按处理失败处理，
+/// 不会写入结果存储，由调度器按失败任务重试。
+struct ValidateResultMiddleware;
+
+impl HandlerMiddleware for ValidateResultMiddleware {
+    fn call<'a>(
+        &'a self,
+        task: &'a Task,
+        db: &'a Database,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+        let handler = next.handler_name();
+        async move {
+            let output = next.run(task, db).await?;
+            if let Err(e) = output.validate() {
+                metrics::counter_with_labels(
+                    "task_handler_invalid_results_total",
+                    &[("handler", handler)],
+                )
+                .inc();
+                return Err(anyhow::anyhow!(
+                    "处理器 {} 返回的结果不合格: {}",
+                    handler,
+                    e
+                ));
+            }
+            Ok(output)
+        }
+        .boxed()
+    }
+}
+
+/// 内置的中间件，可以通过配置选择启用哪些以及它们的顺序。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinMiddleware {
+    Tracing,
+    Timing,
+    CatchPanic,
+    ValidateResult,
+}
+
+impl BuiltinMiddleware {
+    /// 默认的中间件链。
+    pub const DEFAULT: [BuiltinMiddleware; 4] = [
+        BuiltinMiddleware::Tracing,
+        BuiltinMiddleware::Timing,
+        BuiltinMiddleware::CatchPanic,
+        BuiltinMiddleware::ValidateResult,
+    ];
+
+    fn build(self) -> Arc<dyn HandlerMiddleware> {
+        match self {
+            BuiltinMiddleware::Tracing => Arc::new(TracingMiddleware),
+            BuiltinMiddleware::Timing => Arc::new(TimingMiddleware),
+            BuiltinMiddleware::CatchPanic => Arc::new(CatchPanicMiddleware),
+            BuiltinMiddleware::ValidateResult => Arc::new(ValidateResultMiddleware),
+        }
+    }
+}
+
+impl FromStr for BuiltinMiddleware {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "tracing" => Ok(BuiltinMiddleware::Tracing),
+            "timing" => Ok(BuiltinMiddleware::Timing),
+            "catch_panic" => Ok(BuiltinMiddleware::CatchPanic),
+            "validate_result" => Ok(BuiltinMiddleware::ValidateResult),
+            other => Err(format!(
+                "未知的处理器中间件: {}（可选 tracing/timing/catch_panic/validate_result）",
+                other
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryStore;
+    use futures::StreamExt;
+    use serde_json::json;
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    struct PanickingHandler;
+
+    impl TaskHandler for PanickingHandler {
+        fn name(&self) -> &'static str {
+            "panicking"
+        }
+
+        fn run<'a>(
+            &'a self,
+            _: &'a Task,
+            _: &'a Database,
+        ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+            async { panic!("boom") }.boxed()
+        }
+    }
+
+    /// 记录调用顺序的中间件。
+    struct Record(&'static str, Arc<Mutex<Vec<&'static str>>>);
+
+    impl HandlerMiddleware for Record {
+        fn call<'a>(
+            &'a self,
+            task: &'a Task,
+            db: &'a Database,
+            next: Next<'a>,
+        ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+            self.1.lock().unwrap().push(self.0);
+            next.run(task, db)
+        }
+    }
+
+    fn task() -> Task {
+        Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority: 10,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        }
+    }
+
+    /// 测试中间件按顺序执行，panic 被转换为处理失败。
+    #[tokio::test]
+    async fn test_chain_order_and_catch_panic() {
+        let db = Database::Memory(MemoryStore::new());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut chain = HandlerChain::new(
+            Arc::new(PanickingHandler),
+            &[BuiltinMiddleware::Timing, BuiltinMiddleware::CatchPanic],
+        );
+        chain.middleware = [
+            Arc::new(Record("outer", order.clone())) as Arc<dyn HandlerMiddleware>,
+            chain.middleware[0].clone(),
+            Arc::new(Record("inner", order.clone())),
+            chain.middleware[1].clone(),
+        ]
+        .into();
+
+        let error = chain.run(&task(), &db).await.unwrap_err();
+        assert!(error.to_string().contains("boom"));
+        assert_eq!(*order.lock().unwrap(), vec!["outer", "inner"]);
+    }
+
+    /// 返回指定结果类型的流式结果的处理器。
+    struct StreamingHandler(&'static str);
+
+    impl TaskHandler for StreamingHandler {
+        fn name(&self) -> &'static str {
+            "streaming"
+        }
+
+        fn run<'a>(
+            &'a self,
+            _: &'a Task,
+            _: &'a Database,
+        ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+            async move {
+                Ok(TaskOutput::Stream {
+                    content_type: self.0.to_string(),
+                    body: futures::stream::empty().boxed(),
+                })
+            }
+            .boxed()
+        }
+    }
+
+    /// 测试结果类型不合格的流式结果被转换为处理失败。
+    #[tokio::test]
+    async fn test_validate_result() {
+        let db = Database::Memory(MemoryStore::new());
+        for (content_type, valid) in [
+            ("text/csv", true),
+            ("application/json; charset=utf-8", true),
+            ("csv", false),
+            ("text/", false),
+            ("text/csv\n", false),
+        ] {
+            let chain = HandlerChain::new(
+                Arc::new(StreamingHandler(content_type)),
+                &[BuiltinMiddleware::ValidateResult],
+            );
+            let result = chain.run(&task(), &db).await;
+            assert_eq!(result.is_ok(), valid, "{}", content_type);
+        }
+    }
+
+    /// 测试按 JSON Schema 生成示例：必填字段、第一个 `oneOf` 分支、给定的示例值与按类型的占位值。
+    #[test]
+    fn test_example_from_schema() {
+        let schema = json!({
+            "type": "object",
+            "required": ["type", "count", "tags", "source"],
+            "properties": {
+                "type": { "const": "report" },
+                "count": { "type": "integer" },
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "source": { "type": ["null", "string"] },
+                "format": { "enum": ["csv", "json"] },
+                "window": { "type": "string", "examples": ["1h"] },
+                "limit": { "type": "integer", "default": 100 }
+            },
+            "oneOf": [{ "required": ["window", "format"] }, { "required": ["limit"] }]
+        });
+        assert_eq!(
+            example_from_schema(&schema),
+            json!({
+                "type": "report",
+                "count": 0,
+                "tags": ["string"],
+                "source": "string",
+                "window": "1h",
+                "format": "csv"
+            })
+        );
+        assert_eq!(example_from_schema(&json!({})), Value::Null);
+    }
+
+    /// 测试中间件名称的解析。
+    #[test]
+    fn test_parse_middleware() {
+        assert_eq!(
+            "catch_panic".parse::<BuiltinMiddleware>(),
+            Ok(BuiltinMiddleware::CatchPanic)
+        );
+        assert!("retry".parse::<BuiltinMiddleware>().is_err());
+    }
+}
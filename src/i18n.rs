@@ -0,0 +1,252 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// 各语言的消息模板，编译时嵌入二进制文件。
+///
+/// 模板以错误码为键，`{name}` 会被替换为同名参数。新增错误码时需要同时更新所有语言的文件。
+const CATALOG_SOURCES: [(Locale, &str); 2] = [
+    (Locale::Zh, include_str!("../locales/zh-CN.json")),
+    (Locale::En, include_str!("../locales/en.json")),
+];
+
+tokio::task_local! {
+    /// 当前请求协商出的语言，只在 `negotiate_locale` 包裹的请求中存在。
+    static LOCALE: Locale;
+}
+
+/// 错误消息支持的语言。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Locale {
+    /// 简体中文，未携带 `Accept-Language` 或没有匹配的语言时使用。
+    #[default]
+    Zh,
+    /// 英文。
+    En,
+}
+
+impl Locale {
+    /// 语言标签，用于 `Content-Language` 响应头。
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::Zh => "zh-CN",
+            Locale::En => "en",
+        }
+    }
+
+    /// 按主语言子标签匹配，例如 `en-US` 匹配英文、`zh-Hans-CN` 匹配中文。
+    fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split('-').next().unwrap_or_default();
+        if primary.eq_ignore_ascii_case("zh") {
+            Some(Locale::Zh)
+        } else if primary.eq_ignore_ascii_case("en") {
+            Some(Locale::En)
+        } else {
+            None
+        }
+    }
+
+    /// 根据 `Accept-Language` 的值选择语言：取权重（`q`）最高的受支持语言，权重相同时取靠前的。
+    ///
+    /// 没有受支持的语言（包括只有 `*`）时返回默认语言。
+    pub fn negotiate(accept_language: &str) -> Self {
+        let mut best: Option<(Locale, f32)> = None;
+        for entry in accept_language.split(',') {
+            let mut parts = entry.split(';').map(str::trim);
+            let Some(locale) = parts.next().and_then(Locale::from_tag) else {
+                continue;
+            };
+            let weight = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())
+                .unwrap_or(0.0);
+            if weight > 0.0 && best.is_none_or(|(_, w)| weight > w) {
+                best = Some((locale, weight));
+            }
+        }
+        best.map(|(locale, _)| locale).unwrap_or_default()
+    }
+}
+
+/// 当前请求的语言；不在请求中（例如后台任务）时返回默认语言。
+pub fn current_locale() -> Locale {
+    LOCALE.try_with(|locale| *locale).unwrap_or_default()
+}
+
+/// 根据请求的 `Accept-Language` 选择错误消息的语言，在处理请求期间生效。
+///
+/// 错误响应附带 `Content-Language`，成功响应不受影响。
+pub async fn negotiate_locale(request: Request, next: Next) -> Response {
+    let locale = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .map(Locale::negotiate)
+        .unwrap_or_default();
+    let mut response = LOCALE.scope(locale, next.run(request)).await;
+    if response.status().is_client_error() || response.status().is_server_error() {
+        response.headers_mut().insert(
+            header::CONTENT_LANGUAGE,
+            HeaderValue::from_static(locale.tag()),
+        );
+    }
+    response
+}
+
+/// 一条可本地化的消息：稳定的错误码加上填入模板的参数。
+///
+/// `Display` 使用默认语言渲染，日志中的消息保持中文。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    code: &'static str,
+    args: Vec<(&'static str, String)>,
+}
+
+impl Message {
+    pub fn new(code: &'static str) -> Self {
+        Self {
+            code,
+            args: Vec::new(),
+        }
+    }
+
+    /// 添加一个模板参数。
+    pub fn arg(mut self, name: &'static str, value: impl fmt::Display) -> Self {
+        self.args.push((name, value.to_string()));
+        self
+    }
+
+    /// 稳定的错误码，返回给客户端用于区分错误类型。
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// 按指定语言渲染消息；该语言缺少模板时退回默认语言，仍然没有时返回错误码本身。
+    pub fn render(&self, locale: Locale) -> String {
+        let catalogs = catalogs();
+        let Some(template) = catalogs[&locale]
+            .get(self.code)
+            .or_else(|| catalogs[&Locale::default()].get(self.code))
+        else {
+            return self.code.to_string();
+        };
+        self.args
+            .iter()
+            .fold(template.clone(), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), value)
+            })
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(Locale::default()))
+    }
+}
+
+/// 解析后的消息模板，首次使用时解析。
+fn catalogs() -> &'static HashMap<Locale, HashMap<String, String>> {
+    static CATALOGS: OnceLock<HashMap<Locale, HashMap<String, String>>> = OnceLock::new();
+    CATALOGS.get_or_init(|| {
+        CATALOG_SOURCES
+            .iter()
+            .map(|(locale, source)| {
+                let catalog = serde_json::from_str(source).unwrap_or_else(|e| {
+                    panic!("内置的 {} 消息模板不是合法的 JSON: {}", locale.tag(), e)
+                });
+                (*locale, catalog)
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试 `Accept-Language` 的协商，包括权重、地区子标签与不支持的语言。
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Locale::negotiate("en-US,en;q=0.9"), Locale::En);
+        assert_eq!(Locale::negotiate("zh-CN,zh;q=0.9,en;q=0.8"), Locale::Zh);
+        assert_eq!(Locale::negotiate("fr-FR, en;q=0.5"), Locale::En);
+        assert_eq!(Locale::negotiate("zh;q=0.3, EN;q=0.7"), Locale::En);
+        assert_eq!(Locale::negotiate("en;q=0"), Locale::Zh);
+        assert_eq!(Locale::negotiate("fr, *"), Locale::Zh);
+        assert_eq!(Locale::negotiate(""), Locale::Zh);
+    }
+
+    /// 测试模板渲染与参数替换，以及缺少模板时的退回。
+    #[test]
+    fn test_render() {
+        let message = Message::new("TASK_NOT_FOUND").arg("id", 42);
+        assert_eq!(message.render(Locale::Zh), "任务 42 不存在");
+        assert_eq!(message.render(Locale::En), "Task 42 does not exist");
+        assert_eq!(message.to_string(), "任务 42 不存在");
+        assert_eq!(
+            Message::new("NO_SUCH_CODE").render(Locale::En),
+            "NO_SUCH_CODE"
+        );
+    }
+
+    /// 测试错误响应按请求的语言渲染消息，错误码保持不变。
+    #[tokio::test]
+    async fn test_error_response_locale() {
+        use crate::error::AppError;
+        use axum::response::IntoResponse;
+
+        let respond = |locale| {
+            LOCALE.scope(locale, async {
+                let response = AppError::Queue(crate::queue::QueueError::Closed).into_response();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            })
+        };
+        let en = respond(Locale::En).await;
+        assert_eq!(en["code"], "QUEUE_CLOSED");
+        assert!(en["error"].as_str().unwrap().starts_with("Queue is closed"));
+        let zh = respond(Locale::Zh).await;
+        assert_eq!(zh["code"], "QUEUE_CLOSED");
+        assert!(zh["error"].as_str().unwrap().starts_with("队列已关闭"));
+    }
+
+    /// 测试所有语言的模板覆盖相同的错误码，且使用相同的参数。
+    #[test]
+    fn test_catalogs_consistent() {
+        let placeholders = |template: &str| {
+            let mut names: Vec<String> = template
+                .split('{')
+                .skip(1)
+                .filter_map(|rest| rest.split_once('}').map(|(name, _)| name.to_string()))
+                .collect();
+            names.sort();
+            names
+        };
+        let catalogs = catalogs();
+        let default = &catalogs[&Locale::default()];
+        for (locale, catalog) in catalogs {
+            let mut codes: Vec<_> = catalog.keys().collect();
+            let mut expected: Vec<_> = default.keys().collect();
+            codes.sort();
+            expected.sort();
+            assert_eq!(codes, expected, "{} 的错误码与默认语言不一致", locale.tag());
+            for (code, template) in catalog {
+                assert_eq!(
+                    placeholders(template),
+                    placeholders(&default[code]),
+                    "{} 的 {} 参数与默认语言不一致",
+                    locale.tag(),
+                    code
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use axum::http::{HeaderValue, Request};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower_http::request_id::{MakeRequestId, RequestId};
+use uuid::Uuid;
+
+/// Snowflake 的纪元（2024-01-01T00:00:00Z，Unix 毫秒）。41 位毫秒时间戳可以使用约 69 年。
+const SNOWFLAKE_EPOCH_MS: u64 = 1_704_067_200_000;
+/// Snowflake 中工作节点 ID 的位数，最多 1024 个节点。
+const WORKER_ID_BITS: u32 = 10;
+/// Snowflake 中序列号的位数，每个节点每毫秒最多 4096 个 ID。
+const SEQUENCE_BITS: u32 = 12;
+/// 工作节点 ID 的最大值。
+pub const MAX_WORKER_ID: u16 = (1 << WORKER_ID_BITS) - 1;
+
+/// 全局的 ID 生成器，由 `set_generator` 在启动时设置，未设置时使用 UUIDv4。
+static GENERATOR: OnceLock<IdGenerator> = OnceLock::new();
+
+/// 任务 ID 与请求 ID 的格式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdFormat {
+    /// 随机的 UUIDv4（默认）。
+    #[default]
+    UuidV4,
+    /// 以毫秒时间戳开头的 UUIDv7，按生成时间排序。
+    UuidV7,
+    /// Snowflake（时间戳 + 工作节点 ID + 序列号），以 UUIDv8 的形式表示，按生成时间排序。
+    Snowflake,
+}
+
+impl FromStr for IdFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "uuidv4" | "uuid" => Ok(IdFormat::UuidV4),
+            "uuidv7" => Ok(IdFormat::UuidV7),
+            "snowflake" => Ok(IdFormat::Snowflake),
+            other => Err(format!(
+                "未知的 ID 格式: {}（可选 uuidv4/uuidv7/snowflake）",
+                other
+            )),
+        }
+    }
+}
+
+/// ID 生成器。
+///
+/// 生成的 ID 始终是 `Uuid`，数据库中仍以 36 个字符的字符串保存，不需要修改列类型；
+/// UUIDv7 与 Snowflake 的字符串按字典序排列即为生成顺序，插入 B+ 树索引时总是追加在末尾。
+pub enum IdGenerator {
+    UuidV4,
+    UuidV7,
+    Snowflake(Snowflake),
+}
+
+impl IdGenerator {
+    /// 按格式创建生成器；`worker_id` 只对 Snowflake 有效，多个实例必须使用不同的值。
+    pub fn new(format: IdFormat, worker_id: u16) -> Self {
+        match format {
+            IdFormat::UuidV4 => IdGenerator::UuidV4,
+            IdFormat::UuidV7 => IdGenerator::UuidV7,
+            IdFormat::Snowflake => IdGenerator::Snowflake(Snowflake::new(worker_id)),
+        }
+    }
+
+    /// 生成一个新的 ID。
+    pub fn generate(&self) -> Uuid {
+        match self {
+            IdGenerator::UuidV4 => Uuid::new_v4(),
+            IdGenerator::UuidV7 => Uuid::now_v7(),
+            IdGenerator::Snowflake(snowflake) => snowflake_uuid(snowflake.next_id()),
+        }
+    }
+}
+
+/// 设置全局的 ID 生成器，只在启动时调用一次。
+pub fn set_generator(generator: IdGenerator) {
+    let _ = GENERATOR.set(generator);
+}
+
+/// 使用全局的 ID 生成器生成一个新的 ID。
+pub fn generate() -> Uuid {
+    GENERATOR.get_or_init(|| IdGenerator::UuidV4).generate()
+}
+
+/// 使用全局的 ID 生成器生成请求 ID（`x-request-id`）。
+#[derive(Clone, Copy, Default)]
+pub struct MakeRequestIdFromGenerator;
+
+impl MakeRequestId for MakeRequestIdFromGenerator {
+    fn make_request_id<B>(&mut self, _request: &Request<B>) -> Option<RequestId> {
+        HeaderValue::from_str(&generate().to_string())
+            .ok()
+            .map(RequestId::new)
+    }
+}
+
+/// Snowflake ID 生成器：41 位毫秒时间戳、10 位工作节点 ID、12 位序列号。
+///
+/// 同一毫秒内的序列号用完时等待下一毫秒；系统时钟回拨时沿用上一次的时间戳，保证 ID 单调递增。
+pub struct Snowflake {
+    worker_id: u64,
+    /// 上一次使用的时间戳（相对纪元的毫秒数）与序列号。
+    state: Mutex<(u64, u64)>,
+}
+
+impl Snowflake {
+    pub fn new(worker_id: u16) -> Self {
+        Self {
+            worker_id: u64::from(worker_id.min(MAX_WORKER_ID)),
+            state: Mutex::new((0, 0)),
+        }
+    }
+
+    /// 生成下一个 ID。
+    pub fn next_id(&self) -> u64 {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (last_ms, sequence) = *state;
+        let mut now = current_ms().max(last_ms);
+        let sequence = if now == last_ms {
+            let next = (sequence + 1) & ((1 << SEQUENCE_BITS) - 1);
+            if next == 0 {
+                // 本毫秒的序列号已用完，等待时钟前进
+                while now <= last_ms {
+                    std::hint::spin_loop();
+                    now = current_ms();
+                }
+            }
+            next
+        } else {
+            0
+        };
+        *state = (now, sequence);
+        (now << (WORKER_ID_BITS + SEQUENCE_BITS)) | (self.worker_id << SEQUENCE_BITS) | sequence
+    }
+}
+
+/// 相对 Snowflake 纪元的当前毫秒数。
+fn current_ms() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    now.saturating_sub(SNOWFLAKE_EPOCH_MS)
+}
+
+/// 将 Snowflake ID 编码为 UUIDv8：ID 的各位从高到低依次填入版本号与变体之外的位置，
+/// 因此 UUID 的字典序与 ID 的数值顺序一致。
+fn snowflake_uuid(id: u64) -> Uuid {
+    let mut bytes = [0u8; 16];
+    bytes[..6].copy_from_slice(&(id >> 16).to_be_bytes()[2..]);
+    bytes[6] = ((id >> 12) & 0x0F) as u8;
+    bytes[7] = ((id >> 4) & 0xFF) as u8;
+    bytes[8] = (id & 0x0F) as u8;
+    Uuid::new_v8(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试 ID 格式的解析。
+    #[test]
+    fn test_parse_format() {
+        assert_eq!("uuidv4".parse(), Ok(IdFormat::UuidV4));
+        assert_eq!("UUIDv7".parse(), Ok(IdFormat::UuidV7));
+        assert_eq!("snowflake".parse(), Ok(IdFormat::Snowflake));
+        assert!("ulid".parse::<IdFormat>().is_err());
+    }
+
+    /// 测试 UUIDv7 与 Snowflake 生成的 ID 唯一，且字符串的字典序与生成顺序一致。
+    #[test]
+    fn test_time_sortable() {
+        for format in [IdFormat::UuidV7, IdFormat::Snowflake] {
+            let generator = IdGenerator::new(format, 7);
+            let ids: Vec<String> = (0..10_000)
+                .map(|_| generator.generate().to_string())
+                .collect();
+            let mut sorted = ids.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(ids, sorted, "{:?} 生成的 ID 不是严格递增的", format);
+        }
+    }
+
+    /// 测试 Snowflake 的位布局与 UUIDv8 编码。
+    #[test]
+    fn test_snowflake_layout() {
+        let snowflake = Snowflake::new(5);
+        let id = snowflake.next_id();
+        assert_eq!((id >> SEQUENCE_BITS) & u64::from(MAX_WORKER_ID), 5);
+        let uuid = snowflake_uuid(id);
+        assert_eq!(uuid.get_version_num(), 8);
+        assert_eq!(uuid.get_variant(), uuid::Variant::RFC4122);
+        assert!(snowflake_uuid(id) < snowflake_uuid(id + 1));
+    }
+}
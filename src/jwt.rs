@@ -0,0 +1,272 @@
+use crate::error::AppError;
+use crate::i18n::Message;
+use crate::metrics;
+use crate::tokens::Scope;
+use crate::web::is_valid_tenant;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::fmt;
+use std::time::Duration;
+
+/// 校验 JWT 签名使用的密钥。
+#[derive(Clone)]
+pub enum JwtKey {
+    /// HS256 的共享密钥。
+    Hs256(String),
+    /// RS256 的公钥（PEM）。
+    Rs256(String),
+}
+
+impl fmt::Debug for JwtKey {
+    /// 不输出密钥内容，避免出现在日志与诊断信息中。
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtKey::Hs256(_) => f.write_str("Hs256(..)"),
+            JwtKey::Rs256(_) => f.write_str("Rs256(..)"),
+        }
+    }
+}
+
+/// JWT 校验的配置。
+#[derive(Debug, Clone)]
+pub struct JwtSettings {
+    pub key: JwtKey,
+    /// 要求的签发者（`iss`），`None` 表示不校验。
+    pub issuer: Option<String>,
+    /// 要求的受众（`aud`），`None` 表示不校验。
+    pub audience: Option<String>,
+    /// 校验 `exp` 与 `nbf` 时允许的时钟偏差。
+    pub leeway: Duration,
+}
+
+/// 服务使用的 JWT 声明，其余声明被忽略。
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    /// 调用方的标识。
+    #[serde(default)]
+    pub sub: Option<String>,
+    #[serde(default)]
+    pub iss: Option<String>,
+    /// 空格分隔的权限范围（OAuth 2.0 的 `scope` 声明），例如 `tasks:read tasks:write`。
+    #[serde(default)]
+    pub scope: String,
+    /// 调用方所属的租户，设置后请求只能以该租户的身份访问，见 `web::resolve_tenant`。
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+impl Claims {
+    /// 声明中的权限范围是否允许执行需要 `required` 的操作，不认识的权限范围被忽略。
+    pub fn allows(&self, required: Scope) -> bool {
+        self.scope
+            .split_whitespace()
+            .filter_map(scope_from_claim)
+            .any(|scope| scope.allows(required))
+    }
+}
+
+/// JWT 中权限范围的名称与令牌权限范围的对应关系。
+fn scope_from_claim(name: &str) -> Option<Scope> {
+    match name {
+        "tasks:write" => Some(Scope::Submit),
+        "tasks:read" => Some(Scope::Read),
+        "admin" => Some(Scope::Admin),
+        _ => None,
+    }
+}
+
+/// 权限范围在 JWT 中的名称。
+fn claim_name(scope: Scope) -> &'static str {
+    match scope {
+        Scope::Submit => "tasks:write",
+        Scope::Read => "tasks:read",
+        Scope::Admin => "admin",
+    }
+}
+
+/// JWT 的校验器：校验签名、`exp`、`nbf`，以及配置了的 `iss` 与 `aud`。
+///
+/// 只接受配置的密钥对应的一种算法，避免算法混淆攻击（例如用 RS256 公钥作为 HS256 密钥伪造签名）。
+pub struct JwtVerifier {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl JwtVerifier {
+    /// 根据配置创建校验器，RS256 公钥无法解析时返回错误。
+    pub fn new(settings: &JwtSettings) -> Result<Self, String> {
+        let (key, algorithm) = match &settings.key {
+            JwtKey::Hs256(secret) => (
+                DecodingKey::from_secret(secret.as_bytes()),
+                Algorithm::HS256,
+            ),
+            JwtKey::Rs256(pem) => (
+                DecodingKey::from_rsa_pem(pem.as_bytes())
+                    .map_err(|e| format!("无法解析 RS256 公钥: {}", e))?,
+                Algorithm::RS256,
+            ),
+        };
+        let mut validation = Validation::new(algorithm);
+        validation.leeway = settings.leeway.as_secs();
+        if let Some(issuer) = &settings.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &settings.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        Ok(Self { key, validation })
+    }
+
+    /// 校验 JWT，并确认其权限范围允许 `required`。
+    ///
+    /// 签名、过期时间、签发者或受众无效时返回 401，权限范围不足时返回 403。
+    pub fn verify(&self, token: &str, required: Scope) -> Result<Claims, AppError> {
+        let claims = match jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                let (reason, code) = match e.kind() {
+                    ErrorKind::ExpiredSignature => ("expired", "JWT_EXPIRED"),
+                    _ => ("invalid", "INVALID_JWT"),
+                };
+                tracing::debug!(reason, "JWT 校验失败: {}", e);
+                reject(reason);
+                return Err(AppError::Unauthorized(Message::new(code)));
+            }
+        };
+        if claims
+            .tenant
+            .as_deref()
+            .is_some_and(|t| !is_valid_tenant(t))
+        {
+            tracing::debug!(tenant = ?claims.tenant, "JWT 的 tenant 声明不是合法的租户名");
+            reject("tenant");
+            return Err(AppError::Unauthorized(Message::new("INVALID_JWT")));
+        }
+        if !claims.allows(required) {
+            reject("scope");
+            return Err(AppError::Forbidden(
+                Message::new("INSUFFICIENT_TOKEN_SCOPE")
+                    .arg("scope", &claims.scope)
+                    .arg("required", claim_name(required)),
+            ));
+        }
+        Ok(claims)
+    }
+}
+
+/// 记录一次被拒绝的 JWT 校验。
+fn reject(reason: &str) {
+    metrics::counter_with_labels("jwt_rejected_total", &[("reason", reason)]).inc();
+}
+
+/// 将 JWT 声明记录到当前请求的日志 span 中（`http_request` 的 `auth_*` 字段）。
+pub fn record_claims(claims: &Claims) {
+    let span = tracing::Span::current();
+    span.record("auth_sub", claims.sub.as_deref().unwrap_or_default());
+    span.record("auth_iss", claims.iss.as_deref().unwrap_or_default());
+    span.record("auth_scope", claims.scope.as_str());
+    span.record("auth_tenant", claims.tenant.as_deref().unwrap_or_default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    fn settings() -> JwtSettings {
+        JwtSettings {
+            key: JwtKey::Hs256("secret".to_string()),
+            issuer: Some("https://auth.example".to_string()),
+            audience: None,
+            leeway: Duration::ZERO,
+        }
+    }
+
+    fn sign(claims: serde_json::Value, secret: &str) -> String {
+        jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn exp(offset: i64) -> i64 {
+        chrono::Utc::now().timestamp() + offset
+    }
+
+    /// 测试签名、过期时间、签发者、权限范围与租户声明的校验。
+    #[test]
+    fn test_verify() {
+        let verifier = JwtVerifier::new(&settings()).unwrap();
+        let issuer = "https://auth.example";
+        let writer = sign(
+            json!({
+                "sub": "billing",
+                "iss": issuer,
+                "scope": "tasks:write",
+                "tenant": "acme",
+                "exp": exp(60),
+            }),
+            "secret",
+        );
+        let claims = verifier.verify(&writer, Scope::Submit).unwrap();
+        assert_eq!(claims.sub.as_deref(), Some("billing"));
+        assert_eq!(claims.tenant.as_deref(), Some("acme"));
+        assert!(matches!(
+            verifier.verify(&writer, Scope::Read),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            verifier.verify(&writer, Scope::Admin),
+            Err(AppError::Forbidden(_))
+        ));
+
+        let admin = sign(
+            json!({ "iss": issuer, "scope": "openid admin", "exp": exp(60) }),
+            "secret",
+        );
+        assert!(verifier.verify(&admin, Scope::Admin).is_ok());
+        assert!(verifier.verify(&admin, Scope::Submit).is_ok());
+
+        for invalid in [
+            sign(
+                json!({ "iss": issuer, "scope": "admin", "exp": exp(60) }),
+                "other",
+            ),
+            sign(
+                json!({ "iss": issuer, "scope": "admin", "exp": exp(-60) }),
+                "secret",
+            ),
+            sign(
+                json!({ "iss": "https://evil.example", "scope": "admin", "exp": exp(60) }),
+                "secret",
+            ),
+            sign(json!({ "iss": issuer, "scope": "admin" }), "secret"),
+            sign(
+                json!({ "iss": issuer, "scope": "admin", "tenant": "acme corp", "exp": exp(60) }),
+                "secret",
+            ),
+            "not-a-jwt".to_string(),
+        ] {
+            assert!(matches!(
+                verifier.verify(&invalid, Scope::Read),
+                Err(AppError::Unauthorized(_))
+            ));
+        }
+    }
+
+    /// 测试无法解析的 RS256 公钥在创建校验器时报错。
+    #[test]
+    fn test_invalid_rs256_key() {
+        let settings = JwtSettings {
+            key: JwtKey::Rs256("not a pem".to_string()),
+            ..settings()
+        };
+        assert!(JwtVerifier::new(&settings).is_err());
+        assert_eq!(format!("{:?}", settings.key), "Rs256(..)");
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+/// 通过管理 API 请求滚动重启后，进程排空完毕时的退出码（`EX_TEMPFAIL`）。
+///
+/// 编排系统可以据此区分“按计划重启”与崩溃。
+pub const RESTART_EXIT_CODE: i32 = 75;
+
+struct Inner {
+    shutdown: CancellationToken,
+    exit_code: AtomicI32,
+    in_flight: AtomicUsize,
+    idle: Notify,
+    paused: AtomicBool,
+}
+
+/// 进程的生命周期状态，在 HTTP handler、调度器与 `main` 之间共享。
+///
+/// 收到停机信号或重启请求后进入排空状态：不再接收新任务、调度器不再取出新任务，
+/// `main` 等待正在处理的任务全部完成后再停止后台任务并退出。
+#[derive(Clone)]
+pub struct Lifecycle {
+    inner: Arc<Inner>,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                shutdown: CancellationToken::new(),
+                exit_code: AtomicI32::new(0),
+                in_flight: AtomicUsize::new(0),
+                idle: Notify::new(),
+                paused: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// 请求普通停机。
+    pub fn request_shutdown(&self) {
+        self.inner.shutdown.cancel();
+    }
+
+    /// 请求滚动重启：排空后以 `RESTART_EXIT_CODE` 退出。
+    ///
+    /// 已经在停机过程中时返回 `false`，退出码保持不变。
+    pub fn request_restart(&self) -> bool {
+        if self.is_draining() {
+            return false;
+        }
+        self.inner
+            .exit_code
+            .store(RESTART_EXIT_CODE, Ordering::SeqCst);
+        self.inner.shutdown.cancel();
+        true
+    }
+
+    /// 是否已进入排空状态。
+    pub fn is_draining(&self) -> bool {
+        self.inner.shutdown.is_cancelled()
+    }
+
+    /// 停机令牌，收到停机或重启请求时被取消。
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.inner.shutdown.clone()
+    }
+
+    /// 等待停机或重启请求。
+    pub async fn shutdown_requested(&self) {
+        self.inner.shutdown.cancelled().await
+    }
+
+    /// 暂停分发：调度器不再取出新任务，任务留在队列中，正在处理的任务不受影响；已经暂停时返回 `false`。
+    pub fn pause(&self) -> bool {
+        !self.inner.paused.swap(true, Ordering::SeqCst)
+    }
+
+    /// 恢复分发；没有暂停时返回 `false`。
+    pub fn resume(&self) -> bool {
+        self.inner.paused.swap(false, Ordering::SeqCst)
+    }
+
+    /// 是否已通过 `pause` 暂停分发。
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(Ordering::SeqCst)
+    }
+
+    /// 进程退出时应使用的退出码。
+    pub fn exit_code(&self) -> i32 {
+        self.inner.exit_code.load(Ordering::SeqCst)
+    }
+
+    /// 标记一个任务开始处理，返回的守卫被释放时视为处理结束。
+    pub fn track(&self) -> InFlightGuard {
+        self.inner.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// 正在处理的任务数。
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// 等待所有正在处理的任务完成，超时返回 `false`。
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let notified = self.inner.idle.notified();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+}
+
+/// 正在处理的任务的守卫，见 `Lifecycle::track`。
+pub struct InFlightGuard {
+    inner: Arc<Inner>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.inner.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试重启请求设置退出码，并等待进行中的任务完成。
+    #[tokio::test]
+    async fn test_restart_waits_for_in_flight() {
+        let lifecycle = Lifecycle::new();
+        let guard = lifecycle.track();
+
+        assert!(lifecycle.request_restart());
+        assert!(!lifecycle.request_restart());
+        assert!(lifecycle.is_draining());
+        assert_eq!(lifecycle.exit_code(), RESTART_EXIT_CODE);
+        assert!(!lifecycle.wait_idle(Duration::from_millis(20)).await);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(guard);
+        });
+        assert!(lifecycle.wait_idle(Duration::from_secs(1)).await);
+    }
+
+    /// 测试暂停与恢复只在状态变化时返回 `true`。
+    #[test]
+    fn test_pause_and_resume() {
+        let lifecycle = Lifecycle::new();
+        assert!(!lifecycle.resume());
+        assert!(lifecycle.pause());
+        assert!(!lifecycle.pause());
+        assert!(lifecycle.is_paused());
+        assert!(!lifecycle.is_draining());
+        assert!(lifecycle.resume());
+        assert!(!lifecycle.is_paused());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::classifier;
+use crate::queue::PriorityClass;
+use crate::units;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// 任务载荷（序列化后的 JSON）的大小上限，`None` 表示不限制。
+///
+/// 同时配置了档位上限和任务类型上限时取较小的一个。
+/// 大载荷的关键任务会拖慢出队与处理，违背了优先级的初衷，例如可以要求 critical 任务小于 64 KiB。
+#[derive(Debug, Clone, Default)]
+pub struct PayloadLimits {
+    pub low: Option<usize>,
+    pub normal: Option<usize>,
+    pub critical: Option<usize>,
+    /// 按任务类型的上限。
+    pub by_type: HashMap<String, usize>,
+}
+
+/// 载荷超过上限的详细信息。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadTooLarge {
+    pub size: usize,
+    pub limit: usize,
+    /// 生效的上限来源，例如 `priority class critical` 或 `task type report`。
+    pub source: String,
+}
+
+impl PayloadLimits {
+    fn for_class(&self, class: PriorityClass) -> Option<usize> {
+        match class {
+            PriorityClass::Low => self.low,
+            PriorityClass::Normal => self.normal,
+            PriorityClass::Critical => self.critical,
+        }
+    }
+
+    /// 是否配置了任何上限。
+    pub fn is_enabled(&self) -> bool {
+        self.low.is_some()
+            || self.normal.is_some()
+            || self.critical.is_some()
+            || !self.by_type.is_empty()
+    }
+
+    /// 检查载荷是否超过其优先级档位或任务类型的上限。
+    pub fn check(&self, payload: &Value, priority: u8) -> Result<(), PayloadTooLarge> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let class = PriorityClass::from_priority(priority);
+        let task_type = classifier::payload_type(payload);
+        let limits = [
+            self.for_class(class)
+                .map(|limit| (limit, format!("priority class {}", class.as_str()))),
+            self.by_type
+                .get(task_type)
+                .map(|limit| (*limit, format!("task type {}", task_type))),
+        ];
+        let Some((limit, source)) = limits.into_iter().flatten().min_by_key(|(limit, _)| *limit)
+        else {
+            return Ok(());
+        };
+        let size = serde_json::to_vec(payload).map_or(0, |raw| raw.len());
+        if size > limit {
+            return Err(PayloadTooLarge {
+                size,
+                limit,
+                source,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 解析按任务类型的上限，格式为逗号分隔的 `type=size`，大小可以带单位，例如 `report=1MiB`。
+pub fn parse_type_limits(list: &str) -> Result<HashMap<String, usize>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|rule| {
+            let (task_type, bytes) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("{}: 格式应为 type=size", rule))?;
+            let bytes = units::parse_size(bytes)? as usize;
+            Ok((task_type.trim().to_string(), bytes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 测试按档位与按任务类型的上限，二者都配置时取较小的一个。
+    #[test]
+    fn test_payload_limits() {
+        let limits = PayloadLimits {
+            critical: Some(64),
+            by_type: parse_type_limits("report=32, bulk=1000").unwrap(),
+            ..Default::default()
+        };
+        let big = json!({ "data": "x".repeat(100) });
+        assert!(limits.check(&big, 10).is_ok());
+
+        let error = limits.check(&big, 250).unwrap_err();
+        assert_eq!(error.limit, 64);
+        assert_eq!(error.source, "priority class critical");
+
+        let report = json!({ "type": "report", "data": "x".repeat(40) });
+        let error = limits.check(&report, 250).unwrap_err();
+        assert_eq!(
+            (error.limit, error.source.as_str()),
+            (32, "task type report")
+        );
+
+        assert!(parse_type_limits("report").is_err());
+        assert!(parse_type_limits("report=big").is_err());
+        assert_eq!(parse_type_limits("report=64KiB").unwrap()["report"], 65536);
+    }
+}
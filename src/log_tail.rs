@@ -0,0 +1,361 @@
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// 广播通道的容量，订阅者落后超过该数量时跳过中间的日志。
+const BROADCAST_CAPACITY: usize = 1024;
+/// 未通过 `install` 设置时保留的最近日志条数。
+const DEFAULT_CAPACITY: usize = 1000;
+
+/// 进程内唯一的日志缓冲，日志层写入、管理 API 读取。
+static LOG_TAIL: OnceLock<LogTail> = OnceLock::new();
+
+/// 一条结构化日志。
+#[derive(Debug, Clone, Serialize)]
+pub struct LogRecord {
+    /// 进程内单调递增的序号，同时作为 SSE 的事件 ID。
+    pub id: u64,
+    pub at: DateTime<Utc>,
+    pub level: &'static str,
+    pub target: String,
+    pub message: String,
+    /// 除 `message` 之外的字段。
+    pub fields: Map<String, Value>,
+    #[serde(skip)]
+    severity: Level,
+}
+
+/// 订阅日志时的过滤条件。
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    /// 最低级别，例如 `WARN` 只保留 WARN 与 ERROR。
+    pub level: Option<Level>,
+    /// 模块路径，匹配 target 中以 `::` 分隔的完整片段，例如 `scheduler` 匹配 `web_server::scheduler`。
+    pub target: Option<String>,
+}
+
+impl LogFilter {
+    /// 日志是否满足过滤条件。
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        self.level.is_none_or(|level| record.severity <= level)
+            && self
+                .target
+                .as_deref()
+                .is_none_or(|target| target_matches(&record.target, target))
+    }
+}
+
+/// `filter` 是否为 `target` 中以 `::` 分隔的一段连续片段。
+fn target_matches(target: &str, filter: &str) -> bool {
+    target == filter
+        || target.starts_with(&format!("{}::", filter))
+        || target.ends_with(&format!("::{}", filter))
+        || target.contains(&format!("::{}::", filter))
+}
+
+/// 日志订阅流中的一项。
+#[derive(Debug, Clone)]
+pub enum TailEvent {
+    Record(LogRecord),
+    /// 订阅者处理过慢，跳过了给定条数的实时日志。
+    Lagged(u64),
+}
+
+/// 最近日志的环形缓冲与实时日志的广播通道。
+pub struct LogTail {
+    capacity: usize,
+    recent: Mutex<VecDeque<LogRecord>>,
+    sender: broadcast::Sender<LogRecord>,
+    next_id: AtomicU64,
+}
+
+impl LogTail {
+    fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
+        Self {
+            capacity,
+            recent: Mutex::new(VecDeque::with_capacity(capacity)),
+            sender,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// 记录一条日志：写入环形缓冲，超过容量时丢弃最早的一条，再广播给订阅者。
+    fn push(&self, mut record: LogRecord) {
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        // 在锁内分配序号，保证缓冲与广播中的顺序一致
+        record.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if self.capacity > 0 {
+            if recent.len() == self.capacity {
+                recent.pop_front();
+            }
+            recent.push_back(record.clone());
+        }
+        let _ = self.sender.send(record);
+    }
+
+    /// 返回满足条件的最近日志与之后的实时日志。
+    ///
+    /// 指定 `after` 时返回缓冲中序号大于它的日志（断线重连时通过 `Last-Event-ID` 带回），
+    /// 否则返回最近的 `limit` 条。先订阅再读取缓冲，读取期间写入的日志不会遗漏，也不会重复。
+    pub fn tail(
+        &self,
+        filter: LogFilter,
+        limit: usize,
+        after: Option<u64>,
+    ) -> impl Stream<Item = TailEvent> {
+        let receiver = self.sender.subscribe();
+        let (history, last_id) = {
+            let recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+            let matching: Vec<LogRecord> = recent
+                .iter()
+                .filter(|record| after.is_none_or(|after| record.id > after))
+                .filter(|record| filter.matches(record))
+                .cloned()
+                .collect();
+            let skip = if after.is_some() {
+                0
+            } else {
+                matching.len().saturating_sub(limit)
+            };
+            let last_id = self.next_id.load(Ordering::Relaxed).saturating_sub(1);
+            (matching.into_iter().skip(skip).collect::<Vec<_>>(), last_id)
+        };
+        let live = futures::stream::unfold(
+            (receiver, filter),
+            move |(mut receiver, filter)| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(record) if record.id > last_id && filter.matches(&record) => {
+                            return Some((TailEvent::Record(record), (receiver, filter)));
+                        }
+                        Ok(_) => {}
+                        // 落后太多时广播中的旧日志已被覆盖，告知订阅者跳过的条数
+                        Err(RecvError::Lagged(skipped)) => {
+                            return Some((TailEvent::Lagged(skipped), (receiver, filter)));
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        );
+        futures::stream::iter(history.into_iter().map(TailEvent::Record)).chain(live)
+    }
+}
+
+/// 设置保留的最近日志条数，只在第一次调用（初始化日志系统时）生效。
+pub fn install(capacity: usize) -> &'static LogTail {
+    LOG_TAIL.get_or_init(|| LogTail::new(capacity))
+}
+
+/// 进程内的日志缓冲；日志系统尚未初始化时（例如测试中）使用默认容量。
+pub fn handle() -> &'static LogTail {
+    install(DEFAULT_CAPACITY)
+}
+
+/// 将每条日志事件写入 `LogTail` 的日志层，受与其他输出层相同的 `RUST_LOG` 过滤。
+pub struct LogTailLayer {
+    tail: &'static LogTail,
+}
+
+impl LogTailLayer {
+    pub fn new(tail: &'static LogTail) -> Self {
+        Self { tail }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogTailLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        self.tail.push(LogRecord::from_event(event));
+    }
+}
+
+impl LogRecord {
+    /// 由日志事件生成一条记录，序号由写入方分配。
+    fn from_event(event: &Event<'_>) -> Self {
+        let metadata = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        LogRecord {
+            id: 0,
+            at: Utc::now(),
+            level: metadata.level().as_str(),
+            target: metadata.target().to_string(),
+            message: visitor.message,
+            fields: visitor.fields,
+            severity: *metadata.level(),
+        }
+    }
+}
+
+/// 将日志事件收集到内存中的日志层，用于记录一段代码（例如任务重放）执行期间产生的全部日志。
+///
+/// 作为局部 subscriber 的一层使用，不受 `RUST_LOG` 过滤；最多保留 `limit` 条，超出的只计数。
+#[derive(Clone)]
+pub struct CaptureLayer {
+    captured: Arc<Mutex<Captured>>,
+    limit: usize,
+}
+
+#[derive(Default)]
+struct Captured {
+    records: Vec<LogRecord>,
+    dropped: usize,
+}
+
+impl CaptureLayer {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            captured: Arc::default(),
+            limit,
+        }
+    }
+
+    /// 取出收集到的日志，以及因超过上限而丢弃的条数。
+    pub fn take(&self) -> (Vec<LogRecord>, usize) {
+        let mut captured = self.captured.lock().unwrap_or_else(|e| e.into_inner());
+        let dropped = std::mem::take(&mut captured.dropped);
+        (std::mem::take(&mut captured.records), dropped)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut captured = self.captured.lock().unwrap_or_else(|e| e.into_inner());
+        if captured.records.len() >= self.limit {
+            captured.dropped += 1;
+            return;
+        }
+        let mut record = LogRecord::from_event(event);
+        record.id = captured.records.len() as u64 + 1;
+        captured.records.push(record);
+    }
+}
+
+/// 收集事件的字段：`message` 单独保存，其余字段按类型转为 JSON 值。
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: Map<String, Value>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields
+                .insert(field.name().to_string(), format!("{:?}", value).into());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields.insert(field.name().to_string(), value.into());
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    async fn next_record(stream: &mut (impl Stream<Item = TailEvent> + Unpin)) -> LogRecord {
+        match stream.next().await {
+            Some(TailEvent::Record(record)) => record,
+            other => panic!("期望一条日志，实际为 {:?}", other),
+        }
+    }
+
+    /// 测试日志层记录结构化字段、按级别与模块过滤，以及回放与实时日志的衔接。
+    #[tokio::test]
+    async fn test_tail() {
+        let tail: &'static LogTail = Box::leak(Box::new(LogTail::new(3)));
+        let subscriber = tracing_subscriber::registry().with(LogTailLayer::new(tail));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        tracing::info!(target: "web_server::scheduler", task_id = 7, "开始处理");
+        tracing::warn!(target: "web_server::scheduler", retry = true, "处理失败: {}", "超时");
+        tracing::warn!(target: "web_server::web", "限流");
+        tracing::error!(target: "web_server::scheduler_extra", "不应匹配 scheduler");
+
+        let filter = LogFilter {
+            level: Some(Level::WARN),
+            target: Some("scheduler".to_string()),
+        };
+        let mut stream = Box::pin(tail.tail(filter.clone(), 10, None));
+        let first = next_record(&mut stream).await;
+        assert_eq!(
+            (first.level, first.message.as_str()),
+            ("WARN", "处理失败: 超时")
+        );
+        assert_eq!(first.fields["retry"], true);
+        assert_eq!(first.target, "web_server::scheduler");
+
+        // 订阅之后的日志实时送达
+        tracing::error!(target: "web_server::scheduler", "数据库不可用");
+        tracing::info!(target: "web_server::scheduler", "不满足级别");
+        let live = next_record(&mut stream).await;
+        assert_eq!(live.message, "数据库不可用");
+        assert!(live.id > first.id);
+
+        // 缓冲只保留最近 3 条；按 Last-Event-ID 回放之后的日志
+        let all = LogFilter::default();
+        let mut replay = Box::pin(tail.tail(all.clone(), 10, Some(live.id)));
+        assert_eq!(next_record(&mut replay).await.message, "不满足级别");
+        let mut recent = Box::pin(tail.tail(all, 2, None));
+        assert_eq!(next_record(&mut recent).await.message, "数据库不可用");
+        assert!(target_matches("web_server::scheduler::quick", "scheduler"));
+        assert!(!target_matches("web_server::scheduler_extra", "scheduler"));
+    }
+
+    /// 测试收集日志层：收集所有级别的日志，超过上限的只计数。
+    #[test]
+    fn test_capture_layer() {
+        let capture = CaptureLayer::new(2);
+        let subscriber = tracing_subscriber::registry().with(capture.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::trace!(step = 1, "读取载荷");
+            tracing::debug!("调用下游");
+            tracing::info!("不会被保留");
+        });
+        let (records, dropped) = capture.take();
+        assert_eq!(dropped, 1);
+        assert_eq!(records[0].level, "TRACE");
+        assert_eq!(records[0].fields["step"], 1);
+        assert_eq!(
+            (records[1].id, records[1].message.as_str()),
+            (2, "调用下游")
+        );
+        assert!(capture.take().0.is_empty());
+    }
+}
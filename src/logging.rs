@@ -1,52 +1,321 @@
 use crate::config::Config;
+use crate::diagnostics;
+use crate::log_tail::{self, LogTailLayer};
+use crate::metrics;
 use anyhow::Result;
-use tracing_appender::non_blocking::WorkerGuard;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::Subscriber;
+use tracing_appender::non_blocking::{ErrorCounter, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::{
-    fmt::{self, format::FmtSpan},
+    fmt::{self, format::FmtSpan, MakeWriter},
     layer::SubscriberExt,
+    registry::LookupSpan,
     util::SubscriberInitExt,
-    EnvFilter,
+    EnvFilter, Layer,
 };
 
+/// 日志输出格式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// 结构化 JSON，适合日志采集系统。
+    #[default]
+    Json,
+    /// 多行、带颜色的易读格式，适合本地开发。
+    Pretty,
+    /// 单行的紧凑文本格式。
+    Compact,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(LogFormat::Json),
+            "pretty" => Ok(LogFormat::Pretty),
+            "compact" => Ok(LogFormat::Compact),
+            other => Err(format!(
+                "未知的日志格式: {}（可选 json/pretty/compact）",
+                other
+            )),
+        }
+    }
+}
+
+/// 单个日志输出目标（sink）的配置。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkSettings {
+    /// 是否启用该输出目标。
+    pub enabled: bool,
+    /// 该输出目标使用的格式。
+    pub format: LogFormat,
+}
+
+impl Default for SinkSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            format: LogFormat::Json,
+        }
+    }
+}
+
+/// 文件日志的状态，供管理 API 与指标读取；未启用文件日志时为空。
+static FILE_SINK: OnceLock<Arc<FileSinkStatus>> = OnceLock::new();
+
+/// 文件日志的运行状态。
+#[derive(Debug, Default)]
+pub struct FileSinkStatus {
+    /// 日志文件写入失败、正在等待重试。
+    degraded: AtomicBool,
+    /// 写入日志文件失败的次数。
+    write_failures: AtomicU64,
+    /// 非阻塞写入通道已满时丢弃的日志条数。
+    dropped: OnceLock<ErrorCounter>,
+}
+
+/// 文件日志状态的快照。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FileSinkSnapshot {
+    pub degraded: bool,
+    pub write_failures: u64,
+    pub dropped_lines: u64,
+}
+
+impl FileSinkStatus {
+    pub fn snapshot(&self) -> FileSinkSnapshot {
+        FileSinkSnapshot {
+            degraded: self.degraded.load(Ordering::Relaxed),
+            write_failures: self.write_failures.load(Ordering::Relaxed),
+            dropped_lines: self
+                .dropped
+                .get()
+                .map(|counter| counter.dropped_lines() as u64)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// 文件日志的状态，未启用文件日志时返回 `None`。
+pub fn file_sink() -> Option<FileSinkSnapshot> {
+    FILE_SINK.get().map(|status| status.snapshot())
+}
+
+/// 将文件日志的状态写入指标注册表，在导出指标前调用。
+pub fn record_metrics() {
+    if let Some(status) = file_sink() {
+        metrics::gauge("log_file_degraded").set(if status.degraded { 1.0 } else { 0.0 });
+        metrics::gauge("log_lines_dropped_total").set(status.dropped_lines as f64);
+    }
+}
+
+/// 打开 `log_directory` 下按天滚动的日志文件，文件名格式为 `app.log.YYYY-MM-DD`。
+fn open_log_file(log_directory: &PathBuf) -> io::Result<RollingFileAppender> {
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("app.log")
+        .build(log_directory)
+        .map_err(io::Error::other)
+}
+
+/// 写入日志文件的 writer，在非阻塞 writer 的后台线程中运行。
+///
+/// 写入失败（磁盘已满、目录权限被修改等）时不再写文件，只保留标准输出：标准输出层未启用时，
+/// 文件日志的内容改为写入标准输出，避免日志丢失。之后每隔 `retry_interval` 重新打开日志文件再试，
+/// 成功后恢复写入文件。状态变化输出到标准错误并记录到诊断信息，不经过日志系统，避免循环写入。
+struct FallbackWriter<W, R> {
+    /// 日志文件，写入失败后为 `None`。
+    file: Option<W>,
+    /// 重新打开日志文件。
+    reopen: R,
+    /// 写入日志文件失败时的替代输出，标准输出层已启用时为 `None`。
+    fallback: Option<Box<dyn Write + Send>>,
+    retry_interval: Duration,
+    /// 最近一次写入失败或重试失败的时刻。
+    failed_at: Option<Instant>,
+    status: Arc<FileSinkStatus>,
+}
+
+impl<W: Write, R: FnMut() -> io::Result<W>> FallbackWriter<W, R> {
+    fn new(
+        file: io::Result<W>,
+        reopen: R,
+        fallback: Option<Box<dyn Write + Send>>,
+        retry_interval: Duration,
+        status: Arc<FileSinkStatus>,
+    ) -> Self {
+        let mut writer = Self {
+            file: None,
+            reopen,
+            fallback,
+            retry_interval,
+            failed_at: None,
+            status,
+        };
+        match file {
+            Ok(file) => writer.file = Some(file),
+            Err(e) => writer.fail(e),
+        }
+        writer
+    }
+
+    /// 记录一次写入失败，从正常状态进入降级状态时告警。
+    fn fail(&mut self, error: io::Error) {
+        self.file = None;
+        self.failed_at = Some(Instant::now());
+        self.status.write_failures.fetch_add(1, Ordering::Relaxed);
+        metrics::counter("log_write_failures_total").inc();
+        if !self.status.degraded.swap(true, Ordering::Relaxed) {
+            let message = format!(
+                "写入日志文件失败，暂时只输出到标准输出，{:?} 后重试: {}",
+                self.retry_interval, error
+            );
+            eprintln!("{}", message);
+            diagnostics::record_error(message);
+        }
+    }
+
+    /// 降级状态下到达重试时间时重新打开日志文件。
+    fn retry(&mut self) {
+        let due = self
+            .failed_at
+            .is_some_and(|failed_at| failed_at.elapsed() >= self.retry_interval);
+        if self.file.is_none() && due {
+            match (self.reopen)() {
+                Ok(file) => self.file = Some(file),
+                Err(e) => self.fail(e),
+            }
+        }
+    }
+}
+
+impl<W: Write, R: FnMut() -> io::Result<W>> Write for FallbackWriter<W, R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.retry();
+        if let Some(file) = self.file.as_mut() {
+            match file.write_all(buf) {
+                Ok(()) => {
+                    if self.status.degraded.swap(false, Ordering::Relaxed) {
+                        self.failed_at = None;
+                        eprintln!("日志文件已恢复写入");
+                    }
+                    return Ok(buf.len());
+                }
+                Err(e) => self.fail(e),
+            }
+        }
+        if let Some(fallback) = self.fallback.as_mut() {
+            let _ = fallback.write_all(buf);
+        }
+        // 总是报告写入成功，失败已经在这里处理
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(Err(e)) = self.file.as_mut().map(Write::flush) {
+            self.fail(e);
+        }
+        if let Some(fallback) = self.fallback.as_mut() {
+            let _ = fallback.flush();
+        }
+        Ok(())
+    }
+}
+
+/// 按指定格式构建一个输出层。
+fn sink_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer()
+        .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE) // 在 span 创建和关闭时记录事件
+        .with_ansi(ansi)
+        .with_writer(writer);
+    match format {
+        LogFormat::Json => layer.json().boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+    }
+}
+
 /// 初始化日志系统。
 ///
-/// 这个函数配置了 `tracing` subscriber，用于将日志输出到两个地方：
-/// 1. 标准输出 (stdout)，格式为 JSON。
-/// 2. 滚动日志文件，每天创建一个新文件，格式为 JSON。
+/// 这个函数配置了 `tracing` subscriber，可以将日志输出到两个地方：
+/// 1. 标准输出 (stdout)。
+/// 2. 滚动日志文件，每天创建一个新文件。
+///
+/// 两个输出目标可以通过配置分别开关，并分别选择格式（默认均为 JSON）。
+/// 日志文件写入失败时暂时只输出到标准输出，每隔 `LOG_FILE_RETRY_SECS` 重试写入文件；
+/// 失败次数、降级状态与非阻塞通道已满时丢弃的条数通过指标与 `GET /admin/status` 查看。
 ///
 /// # Arguments
-/// * `config` - 应用的配置，主要用于获取 `RUST_LOG` 日志级别。
+/// * `config` - 应用的配置，用于获取 `RUST_LOG` 日志级别和各输出目标的设置。
 /// * `log_directory` - 存放日志文件的目录。
 ///
 /// # Returns
-/// 返回一个 `WorkerGuard`。这个 guard 必须在应用的整个生命周期内保持存活。
+/// 启用文件输出时返回一个 `WorkerGuard`。这个 guard 必须在应用的整个生命周期内保持存活。
 /// 当 `guard`被 drop 时，它会确保所有缓冲的日志都被刷新到文件中。
-pub fn init_logging(config: &Config, log_directory: &str) -> Result<WorkerGuard> {
-    // 配置滚动文件 appender，日志会写入到 `log_directory` 下，文件名格式为 `app.log.YYYY-MM-DD`
-    let file_appender = tracing_appender::rolling::daily(log_directory, "app.log");
-    // 使用 `non_blocking` writer 来避免日志写入操作阻塞应用主线程
-    let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
-
+pub fn init_logging(config: &Config, log_directory: &str) -> Result<Option<WorkerGuard>> {
     // 从配置中创建 EnvFilter，用于根据 `RUST_LOG` 环境变量的值来过滤日志
     let env_filter = EnvFilter::try_new(&config.rust_log)?;
 
     // 配置标准输出层 (layer)
-    let stdout_layer = fmt::layer()
-        .json() // 使用 JSON 格式输出
-        .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE) // 在 span 创建和关闭时记录事件
-        .with_writer(std::io::stdout); // 写入到标准输出
+    let stdout_layer = config
+        .log_stdout
+        .enabled
+        .then(|| sink_layer(config.log_stdout.format, std::io::stdout, true));
 
     // 配置文件输出层 (layer)
-    let file_layer = fmt::layer()
-        .json() // 使用 JSON 格式输出
-        .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE) // 在 span 创建和关闭时记录事件
-        .with_writer(non_blocking); // 写入到非阻塞的文件 appender
+    let (file_layer, guard) = if config.log_file.enabled {
+        // 配置滚动文件 appender，日志会写入到 `log_directory` 下，文件名格式为 `app.log.YYYY-MM-DD`；
+        // 启动时无法打开日志文件同样按写入失败处理，不阻止服务启动
+        let directory = PathBuf::from(log_directory);
+        let status = FILE_SINK.get_or_init(Default::default).clone();
+        // 标准输出层未启用时，写入日志文件失败的日志改为写入标准输出
+        let fallback = (!config.log_stdout.enabled).then(|| Box::new(io::stdout()) as Box<_>);
+        let file_appender = FallbackWriter::new(
+            open_log_file(&directory),
+            move || open_log_file(&directory),
+            fallback,
+            config.log_file_retry,
+            status.clone(),
+        );
+        // 使用 `non_blocking` writer 来避免日志写入操作阻塞应用主线程
+        let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+        let _ = status.dropped.set(non_blocking.error_counter());
+        (
+            Some(sink_layer(config.log_file.format, non_blocking, false)),
+            Some(guard),
+        )
+    } else {
+        (None, None)
+    };
 
-    // 使用 `tracing_subscriber::registry` 组合多个层
+    // 启用 `console` feature 时接入 tokio-console（默认监听 127.0.0.1:6669）。
+    // 环境过滤器对所有层生效，`RUST_LOG` 需要包含 `tokio=trace,runtime=trace`
+    #[cfg(feature = "console")]
+    let console_layer = Some(console_subscriber::spawn());
+    #[cfg(not(feature = "console"))]
+    let console_layer: Option<tracing_subscriber::layer::Identity> = None;
+
+    // 保留最近的日志并广播实时日志，供 `GET /admin/logs/tail` 订阅
+    let tail_layer = LogTailLayer::new(log_tail::install(config.log_tail_buffer));
+
+    // 使用 `tracing_subscriber::registry` 组合多个层，未启用的层为 `None`，不产生任何输出
     tracing_subscriber::registry()
         .with(env_filter) // 添加环境过滤器
         .with(stdout_layer) // 添加标准输出层
         .with(file_layer) // 添加文件输出层
+        .with(console_layer) // 添加 tokio-console 层
+        .with(tail_layer) // 添加日志订阅层
         .try_init()?; // 初始化 subscriber 并设置为全局默认
 
     // 返回 guard，调用者需要负责保持它
@@ -68,9 +337,8 @@ mod tests {
 
         // 创建一个临时的测试配置
         let config = Config {
-            server_address: "".to_string(),
-            database_url: "".to_string(),
             rust_log: "info".to_string(),
+            ..Default::default()
         };
 
         // 初始化日志
@@ -92,4 +360,85 @@ mod tests {
 
         assert!(!log_files.is_empty(), "日志文件未被创建。");
     }
+
+    /// 一次写入失败后恢复的日志文件。
+    #[derive(Clone, Default)]
+    struct FlakyFile {
+        failing: Arc<AtomicBool>,
+        lines: Arc<std::sync::Mutex<Vec<u8>>>,
+    }
+
+    impl Write for FlakyFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(io::Error::other("磁盘已满"));
+            }
+            self.lines.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 测试写入日志文件失败时改为写入标准输出，并在重试间隔之后恢复写入文件。
+    #[test]
+    fn test_fallback_writer() {
+        let file = FlakyFile::default();
+        let fallback = FlakyFile::default();
+        let status = Arc::new(FileSinkStatus::default());
+        let reopen = {
+            let file = file.clone();
+            move || Ok(file.clone())
+        };
+        let mut writer = FallbackWriter::new(
+            Ok(file.clone()),
+            reopen,
+            Some(Box::new(fallback.clone())),
+            Duration::from_millis(50),
+            status.clone(),
+        );
+        writer.write_all(b"a\n").unwrap();
+        file.failing.store(true, Ordering::Relaxed);
+        writer.write_all(b"b\n").unwrap();
+        // 重试间隔内不再尝试写入文件
+        file.failing.store(false, Ordering::Relaxed);
+        writer.write_all(b"c\n").unwrap();
+        assert_eq!(
+            status.snapshot(),
+            FileSinkSnapshot {
+                degraded: true,
+                write_failures: 1,
+                dropped_lines: 0,
+            }
+        );
+        assert_eq!(*fallback.lines.lock().unwrap(), b"b\nc\n");
+
+        std::thread::sleep(Duration::from_millis(60));
+        writer.write_all(b"d\n").unwrap();
+        assert!(!status.snapshot().degraded);
+        assert_eq!(*file.lines.lock().unwrap(), b"a\nd\n");
+
+        // 启动时无法打开日志文件，重新打开同样失败时继续等待
+        let status = Arc::new(FileSinkStatus::default());
+        let mut writer = FallbackWriter::new(
+            Err(io::Error::other("权限不足")),
+            || Err::<FlakyFile, _>(io::Error::other("权限不足")),
+            None,
+            Duration::ZERO,
+            status.clone(),
+        );
+        writer.write_all(b"e\n").unwrap();
+        assert_eq!(status.snapshot().write_failures, 2);
+    }
+
+    /// 测试日志格式的解析。
+    #[test]
+    fn test_log_format_from_str() {
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("pretty".parse::<LogFormat>(), Ok(LogFormat::Pretty));
+        assert_eq!(" compact ".parse::<LogFormat>(), Ok(LogFormat::Compact));
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
 }
@@ -1,56 +1,696 @@
 // 模块声明
+mod admin;
+mod admission;
+mod annotations;
+mod audit;
+mod backpressure;
+mod check_config;
+mod claims;
+mod classifier;
+mod cli;
 mod config;
+mod config_file;
+mod context;
 mod db;
+mod decompress;
+mod diagnostics;
+mod dlq;
 mod error;
+mod events;
+#[cfg(feature = "fixtures")]
+mod fixtures;
+mod handler;
+mod i18n;
+mod ids;
+mod jwt;
+mod lifecycle;
+mod limits;
+mod log_tail;
 mod logging;
+mod metrics;
+mod outbound;
+mod policy;
+mod quarantine;
 mod queue;
+mod queue_migration;
+mod rate_limit;
+mod redis_queue;
+mod replay;
+mod response;
+mod results;
+mod retry_budget;
+mod runtime_metrics;
 mod scheduler;
+#[cfg(feature = "fixtures")]
+mod simulate;
+mod slo;
+mod starvation;
+mod status;
+mod supervisor;
+mod systemd;
+mod task_types;
+mod tokens;
+mod transform;
+mod units;
+mod validation;
+mod watchdog;
 mod web;
 
 // 引入外部依赖和内部模块
-use crate::config::Config;
-use crate::db::create_db_pool;
+use crate::admin::admin_router;
+use crate::admission::SlowAdmission;
+use crate::backpressure::Throughput;
+use crate::claims::run_claim_poller;
+use crate::classifier::SlowClassifier;
+use crate::config::{Config, ListenerRole};
+use crate::db::{check_schema, run_pool_monitor, Connectivity, Database, SchemaCheck};
+use crate::diagnostics::run_diagnostics_signal;
 use crate::error::AppError;
-use crate::queue::PriorityQueue;
-use crate::scheduler::run_scheduler;
+use crate::events::{run_event_recorder, EventBus};
+use crate::ids::IdGenerator;
+use crate::jwt::JwtVerifier;
+use crate::lifecycle::Lifecycle;
+use crate::metrics::QueueMetrics;
+use crate::policy::PolicyEngine;
+use crate::queue::{PriorityQueue, QueueBackend, QueueBackendKind};
+use crate::queue_migration::MigratingQueue;
+use crate::rate_limit::RateLimiter;
+use crate::redis_queue::RedisQueue;
+use crate::results::ResultStore;
+use crate::retry_budget::RetryBudget;
+use crate::scheduler::{run_scheduler, Handlers, SchedulerContext};
+use crate::slo::{run_slo_monitor, SloTracker};
+use crate::starvation::run_starvation_monitor;
+use crate::status::{
+    flush_tenant_stats, run_tenant_stats_flusher, TaskIndex, TENANT_STATS_FLUSH_INTERVAL,
+};
+use crate::supervisor::Supervisor;
+use crate::systemd::{run_systemd_watchdog, Notifier};
+use crate::task_types::TaskTypeConfigs;
+use crate::tokens::TokenStore;
+use crate::transform::{TransformHandler, TRANSFORM_TASK_TYPE};
+use crate::watchdog::{run_watchdog, Heartbeat};
 use crate::web::{api_router, AppState};
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::signal;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// 文件日志与诊断快照的目录。
+const LOG_DIRECTORY: &str = "logs";
+/// 停机时等待正在处理的任务完成的最长时间。
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// 应用主入口
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
-    // 从环境变量加载配置
-    let config = Config::from_env()?;
+    // 管理子命令通过管理 API 操作正在运行的实例，不需要加载服务的配置
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // `check-config` 子命令：校验配置、目录与数据库后退出，不启动服务
+    if args.first().map(String::as_str) == Some("check-config") {
+        let report = check_config::run(Path::new(LOG_DIRECTORY)).await;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).map_err(anyhow::Error::from)?
+        );
+        std::process::exit(if report.ok { 0 } else { 1 });
+    }
+    // `simulate` 子命令：在模拟时钟上用调度器与队列回放负载描述，预测队列深度与延迟，不需要服务的配置
+    if args.first().map(String::as_str) == Some("simulate") {
+        #[cfg(feature = "fixtures")]
+        {
+            let report = simulate::parse_args(&args[1..]).and_then(|(path, workers)| {
+                simulate::run(&simulate::SimulationSpec::load(&path)?, workers)
+            });
+            match report {
+                Ok(report) => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).map_err(anyhow::Error::from)?
+                ),
+                Err(e) => {
+                    eprintln!("错误: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        #[cfg(not(feature = "fixtures"))]
+        {
+            eprintln!("simulate 子命令需要启用 fixtures feature 编译");
+            std::process::exit(2);
+        }
+    }
+    match cli::parse(&args) {
+        Ok(Some(command)) => {
+            if let Err(e) = cli::run(command).await {
+                eprintln!("错误: {:#}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+
+    // 从环境变量（以及 `CONFIG_PATH` 指向的配置文件）加载配置
+    let config = Config::load()?;
     // 初始化日志系统
-    let _guard = logging::init_logging(&config, "logs")?;
+    let _guard = logging::init_logging(&config, LOG_DIRECTORY)?;
+
+    // `bench` 子命令：用合成负载压测队列后直接退出，不连接数据库
+    #[cfg(feature = "fixtures")]
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        let report = fixtures::run_bench(&config.fixtures, config.queue_compression).await;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).map_err(anyhow::Error::from)?
+        );
+        return Ok(());
+    }
+
+    response::set_envelope(config.response_envelope);
+    // 任务 ID 与请求 ID 使用配置的格式生成
+    ids::set_generator(IdGenerator::new(
+        config.id_format,
+        config.snowflake_worker_id,
+    ));
+
+    // 创建数据库连接池；表名前缀必须在执行任何查询（包括迁移检查）之前设置
+    db::set_table_prefix(&config.table_prefix);
+    db::set_slow_query_threshold(config.db_slow_query_threshold);
+    db::set_statement_timeout(config.db_statement_timeout);
+    db::set_circuit_breaker(config.circuit_settings());
+    // 延迟连接时只创建连接池，数据库暂时不可用也能立即开始服务，连接在后台建立；
+    // 立即连接时按 `DB_CONNECT_ATTEMPTS` 退避重试，全部失败才退出
+    let db = if config.db_connect_lazy {
+        Database::connect_lazy(
+            config.db_mode,
+            &config.database_url,
+            &config.pool_settings(),
+        )?
+    } else {
+        Database::connect_with_retry(
+            config.db_mode,
+            &config.database_url,
+            &config.pool_settings(),
+            &config.connect_retry(),
+        )
+        .await?
+    };
+    let connectivity = Arc::new(if config.db_connect_lazy && db.is_durable() {
+        Connectivity::default()
+    } else {
+        Connectivity::connected()
+    });
+    // 为关键任务预留一部分连接，避免被大量低优先级任务占满
+    let db = db.with_pool_reservation(config.db_critical_reserved_percent);
+    if !db.is_durable() {
+        tracing::warn!(
+            backend = db.backend_name(),
+            "正在使用内存数据库，所有数据仅保存在进程内，重启后丢失，请勿在生产环境使用"
+        );
+    }
+    // 所有出站请求共用一个 HTTP 客户端，代理配置在这里统一生效
+    let http = outbound::build_client(&config.outbound_proxy)?;
+    if config.outbound_proxy.is_enabled() {
+        tracing::info!("出站 HTTP 请求将按配置经过代理");
+    }
+
+    // 创建一个带引用计数的、线程安全的优先级队列，持久化的后端会记录每个入队的任务，
+    // 记录由本实例认领，共用数据库的其他实例不会处理它们；
+    // `QUEUE_BACKEND=redis` 时改用多个实例共享的 Redis 队列；
+    // 设置了 `QUEUE_CAPACITY` 时队列有界，提交的任务在队列已满时被拒绝
+    let local_queue = || {
+        PriorityQueue::with_compression(config.queue_compression)
+            .with_capacity(config.queue_capacity)
+            .with_band_capacities(config.queue_band_capacities)
+            .with_journal(db.clone())
+            .with_claims(config.instance_id.clone(), config.queue_claim_lease)
+            .with_hooks(Arc::new(QueueMetrics))
+    };
+    let queue: Arc<dyn QueueBackend> = match config.queue_backend {
+        QueueBackendKind::Local => Arc::new(local_queue()),
+        QueueBackendKind::Redis => {
+            if config.queue_compression.enabled {
+                tracing::warn!("Redis 队列不压缩任务载荷，QUEUE_COMPRESSION 被忽略");
+            }
+            Arc::new(
+                RedisQueue::connect(
+                    &config.redis_url,
+                    &config.redis_queue_prefix,
+                    config.queue_claim_lease,
+                )
+                .await?
+                .with_capacity(config.queue_capacity)
+                .with_band_capacities(config.queue_band_capacities)
+                .with_hooks(Arc::new(QueueMetrics)),
+            )
+        }
+    };
+    // 设置了 `QUEUE_MIGRATE_FROM` 时进入迁移窗口：新任务写入新后端，
+    // 旧后端中的任务照常被处理，并可以通过管理 API 迁移到新后端
+    let queue_migration = match config.queue_migrate_from {
+        Some(QueueBackendKind::Local) => {
+            let migration = Arc::new(MigratingQueue::new(Arc::new(local_queue()), queue.clone()));
+            tracing::warn!(
+                from = "local",
+                to = queue.name(),
+                "队列处于迁移窗口，旧后端中的任务可以通过 POST /admin/queue/migration 迁移"
+            );
+            Some(migration)
+        }
+        Some(QueueBackendKind::Redis) | None => None,
+    };
+    let queue: Arc<dyn QueueBackend> = match &queue_migration {
+        Some(migration) => migration.clone(),
+        None => queue,
+    };
+    tracing::info!(backend = queue.name(), "队列已就绪");
+    // 任务状态索引：提交时同步写入，调度器在状态变化时更新；
+    // 每次状态变化都会产生一条事件，由事件记录器写入历史表并广播给事件流
+    let (event_sender, event_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let tasks = TaskIndex::with_events(event_sender);
+    let events = EventBus::new();
+    // 检查表结构并在调度器启动之前恢复上次未处理完的任务；
+    // 延迟连接时这些步骤在数据库连接成功后于后台执行，在此之前服务未就绪
+    let schema_check = if connectivity.is_connected() {
+        prepare_database(&db, queue.as_ref(), &tasks, &config).await
+    } else {
+        SchemaCheck::pending()
+    };
+
+    // 停机与滚动重启请求通过生命周期状态在各组件间传递
+    let lifecycle = Lifecycle::new();
+    // 各任务类型的耗时统计在调度器重启之间保持不变，管理接口也会读取
+    let classifier = Arc::new(SlowClassifier::new(config.slow_tasks.clone()));
+    // 调度器的出队速度，用于估算新任务的开始时间和过载时的 `Retry-After`
+    let throughput = Arc::new(Throughput::new(config.throughput_window));
+    // 按任务类型的调度配置，调度器读取、管理接口修改，二者共用同一份缓存
+    let task_types = Arc::new(
+        TaskTypeConfigs::new(db.clone(), config.task_type_config_ttl)
+            .with_default_timeout(config.task_timeout),
+    );
+
+    // 所有后台任务都交由监督者持有，崩溃后自动重启
+    let supervisor = Arc::new(Supervisor::new(config.supervisor_max_restarts));
+    // 调度器每次循环都会更新心跳，看门狗据此检测调度循环是否卡住
+    let heartbeat = Heartbeat::new();
+    // 按任务类型的延迟目标统计达标率，调度器在任务结束时记录
+    let slo = Arc::new(SloTracker::new(config.slo.clone()));
+    // 按任务类型注册处理器，未注册的类型按快慢分类使用默认的处理器
+    // 处理器返回的流式结果保存在本地目录中，通过 `GET /tasks/:id/result` 下载
+    let results = ResultStore::new(&config.result_store_dir);
+    let handlers = Handlers::new(&config.handler_middleware)
+        .register(TRANSFORM_TASK_TYPE, Arc::new(TransformHandler));
+    {
+        // 在后台运行调度器
+        let context = SchedulerContext {
+            queue: queue.clone(),
+            db: db.clone(),
+            tasks: tasks.clone(),
+            heartbeat: heartbeat.clone(),
+            // 重试预算在调度器重启之间保持不变
+            budget: Arc::new(RetryBudget::new(config.retry_budget)),
+            classifier: classifier.clone(),
+            // 慢速任务占用的预算在调度器重启之间保持不变
+            admission: Arc::new(SlowAdmission::new(config.slow_admission.clone())),
+            handlers: handlers.clone(),
+            throughput: throughput.clone(),
+            task_types: task_types.clone(),
+            slo: slo.clone(),
+            results: results.clone(),
+            lifecycle: lifecycle.clone(),
+            shutdown: lifecycle.shutdown_token(),
+            workers: config.scheduler_workers,
+            max_slow_tasks: config.max_slow_tasks,
+        };
+        supervisor
+            .spawn("scheduler", move || run_scheduler(context.clone()))
+            .await;
+    }
+    {
+        // 在后台记录任务事件
+        let db = db.clone();
+        let receiver = Arc::new(tokio::sync::Mutex::new(event_receiver));
+        let events = events.clone();
+        supervisor
+            .spawn("event_recorder", move || {
+                run_event_recorder(db.clone(), receiver.clone(), events.clone())
+            })
+            .await;
+    }
+    {
+        // 在后台运行看门狗
+        let heartbeat = heartbeat.clone();
+        let settings = config.watchdog_settings();
+        supervisor
+            .spawn("watchdog", move || {
+                run_watchdog(heartbeat.clone(), settings)
+            })
+            .await;
+    }
+    // 在 systemd 下运行且配置了 `WatchdogSec` 时，按调度器心跳发送保活通知
+    let notifier = Notifier::from_env();
+    if let Some(interval) = systemd::watchdog_interval().filter(|_| notifier.is_enabled()) {
+        let notifier = notifier.clone();
+        let heartbeat = heartbeat.clone();
+        let stall_threshold = config.watchdog_settings().stall_threshold;
+        supervisor
+            .spawn("systemd_watchdog", move || {
+                run_systemd_watchdog(
+                    notifier.clone(),
+                    heartbeat.clone(),
+                    stall_threshold,
+                    interval,
+                )
+            })
+            .await;
+    }
+    {
+        // 在后台定期检查连接池健康状况
+        let db = db.clone();
+        let interval = config.db_health_check_interval;
+        supervisor
+            .spawn("db_pool_monitor", move || {
+                run_pool_monitor(db.clone(), interval)
+            })
+            .await;
+    }
+    {
+        // 在后台定期把按租户的任务统计累加到 `tenant_stats` 表
+        let db = db.clone();
+        let tasks = tasks.clone();
+        supervisor
+            .spawn("tenant_stats_flusher", move || {
+                run_tenant_stats_flusher(tasks.clone(), db.clone(), TENANT_STATS_FLUSH_INTERVAL)
+            })
+            .await;
+    }
+
+    if queue.is_durable() {
+        // 在后台续约本实例认领的任务，并接管失联实例留下的任务
+        let queue = queue.clone();
+        let tasks = tasks.clone();
+        let interval = config.queue_claim_lease / 3;
+        supervisor
+            .spawn("queue_claims", move || {
+                run_claim_poller(queue.clone(), tasks.clone(), interval)
+            })
+            .await;
+    }
+    {
+        // 在后台检测排队过久的任务
+        let queue = queue.clone();
+        let thresholds = config.starvation_thresholds;
+        let interval = config.starvation_check_interval;
+        supervisor
+            .spawn("starvation_monitor", move || {
+                run_starvation_monitor(queue.clone(), thresholds, interval)
+            })
+            .await;
+    }
 
-    // 创建数据库连接池
-    let db_pool = create_db_pool(&config.database_url).await?;
-    // 创建一个带引用计数的、线程安全的优先级队列
-    let queue = Arc::new(PriorityQueue::new());
+    if !config.slo.targets.is_empty() {
+        // 在后台检查 SLO 的消耗速率，快速消耗时告警
+        let slo = slo.clone();
+        let http = http.clone();
+        supervisor
+            .spawn("slo_monitor", move || {
+                run_slo_monitor(slo.clone(), http.clone())
+            })
+            .await;
+    }
 
+    // 配置加载时已经校验过密钥，这里不会失败
+    let jwt = config
+        .jwt
+        .as_ref()
+        .map(JwtVerifier::new)
+        .transpose()
+        .map_err(AppError::Config)?
+        .map(Arc::new);
+    // 配置了外部策略服务时，公开 API 的每个请求在鉴权之后还要经过策略决策
+    let policy = config.policy.clone().map(|settings| {
+        tracing::info!(url = %settings.url, "公开 API 的授权交由外部策略服务决定");
+        Arc::new(PolicyEngine::new(settings, http))
+    });
     // 创建应用状态，用于在 axum handler 中共享
     let app_state = AppState {
-        db_pool: db_pool.clone(),
+        config: Arc::new(config.clone()),
+        db: db.clone(),
         queue: queue.clone(),
+        tasks,
+        events,
+        lifecycle: lifecycle.clone(),
+        classifier,
+        throughput,
+        supervisor: supervisor.clone(),
+        heartbeat,
+        schema_check: Arc::new(RwLock::new(schema_check)),
+        db_connectivity: connectivity.clone(),
+        api_tokens: TokenStore::new(db.clone(), config.api_token_cache_ttl),
+        task_types,
+        handlers,
+        slo,
+        rate_limiter: Arc::new(RateLimiter::new(config.rate_limit)),
+        results,
+        jwt,
+        policy,
+        queue_migration,
     };
 
-    // 在后台 Tokio 任务中运行调度器
-    tokio::spawn(run_scheduler(queue, db_pool));
+    if !connectivity.is_connected() {
+        // 在后台连接数据库，连接成功后完成表结构检查与任务恢复，服务随之变为就绪
+        let state = app_state.clone();
+        tokio::spawn(async move {
+            let retry = state.config.connect_retry();
+            db::wait_until_reachable(&state.db, &state.db_connectivity, &retry).await;
+            let schema =
+                prepare_database(&state.db, state.queue.as_ref(), &state.tasks, &state.config)
+                    .await;
+            *state
+                .schema_check
+                .write()
+                .unwrap_or_else(|e| e.into_inner()) = schema;
+        });
+    }
+
+    // `kill -USR1 <pid>` 输出诊断快照，管理接口无响应时也能排查问题
+    {
+        let state = app_state.clone();
+        supervisor
+            .spawn("diagnostics", move || {
+                run_diagnostics_signal(state.clone(), PathBuf::from(LOG_DIRECTORY))
+            })
+            .await;
+    }
+
+    // 先绑定所有监听地址，任何一个失败都直接终止启动，避免只启动了一部分服务
+    let mut listeners = Vec::new();
+    for listener_config in config.listeners() {
+        let listener = TcpListener::bind(&listener_config.address)
+            .await
+            .map_err(|e| {
+                AppError::Config(format!("无法绑定地址 {}: {}", listener_config.address, e))
+            })?;
+        listeners.push((listener_config.role, listener));
+    }
+
+    // 所有监听器共享同一个停机令牌，收到信号后一起进入优雅停机
+    let shutdown = CancellationToken::new();
+    let mut servers = JoinSet::new();
+    for (role, listener) in listeners {
+        // 根据监听器角色选择对应的路由组合
+        let app = match role {
+            ListenerRole::Public => api_router(app_state.clone()),
+            ListenerRole::Admin => admin_router(app_state.clone()),
+        };
+        let local_addr = listener.local_addr().map_err(anyhow::Error::from)?;
+        tracing::info!(?role, "listening on {}", local_addr);
+
+        let token = shutdown.clone();
+        servers.spawn(async move {
+            // 记录对端地址，公开 API 在没有 API 令牌时按客户端 IP 限流
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(token.cancelled_owned()) // 设置优雅停机
+            .await
+        });
+    }
+
+    // 所有监听器都已开始接收连接，通知 systemd 启动完成
+    notifier.notify("READY=1");
+
+    // 等待停机信号或重启请求；如果某个监听器提前退出，也视为需要停机
+    let mut result = Ok(());
+    tokio::select! {
+        _ = shutdown_signal() => {}
+        _ = lifecycle.shutdown_requested() => {
+            tracing::info!("收到重启请求，开始排空");
+        }
+        Some(joined) = servers.join_next() => {
+            result = flatten_server_result(joined);
+            tracing::error!("监听器意外退出，开始停机");
+        }
+    }
+    notifier.notify("STOPPING=1");
+    // 进入排空状态：不再接收新任务，调度器不再取出新任务
+    lifecycle.request_shutdown();
+    // 封住队列：已经通过排空检查的请求和调度器的重试都无法再放入一个不会被处理的任务
+    let pending = queue.close().await;
+    if pending > 0 && queue.is_durable() {
+        tracing::info!(pending, "队列已关闭，未处理的任务将在下次启动时恢复");
+    } else if pending > 0 {
+        tracing::warn!(pending, "队列已关闭，仍有排队任务未处理");
+    }
+    // 通知所有监听器停止接收新连接
+    shutdown.cancel();
+
+    // 等待所有监听器处理完正在进行的请求
+    while let Some(joined) = servers.join_next().await {
+        if let Err(e) = flatten_server_result(joined) {
+            tracing::error!("监听器退出时出错: {}", e);
+            result = result.and(Err(e));
+        }
+    }
+
+    // 等待调度器排空（正在处理的任务全部完成）后再停止后台任务
+    if !lifecycle.wait_idle(DRAIN_TIMEOUT).await {
+        if queue.is_durable() {
+            tracing::warn!(
+                in_flight = lifecycle.in_flight(),
+                "等待正在处理的任务超时，强制停止，这些任务将在下次启动时恢复"
+            );
+        } else {
+            tracing::warn!(
+                in_flight = lifecycle.in_flight(),
+                "等待正在处理的任务超时，强制停止"
+            );
+        }
+    }
+    supervisor.shutdown().await;
+    // 后台任务都已停止，计数器不会再变化，保存长期计数器供下次启动时加载
+    save_metric_snapshots(&db, &config.persisted_metrics).await;
+    flush_tenant_stats(&app_state.tasks, &db).await;
+
+    // 滚动重启以专用退出码退出，便于编排系统识别
+    let exit_code = lifecycle.exit_code();
+    if exit_code != 0 && result.is_ok() {
+        tracing::info!(exit_code, "排空完成，按重启请求退出");
+        // 先释放日志守卫，确保缓冲中的日志写入文件
+        drop(_guard);
+        std::process::exit(exit_code);
+    }
+
+    result
+}
 
-    // 创建 axum 路由
-    let app = api_router(app_state);
+/// 数据库可用之后的启动步骤：按 `RUN_MIGRATIONS` 应用待执行的迁移，检查表结构，刷新死信队列大小，
+/// 加载上次停机时保存的计数器，恢复上次未处理完的任务。
+///
+/// 表结构不兼容时服务仍会启动（便于通过管理 API 执行迁移），但会被标记为未就绪，
+/// 而不是等到几小时后第一次写入时才失败。
+async fn prepare_database(
+    db: &Database,
+    queue: &dyn QueueBackend,
+    tasks: &TaskIndex,
+    config: &Config,
+) -> SchemaCheck {
+    let persisted_metrics = &config.persisted_metrics;
+    // 迁移失败时不退出，随后的表结构检查会将服务标记为未就绪
+    if config.run_migrations {
+        match db.run_migrations(false).await {
+            Ok(Some(applied)) if !applied.is_empty() => {
+                let versions: Vec<i64> = applied.iter().map(|m| m.version).collect();
+                tracing::info!(?versions, "已应用待执行的迁移");
+            }
+            Ok(_) => tracing::debug!("没有待执行的迁移"),
+            Err(e) => tracing::error!("执行迁移失败: {}", e),
+        }
+    }
+    let schema_check = check_schema(db).await;
+    if schema_check.ok {
+        tracing::info!(
+            version = ?schema_check.applied_version,
+            "表结构检查通过"
+        );
+        // 死信队列与隔离区大小的指标包含之前进程写入的任务
+        if let Err(e) = dlq::refresh_size(db).await {
+            tracing::warn!("读取死信队列大小失败: {}", e);
+        }
+        if let Err(e) = quarantine::refresh_size(db).await {
+            tracing::warn!("读取隔离区大小失败: {}", e);
+        }
+        // 长期计数器从上次停机时保存的值继续累加；加载失败时本次停机也不会保存，以免覆盖保存的值
+        if !persisted_metrics.is_empty() {
+            match db.metric_snapshots().await {
+                Ok(saved) => {
+                    let restored = metrics::restore(&saved, persisted_metrics);
+                    tracing::info!(count = restored, "已加载上次停机时保存的计数器");
+                }
+                Err(e) => tracing::warn!("加载保存的计数器失败，本次运行的计数器从零开始: {}", e),
+            }
+        }
+        // 按租户的统计从表中保存的累计值继续累计；加载失败时只影响本次运行的显示，写入的仍是增量
+        match db.tenant_stats().await {
+            Ok(saved) => tasks.load_tenant_totals(&saved),
+            Err(e) => tracing::warn!("加载按租户的任务统计失败: {}", e),
+        }
+    } else {
+        tracing::error!(
+            code = ?schema_check.code,
+            expected_version = ?schema_check.expected_version,
+            applied_version = ?schema_check.applied_version,
+            "表结构检查失败: {}",
+            schema_check.message
+        );
+    }
+    match queue.restore().await {
+        Ok(restored) => {
+            for task in &restored {
+                tasks.insert_queued(task.id, task.priority, task.tenant_or_default());
+            }
+            if !restored.is_empty() {
+                tracing::info!(count = restored.len(), "已恢复上次未处理完的任务");
+            }
+        }
+        // 表结构不兼容时无法恢复，服务仍然启动（未就绪），迁移后重启即可恢复
+        Err(e) => tracing::error!("恢复排队任务失败: {}", e),
+    }
+    schema_check
+}
 
-    // 绑定服务器地址并启动
-    let listener = TcpListener::bind(&config.server_address).await.unwrap();
-    tracing::info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal()) // 设置优雅停机
-        .await
-        .unwrap();
+/// 保存 `METRICS_PERSISTED` 中的计数器；启动时没有加载过保存的值时跳过。
+async fn save_metric_snapshots(db: &Database, persisted_metrics: &[String]) {
+    if persisted_metrics.is_empty() {
+        return;
+    }
+    let Some(counters) = metrics::snapshot(persisted_metrics) else {
+        tracing::warn!("启动时没有加载保存的计数器，不保存本次运行的计数器");
+        return;
+    };
+    match db.save_metric_snapshots(&counters).await {
+        Ok(()) => tracing::info!(count = counters.len(), "已保存计数器"),
+        Err(e) => tracing::error!("保存计数器失败: {}", e),
+    }
+}
 
+/// 将监听器任务的结果（JoinError 与 IO 错误）统一转换为 `AppError`。
+fn flatten_server_result(
+    joined: Result<std::io::Result<()>, tokio::task::JoinError>,
+) -> Result<(), AppError> {
+    joined
+        .map_err(anyhow::Error::from)?
+        .map_err(anyhow::Error::from)?;
     Ok(())
 }
 
@@ -83,4 +723,4 @@ async fn shutdown_signal() {
     }
 
     tracing::info!("signal received, starting graceful shutdown");
-}
\ No newline at end of file
+}
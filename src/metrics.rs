@@ -0,0 +1,242 @@
+use crate::queue::{PriorityClass, QueueHooks};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// 进程内的指标注册表。
+///
+/// 所有指标以“名称 + 标签”为键保存在全局注册表中，
+/// 通过 `render` 以 Prometheus 文本格式导出。
+/// 计数器只增不减；仪表 (gauge) 可以任意设置，内部以 `f64` 的位模式存储。
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<String, Arc<AtomicU64>>,
+    gauges: BTreeMap<String, Arc<AtomicU64>>,
+}
+
+/// 是否已经加载过上次停机时保存的计数器，加载之后才允许保存快照。
+static RESTORED: AtomicBool = AtomicBool::new(false);
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Registry::default()))
+}
+
+/// 一个单调递增的计数器。
+#[derive(Clone)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    /// 计数器加一。
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// 计数器增加 `n`。
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+/// 一个可以任意设置的仪表。
+#[derive(Clone)]
+pub struct Gauge(Arc<AtomicU64>);
+
+impl Gauge {
+    /// 设置仪表的值。
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// 生成注册表中使用的键，例如 `queue_pending{priority="high"}`。
+fn metric_key(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("{}{{{}}}", name, labels.join(","))
+}
+
+/// 获取（必要时创建）一个无标签的计数器。
+pub fn counter(name: &str) -> Counter {
+    counter_with_labels(name, &[])
+}
+
+/// 获取（必要时创建）一个带标签的计数器。
+pub fn counter_with_labels(name: &str, labels: &[(&str, &str)]) -> Counter {
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    let cell = registry
+        .counters
+        .entry(metric_key(name, labels))
+        .or_default()
+        .clone();
+    Counter(cell)
+}
+
+/// 获取（必要时创建）一个无标签的仪表。
+pub fn gauge(name: &str) -> Gauge {
+    gauge_with_labels(name, &[])
+}
+
+/// 获取（必要时创建）一个带标签的仪表。
+pub fn gauge_with_labels(name: &str, labels: &[(&str, &str)]) -> Gauge {
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    let cell = registry
+        .gauges
+        .entry(metric_key(name, labels))
+        .or_insert_with(|| Arc::new(AtomicU64::new(0f64.to_bits())))
+        .clone();
+    Gauge(cell)
+}
+
+/// 将队列的入队、出队与丢弃记录为指标的 `QueueHooks`。
+///
+/// 入队与出队按优先级档位（`class` 标签）计数，排队时间累计到 `queue_wait_us_total`，
+/// 与出队计数相除即为平均排队时间。
+pub struct QueueMetrics;
+
+impl QueueHooks for QueueMetrics {
+    fn on_push(&self, _id: &Uuid, priority: u8) {
+        let class = PriorityClass::from_priority(priority);
+        counter_with_labels("queue_tasks_pushed_total", &[("class", class.as_str())]).inc();
+    }
+
+    fn on_pop(&self, _id: &Uuid, priority: u8, waited: Duration) {
+        let class = PriorityClass::from_priority(priority);
+        counter_with_labels("queue_tasks_popped_total", &[("class", class.as_str())]).inc();
+        counter("queue_wait_us_total").add(waited.as_micros() as u64);
+    }
+
+    fn on_drop(&self, _id: &Uuid, reason: &'static str) {
+        if reason == "decompression" {
+            counter("queue_decompression_failures_total").inc();
+        }
+        counter_with_labels("queue_tasks_dropped_total", &[("reason", reason)]).inc();
+    }
+}
+
+/// 指标键中的指标名称，例如 `queue_pending{priority="high"}` 的名称为 `queue_pending`。
+fn metric_name(key: &str) -> &str {
+    key.split('{').next().unwrap_or(key)
+}
+
+/// 将上次停机时保存的计数器值累加到名称在 `names` 中的计数器上，返回加载的计数器数。
+///
+/// 只在启动时调用一次，重复调用时不再累加。
+pub fn restore(saved: &[(String, u64)], names: &[String]) -> usize {
+    if RESTORED.swap(true, Ordering::SeqCst) {
+        return 0;
+    }
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    let mut restored = 0;
+    for (key, value) in saved {
+        if names.iter().any(|name| name == metric_name(key)) {
+            registry
+                .counters
+                .entry(key.clone())
+                .or_default()
+                .fetch_add(*value, Ordering::Relaxed);
+            restored += 1;
+        }
+    }
+    restored
+}
+
+/// 名称在 `names` 中的计数器的当前值。
+///
+/// 还没有加载过保存的计数器时（例如启动时数据库不可用）返回 `None`，
+/// 避免用从零开始计数的值覆盖数据库中保存的累计值。
+pub fn snapshot(names: &[String]) -> Option<Vec<(String, u64)>> {
+    if !RESTORED.load(Ordering::SeqCst) {
+        return None;
+    }
+    let registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    Some(
+        registry
+            .counters
+            .iter()
+            .filter(|(key, _)| names.iter().any(|name| name == metric_name(key)))
+            .map(|(key, value)| (key.clone(), value.load(Ordering::Relaxed)))
+            .collect(),
+    )
+}
+
+/// 以 Prometheus 文本格式导出所有指标。
+pub fn render() -> String {
+    let registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    let mut out = String::new();
+    let mut last_name = String::new();
+
+    let counters = registry
+        .counters
+        .iter()
+        .map(|(k, v)| (k, "counter", v.load(Ordering::Relaxed) as f64));
+    let gauges = registry
+        .gauges
+        .iter()
+        .map(|(k, v)| (k, "gauge", f64::from_bits(v.load(Ordering::Relaxed))));
+
+    for (key, kind, value) in counters.chain(gauges) {
+        // 同名指标（不同标签）只输出一次 TYPE 行
+        let name = metric_name(key);
+        if name != last_name {
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            last_name = name.to_string();
+        }
+        let _ = writeln!(out, "{} {}", key, value);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试计数器与仪表的读写以及 Prometheus 文本输出。
+    #[test]
+    fn test_counter_gauge_render() {
+        let counter = counter_with_labels("test_metrics_total", &[("kind", "a")]);
+        counter.inc();
+        counter.add(2);
+        gauge("test_metrics_gauge").set(1.5);
+
+        let text = render();
+        assert!(text.contains("# TYPE test_metrics_total counter"));
+        assert!(text.contains("test_metrics_total{kind=\"a\"} 3"));
+        assert!(text.contains("test_metrics_gauge 1.5"));
+    }
+
+    /// 测试加载之前不能保存快照，加载后计数器从保存的值继续累加，且只加载一次。
+    #[test]
+    fn test_snapshot_restore() {
+        let names = vec!["test_persisted_total".to_string()];
+        assert!(snapshot(&names).is_none());
+
+        counter_with_labels("test_persisted_total", &[("status", "ok")]).inc();
+        let saved = vec![
+            ("test_persisted_total{status=\"ok\"}".to_string(), 41),
+            ("test_persisted_total{status=\"failed\"}".to_string(), 7),
+            ("test_not_persisted_total".to_string(), 5),
+        ];
+        assert_eq!(restore(&saved, &names), 2);
+        assert_eq!(restore(&saved, &names), 0);
+
+        let mut values = snapshot(&names).unwrap();
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                ("test_persisted_total{status=\"failed\"}".to_string(), 7),
+                ("test_persisted_total{status=\"ok\"}".to_string(), 42),
+            ]
+        );
+        assert!(!render().contains("test_not_persisted_total"));
+    }
+}
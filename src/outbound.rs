@@ -0,0 +1,188 @@
+use crate::error::AppError;
+use reqwest::Url;
+use std::time::Duration;
+
+/// 出站请求的默认超时。
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 允许的代理协议。
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+/// 针对某个目标主机的代理覆盖规则。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyOverride {
+    /// 目标主机；同时匹配其所有子域名。
+    pub host: String,
+    /// 使用的代理，`None` 表示直连。
+    pub proxy: Option<Url>,
+}
+
+/// 出站 HTTP 请求的代理设置。
+///
+/// 选择代理的顺序：按目标主机的覆盖规则 → `no_proxy` 列表（直连）→ 按协议选择的代理 → `all`。
+#[derive(Debug, Clone, Default)]
+pub struct ProxySettings {
+    /// 用于 `http://` 目标的代理。
+    pub http: Option<Url>,
+    /// 用于 `https://` 目标的代理。
+    pub https: Option<Url>,
+    /// 未单独配置时所有目标使用的代理，可以是 SOCKS5。
+    pub all: Option<Url>,
+    /// 不经过代理的主机，同时匹配其所有子域名；`*` 表示全部直连。
+    pub no_proxy: Vec<String>,
+    /// 按目标主机的覆盖规则，优先级最高。
+    pub overrides: Vec<ProxyOverride>,
+}
+
+impl ProxySettings {
+    /// 为目标地址选择代理，`None` 表示直连。
+    pub fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        if let Some(rule) = self.overrides.iter().find(|r| host_matches(&host, &r.host)) {
+            return rule.proxy.clone();
+        }
+        if self
+            .no_proxy
+            .iter()
+            .any(|pattern| pattern == "*" || host_matches(&host, pattern))
+        {
+            return None;
+        }
+        let by_scheme = match url.scheme() {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        };
+        by_scheme.or(self.all.as_ref()).cloned()
+    }
+
+    /// 是否配置了任何代理。
+    pub fn is_enabled(&self) -> bool {
+        self.http.is_some()
+            || self.https.is_some()
+            || self.all.is_some()
+            || self.overrides.iter().any(|r| r.proxy.is_some())
+    }
+}
+
+/// `host` 等于 `pattern` 或是其子域名。`pattern` 可以带前导的 `.`。
+fn host_matches(host: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim_start_matches('.');
+    host == pattern
+        || host
+            .strip_suffix(pattern)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// 解析代理地址，只接受 HTTP/HTTPS/SOCKS5 代理。
+pub fn parse_proxy_url(value: &str) -> Result<Url, String> {
+    let url = Url::parse(value.trim()).map_err(|e| format!("{}: {}", value, e))?;
+    if !PROXY_SCHEMES.contains(&url.scheme()) {
+        return Err(format!(
+            "{}: 不支持的代理协议 {}（可选 {}）",
+            value,
+            url.scheme(),
+            PROXY_SCHEMES.join("/")
+        ));
+    }
+    Ok(url)
+}
+
+/// 解析按目标主机的覆盖规则，格式为逗号分隔的 `host=proxy`，`proxy` 为 `direct` 时表示直连。
+pub fn parse_overrides(list: &str) -> Result<Vec<ProxyOverride>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|rule| {
+            let (host, proxy) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("{}: 格式应为 host=proxy", rule))?;
+            let proxy = match proxy.trim() {
+                "direct" => None,
+                other => Some(parse_proxy_url(other)?),
+            };
+            Ok(ProxyOverride {
+                host: host.trim().trim_start_matches('.').to_ascii_lowercase(),
+                proxy,
+            })
+        })
+        .collect()
+}
+
+/// 创建所有出站请求（webhook、回调、`http_request` 任务等）共用的 HTTP 客户端。
+///
+/// 客户端内部维护连接池，应在启动时创建一次后克隆使用。
+pub fn build_client(settings: &ProxySettings) -> Result<reqwest::Client, AppError> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(DEFAULT_TIMEOUT)
+        // 代理完全由配置决定，不读取 HTTP_PROXY 等系统环境变量
+        .no_proxy();
+    if settings.is_enabled() {
+        let settings = settings.clone();
+        builder = builder.proxy(reqwest::Proxy::custom(move |url| settings.proxy_for(url)));
+    }
+    builder
+        .build()
+        .map_err(|e| AppError::Config(format!("无法创建出站 HTTP 客户端: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    /// 测试代理的选择顺序：覆盖规则、直连列表、按协议、兜底。
+    #[test]
+    fn test_proxy_selection() {
+        let settings = ProxySettings {
+            http: Some(url("http://http-proxy:3128")),
+            https: None,
+            all: Some(url("socks5://socks:1080")),
+            no_proxy: vec!["internal.example".to_string()],
+            overrides: parse_overrides(concat!(
+                "vendor.com=http://vendor-proxy:8080,",
+                "api.internal.example=socks5h://jump:1080,",
+                "direct.com=direct",
+            ))
+            .unwrap(),
+        };
+
+        assert_eq!(
+            settings.proxy_for(&url("http://example.com/")),
+            Some(url("http://http-proxy:3128"))
+        );
+        assert_eq!(
+            settings.proxy_for(&url("https://example.com/")),
+            Some(url("socks5://socks:1080"))
+        );
+        assert_eq!(
+            settings.proxy_for(&url("https://db.internal.example/")),
+            None
+        );
+        assert_eq!(
+            settings.proxy_for(&url("https://api.internal.example/")),
+            Some(url("socks5h://jump:1080"))
+        );
+        assert_eq!(
+            settings.proxy_for(&url("https://hooks.vendor.com/x")),
+            Some(url("http://vendor-proxy:8080"))
+        );
+        assert_eq!(settings.proxy_for(&url("https://direct.com/")), None);
+        // 只匹配完整的域名层级
+        assert_eq!(
+            settings.proxy_for(&url("https://notvendor.com/")),
+            Some(url("socks5://socks:1080"))
+        );
+    }
+
+    /// 测试无效的代理配置会被拒绝。
+    #[test]
+    fn test_parse_invalid_proxy() {
+        assert!(parse_proxy_url("ftp://proxy:21").is_err());
+        assert!(parse_overrides("vendor.com").is_err());
+        assert!(build_client(&ProxySettings::default()).is_ok());
+    }
+}
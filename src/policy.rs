@@ -0,0 +1,380 @@
+use crate::context::TaskContext;
+use crate::error::AppError;
+use crate::i18n::Message;
+use crate::metrics;
+use crate::status::TaskRecord;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// 缓存的决策数上限，超过时清空缓存。
+const MAX_CACHED_DECISIONS: usize = 10_000;
+
+/// 策略服务不可用（超时、连接失败、返回无法解析的结果）时的处理方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PolicyFailureMode {
+    /// 拒绝请求，返回 503（默认）。
+    #[default]
+    Closed,
+    /// 放行请求，只记录警告。
+    Open,
+}
+
+impl FromStr for PolicyFailureMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "closed" => Ok(PolicyFailureMode::Closed),
+            "open" => Ok(PolicyFailureMode::Open),
+            other => Err(format!("未知的失败处理方式: {}（可选 closed/open）", other)),
+        }
+    }
+}
+
+/// 外部策略服务的配置。
+#[derive(Debug, Clone)]
+pub struct PolicySettings {
+    /// 策略的决策地址，例如 OPA 的 `http://localhost:8181/v1/data/webserver/allow`。
+    pub url: String,
+    /// 单次决策请求的超时时间。
+    pub timeout: Duration,
+    /// 决策的缓存时间，为零时不缓存。
+    pub cache_ttl: Duration,
+    pub failure_mode: PolicyFailureMode,
+}
+
+/// 发起请求的调用方，由鉴权中间件在校验凭据后填入。
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Subject {
+    /// 以 API 令牌鉴权的调用方。
+    ApiToken {
+        id: Uuid,
+        name: String,
+        scope: &'static str,
+        /// 令牌绑定的租户。
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tenant: Option<String>,
+    },
+    /// 以 JWT 鉴权的调用方。
+    Jwt {
+        sub: Option<String>,
+        iss: Option<String>,
+        scope: String,
+        /// `tenant` 声明中的租户。
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tenant: Option<String>,
+    },
+}
+
+impl Subject {
+    /// 凭据绑定的租户，没有绑定时返回 `None`。
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            Subject::ApiToken { tenant, .. } | Subject::Jwt { tenant, .. } => tenant.as_deref(),
+        }
+    }
+}
+
+/// 请求涉及的任务：查询类接口来自状态索引中的记录，提交类接口来自请求体。
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TaskMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub task_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<&'static str>,
+}
+
+impl TaskMetadata {
+    /// 状态索引中的任务记录。
+    pub fn from_record(record: &TaskRecord) -> Self {
+        Self {
+            id: Some(record.id),
+            task_type: None,
+            priority: Some(record.priority),
+            tenant: Some(record.tenant.clone()),
+            status: Some(record.status.as_str()),
+        }
+    }
+}
+
+/// 发送给策略服务的输入（OPA 请求体中的 `input`）。
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyInput {
+    pub method: String,
+    /// 请求的完整路径。
+    pub path: String,
+    /// 匹配到的路由模板，例如 `/tasks/:id`。
+    pub route: Option<String>,
+    /// 请求声明的租户（`X-Tenant-ID`），请求头不合法时为 `None`。
+    pub tenant: Option<String>,
+    /// 未启用鉴权时为 `None`。
+    pub subject: Option<Subject>,
+    pub task: Option<TaskMetadata>,
+}
+
+/// 策略服务的一次决策。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    pub allow: bool,
+    /// 策略给出的拒绝原因，原样返回给调用方。
+    pub reason: Option<String>,
+}
+
+impl Decision {
+    /// 解析 OPA Data API 的响应体。
+    ///
+    /// `result` 可以是布尔值，也可以是包含 `allow`（与可选的 `reason`）的对象；
+    /// 没有 `result`（策略未定义该规则）视为拒绝，其他形式视为策略服务出错。
+    fn parse(body: &Value) -> Result<Self, String> {
+        match body.get("result") {
+            None => Ok(Decision {
+                allow: false,
+                reason: None,
+            }),
+            Some(Value::Bool(allow)) => Ok(Decision {
+                allow: *allow,
+                reason: None,
+            }),
+            Some(Value::Object(result)) => {
+                let allow = result
+                    .get("allow")
+                    .and_then(Value::as_bool)
+                    .ok_or_else(|| "result.allow 不是布尔值".to_string())?;
+                let reason = result
+                    .get("reason")
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+                Ok(Decision { allow, reason })
+            }
+            Some(other) => Err(format!("无法识别的 result: {}", other)),
+        }
+    }
+}
+
+/// 缓存的决策：按输入的 JSON 索引的决策时间与决策。
+type DecisionCache = HashMap<String, (Instant, Decision)>;
+
+/// 通过外部策略服务（OPA 或兼容其 Data API 的 HTTP 服务）做授权决策。
+///
+/// 每个请求的输入以 `{"input": ...}` 的形式 POST 到 `POLICY_URL`，请求中按 `PROPAGATE_HEADERS` 选出的请求头随决策请求一起发出；
+/// 相同输入的决策在 `cache_ttl` 内直接使用缓存，策略服务出错的结果不缓存。
+#[derive(Clone)]
+pub struct PolicyEngine {
+    settings: PolicySettings,
+    http: reqwest::Client,
+    cache: Arc<Mutex<DecisionCache>>,
+}
+
+impl PolicyEngine {
+    pub fn new(settings: PolicySettings, http: reqwest::Client) -> Self {
+        Self {
+            settings,
+            http,
+            cache: Arc::default(),
+        }
+    }
+
+    /// 判断请求是否允许执行。
+    ///
+    /// 策略拒绝时返回 403；策略服务不可用时按 `failure_mode` 拒绝（503）或放行。
+    /// `context` 中的请求头不影响决策的缓存。
+    pub async fn authorize(
+        &self,
+        input: &PolicyInput,
+        context: &TaskContext,
+    ) -> Result<(), AppError> {
+        let key = serde_json::to_string(input).map_err(anyhow::Error::from)?;
+        let decision = match self.cached(&key) {
+            Some(decision) => {
+                metrics::counter("policy_cache_hits_total").inc();
+                decision
+            }
+            None => match self.evaluate(input, context).await {
+                Ok(decision) => {
+                    if !self.settings.cache_ttl.is_zero() {
+                        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+                        if cache.len() >= MAX_CACHED_DECISIONS {
+                            cache.clear();
+                        }
+                        cache.insert(key, (Instant::now(), decision.clone()));
+                    }
+                    decision
+                }
+                Err(e) => {
+                    metrics::counter("policy_errors_total").inc();
+                    return match self.settings.failure_mode {
+                        PolicyFailureMode::Closed => {
+                            tracing::error!("策略服务不可用，拒绝请求: {}", e);
+                            Err(AppError::Unavailable(Message::new("POLICY_UNAVAILABLE")))
+                        }
+                        PolicyFailureMode::Open => {
+                            tracing::warn!("策略服务不可用，按配置放行请求: {}", e);
+                            Ok(())
+                        }
+                    };
+                }
+            },
+        };
+        let label = if decision.allow { "allow" } else { "deny" };
+        metrics::counter_with_labels("policy_decisions_total", &[("decision", label)]).inc();
+        if decision.allow {
+            return Ok(());
+        }
+        Err(AppError::Forbidden(match decision.reason {
+            Some(reason) => Message::new("POLICY_DENIED_REASON").arg("reason", reason),
+            None => Message::new("POLICY_DENIED"),
+        }))
+    }
+
+    /// 向策略服务请求一次决策。
+    async fn evaluate(
+        &self,
+        input: &PolicyInput,
+        context: &TaskContext,
+    ) -> Result<Decision, String> {
+        let response = context
+            .apply(self.http.post(&self.settings.url))
+            .timeout(self.settings.timeout)
+            .json(&json!({ "input": input }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("策略服务返回 {}", status));
+        }
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        Decision::parse(&body)
+    }
+
+    /// 未过期的缓存决策。
+    fn cached(&self, key: &str) -> Option<Decision> {
+        let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < self.settings.cache_ttl)
+            .map(|(_, decision)| decision.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::HeaderMap, routing::post, Json, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 测试 OPA 响应体的解析。
+    #[test]
+    fn test_parse_decision() {
+        let allow = |allow| Decision {
+            allow,
+            reason: None,
+        };
+        assert_eq!(Decision::parse(&json!({ "result": true })), Ok(allow(true)));
+        assert_eq!(
+            Decision::parse(&json!({ "result": false })),
+            Ok(allow(false))
+        );
+        assert_eq!(Decision::parse(&json!({})), Ok(allow(false)));
+        assert_eq!(
+            Decision::parse(&json!({ "result": { "allow": false, "reason": "tenant mismatch" } })),
+            Ok(Decision {
+                allow: false,
+                reason: Some("tenant mismatch".to_string())
+            })
+        );
+        assert!(Decision::parse(&json!({ "result": { "reason": "x" } })).is_err());
+        assert!(Decision::parse(&json!({ "result": 1 })).is_err());
+        assert!("closed".parse::<PolicyFailureMode>().is_ok());
+        assert!("sometimes".parse::<PolicyFailureMode>().is_err());
+    }
+
+    fn input(tenant: &str) -> PolicyInput {
+        PolicyInput {
+            method: "POST".to_string(),
+            path: "/tasks".to_string(),
+            route: Some("/tasks".to_string()),
+            tenant: Some(tenant.to_string()),
+            subject: None,
+            task: Some(TaskMetadata {
+                task_type: Some("report".to_string()),
+                priority: Some(10),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// 测试向策略服务发送的输入、决策的缓存，以及策略服务不可用时的两种处理方式。
+    #[tokio::test]
+    async fn test_authorize() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        // 只允许 acme 租户提交任务
+        let app = Router::new().route(
+            "/v1/data/webserver/allow",
+            post(move |headers: HeaderMap, Json(body): Json<Value>| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    assert_eq!(headers["x-correlation-id"], "c-1");
+                    let input = &body["input"];
+                    assert_eq!(input["task"]["type"], "report");
+                    let allow = input["tenant"] == "acme";
+                    Json(json!({ "result": { "allow": allow, "reason": "only acme" } }))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let settings = PolicySettings {
+            url: format!("http://{}/v1/data/webserver/allow", addr),
+            timeout: Duration::from_secs(5),
+            cache_ttl: Duration::from_secs(60),
+            failure_mode: PolicyFailureMode::Closed,
+        };
+        let mut context = TaskContext::default();
+        context
+            .headers
+            .insert("x-correlation-id".to_string(), "c-1".to_string());
+        let engine = PolicyEngine::new(settings.clone(), reqwest::Client::new());
+        assert!(engine.authorize(&input("acme"), &context).await.is_ok());
+        assert!(engine.authorize(&input("acme"), &context).await.is_ok());
+        assert!(matches!(
+            engine.authorize(&input("other"), &context).await,
+            Err(AppError::Forbidden(_))
+        ));
+        // 相同输入的第二次决策来自缓存
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let unreachable = PolicySettings {
+            url: "http://127.0.0.1:1/v1/data/webserver/allow".to_string(),
+            ..settings
+        };
+        let closed = PolicyEngine::new(unreachable.clone(), reqwest::Client::new());
+        assert!(matches!(
+            closed.authorize(&input("acme"), &context).await,
+            Err(AppError::Unavailable(_))
+        ));
+        let open = PolicyEngine::new(
+            PolicySettings {
+                failure_mode: PolicyFailureMode::Open,
+                ..unreachable
+            },
+            reqwest::Client::new(),
+        );
+        assert!(open.authorize(&input("acme"), &context).await.is_ok());
+    }
+}
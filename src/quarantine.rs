@@ -0,0 +1,358 @@
+use crate::context::TaskContext;
+use crate::db::Database;
+use crate::metrics;
+use crate::queue::{Task, TaskKind};
+use crate::validation::FieldError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::Error as SqlxError;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// 任务被隔离的原因。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum QuarantineReason {
+    /// 载荷中的字符串包含 `QUARANTINE_PATTERNS` 中的内容。
+    Content { pattern: String, field: String },
+    /// `PAYLOAD_SCHEMA_MODE=lenient` 时未通过 JSON Schema 校验的字段。
+    Schema {
+        field: String,
+        code: String,
+        error: String,
+    },
+    /// 匹配了 `QUARANTINE_RULES` 中的一条可疑规则。
+    Rule { rule: String },
+}
+
+impl QuarantineReason {
+    /// 原因的类别，用作指标标签。
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QuarantineReason::Content { .. } => "content",
+            QuarantineReason::Schema { .. } => "schema",
+            QuarantineReason::Rule { .. } => "rule",
+        }
+    }
+
+    /// 宽松模式下的 Schema 校验错误，消息以默认语言保存，供运维人员审核。
+    pub fn schema(error: &FieldError) -> Self {
+        QuarantineReason::Schema {
+            field: error.field.clone(),
+            code: error.message.code().to_string(),
+            error: error.message.to_string(),
+        }
+    }
+}
+
+/// 可疑规则期望的字段值。
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expected {
+    /// 字段存在即匹配。
+    Present,
+    /// 字段的值等于给定值。
+    Exact(String),
+    /// 字段的值以给定前缀开头（规则中以 `*` 结尾）。
+    Prefix(String),
+}
+
+/// 一条可疑规则：`字段路径` 或 `字段路径=值`，值以 `*` 结尾时按前缀匹配。
+///
+/// 字段路径相对于载荷，以 `.` 分隔，例如 `options.callback_url=http://*`。
+/// 字符串字段与值直接比较，其他类型的字段按 JSON 文本比较（例如 `dry_run=false`）。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuspicionRule {
+    /// 配置中的原文，记录在隔离原因中。
+    source: String,
+    path: Vec<String>,
+    expected: Expected,
+}
+
+impl SuspicionRule {
+    /// 解析一条规则。
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let rule = rule.trim();
+        let (path, expected) = match rule.split_once('=') {
+            Some((path, value)) => match value.strip_suffix('*') {
+                Some(prefix) => (path, Expected::Prefix(prefix.to_string())),
+                None => (path, Expected::Exact(value.to_string())),
+            },
+            None => (rule, Expected::Present),
+        };
+        let path: Vec<String> = path.trim().split('.').map(str::to_string).collect();
+        if path.iter().any(String::is_empty) {
+            return Err(format!("无效的可疑规则: {}", rule));
+        }
+        Ok(Self {
+            source: rule.to_string(),
+            path,
+            expected,
+        })
+    }
+
+    /// 载荷是否匹配该规则。
+    pub fn matches(&self, payload: &Value) -> bool {
+        let Some(value) = self
+            .path
+            .iter()
+            .try_fold(payload, |value, key| value.get(key))
+        else {
+            return false;
+        };
+        let text = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        match &self.expected {
+            Expected::Present => true,
+            Expected::Exact(expected) => text == *expected,
+            Expected::Prefix(prefix) => text.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// 提交时的隔离检查，配置在 `QUARANTINE_PATTERNS` 与 `QUARANTINE_RULES` 中。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuarantineSettings {
+    /// 小写的内容模式，在载荷的所有字符串值与键中查找（不区分大小写）。
+    patterns: Vec<String>,
+    rules: Vec<SuspicionRule>,
+}
+
+impl QuarantineSettings {
+    /// 解析逗号分隔的内容模式与可疑规则。
+    pub fn parse(patterns: &str, rules: &str) -> Result<Self, String> {
+        let split = |list: &str| -> Vec<String> {
+            list.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        Ok(Self {
+            patterns: split(patterns)
+                .into_iter()
+                .map(|p| p.to_lowercase())
+                .collect(),
+            rules: split(rules)
+                .iter()
+                .map(|rule| SuspicionRule::parse(rule))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// 扫描载荷，返回所有隔离原因；每个内容模式只报告第一个匹配的字段。
+    pub fn scan(&self, payload: &Value) -> Vec<QuarantineReason> {
+        let mut reasons = Vec::new();
+        for pattern in &self.patterns {
+            if let Some(field) = find_pattern(payload, pattern, "payload") {
+                reasons.push(QuarantineReason::Content {
+                    pattern: pattern.clone(),
+                    field,
+                });
+            }
+        }
+        reasons.extend(
+            self.rules
+                .iter()
+                .filter(|rule| rule.matches(payload))
+                .map(|rule| QuarantineReason::Rule {
+                    rule: rule.source.clone(),
+                }),
+        );
+        reasons
+    }
+}
+
+/// 在值及其所有子节点的字符串与对象键中查找小写的模式，返回第一个匹配的字段路径。
+fn find_pattern(value: &Value, pattern: &str, path: &str) -> Option<String> {
+    match value {
+        Value::String(s) => s.to_lowercase().contains(pattern).then(|| path.to_string()),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .find_map(|(i, item)| find_pattern(item, pattern, &format!("{}[{}]", path, i))),
+        Value::Object(fields) => fields.iter().find_map(|(key, field)| {
+            let path = format!("{}.{}", path, key);
+            if key.to_lowercase().contains(pattern) {
+                Some(path)
+            } else {
+                find_pattern(field, pattern, &path)
+            }
+        }),
+        _ => None,
+    }
+}
+
+/// 隔离区中的一个任务：提交时被拦下，保存在 `quarantined_tasks` 表中，不会被调度。
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantinedTask {
+    pub id: Uuid,
+    /// 提交任务的租户。
+    pub tenant: String,
+    pub payload: Value,
+    pub priority: u8,
+    pub run_at: Option<DateTime<Utc>>,
+    /// 提交时捕获的请求头，放行后随任务恢复。
+    pub context: TaskContext,
+    pub reasons: Vec<QuarantineReason>,
+    pub quarantined_at: DateTime<Utc>,
+    /// 提交时指定的执行方式，放行后随任务恢复。
+    pub kind: TaskKind,
+}
+
+impl QuarantinedTask {
+    /// 放行时入队的任务：保留提交时的 ID、优先级、开始时刻与请求上下文。
+    pub fn to_task(&self) -> Task {
+        Task {
+            id: self.id,
+            payload: Arc::new(self.payload.clone()),
+            priority: self.priority,
+            retry_count: 0,
+            run_at: self.run_at,
+            context: self.context.clone(),
+            kind: self.kind.clone(),
+            depends_on: Default::default(),
+            tenant: Some(self.tenant.clone()),
+        }
+    }
+}
+
+/// 将任务写入隔离区，按原因类别计入指标并更新隔离区大小。
+pub async fn hold(
+    db: &Database,
+    task: &Task,
+    tenant: &str,
+    reasons: Vec<QuarantineReason>,
+) -> Result<(), SqlxError> {
+    let kinds: BTreeSet<&'static str> = reasons.iter().map(QuarantineReason::kind).collect();
+    let held = QuarantinedTask {
+        id: task.id,
+        tenant: tenant.to_string(),
+        payload: task.payload.as_ref().clone(),
+        priority: task.priority,
+        run_at: task.run_at,
+        context: task.context.clone(),
+        reasons,
+        quarantined_at: Utc::now(),
+        kind: task.kind.clone(),
+    };
+    db.insert_quarantined_task(&held).await?;
+    for kind in kinds {
+        metrics::counter_with_labels("tasks_quarantined_total", &[("reason", kind)]).inc();
+    }
+    refresh_size(db).await
+}
+
+/// 从隔离区取出一个任务（删除其记录），任务不存在或已被并发取出时返回 `None`。
+pub async fn take(db: &Database, id: &Uuid) -> Result<Option<QuarantinedTask>, SqlxError> {
+    let held = db.take_quarantined_task(id).await?;
+    if held.is_some() {
+        refresh_size(db).await?;
+    }
+    Ok(held)
+}
+
+/// 按数据库中的记录数更新 `quarantine_size` 指标；启动时调用一次，使指标包含之前进程写入的任务。
+pub async fn refresh_size(db: &Database) -> Result<(), SqlxError> {
+    let size = db.quarantined_task_count().await?;
+    metrics::gauge("quarantine_size").set(size as f64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Message;
+    use serde_json::json;
+
+    /// 测试内容模式与可疑规则的解析和匹配。
+    #[test]
+    fn test_scan() {
+        let settings = QuarantineSettings::parse(
+            "<script, DROP TABLE",
+            "debug, options.callback_url=http://*, dry_run=false",
+        )
+        .unwrap();
+        assert!(QuarantineSettings::parse("", "options..url").is_err());
+        assert!(QuarantineSettings::default()
+            .scan(&json!({ "debug": true }))
+            .is_empty());
+
+        let payload = json!({
+            "type": "report",
+            "items": [{ "note": "ok" }, { "note": "x<SCRIPT>alert(1)" }],
+            "options": { "callback_url": "http://example.com/hook" },
+            "dry_run": false,
+        });
+        assert_eq!(
+            settings.scan(&payload),
+            vec![
+                QuarantineReason::Content {
+                    pattern: "<script".to_string(),
+                    field: "payload.items[1].note".to_string(),
+                },
+                QuarantineReason::Rule {
+                    rule: "options.callback_url=http://*".to_string(),
+                },
+                QuarantineReason::Rule {
+                    rule: "dry_run=false".to_string(),
+                },
+            ]
+        );
+
+        let clean = json!({
+            "type": "report",
+            "options": { "callback_url": "https://example.com/hook" },
+            "dry_run": "true",
+        });
+        assert!(settings.scan(&clean).is_empty());
+        let key = json!({ "drop table users": 1 });
+        assert_eq!(settings.scan(&key)[0].kind(), "content");
+    }
+
+    /// 测试任务写入隔离区、列出与取出。
+    #[tokio::test]
+    async fn test_hold_and_take() {
+        let db = crate::db::test_database().await;
+        let mut context = TaskContext::default();
+        context
+            .headers
+            .insert("x-tenant-id".to_string(), "acme".to_string());
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "type": "report" }).into(),
+            priority: 40,
+            retry_count: 0,
+            run_at: Some(Utc::now()),
+            context,
+            kind: TaskKind::Slow,
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let error = FieldError {
+            field: "payload.type".to_string(),
+            message: Message::new("SCHEMA_TYPE").arg("expected", "string"),
+        };
+        hold(&db, &task, "acme", vec![QuarantineReason::schema(&error)])
+            .await
+            .unwrap();
+
+        let held = db.quarantined_tasks(10).await.unwrap();
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].tenant, "acme");
+        assert_eq!(held[0].reasons[0].kind(), "schema");
+        assert_eq!(held[0].context, task.context);
+        assert_eq!(db.quarantined_task_count().await.unwrap(), 1);
+
+        let released = take(&db, &task.id).await.unwrap().unwrap().to_task();
+        assert_eq!(released.id, task.id);
+        assert_eq!(released.priority, 40);
+        assert_eq!(released.context, task.context);
+        assert_eq!(released.kind, TaskKind::Slow);
+        assert!(take(&db, &task.id).await.unwrap().is_none());
+        assert_eq!(db.quarantined_task_count().await.unwrap(), 0);
+    }
+}
@@ -1,21 +1,140 @@
+use crate::context::TaskContext;
+use crate::db::Database;
+use crate::i18n::Message;
+use crate::metrics;
+use crate::web::DEFAULT_TENANT;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
-use tokio::sync::Mutex;
+use std::collections::{BinaryHeap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Notify};
 use uuid::Uuid;
 
+/// 未通过 `with_claims` 设置时，认领持久化记录使用的实例标识。
+const DEFAULT_CLAIM_OWNER: &str = "local";
+/// 未通过 `with_claims` 设置时的认领租约时长。
+const DEFAULT_CLAIM_LEASE: Duration = Duration::from_secs(60);
+/// 队列已满时 `QueueError::Full` 默认建议的重试等待时间，调用方可以根据处理速度给出更准确的值。
+pub(crate) const FULL_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// 入队时如何对待容量上限。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OnFull {
+    /// 等待其他任务出队腾出名额。
+    Wait,
+    /// 立即返回 `QueueError::Full`。
+    Fail,
+    /// 不受容量限制，用于已经被接收过的任务。
+    Ignore,
+}
+
+/// 自定义执行方式名称的最大长度。
+pub const MAX_TASK_KIND_LEN: usize = 64;
+
+/// 任务的执行方式，提交任务时通过 `kind` 字段指定，与优先级无关。
+///
+/// 序列化为字符串：`auto`、`quick`、`slow`，其他名称为 `Custom`。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TaskKind {
+    /// 按任务类型的历史耗时与 `SLOW_TASK_OVERRIDES` 自动区分快慢，样本不足时按快速任务处理。
+    #[default]
+    Auto,
+    /// 快速任务：占用一个工作者处理。
+    Quick,
+    /// 慢速任务：在独立的 Tokio 任务中处理，受慢速任务的预算与并发上限约束。
+    Slow,
+    /// 交给以该名称注册的处理器处理（取代载荷中的 `type`），快慢按该名称自动分类。
+    Custom(String),
+}
+
+impl TaskKind {
+    pub fn is_auto(&self) -> bool {
+        *self == TaskKind::Auto
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            TaskKind::Auto => "auto",
+            TaskKind::Quick => "quick",
+            TaskKind::Slow => "slow",
+            TaskKind::Custom(name) => name,
+        }
+    }
+}
+
+impl FromStr for TaskKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "auto" => Ok(TaskKind::Auto),
+            "quick" => Ok(TaskKind::Quick),
+            "slow" => Ok(TaskKind::Slow),
+            "" => Err("任务的执行方式不能为空".to_string()),
+            name if name.len() > MAX_TASK_KIND_LEN => Err(format!(
+                "任务的执行方式不能超过 {} 个字节",
+                MAX_TASK_KIND_LEN
+            )),
+            name => Ok(TaskKind::Custom(name.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for TaskKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TaskKind> for String {
+    fn from(kind: TaskKind) -> Self {
+        kind.as_str().to_string()
+    }
+}
+
 /// 表示一个待处理的任务。
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Task {
     /// 任务的唯一标识符。
     pub id: Uuid,
     /// 任务的有效载荷，可以是任意 JSON 数据。
-    pub payload: Value,
+    /// 使用 `Arc` 共享存储，重试或在队列与 handler 之间传递任务时只复制指针，不复制载荷内容。
+    pub payload: Arc<Value>,
     /// 任务的优先级，数值越大，优先级越高。
     pub priority: u8,
     /// 任务的重试次数。
     pub retry_count: u8,
+    /// 任务最早可以开始处理的时刻；为 `None` 或已经过去时立即可以处理。
+    #[serde(default)]
+    pub run_at: Option<DateTime<Utc>>,
+    /// 提交时捕获的请求头（`PROPAGATE_HEADERS`），处理期间恢复。
+    #[serde(default, skip_serializing_if = "TaskContext::is_empty")]
+    pub context: TaskContext,
+    /// 执行方式，决定任务按快速还是慢速任务处理。
+    #[serde(default, skip_serializing_if = "TaskKind::is_auto")]
+    pub kind: TaskKind,
+    /// 通过 `POST /tasks/transaction` 提交时指定的依赖任务，它们全部成功之后才会开始处理。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<Uuid>,
+    /// 提交任务的租户，随任务持久化，重启或被其他实例接管后据此恢复任务的归属；`None` 表示默认租户。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl Task {
+    /// 提交任务的租户，没有记录租户的任务归入默认租户。
+    pub fn tenant_or_default(&self) -> &str {
+        self.tenant.as_deref().unwrap_or(DEFAULT_TENANT)
+    }
 }
 
 // 为 `Task` 实现 `PartialEq` trait，以便能够比较两个任务是否相等。
@@ -36,40 +155,1373 @@ impl PartialOrd for Task {
     }
 }
 
-// 为 `Task` 实现 `Ord` trait，以定义任务之间的全序关系。
-// `BinaryHeap` 使用这个实现来确定元素的顺序，从而实现最大堆（优先级最高的在顶部）。
+// 为 `Task` 实现 `Ord` trait，以定义任务之间的全序关系（优先级最高的最大）。
+// 队列中同优先级任务的先后由入队序号决定（见 `QueueEntry`），序号只在队列内有意义，不保存在 `Task` 中。
 impl Ord for Task {
     fn cmp(&self, other: &Self) -> Ordering {
         self.priority.cmp(&other.priority)
     }
 }
 
+/// 优先级分档，用于按档位设置阈值和统计指标。
+///
+/// - `Low`: 0–49
+/// - `Normal`: 50–199
+/// - `Critical`: 200–255
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriorityClass {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl PriorityClass {
+    /// 所有档位，按优先级从低到高排列。
+    pub const ALL: [PriorityClass; 3] = [
+        PriorityClass::Low,
+        PriorityClass::Normal,
+        PriorityClass::Critical,
+    ];
+
+    /// 根据优先级数值确定所属档位。
+    pub fn from_priority(priority: u8) -> Self {
+        match priority {
+            0..=49 => PriorityClass::Low,
+            50..=199 => PriorityClass::Normal,
+            _ => PriorityClass::Critical,
+        }
+    }
+
+    /// 档位名称，用于指标标签和日志。
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriorityClass::Low => "low",
+            PriorityClass::Normal => "normal",
+            PriorityClass::Critical => "critical",
+        }
+    }
+}
+
+/// 各优先级档位允许的最长排队时间，超过即视为“饥饿”。
+#[derive(Debug, Clone, Copy)]
+pub struct StarvationThresholds {
+    pub low: Duration,
+    pub normal: Duration,
+    pub critical: Duration,
+}
+
+impl Default for StarvationThresholds {
+    fn default() -> Self {
+        Self {
+            low: Duration::from_secs(600),
+            normal: Duration::from_secs(120),
+            critical: Duration::from_secs(10),
+        }
+    }
+}
+
+impl StarvationThresholds {
+    /// 返回指定档位的阈值。
+    pub fn for_class(&self, class: PriorityClass) -> Duration {
+        match class {
+            PriorityClass::Low => self.low,
+            PriorityClass::Normal => self.normal,
+            PriorityClass::Critical => self.critical,
+        }
+    }
+}
+
+/// 各优先级档位的容量上限，0 表示不限制。
+///
+/// 与总容量（`QUEUE_CAPACITY`）同时生效：大量低优先级任务占满自己的档位后只会被拒绝，
+/// 不会挤占关键任务所需的名额。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BandCapacities {
+    pub low: usize,
+    pub normal: usize,
+    pub critical: usize,
+}
+
+impl BandCapacities {
+    /// 返回指定档位的容量上限，0 表示不限制。
+    pub fn for_class(&self, class: PriorityClass) -> usize {
+        match class {
+            PriorityClass::Low => self.low,
+            PriorityClass::Normal => self.normal,
+            PriorityClass::Critical => self.critical,
+        }
+    }
+}
+
+/// 一个排队时间超过阈值的任务。
+#[derive(Debug, Clone, Serialize)]
+pub struct StarvingTask {
+    pub id: Uuid,
+    pub priority: u8,
+    pub class: PriorityClass,
+    /// 已排队的时间（毫秒）。
+    pub waited_ms: u64,
+    /// 所属档位的阈值（毫秒）。
+    pub threshold_ms: u64,
+}
+
+/// 队列重新分档的筛选条件，所有条件同时满足的任务才会被选中。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RebalanceFilter {
+    /// 只选中这些任务；为空时不按 ID 筛选。
+    #[serde(default)]
+    pub ids: Vec<Uuid>,
+    /// 只选中当前属于该档位的任务。
+    pub class: Option<PriorityClass>,
+    pub min_priority: Option<u8>,
+    pub max_priority: Option<u8>,
+    /// 载荷的顶层字段必须等于给定值，例如 `{"kind": "marketing_email"}`。
+    #[serde(default)]
+    pub payload: serde_json::Map<String, Value>,
+}
+
+impl RebalanceFilter {
+    /// 是否没有任何条件（会选中整个队列）。
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+            && self.class.is_none()
+            && self.min_priority.is_none()
+            && self.max_priority.is_none()
+            && self.payload.is_empty()
+    }
+
+    /// 只根据优先级和 ID 判断，不需要读取载荷。
+    pub(crate) fn matches_header(&self, id: &Uuid, priority: u8) -> bool {
+        (self.ids.is_empty() || self.ids.contains(id))
+            && self
+                .class
+                .is_none_or(|class| PriorityClass::from_priority(priority) == class)
+            && self.min_priority.is_none_or(|min| priority >= min)
+            && self.max_priority.is_none_or(|max| priority <= max)
+    }
+
+    pub(crate) fn matches_payload(&self, payload: &Value) -> bool {
+        self.payload
+            .iter()
+            .all(|(key, expected)| payload.get(key) == Some(expected))
+    }
+}
+
+/// 一次重新分档的结果。
+#[derive(Debug, Clone, Serialize)]
+pub struct RebalanceOutcome {
+    /// 满足筛选条件的任务数。
+    pub matched: usize,
+    /// 优先级实际发生变化的任务。
+    pub changed: Vec<Uuid>,
+}
+
+/// 队列操作的错误。
+///
+/// `is_retryable` 表示同样的操作稍后是否可能成功，HTTP 层据此告诉客户端是否应当重试，
+/// 调度器据此决定重新入队失败时是等待后再试还是直接放弃任务。
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum QueueError {
+    /// 队列已达到容量上限。
+    #[error("队列已满（容量 {capacity}），请稍后重试")]
+    Full {
+        capacity: usize,
+        /// 建议的重试等待时间。
+        retry_after: Duration,
+    },
+    /// 任务所属的优先级档位已达到该档位的容量上限。
+    #[error("{class:?} 档位的排队任务已满（容量 {capacity}），请稍后重试")]
+    BandFull {
+        class: PriorityClass,
+        capacity: usize,
+        /// 建议的重试等待时间。
+        retry_after: Duration,
+    },
+    /// 队列已关闭（服务正在停机或重启），不再接受新任务。
+    #[error("队列已关闭，服务正在停机或重启，请稍后重试")]
+    Closed,
+    /// 同一个任务 ID 已经在队列中。
+    #[error("任务 {0} 已在队列中")]
+    DuplicateKey(Uuid),
+    /// 任务不在队列中（不存在或已经开始处理）。
+    #[error("任务 {0} 不在队列中")]
+    NotFound(Uuid),
+    /// 任务载荷无法序列化或反序列化。
+    #[error("任务载荷序列化失败: {0}")]
+    Serialization(String),
+    /// 无法将任务写入持久化存储。
+    #[error("无法持久化任务: {0}")]
+    Storage(String),
+}
+
+impl QueueError {
+    /// 稍后重试同样的操作是否可能成功。
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            QueueError::Full { .. }
+                | QueueError::BandFull { .. }
+                | QueueError::Closed
+                | QueueError::Storage(_)
+        )
+    }
+
+    /// 建议的重试等待时间，没有估算时返回 `None`。
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            QueueError::Full { retry_after, .. } | QueueError::BandFull { retry_after, .. } => {
+                Some(*retry_after)
+            }
+            _ => None,
+        }
+    }
+
+    /// 稳定的错误码，返回给客户端用于区分错误类型。
+    pub fn code(&self) -> &'static str {
+        match self {
+            QueueError::Full { .. } => "QUEUE_FULL",
+            QueueError::BandFull { .. } => "QUEUE_BAND_FULL",
+            QueueError::Closed => "QUEUE_CLOSED",
+            QueueError::DuplicateKey(_) => "DUPLICATE_TASK",
+            QueueError::NotFound(_) => "TASK_NOT_QUEUED",
+            QueueError::Serialization(_) => "QUEUE_SERIALIZATION",
+            QueueError::Storage(_) => "QUEUE_STORAGE",
+        }
+    }
+
+    /// 返回给客户端的可本地化消息，模板以 `code` 为键。
+    pub fn message(&self) -> Message {
+        let message = Message::new(self.code());
+        match self {
+            QueueError::Full { capacity, .. } => message.arg("capacity", capacity),
+            QueueError::BandFull {
+                class, capacity, ..
+            } => message
+                .arg("class", class.as_str())
+                .arg("capacity", capacity),
+            QueueError::Closed => message,
+            QueueError::DuplicateKey(id) | QueueError::NotFound(id) => message.arg("id", id),
+            QueueError::Serialization(e) | QueueError::Storage(e) => message.arg("error", e),
+        }
+    }
+}
+
+/// 一个排队任务在队列中的位置。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct QueuePosition {
+    pub priority: u8,
+    /// 会先于它出队的任务数：优先级更高的任务，加上同优先级中更早入队的任务。
+    /// 尚未到执行时间的任务为当前优先级不低于它的就绪任务数。
+    pub tasks_ahead: usize,
+    /// 尚未到执行时间的任务的执行时间。
+    pub run_at: Option<DateTime<Utc>>,
+}
+
+/// 队列中任务载荷的压缩设置。
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionSettings {
+    /// 是否启用压缩。
+    pub enabled: bool,
+    /// 序列化后的载荷达到该字节数时才进行压缩，小载荷压缩得不偿失。
+    pub threshold_bytes: usize,
+    /// zstd 压缩级别。
+    pub level: i32,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_bytes: 4096,
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+/// 任务载荷在队列中的存储形式。
+enum StoredPayload {
+    /// 未压缩的原始 JSON，与 `Task` 共享同一份存储。
+    Plain(Arc<Value>),
+    /// zstd 压缩后的 JSON 字节。
+    Compressed(Vec<u8>),
+}
+
+/// 堆中实际保存的条目。
+///
+/// 先按 `priority` 排序，同优先级的按入队序号 `seq` 先进先出。
+struct QueueEntry {
+    id: Uuid,
+    priority: u8,
+    /// 入队序号，在队列锁内按入队顺序分配；重试或推迟后重新入队的任务获得新的序号。
+    seq: u64,
+    retry_count: u8,
+    payload: StoredPayload,
+    /// 入队时刻，用于计算排队时间；延迟任务为到期进入就绪堆的时刻。
+    enqueued_at: Instant,
+    run_at: Option<DateTime<Utc>>,
+    context: TaskContext,
+    kind: TaskKind,
+    depends_on: Vec<Uuid>,
+    tenant: Option<String>,
+}
+
+impl PartialEq for QueueEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueueEntry {}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // 序号较小（更早入队）的条目更大，在最大堆中先出队
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// 尚未到执行时间的条目，按执行时间排序，最早到期的位于堆顶。
+struct DelayedEntry {
+    run_at: DateTime<Utc>,
+    entry: QueueEntry,
+}
+
+impl PartialEq for DelayedEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.run_at == other.run_at
+    }
+}
+
+impl Eq for DelayedEntry {}
+
+impl PartialOrd for DelayedEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DelayedEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.run_at.cmp(&self.run_at)
+    }
+}
+
+/// 同一把锁保护的就绪堆、延迟堆与两者中所有任务的 ID，后者用于入队时检测重复任务。
+///
+/// 出队时先把到期的延迟条目移入就绪堆，再按优先级出队：
+/// 未到执行时间的任务无论优先级多高都不会被取出，到期之后与其他就绪任务按优先级竞争。
+#[derive(Default)]
+struct Entries {
+    heap: BinaryHeap<QueueEntry>,
+    delayed: BinaryHeap<DelayedEntry>,
+    ids: HashSet<Uuid>,
+    /// 各优先级档位（按 `PriorityClass::ALL` 的顺序）的排队任务数，包括延迟任务。
+    class_len: [usize; 3],
+    /// 下一个入队条目的序号。
+    next_seq: u64,
+}
+
+impl Entries {
+    /// 加入一个条目并分配入队序号，执行时间在未来的放入延迟堆。
+    ///
+    /// 延迟条目到期进入就绪堆时保留入队时的序号，与同优先级的其他任务仍按提交顺序出队。
+    fn push(&mut self, mut entry: QueueEntry) {
+        entry.seq = self.next_seq;
+        self.next_seq += 1;
+        self.ids.insert(entry.id);
+        self.class_len[class_index(entry.priority)] += 1;
+        match entry.run_at.filter(|run_at| *run_at > Utc::now()) {
+            Some(run_at) => self.delayed.push(DelayedEntry { run_at, entry }),
+            None => self.heap.push(entry),
+        }
+    }
+
+    /// 将已经到期的延迟条目移入就绪堆，排队时间从到期时刻算起。
+    fn promote_due(&mut self) {
+        let now = Utc::now();
+        while self.delayed.peek().is_some_and(|d| d.run_at <= now) {
+            if let Some(DelayedEntry { mut entry, .. }) = self.delayed.pop() {
+                entry.enqueued_at = Instant::now();
+                self.heap.push(entry);
+            }
+        }
+    }
+
+    /// 从就绪堆中取出优先级最高的条目。
+    fn pop_ready(&mut self) -> Option<QueueEntry> {
+        let entry = self.heap.pop()?;
+        self.ids.remove(&entry.id);
+        self.class_len[class_index(entry.priority)] -= 1;
+        Some(entry)
+    }
+
+    /// 取出一个条目而不论是否到期：先取就绪堆中优先级最高的，就绪堆为空时取最早到期的延迟条目。
+    fn pop_any(&mut self) -> Option<QueueEntry> {
+        if let Some(entry) = self.pop_ready() {
+            return Some(entry);
+        }
+        let DelayedEntry { entry, .. } = self.delayed.pop()?;
+        self.ids.remove(&entry.id);
+        self.class_len[class_index(entry.priority)] -= 1;
+        Some(entry)
+    }
+
+    /// 按条目的当前优先级重新统计各档位的任务数，用于批量修改优先级之后。
+    fn recount_classes(&mut self) {
+        let mut class_len = [0; 3];
+        let all = self
+            .heap
+            .iter()
+            .chain(self.delayed.iter().map(|d| &d.entry));
+        for entry in all {
+            class_len[class_index(entry.priority)] += 1;
+        }
+        self.class_len = class_len;
+    }
+
+    /// 就绪与延迟的条目总数。
+    fn len(&self) -> usize {
+        self.heap.len() + self.delayed.len()
+    }
+
+    /// 指定档位的排队任务数，包括延迟任务。
+    fn class_len(&self, class: PriorityClass) -> usize {
+        self.class_len[class as usize]
+    }
+}
+
+/// 按“超出阈值的倍数”从高到低排序，最多保留 `limit` 个。
+pub(crate) fn rank_starving(mut starving: Vec<StarvingTask>, limit: usize) -> Vec<StarvingTask> {
+    starving.sort_by(|a, b| {
+        let ra = a.waited_ms as f64 / a.threshold_ms.max(1) as f64;
+        let rb = b.waited_ms as f64 / b.threshold_ms.max(1) as f64;
+        rb.total_cmp(&ra)
+    });
+    starving.truncate(limit);
+    starving
+}
+
+/// 优先级所属档位在 `Entries::class_len` 中的下标。
+fn class_index(priority: u8) -> usize {
+    PriorityClass::from_priority(priority) as usize
+}
+
+/// 队列的观测回调，用于指标统计与测试，避免在调用方各处散落统计代码。
+///
+/// 回调在队列内部同步调用（入队时仍持有队列锁），实现应当足够轻量，不能阻塞或再次访问队列。
+pub trait QueueHooks: Send + Sync {
+    /// 任务进入队列，包括从持久化存储恢复或认领的任务。
+    fn on_push(&self, _id: &Uuid, _priority: u8) {}
+    /// 任务出队，`waited` 为任务在就绪堆中的排队时间。
+    fn on_pop(&self, _id: &Uuid, _priority: u8, _waited: Duration) {}
+    /// 任务无法交给调用方而被丢弃，`reason` 为丢弃原因（例如 `decompression`）。
+    fn on_drop(&self, _id: &Uuid, _reason: &'static str) {}
+}
+
+/// 调度器、HTTP 层与后台任务使用的队列操作，语义以 `PriorityQueue` 的同名方法为准。
+///
+/// 通过 `QUEUE_BACKEND` 选择实现：进程内的 `PriorityQueue`（默认），
+/// 或多个实例共享、进程重启后仍然保留任务的 `RedisQueue`。
+pub trait QueueBackend: Send + Sync {
+    /// 后端名称，用于日志和状态接口。
+    fn name(&self) -> &'static str;
+    /// 进程退出后排队的任务是否仍然保留（下次启动或其他实例会继续处理）。
+    fn is_durable(&self) -> bool;
+    /// 启动时恢复上次未处理完的任务。
+    fn restore(&self) -> BoxFuture<'_, Result<Vec<Task>, QueueError>>;
+    /// 续约本实例正在处理的任务，并接管失联实例留下的任务。
+    fn claim_orphans(&self, limit: i64) -> BoxFuture<'_, Result<Vec<Task>, QueueError>>;
+    /// 确认任务已处理结束。
+    fn ack<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, ()>;
+    /// 入队；队列已满时等待名额。
+    fn push(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>>;
+    /// 入队；队列已满时立即返回 `QueueError::Full`。
+    fn try_push(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>>;
+    /// 将一组任务作为整体入队：要么全部入队，要么一个也不入队；队列已满时立即返回错误。
+    fn try_push_all(&self, tasks: Vec<Task>) -> BoxFuture<'_, Result<(), QueueError>>;
+    /// 将已经被接收过的任务放回队列，不受容量限制。
+    fn reinsert(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>>;
+    /// 弹出一个任务，没有就绪任务时最多等待 `timeout`。
+    fn pop_wait(&self, timeout: Duration) -> BoxFuture<'_, Option<Task>>;
+    /// 关闭队列，返回关闭时仍在排队的任务数。
+    fn close(&self) -> BoxFuture<'_, usize>;
+    fn is_closed(&self) -> bool;
+    /// 排队任务数，包括尚未到执行时间的任务。
+    fn len(&self) -> BoxFuture<'_, usize>;
+    fn len_by_class(&self) -> BoxFuture<'_, [(PriorityClass, usize); 3]>;
+    fn check_band_capacity(&self, priority: u8) -> BoxFuture<'_, Result<(), QueueError>>;
+    fn count_at_or_above(&self, priority: u8) -> BoxFuture<'_, usize>;
+    fn position<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, Result<QueuePosition, QueueError>>;
+    fn starving<'a>(
+        &'a self,
+        thresholds: &'a StarvationThresholds,
+        limit: usize,
+    ) -> BoxFuture<'a, Vec<StarvingTask>>;
+    fn rebalance<'a>(
+        &'a self,
+        filter: &'a RebalanceFilter,
+        priority: u8,
+        dry_run: bool,
+    ) -> BoxFuture<'a, Result<RebalanceOutcome, QueueError>>;
+}
+
+/// 队列后端的类型。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueBackendKind {
+    /// 进程内的 `PriorityQueue`（默认），可以用数据库记录排队的任务。
+    #[default]
+    Local,
+    /// Redis 有序集合，多个实例共享一个队列。
+    Redis,
+}
+
+impl FromStr for QueueBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "local" | "memory" => Ok(QueueBackendKind::Local),
+            "redis" => Ok(QueueBackendKind::Redis),
+            other => Err(format!("未知的队列后端: {}（可选 local/redis）", other)),
+        }
+    }
+}
+
 /// 一个线程安全的异步优先级队列。
 /// 内部使用 `tokio::sync::Mutex` 包裹的 `std::collections::BinaryHeap` 实现。
+///
+/// 设置了 `run_at` 的任务在执行时间之前保存在单独的延迟堆中，不会被出队。
+///
+/// 启用压缩后，超过阈值的载荷在入队时被透明地压缩，出队时再解压，
+/// 调用方看到的始终是完整的 `Task`。
+///
+/// 停机时调用 `close` 封住队列：之后的入队返回 `QueueError::Closed`，已有的任务仍可出队。
+///
+/// 设置了持久化存储（`with_journal`）时，每个入队的任务都会先写入 `tasks_queue` 表，
+/// 直到调用方通过 `ack` 确认处理结束才删除；启动时用 `restore` 恢复上次未处理完的任务。
+/// 出队不会删除记录，处理到一半时进程退出的任务在重启后会被再次处理（至少一次）。
+///
+/// 多个实例共用一个数据库时，每条记录由写入或认领它的实例（`with_claims` 设置的 `owner`）持有，
+/// 实例定期续约（`claim_orphans`），其他实例只会认领无人认领或续约超过租约时长的记录，
+/// 因此同一个任务不会被两个存活的实例同时处理。
+///
+/// 通过 `with_hooks` 注册的 `QueueHooks` 在入队、出队与丢弃任务时被调用。
+///
+/// 通过 `with_capacity` 设置容量后，排队的任务（包括延迟任务）数达到容量时：
+/// `push` 等待其他任务出队腾出名额，`try_push` 立即返回 `QueueError::Full`；
+/// 通过 `with_band_capacities` 设置的档位容量同理，任务所属档位已满时等待或返回 `QueueError::BandFull`；
+/// 重试、推迟与恢复的任务（`reinsert`、`restore`、`claim_orphans`）已经被接收过，不受容量限制，
+/// 否则唯一的消费者（调度器）可能在放回任务时永远等待自己腾出名额。
 pub struct PriorityQueue {
-    heap: Mutex<BinaryHeap<Task>>,
+    entries: Mutex<Entries>,
+    journal: Option<Database>,
+    /// 持久化记录的认领者，即当前实例的标识。
+    owner: String,
+    /// 认领的租约时长，超过这个时长没有续约的记录可以被其他实例认领。
+    claim_lease: Duration,
+    /// 只在持有 `entries` 锁时修改，入队也在锁内检查，保证关闭之后不会再有任务进入队列。
+    closed: AtomicBool,
+    compression: CompressionSettings,
+    /// 有新任务入队或队列被关闭时唤醒在 `pop_wait` 中等待的调度器。
+    notify: Notify,
+    /// 容量上限，0 表示不限制。
+    capacity: usize,
+    /// 各优先级档位的容量上限。
+    band_capacities: BandCapacities,
+    /// 有任务出队或队列被关闭时唤醒在 `push` 中等待名额的调用方。
+    space: Notify,
+    hooks: Vec<Arc<dyn QueueHooks>>,
+}
+
+impl Default for PriorityQueue {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PriorityQueue {
     /// 创建一个新的空优先级队列。
     pub fn new() -> Self {
+        Self::with_compression(CompressionSettings::default())
+    }
+
+    /// 创建一个使用指定压缩设置的空优先级队列。
+    pub fn with_compression(compression: CompressionSettings) -> Self {
         Self {
-            heap: Mutex::new(BinaryHeap::new()),
+            entries: Mutex::new(Entries::default()),
+            closed: AtomicBool::new(false),
+            compression,
+            journal: None,
+            owner: DEFAULT_CLAIM_OWNER.to_string(),
+            claim_lease: DEFAULT_CLAIM_LEASE,
+            notify: Notify::new(),
+            capacity: 0,
+            band_capacities: BandCapacities::default(),
+            space: Notify::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// 设置队列的容量上限，0 表示不限制。
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// 设置各优先级档位的容量上限。
+    pub fn with_band_capacities(mut self, capacities: BandCapacities) -> Self {
+        self.band_capacities = capacities;
+        self
+    }
+
+    /// 使用 `db` 持久化队列中的任务；不持久的后端（内存数据库）被忽略。
+    pub fn with_journal(mut self, db: Database) -> Self {
+        self.journal = db.is_durable().then_some(db);
+        self
+    }
+
+    /// 设置认领持久化记录时使用的实例标识与租约时长。
+    pub fn with_claims(mut self, owner: impl Into<String>, lease: Duration) -> Self {
+        self.owner = owner.into();
+        self.claim_lease = lease;
+        self
+    }
+
+    /// 注册一组观测回调，按注册顺序调用。
+    pub fn with_hooks(mut self, hooks: Arc<dyn QueueHooks>) -> Self {
+        self.hooks.push(hooks);
+        self
+    }
+
+    /// 是否会持久化队列中的任务。
+    pub fn is_journaled(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// 从持久化存储中恢复上次未处理完的任务，返回恢复的任务。
+    ///
+    /// 恢复的是本实例之前认领的任务，以及无人认领或租约已过期的任务，它们都会被本实例认领。
+    /// 应在调度器启动之前调用。已经在队列中的任务不会重复加入。
+    pub async fn restore(&self) -> Result<Vec<Task>, QueueError> {
+        self.claim(Some(&self.owner), i64::MAX).await
+    }
+
+    /// 续约本实例认领的任务，然后认领最多 `limit` 个无人认领或租约已过期（认领的实例已经失联）的任务，
+    /// 返回新认领的任务。队列已关闭时不再认领。
+    pub async fn claim_orphans(&self, limit: i64) -> Result<Vec<Task>, QueueError> {
+        let Some(db) = &self.journal else {
+            return Ok(Vec::new());
+        };
+        db.renew_claims(&self.owner)
+            .await
+            .map_err(|e| QueueError::Storage(e.to_string()))?;
+        if self.is_closed() {
+            return Ok(Vec::new());
+        }
+        self.claim(None, limit).await
+    }
+
+    /// 认领持久化的任务并加入队列。
+    async fn claim(&self, reclaim: Option<&str>, limit: i64) -> Result<Vec<Task>, QueueError> {
+        let Some(db) = &self.journal else {
+            return Ok(Vec::new());
+        };
+        let lease = chrono::Duration::from_std(self.claim_lease).unwrap_or(chrono::Duration::MAX);
+        let stale_before = Utc::now()
+            .checked_sub_signed(lease)
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let tasks = db
+            .claim_journaled_tasks(&self.owner, reclaim, stale_before, limit)
+            .await
+            .map_err(|e| QueueError::Storage(e.to_string()))?;
+        let mut restored = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            let entry = self.encode(task.clone());
+            let mut entries = self.entries.lock().await;
+            if !entries.ids.contains(&entry.id) {
+                self.hooks
+                    .iter()
+                    .for_each(|h| h.on_push(&entry.id, entry.priority));
+                entries.push(entry);
+                restored.push(task);
+            }
         }
+        if !restored.is_empty() {
+            self.notify.notify_one();
+        }
+        Ok(restored)
+    }
+
+    /// 确认任务已处理结束（成功或最终失败），从持久化存储中删除它。
+    ///
+    /// 删除失败只记录日志：任务会在下次启动时被再次处理，而不会丢失。
+    pub async fn ack(&self, id: &Uuid) {
+        if let Some(db) = &self.journal {
+            if let Err(e) = db.remove_journaled_task(id).await {
+                tracing::warn!(task_id = %id, "删除已处理任务的持久化记录失败，重启后会再次处理: {}", e);
+            }
+        }
+    }
+
+    /// 将一个任务异步推入队列；队列已满时等待其他任务出队腾出名额。
+    ///
+    /// 队列已关闭（包括等待期间被关闭）时返回 `QueueError::Closed`，
+    /// 同一个任务 ID 已在队列中时返回 `QueueError::DuplicateKey`。
+    /// 持久化失败时返回 `QueueError::Storage`，任务不会进入队列。
+    pub async fn push(&self, task: Task) -> Result<(), QueueError> {
+        self.enqueue(task, OnFull::Wait).await
+    }
+
+    /// 与 `push` 相同，但队列已满时立即返回 `QueueError::Full`，用于处理外部提交的任务。
+    pub async fn try_push(&self, task: Task) -> Result<(), QueueError> {
+        self.enqueue(task, OnFull::Fail).await
+    }
+
+    /// 将一组任务作为整体推入队列：要么全部入队，要么一个也不入队，队列已满时立即返回错误。
+    ///
+    /// 分两个阶段完成：先在队列锁内检查整组任务能否加入（容量按整组计算），
+    /// 然后在一个数据库事务中持久化整组任务，最后重新获取锁再检查一次并一次性全部加入。
+    /// 第二次检查失败（写库期间队列被关闭或被其他提交占满）时删除刚写入的记录，整组任务都不会被处理。
+    pub async fn try_push_all(&self, tasks: Vec<Task>) -> Result<(), QueueError> {
+        if let Some(db) = &self.journal {
+            self.check_insert_all(&*self.entries.lock().await, &tasks)?;
+            // 写库不持有队列锁，以免数据库延迟阻塞调度器出队
+            db.journal_tasks(&tasks, &self.owner)
+                .await
+                .map_err(|e| QueueError::Storage(e.to_string()))?;
+        }
+        let mut entries = self.entries.lock().await;
+        if let Err(e) = self.check_insert_all(&entries, &tasks) {
+            drop(entries);
+            if let Some(db) = &self.journal {
+                for task in &tasks {
+                    if let Err(e) = db.remove_journaled_task(&task.id).await {
+                        tracing::warn!(task_id = %task.id, "撤销未能入队的任务的持久化记录失败: {}", e);
+                    }
+                }
+            }
+            return Err(e);
+        }
+        for task in tasks {
+            let entry = self.encode(task);
+            self.hooks
+                .iter()
+                .for_each(|h| h.on_push(&entry.id, entry.priority));
+            entries.push(entry);
+        }
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// 检查整组任务能否同时加入队列：组内与队列中都没有重复的 ID，总容量与各档位容量都足够容纳整组任务。
+    fn check_insert_all(&self, entries: &Entries, tasks: &[Task]) -> Result<(), QueueError> {
+        if self.closed.load(AtomicOrdering::SeqCst) {
+            return Err(QueueError::Closed);
+        }
+        let mut ids = HashSet::with_capacity(tasks.len());
+        let mut added = [0; 3];
+        for task in tasks {
+            if entries.ids.contains(&task.id) || !ids.insert(task.id) {
+                return Err(QueueError::DuplicateKey(task.id));
+            }
+            added[class_index(task.priority)] += 1;
+        }
+        if self.capacity > 0 && entries.len() + tasks.len() > self.capacity {
+            return Err(QueueError::Full {
+                capacity: self.capacity,
+                retry_after: FULL_RETRY_AFTER,
+            });
+        }
+        for class in PriorityClass::ALL {
+            let capacity = self.band_capacities.for_class(class);
+            let added = added[class as usize];
+            if added > 0 && capacity > 0 && entries.class_len(class) + added > capacity {
+                metrics::counter_with_labels("queue_band_full_total", &[("class", class.as_str())])
+                    .inc();
+                return Err(QueueError::BandFull {
+                    class,
+                    capacity,
+                    retry_after: FULL_RETRY_AFTER,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// 将已经被接收过的任务（重试或推迟的任务）放回队列，不受容量限制。
+    pub async fn reinsert(&self, task: Task) -> Result<(), QueueError> {
+        self.enqueue(task, OnFull::Ignore).await
+    }
+
+    async fn enqueue(&self, task: Task, on_full: OnFull) -> Result<(), QueueError> {
+        let Some(db) = &self.journal else {
+            return self.insert(self.encode(task), on_full).await;
+        };
+        // 先做一次不写库的检查（必要时等待名额），避免为注定失败的入队写入记录
+        drop(
+            self.lock_for_insert(&task.id, task.priority, on_full)
+                .await?,
+        );
+        // 写库不持有队列锁，以免数据库延迟阻塞调度器出队
+        db.journal_task(&task, &self.owner)
+            .await
+            .map_err(|e| QueueError::Storage(e.to_string()))?;
+        let id = task.id;
+        match self.insert(self.encode(task), on_full).await {
+            // 写库期间队列被关闭时记录会保留下来，任务在下次启动时恢复；
+            // 同一个任务 ID 已在队列中时记录属于队列中的任务，不能删除
+            Err(e @ (QueueError::Closed | QueueError::DuplicateKey(_))) => Err(e),
+            // 写库期间名额被其他提交占满：删除刚写入的记录，以免重启后恢复一个被拒绝的任务
+            Err(e) => {
+                if let Err(e) = db.remove_journaled_task(&id).await {
+                    tracing::warn!(task_id = %id, "撤销未能入队的任务的持久化记录失败: {}", e);
+                }
+                Err(e)
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// 检查任务能否加入队列。
+    fn check_insert(
+        &self,
+        entries: &Entries,
+        id: &Uuid,
+        priority: u8,
+        on_full: OnFull,
+    ) -> Result<(), QueueError> {
+        if self.closed.load(AtomicOrdering::SeqCst) {
+            return Err(QueueError::Closed);
+        }
+        if entries.ids.contains(id) {
+            return Err(QueueError::DuplicateKey(*id));
+        }
+        if on_full != OnFull::Ignore && self.capacity > 0 && entries.len() >= self.capacity {
+            return Err(QueueError::Full {
+                capacity: self.capacity,
+                retry_after: FULL_RETRY_AFTER,
+            });
+        }
+        if on_full != OnFull::Ignore {
+            self.check_band(entries, priority)?;
+        }
+        Ok(())
     }
 
-    /// 将一个任务异步推入队列。
-    pub async fn push(&self, task: Task) {
-        let mut heap = self.heap.lock().await;
-        heap.push(task);
+    /// 检查优先级所属的档位是否还有名额。
+    fn check_band(&self, entries: &Entries, priority: u8) -> Result<(), QueueError> {
+        let class = PriorityClass::from_priority(priority);
+        let capacity = self.band_capacities.for_class(class);
+        if capacity > 0 && entries.class_len(class) >= capacity {
+            metrics::counter_with_labels("queue_band_full_total", &[("class", class.as_str())])
+                .inc();
+            return Err(QueueError::BandFull {
+                class,
+                capacity,
+                retry_after: FULL_RETRY_AFTER,
+            });
+        }
+        Ok(())
+    }
+
+    /// 获取队列锁并确认任务可以加入队列；`on_full` 为 `Wait` 时在队列已满期间释放锁等待名额。
+    async fn lock_for_insert(
+        &self,
+        id: &Uuid,
+        priority: u8,
+        on_full: OnFull,
+    ) -> Result<tokio::sync::MutexGuard<'_, Entries>, QueueError> {
+        loop {
+            // 先注册再检查，检查之后、等待之前出队的任务不会错过
+            let space = self.space.notified();
+            let entries = self.entries.lock().await;
+            match self.check_insert(&entries, id, priority, on_full) {
+                Ok(()) => return Ok(entries),
+                Err(QueueError::Full { .. } | QueueError::BandFull { .. })
+                    if on_full == OnFull::Wait =>
+                {
+                    drop(entries);
+                    metrics::counter("queue_push_waits_total").inc();
+                    space.await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// 将条目加入堆。
+    async fn insert(&self, entry: QueueEntry, on_full: OnFull) -> Result<(), QueueError> {
+        let mut entries = self
+            .lock_for_insert(&entry.id, entry.priority, on_full)
+            .await?;
+        self.hooks
+            .iter()
+            .for_each(|h| h.on_push(&entry.id, entry.priority));
+        entries.push(entry);
+        // 没有等待者时保留一个许可，下一次 `pop_wait` 不会错过这个任务
+        self.notify.notify_one();
+        Ok(())
     }
 
     /// 从队列中异步弹出一个任务。
-    /// 如果队列为空，则返回 `None`。
-    /// 由于内部是最大堆，弹出的总是优先级最高的任务。
+    /// 如果没有已到执行时间的任务，则返回 `None`。
+    /// 由于内部是最大堆，弹出的总是已到执行时间的任务中优先级最高的一个。
     pub async fn pop(&self) -> Option<Task> {
-        let mut heap = self.heap.lock().await;
-        heap.pop()
+        loop {
+            let entry = {
+                let mut entries = self.entries.lock().await;
+                entries.promote_due();
+                entries.pop_ready()?
+            };
+            // 腾出了一个名额，唤醒等待入队的调用方
+            self.space.notify_waiters();
+            let (id, priority, waited) = (entry.id, entry.priority, entry.enqueued_at.elapsed());
+            match Self::decode(entry) {
+                Ok(task) => {
+                    self.hooks
+                        .iter()
+                        .for_each(|h| h.on_pop(&id, priority, waited));
+                    return Some(task);
+                }
+                // 解压失败的任务无法恢复，跳过并继续弹出下一个
+                Err(e) => {
+                    tracing::error!(task_id = %id, "{}，任务被丢弃", e);
+                    self.hooks
+                        .iter()
+                        .for_each(|h| h.on_drop(&id, "decompression"));
+                }
+            }
+        }
+    }
+
+    /// 弹出一个任务；没有就绪任务时等待新任务入队或最早的延迟任务到期，最多等待 `timeout`。
+    ///
+    /// 超时或队列被关闭时返回 `None`。调用方可以据此定期执行其他工作（例如更新心跳），
+    /// 而不必以固定间隔轮询队列。
+    pub async fn pop_wait(&self, timeout: Duration) -> Option<Task> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notified = self.notify.notified();
+            if let Some(task) = self.pop().await {
+                return Some(task);
+            }
+            let now = tokio::time::Instant::now();
+            if now >= deadline || self.is_closed() {
+                return None;
+            }
+            let wait = match self.next_due_in().await {
+                Some(due) => due.min(deadline - now),
+                None => deadline - now,
+            };
+            tokio::select! {
+                _ = notified => {}
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+    }
+
+    /// 取出最多 `limit` 个排队任务，包括尚未到执行时间的任务，用于把任务迁移到其他队列后端。
+    ///
+    /// 与出队不同，持久化记录被保留：调用方把任务写入新后端之后再 `ack`，
+    /// 写入失败时用 `reinsert` 放回，进程在两者之间退出时任务会在重启后恢复。
+    /// 取出的任务以 `migrated` 为原因通知 `on_drop`。
+    pub async fn drain(&self, limit: usize) -> Vec<Task> {
+        let drained: Vec<QueueEntry> = {
+            let mut entries = self.entries.lock().await;
+            std::iter::from_fn(|| entries.pop_any())
+                .take(limit)
+                .collect()
+        };
+        if !drained.is_empty() {
+            self.space.notify_waiters();
+        }
+        let mut tasks = Vec::with_capacity(drained.len());
+        for entry in drained {
+            let id = entry.id;
+            match Self::decode(entry) {
+                Ok(task) => {
+                    self.hooks.iter().for_each(|h| h.on_drop(&id, "migrated"));
+                    tasks.push(task);
+                }
+                Err(e) => {
+                    tracing::error!(task_id = %id, "{}，任务被丢弃", e);
+                    self.hooks
+                        .iter()
+                        .for_each(|h| h.on_drop(&id, "decompression"));
+                }
+            }
+        }
+        tasks
+    }
+
+    /// 关闭队列，拒绝之后的所有入队，返回关闭时仍在排队的任务数。
+    ///
+    /// 在获取队列锁之后才标记关闭：已经拿到锁的入队会先完成，之后的入队一定会失败。
+    pub async fn close(&self) -> usize {
+        let entries = self.entries.lock().await;
+        self.closed.store(true, AtomicOrdering::SeqCst);
+        self.notify.notify_waiters();
+        self.space.notify_waiters();
+        entries.len()
+    }
+
+    /// 队列是否已关闭。
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(AtomicOrdering::SeqCst)
+    }
+
+    /// 返回队列中待处理任务的数量，包括尚未到执行时间的任务。
+    pub async fn len(&self) -> usize {
+        self.entries.lock().await.len()
+    }
+
+    /// 返回各优先级档位的排队任务数（包括尚未到执行时间的任务），按 `PriorityClass::ALL` 的顺序。
+    pub async fn len_by_class(&self) -> [(PriorityClass, usize); 3] {
+        let entries = self.entries.lock().await;
+        PriorityClass::ALL.map(|class| (class, entries.class_len(class)))
+    }
+
+    /// 检查优先级所属的档位是否已满，用于在生成任务之前提前拒绝；入队时会在锁内再检查一次。
+    pub async fn check_band_capacity(&self, priority: u8) -> Result<(), QueueError> {
+        let entries = self.entries.lock().await;
+        self.check_band(&entries, priority)
+    }
+
+    /// 距离最早的延迟任务到期还有多久，没有延迟任务时返回 `None`。
+    pub async fn next_due_in(&self) -> Option<Duration> {
+        let entries = self.entries.lock().await;
+        let run_at = entries.delayed.peek()?.run_at;
+        Some((run_at - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+    }
+
+    /// 返回优先级不低于 `priority` 的就绪任务数，即新提交的同优先级任务前面排着的任务数。
+    pub async fn count_at_or_above(&self, priority: u8) -> usize {
+        self.entries
+            .lock()
+            .await
+            .heap
+            .iter()
+            .filter(|entry| entry.priority >= priority)
+            .count()
+    }
+
+    /// 返回排队任务的位置，任务不在队列中（不存在或已经开始处理）时返回 `QueueError::NotFound`。
+    ///
+    /// 同优先级的任务按入队顺序排在前面，与实际出队顺序一致（尚未到期的延迟任务除外）。
+    pub async fn position(&self, id: &Uuid) -> Result<QueuePosition, QueueError> {
+        let entries = self.entries.lock().await;
+        if let Some(delayed) = entries.delayed.iter().find(|d| d.entry.id == *id) {
+            let priority = delayed.entry.priority;
+            return Ok(QueuePosition {
+                priority,
+                tasks_ahead: entries
+                    .heap
+                    .iter()
+                    .filter(|entry| entry.priority >= priority)
+                    .count(),
+                run_at: Some(delayed.run_at),
+            });
+        }
+        let target = entries
+            .heap
+            .iter()
+            .find(|entry| entry.id == *id)
+            .ok_or(QueueError::NotFound(*id))?;
+        let tasks_ahead = entries
+            .heap
+            .iter()
+            .filter(|entry| {
+                entry.priority > target.priority
+                    || (entry.priority == target.priority && entry.seq < target.seq)
+            })
+            .count();
+        Ok(QueuePosition {
+            priority: target.priority,
+            tasks_ahead,
+            run_at: None,
+        })
+    }
+
+    /// 返回排队时间超过所属档位阈值的任务，最多 `limit` 个。
+    ///
+    /// 尚未到执行时间的任务不算在内，延迟任务的排队时间从到期时刻算起。
+    ///
+    /// 结果按“超出阈值的倍数”从高到低排序，最严重的排在最前面。
+    pub async fn starving(
+        &self,
+        thresholds: &StarvationThresholds,
+        limit: usize,
+    ) -> Vec<StarvingTask> {
+        let now = Instant::now();
+        let starving: Vec<StarvingTask> = {
+            let entries = self.entries.lock().await;
+            entries
+                .heap
+                .iter()
+                .filter_map(|entry| {
+                    let class = PriorityClass::from_priority(entry.priority);
+                    let threshold = thresholds.for_class(class);
+                    let waited = now.duration_since(entry.enqueued_at);
+                    (waited >= threshold).then_some(StarvingTask {
+                        id: entry.id,
+                        priority: entry.priority,
+                        class,
+                        waited_ms: waited.as_millis() as u64,
+                        threshold_ms: threshold.as_millis() as u64,
+                    })
+                })
+                .collect()
+        };
+        rank_starving(starving, limit)
+    }
+
+    /// 将满足 `filter` 的排队任务的优先级改为 `priority`。
+    ///
+    /// 在持有队列锁期间完成筛选与修改，调度器不会看到只修改了一部分的队列；
+    /// 任务的入队时刻保持不变。`dry_run` 为 `true` 时只统计，不修改队列。
+    /// 按 ID 筛选时，任何一个 ID 不在队列中都返回 `QueueError::NotFound`，队列保持不变。
+    pub async fn rebalance(
+        &self,
+        filter: &RebalanceFilter,
+        priority: u8,
+        dry_run: bool,
+    ) -> Result<RebalanceOutcome, QueueError> {
+        let outcome = self.rebalance_entries(filter, priority, dry_run).await?;
+        if let (Some(db), false) = (&self.journal, dry_run) {
+            for id in &outcome.changed {
+                if let Err(e) = db.update_journaled_priority(id, priority).await {
+                    tracing::warn!(task_id = %id, "更新持久化记录的优先级失败，重启后将恢复为原优先级: {}", e);
+                }
+            }
+        }
+        Ok(outcome)
+    }
+
+    async fn rebalance_entries(
+        &self,
+        filter: &RebalanceFilter,
+        priority: u8,
+        dry_run: bool,
+    ) -> Result<RebalanceOutcome, QueueError> {
+        let mut guard = self.entries.lock().await;
+        if let Some(missing) = filter.ids.iter().find(|id| !guard.ids.contains(id)) {
+            return Err(QueueError::NotFound(*missing));
+        }
+        let mut entries = std::mem::take(&mut guard.heap).into_vec();
+        let mut delayed = std::mem::take(&mut guard.delayed).into_vec();
+        let mut outcome = RebalanceOutcome {
+            matched: 0,
+            changed: Vec::new(),
+        };
+        let all = entries
+            .iter_mut()
+            .chain(delayed.iter_mut().map(|d| &mut d.entry));
+        for entry in all {
+            if !filter.matches_header(&entry.id, entry.priority) {
+                continue;
+            }
+            if !filter.payload.is_empty() && !Self::payload_matches(filter, &entry.payload) {
+                continue;
+            }
+            outcome.matched += 1;
+            if entry.priority != priority {
+                outcome.changed.push(entry.id);
+                if !dry_run {
+                    entry.priority = priority;
+                }
+            }
+        }
+        guard.heap = BinaryHeap::from(entries);
+        guard.delayed = BinaryHeap::from(delayed);
+        // 重新分档是运维操作，可以让档位超出容量，之后该档位的新任务会被拒绝直到降回容量以下
+        guard.recount_classes();
+        Ok(outcome)
+    }
+
+    /// 判断条目的载荷是否满足筛选条件，压缩的载荷需要先解压。
+    fn payload_matches(filter: &RebalanceFilter, payload: &StoredPayload) -> bool {
+        match payload {
+            StoredPayload::Plain(value) => filter.matches_payload(value),
+            StoredPayload::Compressed(bytes) => zstd::stream::decode_all(bytes.as_slice())
+                .ok()
+                .and_then(|raw| serde_json::from_slice::<Value>(&raw).ok())
+                .is_some_and(|value| filter.matches_payload(&value)),
+        }
+    }
+
+    /// 将任务转换为堆条目，必要时压缩载荷。
+    fn encode(&self, task: Task) -> QueueEntry {
+        let payload = if self.compression.enabled {
+            self.compress(task.payload)
+        } else {
+            StoredPayload::Plain(task.payload)
+        };
+        QueueEntry {
+            id: task.id,
+            priority: task.priority,
+            // 由 `Entries::push` 在队列锁内分配
+            seq: 0,
+            retry_count: task.retry_count,
+            payload,
+            enqueued_at: Instant::now(),
+            run_at: task.run_at,
+            context: task.context,
+            kind: task.kind,
+            depends_on: task.depends_on,
+            tenant: task.tenant,
+        }
+    }
+
+    /// 压缩超过阈值的载荷；载荷较小或压缩失败时保留原始值。
+    fn compress(&self, payload: Arc<Value>) -> StoredPayload {
+        let raw = match serde_json::to_vec(payload.as_ref()) {
+            Ok(raw) if raw.len() >= self.compression.threshold_bytes => raw,
+            _ => return StoredPayload::Plain(payload),
+        };
+
+        let start = Instant::now();
+        match zstd::bulk::compress(&raw, self.compression.level) {
+            // 压缩后反而变大时不值得保存压缩结果
+            Ok(compressed) if compressed.len() < raw.len() => {
+                metrics::counter("queue_compression_duration_us_total")
+                    .add(start.elapsed().as_micros() as u64);
+                metrics::counter("queue_payloads_compressed_total").inc();
+                metrics::counter("queue_payload_raw_bytes_total").add(raw.len() as u64);
+                metrics::counter("queue_payload_compressed_bytes_total")
+                    .add(compressed.len() as u64);
+                metrics::gauge("queue_compression_ratio")
+                    .set(compressed.len() as f64 / raw.len() as f64);
+                StoredPayload::Compressed(compressed)
+            }
+            Ok(_) => StoredPayload::Plain(payload),
+            Err(e) => {
+                tracing::warn!("压缩任务载荷失败，将以原始形式入队: {}", e);
+                StoredPayload::Plain(payload)
+            }
+        }
+    }
+
+    /// 将堆条目还原为任务。
+    fn decode(entry: QueueEntry) -> Result<Task, QueueError> {
+        let payload = match entry.payload {
+            StoredPayload::Plain(value) => value,
+            StoredPayload::Compressed(bytes) => {
+                let start = Instant::now();
+                let decoded = zstd::stream::decode_all(bytes.as_slice())
+                    .map_err(anyhow::Error::from)
+                    .and_then(|raw| serde_json::from_slice(&raw).map_err(anyhow::Error::from));
+                metrics::counter("queue_decompression_duration_us_total")
+                    .add(start.elapsed().as_micros() as u64);
+                Arc::new(decoded.map_err(|e| QueueError::Serialization(e.to_string()))?)
+            }
+        };
+        Ok(Task {
+            id: entry.id,
+            payload,
+            priority: entry.priority,
+            retry_count: entry.retry_count,
+            run_at: entry.run_at,
+            context: entry.context,
+            kind: entry.kind,
+            depends_on: entry.depends_on,
+            tenant: entry.tenant,
+        })
+    }
+}
+
+impl QueueBackend for PriorityQueue {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn is_durable(&self) -> bool {
+        self.is_journaled()
+    }
+
+    fn restore(&self) -> BoxFuture<'_, Result<Vec<Task>, QueueError>> {
+        Box::pin(PriorityQueue::restore(self))
+    }
+
+    fn claim_orphans(&self, limit: i64) -> BoxFuture<'_, Result<Vec<Task>, QueueError>> {
+        Box::pin(PriorityQueue::claim_orphans(self, limit))
+    }
+
+    fn ack<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, ()> {
+        Box::pin(PriorityQueue::ack(self, id))
+    }
+
+    fn push(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(PriorityQueue::push(self, task))
+    }
+
+    fn try_push(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(PriorityQueue::try_push(self, task))
+    }
+
+    fn try_push_all(&self, tasks: Vec<Task>) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(PriorityQueue::try_push_all(self, tasks))
+    }
+
+    fn reinsert(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(PriorityQueue::reinsert(self, task))
+    }
+
+    fn pop_wait(&self, timeout: Duration) -> BoxFuture<'_, Option<Task>> {
+        Box::pin(PriorityQueue::pop_wait(self, timeout))
+    }
+
+    fn close(&self) -> BoxFuture<'_, usize> {
+        Box::pin(PriorityQueue::close(self))
+    }
+
+    fn is_closed(&self) -> bool {
+        PriorityQueue::is_closed(self)
+    }
+
+    fn len(&self) -> BoxFuture<'_, usize> {
+        Box::pin(PriorityQueue::len(self))
+    }
+
+    fn len_by_class(&self) -> BoxFuture<'_, [(PriorityClass, usize); 3]> {
+        Box::pin(PriorityQueue::len_by_class(self))
+    }
+
+    fn check_band_capacity(&self, priority: u8) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(PriorityQueue::check_band_capacity(self, priority))
+    }
+
+    fn count_at_or_above(&self, priority: u8) -> BoxFuture<'_, usize> {
+        Box::pin(PriorityQueue::count_at_or_above(self, priority))
+    }
+
+    fn position<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, Result<QueuePosition, QueueError>> {
+        Box::pin(PriorityQueue::position(self, id))
+    }
+
+    fn starving<'a>(
+        &'a self,
+        thresholds: &'a StarvationThresholds,
+        limit: usize,
+    ) -> BoxFuture<'a, Vec<StarvingTask>> {
+        Box::pin(PriorityQueue::starving(self, thresholds, limit))
+    }
+
+    fn rebalance<'a>(
+        &'a self,
+        filter: &'a RebalanceFilter,
+        priority: u8,
+        dry_run: bool,
+    ) -> BoxFuture<'a, Result<RebalanceOutcome, QueueError>> {
+        Box::pin(PriorityQueue::rebalance(self, filter, priority, dry_run))
     }
 }
 
@@ -83,21 +1535,39 @@ mod tests {
     fn test_task_ordering() {
         let high_prio_task = Task {
             id: Uuid::new_v4(),
-            payload: json!({}),
+            payload: json!({}).into(),
             priority: 100,
             retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
         };
 
         let low_prio_task = Task {
             id: Uuid::new_v4(),
-            payload: json!({}),
+            payload: json!({}).into(),
             priority: 10,
             retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
         };
 
         assert!(high_prio_task > low_prio_task);
     }
 
+    /// 测试队列后端类型的解析。
+    #[test]
+    fn test_queue_backend_kind() {
+        assert_eq!("local".parse(), Ok(QueueBackendKind::Local));
+        assert_eq!(" Redis ".parse(), Ok(QueueBackendKind::Redis));
+        assert!("kafka".parse::<QueueBackendKind>().is_err());
+    }
+
     /// 测试 `PriorityQueue` 的 `push` 和 `pop` 操作是否正确。
     /// 应该先弹出优先级高的任务。
     #[tokio::test]
@@ -106,19 +1576,29 @@ mod tests {
 
         let low_prio_task = Task {
             id: Uuid::new_v4(),
-            payload: json!({ "task": "low" }),
+            payload: json!({ "task": "low" }).into(),
             priority: 10,
             retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
         };
         let high_prio_task = Task {
             id: Uuid::new_v4(),
-            payload: json!({ "task": "high" }),
+            payload: json!({ "task": "high" }).into(),
             priority: 100,
             retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
         };
 
-        queue.push(low_prio_task.clone()).await;
-        queue.push(high_prio_task.clone()).await;
+        queue.push(low_prio_task.clone()).await.unwrap();
+        queue.push(high_prio_task.clone()).await.unwrap();
 
         // 第一次弹出的应该是高优先级的任务
         let first_popped = queue.pop().await.unwrap();
@@ -130,5 +1610,891 @@ mod tests {
 
         // 队列现在应该为空
         assert!(queue.pop().await.is_none());
+        assert_eq!(queue.count_at_or_above(0).await, 0);
+    }
+
+    /// 测试同优先级的任务按入队顺序出队，包括到期的延迟任务与重新入队的任务。
+    #[tokio::test]
+    async fn test_fifo_within_priority() {
+        let queue = PriorityQueue::new();
+        let task = |priority, run_at| Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority,
+            retry_count: 0,
+            run_at,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        // 延迟任务最先提交，到期后排在同优先级任务的最前面
+        let delayed = task(50, Some(Utc::now() + chrono::Duration::milliseconds(20)));
+        let delayed_id = delayed.id;
+        queue.push(delayed).await.unwrap();
+        let mut ids = Vec::new();
+        for _ in 0..20 {
+            let next = task(50, None);
+            ids.push(next.id);
+            queue.push(next).await.unwrap();
+        }
+        let urgent = task(200, None);
+        let urgent_id = urgent.id;
+        queue.push(urgent).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        assert_eq!(queue.pop().await.unwrap().id, urgent_id);
+        let retried = queue.pop().await.unwrap();
+        assert_eq!(retried.id, delayed_id);
+        // 重新入队的任务排到同优先级任务的最后
+        queue.reinsert(retried).await.unwrap();
+        ids.push(delayed_id);
+        let mut popped = Vec::new();
+        while let Some(task) = queue.pop().await {
+            popped.push(task.id);
+        }
+        assert_eq!(popped, ids);
+    }
+
+    /// 测试克隆任务时载荷是共享的，而不是被复制。
+    #[test]
+    fn test_task_clone_shares_payload() {
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "data": "x".repeat(1024) }).into(),
+            priority: 10,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let cloned = task.clone();
+        assert!(Arc::ptr_eq(&task.payload, &cloned.payload));
+    }
+
+    /// 测试排队时间超过档位阈值的任务会被识别为饥饿任务。
+    #[tokio::test]
+    async fn test_starving_tasks() {
+        let queue = PriorityQueue::new();
+        let old_task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority: 10,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        queue.push(old_task.clone()).await.unwrap();
+        queue
+            .push(Task {
+                id: Uuid::new_v4(),
+                payload: json!({}).into(),
+                priority: 250,
+                retry_count: 0,
+                run_at: None,
+                context: Default::default(),
+                kind: Default::default(),
+                depends_on: Default::default(),
+                tenant: None,
+            })
+            .await
+            .unwrap();
+
+        let thresholds = StarvationThresholds {
+            low: Duration::ZERO,
+            normal: Duration::from_secs(3600),
+            critical: Duration::from_secs(3600),
+        };
+        let starving = queue.starving(&thresholds, 10).await;
+        assert_eq!(starving.len(), 1);
+        assert_eq!(starving[0].id, old_task.id);
+        assert_eq!(starving[0].class, PriorityClass::Low);
+    }
+
+    /// 测试重新分档只修改满足条件的任务，并按新的优先级出队。
+    #[tokio::test]
+    async fn test_rebalance() {
+        let queue = PriorityQueue::with_compression(CompressionSettings {
+            enabled: true,
+            threshold_bytes: 64,
+            level: 3,
+        });
+        let marketing = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "kind": "marketing", "data": "x".repeat(1000) }).into(),
+            priority: 150,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let billing = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "kind": "billing" }).into(),
+            priority: 100,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        queue.push(marketing.clone()).await.unwrap();
+        queue.push(billing.clone()).await.unwrap();
+
+        let filter = RebalanceFilter {
+            class: Some(PriorityClass::Normal),
+            payload: json!({ "kind": "marketing" }).as_object().unwrap().clone(),
+            ..Default::default()
+        };
+        let outcome = queue.rebalance(&filter, 10, true).await.unwrap();
+        assert_eq!(outcome.matched, 1);
+        assert_eq!(queue.pop().await.unwrap().id, marketing.id);
+        queue.push(marketing.clone()).await.unwrap();
+
+        let outcome = queue.rebalance(&filter, 10, false).await.unwrap();
+        assert_eq!(outcome.changed, vec![marketing.id]);
+        assert_eq!(queue.pop().await.unwrap().id, billing.id);
+        let demoted = queue.pop().await.unwrap();
+        assert_eq!((demoted.id, demoted.priority), (marketing.id, 10));
+    }
+
+    /// 测试排队任务的位置：优先级更高的与同优先级中更早入队的任务排在前面。
+    #[tokio::test]
+    async fn test_position() {
+        let queue = PriorityQueue::new();
+        let mut ids = Vec::new();
+        for priority in [10, 50, 10, 200] {
+            let task = Task {
+                id: Uuid::new_v4(),
+                payload: json!({}).into(),
+                priority,
+                retry_count: 0,
+                run_at: None,
+                context: Default::default(),
+                kind: Default::default(),
+                depends_on: Default::default(),
+                tenant: None,
+            };
+            ids.push(task.id);
+            queue.push(task).await.unwrap();
+        }
+        let position = |i: usize| {
+            let queue = &queue;
+            let id = ids[i];
+            async move { queue.position(&id).await.unwrap().tasks_ahead }
+        };
+        assert_eq!(position(3).await, 0);
+        assert_eq!(position(1).await, 1);
+        assert_eq!(position(0).await, 2);
+        assert_eq!(position(2).await, 3);
+        assert!(matches!(
+            queue.position(&Uuid::new_v4()).await,
+            Err(QueueError::NotFound(_))
+        ));
+    }
+
+    /// 测试 `pop_wait` 在任务入队时立即返回，没有任务时等待到超时，队列关闭时不再等待。
+    #[tokio::test(start_paused = true)]
+    async fn test_pop_wait() {
+        let queue = Arc::new(PriorityQueue::new());
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority: 10,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let producer = {
+            let queue = queue.clone();
+            let task = task.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                queue.push(task).await.unwrap();
+            })
+        };
+        let started = tokio::time::Instant::now();
+        let popped = queue.pop_wait(Duration::from_secs(60)).await.unwrap();
+        assert_eq!(popped.id, task.id);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        producer.await.unwrap();
+
+        let started = tokio::time::Instant::now();
+        assert!(queue.pop_wait(Duration::from_secs(5)).await.is_none());
+        assert!(started.elapsed() >= Duration::from_secs(5));
+
+        queue.close().await;
+        let started = tokio::time::Instant::now();
+        assert!(queue.pop_wait(Duration::from_secs(5)).await.is_none());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    /// 测试延迟任务在执行时间之前不会出队，到期后与就绪任务按优先级竞争。
+    #[tokio::test]
+    async fn test_delayed_tasks() {
+        let queue = PriorityQueue::new();
+        let task = |priority, run_at| Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority,
+            retry_count: 0,
+            run_at,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let run_at = Utc::now() + chrono::Duration::milliseconds(50);
+        let delayed = task(200, Some(run_at));
+        // 执行时间已经过去的任务立即可以处理
+        let overdue = task(10, Some(Utc::now() - chrono::Duration::seconds(1)));
+        queue.push(delayed.clone()).await.unwrap();
+        queue.push(overdue.clone()).await.unwrap();
+        assert_eq!(queue.len().await, 2);
+        assert_eq!(queue.count_at_or_above(0).await, 1);
+
+        let position = queue.position(&delayed.id).await.unwrap();
+        assert_eq!(position.run_at, Some(run_at));
+        assert_eq!(position.tasks_ahead, 0);
+        assert!(queue.next_due_in().await.unwrap() <= Duration::from_millis(50));
+
+        assert_eq!(queue.pop().await.unwrap().id, overdue.id);
+        assert!(queue.pop().await.is_none());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        queue.push(task(100, None)).await.unwrap();
+        let first = queue.pop().await.unwrap();
+        assert_eq!((first.id, first.run_at), (delayed.id, Some(run_at)));
+        assert_eq!(queue.pop().await.unwrap().priority, 100);
+        assert!(queue.next_due_in().await.is_none());
+    }
+
+    /// 测试重复入队与按不存在的 ID 重新分档返回对应的错误。
+    #[tokio::test]
+    async fn test_queue_errors() {
+        let queue = PriorityQueue::new();
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority: 10,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        queue.push(task.clone()).await.unwrap();
+        let duplicate = queue.push(task.clone()).await.unwrap_err();
+        assert!(matches!(duplicate, QueueError::DuplicateKey(id) if id == task.id));
+        assert!(!duplicate.is_retryable());
+        assert_eq!(queue.len().await, 1);
+
+        let missing = Uuid::new_v4();
+        let filter = RebalanceFilter {
+            ids: vec![task.id, missing],
+            ..Default::default()
+        };
+        let not_found = queue.rebalance(&filter, 200, false).await.unwrap_err();
+        assert!(matches!(not_found, QueueError::NotFound(id) if id == missing));
+        assert_eq!(queue.pop().await.unwrap().priority, 10);
+
+        // 出队后可以用同一个 ID 再次入队（重试）
+        queue.push(task).await.unwrap();
+        let full = QueueError::Full {
+            capacity: 1,
+            retry_after: Duration::from_secs(5),
+        };
+        assert!(full.is_retryable());
+        assert_eq!(full.retry_after(), Some(Duration::from_secs(5)));
+    }
+
+    /// 测试关闭后入队失败，已有的任务仍可出队。
+    #[tokio::test]
+    async fn test_close_rejects_push() {
+        let queue = PriorityQueue::new();
+        let task = |priority| Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        queue.push(task(10)).await.unwrap();
+        assert_eq!(queue.close().await, 1);
+        assert!(queue.is_closed());
+
+        let error = queue.push(task(20)).await.unwrap_err();
+        assert!(matches!(error, QueueError::Closed));
+        assert_eq!(queue.pop().await.unwrap().priority, 10);
+        assert!(queue.pop().await.is_none());
+    }
+
+    /// 测试有界队列：已满时 `try_push` 失败、`push` 等待名额，`reinsert` 不受容量限制。
+    #[tokio::test]
+    async fn test_capacity() {
+        let queue = Arc::new(PriorityQueue::new().with_capacity(2));
+        let task = |priority| Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        queue.try_push(task(10)).await.unwrap();
+        queue.push(task(20)).await.unwrap();
+        let full = queue.try_push(task(30)).await.unwrap_err();
+        assert!(matches!(full, QueueError::Full { capacity: 2, .. }));
+        assert_eq!(queue.len().await, 2);
+
+        // 放回的任务已经被接收过，可以超出容量
+        queue.reinsert(task(5)).await.unwrap();
+        assert_eq!(queue.len().await, 3);
+
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push(task(40)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        queue.pop().await.unwrap();
+        queue.pop().await.unwrap();
+        waiting.await.unwrap().unwrap();
+        assert_eq!(queue.pop().await.unwrap().priority, 40);
+
+        // 等待名额期间队列被关闭时返回 `Closed`
+        queue.push(task(50)).await.unwrap();
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push(task(60)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        queue.close().await;
+        assert!(matches!(waiting.await.unwrap(), Err(QueueError::Closed)));
+    }
+
+    /// 测试档位容量：一个档位已满时只拒绝该档位的任务，出队与重新分档后名额随之变化。
+    #[tokio::test]
+    async fn test_band_capacities() {
+        let queue = Arc::new(PriorityQueue::new().with_band_capacities(BandCapacities {
+            low: 2,
+            critical: 1,
+            ..Default::default()
+        }));
+        let task = |priority| Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        queue.try_push(task(10)).await.unwrap();
+        queue.try_push(task(20)).await.unwrap();
+        let full = queue.try_push(task(30)).await.unwrap_err();
+        assert!(matches!(
+            full,
+            QueueError::BandFull {
+                class: PriorityClass::Low,
+                capacity: 2,
+                ..
+            }
+        ));
+        assert_eq!(full.code(), "QUEUE_BAND_FULL");
+        assert!(full.is_retryable());
+        assert!(queue.check_band_capacity(5).await.is_err());
+        // 其他档位不受影响，放回的任务不受档位容量限制
+        queue.try_push(task(100)).await.unwrap();
+        queue.try_push(task(220)).await.unwrap();
+        queue.reinsert(task(40)).await.unwrap();
+        assert_eq!(
+            queue.len_by_class().await,
+            [
+                (PriorityClass::Low, 3),
+                (PriorityClass::Normal, 1),
+                (PriorityClass::Critical, 1),
+            ]
+        );
+
+        // 关键任务出队后腾出名额，等待中的入队随之完成
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push(task(250)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        assert_eq!(queue.pop().await.unwrap().priority, 220);
+        waiting.await.unwrap().unwrap();
+
+        // 重新分档后按新的优先级统计
+        let filter = RebalanceFilter {
+            class: Some(PriorityClass::Low),
+            ..Default::default()
+        };
+        queue.rebalance(&filter, 100, false).await.unwrap();
+        assert_eq!(queue.len_by_class().await[0], (PriorityClass::Low, 0));
+        queue.try_push(task(30)).await.unwrap();
+    }
+
+    /// 测试整组入队：容量、档位容量或重复 ID 任何一项不满足时整组都不入队。
+    #[tokio::test]
+    async fn test_try_push_all() {
+        let queue = PriorityQueue::new()
+            .with_capacity(4)
+            .with_band_capacities(BandCapacities {
+                critical: 1,
+                ..Default::default()
+            });
+        let task = |priority| Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        queue.try_push(task(10)).await.unwrap();
+
+        // 单个任务放得下，但整组放不下
+        let full = queue
+            .try_push_all(vec![task(10), task(20), task(30), task(40)])
+            .await
+            .unwrap_err();
+        assert!(matches!(full, QueueError::Full { capacity: 4, .. }));
+        let band_full = queue
+            .try_push_all(vec![task(10), task(220), task(230)])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            band_full,
+            QueueError::BandFull {
+                class: PriorityClass::Critical,
+                ..
+            }
+        ));
+        let repeated = task(10);
+        let duplicate = queue
+            .try_push_all(vec![repeated.clone(), repeated.clone()])
+            .await
+            .unwrap_err();
+        assert!(matches!(duplicate, QueueError::DuplicateKey(id) if id == repeated.id));
+        assert_eq!(queue.len().await, 1);
+
+        let mut dependent = task(50);
+        dependent.depends_on = vec![repeated.id];
+        queue
+            .try_push_all(vec![repeated.clone(), dependent.clone(), task(220)])
+            .await
+            .unwrap();
+        assert_eq!(queue.len().await, 4);
+        assert_eq!(queue.pop().await.unwrap().priority, 220);
+        assert_eq!(queue.pop().await.unwrap().depends_on, vec![repeated.id]);
+
+        queue.close().await;
+        let closed = queue.try_push_all(vec![task(10)]).await.unwrap_err();
+        assert!(matches!(closed, QueueError::Closed));
+    }
+
+    /// 测试持久化的队列整组入队：整组任务（包括依赖）一起写入，被拒绝的一组不留下任何记录。
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_try_push_all_journaled() {
+        let db = crate::db::test_database().await;
+        let queue = PriorityQueue::new()
+            .with_capacity(3)
+            .with_journal(db.clone());
+        let task = |priority| Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "n": priority }).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let first = task(20);
+        let mut second = task(10);
+        second.depends_on = vec![first.id];
+        queue
+            .try_push_all(vec![first.clone(), second.clone()])
+            .await
+            .unwrap();
+        assert!(queue.try_push_all(vec![task(30), task(40)]).await.is_err());
+
+        let restarted = PriorityQueue::new().with_journal(db);
+        let restored = restarted.restore().await.unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restarted.pop().await.unwrap().id, first.id);
+        let dependent = restarted.pop().await.unwrap();
+        assert_eq!(dependent.id, second.id);
+        assert_eq!(dependent.depends_on, vec![first.id]);
+    }
+
+    /// 测试执行方式的解析与序列化，自动分类时不写入序列化结果。
+    #[test]
+    fn test_task_kind() {
+        assert_eq!("slow".parse(), Ok(TaskKind::Slow));
+        assert_eq!(
+            " report ".parse(),
+            Ok(TaskKind::Custom("report".to_string()))
+        );
+        assert!("".parse::<TaskKind>().is_err());
+        assert!("x"
+            .repeat(MAX_TASK_KIND_LEN + 1)
+            .parse::<TaskKind>()
+            .is_err());
+
+        let mut task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority: 10,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        assert!(serde_json::to_value(&task).unwrap().get("kind").is_none());
+        task.kind = TaskKind::Quick;
+        let value = serde_json::to_value(&task).unwrap();
+        assert_eq!(value["kind"], "quick");
+        let restored: Task = serde_json::from_value(value).unwrap();
+        assert_eq!(restored.kind, TaskKind::Quick);
+    }
+
+    /// 测试持久化的队列在“重启”后恢复未确认的任务，确认后的任务不再恢复。
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_journal_restore_and_ack() {
+        let db = crate::db::test_database().await;
+        let queue = PriorityQueue::new().with_journal(db.clone());
+        assert!(queue.is_journaled());
+        let task = |priority| Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "n": priority }).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let (done, running, mut queued) = (task(30), task(20), task(10));
+        // 执行时间、捕获的请求头、执行方式与租户会随任务一起持久化
+        queued.run_at = Some(Utc::now() - chrono::Duration::seconds(1));
+        queued.kind = TaskKind::Custom("report".to_string());
+        queued.tenant = Some("acme".to_string());
+        queued
+            .context
+            .headers
+            .insert("x-correlation-id".to_string(), "c-1".to_string());
+        for t in [&done, &running, &queued] {
+            queue.push(t.clone()).await.unwrap();
+        }
+        assert_eq!(queue.pop().await.unwrap().id, done.id);
+        queue.ack(&done.id).await;
+        // 已出队但未确认的任务（处理到一半时进程退出）也会被恢复
+        assert_eq!(queue.pop().await.unwrap().id, running.id);
+        queue
+            .rebalance(
+                &RebalanceFilter {
+                    ids: vec![queued.id],
+                    ..Default::default()
+                },
+                50,
+                false,
+            )
+            .await
+            .unwrap();
+
+        let restarted = PriorityQueue::new().with_journal(db);
+        let restored = restarted.restore().await.unwrap();
+        assert_eq!(restored.len(), 2);
+        let first = restarted.pop().await.unwrap();
+        assert_eq!((first.id, first.priority), (queued.id, 50));
+        assert_eq!(
+            first.run_at.map(|t| t.timestamp_millis()),
+            queued.run_at.map(|t| t.timestamp_millis())
+        );
+        assert_eq!(*first.payload, *queued.payload);
+        assert_eq!(first.context, queued.context);
+        assert_eq!(first.kind, queued.kind);
+        assert_eq!(first.tenant_or_default(), "acme");
+        let second = restarted.pop().await.unwrap();
+        assert_eq!(second.id, running.id);
+        assert!(second.context.is_empty());
+        assert!(second.kind.is_auto());
+        assert_eq!(second.tenant_or_default(), DEFAULT_TENANT);
+        // 再次恢复不会重复加入已在队列中的任务
+        restarted.push(running.clone()).await.unwrap();
+        assert_eq!(restarted.restore().await.unwrap().len(), 1);
+    }
+
+    /// 测试两个提交并发争抢持久化队列的最后一个名额：只有一个被接收，被拒绝的任务不会在重启后恢复。
+    #[cfg(feature = "sqlite")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_journaled_try_push_race_for_last_slot() {
+        let db = crate::db::test_database().await;
+        for _ in 0..20 {
+            let queue = Arc::new(
+                PriorityQueue::new()
+                    .with_capacity(1)
+                    .with_journal(db.clone()),
+            );
+            let task = || Task {
+                id: Uuid::new_v4(),
+                payload: json!({}).into(),
+                priority: 10,
+                retry_count: 0,
+                run_at: None,
+                context: Default::default(),
+                kind: Default::default(),
+                depends_on: Default::default(),
+                tenant: None,
+            };
+            let (a, b) = (task(), task());
+            let (ra, rb) = tokio::join!(
+                tokio::spawn({
+                    let (queue, a) = (queue.clone(), a.clone());
+                    async move { queue.try_push(a).await }
+                }),
+                tokio::spawn({
+                    let (queue, b) = (queue.clone(), b.clone());
+                    async move { queue.try_push(b).await }
+                }),
+            );
+            let (ra, rb) = (ra.unwrap(), rb.unwrap());
+            assert_eq!(ra.is_ok() as u8 + rb.is_ok() as u8, 1);
+            let rejected = if ra.is_ok() { rb } else { ra };
+            assert!(matches!(rejected, Err(QueueError::Full { .. })));
+            assert_eq!(queue.len().await, 1);
+            let accepted = queue.pop().await.unwrap().id;
+
+            let restarted = PriorityQueue::new().with_journal(db.clone());
+            let restored = restarted.restore().await.unwrap();
+            assert_eq!(restored.len(), 1);
+            assert_eq!(restored[0].id, accepted);
+            restarted.ack(&accepted).await;
+        }
+    }
+
+    /// 测试多个实例并发认领持久化的任务：每个任务只被一个实例认领，存活实例的任务不会被接管，
+    /// 实例重启后恢复的是自己认领的任务。
+    #[cfg(feature = "sqlite")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_claimers() {
+        let db = crate::db::test_database().await;
+        let crashed = PriorityQueue::new()
+            .with_journal(db.clone())
+            .with_claims("crashed", Duration::from_secs(60));
+        for priority in 0..40 {
+            crashed
+                .push(Task {
+                    id: Uuid::new_v4(),
+                    payload: json!({ "n": priority }).into(),
+                    priority,
+                    retry_count: 0,
+                    run_at: None,
+                    context: Default::default(),
+                    kind: Default::default(),
+                    depends_on: Default::default(),
+                    tenant: None,
+                })
+                .await
+                .unwrap();
+        }
+        let claimers: Vec<Arc<PriorityQueue>> = (0..4)
+            .map(|n| {
+                Arc::new(
+                    PriorityQueue::new()
+                        .with_journal(db.clone())
+                        .with_claims(format!("worker-{}", n), Duration::from_secs(60)),
+                )
+            })
+            .collect();
+        let crate::db::Database::Sqlite(pool, _) = &db else {
+            unreachable!()
+        };
+        // 将认领时间改到很久以前，模拟租约已经过期的失联实例
+        sqlx::query("UPDATE tasks_queue SET claimed_at = '2000-01-01 00:00:00'")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let handles: Vec<_> = claimers
+            .iter()
+            .cloned()
+            .map(|queue| {
+                tokio::spawn(async move {
+                    let mut claimed = Vec::new();
+                    loop {
+                        let batch = queue.claim_orphans(3).await.unwrap();
+                        if batch.is_empty() {
+                            return claimed;
+                        }
+                        claimed.extend(batch.into_iter().map(|t| t.id));
+                    }
+                })
+            })
+            .collect();
+        let mut all = Vec::new();
+        for handle in handles {
+            all.extend(handle.await.unwrap());
+        }
+        let unique: HashSet<Uuid> = all.iter().copied().collect();
+        assert_eq!(all.len(), 40);
+        assert_eq!(unique.len(), 40);
+
+        // 存活实例认领的任务不会被接管
+        for queue in &claimers {
+            assert!(queue.claim_orphans(10).await.unwrap().is_empty());
+        }
+        // 重启后只恢复自己认领的任务
+        let owned = claimers[0].len().await;
+        let restarted = PriorityQueue::new()
+            .with_journal(db)
+            .with_claims("worker-0", Duration::from_secs(60));
+        assert_eq!(restarted.restore().await.unwrap().len(), owned);
+    }
+
+    /// 测试启用压缩后，大载荷在出队时能被完整还原，小载荷保持原样。
+    #[tokio::test]
+    async fn test_priority_queue_compression_roundtrip() {
+        let queue = PriorityQueue::with_compression(CompressionSettings {
+            enabled: true,
+            threshold_bytes: 64,
+            level: 3,
+        });
+
+        let large_payload = json!({ "data": "x".repeat(10_000) });
+        let large_task = Task {
+            id: Uuid::new_v4(),
+            payload: large_payload.clone().into(),
+            priority: 100,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let small_task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "task": "small" }).into(),
+            priority: 10,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+
+        queue.push(large_task.clone()).await.unwrap();
+        queue.push(small_task.clone()).await.unwrap();
+
+        // 大载荷在堆中应以压缩形式保存
+        {
+            let entries = queue.entries.lock().await;
+            let compressed = entries
+                .heap
+                .iter()
+                .filter(|e| matches!(e.payload, StoredPayload::Compressed(_)))
+                .count();
+            assert_eq!(compressed, 1);
+        }
+
+        let first = queue.pop().await.unwrap();
+        assert_eq!(first.id, large_task.id);
+        assert_eq!(*first.payload, large_payload);
+
+        let second = queue.pop().await.unwrap();
+        assert_eq!(second.id, small_task.id);
+        assert_eq!(second.payload, small_task.payload);
+    }
+
+    /// 记录回调调用的 `QueueHooks`。
+    #[derive(Default)]
+    struct RecordingHooks {
+        calls: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl QueueHooks for RecordingHooks {
+        fn on_push(&self, _id: &Uuid, priority: u8) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("push {}", priority));
+        }
+
+        fn on_pop(&self, _id: &Uuid, priority: u8, _waited: Duration) {
+            self.calls.lock().unwrap().push(format!("pop {}", priority));
+        }
+
+        fn on_drop(&self, _id: &Uuid, reason: &'static str) {
+            self.calls.lock().unwrap().push(format!("drop {}", reason));
+        }
+    }
+
+    /// 测试入队、出队与丢弃时调用观测回调，被拒绝的入队不会触发回调。
+    #[tokio::test]
+    async fn test_hooks() {
+        let hooks = Arc::new(RecordingHooks::default());
+        let queue = PriorityQueue::new().with_hooks(hooks.clone());
+        let task = |priority| Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let low = task(10);
+        queue.push(low.clone()).await.unwrap();
+        queue.push(task(90)).await.unwrap();
+        assert!(queue.push(low).await.is_err());
+        queue.pop().await.unwrap();
+
+        // 损坏的压缩载荷在出队时被丢弃
+        queue.entries.lock().await.heap.peek_mut().unwrap().payload =
+            StoredPayload::Compressed(vec![1, 2, 3]);
+        assert!(queue.pop().await.is_none());
+
+        assert_eq!(
+            *hooks.calls.lock().unwrap(),
+            ["push 10", "push 90", "pop 90", "drop decompression"]
+        );
     }
 }
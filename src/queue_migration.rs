@@ -0,0 +1,547 @@
+use crate::metrics;
+use crate::queue::{
+    rank_starving, PriorityClass, PriorityQueue, QueueBackend, QueueError, QueuePosition,
+    RebalanceFilter, RebalanceOutcome, StarvationThresholds, StarvingTask, Task,
+};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// 迁移时每批从旧后端取出的任务数。
+const MIGRATION_BATCH: usize = 100;
+
+/// 一次迁移的状态。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationState {
+    /// 尚未开始迁移，新提交的任务已经写入新后端。
+    Idle,
+    Running,
+    Completed,
+    /// 迁移中途出错，未迁移的任务仍在旧后端中，可以再次发起迁移。
+    Failed,
+}
+
+/// 迁移进度，由 `GET /admin/queue/migration` 返回。
+///
+/// 迁移结束时校验计数：开始时旧后端中的任务，加上迁移期间恢复或认领到旧后端的任务，
+/// 必须等于迁移到新后端的任务与调度器直接从旧后端取出处理的任务之和，且旧后端已经为空。
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub state: MigrationState,
+    /// 旧后端的名称。
+    pub source: &'static str,
+    /// 新后端的名称。
+    pub target: &'static str,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// 开始迁移时旧后端中的排队任务数。
+    pub source_before: usize,
+    /// 迁移期间恢复或认领到旧后端的任务数。
+    pub claimed: usize,
+    /// 已经写入新后端的任务数。
+    pub moved: usize,
+    /// 迁移期间调度器直接从旧后端取出处理的任务数。
+    pub consumed: usize,
+    /// 旧后端中剩余的排队任务数。
+    pub remaining: usize,
+    /// 新后端中的排队任务数，包括迁移窗口内新提交的任务。
+    pub target_len: usize,
+    /// 迁移结束时计数是否吻合，迁移结束之前为 `None`。
+    pub verified: Option<bool>,
+    pub error: Option<String>,
+}
+
+/// 迁移过程中记录的状态，计数器的起点在开始迁移时记下，报告中只统计迁移期间的变化。
+struct Progress {
+    state: MigrationState,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+    source_before: usize,
+    moved: usize,
+    /// 开始迁移时 `MigratingQueue::consumed` 与 `MigratingQueue::claimed` 的值。
+    consumed_at_start: usize,
+    claimed_at_start: usize,
+    /// 迁移结束时的计数，结束之后报告不再变化。
+    finished: Option<(usize, usize, usize)>,
+    verified: Option<bool>,
+    error: Option<String>,
+}
+
+/// 切换队列后端期间使用的队列：新提交的任务写入新后端，调度器同时从两个后端取任务，
+/// 旧后端中的任务通过 `start` 分批迁移到新后端。
+///
+/// 通过 `QUEUE_MIGRATE_FROM` 启用。迁移窗口内 API 照常接收任务，但每个任务只写入新后端：
+/// 同时写入两个后端会让两个后端各处理一次同一个任务。
+/// 调度器优先处理旧后端中剩余的任务，旧后端为空时才从新后端取任务。
+///
+/// 迁移时从旧后端取出的任务先写入新后端，成功后才删除旧后端的持久化记录，
+/// 进程在两者之间退出时任务会在重启后再次迁移（新后端按任务 ID 去重）。
+pub struct MigratingQueue {
+    source: Arc<PriorityQueue>,
+    target: Arc<dyn QueueBackend>,
+    /// 调度器从旧后端取出的任务数。
+    consumed: AtomicUsize,
+    /// 恢复或认领到旧后端的任务数。
+    claimed: AtomicUsize,
+    /// 从旧后端出队、认领并计数时持有，迁移结束时据此得到一致的计数。
+    source_lock: tokio::sync::Mutex<()>,
+    progress: Mutex<Progress>,
+}
+
+impl MigratingQueue {
+    pub fn new(source: Arc<PriorityQueue>, target: Arc<dyn QueueBackend>) -> Self {
+        Self {
+            source,
+            target,
+            consumed: AtomicUsize::new(0),
+            claimed: AtomicUsize::new(0),
+            source_lock: tokio::sync::Mutex::new(()),
+            progress: Mutex::new(Progress {
+                state: MigrationState::Idle,
+                started_at: None,
+                finished_at: None,
+                source_before: 0,
+                moved: 0,
+                consumed_at_start: 0,
+                claimed_at_start: 0,
+                finished: None,
+                verified: None,
+                error: None,
+            }),
+        }
+    }
+
+    /// 在后台开始迁移旧后端中的任务；已经在迁移时返回 `false`。
+    ///
+    /// 迁移完成或失败之后可以再次调用，例如迁移失败或其他实例的任务又被认领到旧后端之后。
+    pub async fn start(self: &Arc<Self>) -> bool {
+        // 与出队、认领互斥，开始时的任务数与计数器起点一致
+        let _guard = self.source_lock.lock().await;
+        let source_before = self.source.len().await;
+        {
+            let mut progress = self.progress.lock().unwrap();
+            if progress.state == MigrationState::Running {
+                return false;
+            }
+            *progress = Progress {
+                state: MigrationState::Running,
+                started_at: Some(Utc::now()),
+                finished_at: None,
+                source_before,
+                moved: 0,
+                consumed_at_start: self.consumed.load(Ordering::SeqCst),
+                claimed_at_start: self.claimed.load(Ordering::SeqCst),
+                finished: None,
+                verified: None,
+                error: None,
+            };
+        }
+        let queue = Arc::clone(self);
+        tokio::spawn(async move { queue.run().await });
+        true
+    }
+
+    /// 逐批把旧后端中的任务写入新后端，直到旧后端为空或出错。
+    async fn run(&self) {
+        let result = self.migrate().await;
+        let _guard = self.source_lock.lock().await;
+        let remaining = self.source.len().await;
+        let (consumed, claimed) = self.counts_since_start();
+        let mut progress = self.progress.lock().unwrap();
+        progress.finished_at = Some(Utc::now());
+        progress.finished = Some((consumed, claimed, remaining));
+        match result {
+            Ok(()) => {
+                let verified =
+                    remaining == 0 && progress.source_before + claimed == progress.moved + consumed;
+                progress.state = MigrationState::Completed;
+                progress.verified = Some(verified);
+                if verified {
+                    tracing::info!(
+                        moved = progress.moved,
+                        consumed,
+                        claimed,
+                        "队列迁移完成，计数校验通过"
+                    );
+                } else {
+                    tracing::error!(
+                        source_before = progress.source_before,
+                        moved = progress.moved,
+                        consumed,
+                        claimed,
+                        remaining,
+                        "队列迁移完成，但计数不一致"
+                    );
+                }
+            }
+            Err(e) => {
+                tracing::error!(moved = progress.moved, remaining, "队列迁移失败: {}", e);
+                progress.state = MigrationState::Failed;
+                progress.verified = Some(false);
+                progress.error = Some(e.to_string());
+            }
+        }
+    }
+
+    async fn migrate(&self) -> Result<(), QueueError> {
+        loop {
+            let batch = self.source.drain(MIGRATION_BATCH).await;
+            if batch.is_empty() {
+                return Ok(());
+            }
+            let mut batch = batch.into_iter();
+            while let Some(task) = batch.next() {
+                let id = task.id;
+                match self.target.reinsert(task.clone()).await {
+                    Ok(()) | Err(QueueError::DuplicateKey(_)) => {
+                        self.source.ack(&id).await;
+                        metrics::counter("queue_migrated_tasks_total").inc();
+                        self.progress.lock().unwrap().moved += 1;
+                    }
+                    Err(e) => {
+                        // 本批剩余的任务放回旧后端，持久化记录仍然保留
+                        for task in std::iter::once(task).chain(batch) {
+                            let id = task.id;
+                            if let Err(e) = self.source.reinsert(task).await {
+                                tracing::error!(task_id = %id, "无法将任务放回旧队列后端: {}", e);
+                            }
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+            tracing::info!(
+                moved = self.progress.lock().unwrap().moved,
+                "队列迁移进行中"
+            );
+        }
+    }
+
+    /// 开始迁移以来调度器从旧后端取出的任务数与认领到旧后端的任务数。
+    fn counts_since_start(&self) -> (usize, usize) {
+        let progress = self.progress.lock().unwrap();
+        (
+            self.consumed.load(Ordering::SeqCst) - progress.consumed_at_start,
+            self.claimed.load(Ordering::SeqCst) - progress.claimed_at_start,
+        )
+    }
+
+    /// 当前的迁移进度。
+    pub async fn report(&self) -> MigrationReport {
+        let live_remaining = self.source.len().await;
+        let target_len = self.target.len().await;
+        let (live_consumed, live_claimed) = self.counts_since_start();
+        let progress = self.progress.lock().unwrap();
+        let (consumed, claimed, remaining) =
+            match progress.state {
+                MigrationState::Idle => (0, 0, live_remaining),
+                MigrationState::Running => (live_consumed, live_claimed, live_remaining),
+                MigrationState::Completed | MigrationState::Failed => progress
+                    .finished
+                    .unwrap_or((live_consumed, live_claimed, live_remaining)),
+            };
+        MigrationReport {
+            state: progress.state,
+            source: self.source.name(),
+            target: self.target.name(),
+            started_at: progress.started_at,
+            finished_at: progress.finished_at,
+            source_before: progress.source_before,
+            claimed,
+            moved: progress.moved,
+            consumed,
+            remaining,
+            target_len,
+            verified: progress.verified,
+            error: progress.error.clone(),
+        }
+    }
+
+    /// 按任务所在的后端拆分 ID 筛选条件，不在旧后端中的 ID 交给新后端判断是否存在。
+    async fn split_ids(&self, ids: &[Uuid]) -> (Vec<Uuid>, Vec<Uuid>) {
+        let mut in_source = Vec::new();
+        let mut in_target = Vec::new();
+        for id in ids {
+            match self.source.position(id).await {
+                Ok(_) => in_source.push(*id),
+                Err(_) => in_target.push(*id),
+            }
+        }
+        (in_source, in_target)
+    }
+}
+
+impl QueueBackend for MigratingQueue {
+    fn name(&self) -> &'static str {
+        self.target.name()
+    }
+
+    fn is_durable(&self) -> bool {
+        self.source.is_journaled() && self.target.is_durable()
+    }
+
+    fn restore(&self) -> BoxFuture<'_, Result<Vec<Task>, QueueError>> {
+        Box::pin(async move {
+            let mut tasks = {
+                let _guard = self.source_lock.lock().await;
+                let tasks = self.source.restore().await?;
+                self.claimed.fetch_add(tasks.len(), Ordering::SeqCst);
+                tasks
+            };
+            tasks.extend(self.target.restore().await?);
+            Ok(tasks)
+        })
+    }
+
+    fn claim_orphans(&self, limit: i64) -> BoxFuture<'_, Result<Vec<Task>, QueueError>> {
+        Box::pin(async move {
+            let mut tasks = {
+                let _guard = self.source_lock.lock().await;
+                let tasks = self.source.claim_orphans(limit).await?;
+                self.claimed.fetch_add(tasks.len(), Ordering::SeqCst);
+                tasks
+            };
+            tasks.extend(self.target.claim_orphans(limit).await?);
+            Ok(tasks)
+        })
+    }
+
+    fn ack<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, ()> {
+        // 调用方不知道任务来自哪个后端，两个后端都确认；不在其中的后端忽略该 ID
+        Box::pin(async move {
+            self.source.ack(id).await;
+            self.target.ack(id).await;
+        })
+    }
+
+    fn push(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>> {
+        self.target.push(task)
+    }
+
+    fn try_push(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>> {
+        self.target.try_push(task)
+    }
+
+    fn try_push_all(&self, tasks: Vec<Task>) -> BoxFuture<'_, Result<(), QueueError>> {
+        self.target.try_push_all(tasks)
+    }
+
+    fn reinsert(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>> {
+        self.target.reinsert(task)
+    }
+
+    fn pop_wait(&self, timeout: Duration) -> BoxFuture<'_, Option<Task>> {
+        Box::pin(async move {
+            {
+                let _guard = self.source_lock.lock().await;
+                if let Some(task) = self.source.pop().await {
+                    self.consumed.fetch_add(1, Ordering::SeqCst);
+                    return Some(task);
+                }
+            }
+            self.target.pop_wait(timeout).await
+        })
+    }
+
+    fn close(&self) -> BoxFuture<'_, usize> {
+        Box::pin(async move { self.source.close().await + self.target.close().await })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.target.is_closed()
+    }
+
+    fn len(&self) -> BoxFuture<'_, usize> {
+        Box::pin(async move { self.source.len().await + self.target.len().await })
+    }
+
+    fn len_by_class(&self) -> BoxFuture<'_, [(PriorityClass, usize); 3]> {
+        Box::pin(async move {
+            let mut lens = self.target.len_by_class().await;
+            for ((_, len), (_, source_len)) in lens.iter_mut().zip(self.source.len_by_class().await)
+            {
+                *len += source_len;
+            }
+            lens
+        })
+    }
+
+    fn check_band_capacity(&self, priority: u8) -> BoxFuture<'_, Result<(), QueueError>> {
+        self.target.check_band_capacity(priority)
+    }
+
+    fn count_at_or_above(&self, priority: u8) -> BoxFuture<'_, usize> {
+        Box::pin(async move {
+            self.source.count_at_or_above(priority).await
+                + self.target.count_at_or_above(priority).await
+        })
+    }
+
+    fn position<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, Result<QueuePosition, QueueError>> {
+        Box::pin(async move {
+            match self.source.position(id).await {
+                Err(QueueError::NotFound(_)) => {
+                    // 旧后端中的任务全部先于新后端的任务出队
+                    let mut position = self.target.position(id).await?;
+                    position.tasks_ahead += self.source.len().await;
+                    Ok(position)
+                }
+                result => result,
+            }
+        })
+    }
+
+    fn starving<'a>(
+        &'a self,
+        thresholds: &'a StarvationThresholds,
+        limit: usize,
+    ) -> BoxFuture<'a, Vec<StarvingTask>> {
+        Box::pin(async move {
+            let mut starving = self.source.starving(thresholds, limit).await;
+            starving.extend(self.target.starving(thresholds, limit).await);
+            rank_starving(starving, limit)
+        })
+    }
+
+    fn rebalance<'a>(
+        &'a self,
+        filter: &'a RebalanceFilter,
+        priority: u8,
+        dry_run: bool,
+    ) -> BoxFuture<'a, Result<RebalanceOutcome, QueueError>> {
+        // 先修改新后端：不在任何一个后端中的 ID 在这里返回 `NotFound`，此时两个后端都没有被修改
+        Box::pin(async move {
+            let (source_filter, target_filter) = if filter.ids.is_empty() {
+                (Some(filter.clone()), Some(filter.clone()))
+            } else {
+                let (in_source, in_target) = self.split_ids(&filter.ids).await;
+                let with_ids = |ids: Vec<Uuid>| {
+                    (!ids.is_empty()).then(|| RebalanceFilter {
+                        ids,
+                        ..filter.clone()
+                    })
+                };
+                (with_ids(in_source), with_ids(in_target))
+            };
+            let mut outcome = match &target_filter {
+                Some(filter) => self.target.rebalance(filter, priority, dry_run).await?,
+                None => RebalanceOutcome {
+                    matched: 0,
+                    changed: Vec::new(),
+                },
+            };
+            if let Some(filter) = &source_filter {
+                let source = self.source.rebalance(filter, priority, dry_run).await?;
+                outcome.matched += source.matched;
+                outcome.changed.extend(source.changed);
+            }
+            Ok(outcome)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn task(priority: u8) -> Task {
+        Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        }
+    }
+
+    async fn wait_finished(queue: &MigratingQueue) -> MigrationReport {
+        for _ in 0..100 {
+            let report = queue.report().await;
+            if report.state != MigrationState::Running {
+                return report;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("迁移没有在预期时间内结束");
+    }
+
+    /// 测试迁移窗口：新任务只写入新后端，调度器先处理旧后端的任务，
+    /// 迁移把剩余的任务（包括延迟任务）移到新后端并校验计数。
+    #[tokio::test]
+    async fn test_migrate_local_queue() {
+        let source = Arc::new(PriorityQueue::new());
+        let target = Arc::new(PriorityQueue::new());
+        for priority in [10, 20, 30] {
+            source.push(task(priority)).await.unwrap();
+        }
+        let delayed = Task {
+            run_at: Some(Utc::now() + chrono::Duration::hours(1)),
+            ..task(40)
+        };
+        source.push(delayed.clone()).await.unwrap();
+        let queue = Arc::new(MigratingQueue::new(source.clone(), target.clone()));
+
+        let submitted = task(255);
+        queue.push(submitted.clone()).await.unwrap();
+        assert_eq!(source.len().await, 4);
+        assert_eq!(target.len().await, 1);
+        assert_eq!(queue.len().await, 5);
+        assert_eq!(queue.report().await.state, MigrationState::Idle);
+
+        // 旧后端中的任务先出队，即使新后端中的任务优先级更高
+        let popped = queue.pop_wait(Duration::from_millis(10)).await.unwrap();
+        assert_eq!(popped.priority, 30);
+        let position = queue.position(&submitted.id).await.unwrap();
+        assert_eq!(position.tasks_ahead, 3);
+
+        assert!(queue.start().await);
+        let report = wait_finished(&queue).await;
+        assert_eq!(report.state, MigrationState::Completed);
+        assert_eq!(report.source_before, 3);
+        assert_eq!(report.moved, 3);
+        assert_eq!(report.consumed, 0);
+        assert_eq!(report.remaining, 0);
+        assert_eq!(report.target_len, 4);
+        assert_eq!(report.verified, Some(true));
+        assert_eq!(source.len().await, 0);
+        assert_eq!(
+            target.position(&delayed.id).await.unwrap().run_at,
+            delayed.run_at
+        );
+
+        // 迁移之后按新后端的优先级出队
+        let popped = queue.pop_wait(Duration::from_millis(10)).await.unwrap();
+        assert_eq!(popped.id, submitted.id);
+    }
+
+    /// 测试新后端拒绝写入时迁移失败，未迁移的任务留在旧后端，可以再次迁移。
+    #[tokio::test]
+    async fn test_migrate_failure_keeps_tasks() {
+        let source = Arc::new(PriorityQueue::new());
+        let target = Arc::new(PriorityQueue::new());
+        for priority in [10, 20] {
+            source.push(task(priority)).await.unwrap();
+        }
+        let queue = Arc::new(MigratingQueue::new(source.clone(), target.clone()));
+        target.close().await;
+
+        assert!(queue.start().await);
+        let report = wait_finished(&queue).await;
+        assert_eq!(report.state, MigrationState::Failed);
+        assert_eq!(report.moved, 0);
+        assert_eq!(report.remaining, 2);
+        assert_eq!(report.verified, Some(false));
+        assert!(report.error.is_some());
+        assert_eq!(source.len().await, 2);
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 超过该数量的客户端被跟踪时，清理令牌已经补满的客户端，避免占用的内存无限增长。
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// 按客户端限流的配置。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateLimitSettings {
+    /// 每个客户端每秒补充的请求数，0 表示不限流。
+    pub per_second: u32,
+    /// 每个客户端允许的突发请求数（令牌桶的容量）。
+    pub burst: u32,
+}
+
+/// 单个客户端的令牌桶：`(剩余令牌数, 上次补充的时间)`。
+type Bucket = (f64, Instant);
+
+/// 按客户端（API 令牌或 IP 地址）的令牌桶限流器。
+///
+/// 每个客户端的桶最多容纳 `burst` 个令牌，以每秒 `per_second` 个的速度补充，
+/// 每个请求消耗一个令牌，令牌不足时拒绝请求并给出补充一个令牌所需的时间。
+/// 限流只在本实例内进行，多实例部署时每个实例单独计数。
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self {
+            settings,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 是否启用了限流。
+    pub fn is_enabled(&self) -> bool {
+        self.settings.per_second > 0
+    }
+
+    /// 为客户端 `key` 消耗一个令牌；令牌不足时返回需要等待的时长。
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let rate = f64::from(self.settings.per_second);
+        let capacity = f64::from(self.settings.burst.max(1));
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(key) {
+            // 令牌已经补满的桶与新建的桶没有区别，可以丢弃
+            buckets.retain(|_, (tokens, last)| {
+                *tokens + now.duration_since(*last).as_secs_f64() * rate < capacity
+            });
+        }
+        let (tokens, last) = buckets.entry(key.to_string()).or_insert((capacity, now));
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * rate).min(capacity);
+        *last = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - *tokens) / rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试突发请求用完后被拒绝，令牌按速度补充，不同客户端互不影响。
+    #[tokio::test(start_paused = true)]
+    async fn test_check() {
+        let limiter = RateLimiter::new(RateLimitSettings {
+            per_second: 2,
+            burst: 3,
+        });
+        for _ in 0..3 {
+            assert!(limiter.check("ip:10.0.0.1").is_ok());
+        }
+        let wait = limiter.check("ip:10.0.0.1").unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+        assert!(limiter.check("ip:10.0.0.2").is_ok());
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(limiter.check("ip:10.0.0.1").is_ok());
+        assert!(limiter.check("ip:10.0.0.1").is_err());
+
+        // 长时间空闲后最多恢复到 `burst` 个令牌
+        tokio::time::advance(Duration::from_secs(60)).await;
+        for _ in 0..3 {
+            assert!(limiter.check("ip:10.0.0.1").is_ok());
+        }
+        assert!(limiter.check("ip:10.0.0.1").is_err());
+    }
+
+    /// 测试未启用时不限流。
+    #[test]
+    fn test_disabled() {
+        let limiter = RateLimiter::new(RateLimitSettings::default());
+        assert!(!limiter.is_enabled());
+        for _ in 0..100 {
+            assert!(limiter.check("ip:10.0.0.1").is_ok());
+        }
+    }
+}
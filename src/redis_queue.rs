@@ -0,0 +1,1219 @@
+use crate::metrics;
+use crate::queue::{
+    rank_starving, BandCapacities, OnFull, PriorityClass, QueueBackend, QueueError, QueueHooks,
+    QueuePosition, RebalanceFilter, RebalanceOutcome, StarvationThresholds, StarvingTask, Task,
+    FULL_RETRY_AFTER,
+};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use redis::aio::ConnectionManager;
+use redis::{RedisError, Script};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// 等待其他实例入队的任务或腾出的名额时的轮询间隔，本实例的入队与出队会立即唤醒等待者。
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// 每次出队时最多将多少个到期的延迟任务移入就绪集合。
+const PROMOTE_BATCH: usize = 100;
+/// 就绪集合的分数中入队序号占用的位数，高位是优先级的反码。
+///
+/// 分数最大为 `255 << 44 | (2^44 - 1)`，小于 2^53，能被 Redis 有序集合的双精度分数精确表示。
+const SEQ_BITS: u32 = 44;
+const SEQ_MASK: u64 = (1 << SEQ_BITS) - 1;
+
+/// 入队：检查重复与容量，写入任务与元数据，加入就绪或延迟集合。
+///
+/// KEYS: ready, delayed, tasks, meta, bands, processing
+/// ARGV: id, score, run_at_ms（空表示立即就绪）, task_json, meta, class, capacity, band_capacity, check_capacity
+const PUSH_SCRIPT: &str = r"
+local id = ARGV[1]
+if redis.call('ZSCORE', KEYS[1], id) or redis.call('ZSCORE', KEYS[2], id) then
+  return 'duplicate'
+end
+if ARGV[9] == '1' then
+  local capacity = tonumber(ARGV[7])
+  if capacity > 0 and redis.call('ZCARD', KEYS[1]) + redis.call('ZCARD', KEYS[2]) >= capacity then
+    return 'full'
+  end
+  local band_capacity = tonumber(ARGV[8])
+  if band_capacity > 0 and tonumber(redis.call('HGET', KEYS[5], ARGV[6]) or '0') >= band_capacity then
+    return 'band_full'
+  end
+end
+redis.call('HSET', KEYS[3], id, ARGV[4])
+redis.call('HSET', KEYS[4], id, ARGV[5])
+redis.call('ZREM', KEYS[6], id)
+if ARGV[3] == '' then
+  redis.call('ZADD', KEYS[1], ARGV[2], id)
+else
+  redis.call('ZADD', KEYS[2], ARGV[3], id)
+end
+redis.call('HINCRBY', KEYS[5], ARGV[6], 1)
+return 'ok'
+";
+
+/// 整组入队：先检查组内与队列中的重复、总容量与各档位容量，全部通过后才写入整组任务，
+/// 任何一项检查失败时一个任务也不写入。返回 `{结果}`，失败时附带出错任务在组内的下标（从 0 开始）。
+///
+/// KEYS: ready, delayed, tasks, meta, bands, processing
+/// ARGV: capacity, 之后每个任务 7 项：id, score, run_at_ms（空表示立即就绪）, task_json, meta, class, band_capacity
+const PUSH_ALL_SCRIPT: &str = r"
+local n = (#ARGV - 1) / 7
+local capacity = tonumber(ARGV[1])
+if capacity > 0 and redis.call('ZCARD', KEYS[1]) + redis.call('ZCARD', KEYS[2]) + n > capacity then
+  return {'full'}
+end
+local seen = {}
+local added = {}
+for i = 0, n - 1 do
+  local base = 1 + i * 7
+  local id = ARGV[base + 1]
+  if seen[id] or redis.call('ZSCORE', KEYS[1], id) or redis.call('ZSCORE', KEYS[2], id) then
+    return {'duplicate', tostring(i)}
+  end
+  seen[id] = true
+  local class = ARGV[base + 6]
+  added[class] = (added[class] or 0) + 1
+  local band_capacity = tonumber(ARGV[base + 7])
+  if band_capacity > 0 and tonumber(redis.call('HGET', KEYS[5], class) or '0') + added[class] > band_capacity then
+    return {'band_full', tostring(i)}
+  end
+end
+for i = 0, n - 1 do
+  local base = 1 + i * 7
+  local id = ARGV[base + 1]
+  redis.call('HSET', KEYS[3], id, ARGV[base + 4])
+  redis.call('HSET', KEYS[4], id, ARGV[base + 5])
+  redis.call('ZREM', KEYS[6], id)
+  if ARGV[base + 3] == '' then
+    redis.call('ZADD', KEYS[1], ARGV[base + 2], id)
+  else
+    redis.call('ZADD', KEYS[2], ARGV[base + 3], id)
+  end
+  redis.call('HINCRBY', KEYS[5], ARGV[base + 6], 1)
+end
+return {'ok'}
+";
+
+/// 出队：先把到期的延迟任务移入就绪集合，再弹出分数最小（优先级最高、最早入队）的任务，
+/// 记入处理中集合，分数为租约到期时刻。
+///
+/// KEYS: ready, delayed, tasks, meta, bands, processing
+/// ARGV: now_ms, lease_deadline_ms, promote_batch
+const POP_SCRIPT: &str = r"
+local due = redis.call('ZRANGEBYSCORE', KEYS[2], '-inf', ARGV[1], 'LIMIT', 0, ARGV[3])
+for _, id in ipairs(due) do
+  redis.call('ZREM', KEYS[2], id)
+  local meta = redis.call('HGET', KEYS[4], id)
+  if meta then
+    redis.call('ZADD', KEYS[1], string.match(meta, '^%d+:(%d+):'), id)
+  end
+end
+local popped = redis.call('ZPOPMIN', KEYS[1])
+if #popped == 0 then
+  return false
+end
+local id = popped[1]
+local meta = redis.call('HGET', KEYS[4], id) or ''
+local class = string.match(meta, ':(%a+)$')
+if class then
+  redis.call('HINCRBY', KEYS[5], class, -1)
+end
+redis.call('ZADD', KEYS[6], ARGV[2], id)
+return {id, redis.call('HGET', KEYS[3], id) or '', meta}
+";
+
+/// 确认：移出处理中集合；任务没有被重新放回队列时删除任务与元数据。
+///
+/// KEYS: processing, ready, delayed, tasks, meta
+/// ARGV: id
+const ACK_SCRIPT: &str = r"
+redis.call('ZREM', KEYS[1], ARGV[1])
+if not redis.call('ZSCORE', KEYS[2], ARGV[1]) and not redis.call('ZSCORE', KEYS[3], ARGV[1]) then
+  redis.call('HDEL', KEYS[4], ARGV[1])
+  redis.call('HDEL', KEYS[5], ARGV[1])
+end
+return 1
+";
+
+/// 接管：把租约已过期的处理中任务放回就绪集合，返回它们的 ID。
+///
+/// KEYS: processing, ready, meta, bands
+/// ARGV: now_ms, limit
+const RECLAIM_SCRIPT: &str = r"
+local expired = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+local reclaimed = {}
+for _, id in ipairs(expired) do
+  redis.call('ZREM', KEYS[1], id)
+  local meta = redis.call('HGET', KEYS[3], id)
+  if meta then
+    local score, class = string.match(meta, '^%d+:(%d+):%d+:(%a+)$')
+    redis.call('ZADD', KEYS[2], score, id)
+    redis.call('HINCRBY', KEYS[4], class, 1)
+    table.insert(reclaimed, id)
+  end
+end
+return reclaimed
+";
+
+/// 修改排队任务的优先级；任务已经不在队列中时返回 0。
+///
+/// KEYS: ready, delayed, meta, bands
+/// ARGV: id, new_meta, new_score, new_class
+const REPRIORITIZE_SCRIPT: &str = r"
+local meta = redis.call('HGET', KEYS[3], ARGV[1])
+if not meta then
+  return 0
+end
+local in_ready = redis.call('ZSCORE', KEYS[1], ARGV[1])
+if not in_ready and not redis.call('ZSCORE', KEYS[2], ARGV[1]) then
+  return 0
+end
+redis.call('HSET', KEYS[3], ARGV[1], ARGV[2])
+redis.call('HINCRBY', KEYS[4], string.match(meta, ':(%a+)$'), -1)
+redis.call('HINCRBY', KEYS[4], ARGV[4], 1)
+if in_ready then
+  redis.call('ZADD', KEYS[1], ARGV[3], ARGV[1])
+end
+return 1
+";
+
+/// 就绪集合的分数：优先级越高、入队越早，分数越小，`ZPOPMIN` 弹出的就是下一个应当处理的任务。
+fn ready_score(priority: u8, seq: u64) -> u64 {
+    (u64::from(255 - priority) << SEQ_BITS) | (seq & SEQ_MASK)
+}
+
+/// 优先级不低于 `priority` 的任务的分数上界（不含）。
+fn score_bound(priority: u8) -> u64 {
+    u64::from(256 - u16::from(priority)) << SEQ_BITS
+}
+
+/// 队列使用的键，都以配置的前缀开头。
+struct Keys {
+    /// 就绪任务的有序集合，分数见 `ready_score`。
+    ready: String,
+    /// 延迟任务的有序集合，分数为执行时间（毫秒时间戳）。
+    delayed: String,
+    /// 任务 ID 到任务 JSON 的哈希。
+    tasks: String,
+    /// 任务 ID 到元数据（见 `Meta`）的哈希。
+    meta: String,
+    /// 各优先级档位的排队任务数。
+    bands: String,
+    /// 已出队、尚未确认的任务，分数为租约到期时刻（毫秒时间戳）。
+    processing: String,
+    /// 入队序号的计数器。
+    seq: String,
+}
+
+impl Keys {
+    fn new(prefix: &str) -> Self {
+        Self {
+            ready: format!("{prefix}ready"),
+            delayed: format!("{prefix}delayed"),
+            tasks: format!("{prefix}tasks"),
+            meta: format!("{prefix}meta"),
+            bands: format!("{prefix}bands"),
+            processing: format!("{prefix}processing"),
+            seq: format!("{prefix}seq"),
+        }
+    }
+}
+
+/// 任务的元数据，以 `priority:score:since_ms:class` 的形式保存，脚本据此移动任务与维护档位计数。
+///
+/// 优先级以元数据为准（重新分档只修改元数据），任务 JSON 中的优先级可能是旧值。
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Meta {
+    priority: u8,
+    score: u64,
+    /// 开始排队的时刻（毫秒时间戳），延迟任务为执行时间。
+    since_ms: i64,
+}
+
+impl Meta {
+    fn encode(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            self.priority,
+            self.score,
+            self.since_ms,
+            PriorityClass::from_priority(self.priority).as_str()
+        )
+    }
+
+    fn parse(raw: &str) -> Option<Self> {
+        let mut parts = raw.split(':');
+        Some(Self {
+            priority: parts.next()?.parse().ok()?,
+            score: parts.next()?.parse().ok()?,
+            since_ms: parts.next()?.parse().ok()?,
+        })
+    }
+
+    /// 修改优先级后的元数据，入队序号与开始排队的时刻保持不变。
+    fn with_priority(&self, priority: u8) -> Self {
+        Self {
+            priority,
+            score: ready_score(priority, self.score),
+            since_ms: self.since_ms,
+        }
+    }
+}
+
+fn storage(e: RedisError) -> QueueError {
+    QueueError::Storage(e.to_string())
+}
+
+/// 从任务 JSON 与元数据还原任务。
+fn decode(json: &str, meta: &str) -> Result<(Task, Meta), QueueError> {
+    let meta = Meta::parse(meta)
+        .ok_or_else(|| QueueError::Serialization(format!("无效的任务元数据: {}", meta)))?;
+    let mut task: Task =
+        serde_json::from_str(json).map_err(|e| QueueError::Serialization(e.to_string()))?;
+    task.priority = meta.priority;
+    Ok((task, meta))
+}
+
+fn elapsed_since(since_ms: i64) -> Duration {
+    Duration::from_millis((Utc::now().timestamp_millis() - since_ms).max(0) as u64)
+}
+
+/// 以 Redis 保存的优先级队列，多个实例共享同一组键，进程重启后任务仍然保留。
+///
+/// 就绪任务保存在有序集合中，分数由优先级和全局入队序号组成，出队顺序与 `PriorityQueue` 相同：
+/// 优先级高的先出队，同优先级按入队顺序。入队、出队、确认与接管都由 Lua 脚本原子地完成，
+/// 两个实例不会弹出同一个任务。
+///
+/// 出队的任务进入处理中集合，带有租约；本实例通过 `claim_orphans` 定期续约，
+/// 租约过期（实例失联）的任务被放回就绪集合，由任意实例接管（至少一次）。
+///
+/// 与 `PriorityQueue` 的差异：
+/// - `close` 只封住本实例，其他实例仍可入队和出队；
+/// - 载荷不压缩；
+/// - 其他实例入队的任务最多在 `POLL_INTERVAL` 后被发现；
+/// - `starving` 与 `rebalance` 需要读取整个队列的元数据，重新分档逐个任务原子地修改，而不是整体原子。
+pub struct RedisQueue {
+    conn: ConnectionManager,
+    keys: Keys,
+    push_script: Script,
+    push_all_script: Script,
+    pop_script: Script,
+    ack_script: Script,
+    reclaim_script: Script,
+    reprioritize_script: Script,
+    /// 容量上限，0 表示不限制。
+    capacity: usize,
+    band_capacities: BandCapacities,
+    /// 处理中任务的租约时长。
+    lease: Duration,
+    closed: AtomicBool,
+    /// 本实例有新任务入队或队列被关闭时唤醒在 `pop_wait` 中等待的调度器。
+    notify: Notify,
+    /// 本实例有任务出队或队列被关闭时唤醒在 `push` 中等待名额的调用方。
+    space: Notify,
+    /// 本实例出队、尚未确认的任务，续约时只续这些任务的租约。
+    in_flight: Mutex<HashSet<Uuid>>,
+    hooks: Vec<Arc<dyn QueueHooks>>,
+}
+
+impl RedisQueue {
+    /// 连接 Redis，所有键以 `prefix` 开头；共享同一个队列的实例必须使用相同的前缀。
+    pub async fn connect(url: &str, prefix: &str, lease: Duration) -> Result<Self, QueueError> {
+        let client = redis::Client::open(url).map_err(storage)?;
+        let conn = ConnectionManager::new(client).await.map_err(storage)?;
+        Ok(Self {
+            conn,
+            keys: Keys::new(prefix),
+            push_script: Script::new(PUSH_SCRIPT),
+            push_all_script: Script::new(PUSH_ALL_SCRIPT),
+            pop_script: Script::new(POP_SCRIPT),
+            ack_script: Script::new(ACK_SCRIPT),
+            reclaim_script: Script::new(RECLAIM_SCRIPT),
+            reprioritize_script: Script::new(REPRIORITIZE_SCRIPT),
+            capacity: 0,
+            band_capacities: BandCapacities::default(),
+            lease,
+            closed: AtomicBool::new(false),
+            notify: Notify::new(),
+            space: Notify::new(),
+            in_flight: Mutex::new(HashSet::new()),
+            hooks: Vec::new(),
+        })
+    }
+
+    /// 设置队列的容量上限（所有实例共享），0 表示不限制。
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// 设置各优先级档位的容量上限。
+    pub fn with_band_capacities(mut self, capacities: BandCapacities) -> Self {
+        self.band_capacities = capacities;
+        self
+    }
+
+    /// 注册一组观测回调，按注册顺序调用。
+    pub fn with_hooks(mut self, hooks: Arc<dyn QueueHooks>) -> Self {
+        self.hooks.push(hooks);
+        self
+    }
+
+    fn in_flight(&self) -> std::sync::MutexGuard<'_, HashSet<Uuid>> {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn enqueue(&self, task: Task, on_full: OnFull) -> Result<(), QueueError> {
+        let mut waited = false;
+        loop {
+            // 先注册再尝试，尝试之后、等待之前腾出的名额不会错过
+            let space = self.space.notified();
+            if self.is_closed() {
+                return Err(QueueError::Closed);
+            }
+            match self.insert(&task, on_full != OnFull::Ignore).await {
+                Err(QueueError::Full { .. } | QueueError::BandFull { .. })
+                    if on_full == OnFull::Wait =>
+                {
+                    if !waited {
+                        metrics::counter("queue_push_waits_total").inc();
+                        waited = true;
+                    }
+                    tokio::select! {
+                        _ = space => {}
+                        _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn insert(&self, task: &Task, check_capacity: bool) -> Result<(), QueueError> {
+        let mut conn = self.conn.clone();
+        let seq: u64 = redis::cmd("INCR")
+            .arg(&self.keys.seq)
+            .query_async(&mut conn)
+            .await
+            .map_err(storage)?;
+        let now = Utc::now().timestamp_millis();
+        let run_at = task
+            .run_at
+            .map(|run_at| run_at.timestamp_millis())
+            .filter(|ms| *ms > now);
+        let meta = Meta {
+            priority: task.priority,
+            score: ready_score(task.priority, seq),
+            since_ms: run_at.unwrap_or(now),
+        };
+        let json =
+            serde_json::to_string(task).map_err(|e| QueueError::Serialization(e.to_string()))?;
+        let class = PriorityClass::from_priority(task.priority);
+        let band_capacity = self.band_capacities.for_class(class);
+        let outcome: String = self
+            .push_script
+            .key(&self.keys.ready)
+            .key(&self.keys.delayed)
+            .key(&self.keys.tasks)
+            .key(&self.keys.meta)
+            .key(&self.keys.bands)
+            .key(&self.keys.processing)
+            .arg(task.id.to_string())
+            .arg(meta.score)
+            .arg(run_at.map(|ms| ms.to_string()).unwrap_or_default())
+            .arg(json)
+            .arg(meta.encode())
+            .arg(class.as_str())
+            .arg(self.capacity)
+            .arg(band_capacity)
+            .arg(u8::from(check_capacity))
+            .invoke_async(&mut conn)
+            .await
+            .map_err(storage)?;
+        match outcome.as_str() {
+            "duplicate" => Err(QueueError::DuplicateKey(task.id)),
+            "full" => Err(QueueError::Full {
+                capacity: self.capacity,
+                retry_after: FULL_RETRY_AFTER,
+            }),
+            "band_full" => {
+                metrics::counter_with_labels("queue_band_full_total", &[("class", class.as_str())])
+                    .inc();
+                Err(QueueError::BandFull {
+                    class,
+                    capacity: band_capacity,
+                    retry_after: FULL_RETRY_AFTER,
+                })
+            }
+            _ => {
+                self.in_flight().remove(&task.id);
+                self.hooks
+                    .iter()
+                    .for_each(|h| h.on_push(&task.id, task.priority));
+                self.notify.notify_one();
+                Ok(())
+            }
+        }
+    }
+
+    /// 用一次脚本调用将整组任务加入队列，见 `PUSH_ALL_SCRIPT`。
+    async fn insert_all(&self, tasks: &[Task]) -> Result<(), QueueError> {
+        if self.is_closed() {
+            return Err(QueueError::Closed);
+        }
+        if tasks.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.conn.clone();
+        // 一次预留整组任务的入队序号，组内按提交顺序排列
+        let last: u64 = redis::cmd("INCRBY")
+            .arg(&self.keys.seq)
+            .arg(tasks.len())
+            .query_async(&mut conn)
+            .await
+            .map_err(storage)?;
+        let first = last + 1 - tasks.len() as u64;
+        let now = Utc::now().timestamp_millis();
+        let mut invocation = self.push_all_script.prepare_invoke();
+        invocation
+            .key(&self.keys.ready)
+            .key(&self.keys.delayed)
+            .key(&self.keys.tasks)
+            .key(&self.keys.meta)
+            .key(&self.keys.bands)
+            .key(&self.keys.processing)
+            .arg(self.capacity);
+        for (seq, task) in (first..).zip(tasks) {
+            let run_at = task
+                .run_at
+                .map(|run_at| run_at.timestamp_millis())
+                .filter(|ms| *ms > now);
+            let meta = Meta {
+                priority: task.priority,
+                score: ready_score(task.priority, seq),
+                since_ms: run_at.unwrap_or(now),
+            };
+            let json = serde_json::to_string(task)
+                .map_err(|e| QueueError::Serialization(e.to_string()))?;
+            let class = PriorityClass::from_priority(task.priority);
+            invocation
+                .arg(task.id.to_string())
+                .arg(meta.score)
+                .arg(run_at.map(|ms| ms.to_string()).unwrap_or_default())
+                .arg(json)
+                .arg(meta.encode())
+                .arg(class.as_str())
+                .arg(self.band_capacities.for_class(class));
+        }
+        let outcome: Vec<String> = invocation.invoke_async(&mut conn).await.map_err(storage)?;
+        let failed = outcome
+            .get(1)
+            .and_then(|index| index.parse::<usize>().ok())
+            .and_then(|index| tasks.get(index));
+        match (outcome.first().map(String::as_str), failed) {
+            (Some("full"), _) => Err(QueueError::Full {
+                capacity: self.capacity,
+                retry_after: FULL_RETRY_AFTER,
+            }),
+            (Some("duplicate"), Some(task)) => Err(QueueError::DuplicateKey(task.id)),
+            (Some("band_full"), Some(task)) => {
+                let class = PriorityClass::from_priority(task.priority);
+                metrics::counter_with_labels("queue_band_full_total", &[("class", class.as_str())])
+                    .inc();
+                Err(QueueError::BandFull {
+                    class,
+                    capacity: self.band_capacities.for_class(class),
+                    retry_after: FULL_RETRY_AFTER,
+                })
+            }
+            (Some("ok"), _) => {
+                let mut in_flight = self.in_flight();
+                for task in tasks {
+                    in_flight.remove(&task.id);
+                    self.hooks
+                        .iter()
+                        .for_each(|h| h.on_push(&task.id, task.priority));
+                }
+                drop(in_flight);
+                self.notify.notify_one();
+                Ok(())
+            }
+            _ => Err(QueueError::Storage(format!(
+                "无法识别的入队结果: {:?}",
+                outcome
+            ))),
+        }
+    }
+
+    async fn pop_one(&self) -> Option<Task> {
+        loop {
+            let mut conn = self.conn.clone();
+            let now = Utc::now().timestamp_millis();
+            let deadline = now + self.lease.as_millis() as i64;
+            let popped: Option<(String, String, String)> = match self
+                .pop_script
+                .key(&self.keys.ready)
+                .key(&self.keys.delayed)
+                .key(&self.keys.tasks)
+                .key(&self.keys.meta)
+                .key(&self.keys.bands)
+                .key(&self.keys.processing)
+                .arg(now)
+                .arg(deadline)
+                .arg(PROMOTE_BATCH)
+                .invoke_async(&mut conn)
+                .await
+            {
+                Ok(popped) => popped,
+                Err(e) => {
+                    tracing::warn!("从 Redis 队列出队失败: {}", e);
+                    return None;
+                }
+            };
+            let (id, json, meta) = popped?;
+            self.space.notify_waiters();
+            match decode(&json, &meta) {
+                Ok((task, meta)) => {
+                    self.in_flight().insert(task.id);
+                    let waited = elapsed_since(meta.since_ms);
+                    self.hooks
+                        .iter()
+                        .for_each(|h| h.on_pop(&task.id, task.priority, waited));
+                    return Some(task);
+                }
+                // 无法还原的任务删除后继续弹出下一个，否则它会在租约过期后被反复接管
+                Err(e) => {
+                    tracing::error!(task_id = %id, "{}，任务被丢弃", e);
+                    self.remove(&id).await;
+                    if let Ok(id) = id.parse::<Uuid>() {
+                        self.hooks
+                            .iter()
+                            .for_each(|h| h.on_drop(&id, "deserialization"));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn wait_pop(&self, timeout: Duration) -> Option<Task> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notified = self.notify.notified();
+            if let Some(task) = self.pop_one().await {
+                return Some(task);
+            }
+            let now = tokio::time::Instant::now();
+            if now >= deadline || self.is_closed() {
+                return None;
+            }
+            tokio::select! {
+                _ = notified => {}
+                _ = tokio::time::sleep(POLL_INTERVAL.min(deadline - now)) => {}
+            }
+        }
+    }
+
+    /// 删除已处理结束的任务；删除失败时任务会在租约过期后被再次处理。
+    async fn remove(&self, id: &str) {
+        let mut conn = self.conn.clone();
+        let result: Result<i64, RedisError> = self
+            .ack_script
+            .key(&self.keys.processing)
+            .key(&self.keys.ready)
+            .key(&self.keys.delayed)
+            .key(&self.keys.tasks)
+            .key(&self.keys.meta)
+            .arg(id)
+            .invoke_async(&mut conn)
+            .await;
+        if let Err(e) = result {
+            tracing::warn!(task_id = %id, "从 Redis 删除已处理的任务失败，租约过期后会再次处理: {}", e);
+        }
+    }
+
+    /// 续约本实例正在处理的任务。
+    async fn renew(&self) -> Result<(), QueueError> {
+        let ids: Vec<Uuid> = self.in_flight().iter().copied().collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let deadline = Utc::now().timestamp_millis() + self.lease.as_millis() as i64;
+        let mut cmd = redis::cmd("ZADD");
+        cmd.arg(&self.keys.processing).arg("XX");
+        for id in &ids {
+            cmd.arg(deadline).arg(id.to_string());
+        }
+        cmd.query_async::<_, i64>(&mut self.conn.clone())
+            .await
+            .map_err(storage)?;
+        Ok(())
+    }
+
+    /// 将最多 `limit` 个租约已过期的任务放回就绪集合，返回这些任务。
+    async fn reclaim(&self, limit: i64) -> Result<Vec<Task>, QueueError> {
+        let mut conn = self.conn.clone();
+        let ids: Vec<String> = self
+            .reclaim_script
+            .key(&self.keys.processing)
+            .key(&self.keys.ready)
+            .key(&self.keys.meta)
+            .key(&self.keys.bands)
+            .arg(Utc::now().timestamp_millis())
+            .arg(limit)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(storage)?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (jsons, metas): (Vec<Option<String>>, Vec<Option<String>>) = redis::pipe()
+            .cmd("HMGET")
+            .arg(&self.keys.tasks)
+            .arg(&ids)
+            .cmd("HMGET")
+            .arg(&self.keys.meta)
+            .arg(&ids)
+            .query_async(&mut conn)
+            .await
+            .map_err(storage)?;
+        let mut reclaimed = Vec::with_capacity(ids.len());
+        for ((id, json), meta) in ids.iter().zip(jsons).zip(metas) {
+            match decode(&json.unwrap_or_default(), &meta.unwrap_or_default()) {
+                Ok((task, _)) => {
+                    self.hooks
+                        .iter()
+                        .for_each(|h| h.on_push(&task.id, task.priority));
+                    reclaimed.push(task);
+                }
+                // 留在就绪集合中，出队时再丢弃
+                Err(e) => tracing::warn!(task_id = %id, "接管的任务无法还原: {}", e),
+            }
+        }
+        if !reclaimed.is_empty() {
+            self.notify.notify_one();
+        }
+        Ok(reclaimed)
+    }
+
+    async fn queued_len(&self) -> Result<usize, QueueError> {
+        let (ready, delayed): (usize, usize) = redis::pipe()
+            .cmd("ZCARD")
+            .arg(&self.keys.ready)
+            .cmd("ZCARD")
+            .arg(&self.keys.delayed)
+            .query_async(&mut self.conn.clone())
+            .await
+            .map_err(storage)?;
+        Ok(ready + delayed)
+    }
+
+    async fn class_lens(&self) -> Result<[(PriorityClass, usize); 3], QueueError> {
+        let counts: Vec<Option<i64>> = redis::cmd("HMGET")
+            .arg(&self.keys.bands)
+            .arg(PriorityClass::ALL.map(|class| class.as_str()).as_slice())
+            .query_async(&mut self.conn.clone())
+            .await
+            .map_err(storage)?;
+        let mut lens = PriorityClass::ALL.map(|class| (class, 0));
+        for ((_, len), count) in lens.iter_mut().zip(counts) {
+            *len = count.unwrap_or(0).max(0) as usize;
+        }
+        Ok(lens)
+    }
+
+    async fn check_band(&self, priority: u8) -> Result<(), QueueError> {
+        let class = PriorityClass::from_priority(priority);
+        let capacity = self.band_capacities.for_class(class);
+        if capacity == 0 {
+            return Ok(());
+        }
+        let count: Option<i64> = redis::cmd("HGET")
+            .arg(&self.keys.bands)
+            .arg(class.as_str())
+            .query_async(&mut self.conn.clone())
+            .await
+            .map_err(storage)?;
+        if count.unwrap_or(0) >= capacity as i64 {
+            metrics::counter_with_labels("queue_band_full_total", &[("class", class.as_str())])
+                .inc();
+            return Err(QueueError::BandFull {
+                class,
+                capacity,
+                retry_after: FULL_RETRY_AFTER,
+            });
+        }
+        Ok(())
+    }
+
+    async fn count_ready_below(&self, bound: u64) -> Result<usize, QueueError> {
+        redis::cmd("ZCOUNT")
+            .arg(&self.keys.ready)
+            .arg("-inf")
+            .arg(format!("({}", bound))
+            .query_async(&mut self.conn.clone())
+            .await
+            .map_err(storage)
+    }
+
+    async fn find(&self, id: &Uuid) -> Result<QueuePosition, QueueError> {
+        // 分数以浮点数返回，较大的分数可能使用指数形式
+        let (ready, delayed, meta): (Option<f64>, Option<f64>, Option<String>) = redis::pipe()
+            .cmd("ZSCORE")
+            .arg(&self.keys.ready)
+            .arg(id.to_string())
+            .cmd("ZSCORE")
+            .arg(&self.keys.delayed)
+            .arg(id.to_string())
+            .cmd("HGET")
+            .arg(&self.keys.meta)
+            .arg(id.to_string())
+            .query_async(&mut self.conn.clone())
+            .await
+            .map_err(storage)?;
+        let meta = meta
+            .as_deref()
+            .and_then(Meta::parse)
+            .ok_or(QueueError::NotFound(*id))?;
+        match (ready, delayed) {
+            (Some(score), _) => Ok(QueuePosition {
+                priority: meta.priority,
+                tasks_ahead: self.count_ready_below(score as u64).await?,
+                run_at: None,
+            }),
+            (None, Some(run_at)) => Ok(QueuePosition {
+                priority: meta.priority,
+                tasks_ahead: self.count_ready_below(score_bound(meta.priority)).await?,
+                run_at: DateTime::from_timestamp_millis(run_at as i64),
+            }),
+            (None, None) => Err(QueueError::NotFound(*id)),
+        }
+    }
+
+    /// 读取排队任务（`delayed` 为 `true` 时包括延迟任务）的 ID 与元数据。
+    async fn queued_meta(&self, delayed: bool) -> Result<Vec<(String, Meta)>, QueueError> {
+        let mut conn = self.conn.clone();
+        let mut ids: Vec<String> = redis::cmd("ZRANGE")
+            .arg(&self.keys.ready)
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut conn)
+            .await
+            .map_err(storage)?;
+        if delayed {
+            let more: Vec<String> = redis::cmd("ZRANGE")
+                .arg(&self.keys.delayed)
+                .arg(0)
+                .arg(-1)
+                .query_async(&mut conn)
+                .await
+                .map_err(storage)?;
+            ids.extend(more);
+        }
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let metas: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(&self.keys.meta)
+            .arg(&ids)
+            .query_async(&mut conn)
+            .await
+            .map_err(storage)?;
+        Ok(ids
+            .into_iter()
+            .zip(metas)
+            .filter_map(|(id, meta)| Some((id, Meta::parse(meta.as_deref()?)?)))
+            .collect())
+    }
+
+    async fn find_starving(
+        &self,
+        thresholds: &StarvationThresholds,
+        limit: usize,
+    ) -> Result<Vec<StarvingTask>, QueueError> {
+        let starving = self
+            .queued_meta(false)
+            .await?
+            .into_iter()
+            .filter_map(|(id, meta)| {
+                let class = PriorityClass::from_priority(meta.priority);
+                let threshold = thresholds.for_class(class);
+                let waited = elapsed_since(meta.since_ms);
+                (waited >= threshold).then_some(StarvingTask {
+                    id: id.parse().ok()?,
+                    priority: meta.priority,
+                    class,
+                    waited_ms: waited.as_millis() as u64,
+                    threshold_ms: threshold.as_millis() as u64,
+                })
+            })
+            .collect();
+        Ok(rank_starving(starving, limit))
+    }
+
+    async fn reprioritize(
+        &self,
+        filter: &RebalanceFilter,
+        priority: u8,
+        dry_run: bool,
+    ) -> Result<RebalanceOutcome, QueueError> {
+        let queued = self.queued_meta(true).await?;
+        if let Some(missing) = filter
+            .ids
+            .iter()
+            .find(|id| !queued.iter().any(|(queued, _)| *queued == id.to_string()))
+        {
+            return Err(QueueError::NotFound(*missing));
+        }
+        let mut candidates: Vec<(Uuid, Meta)> = queued
+            .into_iter()
+            .filter_map(|(id, meta)| Some((id.parse().ok()?, meta)))
+            .filter(|(id, meta)| filter.matches_header(id, meta.priority))
+            .collect();
+        if !filter.payload.is_empty() && !candidates.is_empty() {
+            let ids: Vec<String> = candidates.iter().map(|(id, _)| id.to_string()).collect();
+            let jsons: Vec<Option<String>> = redis::cmd("HMGET")
+                .arg(&self.keys.tasks)
+                .arg(&ids)
+                .query_async(&mut self.conn.clone())
+                .await
+                .map_err(storage)?;
+            let mut jsons = jsons.into_iter();
+            candidates.retain(|_| {
+                jsons
+                    .next()
+                    .flatten()
+                    .and_then(|json| serde_json::from_str::<Task>(&json).ok())
+                    .is_some_and(|task| filter.matches_payload(&task.payload))
+            });
+        }
+        let mut outcome = RebalanceOutcome {
+            matched: candidates.len(),
+            changed: Vec::new(),
+        };
+        for (id, meta) in candidates {
+            if meta.priority == priority {
+                continue;
+            }
+            if dry_run {
+                outcome.changed.push(id);
+                continue;
+            }
+            let updated = meta.with_priority(priority);
+            // 筛选之后被其他实例出队的任务不再修改
+            let changed: i64 = self
+                .reprioritize_script
+                .key(&self.keys.ready)
+                .key(&self.keys.delayed)
+                .key(&self.keys.meta)
+                .key(&self.keys.bands)
+                .arg(id.to_string())
+                .arg(updated.encode())
+                .arg(updated.score)
+                .arg(PriorityClass::from_priority(priority).as_str())
+                .invoke_async(&mut self.conn.clone())
+                .await
+                .map_err(storage)?;
+            if changed == 1 {
+                outcome.changed.push(id);
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+impl QueueBackend for RedisQueue {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn is_durable(&self) -> bool {
+        true
+    }
+
+    /// 接管所有租约已过期的任务；本实例上次停机时仍在处理、租约尚未过期的任务由之后的续约轮询接管。
+    fn restore(&self) -> BoxFuture<'_, Result<Vec<Task>, QueueError>> {
+        Box::pin(self.reclaim(i64::MAX))
+    }
+
+    fn claim_orphans(&self, limit: i64) -> BoxFuture<'_, Result<Vec<Task>, QueueError>> {
+        Box::pin(async move {
+            self.renew().await?;
+            if self.is_closed() {
+                return Ok(Vec::new());
+            }
+            self.reclaim(limit).await
+        })
+    }
+
+    fn ack<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            self.in_flight().remove(id);
+            self.remove(&id.to_string()).await;
+        })
+    }
+
+    fn push(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(self.enqueue(task, OnFull::Wait))
+    }
+
+    fn try_push(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(self.enqueue(task, OnFull::Fail))
+    }
+
+    fn try_push_all(&self, tasks: Vec<Task>) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(async move { self.insert_all(&tasks).await })
+    }
+
+    fn reinsert(&self, task: Task) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(self.enqueue(task, OnFull::Ignore))
+    }
+
+    fn pop_wait(&self, timeout: Duration) -> BoxFuture<'_, Option<Task>> {
+        Box::pin(self.wait_pop(timeout))
+    }
+
+    fn close(&self) -> BoxFuture<'_, usize> {
+        Box::pin(async move {
+            self.closed.store(true, Ordering::SeqCst);
+            self.notify.notify_waiters();
+            self.space.notify_waiters();
+            QueueBackend::len(self).await
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    fn len(&self) -> BoxFuture<'_, usize> {
+        Box::pin(async move {
+            self.queued_len().await.unwrap_or_else(|e| {
+                tracing::warn!("读取 Redis 队列长度失败: {}", e);
+                0
+            })
+        })
+    }
+
+    fn len_by_class(&self) -> BoxFuture<'_, [(PriorityClass, usize); 3]> {
+        Box::pin(async move {
+            self.class_lens().await.unwrap_or_else(|e| {
+                tracing::warn!("读取 Redis 队列各档位任务数失败: {}", e);
+                PriorityClass::ALL.map(|class| (class, 0))
+            })
+        })
+    }
+
+    fn check_band_capacity(&self, priority: u8) -> BoxFuture<'_, Result<(), QueueError>> {
+        Box::pin(self.check_band(priority))
+    }
+
+    fn count_at_or_above(&self, priority: u8) -> BoxFuture<'_, usize> {
+        Box::pin(async move {
+            self.count_ready_below(score_bound(priority))
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!("统计 Redis 队列中的任务数失败: {}", e);
+                    0
+                })
+        })
+    }
+
+    fn position<'a>(&'a self, id: &'a Uuid) -> BoxFuture<'a, Result<QueuePosition, QueueError>> {
+        Box::pin(self.find(id))
+    }
+
+    fn starving<'a>(
+        &'a self,
+        thresholds: &'a StarvationThresholds,
+        limit: usize,
+    ) -> BoxFuture<'a, Vec<StarvingTask>> {
+        Box::pin(async move {
+            self.find_starving(thresholds, limit)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!("检查 Redis 队列中排队过久的任务失败: {}", e);
+                    Vec::new()
+                })
+        })
+    }
+
+    fn rebalance<'a>(
+        &'a self,
+        filter: &'a RebalanceFilter,
+        priority: u8,
+        dry_run: bool,
+    ) -> BoxFuture<'a, Result<RebalanceOutcome, QueueError>> {
+        Box::pin(self.reprioritize(filter, priority, dry_run))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::env;
+
+    #[test]
+    fn test_ready_score_order() {
+        // 优先级高的分数小；同优先级按入队序号
+        assert!(ready_score(200, 10) < ready_score(100, 1));
+        assert!(ready_score(100, 1) < ready_score(100, 2));
+        assert!(ready_score(0, SEQ_MASK) < (1 << 53));
+        // 优先级不低于 p 的任务分数都小于上界，更低的都不小于
+        for priority in [0u8, 49, 50, 255] {
+            assert!(ready_score(priority, SEQ_MASK) < score_bound(priority));
+            if priority > 0 {
+                assert!(ready_score(priority - 1, 0) >= score_bound(priority));
+            }
+        }
+    }
+
+    #[test]
+    fn test_meta_roundtrip() {
+        let meta = Meta {
+            priority: 120,
+            score: ready_score(120, 42),
+            since_ms: 1_700_000_000_000,
+        };
+        assert!(meta.encode().ends_with(":normal"));
+        assert_eq!(Meta::parse(&meta.encode()), Some(meta.clone()));
+        // 修改优先级保留入队序号
+        let updated = meta.with_priority(220);
+        assert_eq!(updated.score, ready_score(220, 42));
+        assert_eq!(updated.since_ms, meta.since_ms);
+        assert!(Meta::parse("abc").is_none());
+
+        let json = serde_json::to_string(&Task {
+            id: Uuid::new_v4(),
+            payload: Arc::new(json!({ "n": 1 })),
+            priority: 1,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        })
+        .unwrap();
+        // 优先级以元数据为准
+        let (task, _) = decode(&json, &meta.encode()).unwrap();
+        assert_eq!(task.priority, 120);
+        assert!(matches!(
+            decode(&json, "bad"),
+            Err(QueueError::Serialization(_))
+        ));
+    }
+
+    fn task(priority: u8) -> Task {
+        Task {
+            id: Uuid::new_v4(),
+            payload: Arc::new(json!({ "priority": priority })),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        }
+    }
+
+    /// 需要设置 `REDIS_URL` 后用 `--ignored` 运行，每次使用独立的键前缀。
+    async fn test_queue(lease: Duration) -> RedisQueue {
+        dotenvy::dotenv().ok();
+        let url = env::var("REDIS_URL").expect("REDIS_URL must be set for tests");
+        RedisQueue::connect(&url, &format!("test:{}:", Uuid::new_v4()), lease)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redis_queue_order_and_capacity() {
+        let queue = test_queue(Duration::from_secs(60)).await.with_capacity(3);
+        let (low, high, normal) = (task(10), task(250), task(100));
+        for task in [&low, &high, &normal] {
+            queue.try_push(task.clone()).await.unwrap();
+        }
+        assert!(matches!(
+            queue.try_push(task(1)).await,
+            Err(QueueError::Full { .. })
+        ));
+        assert!(matches!(
+            queue.reinsert(low.clone()).await,
+            Err(QueueError::DuplicateKey(_))
+        ));
+        assert_eq!(queue.position(&low.id).await.unwrap().tasks_ahead, 2);
+        assert_eq!(QueueBackend::count_at_or_above(&queue, 100).await, 2);
+        let order: Vec<Uuid> = [
+            queue.pop_one().await.unwrap(),
+            queue.pop_one().await.unwrap(),
+            queue.pop_one().await.unwrap(),
+        ]
+        .iter()
+        .map(|task| task.id)
+        .collect();
+        assert_eq!(order, vec![high.id, normal.id, low.id]);
+        assert!(queue.pop_one().await.is_none());
+        assert_eq!(QueueBackend::len(&queue).await, 0);
+
+        // 整组入队：放不下或有重复时一个也不写入
+        assert!(matches!(
+            queue
+                .insert_all(&[task(1), task(2), task(3), task(4)])
+                .await,
+            Err(QueueError::Full { .. })
+        ));
+        let repeated = task(5);
+        assert!(matches!(
+            queue.insert_all(&[repeated.clone(), repeated.clone()]).await,
+            Err(QueueError::DuplicateKey(id)) if id == repeated.id
+        ));
+        assert_eq!(QueueBackend::len(&queue).await, 0);
+        let mut dependent = task(200);
+        dependent.depends_on = vec![repeated.id];
+        queue
+            .insert_all(&[repeated.clone(), dependent.clone()])
+            .await
+            .unwrap();
+        assert_eq!(QueueBackend::len(&queue).await, 2);
+        let popped = queue.pop_one().await.unwrap();
+        assert_eq!(popped.depends_on, vec![repeated.id]);
+        assert_eq!(queue.pop_one().await.unwrap().id, repeated.id);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redis_queue_reclaim_and_rebalance() {
+        let queue = test_queue(Duration::ZERO).await;
+        let first = task(10);
+        queue.push(first.clone()).await.unwrap();
+        let popped = queue.pop_one().await.unwrap();
+        assert_eq!(popped.id, first.id);
+        // 租约为零，出队的任务立即可以被接管
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        queue.in_flight().clear();
+        let reclaimed = queue.restore().await.unwrap();
+        assert_eq!(reclaimed.len(), 1);
+        assert_eq!(QueueBackend::len(&queue).await, 1);
+
+        let filter = RebalanceFilter {
+            class: Some(PriorityClass::Low),
+            ..Default::default()
+        };
+        let outcome = queue.rebalance(&filter, 220, false).await.unwrap();
+        assert_eq!(outcome.changed, vec![first.id]);
+        let lens = queue.len_by_class().await;
+        assert_eq!(lens[0].1, 0);
+        assert_eq!(lens[2].1, 1);
+        let popped = queue.pop_one().await.unwrap();
+        assert_eq!(popped.priority, 220);
+        queue.ack(&popped.id).await;
+        assert!(queue.restore().await.unwrap().is_empty());
+    }
+}
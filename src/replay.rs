@@ -0,0 +1,301 @@
+use crate::db::{Database, MemoryStore};
+use crate::handler::{HandlerChain, TaskOutput};
+use crate::log_tail::{CaptureLayer, LogRecord};
+use crate::metrics;
+use crate::queue::Task;
+use crate::scheduler::Handlers;
+use crate::task_types::TaskTypeConfig;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+use tracing::instrument::WithSubscriber;
+use tracing_subscriber::layer::SubscriberExt;
+use uuid::Uuid;
+
+/// 重放的最长执行时间：快照中没有超时时间或超时时间更长时以此为准，避免一次重放长时间占用请求。
+const MAX_REPLAY_DURATION: Duration = Duration::from_secs(60);
+
+/// 一次重放最多收集的日志条数，超出的只计数。
+const MAX_REPLAY_RECORDS: usize = 1000;
+
+/// 任务最终失败时最后一次执行的输入：载荷、生效的任务类型配置与处理器版本，保存在 `task_snapshots` 表中。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionSnapshot {
+    /// 提交任务的租户。
+    pub tenant: String,
+    /// 执行时的任务，包括载荷、重试次数与上下文。
+    pub task: Task,
+    /// 载荷的 SHA-256 摘要（十六进制），用于确认重放的是同一版本的载荷。
+    pub payload_sha256: String,
+    pub task_type: String,
+    /// 执行时是否被分类为慢速任务，未注册处理器的任务据此选择默认处理器。
+    pub slow: bool,
+    /// 处理器名称与版本。
+    pub handler: String,
+    pub handler_version: String,
+    /// 执行时生效的任务类型配置。
+    pub config: TaskTypeConfig,
+    /// 执行时生效的超时时间（毫秒），综合了任务类型配置与载荷中的 `timeout_ms`。
+    pub timeout_ms: Option<u64>,
+    /// 执行时的服务版本。
+    pub service_version: String,
+    /// 最后一次执行的错误。
+    pub error: String,
+    pub captured_at: DateTime<Utc>,
+}
+
+impl ExecutionSnapshot {
+    pub fn capture(
+        task: &Task,
+        tenant: &str,
+        task_type: &str,
+        slow: bool,
+        handler: &HandlerChain,
+        config: &TaskTypeConfig,
+        error: &str,
+    ) -> Self {
+        Self {
+            tenant: tenant.to_string(),
+            task: task.clone(),
+            payload_sha256: payload_digest(task),
+            task_type: task_type.to_string(),
+            slow,
+            handler: handler.handler().name().to_string(),
+            handler_version: handler.handler().version().to_string(),
+            config: config.clone(),
+            timeout_ms: config
+                .timeout_for(&task.payload)
+                .map(|timeout| timeout.as_millis() as u64),
+            service_version: env!("CARGO_PKG_VERSION").to_string(),
+            error: error.to_string(),
+            captured_at: Utc::now(),
+        }
+    }
+}
+
+/// 载荷序列化后的 SHA-256 摘要（十六进制）。
+fn payload_digest(task: &Task) -> String {
+    let payload = serde_json::to_vec(task.payload.as_ref()).unwrap_or_default();
+    Sha256::digest(&payload)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+/// 保存任务的执行快照；保存失败只记录日志，不影响任务失败的处理。
+pub async fn record(db: &Database, snapshot: &ExecutionSnapshot) {
+    match db.insert_task_snapshot(snapshot).await {
+        Ok(()) => metrics::counter("task_snapshots_total").inc(),
+        Err(e) => {
+            tracing::error!(task_id = %snapshot.task.id, "保存任务执行快照失败: {}", e)
+        }
+    }
+}
+
+/// 重放的结果。
+#[derive(Debug, Serialize)]
+pub struct ReplayOutcome {
+    pub task_id: Uuid,
+    pub succeeded: bool,
+    /// 重放失败时的错误。
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    /// 重放使用的处理器及其版本，与快照中的版本不同时 `handler_changed` 为 `true`。
+    pub handler: String,
+    pub handler_version: String,
+    pub handler_changed: bool,
+    /// 重放期间产生的全部日志（包括 DEBUG 与 TRACE 级别），按产生的顺序排列。
+    pub trace: Vec<LogRecord>,
+    /// 超过收集上限而没有记录的日志条数。
+    pub trace_dropped: usize,
+    /// 处理器返回的流式结果：只读取并统计大小，不保存。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<ReplayOutput>,
+}
+
+/// 重放时处理器返回的流式结果的概要。
+#[derive(Debug, Serialize)]
+pub struct ReplayOutput {
+    pub content_type: String,
+    pub size: u64,
+}
+
+/// 在隔离的空跑环境中重新执行快照中的任务。
+///
+/// 处理器使用一个全新的内存数据库，不会读写生产数据；流式结果只读取不保存。重放期间的日志由
+/// 局部的 subscriber 收集，不受 `RUST_LOG` 过滤，也不会写入服务的日志。
+pub async fn replay(handlers: &Handlers, snapshot: &ExecutionSnapshot) -> ReplayOutcome {
+    let handler = handlers.for_task(&snapshot.task_type, snapshot.slow);
+    let db = Database::Memory(MemoryStore::new());
+    let timeout = snapshot
+        .timeout_ms
+        .map(Duration::from_millis)
+        .map_or(MAX_REPLAY_DURATION, |timeout| {
+            timeout.min(MAX_REPLAY_DURATION)
+        });
+    let capture = CaptureLayer::new(MAX_REPLAY_RECORDS);
+    let subscriber = tracing_subscriber::registry().with(capture.clone());
+    let task = &snapshot.task;
+
+    let started = Instant::now();
+    let run = async {
+        tracing::info!(
+            task_id = %task.id,
+            task_type = %snapshot.task_type,
+            retry_count = task.retry_count,
+            "开始重放任务"
+        );
+        let result = match tokio::time::timeout(timeout, drain(handler, task, &db)).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("重放超时（{:?}）", timeout)),
+        };
+        match &result {
+            Ok(_) => tracing::info!(task_id = %task.id, "重放成功"),
+            Err(e) => tracing::error!(task_id = %task.id, "重放失败: {:#}", e),
+        }
+        result
+    };
+    let result = run.with_subscriber(subscriber).await;
+    let duration = started.elapsed();
+    let (trace, trace_dropped) = capture.take();
+    metrics::counter("task_replays_total").inc();
+
+    let handler_version = handler.handler().version().to_string();
+    let (output, error) = match result {
+        Ok(output) => (output, None),
+        Err(e) => (None, Some(format!("{:#}", e))),
+    };
+    ReplayOutcome {
+        task_id: task.id,
+        succeeded: error.is_none(),
+        error,
+        duration_ms: duration.as_millis() as u64,
+        handler: handler.handler().name().to_string(),
+        handler_changed: handler_version != snapshot.handler_version,
+        handler_version,
+        trace,
+        trace_dropped,
+        output,
+    }
+}
+
+/// 执行处理器，并读完它返回的流式结果；读取结果出错与处理失败一样按失败处理。
+async fn drain(
+    handler: &HandlerChain,
+    task: &Task,
+    db: &Database,
+) -> anyhow::Result<Option<ReplayOutput>> {
+    match handler.run(task, db).await? {
+        TaskOutput::Empty => Ok(None),
+        TaskOutput::Stream {
+            content_type,
+            mut body,
+        } => {
+            let mut size = 0;
+            while let Some(chunk) = body.next().await {
+                size += chunk?.len() as u64;
+            }
+            tracing::debug!(task_id = %task.id, size, "已读取处理器返回的结果");
+            Ok(Some(ReplayOutput { content_type, size }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::TaskHandler;
+    use futures::future::BoxFuture;
+    use futures::FutureExt;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// 写入数据库后失败的处理器，并输出 DEBUG 级别的日志。
+    struct FailingHandler;
+
+    impl TaskHandler for FailingHandler {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        fn version(&self) -> &'static str {
+            "2"
+        }
+
+        fn run<'a>(
+            &'a self,
+            task: &'a Task,
+            db: &'a Database,
+        ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+            async move {
+                tracing::debug!(task_id = %task.id, step = "load", "读取载荷");
+                db.save_data(&task.payload).await?;
+                anyhow::bail!("载荷缺少字段 amount")
+            }
+            .boxed()
+        }
+    }
+
+    /// 测试重放使用隔离的数据库、收集 DEBUG 日志，并提示处理器版本的变化。
+    #[tokio::test]
+    async fn test_replay_failed_task() {
+        let handlers = Handlers::new(&[]).register("charge", Arc::new(FailingHandler));
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: Arc::new(json!({"type": "charge", "timeout_ms": 1000})),
+            priority: 5,
+            retry_count: 3,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let config = TaskTypeConfig {
+            max_retries: Some(3),
+            ..Default::default()
+        };
+        let mut snapshot = ExecutionSnapshot::capture(
+            &task,
+            "acme",
+            "charge",
+            false,
+            handlers.for_task("charge", false),
+            &config,
+            "载荷缺少字段 amount",
+        );
+        assert_eq!(snapshot.handler, "failing");
+        assert_eq!(snapshot.handler_version, "2");
+        assert_eq!(snapshot.timeout_ms, Some(1000));
+        assert_eq!(snapshot.payload_sha256.len(), 64);
+
+        let store = MemoryStore::new();
+        let production = Database::Memory(store.clone());
+        record(&production, &snapshot).await;
+        let stored = production.task_snapshot(&task.id).await.unwrap().unwrap();
+        assert_eq!(stored.payload_sha256, snapshot.payload_sha256);
+        assert_eq!(stored.config, config);
+
+        let outcome = replay(&handlers, &stored).await;
+        assert!(!outcome.succeeded);
+        assert!(outcome.error.unwrap().contains("amount"));
+        assert!(!outcome.handler_changed);
+        let debug = outcome
+            .trace
+            .iter()
+            .find(|record| record.message == "读取载荷")
+            .expect("应收集到处理器的 DEBUG 日志");
+        assert_eq!(debug.level, "DEBUG");
+        assert_eq!(debug.fields["step"], "load");
+        // 处理器写入的是重放专用的内存数据库
+        assert_eq!(store.task_count(), 0);
+
+        snapshot.handler_version = "1".into();
+        assert!(replay(&handlers, &snapshot).await.handler_changed);
+    }
+}
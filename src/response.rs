@@ -0,0 +1,172 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+/// 是否将 JSON 响应包装为统一的信封，由 `set_envelope` 在启动时设置，未设置时不包装。
+static ENVELOPE: OnceLock<bool> = OnceLock::new();
+
+/// 设置是否启用响应信封，只在第一次调用时生效。
+pub fn set_envelope(enabled: bool) {
+    let _ = ENVELOPE.set(enabled);
+}
+
+fn envelope_enabled() -> bool {
+    ENVELOPE.get().copied().unwrap_or(false)
+}
+
+/// 将 JSON 响应包装为 `{"data": ..., "meta": {...}, "errors": [...]}`，未启用 `RESPONSE_ENVELOPE` 时原样返回。
+///
+/// 只处理 `Content-Type` 为 `application/json` 的响应，SSE、NDJSON、任务结果、Prometheus 指标等保持不变。
+/// 必须位于 `request_id_middleware` 之内，`meta.request_id` 才能读取到请求 ID。
+pub async fn envelope(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    if !envelope_enabled() {
+        return response;
+    }
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("读取响应体失败，无法包装响应: {}", e);
+            parts.headers.remove(header::CONTENT_LENGTH);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let Ok(body) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    let wrapped = wrap(
+        parts.status,
+        body,
+        crate::web::current_request_id().as_deref(),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(wrapped.to_string()))
+}
+
+/// 按状态码包装响应体：成功时原响应体放在 `data` 中，`errors` 为空；
+/// 失败时 `data` 为 `null`，错误响应中的 `code`、`error` 与字段错误 `details` 转为 `errors` 列表，
+/// `retryable` 移到 `meta` 中。
+fn wrap(status: StatusCode, body: Value, request_id: Option<&str>) -> Value {
+    let mut meta = Map::new();
+    if let Some(request_id) = request_id {
+        meta.insert("request_id".to_string(), request_id.into());
+    }
+    if !(status.is_client_error() || status.is_server_error()) {
+        return json!({ "data": body, "meta": meta, "errors": [] });
+    }
+    if let Some(retryable) = body.get("retryable") {
+        meta.insert("retryable".to_string(), retryable.clone());
+    }
+    let errors: Vec<Value> = match body.get("details").and_then(Value::as_array) {
+        Some(details) => details
+            .iter()
+            .map(|detail| {
+                json!({
+                    "code": detail["code"],
+                    "message": detail["error"],
+                    "field": detail["field"],
+                })
+            })
+            .collect(),
+        None => vec![json!({ "code": body["code"], "message": body["error"] })],
+    };
+    json!({ "data": null, "meta": meta, "errors": errors })
+}
+
+/// `?fields=id,status,priority` 稀疏字段集：只返回列出的顶层字段，未指定时返回全部字段。
+///
+/// 不存在的字段被忽略，不会报错。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fields(Option<BTreeSet<String>>);
+
+impl Fields {
+    /// 解析查询参数 `fields` 中逗号分隔的字段列表，未提供或为空列表时视为未指定。
+    pub fn parse(fields: Option<&str>) -> Self {
+        let fields: BTreeSet<String> = fields
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(str::to_string)
+            .collect();
+        Fields((!fields.is_empty()).then_some(fields))
+    }
+
+    /// 只保留对象中选中的字段；数组按元素逐个处理，其他值原样返回。
+    pub fn apply(&self, value: Value) -> Value {
+        let Some(fields) = &self.0 else {
+            return value;
+        };
+        match value {
+            Value::Object(object) => Value::Object(
+                object
+                    .into_iter()
+                    .filter(|(key, _)| fields.contains(key))
+                    .collect(),
+            ),
+            Value::Array(items) => {
+                Value::Array(items.into_iter().map(|item| self.apply(item)).collect())
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试成功与失败响应的包装，以及稀疏字段集。
+    #[test]
+    fn test_wrap_and_fields() {
+        let ok = wrap(StatusCode::OK, json!({ "id": 1 }), Some("req-1"));
+        assert_eq!(
+            ok,
+            json!({ "data": { "id": 1 }, "meta": { "request_id": "req-1" }, "errors": [] })
+        );
+
+        let error = json!({ "error": "队列已满", "code": "QUEUE_FULL", "retryable": true });
+        let wrapped = wrap(StatusCode::TOO_MANY_REQUESTS, error, None);
+        assert_eq!(wrapped["data"], Value::Null);
+        assert_eq!(wrapped["meta"]["retryable"], true);
+        assert_eq!(
+            wrapped["errors"],
+            json!([{ "code": "QUEUE_FULL", "message": "队列已满" }])
+        );
+        let invalid = json!({
+            "error": "参数无效",
+            "code": "VALIDATION_FAILED",
+            "details": [{ "field": "priority", "code": "OUT_OF_RANGE", "error": "超出范围" }],
+        });
+        let wrapped = wrap(StatusCode::UNPROCESSABLE_ENTITY, invalid, None);
+        assert_eq!(wrapped["errors"][0]["field"], "priority");
+
+        let fields = Fields::parse(Some("id, status,,"));
+        let task = json!({ "id": 1, "status": "queued", "priority": 5 });
+        assert_eq!(
+            fields.apply(task.clone()),
+            json!({ "id": 1, "status": "queued" })
+        );
+        assert_eq!(
+            fields.apply(json!([task.clone()])),
+            json!([{ "id": 1, "status": "queued" }])
+        );
+        assert_eq!(Fields::parse(Some(" , ")).apply(task.clone()), task);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::metrics;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+/// 流式结果的数据块，处理器按块产出，结果存储按块写入，整个结果不会在内存中缓冲。
+pub type ResultStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// 与结果内容一起保存的元数据。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultMeta {
+    /// 下载结果时返回的 `Content-Type`。
+    pub content_type: String,
+    /// 结果的字节数。
+    pub size: u64,
+    /// 结果保存完成的时刻，重试覆盖结果时随之改变。
+    pub saved_at: DateTime<Utc>,
+}
+
+impl ResultMeta {
+    /// 结果的强 ETag，由大小与保存时刻组成，结果被覆盖后随之改变。
+    pub fn etag(&self) -> String {
+        format!("\"{:x}-{:x}\"", self.size, self.saved_at.timestamp_micros())
+    }
+
+    /// `Last-Modified` 响应头的值（HTTP 日期格式）。
+    pub fn last_modified(&self) -> String {
+        self.saved_at
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string()
+    }
+
+    /// `If-Range` 请求头是否仍然指向当前的结果：ETag 按强比较，日期必须与 `Last-Modified` 完全相同。
+    ///
+    /// 不匹配时说明客户端已下载的部分来自被覆盖之前的结果，应当返回完整内容而不是续传。
+    pub fn if_range_matches(&self, if_range: &str) -> bool {
+        let if_range = if_range.trim();
+        if if_range.starts_with('"') || if_range.starts_with("W/") {
+            return if_range == self.etag();
+        }
+        DateTime::parse_from_rfc2822(if_range)
+            .is_ok_and(|date| date.timestamp() == self.saved_at.timestamp())
+    }
+}
+
+/// 保存在本地目录中的任务结果（blob 存储）。
+///
+/// 每个结果对应两个文件：`<任务 ID>` 保存内容，`<任务 ID>.json` 保存元数据。
+/// 两者都先写入临时文件再重命名，内容先于元数据就位，读取方看到元数据时内容一定是完整的。
+/// 多实例部署时各实例需要挂载同一个目录，否则只能从处理该任务的实例下载结果。
+#[derive(Debug, Clone)]
+pub struct ResultStore {
+    dir: PathBuf,
+}
+
+impl ResultStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn data_path(&self, id: &Uuid) -> PathBuf {
+        self.dir.join(id.to_string())
+    }
+
+    fn meta_path(&self, id: &Uuid) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// 边读边写保存任务 `id` 的结果，覆盖已有的结果（例如重试前一次写入的结果）。
+    ///
+    /// 读取 `body` 出错或写入失败时删除已写入的部分并返回错误。
+    pub async fn save(
+        &self,
+        id: &Uuid,
+        content_type: &str,
+        body: ResultStream,
+    ) -> io::Result<ResultMeta> {
+        fs::create_dir_all(&self.dir).await?;
+        let partial = self.dir.join(format!("{}.tmp", id));
+        let size = match write_stream(&partial, body).await {
+            Ok(size) => size,
+            Err(e) => {
+                let _ = fs::remove_file(&partial).await;
+                return Err(e);
+            }
+        };
+        // 先删除旧的元数据，替换内容期间读取方看到的是没有结果，而不是新旧不一致的结果
+        match fs::remove_file(self.meta_path(id)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        fs::rename(&partial, self.data_path(id)).await?;
+
+        let meta = ResultMeta {
+            content_type: content_type.to_string(),
+            size,
+            saved_at: Utc::now(),
+        };
+        let meta_partial = self.dir.join(format!("{}.json.tmp", id));
+        fs::write(&meta_partial, serde_json::to_vec(&meta)?).await?;
+        fs::rename(&meta_partial, self.meta_path(id)).await?;
+        metrics::counter("task_results_saved_total").inc();
+        metrics::counter("task_result_bytes_total").add(size);
+        Ok(meta)
+    }
+
+    /// 打开任务 `id` 的结果，没有结果时返回 `None`。
+    pub async fn open(&self, id: &Uuid) -> io::Result<Option<(ResultMeta, File)>> {
+        let meta = match fs::read(self.meta_path(id)).await {
+            Ok(raw) => serde_json::from_slice::<ResultMeta>(&raw)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let file = File::open(self.data_path(id)).await?;
+        Ok(Some((meta, file)))
+    }
+}
+
+/// 将 `body` 写入 `path`，返回写入的字节数。
+async fn write_stream(path: &Path, mut body: ResultStream) -> io::Result<u64> {
+    let mut file = File::create(path).await?;
+    let mut size = 0u64;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        size += chunk.len() as u64;
+    }
+    file.sync_all().await?;
+    Ok(size)
+}
+
+/// `Range` 请求头选中的字节范围。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// 返回完整内容：没有 `Range` 请求头，或者它无法解析、包含多个范围（按 RFC 9110 可以忽略）。
+    Full,
+    /// 返回 `[start, end]`（闭区间）之间的内容。
+    Partial { start: u64, end: u64 },
+    /// 范围超出内容长度，返回 416。
+    Unsatisfiable,
+}
+
+/// 按 RFC 9110 解析单个字节范围：`bytes=0-499`、`bytes=500-`、`bytes=-500`（最后 500 字节）。
+pub fn byte_range(header: Option<&str>, size: u64) -> ByteRange {
+    let Some(spec) = header.and_then(|value| value.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        // 后缀范围：最后 `suffix` 个字节
+        return match end.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial {
+                start: size.saturating_sub(suffix),
+                end: size - 1,
+            },
+            Err(_) => ByteRange::Full,
+        };
+    }
+    let Ok(start) = start.parse::<u64>() else {
+        return ByteRange::Full;
+    };
+    let end = match end {
+        "" => None,
+        end => match end.parse::<u64>() {
+            Ok(end) if end >= start => Some(end),
+            _ => return ByteRange::Full,
+        },
+    };
+    if start >= size {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial {
+        start,
+        end: end.map_or(size - 1, |end| end.min(size - 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use tokio::io::AsyncReadExt;
+
+    /// 测试各种写法的字节范围。
+    #[test]
+    fn test_byte_range() {
+        let partial = |start, end| ByteRange::Partial { start, end };
+        assert_eq!(byte_range(None, 1000), ByteRange::Full);
+        assert_eq!(byte_range(Some("bytes=0-499"), 1000), partial(0, 499));
+        assert_eq!(byte_range(Some("bytes=500-"), 1000), partial(500, 999));
+        assert_eq!(byte_range(Some("bytes=-200"), 1000), partial(800, 999));
+        assert_eq!(byte_range(Some("bytes=-2000"), 1000), partial(0, 999));
+        assert_eq!(byte_range(Some("bytes=900-5000"), 1000), partial(900, 999));
+        assert_eq!(
+            byte_range(Some("bytes=1000-"), 1000),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(byte_range(Some("bytes=-0"), 1000), ByteRange::Unsatisfiable);
+        assert_eq!(byte_range(Some("bytes=0-"), 0), ByteRange::Unsatisfiable);
+        for ignored in [
+            "items=0-1",
+            "bytes=0-1,5-6",
+            "bytes=5-1",
+            "bytes=x-1",
+            "bytes=-",
+        ] {
+            assert_eq!(
+                byte_range(Some(ignored), 1000),
+                ByteRange::Full,
+                "{}",
+                ignored
+            );
+        }
+    }
+
+    /// 测试分块写入结果后可以完整读回，中途出错时不留下结果。
+    #[tokio::test]
+    async fn test_save_and_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ResultStore::new(dir.path().join("results"));
+        let id = Uuid::new_v4();
+        assert!(store.open(&id).await.unwrap().is_none());
+
+        let chunks = ["id,sku\n", "1,A1\n", "2,B2\n"].map(|chunk| Ok(Bytes::from(chunk)));
+        let meta = store
+            .save(&id, "text/csv", stream::iter(chunks).boxed())
+            .await
+            .unwrap();
+        assert_eq!(meta.size, 17);
+        let (read_meta, mut file) = store.open(&id).await.unwrap().unwrap();
+        assert_eq!(read_meta, meta);
+        let mut content = String::new();
+        file.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "id,sku\n1,A1\n2,B2\n");
+
+        let failed = Uuid::new_v4();
+        let chunks = vec![
+            Ok(Bytes::from("partial")),
+            Err(io::Error::other("生成失败")),
+        ];
+        assert!(store
+            .save(&failed, "text/csv", stream::iter(chunks).boxed())
+            .await
+            .is_err());
+        assert!(store.open(&failed).await.unwrap().is_none());
+        assert!(!dir.path().join(format!("results/{}.tmp", failed)).exists());
+    }
+
+    /// 测试 `If-Range` 按 ETag 或 `Last-Modified` 判断结果是否被覆盖。
+    #[test]
+    fn test_if_range_matches() {
+        let meta = ResultMeta {
+            content_type: "text/csv".to_string(),
+            size: 4096,
+            saved_at: "2024-08-01T09:00:00.250Z".parse().unwrap(),
+        };
+        assert!(meta.if_range_matches(&meta.etag()));
+        assert!(!meta.if_range_matches(&format!("W/{}", meta.etag())));
+        assert!(!meta.if_range_matches("\"1000-0\""));
+        assert_eq!(meta.last_modified(), "Thu, 01 Aug 2024 09:00:00 GMT");
+        assert!(meta.if_range_matches(&meta.last_modified()));
+        assert!(!meta.if_range_matches("Thu, 01 Aug 2024 08:59:59 GMT"));
+        assert!(!meta.if_range_matches("not a date"));
+
+        let replaced = ResultMeta {
+            saved_at: "2024-08-01T09:05:00Z".parse().unwrap(),
+            ..meta.clone()
+        };
+        assert!(!replaced.if_range_matches(&meta.etag()));
+    }
+}
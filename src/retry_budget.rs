@@ -0,0 +1,134 @@
+use crate::metrics;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 重试预算的配置。
+#[derive(Debug, Clone, Copy)]
+pub struct RetryBudgetSettings {
+    /// 统计窗口。
+    pub window: Duration,
+    /// 窗口内重试次数占总尝试次数的上限，例如 0.2 表示 20%。
+    pub ratio: f64,
+    /// 窗口内无论比例如何都允许的重试次数，避免低流量时少量失败就被限流。
+    pub min_retries: u64,
+    /// 超出预算的重试被推迟的时长。
+    pub delay: Duration,
+}
+
+impl Default for RetryBudgetSettings {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            ratio: 0.2,
+            min_retries: 10,
+            delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// 每秒一个桶：`(秒序号, 尝试次数, 重试次数)`。
+type Bucket = (u64, u64, u64);
+
+/// 全局重试预算。
+///
+/// 下游依赖出问题时，大量任务同时失败并立即重试会放大故障。
+/// 预算按滑动窗口统计所有处理尝试，重试次数超过 `ratio` 后，
+/// 新的重试不再立即执行，而是推迟 `delay` 后再入队。
+pub struct RetryBudget {
+    settings: RetryBudgetSettings,
+    origin: Instant,
+    buckets: Mutex<VecDeque<Bucket>>,
+}
+
+impl RetryBudget {
+    pub fn new(settings: RetryBudgetSettings) -> Self {
+        Self {
+            settings,
+            origin: Instant::now(),
+            buckets: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// 超出预算的重试需要推迟的时长。
+    pub fn delay(&self) -> Duration {
+        self.settings.delay
+    }
+
+    /// 记录一次处理尝试（首次执行或重试都算）。
+    pub fn record_attempt(&self) {
+        self.update(|bucket| bucket.1 += 1);
+    }
+
+    /// 申请一次立即重试；预算不足时返回 `false`，调用方应推迟重试。
+    pub fn try_acquire_retry(&self) -> bool {
+        let mut buckets = self.lock_current();
+        let (attempts, retries) = buckets
+            .iter()
+            .fold((0, 0), |(a, r), bucket| (a + bucket.1, r + bucket.2));
+        let allowed = (attempts as f64 * self.settings.ratio) as u64;
+        let granted = retries < allowed.max(self.settings.min_retries);
+        if granted {
+            if let Some(bucket) = buckets.back_mut() {
+                bucket.2 += 1;
+            }
+        } else {
+            metrics::counter("retry_budget_throttled_total").inc();
+        }
+        let retries = retries + u64::from(granted);
+        metrics::gauge("retry_budget_attempts").set(attempts as f64);
+        metrics::gauge("retry_budget_retries").set(retries as f64);
+        metrics::gauge("retry_budget_exhausted").set(if granted { 0.0 } else { 1.0 });
+        granted
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Bucket)) {
+        let mut buckets = self.lock_current();
+        if let Some(bucket) = buckets.back_mut() {
+            f(bucket);
+        }
+    }
+
+    /// 丢弃窗口外的桶并确保当前秒的桶存在，返回持有锁的桶列表。
+    fn lock_current(&self) -> std::sync::MutexGuard<'_, VecDeque<Bucket>> {
+        let now = self.origin.elapsed().as_secs();
+        let window = self.settings.window.as_secs().max(1);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        while buckets
+            .front()
+            .is_some_and(|(sec, _, _)| now.saturating_sub(*sec) >= window)
+        {
+            buckets.pop_front();
+        }
+        if buckets.back().map(|(sec, _, _)| *sec) != Some(now) {
+            buckets.push_back((now, 0, 0));
+        }
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试重试超过比例后被拒绝，窗口滑过后恢复。
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_budget_ratio() {
+        let budget = RetryBudget::new(RetryBudgetSettings {
+            window: Duration::from_secs(10),
+            ratio: 0.2,
+            min_retries: 1,
+            delay: Duration::from_secs(5),
+        });
+        for _ in 0..20 {
+            budget.record_attempt();
+        }
+        // 20 次尝试的 20% 为 4 次重试
+        let granted = (0..10).filter(|_| budget.try_acquire_retry()).count();
+        assert_eq!(granted, 4);
+
+        tokio::time::advance(Duration::from_secs(11)).await;
+        assert!(budget.try_acquire_retry());
+    }
+}
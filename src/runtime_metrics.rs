@@ -0,0 +1,49 @@
+use crate::metrics;
+
+/// 将 Tokio 运行时的指标写入指标注册表，在导出指标前调用。
+///
+/// 稳定版 Tokio 提供工作线程数、存活任务数、全局队列深度以及各工作线程的忙碌时长和停放次数；
+/// 阻塞线程池与轮询次数等指标只在以 `--cfg tokio_unstable` 编译时可用。
+pub fn record() {
+    let runtime = tokio::runtime::Handle::current().metrics();
+    let workers = runtime.num_workers();
+    metrics::gauge("tokio_workers").set(workers as f64);
+    metrics::gauge("tokio_alive_tasks").set(runtime.num_alive_tasks() as f64);
+    metrics::gauge("tokio_global_queue_depth").set(runtime.global_queue_depth() as f64);
+
+    let mut busy_secs = 0.0;
+    let mut parks = 0;
+    for worker in 0..workers {
+        busy_secs += runtime.worker_total_busy_duration(worker).as_secs_f64();
+        parks += runtime.worker_park_count(worker);
+    }
+    // 忙碌时长持续增加而停放次数几乎不变，说明工作线程被耗时的 handler 占满
+    metrics::gauge("tokio_worker_busy_seconds_total").set(busy_secs);
+    metrics::gauge("tokio_worker_park_total").set(parks as f64);
+
+    #[cfg(tokio_unstable)]
+    {
+        metrics::gauge("tokio_blocking_threads").set(runtime.num_blocking_threads() as f64);
+        metrics::gauge("tokio_blocking_queue_depth").set(runtime.blocking_queue_depth() as f64);
+        let polls: u64 = (0..workers).map(|w| runtime.worker_poll_count(w)).sum();
+        metrics::gauge("tokio_worker_poll_total").set(polls as f64);
+        let local_queue: usize = (0..workers)
+            .map(|w| runtime.worker_local_queue_depth(w))
+            .sum();
+        metrics::gauge("tokio_local_queue_depth").set(local_queue as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试运行时指标会出现在导出的指标中。
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_record_runtime_metrics() {
+        record();
+        let rendered = metrics::render();
+        assert!(rendered.contains("tokio_workers 2"));
+        assert!(rendered.contains("tokio_alive_tasks"));
+    }
+}
@@ -1,21 +1,52 @@
-use crate::db::save_data_to_db;
-use crate::queue::{PriorityQueue, Task};
-use sqlx::MySqlPool;
+use crate::admission::SlowAdmission;
+use crate::backpressure::Throughput;
+use crate::classifier::{self, SlowClassifier};
+use crate::db::{self, CircuitState, Database};
+use crate::diagnostics;
+use crate::dlq;
+use crate::handler::{BuiltinMiddleware, HandlerChain, TaskHandler, TaskOutput};
+use crate::lifecycle::{InFlightGuard, Lifecycle};
+use crate::metrics;
+use crate::queue::{PriorityClass, QueueBackend, Task};
+use crate::replay::{self, ExecutionSnapshot};
+use crate::results::ResultStore;
+use crate::retry_budget::RetryBudget;
+use crate::slo::SloTracker;
+use crate::status::{TaskIndex, TaskState};
+use crate::task_types::{TaskTypeConfig, TaskTypeConfigs, TypePermit};
+use crate::watchdog::Heartbeat;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
 
-// 定义任务失败后的最大重试次数
-const MAX_RETRIES: u8 = 3;
+// 定义任务失败后的最大重试次数，任务类型可以通过 `task_type_configs` 单独设置
+pub const MAX_RETRIES: u8 = 3;
+/// 队列为空时每次等待新任务的最长时间，超时后回到循环开头更新心跳。
+const IDLE_WAIT: Duration = Duration::from_secs(1);
+/// 重新入队遇到可重试的队列错误时，最多再尝试的次数。
+const REQUEUE_ATTEMPTS: u32 = 3;
+/// 慢速任务的并发名额用完时，任务推迟的时长。
+const SLOW_SLOT_WAIT: Duration = Duration::from_millis(500);
+/// 通过管理 API 暂停期间，调度器检查是否已恢复的间隔。
+const PAUSE_POLL: Duration = Duration::from_millis(100);
+/// 依赖的任务尚未全部成功时，任务推迟的时长。
+const DEPENDENCY_WAIT: Duration = Duration::from_millis(500);
 
 /// 处理可以快速完成的任务。
 ///
 /// 这个函数会尝试将任务的载荷保存到数据库。
 /// 如果失败，它会返回一个错误，由调用者决定是否重试。
-async fn handle_quick_task(task: &Task, db_pool: &MySqlPool) -> Result<(), anyhow::Error> {
+async fn handle_quick_task(task: &Task, db: &Database) -> anyhow::Result<TaskOutput> {
     tracing::info!(task_id = %task.id, "正在处理快速任务");
-    save_data_to_db(db_pool, &task.payload).await?;
-    Ok(())
+    db.save_data(&task.payload).await?;
+    Ok(TaskOutput::Empty)
 }
 
 /// 处理需要较长时间的慢速任务。
@@ -23,69 +54,786 @@ async fn handle_quick_task(task: &Task, db_pool: &MySqlPool) -> Result<(), anyho
 /// 这个函数会模拟一个耗时操作（如调用第三方 API 或进行复杂计算），
 /// 然后将结果保存到数据库。慢速任务会在一个独立的 Tokio 任务中运行，
 /// 以避免阻塞调度器主循环。
-async fn handle_slow_task(task: Task, db_pool: MySqlPool) {
+async fn handle_slow_task(task: &Task, db: &Database) -> anyhow::Result<TaskOutput> {
     tracing::info!(task_id = %task.id, "正在处理慢速任务");
     // 模拟一个耗时 5 秒的操作
     sleep(Duration::from_secs(5)).await;
-    if let Err(e) = save_data_to_db(&db_pool, &task.payload).await {
-        tracing::error!(task_id = %task.id, "处理慢速任务失败: {}", e);
+    db.save_data(&task.payload).await?;
+    Ok(TaskOutput::Empty)
+}
+
+/// 快速任务的处理器。
+struct QuickTaskHandler;
+
+impl TaskHandler for QuickTaskHandler {
+    fn name(&self) -> &'static str {
+        "quick"
+    }
+
+    fn run<'a>(
+        &'a self,
+        task: &'a Task,
+        db: &'a Database,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+        handle_quick_task(task, db).boxed()
+    }
+}
+
+/// 慢速任务的处理器。
+struct SlowTaskHandler;
+
+impl TaskHandler for SlowTaskHandler {
+    fn name(&self) -> &'static str {
+        "slow"
+    }
+
+    fn run<'a>(
+        &'a self,
+        task: &'a Task,
+        db: &'a Database,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+        handle_slow_task(task, db).boxed()
+    }
+}
+
+/// 调度器使用的处理器注册表，按任务类型（载荷的 `type` 字段）分发，所有处理器共用同一组中间件。
+///
+/// 注册了处理器的任务类型总是交给该处理器，快慢分类只决定它在调度循环中还是在独立的任务中运行；
+/// 其余任务按快慢分类交给默认的快速/慢速处理器。处理器在 `main.rs` 中注册，新增任务类型不需要修改调度器。
+#[derive(Clone)]
+pub struct Handlers {
+    middleware: Vec<BuiltinMiddleware>,
+    by_type: HashMap<String, HandlerChain>,
+    quick: HandlerChain,
+    slow: HandlerChain,
+}
+
+impl Handlers {
+    pub fn new(middleware: &[BuiltinMiddleware]) -> Self {
+        Self {
+            middleware: middleware.to_vec(),
+            by_type: HashMap::new(),
+            quick: HandlerChain::new(Arc::new(QuickTaskHandler), middleware),
+            slow: HandlerChain::new(Arc::new(SlowTaskHandler), middleware),
+        }
+    }
+
+    /// 为任务类型注册处理器；同一类型重复注册时后注册的生效。
+    pub fn register(mut self, task_type: &str, handler: Arc<dyn TaskHandler>) -> Self {
+        let chain = HandlerChain::new(handler, &self.middleware);
+        if self.by_type.insert(task_type.to_string(), chain).is_some() {
+            tracing::warn!(task_type, "任务类型的处理器被重复注册，使用后注册的处理器");
+        }
+        self
+    }
+
+    /// 是否为任务类型注册了处理器。
+    pub fn is_registered(&self, task_type: &str) -> bool {
+        self.by_type.contains_key(task_type)
+    }
+
+    /// 按名称排序的已注册任务类型及其处理器。
+    pub fn registered(&self) -> Vec<(&str, &dyn TaskHandler)> {
+        let mut registered: Vec<_> = self
+            .by_type
+            .iter()
+            .map(|(task_type, chain)| (task_type.as_str(), chain.handler()))
+            .collect();
+        registered.sort_by_key(|(task_type, _)| *task_type);
+        registered
+    }
+
+    /// 任务类型的默认优先级，未注册或处理器没有默认优先级时为 `None`。
+    pub fn default_priority(&self, task_type: &str) -> Option<u8> {
+        self.by_type
+            .get(task_type)
+            .and_then(|chain| chain.handler().default_priority())
+    }
+
+    /// 任务类型的处理器声明的载荷 JSON Schema，未注册或没有声明时为 `None`。
+    pub fn payload_schema(&self, task_type: &str) -> Option<serde_json::Value> {
+        self.by_type
+            .get(task_type)
+            .and_then(|chain| chain.handler().payload_schema())
+    }
+
+    /// 选择处理任务的处理器链：注册了处理器的任务类型使用各自的处理器，其余按快慢分类选择。
+    pub fn for_task(&self, task_type: &str, slow: bool) -> &HandlerChain {
+        match self.by_type.get(task_type) {
+            Some(chain) => chain,
+            None if slow => &self.slow,
+            None => &self.quick,
+        }
     }
 }
 
+/// 调度器运行所需的共享状态，在调度器被监督者重启之间保持不变。
+#[derive(Clone)]
+pub struct SchedulerContext {
+    pub queue: Arc<dyn QueueBackend>,
+    pub db: Database,
+    pub tasks: TaskIndex,
+    pub heartbeat: Heartbeat,
+    pub budget: Arc<RetryBudget>,
+    pub classifier: Arc<SlowClassifier>,
+    pub admission: Arc<SlowAdmission>,
+    pub handlers: Handlers,
+    pub throughput: Arc<Throughput>,
+    /// 按任务类型的重试次数、超时与并发、速率限制，修改后对之后出队的任务生效。
+    pub task_types: Arc<TaskTypeConfigs>,
+    /// 任务最终成功或失败时记录端到端延迟，供按任务类型的 SLO 统计。
+    pub slo: Arc<SloTracker>,
+    /// 处理器返回的流式结果保存在这里。
+    pub results: ResultStore,
+    pub lifecycle: Lifecycle,
+    /// 停机令牌，取消后调度器不再取出新任务，等待正在处理的任务完成。
+    pub shutdown: CancellationToken,
+    /// 并发处理快速任务的工作者数量，为 1 时快速任务在调度循环中依次处理。
+    pub workers: usize,
+    /// 同时处理的慢速任务数量上限，0 表示不限制。
+    pub max_slow_tasks: usize,
+}
+
 /// 运行后台任务调度器。
 ///
 /// 这是一个无限循环，不断地从优先级队列中弹出任务并进行处理。
-pub async fn run_scheduler(queue: Arc<PriorityQueue>, db_pool: MySqlPool) {
-    tracing::info!("调度器已启动");
+/// 每次循环迭代都会更新 `heartbeat`，供看门狗检测调度循环是否卡住；
+/// 任务的状态变化同步写入 `tasks`，供状态查询接口使用。
+/// 失败任务的重试受全局重试预算 `budget` 约束，超出预算的重试会被推迟。
+/// 任务按 `classifier` 的分类交给 `handlers` 中的快速或慢速处理器链处理，
+/// 每次执行的耗时都会反馈给分类器。
+/// 慢速任务开始处理前需要通过 `admission` 申请租户与任务类型的预算，预算用完时任务被推迟。
+/// 任务类型在 `task_types` 中配置了并发或速率限制时，超出限制的任务同样被推迟；
+/// 配置的超时时间与重试次数在每次处理时读取，修改配置不需要重启调度器。
+/// 重试次数用尽的任务写入死信队列（`dead_tasks` 表），可以通过管理 API 重新入队。
+/// 同时处理的慢速任务不超过 `max_slow_tasks` 个，名额用完时出队的慢速任务被推迟，等待名额空出。
+/// 快速任务由最多 `workers` 个工作者并发处理，只有一个工作者时在调度循环中依次处理；
+/// 调度器只在有空闲工作者时出队，保证空闲的工作者总是拿到当时优先级最高的任务。
+/// 正在处理的任务通过 `Lifecycle::track` 登记，供停机流程等待它们完成。
+///
+/// `shutdown` 被取消后调度器立即停止出队，等待自己启动的所有任务处理结束后停在原处，
+/// 由停机流程通过 `Supervisor::shutdown` 停止；等待超时被强制停止时，这些任务随调度器一起被取消，
+/// 它们在 `tasks_queue` 表中的记录没有被删除，会在下次启动时恢复。
+/// 队列中剩余的任务同样保留在 `tasks_queue` 表中。
+pub async fn run_scheduler(context: SchedulerContext) {
+    let shared = context.clone();
+    let SchedulerContext {
+        queue,
+        db,
+        tasks,
+        heartbeat,
+        budget,
+        classifier,
+        admission,
+        handlers,
+        throughput,
+        task_types,
+        slo,
+        results,
+        lifecycle,
+        shutdown,
+        workers: pool_size,
+        max_slow_tasks,
+    } = context;
+    let pool_size = pool_size.max(1);
+    let workers = Arc::new(Semaphore::new(pool_size));
+    // 慢速任务各自在独立的 Tokio 任务中处理，限制同时处理的数量，防止突发时耗尽内存与数据库连接
+    let slow_slots = (max_slow_tasks > 0).then(|| Arc::new(Semaphore::new(max_slow_tasks)));
+    // 调度器启动的所有任务，停机时逐一等待它们结束
+    let mut running = JoinSet::new();
+    // 数据库熔断器打开期间暂停分发
+    let mut paused = false;
+    // 运维人员通过管理 API 暂停分发（`POST /admin/pause`）
+    let mut operator_paused = false;
+    tracing::info!(workers = pool_size, "调度器已启动");
     loop {
-        // 尝试从队列中弹出一个任务
-        if let Some(mut task) = queue.pop().await {
+        heartbeat.beat();
+        // 回收已经处理结束的任务
+        while running.try_join_next().is_some() {}
+        // 通过管理 API 暂停时不出队，任务留在队列中，正在处理的任务继续完成
+        if lifecycle.is_paused() != operator_paused {
+            operator_paused = !operator_paused;
+            if operator_paused {
+                tracing::warn!("调度器已通过管理 API 暂停，停止分发任务");
+            } else {
+                tracing::info!("调度器已通过管理 API 恢复，继续分发任务");
+            }
+            metrics::gauge("scheduler_dispatch_paused").set(if operator_paused || paused {
+                1.0
+            } else {
+                0.0
+            });
+        }
+        if operator_paused {
+            tokio::select! {
+                biased;
+                _ = shutdown.cancelled() => break,
+                _ = sleep(PAUSE_POLL) => {}
+            }
+            continue;
+        }
+        // 数据库熔断器打开时暂停出队，任务留在队列中，不会因为数据库不可用而失败、消耗重试次数；
+        // 等待期过后由调度器发起探测查询，数据库恢复后熔断器关闭，继续分发
+        if let Some(breaker) = db::circuit_breaker() {
+            let state = breaker.state();
+            if state != CircuitState::Closed && !paused {
+                tracing::warn!("数据库熔断器已打开，暂停分发任务");
+                metrics::gauge("scheduler_dispatch_paused").set(1.0);
+                paused = true;
+            }
+            match state {
+                CircuitState::Closed if paused => {
+                    tracing::info!("数据库熔断器已关闭，恢复分发任务");
+                    metrics::gauge("scheduler_dispatch_paused").set(0.0);
+                    paused = false;
+                }
+                CircuitState::Closed => {}
+                CircuitState::Open | CircuitState::HalfOpen => {
+                    // 半开时发起探测查询，成功则熔断器关闭，下一轮恢复分发；
+                    // 失败（或探测名额已被其他查询占用）时与打开状态一样等待一段时间
+                    if state == CircuitState::HalfOpen && db.ping().await.is_ok() {
+                        continue;
+                    }
+                    tokio::select! {
+                        biased;
+                        _ = shutdown.cancelled() => break,
+                        _ = sleep(IDLE_WAIT) => {}
+                    }
+                    continue;
+                }
+            }
+        }
+        // 先等待一个空闲的工作者再出队，工作者都在忙时任务留在队列中按优先级排队；
+        // 最多等待 1 秒，超时后回到循环开头更新心跳
+        let worker = tokio::select! {
+            biased;
+            _ = shutdown.cancelled() => break,
+            acquired = tokio::time::timeout(IDLE_WAIT, workers.clone().acquire_owned()) => {
+                match acquired {
+                    Ok(Ok(worker)) => worker,
+                    _ => continue,
+                }
+            }
+        };
+        // 从队列中弹出一个任务；队列为空时等待新任务入队，最多等待 1 秒以保持心跳。
+        // 只有返回任务的那一次轮询才会真正出队，停机时放弃等待不会丢失任务
+        let task = tokio::select! {
+            // 停机与新任务同时就绪时以停机为准
+            biased;
+            _ = shutdown.cancelled() => break,
+            task = queue.pop_wait(IDLE_WAIT) => task,
+        };
+        if let Some(task) = task {
             tracing::debug!(task_id = %task.id, "从队列中取出一个任务");
-            let db_pool_clone = db_pool.clone();
+            // 依赖的任务全部成功之后才开始处理；依赖失败时本任务不会再有机会成功，直接失败
+            match check_dependencies(&tasks, &db, &task).await {
+                Dependencies::Ready => {}
+                Dependencies::Waiting => {
+                    defer(queue.as_ref(), &tasks, task, DEPENDENCY_WAIT, "dependency").await;
+                    continue;
+                }
+                Dependencies::Failed(dependency) => {
+                    let error = format!("依赖的任务 {} 处理失败", dependency);
+                    tracing::warn!(task_id = %task.id, "{}，任务不再处理", error);
+                    queue.ack(&task.id).await;
+                    record_slo(&slo, &tasks, &task, classifier::task_type(&task), false);
+                    tasks.set_state(&task.id, TaskState::Failed, task.retry_count, Some(error));
+                    continue;
+                }
+            }
+            // 显式指定了执行方式（`TaskKind::Quick`/`Slow`）或配置了覆盖的任务直接按指定方式处理，
+            // 其余根据任务类型的历史耗时决定，样本不足时按快速任务处理
+            let task_type = classifier::task_type(&task).to_string();
+            let slow = classifier.is_slow(&task);
+            let settings = task_types.get(&task_type).await;
+            // 慢速任务的并发名额用完时推迟处理，任务回到队列中排队，不计入重试次数
+            let slow_slot = match slow_slots.as_ref().filter(|_| slow) {
+                Some(slots) => match slots.clone().try_acquire_owned() {
+                    Ok(slot) => Some(slot),
+                    Err(_) => {
+                        metrics::counter("slow_tasks_limited_total").inc();
+                        defer(queue.as_ref(), &tasks, task, SLOW_SLOT_WAIT, "slow_limit").await;
+                        continue;
+                    }
+                },
+                None => None,
+            };
+            // 慢速任务先申请预算，预算用完时推迟处理，不占用本次调度
+            let permit = if slow {
+                let tenant = tenant_of(&tasks, &task);
+                let cost = classifier.estimated_cost(&task_type);
+                match admission.try_admit(&tenant, &task_type, cost) {
+                    Ok(permit) => Some(permit),
+                    Err(reason) => {
+                        defer(
+                            queue.as_ref(),
+                            &tasks,
+                            task,
+                            admission.defer(),
+                            reason.as_str(),
+                        )
+                        .await;
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+            // 任务类型的并发或速率名额用完时推迟处理，不计入重试次数
+            let type_permit = match task_types.try_start(&task_type, &settings) {
+                Ok(permit) => permit,
+                Err(limited) => {
+                    let delay = limited.retry_after().unwrap_or_else(|| admission.defer());
+                    defer(queue.as_ref(), &tasks, task, delay, limited.as_str()).await;
+                    continue;
+                }
+            };
+            let db_clone = db.clone();
             let queue_clone = queue.clone();
+            tasks.set_state(&task.id, TaskState::Running, task.retry_count, None);
+            budget.record_attempt();
+            throughput.record();
+            let in_flight = lifecycle.track();
 
-            // 简单的任务区分逻辑：根据优先级决定如何处理
-            if task.priority > 100 {
-                // 对于高优先级任务，我们假设它们是“慢速任务”，
-                // 在一个新的 Tokio 任务中异步处理，防止阻塞调度器。
-                tokio::spawn(async move {
-                    handle_slow_task(task, db_pool_clone).await;
-                });
-            } else {
-                // 对于普通任务，我们假设它们是“快速任务”，
-                // 直接在当前循环中处理。
-                match handle_quick_task(&task, &db_pool_clone).await {
-                    Ok(_) => tracing::info!(task_id = %task.id, "快速任务处理成功"),
-                    Err(e) => {
-                        // 如果任务处理失败，记录错误并检查是否可以重试
-                        tracing::error!(task_id = %task.id, "处理快速任务失败: {}. 正在重试...", e);
-                        if task.retry_count < MAX_RETRIES {
-                            // 如果重试次数未达上限，增加重试计数并将任务重新推入队列
+            // 数据库连接按任务的优先级档位分配，关键任务可以使用预留连接
+            let class = PriorityClass::from_priority(task.priority);
+
+            let started = Instant::now();
+            let handler = handlers.for_task(&task_type, slow).clone();
+            if slow {
+                // 慢速任务在一个新的 Tokio 任务中异步处理，防止阻塞调度器，不占用工作者。
+                drop(worker);
+                let tasks = tasks.clone();
+                let classifier = classifier.clone();
+                let slo = slo.clone();
+                let results = results.clone();
+                let budget = budget.clone();
+                let max_retries = settings.max_retries.unwrap_or(MAX_RETRIES);
+                running.spawn(db::with_priority_class(class, async move {
+                    let mut task = task;
+                    let timeout = settings.timeout_for(&task.payload);
+                    let result = run_handler(&handler, &task, &db_clone, &results, timeout).await;
+                    classifier.record(&task_type, started.elapsed());
+                    // 超时的慢速任务在重试次数内重新入队，其他错误直接失败
+                    if let Err(e) = &result {
+                        if e.is::<TaskTimedOut>() && task.retry_count < max_retries {
+                            tracing::warn!(task_id = %task.id, "慢速任务{}，重新入队", e);
                             task.retry_count += 1;
-                            queue_clone.push(task).await;
-                        } else {
-                            // 如果已达到最大重试次数，则放弃任务
-                            tracing::error!(task_id = %task.id, "任务在 {} 次重试后失败", MAX_RETRIES);
+                            tasks.set_state(
+                                &task.id,
+                                TaskState::Queued,
+                                task.retry_count,
+                                Some(e.to_string()),
+                            );
+                            retry(queue_clone, &tasks, &budget, task).await;
+                            drop(permit);
+                            drop(type_permit);
+                            drop(slow_slot);
+                            drop(in_flight);
+                            return;
                         }
                     }
+                    queue_clone.ack(&task.id).await;
+                    record_slo(&slo, &tasks, &task, &task_type, result.is_ok());
+                    match result {
+                        Ok(()) => {
+                            tasks.set_state(&task.id, TaskState::Succeeded, task.retry_count, None)
+                        }
+                        Err(e) => {
+                            tracing::error!(task_id = %task.id, "处理慢速任务失败: {}，转入死信队列", e);
+                            diagnostics::record_error(format!("任务 {} 处理失败: {}", task.id, e));
+                            let tenant = tenant_of(&tasks, &task);
+                            if let Err(dlq_error) =
+                                dlq::bury(&db_clone, &task, &tenant, &e.to_string()).await
+                            {
+                                tracing::error!(task_id = %task.id, "写入死信队列失败: {}", dlq_error);
+                            }
+                            let snapshot = ExecutionSnapshot::capture(
+                                &task,
+                                &tenant,
+                                &task_type,
+                                true,
+                                &handler,
+                                &settings,
+                                &e.to_string(),
+                            );
+                            replay::record(&db_clone, &snapshot).await;
+                            tasks.set_state(
+                                &task.id,
+                                TaskState::Failed,
+                                task.retry_count,
+                                Some(e.to_string()),
+                            );
+                        }
+                    }
+                    drop(permit);
+                    drop(type_permit);
+                    drop(slow_slot);
+                    drop(in_flight);
+                }));
+            } else {
+                // 快速任务占用一个工作者：只有一个工作者时直接在当前循环中处理，
+                // 否则在新的 Tokio 任务中处理，调度器继续取出下一个任务
+                let guards = QuickTaskGuards {
+                    in_flight,
+                    worker,
+                    type_permit,
+                };
+                let run = run_quick_task(
+                    shared.clone(),
+                    task,
+                    task_type,
+                    handler,
+                    settings,
+                    started,
+                    guards,
+                );
+                if pool_size > 1 {
+                    running.spawn(run);
+                } else {
+                    run.await;
                 }
             }
-        } else {
-            // 如果队列为空，则休眠 1 秒，避免忙等待消耗过多 CPU
-            sleep(Duration::from_secs(1)).await;
         }
     }
+
+    if !running.is_empty() {
+        tracing::info!(
+            in_flight = running.len(),
+            "调度器已停止取出新任务，等待正在处理的任务完成"
+        );
+    }
+    // 等待期间保持心跳，慢速任务耗时较长时看门狗不会误报
+    loop {
+        heartbeat.beat();
+        if let Ok(None) = tokio::time::timeout(IDLE_WAIT, running.join_next()).await {
+            break;
+        }
+    }
+    tracing::info!("调度器已排空");
+    // 返回会被监督者视为异常退出而重启，停在这里等待停机流程停止调度器
+    loop {
+        heartbeat.beat();
+        sleep(IDLE_WAIT).await;
+    }
+}
+
+/// 任务处理超过超时时间，按可重试的失败处理。
+#[derive(Debug, thiserror::Error)]
+#[error("处理超时（{0:?}）")]
+struct TaskTimedOut(Duration);
+
+/// 执行处理器，并将它返回的流式结果保存到结果存储；保存失败与处理失败一样按失败处理。
+///
+/// 配置了超时时间时，超时（包括保存结果的时间）的执行被取消，返回 `TaskTimedOut`。
+async fn run_handler(
+    handler: &HandlerChain,
+    task: &Task,
+    db: &Database,
+    results: &ResultStore,
+    timeout: Option<Duration>,
+) -> anyhow::Result<()> {
+    let run = async {
+        match handler.run(task, db).await? {
+            TaskOutput::Empty => Ok(()),
+            TaskOutput::Stream { content_type, body } => {
+                let meta = results
+                    .save(&task.id, &content_type, body)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("保存任务结果失败: {}", e))?;
+                tracing::info!(task_id = %task.id, size = meta.size, "任务结果已保存");
+                Ok(())
+            }
+        }
+    };
+    let Some(limit) = timeout else {
+        return run.await;
+    };
+    match tokio::time::timeout(limit, run).await {
+        Ok(result) => result,
+        Err(_) => {
+            metrics::counter("task_timeouts_total").inc();
+            Err(TaskTimedOut(limit).into())
+        }
+    }
+}
+
+/// 一个快速任务在处理期间占用的资源，处理结束后一起释放。
+struct QuickTaskGuards {
+    in_flight: InFlightGuard,
+    worker: OwnedSemaphorePermit,
+    type_permit: TypePermit,
+}
+
+/// 处理一个快速任务：失败时按重试预算重新入队，重试次数（`settings.max_retries`，默认 `MAX_RETRIES`）
+/// 用尽后转入死信队列。
+///
+/// `guards` 在处理结束后释放。
+async fn run_quick_task(
+    context: SchedulerContext,
+    mut task: Task,
+    task_type: String,
+    handler: HandlerChain,
+    settings: TaskTypeConfig,
+    started: Instant,
+    guards: QuickTaskGuards,
+) {
+    let SchedulerContext {
+        queue,
+        db,
+        tasks,
+        budget,
+        classifier,
+        slo,
+        results,
+        ..
+    } = context;
+    // 数据库连接按任务的优先级档位分配，关键任务可以使用预留连接
+    let class = PriorityClass::from_priority(task.priority);
+    let result = db::with_priority_class(
+        class,
+        run_handler(
+            &handler,
+            &task,
+            &db,
+            &results,
+            settings.timeout_for(&task.payload),
+        ),
+    )
+    .await;
+    let max_retries = settings.max_retries.unwrap_or(MAX_RETRIES);
+    classifier.record(&task_type, started.elapsed());
+    match result {
+        Ok(_) => {
+            tracing::info!(task_id = %task.id, "快速任务处理成功");
+            queue.ack(&task.id).await;
+            record_slo(&slo, &tasks, &task, &task_type, true);
+            tasks.set_state(&task.id, TaskState::Succeeded, task.retry_count, None);
+        }
+        Err(e) => {
+            // 如果任务处理失败，记录错误并检查是否可以重试
+            tracing::error!(task_id = %task.id, "处理快速任务失败: {}. 正在重试...", e);
+            if task.retry_count < max_retries {
+                // 如果重试次数未达上限，增加重试计数并将任务重新推入队列
+                task.retry_count += 1;
+                tasks.set_state(
+                    &task.id,
+                    TaskState::Queued,
+                    task.retry_count,
+                    Some(e.to_string()),
+                );
+                retry(queue.clone(), &tasks, &budget, task).await;
+            } else {
+                // 如果已达到最大重试次数，则将任务转入死信队列
+                tracing::error!(task_id = %task.id, "任务在 {} 次重试后失败，转入死信队列", task.retry_count);
+                diagnostics::record_error(format!("任务 {} 处理失败: {}", task.id, e));
+                let tenant = tenant_of(&tasks, &task);
+                if let Err(dlq_error) = dlq::bury(&db, &task, &tenant, &e.to_string()).await {
+                    tracing::error!(task_id = %task.id, "写入死信队列失败: {}", dlq_error);
+                }
+                let snapshot = ExecutionSnapshot::capture(
+                    &task,
+                    &tenant,
+                    &task_type,
+                    false,
+                    &handler,
+                    &settings,
+                    &e.to_string(),
+                );
+                replay::record(&db, &snapshot).await;
+                queue.ack(&task.id).await;
+                record_slo(&slo, &tasks, &task, &task_type, false);
+                tasks.set_state(
+                    &task.id,
+                    TaskState::Failed,
+                    task.retry_count,
+                    Some(e.to_string()),
+                );
+            }
+        }
+    }
+
+    let QuickTaskGuards {
+        in_flight,
+        worker,
+        type_permit,
+    } = guards;
+    drop(worker);
+    drop(type_permit);
+    drop(in_flight);
+}
+
+/// 提交任务的租户：优先取状态索引中的记录，查不到时取任务随身携带的租户。
+fn tenant_of(tasks: &TaskIndex, task: &Task) -> String {
+    tasks.get(&task.id).map_or_else(
+        || task.tenant_or_default().to_string(),
+        |record| record.tenant,
+    )
+}
+
+/// 任务最终成功或失败时，按提交时间计算端到端延迟并计入任务类型的 SLO。
+///
+/// 状态索引中查不到的任务（例如重启前提交的任务）不计入。
+fn record_slo(slo: &SloTracker, tasks: &TaskIndex, task: &Task, task_type: &str, succeeded: bool) {
+    if let Some(record) = tasks.get(&task.id) {
+        let latency = (chrono::Utc::now() - record.created_at)
+            .to_std()
+            .unwrap_or_default();
+        slo.record(task_type, latency, succeeded);
+    }
+}
+
+/// 任务依赖的其他任务的处理情况。
+#[derive(Debug, PartialEq, Eq)]
+enum Dependencies {
+    /// 没有依赖，或依赖的任务都已成功。
+    Ready,
+    /// 还有依赖的任务没有处理结束。
+    Waiting,
+    /// 依赖的任务最终失败。
+    Failed(Uuid),
+}
+
+/// 检查任务依赖的其他任务的状态。
+///
+/// 状态索引中查不到的依赖（进程重启或记录已被淘汰）从 `task_status` 表读取；
+/// 该表中也没有记录时视为已经满足，以免任务永远等待一个不会再出现的状态。
+async fn check_dependencies(tasks: &TaskIndex, db: &Database, task: &Task) -> Dependencies {
+    for id in &task.depends_on {
+        let status = match tasks.get(id) {
+            Some(record) => Some(record.status),
+            None => match db.task_status(id).await {
+                Ok(record) => record.map(|record| record.status),
+                // 读取失败时稍后再查，而不是贸然开始处理
+                Err(_) => return Dependencies::Waiting,
+            },
+        };
+        match status {
+            Some(TaskState::Succeeded) | None => {}
+            Some(TaskState::Failed) => return Dependencies::Failed(*id),
+            Some(_) => return Dependencies::Waiting,
+        }
+    }
+    Dependencies::Ready
+}
+
+/// 将超出慢速任务预算或任务类型限制的任务推迟 `delay` 后重新入队，不计入重试次数。
+async fn defer(
+    queue: &dyn QueueBackend,
+    tasks: &TaskIndex,
+    mut task: Task,
+    delay: Duration,
+    reason: &'static str,
+) {
+    tracing::info!(
+        task_id = %task.id,
+        reason,
+        "任务暂时不能开始处理，推迟 {:?} 后处理",
+        delay
+    );
+    tasks.set_state(&task.id, TaskState::Deferred, task.retry_count, None);
+    let delay = chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::zero());
+    task.run_at = Some(chrono::Utc::now() + delay);
+    requeue(queue, tasks, task).await;
+}
+
+/// 按重试预算将失败的任务重新入队，快速任务与超时的慢速任务共用。
+///
+/// 重试预算用完时推迟 `budget.delay()` 后再重试，避免在下游故障时放大压力。
+async fn retry(queue: Arc<dyn QueueBackend>, tasks: &TaskIndex, budget: &RetryBudget, task: Task) {
+    if budget.try_acquire_retry() {
+        requeue(queue.as_ref(), tasks, task).await;
+        return;
+    }
+    let delay = budget.delay();
+    tracing::warn!(task_id = %task.id, "重试预算已用完，推迟 {:?} 后重试", delay);
+    let tasks = tasks.clone();
+    tokio::spawn(async move {
+        sleep(delay).await;
+        // 推迟的重试在独立的任务中等待，队列已满时等待名额而不是超出容量，
+        // 不会阻塞调度器；其他错误交给 `requeue` 处理
+        if queue.push(task.clone()).await.is_err() {
+            requeue(queue.as_ref(), &tasks, task).await;
+        }
+    });
+}
+
+/// 将需要重试的任务放回队列。
+///
+/// 队列暂时无法接收（`QueueError::is_retryable`）时等待后再试，最多 `REQUEUE_ATTEMPTS` 次；
+/// 队列已关闭、其他错误或多次尝试仍失败时将任务标记为失败，而不是让它静默消失；
+/// 例外是持久化的队列被关闭（停机）时，任务的持久化记录仍在，下次启动时会被恢复。
+async fn requeue(queue: &dyn QueueBackend, tasks: &TaskIndex, task: Task) {
+    let mut attempts = 0;
+    loop {
+        // 克隆任务只复制载荷的指针
+        // 重试的任务已经被接收过，不受队列容量限制
+        let error = match queue.reinsert(task.clone()).await {
+            Ok(()) => return,
+            Err(e) => e,
+        };
+        // 关闭的队列不会再打开，等待没有意义
+        if error.is_retryable() && !queue.is_closed() && attempts < REQUEUE_ATTEMPTS {
+            attempts += 1;
+            let delay = error.retry_after().unwrap_or(Duration::from_secs(1));
+            tracing::warn!(task_id = %task.id, "重新入队失败: {}，{:?} 后再试", error, delay);
+            sleep(delay).await;
+            continue;
+        }
+        if queue.is_closed() && queue.is_durable() {
+            tracing::info!(task_id = %task.id, "队列已关闭，任务将在下次启动时恢复");
+            return;
+        }
+        tracing::error!(task_id = %task.id, "重新入队失败，任务被放弃: {}", error);
+        queue.ack(&task.id).await;
+        diagnostics::record_error(format!("任务 {} 重新入队失败: {}", task.id, error));
+        tasks.set_state(
+            &task.id,
+            TaskState::Failed,
+            task.retry_count,
+            Some(error.to_string()),
+        );
+        return;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::queue::Task;
+    use crate::db::filter::TaskFilter;
+    use crate::db::MemoryStore;
+    use crate::queue::{PriorityQueue, Task, TaskKind};
+    use crate::retry_budget::RetryBudgetSettings;
+    use crate::slo::{self, SloSettings};
+    use crate::transform::{TransformHandler, TRANSFORM_TASK_TYPE};
+    use crate::web::DEFAULT_TENANT;
     use serde_json::json;
     use sqlx::MySqlPool;
     use std::sync::Arc;
     use uuid::Uuid;
 
+    // 辅助函数：使用内存队列、默认设置与一个快速任务工作者的调度器上下文，测试按需用 `..` 覆盖其中的字段
+    fn test_context(db: Database, handlers: Handlers) -> SchedulerContext {
+        SchedulerContext {
+            queue: Arc::new(PriorityQueue::new()),
+            task_types: Arc::new(TaskTypeConfigs::new(db.clone(), Duration::from_secs(60))),
+            db,
+            tasks: TaskIndex::default(),
+            heartbeat: Heartbeat::new(),
+            budget: Arc::new(RetryBudget::new(Default::default())),
+            classifier: Arc::new(SlowClassifier::new(Default::default())),
+            admission: Arc::new(SlowAdmission::new(Default::default())),
+            handlers,
+            throughput: Arc::new(Throughput::new(Duration::from_secs(60))),
+            slo: Arc::new(SloTracker::new(Default::default())),
+            results: ResultStore::new(std::env::temp_dir()),
+            lifecycle: Lifecycle::new(),
+            shutdown: CancellationToken::new(),
+            workers: 1,
+            max_slow_tasks: 0,
+        }
+    }
+
     // 辅助函数：为测试创建一个临时的 `tasks` 表
     async fn create_temp_task_table(pool: &MySqlPool) -> sqlx::Result<()> {
         sqlx::query(
@@ -107,12 +855,17 @@ mod tests {
 
         let task = Task {
             id: Uuid::new_v4(),
-            payload: json!({ "test": "quick_task" }),
+            payload: json!({ "test": "quick_task" }).into(),
             priority: 50,
             retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
         };
 
-        let result = handle_quick_task(&task, &pool).await;
+        let result = handle_quick_task(&task, &Database::MySql(pool.clone(), None)).await;
         assert!(result.is_ok());
 
         // 验证数据是否已插入
@@ -124,15 +877,589 @@ mod tests {
         Ok(())
     }
 
+    /// 在测试数据库（默认为嵌入式 SQLite）上测试 `handle_quick_task` 成功执行的情况
+    #[tokio::test]
+    async fn test_handle_quick_task_test_database() {
+        let db = crate::db::test_database().await;
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "test": "quick_task" }).into(),
+            priority: 50,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+
+        assert!(handle_quick_task(&task, &db).await.is_ok());
+    }
+
+    /// 测试内存数据库模式下 `handle_quick_task` 成功执行的情况
+    #[tokio::test]
+    async fn test_handle_quick_task_memory() {
+        let store = MemoryStore::new();
+        let db = Database::Memory(store.clone());
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "test": "quick_task" }).into(),
+            priority: 50,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+
+        assert!(handle_quick_task(&task, &db).await.is_ok());
+        assert_eq!(store.task_count(), 1);
+    }
+
+    /// 测试多个工作者并发处理快速任务，所有任务都处理成功。
+    #[tokio::test]
+    async fn test_worker_pool() {
+        let store = MemoryStore::new();
+        let context = SchedulerContext {
+            workers: 4,
+            ..test_context(Database::Memory(store.clone()), Handlers::new(&[]))
+        };
+        let mut ids = Vec::new();
+        for i in 0..8 {
+            let task = Task {
+                id: Uuid::new_v4(),
+                payload: json!({ "n": i }).into(),
+                priority: 10,
+                retry_count: 0,
+                run_at: None,
+                context: Default::default(),
+                kind: Default::default(),
+                depends_on: Default::default(),
+                tenant: None,
+            };
+            context
+                .tasks
+                .insert_queued(task.id, task.priority, DEFAULT_TENANT);
+            ids.push(task.id);
+            context.queue.push(task).await.unwrap();
+        }
+
+        let scheduler = tokio::spawn(run_scheduler(context.clone()));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while store.task_count() < ids.len() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("任务没有在限定时间内处理完");
+        scheduler.abort();
+
+        // 状态在数据写入之后更新，等待所有工作者结束
+        context.lifecycle.wait_idle(Duration::from_secs(1)).await;
+        for id in &ids {
+            assert_eq!(context.tasks.get(id).unwrap().status, TaskState::Succeeded);
+        }
+    }
+
+    /// 按任务类型注册的处理器，记录处理过的任务。
+    struct RecordingHandler(Arc<std::sync::Mutex<Vec<Uuid>>>);
+
+    impl TaskHandler for RecordingHandler {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        fn run<'a>(
+            &'a self,
+            task: &'a Task,
+            _: &'a Database,
+        ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+            self.0.lock().unwrap().push(task.id);
+            async { Ok(TaskOutput::Empty) }.boxed()
+        }
+    }
+
+    /// 测试注册了处理器的任务类型交给该处理器，其余任务使用默认的处理器。
+    #[tokio::test]
+    async fn test_dispatch_by_task_type() {
+        let store = MemoryStore::new();
+        let handled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let slo = Arc::new(SloTracker::new(SloSettings {
+            targets: slo::parse_targets("report=99%@30s").unwrap(),
+            ..Default::default()
+        }));
+        let context = SchedulerContext {
+            slo: slo.clone(),
+            ..test_context(
+                Database::Memory(store.clone()),
+                Handlers::new(&[]).register("report", Arc::new(RecordingHandler(handled.clone()))),
+            )
+        };
+        let mut ids = Vec::new();
+        for payload in [json!({ "type": "report" }), json!({ "type": "email" })] {
+            let task = Task {
+                id: Uuid::new_v4(),
+                payload: payload.into(),
+                priority: 10,
+                retry_count: 0,
+                run_at: None,
+                context: Default::default(),
+                kind: Default::default(),
+                depends_on: Default::default(),
+                tenant: None,
+            };
+            context
+                .tasks
+                .insert_queued(task.id, task.priority, DEFAULT_TENANT);
+            ids.push(task.id);
+            context.queue.push(task).await.unwrap();
+        }
+
+        let scheduler = tokio::spawn(run_scheduler(context.clone()));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while ids
+                .iter()
+                .any(|id| context.tasks.get(id).unwrap().status != TaskState::Succeeded)
+            {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("任务没有在限定时间内处理完");
+        scheduler.abort();
+
+        assert_eq!(*handled.lock().unwrap(), vec![ids[0]]);
+        // 未注册的类型由默认的快速任务处理器写入数据库
+        assert_eq!(store.task_count(), 1);
+        // 只有配置了延迟目标的任务类型计入 SLO
+        let reports = slo.reports();
+        assert_eq!((reports[0].total, reports[0].good), (1, 1));
+    }
+
+    /// 测试任务依赖：依赖的任务成功之后才处理，即使本任务的优先级更高；依赖失败时本任务直接失败。
+    #[tokio::test]
+    async fn test_task_dependencies() {
+        let store = MemoryStore::new();
+        let handled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let context = test_context(
+            Database::Memory(store.clone()),
+            Handlers::new(&[]).register("report", Arc::new(RecordingHandler(handled.clone()))),
+        );
+        let failed = Uuid::new_v4();
+        context.tasks.insert_queued(failed, 10, DEFAULT_TENANT);
+        context
+            .tasks
+            .set_state(&failed, TaskState::Failed, 0, Some("boom".to_string()));
+        let task = |priority, depends_on| Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "type": "report" }).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: TaskKind::Quick,
+            depends_on,
+            tenant: None,
+        };
+        let first = task(10, Vec::new());
+        let second = task(100, vec![first.id]);
+        let orphaned = task(100, vec![failed]);
+        for task in [&first, &second, &orphaned] {
+            context
+                .tasks
+                .insert_queued(task.id, task.priority, DEFAULT_TENANT);
+        }
+        context
+            .queue
+            .try_push_all(vec![first.clone(), second.clone(), orphaned.clone()])
+            .await
+            .unwrap();
+
+        let scheduler = tokio::spawn(run_scheduler(context.clone()));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while [&first, &second, &orphaned]
+                .iter()
+                .any(|task| !context.tasks.get(&task.id).unwrap().status.is_finished())
+            {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("任务没有在限定时间内处理完");
+        scheduler.abort();
+
+        assert_eq!(*handled.lock().unwrap(), vec![first.id, second.id]);
+        let record = context.tasks.get(&orphaned.id).unwrap();
+        assert_eq!(record.status, TaskState::Failed);
+        assert!(record.last_error.unwrap().contains(&failed.to_string()));
+    }
+
+    /// 测试按名称列出已注册的任务类型，以及默认优先级的查找。
+    #[test]
+    fn test_registered_task_types() {
+        let handled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let handlers = Handlers::new(&[])
+            .register(TRANSFORM_TASK_TYPE, Arc::new(TransformHandler))
+            .register("report", Arc::new(RecordingHandler(handled)));
+        let names: Vec<_> = handlers
+            .registered()
+            .iter()
+            .map(|(name, _)| *name)
+            .collect();
+        assert_eq!(names, vec!["report", TRANSFORM_TASK_TYPE]);
+        assert_eq!(handlers.default_priority(TRANSFORM_TASK_TYPE), Some(50));
+        assert_eq!(handlers.default_priority("report"), None);
+        assert_eq!(handlers.default_priority("email"), None);
+    }
+
+    /// 不会结束的处理器。
+    struct HangingHandler;
+
+    impl TaskHandler for HangingHandler {
+        fn name(&self) -> &'static str {
+            "hanging"
+        }
+
+        fn run<'a>(
+            &'a self,
+            _: &'a Task,
+            _: &'a Database,
+        ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+            std::future::pending().boxed()
+        }
+    }
+
+    /// 测试任务类型配置的超时时间与重试次数在运行时生效。
+    #[tokio::test]
+    async fn test_task_type_config_applied() {
+        let store = MemoryStore::new();
+        let db = Database::Memory(store.clone());
+        let task_types = Arc::new(TaskTypeConfigs::new(db.clone(), Duration::from_secs(60)));
+        let context = SchedulerContext {
+            task_types: task_types.clone(),
+            ..test_context(
+                db.clone(),
+                Handlers::new(&[]).register("hang", Arc::new(HangingHandler)),
+            )
+        };
+        let scheduler = tokio::spawn(run_scheduler(context.clone()));
+        // 调度器已经在运行，配置修改之后出队的任务按新配置处理
+        task_types
+            .set(
+                "hang",
+                TaskTypeConfig {
+                    max_retries: Some(0),
+                    timeout_ms: Some(50),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "type": "hang" }).into(),
+            priority: 10,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let id = task.id;
+        context
+            .tasks
+            .insert_queued(id, task.priority, DEFAULT_TENANT);
+        context.queue.push(task).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while context.tasks.get(&id).unwrap().status != TaskState::Failed {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("超时的任务没有在限定时间内失败");
+        scheduler.abort();
+
+        let record = context.tasks.get(&id).unwrap();
+        assert_eq!(record.retry_count, 0);
+        assert!(record.last_error.unwrap().contains("超时"));
+        assert_eq!(
+            db.dead_tasks(&TaskFilter::new(), 10).await.unwrap().len(),
+            1
+        );
+        // 最终失败时记录执行快照，包括当时生效的任务类型配置
+        let snapshot = db.task_snapshot(&id).await.unwrap().unwrap();
+        assert_eq!(snapshot.handler, "hanging");
+        assert_eq!(snapshot.config.timeout_ms, Some(50));
+        assert_eq!(snapshot.timeout_ms, Some(50));
+        assert!(snapshot.error.contains("超时"));
+    }
+
+    /// 测试慢速任务的并发名额用完时，之后出队的慢速任务被推迟而不是立即开始处理。
+    #[tokio::test]
+    async fn test_max_slow_tasks() {
+        let context = SchedulerContext {
+            max_slow_tasks: 1,
+            ..test_context(
+                Database::Memory(MemoryStore::new()),
+                Handlers::new(&[]).register("hang", Arc::new(HangingHandler)),
+            )
+        };
+        let ids: Vec<Uuid> = (0..2).map(|_| Uuid::new_v4()).collect();
+        for id in &ids {
+            context.tasks.insert_queued(*id, 200, DEFAULT_TENANT);
+            let task = Task {
+                id: *id,
+                payload: json!({ "type": "hang" }).into(),
+                priority: 200,
+                retry_count: 0,
+                run_at: None,
+                context: Default::default(),
+                kind: TaskKind::Slow,
+                depends_on: Default::default(),
+                tenant: None,
+            };
+            context.queue.push(task).await.unwrap();
+        }
+
+        let scheduler = tokio::spawn(run_scheduler(context.clone()));
+        let states = || -> Vec<TaskState> {
+            ids.iter()
+                .map(|id| context.tasks.get(id).unwrap().status)
+                .collect()
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while states() != [TaskState::Running, TaskState::Deferred] {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("超出并发上限的慢速任务没有被推迟");
+        // 名额一直被占用，推迟的任务到期后再次被推迟，不会开始处理
+        sleep(SLOW_SLOT_WAIT * 2).await;
+        assert_eq!(context.lifecycle.in_flight(), 1);
+        scheduler.abort();
+    }
+
+    /// 测试超时的慢速任务的重试同样受重试预算约束：预算用完时推迟重试，而不是立即重新入队。
+    #[tokio::test]
+    async fn test_slow_timeout_retry_respects_budget() {
+        let db = Database::Memory(MemoryStore::new());
+        let task_types = Arc::new(TaskTypeConfigs::new(db.clone(), Duration::from_secs(60)));
+        task_types
+            .set(
+                "hang",
+                TaskTypeConfig {
+                    max_retries: Some(3),
+                    timeout_ms: Some(50),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let context = SchedulerContext {
+            task_types,
+            budget: Arc::new(RetryBudget::new(RetryBudgetSettings {
+                window: Duration::from_secs(60),
+                ratio: 0.0,
+                min_retries: 0,
+                delay: Duration::from_secs(60),
+            })),
+            ..test_context(
+                db,
+                Handlers::new(&[]).register("hang", Arc::new(HangingHandler)),
+            )
+        };
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "type": "hang" }).into(),
+            priority: 200,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: TaskKind::Slow,
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let id = task.id;
+        context
+            .tasks
+            .insert_queued(id, task.priority, DEFAULT_TENANT);
+        context.queue.push(task).await.unwrap();
+
+        let scheduler = tokio::spawn(run_scheduler(context.clone()));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while context.tasks.get(&id).unwrap().retry_count == 0 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("超时的慢速任务没有进入重试");
+        // 预算已用完，重试被推迟，任务不会立即回到队列中再次执行
+        sleep(Duration::from_millis(200)).await;
+        let record = context.tasks.get(&id).unwrap();
+        assert_eq!((record.status, record.retry_count), (TaskState::Queued, 1));
+        assert_eq!(context.queue.len().await, 0);
+        assert_eq!(context.lifecycle.in_flight(), 0);
+        scheduler.abort();
+    }
+
+    /// 测试一直超时的慢速任务在重试次数用尽后转入死信队列。
+    #[tokio::test]
+    async fn test_slow_task_exhausted_retries_buried() {
+        let db = Database::Memory(MemoryStore::new());
+        let task_types = Arc::new(TaskTypeConfigs::new(db.clone(), Duration::from_secs(60)));
+        task_types
+            .set(
+                "hang",
+                TaskTypeConfig {
+                    max_retries: Some(1),
+                    timeout_ms: Some(50),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let context = SchedulerContext {
+            task_types,
+            ..test_context(
+                db.clone(),
+                Handlers::new(&[]).register("hang", Arc::new(HangingHandler)),
+            )
+        };
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "type": "hang" }).into(),
+            priority: 200,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: TaskKind::Slow,
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let id = task.id;
+        context.tasks.insert_queued(id, task.priority, "acme");
+        context.queue.push(task).await.unwrap();
+
+        let scheduler = tokio::spawn(run_scheduler(context.clone()));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while context.tasks.get(&id).unwrap().status != TaskState::Failed {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("一直超时的慢速任务没有在限定时间内失败");
+        scheduler.abort();
+
+        let dead = db.dead_tasks(&TaskFilter::new(), 10).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!((dead[0].id, dead[0].retry_count), (id, 1));
+        assert_eq!(dead[0].tenant, "acme");
+        assert!(dead[0].error.contains("超时"));
+    }
+
+    /// 测试停机时调度器不再取出新任务，并等待正在处理的慢速任务完成。
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_drains_in_flight_tasks() {
+        let store = MemoryStore::new();
+        let context = test_context(Database::Memory(store.clone()), Handlers::new(&[]));
+        let task = |priority| Task {
+            id: Uuid::new_v4(),
+            payload: json!({ "priority": priority }).into(),
+            priority,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        let slow = Task {
+            kind: TaskKind::Slow,
+            ..task(200)
+        };
+        context
+            .tasks
+            .insert_queued(slow.id, slow.priority, DEFAULT_TENANT);
+        context.queue.push(slow.clone()).await.unwrap();
+
+        let scheduler = tokio::spawn(run_scheduler(context.clone()));
+        while context.lifecycle.in_flight() == 0 {
+            tokio::task::yield_now().await;
+        }
+        context.shutdown.cancel();
+        // 停机之后入队的任务留在队列中
+        context.queue.push(task(10)).await.unwrap();
+
+        assert!(context.lifecycle.wait_idle(Duration::from_secs(10)).await);
+        assert_eq!(store.task_count(), 1);
+        assert_eq!(
+            context.tasks.get(&slow.id).unwrap().status,
+            TaskState::Succeeded
+        );
+        sleep(Duration::from_secs(5)).await;
+        assert_eq!(context.queue.len().await, 1);
+        // 排空后调度器停在原处，不会被监督者当作异常退出
+        assert!(!scheduler.is_finished());
+        scheduler.abort();
+    }
+
+    /// 测试运维暂停期间任务留在队列中，恢复后继续分发
+    #[tokio::test]
+    async fn test_operator_pause_and_resume() {
+        let store = MemoryStore::new();
+        let context = test_context(Database::Memory(store.clone()), Handlers::new(&[]));
+        assert!(context.lifecycle.pause());
+        let scheduler = tokio::spawn(run_scheduler(context.clone()));
+        let task = Task {
+            id: Uuid::new_v4(),
+            payload: json!({}).into(),
+            priority: 1,
+            retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
+        };
+        context
+            .tasks
+            .insert_queued(task.id, task.priority, DEFAULT_TENANT);
+        context.queue.push(task.clone()).await.unwrap();
+
+        sleep(PAUSE_POLL * 3).await;
+        assert_eq!(context.queue.len().await, 1);
+        assert_eq!(store.task_count(), 0);
+
+        assert!(context.lifecycle.resume());
+        for _ in 0..100 {
+            if store.task_count() == 1 {
+                break;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(store.task_count(), 1);
+        assert_eq!(context.queue.len().await, 0);
+        scheduler.abort();
+    }
+
     /// 测试任务失败后的重试逻辑
     #[tokio::test]
     async fn test_retry_logic() {
         let queue = Arc::new(PriorityQueue::new());
         let task = Task {
             id: Uuid::new_v4(),
-            payload: json!({}),
+            payload: json!({}).into(),
             priority: 1,
             retry_count: 0,
+            run_at: None,
+            context: Default::default(),
+            kind: Default::default(),
+            depends_on: Default::default(),
+            tenant: None,
         };
 
         // 这个测试通过不提供真实数据库来模拟 `handle_quick_task` 的失败。
@@ -143,7 +1470,7 @@ mod tests {
         let mut task_to_retry = task.clone();
         if task_to_retry.retry_count < MAX_RETRIES {
             task_to_retry.retry_count += 1;
-            queue.push(task_to_retry).await;
+            queue.push(task_to_retry).await.unwrap();
         }
 
         // 验证任务被重新推入队列后，其重试计数增加了
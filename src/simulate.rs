@@ -0,0 +1,685 @@
+use crate::admission::SlowAdmission;
+use crate::backpressure::Throughput;
+use crate::classifier::{ClassifierSettings, SlowClassifier};
+use crate::db::{Database, MemoryStore};
+use crate::fixtures::{pick_priority, SplitMix64};
+use crate::handler::{TaskHandler, TaskOutput};
+use crate::lifecycle::Lifecycle;
+use crate::queue::{PriorityClass, PriorityQueue, Task};
+use crate::results::ResultStore;
+use crate::retry_budget::RetryBudget;
+use crate::scheduler::{run_scheduler, Handlers, SchedulerContext};
+use crate::slo::SloTracker;
+use crate::status::TaskIndex;
+use crate::task_types::TaskTypeConfigs;
+use crate::units;
+use crate::watchdog::Heartbeat;
+use crate::web::DEFAULT_TENANT;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{sleep, sleep_until, Instant};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// 最后一个任务到达之后，最多再模拟多长时间等待排队的任务处理完，超出后剩余的任务计入 `unfinished`。
+const MAX_DRAIN: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 一段到达速率恒定的时间。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Phase {
+    /// 这一段的时长。
+    #[serde(deserialize_with = "duration")]
+    pub duration: Duration,
+    /// 每秒到达的任务数（泊松到达）。
+    pub rate: f64,
+}
+
+/// 负载中的一种任务类型。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TypeSpec {
+    /// 任务类型，即载荷中的 `type`。
+    pub name: String,
+    /// 该类型在到达的任务中所占的权重。
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// 处理耗时的中位数。
+    #[serde(deserialize_with = "duration")]
+    pub duration: Duration,
+    /// 处理耗时的 p95，省略时耗时固定为 `duration`；两者不同时耗时服从对数正态分布。
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub duration_p95: Option<Duration>,
+    /// 是否按慢速任务处理，省略时按 p95 是否超过 `slow_threshold` 判断（与调度器积累足够样本后的分类一致）。
+    pub slow: Option<bool>,
+}
+
+/// `simulate` 子命令的负载描述，可以是 JSON、TOML 或 YAML 文件。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimulationSpec {
+    /// 随机数种子，相同的描述总是生成相同的到达序列。
+    #[serde(default = "default_seed")]
+    pub seed: u64,
+    /// 依次模拟的到达阶段，例如平稳期之后的一段突发流量。
+    pub phases: Vec<Phase>,
+    pub types: Vec<TypeSpec>,
+    /// Low/Normal/Critical 三个档位的权重。
+    #[serde(default = "default_priority_weights")]
+    pub priority_weights: [u32; 3],
+    /// 要比较的工作者数量（`SCHEDULER_WORKERS`），每个取值单独模拟一次。
+    #[serde(default)]
+    pub workers: Vec<usize>,
+    /// 队列容量（`QUEUE_CAPACITY`），0 表示不限制；队列已满时到达的任务被拒绝。
+    #[serde(default)]
+    pub queue_capacity: usize,
+    /// 慢速任务的耗时阈值（`SLOW_TASK_THRESHOLD_MS`）。
+    #[serde(default = "default_slow_threshold", deserialize_with = "duration")]
+    pub slow_threshold: Duration,
+    /// 采样队列深度的间隔。
+    #[serde(default = "default_sample_interval", deserialize_with = "duration")]
+    pub sample_interval: Duration,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_seed() -> u64 {
+    42
+}
+
+fn default_priority_weights() -> [u32; 3] {
+    [70, 25, 5]
+}
+
+fn default_slow_threshold() -> Duration {
+    ClassifierSettings::default().threshold
+}
+
+fn default_sample_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+/// 时长可以写成整数（毫秒）或带单位的字符串，例如 `"250ms"`、`"5m"`。
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Millis(u64),
+        Text(String),
+    }
+    match Raw::deserialize(deserializer)? {
+        Raw::Millis(ms) => Ok(Duration::from_millis(ms)),
+        Raw::Text(text) => {
+            units::parse_duration(&text, Duration::from_millis(1)).map_err(D::Error::custom)
+        }
+    }
+}
+
+fn optional_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    duration(deserializer).map(Some)
+}
+
+impl SimulationSpec {
+    /// 读取负载描述，格式按扩展名判断（`.json`、`.toml`、`.yaml`/`.yml`）。
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("无法读取负载描述 {}: {}", path.display(), e))?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("json") => serde_json::from_str(&content).map_err(|e| e.to_string()),
+            Some("toml") => toml::from_str(&content).map_err(|e| e.to_string()),
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+            _ => Err("扩展名应为 .json、.toml、.yaml 或 .yml".to_string()),
+        }
+        .map_err(|e| format!("负载描述 {} 无效: {}", path.display(), e))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.phases.is_empty() {
+            return Err("phases 不能为空".to_string());
+        }
+        if let Some(phase) = self
+            .phases
+            .iter()
+            .find(|p| !p.rate.is_finite() || p.rate < 0.0)
+        {
+            return Err(format!("到达速率必须是非负数，当前值: {}", phase.rate));
+        }
+        if self.types.iter().all(|t| t.weight == 0) {
+            return Err("types 中至少要有一个权重大于 0 的任务类型".to_string());
+        }
+        if let Some(t) = self
+            .types
+            .iter()
+            .find(|t| t.duration_p95.is_some_and(|p95| p95 < t.duration))
+        {
+            return Err(format!("{}: duration_p95 不能小于 duration", t.name));
+        }
+        if self.priority_weights.iter().all(|w| *w == 0) {
+            return Err("priority_weights 不能全为 0".to_string());
+        }
+        if self.workers.contains(&0) {
+            return Err("工作者数量必须大于 0".to_string());
+        }
+        if self.sample_interval.is_zero() {
+            return Err("sample_interval 必须大于 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// 各任务类型是否按慢速任务处理。
+    fn slow_overrides(&self) -> HashMap<String, bool> {
+        self.types
+            .iter()
+            .map(|t| {
+                let p95 = t.duration_p95.unwrap_or(t.duration);
+                (t.name.clone(), t.slow.unwrap_or(p95 > self.slow_threshold))
+            })
+            .collect()
+    }
+}
+
+/// 一个到达的任务。
+#[derive(Debug, Clone, PartialEq)]
+struct Arrival {
+    /// 相对模拟开始的到达时刻。
+    at: Duration,
+    task_type: String,
+    priority: u8,
+    /// 处理耗时。
+    duration: Duration,
+}
+
+/// 按描述生成到达序列：每个阶段内按泊松过程到达，任务类型、优先级与耗时按描述的分布抽取。
+fn arrivals(spec: &SimulationSpec) -> Vec<Arrival> {
+    // 正态分布的 p95 对应的标准差倍数
+    const Z95: f64 = 1.644_853_6;
+    let mut rng = SplitMix64::new(spec.seed);
+    let total_weight: u32 = spec.types.iter().map(|t| t.weight).sum();
+    let mut arrivals = Vec::new();
+    let mut phase_start = Duration::ZERO;
+    for phase in &spec.phases {
+        let end = phase_start + phase.duration;
+        let mut at = phase_start.as_secs_f64();
+        loop {
+            if phase.rate <= 0.0 {
+                break;
+            }
+            // 指数分布的到达间隔，`1 - unit` 落在 (0, 1]，避免 ln(0)
+            at += -(1.0 - rng.unit()).ln() / phase.rate;
+            if at >= end.as_secs_f64() {
+                break;
+            }
+            let mut pick = rng.range(0, u64::from(total_weight) - 1);
+            let task_type = spec
+                .types
+                .iter()
+                .find(|t| {
+                    let hit = pick < u64::from(t.weight);
+                    pick = pick.saturating_sub(u64::from(t.weight));
+                    hit
+                })
+                .expect("权重之和大于 0");
+            let priority = pick_priority(&mut rng, spec.priority_weights);
+            let duration = match task_type.duration_p95 {
+                Some(p95) if p95 > task_type.duration => {
+                    // 对数正态分布：中位数为 duration，p95 为 duration_p95（Box-Muller 变换生成标准正态分布）
+                    let sigma = (p95.as_secs_f64() / task_type.duration.as_secs_f64()).ln() / Z95;
+                    let z = (-2.0 * (1.0 - rng.unit()).ln()).sqrt()
+                        * (2.0 * std::f64::consts::PI * rng.unit()).cos();
+                    task_type.duration.mul_f64((sigma * z).exp())
+                }
+                _ => task_type.duration,
+            };
+            arrivals.push(Arrival {
+                at: Duration::from_secs_f64(at),
+                task_type: task_type.name.clone(),
+                priority,
+                duration,
+            });
+        }
+        phase_start = end;
+    }
+    arrivals
+}
+
+/// 一个任务的模拟结果，时刻均相对模拟开始。
+#[derive(Debug, Clone)]
+struct Sample {
+    task_type: String,
+    class: PriorityClass,
+    arrived: Duration,
+    started: Duration,
+    finished: Duration,
+}
+
+/// 模拟的处理器：按载荷中的耗时等待（模拟时钟），然后记录任务的到达、开始与完成时刻。
+struct SimulatedHandler {
+    epoch: Instant,
+    samples: Arc<Mutex<Vec<Sample>>>,
+}
+
+impl TaskHandler for SimulatedHandler {
+    fn name(&self) -> &'static str {
+        "simulated"
+    }
+
+    fn run<'a>(
+        &'a self,
+        task: &'a Task,
+        _: &'a Database,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+        async move {
+            let micros = |key: &str| Duration::from_micros(task.payload[key].as_u64().unwrap_or(0));
+            let started = self.epoch.elapsed();
+            sleep(micros("duration_us")).await;
+            let sample = Sample {
+                task_type: task.payload["type"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                class: PriorityClass::from_priority(task.priority),
+                arrived: micros("arrived_us"),
+                started,
+                finished: self.epoch.elapsed(),
+            };
+            self.samples
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(sample);
+            Ok(TaskOutput::Empty)
+        }
+        .boxed()
+    }
+}
+
+/// 一组耗时的分位数（毫秒）。
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Percentiles {
+    pub count: usize,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    fn of(durations: impl IntoIterator<Item = Duration>) -> Self {
+        let mut ms: Vec<f64> = durations
+            .into_iter()
+            .map(|d| d.as_micros() as f64 / 1000.0)
+            .collect();
+        if ms.is_empty() {
+            return Self::default();
+        }
+        ms.sort_by(f64::total_cmp);
+        let at = |p: usize| ms[(ms.len() * p).div_ceil(100) - 1];
+        Self {
+            count: ms.len(),
+            p50: at(50),
+            p90: at(90),
+            p99: at(99),
+            max: ms[ms.len() - 1],
+        }
+    }
+}
+
+/// 按 `sample_interval` 采样的排队任务数。
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DepthStats {
+    pub max: usize,
+    pub mean: f64,
+    pub p95: usize,
+}
+
+impl DepthStats {
+    fn of(mut depths: Vec<usize>) -> Self {
+        if depths.is_empty() {
+            return Self::default();
+        }
+        let mean = depths.iter().sum::<usize>() as f64 / depths.len() as f64;
+        depths.sort_unstable();
+        Self {
+            max: depths[depths.len() - 1],
+            mean,
+            p95: depths[(depths.len() * 95).div_ceil(100) - 1],
+        }
+    }
+}
+
+/// 一种工作者数量的模拟结果。
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    pub workers: usize,
+    pub completed: usize,
+    /// 队列已满时被拒绝的任务数。
+    pub rejected: usize,
+    /// 模拟结束（超过 `MAX_DRAIN`）时仍未处理完的任务数。
+    pub unfinished: usize,
+    /// 从模拟开始到最后一个任务完成的模拟时长。
+    pub simulated_secs: f64,
+    pub throughput_per_sec: f64,
+    pub queue_depth: DepthStats,
+    /// 从到达到开始处理的排队时间。
+    pub wait_ms: Percentiles,
+    /// 从到达到处理完成的端到端延迟。
+    pub latency_ms: Percentiles,
+    pub latency_ms_by_class: BTreeMap<&'static str, Percentiles>,
+    pub latency_ms_by_type: BTreeMap<String, Percentiles>,
+}
+
+/// `simulate` 的输出。
+#[derive(Debug, Serialize)]
+pub struct SimulationReport {
+    pub seed: u64,
+    /// 到达的任务总数，每次模拟使用完全相同的到达序列。
+    pub arrivals: usize,
+    /// 所有到达阶段的总时长。
+    pub arrival_window_secs: f64,
+    /// 按任务类型的快慢分类。
+    pub slow_types: BTreeMap<String, bool>,
+    pub runs: Vec<RunReport>,
+}
+
+/// 解析 `simulate` 的参数（不含子命令本身）：`<负载描述文件> [--workers 4,8,16]`。
+pub fn parse_args(args: &[String]) -> Result<(PathBuf, Option<Vec<usize>>), String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (path, workers) = match args.as_slice() {
+        [path] => (path, None),
+        [path, "--workers", list] | ["--workers", list, path] => (path, Some(list)),
+        _ => return Err("用法: web_server simulate <负载描述文件> [--workers 4,8,16]".to_string()),
+    };
+    let workers = workers
+        .map(|list| {
+            list.split(',')
+                .map(|n| match n.trim().parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(format!(
+                        "--workers 必须是逗号分隔的正整数，当前值: {}",
+                        list
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+    Ok((PathBuf::from(path), workers))
+}
+
+/// 按负载描述逐一模拟每种工作者数量；`workers` 不为 `None` 时覆盖描述中的 `workers`。
+///
+/// 每次模拟在一个独立的、时钟暂停的 Tokio 运行时中运行真实的调度器与优先级队列：
+/// 处理器只在模拟时钟上等待，运行时空闲时时钟直接跳到下一个到达或完成的时刻，
+/// 几小时的负载在几秒内模拟完。模拟不连接数据库，也不执行真实的处理器，
+/// 延迟与重试预算、慢速任务预算、任务类型限制等依赖真实时钟的逻辑不在模拟范围内。
+pub fn run(spec: &SimulationSpec, workers: Option<Vec<usize>>) -> Result<SimulationReport, String> {
+    spec.validate()?;
+    let workers = workers.unwrap_or_else(|| spec.workers.clone());
+    if workers.is_empty() {
+        return Err("必须在负载描述的 workers 或 --workers 中指定工作者数量".to_string());
+    }
+    let arrivals = arrivals(spec);
+    let overrides = spec.slow_overrides();
+    // 时钟暂停的运行时不能嵌套在服务的运行时中，在单独的线程里创建
+    let runs = std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                workers
+                    .iter()
+                    .map(|&workers| {
+                        tokio::runtime::Builder::new_current_thread()
+                            .enable_all()
+                            .start_paused(true)
+                            .build()
+                            .map_err(|e| format!("无法创建模拟运行时: {}", e))
+                            .map(|runtime| {
+                                runtime.block_on(simulate(spec, &arrivals, &overrides, workers))
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .join()
+            .map_err(|_| "模拟线程异常退出".to_string())?
+    })?;
+    Ok(SimulationReport {
+        seed: spec.seed,
+        arrivals: arrivals.len(),
+        arrival_window_secs: spec.phases.iter().map(|p| p.duration.as_secs_f64()).sum(),
+        slow_types: overrides.into_iter().collect(),
+        runs,
+    })
+}
+
+/// 以给定的工作者数量模拟一次：按到达时刻提交任务，直到所有任务处理完。
+async fn simulate(
+    spec: &SimulationSpec,
+    arrivals: &[Arrival],
+    overrides: &HashMap<String, bool>,
+    workers: usize,
+) -> RunReport {
+    let store = MemoryStore::new();
+    let queue = Arc::new(PriorityQueue::new().with_capacity(spec.queue_capacity));
+    let samples = Arc::new(Mutex::new(Vec::new()));
+    let epoch = Instant::now();
+    let handlers = overrides.keys().fold(Handlers::new(&[]), |handlers, name| {
+        handlers.register(
+            name,
+            Arc::new(SimulatedHandler {
+                epoch,
+                samples: samples.clone(),
+            }),
+        )
+    });
+    let shutdown = CancellationToken::new();
+    let context = SchedulerContext {
+        queue: queue.clone(),
+        db: Database::Memory(store.clone()),
+        tasks: TaskIndex::default(),
+        heartbeat: Heartbeat::new(),
+        budget: Arc::new(RetryBudget::new(Default::default())),
+        classifier: Arc::new(SlowClassifier::new(ClassifierSettings {
+            threshold: spec.slow_threshold,
+            overrides: overrides.clone(),
+            ..Default::default()
+        })),
+        admission: Arc::new(SlowAdmission::new(Default::default())),
+        handlers,
+        throughput: Arc::new(Throughput::new(Duration::from_secs(60))),
+        task_types: Arc::new(TaskTypeConfigs::new(
+            Database::Memory(store),
+            Duration::from_secs(60),
+        )),
+        slo: Arc::new(SloTracker::new(Default::default())),
+        results: ResultStore::new(std::env::temp_dir()),
+        lifecycle: Lifecycle::new(),
+        shutdown: shutdown.clone(),
+        workers,
+        max_slow_tasks: 0,
+    };
+    let tasks = context.tasks.clone();
+    let scheduler = tokio::spawn(run_scheduler(context));
+
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let rejected = Arc::new(AtomicUsize::new(0));
+    let driver = {
+        let arrivals = arrivals.to_vec();
+        let (queue, accepted, rejected) = (queue.clone(), accepted.clone(), rejected.clone());
+        tokio::spawn(async move {
+            for (i, arrival) in arrivals.into_iter().enumerate() {
+                sleep_until(epoch + arrival.at).await;
+                let task = Task {
+                    id: Uuid::from_u128(i as u128 + 1),
+                    payload: Arc::new(json!({
+                        "type": arrival.task_type,
+                        "duration_us": arrival.duration.as_micros() as u64,
+                        "arrived_us": arrival.at.as_micros() as u64,
+                    })),
+                    priority: arrival.priority,
+                    retry_count: 0,
+                    run_at: None,
+                    context: Default::default(),
+                    kind: Default::default(),
+                    depends_on: Default::default(),
+                    tenant: None,
+                };
+                tasks.insert_queued(task.id, task.priority, DEFAULT_TENANT);
+                match queue.try_push(task).await {
+                    Ok(()) => accepted.fetch_add(1, Ordering::Relaxed),
+                    Err(_) => rejected.fetch_add(1, Ordering::Relaxed),
+                };
+            }
+        })
+    };
+
+    // 按采样间隔记录队列深度，直到所有到达的任务处理完
+    let last_arrival = arrivals.last().map_or(Duration::ZERO, |a| a.at);
+    let mut depths = Vec::new();
+    loop {
+        depths.push(queue.len().await);
+        let completed = samples.lock().unwrap_or_else(|e| e.into_inner()).len();
+        if driver.is_finished() && completed >= accepted.load(Ordering::Relaxed) {
+            break;
+        }
+        if epoch.elapsed() > last_arrival + MAX_DRAIN {
+            break;
+        }
+        sleep(spec.sample_interval).await;
+    }
+    shutdown.cancel();
+    scheduler.abort();
+
+    let samples = std::mem::take(&mut *samples.lock().unwrap_or_else(|e| e.into_inner()));
+    let completed = samples.len();
+    let simulated = samples.iter().map(|s| s.finished).max().unwrap_or_default();
+    let mut by_class = BTreeMap::new();
+    for class in PriorityClass::ALL {
+        let latencies = samples
+            .iter()
+            .filter(|s| s.class == class)
+            .map(|s| s.finished - s.arrived);
+        by_class.insert(class.as_str(), Percentiles::of(latencies));
+    }
+    let mut by_type = BTreeMap::new();
+    for name in overrides.keys() {
+        let latencies = samples
+            .iter()
+            .filter(|s| s.task_type == *name)
+            .map(|s| s.finished - s.arrived);
+        by_type.insert(name.clone(), Percentiles::of(latencies));
+    }
+    RunReport {
+        workers,
+        completed,
+        rejected: rejected.load(Ordering::Relaxed),
+        unfinished: accepted.load(Ordering::Relaxed).saturating_sub(completed),
+        simulated_secs: simulated.as_secs_f64(),
+        throughput_per_sec: completed as f64 / simulated.as_secs_f64().max(1e-9),
+        queue_depth: DepthStats::of(depths),
+        wait_ms: Percentiles::of(samples.iter().map(|s| s.started - s.arrived)),
+        latency_ms: Percentiles::of(samples.iter().map(|s| s.finished - s.arrived)),
+        latency_ms_by_class: by_class,
+        latency_ms_by_type: by_type,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(rate: f64) -> SimulationSpec {
+        toml::from_str(&format!(
+            r#"
+seed = 7
+priority_weights = [50, 0, 50]
+phases = [{{ duration = "60s", rate = {} }}]
+types = [
+    {{ name = "resize", weight = 3, duration = "100ms", duration_p95 = "300ms" }},
+    {{ name = "report", weight = 1, duration = 250 }},
+]
+"#,
+            rate
+        ))
+        .unwrap()
+    }
+
+    /// 测试到达序列可复现，且到达速率、类型比例与耗时分布大致符合描述。
+    #[test]
+    fn test_arrivals() {
+        let spec = spec(20.0);
+        let a = arrivals(&spec);
+        assert_eq!(a, arrivals(&spec));
+        assert!((1_000..1_400).contains(&a.len()));
+        assert!(a.windows(2).all(|w| w[0].at <= w[1].at));
+        assert!(a.iter().all(|t| t.at < Duration::from_secs(60)));
+        let reports = a.iter().filter(|t| t.task_type == "report").count();
+        assert!((200..400).contains(&reports));
+        assert!(a
+            .iter()
+            .filter(|t| t.task_type == "report")
+            .all(|t| t.duration == Duration::from_millis(250)));
+        let mut resize: Vec<Duration> = a
+            .iter()
+            .filter(|t| t.task_type == "resize")
+            .map(|t| t.duration)
+            .collect();
+        resize.sort();
+        let median = resize[resize.len() / 2];
+        assert!(median > Duration::from_millis(80) && median < Duration::from_millis(120));
+        assert!(a
+            .iter()
+            .all(|t| PriorityClass::from_priority(t.priority) != PriorityClass::Normal));
+    }
+
+    /// 测试在模拟时钟上运行调度器：所有任务都被处理，增加工作者降低排队时间，过载时关键任务优先。
+    #[test]
+    fn test_run() {
+        // 平均耗时约 0.15 秒、每秒 10 个任务，需要约 1.5 个工作者
+        let spec = spec(10.0);
+        let report = run(&spec, Some(vec![1, 4])).unwrap();
+        assert_eq!(report.runs.len(), 2);
+        assert!(!report.slow_types["report"]);
+        let (one, four) = (&report.runs[0], &report.runs[1]);
+        for run in &report.runs {
+            assert_eq!(run.completed, report.arrivals);
+            assert_eq!((run.rejected, run.unfinished), (0, 0));
+            assert!(run.latency_ms_by_type["report"].p50 >= 250.0);
+        }
+        assert!(one.queue_depth.max > four.queue_depth.max);
+        assert!(one.wait_ms.p90 > four.wait_ms.p90);
+        assert!(four.wait_ms.p50 < 1.0);
+        assert!(
+            one.latency_ms_by_class["critical"].p90 < one.latency_ms_by_class["low"].p90,
+            "过载时关键任务应当先被处理"
+        );
+        assert!(run(&spec, None).is_err());
+    }
+
+    /// 测试参数解析。
+    #[test]
+    fn test_parse_args() {
+        let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            parse_args(&args("load.toml --workers 4,8")).unwrap(),
+            (PathBuf::from("load.toml"), Some(vec![4, 8]))
+        );
+        assert_eq!(
+            parse_args(&args("load.yaml")).unwrap(),
+            (PathBuf::from("load.yaml"), None)
+        );
+        assert!(parse_args(&args("load.toml --workers 0")).is_err());
+        assert!(parse_args(&[]).is_err());
+    }
+}
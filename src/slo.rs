@@ -0,0 +1,340 @@
+use crate::metrics;
+use crate::units;
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 判定快速消耗所需的最少完成数，避免流量很小时一两次失败就触发告警。
+const MIN_FAST_BURN_EVENTS: u64 = 10;
+
+/// 单个任务类型的延迟目标：`objective` 比例的任务应在 `threshold` 内完成。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SloTarget {
+    /// 目标比例，例如 0.99 表示 99%。
+    pub objective: f64,
+    /// 从提交到成功完成的端到端耗时上限。
+    pub threshold: Duration,
+}
+
+/// SLO 跟踪的配置。
+#[derive(Debug, Clone)]
+pub struct SloSettings {
+    /// 按任务类型的延迟目标，没有配置目标的任务类型不跟踪。
+    pub targets: HashMap<String, SloTarget>,
+    /// 计算达标率与消耗速率的滚动窗口。
+    pub window: Duration,
+    /// 判定快速消耗的短窗口。
+    pub fast_window: Duration,
+    /// 短窗口内的消耗速率达到该值时视为快速消耗并告警。
+    pub fast_burn_rate: f64,
+    /// 检查消耗速率并更新指标的间隔。
+    pub check_interval: Duration,
+    /// 快速消耗时接收告警的 webhook，`None` 表示只记录日志与指标。
+    pub alert_webhook: Option<Url>,
+}
+
+impl Default for SloSettings {
+    fn default() -> Self {
+        Self {
+            targets: HashMap::new(),
+            window: Duration::from_secs(3600),
+            fast_window: Duration::from_secs(300),
+            fast_burn_rate: 14.4,
+            check_interval: Duration::from_secs(30),
+            alert_webhook: None,
+        }
+    }
+}
+
+/// 解析按任务类型的延迟目标，格式为逗号分隔的 `type=objective@threshold`，
+/// 例如 `transform=99%@30s,report=99.9%@2m`；目标比例的 `%` 可以省略，不带单位的时长按秒解释。
+pub fn parse_targets(list: &str) -> Result<HashMap<String, SloTarget>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|rule| {
+            let (task_type, target) = rule
+                .split_once('=')
+                .ok_or_else(|| format!("{}: 格式应为 type=objective@threshold", rule))?;
+            let (objective, threshold) = target
+                .split_once('@')
+                .ok_or_else(|| format!("{}: 格式应为 type=objective@threshold", rule))?;
+            let objective: f64 = objective
+                .trim()
+                .trim_end_matches('%')
+                .parse()
+                .map_err(|_| format!("{}: 目标比例必须是数字", rule))?;
+            if !(objective > 0.0 && objective < 100.0) {
+                return Err(format!("{}: 目标比例必须大于 0 且小于 100%", rule));
+            }
+            let threshold = units::parse_duration(threshold, Duration::from_secs(1))?;
+            Ok((
+                task_type.trim().to_string(),
+                SloTarget {
+                    objective: objective / 100.0,
+                    threshold,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// 单个任务类型的 SLO 状态。
+#[derive(Debug, Clone, Serialize)]
+pub struct SloReport {
+    pub task_type: String,
+    /// 目标比例，例如 0.99。
+    pub objective: f64,
+    pub threshold_ms: u64,
+    pub window_secs: u64,
+    /// 窗口内完成（成功或最终失败）的任务数。
+    pub total: u64,
+    /// 窗口内在时限内成功完成的任务数。
+    pub good: u64,
+    /// 窗口内的达标率，没有完成的任务时为 `null`。
+    pub compliance: Option<f64>,
+    /// 窗口内错误预算的消耗速率：1 表示恰好在窗口结束时用完预算。
+    pub burn_rate: Option<f64>,
+    /// 短窗口内错误预算的消耗速率。
+    pub fast_burn_rate: Option<f64>,
+    /// 短窗口的消耗速率是否达到告警阈值。
+    pub fast_burning: bool,
+}
+
+/// 每秒一个桶：`(秒序号, 完成数, 达标数)`。
+type Bucket = (u64, u64, u64);
+
+/// 按任务类型统计端到端延迟的达标情况。
+///
+/// 任务最终成功或失败时由调度器记录一次：在目标时限内成功完成的任务计为达标，
+/// 超时完成或最终失败的任务都消耗错误预算。统计只在本实例内进行，
+/// 窗口按秒分桶，超出窗口的桶被丢弃。
+pub struct SloTracker {
+    settings: SloSettings,
+    origin: Instant,
+    buckets: Mutex<HashMap<String, VecDeque<Bucket>>>,
+}
+
+impl SloTracker {
+    pub fn new(settings: SloSettings) -> Self {
+        Self {
+            settings,
+            origin: Instant::now(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 记录一个最终成功或失败的任务，`latency` 为从提交到结束的耗时。
+    pub fn record(&self, task_type: &str, latency: Duration, succeeded: bool) {
+        let Some(target) = self.settings.targets.get(task_type) else {
+            return;
+        };
+        let good = succeeded && latency <= target.threshold;
+        let now = self.origin.elapsed().as_secs();
+        let mut buckets = self.lock();
+        let buckets = buckets.entry(task_type.to_string()).or_default();
+        match buckets.back_mut() {
+            Some(bucket) if bucket.0 == now => {
+                bucket.1 += 1;
+                bucket.2 += u64::from(good);
+            }
+            _ => buckets.push_back((now, 1, u64::from(good))),
+        }
+        let window = self.settings.window.as_secs().max(1);
+        while buckets
+            .front()
+            .is_some_and(|bucket| bucket.0 + window <= now)
+        {
+            buckets.pop_front();
+        }
+    }
+
+    /// 所有配置了目标的任务类型的当前状态，按任务类型排序。
+    pub fn reports(&self) -> Vec<SloReport> {
+        let now = self.origin.elapsed().as_secs();
+        let window = self.settings.window.as_secs().max(1);
+        let fast_window = self.settings.fast_window.as_secs().max(1);
+        let buckets = self.lock();
+        let mut reports: Vec<SloReport> = self
+            .settings
+            .targets
+            .iter()
+            .map(|(task_type, target)| {
+                let (mut total, mut good, mut fast_total, mut fast_good) = (0, 0, 0, 0);
+                for bucket in buckets.get(task_type).into_iter().flatten() {
+                    if bucket.0 + window > now {
+                        total += bucket.1;
+                        good += bucket.2;
+                    }
+                    if bucket.0 + fast_window > now {
+                        fast_total += bucket.1;
+                        fast_good += bucket.2;
+                    }
+                }
+                let fast_burn_rate = burn_rate(fast_total, fast_good, target.objective);
+                SloReport {
+                    task_type: task_type.clone(),
+                    objective: target.objective,
+                    threshold_ms: target.threshold.as_millis() as u64,
+                    window_secs: window,
+                    total,
+                    good,
+                    compliance: (total > 0).then(|| good as f64 / total as f64),
+                    burn_rate: burn_rate(total, good, target.objective),
+                    fast_burn_rate,
+                    fast_burning: fast_total >= MIN_FAST_BURN_EVENTS
+                        && fast_burn_rate.is_some_and(|rate| rate >= self.settings.fast_burn_rate),
+                }
+            })
+            .collect();
+        reports.sort_by(|a, b| a.task_type.cmp(&b.task_type));
+        reports
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, VecDeque<Bucket>>> {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 错误预算的消耗速率：未达标比例与允许的未达标比例之比。
+fn burn_rate(total: u64, good: u64, objective: f64) -> Option<f64> {
+    (total > 0).then(|| (total - good) as f64 / total as f64 / (1.0 - objective))
+}
+
+/// 定期检查各任务类型的 SLO，更新指标并在快速消耗时告警。
+///
+/// 每次检查都会更新 `slo_compliance` 与 `slo_burn_rate{window="long"|"fast"}` 指标；
+/// 任务类型进入快速消耗状态时计入 `slo_fast_burn_alerts_total`、记录一条 WARN 日志，
+/// 并向配置的 webhook 发送告警，同一次快速消耗只告警一次，恢复后再次进入时重新告警。
+pub async fn run_slo_monitor(tracker: Arc<SloTracker>, http: reqwest::Client) {
+    let mut ticker = tokio::time::interval(tracker.settings.check_interval);
+    let mut burning: HashSet<String> = HashSet::new();
+    loop {
+        ticker.tick().await;
+        for report in tracker.reports() {
+            let labels = [("task_type", report.task_type.as_str())];
+            if let Some(compliance) = report.compliance {
+                metrics::gauge_with_labels("slo_compliance", &labels).set(compliance);
+            }
+            for (window, rate) in [("long", report.burn_rate), ("fast", report.fast_burn_rate)] {
+                metrics::gauge_with_labels(
+                    "slo_burn_rate",
+                    &[("task_type", report.task_type.as_str()), ("window", window)],
+                )
+                .set(rate.unwrap_or(0.0));
+            }
+            if report.fast_burning {
+                if burning.insert(report.task_type.clone()) {
+                    alert(&tracker.settings, &http, &report).await;
+                }
+            } else if burning.remove(&report.task_type) {
+                tracing::info!(task_type = %report.task_type, "SLO 错误预算的消耗速率已恢复正常");
+            }
+        }
+    }
+}
+
+/// 发出一次快速消耗告警。
+async fn alert(settings: &SloSettings, http: &reqwest::Client, report: &SloReport) {
+    metrics::counter_with_labels(
+        "slo_fast_burn_alerts_total",
+        &[("task_type", report.task_type.as_str())],
+    )
+    .inc();
+    tracing::warn!(
+        task_type = %report.task_type,
+        fast_burn_rate = report.fast_burn_rate.unwrap_or_default(),
+        threshold = settings.fast_burn_rate,
+        "SLO 错误预算正在快速消耗"
+    );
+    let Some(webhook) = &settings.alert_webhook else {
+        return;
+    };
+    let body = json!({ "event": "slo_fast_burn", "slo": report });
+    let sent = http
+        .post(webhook.clone())
+        .json(&body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(e) = sent {
+        metrics::counter("slo_alert_failures_total").inc();
+        tracing::error!(task_type = %report.task_type, "发送 SLO 告警失败: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> SloTracker {
+        SloTracker::new(SloSettings {
+            targets: parse_targets("report=99%@30s").unwrap(),
+            ..Default::default()
+        })
+    }
+
+    /// 测试延迟目标的解析。
+    #[test]
+    fn test_parse_targets() {
+        let targets = parse_targets("transform=99%@30s, report = 99.9@2m").unwrap();
+        assert_eq!(
+            targets["transform"],
+            SloTarget {
+                objective: 0.99,
+                threshold: Duration::from_secs(30),
+            }
+        );
+        assert!((targets["report"].objective - 0.999).abs() < 1e-9);
+        assert_eq!(targets["report"].threshold, Duration::from_secs(120));
+        assert!(parse_targets("report=99%").is_err());
+        assert!(parse_targets("report=100%@30s").is_err());
+        assert!(parse_targets("report=abc@30s").is_err());
+    }
+
+    /// 测试达标率与消耗速率的计算，以及快速消耗的判定。
+    #[tokio::test(start_paused = true)]
+    async fn test_reports() {
+        let tracker = tracker();
+        let report = &tracker.reports()[0];
+        assert_eq!(report.total, 0);
+        assert_eq!(report.compliance, None);
+        assert!(!report.fast_burning);
+
+        // 未配置目标的任务类型不跟踪
+        tracker.record("email", Duration::from_secs(1), true);
+        for _ in 0..90 {
+            tracker.record("report", Duration::from_secs(1), true);
+        }
+        // 超时完成与最终失败都消耗错误预算
+        for _ in 0..5 {
+            tracker.record("report", Duration::from_secs(60), true);
+            tracker.record("report", Duration::from_secs(1), false);
+        }
+        let reports = tracker.reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!((reports[0].total, reports[0].good), (100, 90));
+        assert!((reports[0].compliance.unwrap() - 0.9).abs() < 1e-9);
+        assert!((reports[0].burn_rate.unwrap() - 10.0).abs() < 1e-6);
+        assert!(!reports[0].fast_burning);
+
+        for _ in 0..20 {
+            tracker.record("report", Duration::from_secs(1), false);
+        }
+        assert!(tracker.reports()[0].fast_burning);
+
+        // 短窗口过去后不再处于快速消耗状态，长窗口仍然计入
+        tokio::time::advance(Duration::from_secs(301)).await;
+        let report = &tracker.reports()[0];
+        assert!(!report.fast_burning);
+        assert_eq!(report.fast_burn_rate, None);
+        assert_eq!(report.total, 120);
+
+        tokio::time::advance(Duration::from_secs(3600)).await;
+        assert_eq!(tracker.reports()[0].total, 0);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::metrics;
+use crate::queue::{PriorityClass, QueueBackend, StarvationThresholds};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// 每次扫描时最多检查的饥饿任务数量，避免队列积压严重时扫描结果过大。
+const MAX_REPORTED: usize = 10_000;
+
+/// 定期扫描队列，检测排队时间超过阈值的任务。
+///
+/// 每次扫描都会按档位更新 `queue_starving_tasks` 指标；
+/// 新出现的饥饿任务会被计入 `queue_starvation_detected_total` 并记录一条 WARN 事件，
+/// 同一个任务只告警一次。
+pub async fn run_starvation_monitor(
+    queue: Arc<dyn QueueBackend>,
+    thresholds: StarvationThresholds,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    let mut reported: HashSet<Uuid> = HashSet::new();
+
+    loop {
+        ticker.tick().await;
+        let starving = queue.starving(&thresholds, MAX_REPORTED).await;
+
+        let mut per_class: HashMap<PriorityClass, usize> = HashMap::new();
+        let mut current = HashSet::with_capacity(starving.len());
+        for task in &starving {
+            *per_class.entry(task.class).or_default() += 1;
+            current.insert(task.id);
+            if !reported.contains(&task.id) {
+                metrics::counter_with_labels(
+                    "queue_starvation_detected_total",
+                    &[("class", task.class.as_str())],
+                )
+                .inc();
+                tracing::warn!(
+                    task_id = %task.id,
+                    priority = task.priority,
+                    class = task.class.as_str(),
+                    waited_ms = task.waited_ms,
+                    "任务排队时间超过阈值"
+                );
+            }
+        }
+        // 只保留仍在饥饿的任务，已出队的任务不再跟踪
+        reported = current;
+
+        for class in PriorityClass::ALL {
+            let count = per_class.get(&class).copied().unwrap_or(0);
+            metrics::gauge_with_labels("queue_starving_tasks", &[("class", class.as_str())])
+                .set(count as f64);
+        }
+    }
+}
@@ -0,0 +1,565 @@
+use crate::db::Database;
+use crate::events::NewTaskEvent;
+use crate::metrics;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+/// 最多保留的已结束（成功或失败）任务记录数，超过后最早结束的记录被淘汰。
+const MAX_FINISHED_RECORDS: usize = 10_000;
+
+/// 将按租户的累计统计写入 `tenant_stats` 表的间隔。
+pub const TENANT_STATS_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 任务的处理状态。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    /// 已入队，等待调度。
+    Queued,
+    /// 提交时被隔离，等待运维人员审核，不会被调度。
+    Quarantined,
+    /// 慢速任务的预算已用完，推迟一段时间后重新排队。
+    Deferred,
+    /// 正在处理。
+    Running,
+    /// 处理成功。
+    Succeeded,
+    /// 重试次数用尽后最终失败。
+    Failed,
+}
+
+impl TaskState {
+    /// 状态的字符串表示，与序列化结果一致。
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TaskState::Queued => "queued",
+            TaskState::Quarantined => "quarantined",
+            TaskState::Deferred => "deferred",
+            TaskState::Running => "running",
+            TaskState::Succeeded => "succeeded",
+            TaskState::Failed => "failed",
+        }
+    }
+
+    /// 是否为终态。
+    pub fn is_finished(self) -> bool {
+        matches!(self, TaskState::Succeeded | TaskState::Failed)
+    }
+}
+
+impl FromStr for TaskState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(TaskState::Queued),
+            "quarantined" => Ok(TaskState::Quarantined),
+            "deferred" => Ok(TaskState::Deferred),
+            "running" => Ok(TaskState::Running),
+            "succeeded" => Ok(TaskState::Succeeded),
+            "failed" => Ok(TaskState::Failed),
+            other => Err(format!("未知的任务状态: {}", other)),
+        }
+    }
+}
+
+/// 单个任务的状态记录。
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskRecord {
+    pub id: Uuid,
+    /// 提交任务的租户。
+    pub tenant: String,
+    pub status: TaskState,
+    pub priority: u8,
+    pub retry_count: u8,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// 最近一次处理失败的原因。
+    pub last_error: Option<String>,
+    /// 记录的版本号，每次状态变化加一，用于生成 ETag。
+    pub version: u64,
+}
+
+impl TaskRecord {
+    /// 记录当前版本的强 ETag（含引号）。
+    ///
+    /// 由任务 ID 与版本号组成：同一毫秒内的多次状态变化也能得到不同的 ETag。
+    pub fn etag(&self) -> String {
+        format!("\"{}-{}\"", self.id.simple(), self.version)
+    }
+}
+
+/// 单个租户的累计计数。
+#[derive(Debug, Clone, Default)]
+struct TenantCounters {
+    queued: u64,
+    quarantined: u64,
+    deferred: u64,
+    running: u64,
+    succeeded: u64,
+    failed: u64,
+    /// 已结束任务从提交到结束的总耗时。
+    total_latency_ms: u64,
+}
+
+impl TenantCounters {
+    fn slot(&mut self, status: TaskState) -> &mut u64 {
+        match status {
+            TaskState::Queued => &mut self.queued,
+            TaskState::Quarantined => &mut self.quarantined,
+            TaskState::Deferred => &mut self.deferred,
+            TaskState::Running => &mut self.running,
+            TaskState::Succeeded => &mut self.succeeded,
+            TaskState::Failed => &mut self.failed,
+        }
+    }
+}
+
+/// 单个租户已结束任务的累计值，即 `tenant_stats` 表的一行；写入时作为增量累加到表中。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TenantTotals {
+    pub tenant: String,
+    pub succeeded: u64,
+    pub failed: u64,
+    pub total_latency_ms: u64,
+}
+
+impl TenantTotals {
+    fn add(&mut self, other: &TenantTotals) {
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        self.total_latency_ms += other.total_latency_ms;
+    }
+}
+
+/// 单个租户的任务统计。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TenantStats {
+    pub tenant: String,
+    /// 当前排队中的任务数（包括等待重试的任务）。
+    pub queued: u64,
+    /// 当前在隔离区等待审核的任务数。
+    pub quarantined: u64,
+    /// 当前因慢速任务预算用完而被推迟的任务数。
+    pub deferred: u64,
+    /// 当前正在处理的任务数。
+    pub running: u64,
+    /// 累计成功的任务数。
+    pub succeeded: u64,
+    /// 累计最终失败的任务数。
+    pub failed: u64,
+    /// 已结束任务中失败的比例，没有已结束任务时为 0。
+    pub failure_rate: f64,
+    /// 已结束任务从提交到结束的平均耗时（毫秒）。
+    pub avg_latency_ms: u64,
+}
+
+impl TenantStats {
+    fn new(tenant: &str, counters: &TenantCounters) -> Self {
+        let completed = counters.succeeded + counters.failed;
+        Self {
+            tenant: tenant.to_string(),
+            queued: counters.queued,
+            quarantined: counters.quarantined,
+            deferred: counters.deferred,
+            running: counters.running,
+            succeeded: counters.succeeded,
+            failed: counters.failed,
+            failure_rate: if completed == 0 {
+                0.0
+            } else {
+                counters.failed as f64 / completed as f64
+            },
+            avg_latency_ms: counters
+                .total_latency_ms
+                .checked_div(completed)
+                .unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    records: HashMap<Uuid, TaskRecord>,
+    /// 已结束任务的 ID，按结束时间排列，用于淘汰最早的记录。
+    finished: VecDeque<Uuid>,
+    /// 按租户的累计计数，不受已结束记录淘汰的影响。
+    tenants: HashMap<String, TenantCounters>,
+    /// 还没有写入 `tenant_stats` 表的已结束任务的增量。
+    unflushed: HashMap<String, TenantTotals>,
+}
+
+/// 进程内的任务状态索引。
+///
+/// 一致性模型：`POST /tasks` 在返回 202 **之前**同步写入索引，
+/// 因此同一实例上紧随其后的 `GET /tasks/:id` 一定能读到刚提交的任务（read-your-writes）；
+/// 调度器在状态变化时同步更新索引，读到的状态总是该实例上的最新状态。
+/// 已结束的任务只保留最近的 `MAX_FINISHED_RECORDS` 条；按租户的统计是累计值，不受淘汰影响，
+/// 其中成功数、失败数与耗时定期累加到 `tenant_stats` 表，重启后从表中继续累计（见 `run_tenant_stats_flusher`）。
+///
+/// 状态查询接口优先读取该索引：调度器在每次状态变化时同步写入（write-through），
+/// 同一记录的读写都在同一把锁内完成，读到的版本号单调递增，不会读到比已返回的写入更旧的状态。
+///
+/// 通过 `with_events` 创建时，每次状态变化都会发送一条事件，由事件记录器写入历史表并广播，
+/// 同时更新 `task_status` 表中的最新状态；索引中查不到的任务（进程重启或已被淘汰）从该表读取。
+#[derive(Clone, Default)]
+pub struct TaskIndex {
+    inner: Arc<Mutex<Inner>>,
+    events: Option<UnboundedSender<NewTaskEvent>>,
+}
+
+impl TaskIndex {
+    /// 创建一个在状态变化时向 `events` 发送事件的索引。
+    pub fn with_events(events: UnboundedSender<NewTaskEvent>) -> Self {
+        Self {
+            inner: Arc::default(),
+            events: Some(events),
+        }
+    }
+
+    /// 发送一条状态变化事件，携带变化后的完整记录；事件记录器已停止时忽略。
+    fn emit(&self, record: &TaskRecord) {
+        if let Some(events) = &self.events {
+            let _ = events.send(NewTaskEvent {
+                task_id: record.id,
+                status: record.status,
+                created_at: record.updated_at,
+                record: record.clone(),
+            });
+        }
+    }
+
+    /// 记录一个刚入队的任务。
+    pub fn insert_queued(&self, id: Uuid, priority: u8, tenant: &str) {
+        self.insert(id, priority, tenant, TaskState::Queued);
+    }
+
+    /// 记录一个提交时被隔离的任务。
+    pub fn insert_quarantined(&self, id: Uuid, priority: u8, tenant: &str) {
+        self.insert(id, priority, tenant, TaskState::Quarantined);
+    }
+
+    fn insert(&self, id: Uuid, priority: u8, tenant: &str, status: TaskState) {
+        let now = Utc::now();
+        let record = TaskRecord {
+            id,
+            tenant: tenant.to_string(),
+            status,
+            priority,
+            retry_count: 0,
+            created_at: now,
+            updated_at: now,
+            last_error: None,
+            version: 1,
+        };
+        let mut inner = self.lock();
+        self.emit(&record);
+        *inner
+            .tenants
+            .entry(tenant.to_string())
+            .or_default()
+            .slot(status) += 1;
+        inner.records.insert(id, record);
+    }
+
+    /// 查询任务的当前状态。
+    pub fn get(&self, id: &Uuid) -> Option<TaskRecord> {
+        self.lock().records.get(id).cloned()
+    }
+
+    /// 更新任务的状态；未知的任务（例如已被淘汰）会被忽略。
+    pub fn set_state(&self, id: &Uuid, status: TaskState, retry_count: u8, error: Option<String>) {
+        let now = Utc::now();
+        let mut guard = self.lock();
+        let inner = &mut *guard;
+        let Some(record) = inner.records.get_mut(id) else {
+            return;
+        };
+        let counters = inner.tenants.entry(record.tenant.clone()).or_default();
+        let previous = counters.slot(record.status);
+        *previous = previous.saturating_sub(1);
+        *counters.slot(status) += 1;
+        if status.is_finished() {
+            let latency_ms = (now - record.created_at).num_milliseconds().max(0) as u64;
+            counters.total_latency_ms += latency_ms;
+            let unflushed = inner
+                .unflushed
+                .entry(record.tenant.clone())
+                .or_insert_with(|| TenantTotals {
+                    tenant: record.tenant.clone(),
+                    ..Default::default()
+                });
+            unflushed.add(&TenantTotals {
+                tenant: String::new(),
+                succeeded: u64::from(status == TaskState::Succeeded),
+                failed: u64::from(status == TaskState::Failed),
+                total_latency_ms: latency_ms,
+            });
+            metrics::counter_with_labels(
+                "tasks_completed_total",
+                &[("tenant", &record.tenant), ("status", status.as_str())],
+            )
+            .inc();
+        }
+        record.status = status;
+        record.retry_count = retry_count;
+        record.updated_at = now;
+        record.version += 1;
+        if error.is_some() {
+            record.last_error = error;
+        }
+        self.emit(record);
+        if status.is_finished() {
+            inner.finished.push_back(*id);
+            while inner.finished.len() > MAX_FINISHED_RECORDS {
+                if let Some(old) = inner.finished.pop_front() {
+                    inner.records.remove(&old);
+                }
+            }
+        }
+    }
+
+    /// 所有租户的任务统计，按租户名排序。
+    pub fn tenant_stats(&self) -> Vec<TenantStats> {
+        let inner = self.lock();
+        let mut stats: Vec<TenantStats> = inner
+            .tenants
+            .iter()
+            .map(|(tenant, counters)| TenantStats::new(tenant, counters))
+            .collect();
+        stats.sort_by(|a, b| a.tenant.cmp(&b.tenant));
+        stats
+    }
+
+    /// 单个租户的任务统计；没有提交过任务的租户各项均为 0。
+    pub fn tenant_stats_for(&self, tenant: &str) -> TenantStats {
+        let inner = self.lock();
+        let counters = inner.tenants.get(tenant).cloned().unwrap_or_default();
+        TenantStats::new(tenant, &counters)
+    }
+
+    /// 将 `tenant_stats` 表中保存的累计值（之前的进程与其他实例写入的）加到按租户的统计上，启动时调用。
+    pub fn load_tenant_totals(&self, totals: &[TenantTotals]) {
+        let mut inner = self.lock();
+        for saved in totals {
+            let counters = inner.tenants.entry(saved.tenant.clone()).or_default();
+            counters.succeeded += saved.succeeded;
+            counters.failed += saved.failed;
+            counters.total_latency_ms += saved.total_latency_ms;
+        }
+    }
+
+    /// 取出还没有写入 `tenant_stats` 表的增量。
+    fn take_unflushed(&self) -> Vec<TenantTotals> {
+        self.lock()
+            .unflushed
+            .drain()
+            .map(|(_, totals)| totals)
+            .collect()
+    }
+
+    /// 写入失败时放回取出的增量，下次一起写入。
+    fn restore_unflushed(&self, totals: Vec<TenantTotals>) {
+        let mut inner = self.lock();
+        for totals in totals {
+            match inner.unflushed.get_mut(&totals.tenant) {
+                Some(unflushed) => unflushed.add(&totals),
+                None => {
+                    inner.unflushed.insert(totals.tenant.clone(), totals);
+                }
+            }
+        }
+    }
+
+    /// 更新排队任务的优先级（例如管理员重新分档后）；不产生状态变化事件。
+    pub fn set_priority(&self, id: &Uuid, priority: u8) {
+        if let Some(record) = self.lock().records.get_mut(id) {
+            record.priority = priority;
+            record.updated_at = Utc::now();
+            record.version += 1;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 将按租户统计的增量累加到 `tenant_stats` 表；写入失败时增量保留到下次。
+///
+/// 以累加而不是覆盖的方式写入，多个实例共用一张表时互不覆盖。
+pub async fn flush_tenant_stats(tasks: &TaskIndex, db: &Database) {
+    let totals = tasks.take_unflushed();
+    if totals.is_empty() {
+        return;
+    }
+    match db.add_tenant_stats(&totals).await {
+        Ok(()) => tracing::debug!(tenants = totals.len(), "已保存按租户的任务统计"),
+        Err(e) => {
+            tracing::warn!("保存按租户的任务统计失败，稍后重试: {}", e);
+            tasks.restore_unflushed(totals);
+        }
+    }
+}
+
+/// 定期将按租户统计的增量写入 `tenant_stats` 表。
+pub async fn run_tenant_stats_flusher(tasks: TaskIndex, db: Database, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // 第一次 tick 立即完成，跳过它，避免启动时写入
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        flush_tenant_stats(&tasks, &db).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试任务记录的写入、状态更新与读取。
+    #[test]
+    fn test_task_index_transitions() {
+        let index = TaskIndex::default();
+        let id = Uuid::new_v4();
+        index.insert_queued(id, 10, "acme");
+        let etag = index.get(&id).unwrap().etag();
+        assert_eq!(index.get(&id).unwrap().status, TaskState::Queued);
+
+        index.set_state(&id, TaskState::Running, 0, None);
+        index.set_state(&id, TaskState::Queued, 1, Some("boom".to_string()));
+        let record = index.get(&id).unwrap();
+        assert_eq!(record.status, TaskState::Queued);
+        assert_eq!(record.retry_count, 1);
+        assert_eq!(record.last_error.as_deref(), Some("boom"));
+        assert_eq!(record.version, 3);
+        assert_ne!(record.etag(), etag);
+
+        index.set_state(&id, TaskState::Succeeded, 1, None);
+        assert_eq!(index.get(&id).unwrap().status, TaskState::Succeeded);
+        assert!(index.get(&Uuid::new_v4()).is_none());
+    }
+
+    /// 测试按租户统计排队、结束的任务数与失败率。
+    #[test]
+    fn test_tenant_stats() {
+        let index = TaskIndex::default();
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        for id in &ids[..3] {
+            index.insert_queued(*id, 10, "acme");
+        }
+        index.insert_queued(ids[3], 10, "globex");
+
+        index.set_state(&ids[0], TaskState::Running, 0, None);
+        index.set_state(&ids[0], TaskState::Succeeded, 0, None);
+        index.set_state(&ids[1], TaskState::Running, 0, None);
+        index.set_state(&ids[1], TaskState::Failed, 3, Some("boom".to_string()));
+        index.set_state(&ids[2], TaskState::Running, 0, None);
+
+        // 被隔离的任务放行后计入排队数
+        let quarantined = Uuid::new_v4();
+        index.insert_quarantined(quarantined, 10, "hooli");
+        assert_eq!(index.tenant_stats_for("hooli").quarantined, 1);
+        index.set_state(&quarantined, TaskState::Queued, 0, None);
+        let hooli = index.tenant_stats_for("hooli");
+        assert_eq!((hooli.queued, hooli.quarantined), (1, 0));
+
+        let acme = index.tenant_stats_for("acme");
+        assert_eq!((acme.queued, acme.running), (0, 1));
+        assert_eq!((acme.succeeded, acme.failed), (1, 1));
+        assert_eq!(acme.failure_rate, 0.5);
+
+        let all = index.tenant_stats();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[1].tenant, "globex");
+        assert_eq!(all[1].queued, 1);
+        assert_eq!(index.tenant_stats_for("initech").queued, 0);
+    }
+
+    /// 测试按租户的累计统计写入 `tenant_stats` 表后，重启的进程从保存的值继续累计。
+    #[tokio::test]
+    async fn test_tenant_stats_survive_restart() {
+        let db = crate::db::test_database().await;
+        let index = TaskIndex::default();
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        for id in &ids {
+            index.insert_queued(*id, 10, "acme");
+        }
+        index.set_state(&ids[0], TaskState::Succeeded, 0, None);
+        index.set_state(&ids[1], TaskState::Failed, 3, None);
+        flush_tenant_stats(&index, &db).await;
+        // 已写入的增量不会被重复累加
+        flush_tenant_stats(&index, &db).await;
+        index.set_state(&ids[2], TaskState::Succeeded, 0, None);
+        flush_tenant_stats(&index, &db).await;
+
+        let restarted = TaskIndex::default();
+        restarted.load_tenant_totals(&db.tenant_stats().await.unwrap());
+        let acme = restarted.tenant_stats_for("acme");
+        assert_eq!((acme.succeeded, acme.failed, acme.queued), (2, 1, 0));
+        assert_eq!(
+            acme.failure_rate,
+            index.tenant_stats_for("acme").failure_rate
+        );
+    }
+
+    /// 测试并发的状态变化与读取：读取方看到的版本号单调递增，写入返回后立即可见。
+    #[test]
+    fn test_concurrent_transitions_and_reads() {
+        let index = TaskIndex::default();
+        let id = Uuid::new_v4();
+        index.insert_queued(id, 10, "acme");
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for retry in 0..500u32 {
+                    let status = if retry % 2 == 0 {
+                        TaskState::Running
+                    } else {
+                        TaskState::Queued
+                    };
+                    index.set_state(&id, status, (retry % 4) as u8, None);
+                    // 写入返回后立即读取，必须看到这次写入或更新的版本
+                    assert!(index.get(&id).unwrap().version >= u64::from(retry) + 2);
+                }
+            });
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..10_000 {
+                        let version = index.get(&id).unwrap().version;
+                        assert!(version >= last, "版本号回退: {} < {}", version, last);
+                        last = version;
+                    }
+                });
+            }
+        });
+
+        let record = index.get(&id).unwrap();
+        assert_eq!(record.version, 501);
+        assert_eq!(record.status, TaskState::Queued);
+        let stats = index.tenant_stats_for("acme");
+        assert_eq!((stats.queued, stats.running), (1, 0));
+    }
+
+    /// 测试每次状态变化都会发送一条事件。
+    #[test]
+    fn test_task_index_emits_events() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let index = TaskIndex::with_events(tx);
+        let id = Uuid::new_v4();
+        index.insert_queued(id, 10, "acme");
+        index.set_state(&id, TaskState::Running, 0, None);
+
+        let statuses: Vec<TaskState> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|e| e.status)
+            .collect();
+        assert_eq!(statuses, vec![TaskState::Queued, TaskState::Running]);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::metrics;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// 首次重启前的等待时间。
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// 重启等待时间的上限。
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// 子任务稳定运行超过该时长后，重启等待时间恢复为初始值。
+const STABLE_RUN: Duration = Duration::from_secs(60);
+/// 统计重启次数的时间窗口。
+const RESTART_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// 单个后台子任务的状态。
+#[derive(Debug, Clone, Serialize)]
+pub struct ChildStatus {
+    /// 子任务名称，例如 "scheduler"。
+    pub name: &'static str,
+    /// 子任务当前是否在运行。
+    pub running: bool,
+    /// 自启动以来的重启总次数。
+    pub restarts: u64,
+    /// 最近一次异常退出的原因。
+    pub last_error: Option<String>,
+    #[serde(skip)]
+    recent_restarts: VecDeque<Instant>,
+}
+
+/// 所有后台子任务的状态汇总。
+#[derive(Debug, Clone, Serialize)]
+pub struct SupervisorStatus {
+    /// 是否有子任务在时间窗口内重启次数超过阈值。
+    pub healthy: bool,
+    pub children: Vec<ChildStatus>,
+}
+
+/// 后台任务的监督者。
+///
+/// 所有后台子任务（调度器、连接池监控等）都通过 `spawn` 启动并由监督者持有：
+/// 子任务 panic 或意外退出时，按指数退避重新启动；
+/// 如果某个子任务在时间窗口内的重启次数超过阈值，`status().healthy` 变为 `false`，
+/// 供就绪检查使用。
+pub struct Supervisor {
+    children: Arc<Mutex<Vec<ChildStatus>>>,
+    tasks: tokio::sync::Mutex<JoinSet<()>>,
+    shutdown: CancellationToken,
+    max_restarts: usize,
+}
+
+impl Supervisor {
+    /// 创建一个监督者，`max_restarts` 为时间窗口内允许的最大重启次数。
+    pub fn new(max_restarts: usize) -> Self {
+        Self {
+            children: Arc::new(Mutex::new(Vec::new())),
+            tasks: tokio::sync::Mutex::new(JoinSet::new()),
+            shutdown: CancellationToken::new(),
+            max_restarts,
+        }
+    }
+
+    /// 在监督下启动一个后台子任务。
+    ///
+    /// `factory` 每次被调用都会创建一个新的子任务 future，用于首次启动和之后的重启。
+    pub async fn spawn<F, Fut>(&self, name: &'static str, factory: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let index = {
+            let mut children = lock(&self.children);
+            children.push(ChildStatus {
+                name,
+                running: false,
+                restarts: 0,
+                last_error: None,
+                recent_restarts: VecDeque::new(),
+            });
+            children.len() - 1
+        };
+
+        let children = self.children.clone();
+        let shutdown = self.shutdown.clone();
+        self.tasks
+            .lock()
+            .await
+            .spawn(supervise(name, index, factory, children, shutdown));
+    }
+
+    /// 返回所有子任务的当前状态。
+    pub fn status(&self) -> SupervisorStatus {
+        let children = lock(&self.children).clone();
+        let healthy = children
+            .iter()
+            .all(|child| child.recent_restarts.len() <= self.max_restarts);
+        SupervisorStatus { healthy, children }
+    }
+
+    /// 停止所有子任务并等待它们退出。
+    pub async fn shutdown(&self) {
+        self.shutdown.cancel();
+        let mut tasks = self.tasks.lock().await;
+        while tasks.join_next().await.is_some() {}
+    }
+}
+
+/// 监督单个子任务：运行、捕获退出原因、按退避策略重启，直到收到停机信号。
+async fn supervise<F, Fut>(
+    name: &'static str,
+    index: usize,
+    factory: F,
+    children: Arc<Mutex<Vec<ChildStatus>>>,
+    shutdown: CancellationToken,
+) where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        lock(&children)[index].running = true;
+        let started = Instant::now();
+        let mut handle = tokio::spawn(factory());
+
+        let reason = tokio::select! {
+            result = &mut handle => match result {
+                Ok(()) => "子任务意外退出".to_string(),
+                Err(e) if e.is_panic() => format!("子任务 panic: {}", panic_message(e.into_panic())),
+                Err(e) => format!("子任务被取消: {}", e),
+            },
+            _ = shutdown.cancelled() => {
+                handle.abort();
+                let _ = handle.await;
+                lock(&children)[index].running = false;
+                return;
+            }
+        };
+
+        tracing::error!(task = name, "后台任务异常退出: {}", reason);
+        metrics::counter_with_labels("supervisor_restarts_total", &[("task", name)]).inc();
+        {
+            let mut children = lock(&children);
+            let child = &mut children[index];
+            let now = Instant::now();
+            child.running = false;
+            child.restarts += 1;
+            child.last_error = Some(reason);
+            child.recent_restarts.push_back(now);
+            while child
+                .recent_restarts
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > RESTART_WINDOW)
+            {
+                child.recent_restarts.pop_front();
+            }
+        }
+
+        // 稳定运行一段时间后再崩溃，视为新的故障，从初始退避开始
+        if started.elapsed() >= STABLE_RUN {
+            backoff = INITIAL_BACKOFF;
+        }
+        tracing::info!(task = name, "将在 {:?} 后重启后台任务", backoff);
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown.cancelled() => return,
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// 从 panic 载荷中提取可读的错误信息。
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "未知错误".to_string()
+    }
+}
+
+/// 获取状态锁；即使锁被毒化也继续使用其中的数据，状态信息不值得让进程崩溃。
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 测试子任务 panic 后会被重启，且重启次数超过阈值后状态变为不健康。
+    #[tokio::test(start_paused = true)]
+    async fn test_supervisor_restarts_crashed_task() {
+        let supervisor = Supervisor::new(1);
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let counter = runs.clone();
+        supervisor
+            .spawn("crashy", move || {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    panic!("boom");
+                }
+            })
+            .await;
+
+        // 暂停的时钟会自动推进，跳过退避等待
+        tokio::time::sleep(Duration::from_secs(10)).await;
+
+        let status = supervisor.status();
+        assert!(runs.load(Ordering::SeqCst) >= 3);
+        assert!(status.children[0].restarts >= 2);
+        assert_eq!(
+            status.children[0].last_error.as_deref(),
+            Some("子任务 panic: boom")
+        );
+        assert!(!status.healthy);
+
+        supervisor.shutdown().await;
+        assert!(!supervisor.status().children[0].running);
+    }
+}
@@ -0,0 +1,158 @@
+use crate::watchdog::Heartbeat;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// systemd 的服务通知（`sd_notify` 协议）。
+///
+/// 以 `Type=notify` 运行时，systemd 通过 `NOTIFY_SOCKET` 传入一个 Unix 数据报套接字的地址，
+/// 服务向其发送 `READY=1`、`STOPPING=1`、`WATCHDOG=1` 等状态。
+/// 不在 systemd 下运行（没有 `NOTIFY_SOCKET`）或不是 Unix 平台时，所有通知都被忽略。
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    socket: Option<PathBuf>,
+}
+
+impl Notifier {
+    /// 根据 `NOTIFY_SOCKET` 创建通知器。
+    pub fn from_env() -> Self {
+        Self {
+            socket: std::env::var_os("NOTIFY_SOCKET")
+                .filter(|s| !s.is_empty())
+                .map(PathBuf::from),
+        }
+    }
+
+    /// 是否运行在 systemd 的通知模式下。
+    pub fn is_enabled(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// 发送一条状态通知；失败只记录日志，不影响服务运行。
+    pub fn notify(&self, state: &str) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+        if let Err(e) = send(socket, state) {
+            tracing::warn!(state, "向 systemd 发送通知失败: {}", e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn send(socket: &std::path::Path, state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let sender = UnixDatagram::unbound()?;
+    // 以 `@` 开头的是 Linux 的抽象命名空间地址
+    #[cfg(target_os = "linux")]
+    if let Some(name) = socket.to_str().and_then(|s| s.strip_prefix('@')) {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        sender.send_to_addr(state.as_bytes(), &addr)?;
+        return Ok(());
+    }
+    sender.send_to(state.as_bytes(), socket)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &std::path::Path, _state: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// 根据 systemd 传入的 `WATCHDOG_USEC`（以及可选的 `WATCHDOG_PID`）计算发送保活通知的间隔。
+///
+/// 间隔取超时时间的一半，留出调度抖动的余量；未启用看门狗或 `WATCHDOG_PID` 不是本进程时返回 `None`。
+pub fn watchdog_interval() -> Option<Duration> {
+    parse_watchdog_interval(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+fn parse_watchdog_interval(
+    usec: Option<&str>,
+    pid: Option<&str>,
+    own_pid: u32,
+) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.trim().parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+    let usec: u64 = usec?.trim().parse().ok()?;
+    if usec == 0 {
+        return None;
+    }
+    Some(Duration::from_micros(usec) / 2)
+}
+
+/// 定期向 systemd 发送 `WATCHDOG=1` 保活通知。
+///
+/// 只有调度器心跳在 `stall_threshold` 内更新过才发送；调度循环卡住时停止保活，
+/// 由 systemd 在 `WatchdogSec` 到期后按 `Restart=` 的设置重启服务。
+pub async fn run_systemd_watchdog(
+    notifier: Notifier,
+    heartbeat: Heartbeat,
+    stall_threshold: Duration,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if heartbeat.age() < stall_threshold {
+            notifier.notify("WATCHDOG=1");
+        } else {
+            tracing::error!(
+                heartbeat_age_ms = heartbeat.age().as_millis() as u64,
+                "调度器心跳已过期，停止向 systemd 发送保活通知"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试 `WATCHDOG_USEC` 与 `WATCHDOG_PID` 的解析。
+    #[test]
+    fn test_parse_watchdog_interval() {
+        assert_eq!(
+            parse_watchdog_interval(Some("30000000"), None, 42),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog_interval(Some("30000000"), Some("42"), 42),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            parse_watchdog_interval(Some("30000000"), Some("7"), 42),
+            None
+        );
+        assert_eq!(parse_watchdog_interval(Some("0"), None, 42), None);
+        assert_eq!(parse_watchdog_interval(Some("abc"), None, 42), None);
+        assert_eq!(parse_watchdog_interval(None, None, 42), None);
+    }
+
+    /// 测试通知通过 Unix 数据报套接字发送。
+    #[cfg(unix)]
+    #[test]
+    fn test_notify() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        let notifier = Notifier { socket: Some(path) };
+        assert!(notifier.is_enabled());
+        notifier.notify("READY=1");
+        let mut buf = [0u8; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+
+        // 未在 systemd 下运行时什么都不做
+        Notifier::default().notify("READY=1");
+    }
+}
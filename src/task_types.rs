@@ -0,0 +1,461 @@
+use crate::db::Database;
+use crate::error::AppError;
+use crate::i18n::Message;
+use crate::metrics;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 任务类型名称的最大长度（字符数），与 `task_type_configs.task_type` 列的长度一致。
+pub const MAX_TYPE_LEN: usize = 128;
+/// `max_retries` 的上限。
+const MAX_RETRIES_LIMIT: u64 = 20;
+/// `timeout_ms` 的上限（一天），同时适用于载荷中的 `timeout_ms`。
+pub const MAX_TIMEOUT_MS: u64 = 86_400_000;
+/// `max_concurrency` 的上限。
+const MAX_CONCURRENCY_LIMIT: u64 = 10_000;
+/// `rate_per_minute` 的上限。
+const MAX_RATE_PER_MINUTE: u64 = 1_000_000;
+/// 速率限制的统计窗口。
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// 一个任务类型的调度配置，`None` 表示沿用默认值（重试 3 次、不限时、不限并发、不限速）。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskTypeConfig {
+    /// 失败后的最大重试次数。
+    #[serde(default)]
+    pub max_retries: Option<u8>,
+    /// 单次执行的超时时间（毫秒），超时的执行按失败处理。
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// 同时处理的该类型任务数的上限。
+    #[serde(default)]
+    pub max_concurrency: Option<u32>,
+    /// 每分钟最多开始处理的该类型任务数。
+    #[serde(default)]
+    pub rate_per_minute: Option<u32>,
+}
+
+impl TaskTypeConfig {
+    /// 检查各项配置是否在允许的范围内。
+    pub fn validate(&self) -> Result<(), AppError> {
+        let fields = [
+            (
+                "max_retries",
+                self.max_retries.map(u64::from),
+                0,
+                MAX_RETRIES_LIMIT,
+            ),
+            ("timeout_ms", self.timeout_ms, 1, MAX_TIMEOUT_MS),
+            (
+                "max_concurrency",
+                self.max_concurrency.map(u64::from),
+                1,
+                MAX_CONCURRENCY_LIMIT,
+            ),
+            (
+                "rate_per_minute",
+                self.rate_per_minute.map(u64::from),
+                1,
+                MAX_RATE_PER_MINUTE,
+            ),
+        ];
+        for (field, value, min, max) in fields {
+            if value.is_some_and(|value| value < min || value > max) {
+                return Err(AppError::Validation(
+                    Message::new("INVALID_TASK_TYPE_CONFIG")
+                        .arg("field", field)
+                        .arg("min", min)
+                        .arg("max", max),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// 单次执行的超时时间。
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_ms.map(Duration::from_millis)
+    }
+
+    /// 处理 `payload` 时的超时时间：载荷中的 `timeout_ms` 只能缩短任务类型的超时时间，不能延长；
+    /// 任务类型不限时时直接使用载荷中的值。
+    pub fn timeout_for(&self, payload: &Value) -> Option<Duration> {
+        let requested = payload
+            .get("timeout_ms")
+            .and_then(Value::as_u64)
+            .filter(|ms| (1..=MAX_TIMEOUT_MS).contains(ms))
+            .map(Duration::from_millis);
+        match (self.timeout(), requested) {
+            (Some(limit), Some(requested)) => Some(limit.min(requested)),
+            (limit, requested) => limit.or(requested),
+        }
+    }
+}
+
+/// 保存在 `task_type_configs` 表中的一条任务类型配置。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaskTypeOverride {
+    pub task_type: String,
+    #[serde(flatten)]
+    pub config: TaskTypeConfig,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 任务因任务类型的限制暂时不能开始处理的原因。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeLimited {
+    /// 正在处理的该类型任务数已达到 `max_concurrency`。
+    Concurrency,
+    /// 最近一分钟开始处理的该类型任务数已达到 `rate_per_minute`，附带距离窗口腾出名额的时长。
+    Rate(Duration),
+}
+
+impl TypeLimited {
+    /// 用于指标标签和日志。
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TypeLimited::Concurrency => "concurrency",
+            TypeLimited::Rate(_) => "rate",
+        }
+    }
+
+    /// 任务需要推迟的时长，并发限制没有确定的等待时间，返回 `None`。
+    pub fn retry_after(self) -> Option<Duration> {
+        match self {
+            TypeLimited::Concurrency => None,
+            TypeLimited::Rate(wait) => Some(wait),
+        }
+    }
+}
+
+/// 一个任务类型当前的使用情况。
+#[derive(Default)]
+struct Usage {
+    running: usize,
+    /// 最近 `RATE_WINDOW` 内开始处理的时刻，只在配置了速率限制时记录。
+    starts: VecDeque<Instant>,
+}
+
+/// 缓存的配置：读取时刻与按任务类型索引的配置。
+type ConfigCache = Option<(Instant, Arc<HashMap<String, TaskTypeConfig>>)>;
+
+/// 按任务类型的调度配置，带有内存缓存与并发、速率限制的计数。
+///
+/// 配置保存在 `task_type_configs` 表中，整张表缓存在内存里，超过 `ttl` 后由下一次读取重新加载；
+/// 通过本实例的管理 API 修改时立即清空缓存，其他实例最迟在 `ttl` 之后生效，调度器不需要重启。
+/// 并发与速率的计数只在本实例内生效，多个实例时每个实例各自限制。
+pub struct TaskTypeConfigs {
+    db: Database,
+    ttl: Duration,
+    cache: Mutex<ConfigCache>,
+    /// 每次清空缓存时加一，避免清空之前开始的加载把旧配置写回缓存。
+    generation: AtomicU64,
+    usage: Mutex<HashMap<String, Usage>>,
+    /// 没有配置 `timeout_ms` 的任务类型使用的超时时间（`TASK_TIMEOUT_MS`），`None` 表示不限时。
+    default_timeout: Option<Duration>,
+}
+
+impl TaskTypeConfigs {
+    pub fn new(db: Database, ttl: Duration) -> Self {
+        Self {
+            db,
+            ttl,
+            default_timeout: None,
+            cache: Mutex::new(None),
+            generation: AtomicU64::new(0),
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 设置没有配置 `timeout_ms` 的任务类型使用的超时时间。
+    pub fn with_default_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.default_timeout = timeout;
+        self
+    }
+
+    /// 任务类型当前生效的配置；没有配置时返回默认值，没有配置超时时间时使用 `default_timeout`。
+    ///
+    /// 加载失败时记录日志并继续使用上一次加载的配置（从未加载成功时使用默认值），不影响任务处理。
+    pub async fn get(&self, task_type: &str) -> TaskTypeConfig {
+        let (cached, fresh) = {
+            let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            match &*cache {
+                Some((at, configs)) => (Some(configs.clone()), at.elapsed() < self.ttl),
+                None => (None, false),
+            }
+        };
+        let configs = match (cached, fresh) {
+            (Some(configs), true) => configs,
+            (cached, _) => match self.reload().await {
+                Ok(configs) => configs,
+                Err(e) => {
+                    tracing::warn!("加载任务类型配置失败，继续使用缓存的配置: {}", e);
+                    cached.unwrap_or_default()
+                }
+            },
+        };
+        let mut config = configs.get(task_type).cloned().unwrap_or_default();
+        if config.timeout_ms.is_none() {
+            config.timeout_ms = self
+                .default_timeout
+                .map(|timeout| timeout.as_millis() as u64);
+        }
+        config
+    }
+
+    /// 从数据库重新加载所有配置并写入缓存。
+    async fn reload(&self) -> Result<Arc<HashMap<String, TaskTypeConfig>>, sqlx::Error> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        let configs: Arc<HashMap<_, _>> = Arc::new(
+            self.db
+                .task_type_configs()
+                .await?
+                .into_iter()
+                .map(|o| (o.task_type, o.config))
+                .collect(),
+        );
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if self.generation.load(Ordering::SeqCst) == generation {
+            *cache = Some((Instant::now(), configs.clone()));
+        }
+        Ok(configs)
+    }
+
+    /// 校验并保存任务类型的配置（整体覆盖），清空缓存使其立即在本实例生效。
+    pub async fn set(
+        &self,
+        task_type: &str,
+        config: TaskTypeConfig,
+    ) -> Result<TaskTypeOverride, AppError> {
+        let task_type = task_type.trim();
+        if task_type.is_empty() || task_type.chars().count() > MAX_TYPE_LEN {
+            return Err(AppError::Validation(
+                Message::new("INVALID_TASK_TYPE_NAME").arg("max", MAX_TYPE_LEN),
+            ));
+        }
+        config.validate()?;
+        let record = TaskTypeOverride {
+            task_type: task_type.to_string(),
+            config,
+            updated_at: Utc::now(),
+        };
+        self.db.upsert_task_type_config(&record).await?;
+        self.invalidate();
+        Ok(record)
+    }
+
+    /// 清空缓存的配置。
+    pub fn invalidate(&self) {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        *cache = None;
+    }
+
+    /// 按 `config` 的并发与速率限制为一个任务申请名额；成功时返回许可，许可被丢弃时归还并发名额。
+    pub fn try_start(
+        self: &Arc<Self>,
+        task_type: &str,
+        config: &TaskTypeConfig,
+    ) -> Result<TypePermit, TypeLimited> {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = usage.entry(task_type.to_string()).or_default();
+        let now = Instant::now();
+        while entry
+            .starts
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= RATE_WINDOW)
+        {
+            entry.starts.pop_front();
+        }
+        let denied = if config
+            .max_concurrency
+            .is_some_and(|limit| entry.running >= limit as usize)
+        {
+            Some(TypeLimited::Concurrency)
+        } else {
+            match (config.rate_per_minute, entry.starts.front()) {
+                (Some(limit), Some(oldest)) if entry.starts.len() >= limit as usize => Some(
+                    TypeLimited::Rate(RATE_WINDOW.saturating_sub(now.duration_since(*oldest))),
+                ),
+                _ => None,
+            }
+        };
+        if let Some(reason) = denied {
+            if entry.running == 0 && entry.starts.is_empty() {
+                usage.remove(task_type);
+            }
+            metrics::counter_with_labels("task_type_limited_total", &[("reason", reason.as_str())])
+                .inc();
+            return Err(reason);
+        }
+        entry.running += 1;
+        if config.rate_per_minute.is_some() {
+            entry.starts.push_back(now);
+        }
+        Ok(TypePermit {
+            configs: self.clone(),
+            task_type: task_type.to_string(),
+        })
+    }
+
+    fn release(&self, task_type: &str) {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = usage.get_mut(task_type) {
+            entry.running = entry.running.saturating_sub(1);
+            if entry.running == 0 && entry.starts.is_empty() {
+                usage.remove(task_type);
+            }
+        }
+    }
+}
+
+/// 一个正在处理的任务占用的并发名额，丢弃时归还。
+pub struct TypePermit {
+    configs: Arc<TaskTypeConfigs>,
+    task_type: String,
+}
+
+impl Drop for TypePermit {
+    fn drop(&mut self) {
+        self.configs.release(&self.task_type);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试配置的范围校验与未知字段。
+    #[test]
+    fn test_validate() {
+        assert!(TaskTypeConfig::default().validate().is_ok());
+        let config = TaskTypeConfig {
+            max_retries: Some(0),
+            timeout_ms: Some(30_000),
+            max_concurrency: Some(4),
+            rate_per_minute: Some(600),
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.timeout(), Some(Duration::from_secs(30)));
+        for invalid in [
+            TaskTypeConfig {
+                max_retries: Some(21),
+                ..Default::default()
+            },
+            TaskTypeConfig {
+                timeout_ms: Some(0),
+                ..Default::default()
+            },
+            TaskTypeConfig {
+                max_concurrency: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err());
+        }
+        assert!(serde_json::from_str::<TaskTypeConfig>(r#"{"retries": 1}"#).is_err());
+    }
+
+    /// 测试载荷中的 `timeout_ms` 只能缩短任务类型的超时时间，以及默认超时时间。
+    #[tokio::test]
+    async fn test_timeout_for() {
+        let limited = TaskTypeConfig {
+            timeout_ms: Some(30_000),
+            ..Default::default()
+        };
+        let short = serde_json::json!({ "timeout_ms": 500 });
+        let long = serde_json::json!({ "timeout_ms": 60_000 });
+        assert_eq!(
+            limited.timeout_for(&short),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(limited.timeout_for(&long), Some(Duration::from_secs(30)));
+        assert_eq!(
+            limited.timeout_for(&serde_json::json!({ "timeout_ms": "1s" })),
+            Some(Duration::from_secs(30))
+        );
+        let unlimited = TaskTypeConfig::default();
+        assert_eq!(unlimited.timeout_for(&long), Some(Duration::from_secs(60)));
+        assert_eq!(unlimited.timeout_for(&serde_json::json!({})), None);
+
+        let db = crate::db::test_database().await;
+        let configs = TaskTypeConfigs::new(db, Duration::from_secs(60))
+            .with_default_timeout(Some(Duration::from_secs(10)));
+        assert_eq!(configs.get("report").await.timeout_ms, Some(10_000));
+        configs.set("report", limited).await.unwrap();
+        assert_eq!(configs.get("report").await.timeout_ms, Some(30_000));
+    }
+
+    /// 测试配置保存后立即生效，以及缓存过期之前不会看到其他实例的修改。
+    #[tokio::test]
+    async fn test_set_and_cache() {
+        let db = crate::db::test_database().await;
+        let configs = TaskTypeConfigs::new(db.clone(), Duration::from_secs(60));
+        assert_eq!(configs.get("report").await, TaskTypeConfig::default());
+
+        let config = TaskTypeConfig {
+            max_retries: Some(5),
+            ..Default::default()
+        };
+        let saved = configs.set(" report ", config.clone()).await.unwrap();
+        assert_eq!(saved.task_type, "report");
+        assert_eq!(configs.get("report").await, config);
+
+        // 另一个实例直接写库：本实例在缓存过期或被清空之前仍使用缓存的配置
+        let other = TaskTypeConfigs::new(db.clone(), Duration::from_secs(60));
+        other
+            .set(
+                "report",
+                TaskTypeConfig {
+                    max_retries: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(configs.get("report").await.max_retries, Some(5));
+        configs.invalidate();
+        assert_eq!(configs.get("report").await.max_retries, Some(1));
+        assert_eq!(db.task_type_configs().await.unwrap().len(), 1);
+
+        assert!(configs.set("", TaskTypeConfig::default()).await.is_err());
+    }
+
+    /// 测试并发与速率限制，以及许可丢弃后归还并发名额。
+    #[test]
+    fn test_try_start() {
+        let configs = Arc::new(TaskTypeConfigs::new(
+            Database::Memory(crate::db::MemoryStore::new()),
+            Duration::from_secs(60),
+        ));
+        let concurrency = TaskTypeConfig {
+            max_concurrency: Some(1),
+            ..Default::default()
+        };
+        let first = configs.try_start("export", &concurrency).unwrap();
+        assert_eq!(
+            configs.try_start("export", &concurrency).err(),
+            Some(TypeLimited::Concurrency)
+        );
+        // 其他类型不受影响
+        let _other = configs.try_start("report", &concurrency).unwrap();
+        drop(first);
+        assert!(configs.try_start("export", &concurrency).is_ok());
+
+        let rate = TaskTypeConfig {
+            rate_per_minute: Some(2),
+            ..Default::default()
+        };
+        drop(configs.try_start("mail", &rate).unwrap());
+        drop(configs.try_start("mail", &rate).unwrap());
+        let limited = configs.try_start("mail", &rate).err().unwrap();
+        assert_eq!(limited.as_str(), "rate");
+        assert!(limited.retry_after().unwrap() <= RATE_WINDOW);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::db::Database;
+use crate::error::AppError;
+use crate::i18n::Message;
+use crate::metrics;
+use crate::web::is_valid_tenant;
+use axum::http::{header, HeaderMap};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// API 令牌的前缀，便于在日志与代码仓库中识别泄露的令牌，也用于与 `ADMIN_TOKEN` 区分。
+pub const TOKEN_PREFIX: &str = "wsk_";
+/// 令牌名称的最大长度（字符数）。
+pub const MAX_NAME_LEN: usize = 64;
+/// 缓存的令牌校验结果数上限，超过后清空缓存，防止随机令牌撑大内存。
+const MAX_CACHED_TOKENS: usize = 10_000;
+
+/// 令牌的权限范围。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// 只能提交任务（`POST /tasks`、`POST /tasks/validate`）。
+    Submit,
+    /// 只能查询任务与统计（公开 API 的 `GET` 接口）。
+    Read,
+    /// 可以访问公开 API 与管理 API 的所有接口。
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Submit => "submit",
+            Scope::Read => "read",
+            Scope::Admin => "admin",
+        }
+    }
+
+    /// 该权限范围是否允许执行需要 `required` 的操作。
+    pub fn allows(self, required: Scope) -> bool {
+        self == Scope::Admin || self == required
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "submit" => Ok(Scope::Submit),
+            "read" => Ok(Scope::Read),
+            "admin" => Ok(Scope::Admin),
+            other => Err(format!("未知的令牌权限范围: {}", other)),
+        }
+    }
+}
+
+/// 一个 API 令牌，保存在 `api_tokens` 表中。
+///
+/// 数据库只保存令牌的 SHA-256 摘要，令牌本身只在创建时返回一次。
+/// 吊销的令牌不会被删除，`revoked_at` 不为空的记录即为吊销列表。
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiToken {
+    pub id: Uuid,
+    /// 令牌的用途说明，例如调用方的服务名。
+    pub name: String,
+    pub scope: Scope,
+    /// 令牌绑定的租户，`None` 表示未绑定：以该令牌提交与查询时由 `X-Tenant-ID` 声明租户。
+    pub tenant: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// 过期时间，`None` 表示永不过期。
+    pub expires_at: Option<DateTime<Utc>>,
+    /// 吊销时间，`None` 表示未吊销。
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    /// 校验并创建一个新令牌，返回令牌记录与令牌明文。
+    pub fn generate(
+        name: &str,
+        scope: Scope,
+        tenant: Option<&str>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(Self, String), AppError> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+            return Err(AppError::Validation(
+                Message::new("INVALID_TOKEN_NAME").arg("max", MAX_NAME_LEN),
+            ));
+        }
+        if tenant.is_some_and(|t| !is_valid_tenant(t)) {
+            return Err(AppError::Validation(Message::new("INVALID_TOKEN_TENANT")));
+        }
+        let now = Utc::now();
+        if expires_at.is_some_and(|at| at <= now) {
+            return Err(AppError::Validation(Message::new("INVALID_TOKEN_EXPIRY")));
+        }
+        let token = Self {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            scope,
+            tenant: tenant.map(str::to_string),
+            created_at: now,
+            expires_at,
+            revoked_at: None,
+        };
+        Ok((token, generate_secret()))
+    }
+
+    /// 检查令牌在 `now` 时是否可用，不可用时返回原因。
+    fn check(&self, now: DateTime<Utc>) -> Result<(), &'static str> {
+        if self.revoked_at.is_some() {
+            return Err("revoked");
+        }
+        if self.expires_at.is_some_and(|at| at <= now) {
+            return Err("expired");
+        }
+        Ok(())
+    }
+}
+
+/// 生成令牌明文：前缀加 256 位随机数的十六进制表示。
+fn generate_secret() -> String {
+    let mut secret = String::from(TOKEN_PREFIX);
+    for byte in Uuid::new_v4()
+        .as_bytes()
+        .iter()
+        .chain(Uuid::new_v4().as_bytes())
+    {
+        let _ = write!(secret, "{:02x}", byte);
+    }
+    secret
+}
+
+/// 令牌明文的 SHA-256 摘要（十六进制），即数据库中保存的值。
+pub fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+/// 从 `Authorization: Bearer <token>` 请求头中取出令牌。
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// 缓存的校验结果：按令牌摘要索引的读取时间与令牌记录（`None` 表示令牌不存在）。
+type TokenCache = HashMap<String, (Instant, Option<ApiToken>)>;
+
+/// API 令牌的校验器，带有按摘要缓存的校验结果。
+///
+/// 每次校验先查缓存，缓存未命中或超过 `ttl` 时从 `api_tokens` 表重新读取；
+/// 不存在的令牌同样会被缓存，避免无效令牌的请求都落到数据库上。
+/// 通过本实例的管理 API 吊销令牌时立即清空缓存，其他实例最迟在 `ttl` 之后看到吊销。
+#[derive(Clone)]
+pub struct TokenStore {
+    db: Database,
+    ttl: Duration,
+    cache: Arc<Mutex<TokenCache>>,
+}
+
+impl TokenStore {
+    pub fn new(db: Database, ttl: Duration) -> Self {
+        Self {
+            db,
+            ttl,
+            cache: Arc::default(),
+        }
+    }
+
+    /// 校验令牌明文，并确认其权限范围允许 `required`。
+    ///
+    /// 令牌不存在、已吊销或已过期时返回 401，权限范围不足时返回 403。
+    pub async fn authenticate(&self, secret: &str, required: Scope) -> Result<ApiToken, AppError> {
+        let hash = hash_secret(secret);
+        let token = match self.cached(&hash) {
+            Some(token) => token,
+            None => {
+                let token = self.db.api_token_by_hash(&hash).await?;
+                let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+                if cache.len() >= MAX_CACHED_TOKENS {
+                    cache.clear();
+                }
+                cache.insert(hash, (Instant::now(), token.clone()));
+                token
+            }
+        };
+        let Some(token) = token else {
+            reject("invalid");
+            return Err(AppError::Unauthorized(Message::new("INVALID_API_TOKEN")));
+        };
+        if let Err(reason) = token.check(Utc::now()) {
+            reject(reason);
+            let code = if reason == "revoked" {
+                "API_TOKEN_REVOKED"
+            } else {
+                "API_TOKEN_EXPIRED"
+            };
+            return Err(AppError::Unauthorized(Message::new(code)));
+        }
+        if !token.scope.allows(required) {
+            reject("scope");
+            return Err(AppError::Forbidden(
+                Message::new("INSUFFICIENT_TOKEN_SCOPE")
+                    .arg("scope", token.scope.as_str())
+                    .arg("required", required.as_str()),
+            ));
+        }
+        Ok(token)
+    }
+
+    /// 清空缓存的校验结果，吊销令牌后调用。
+    pub fn invalidate(&self) {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// 未过期的缓存结果；外层的 `None` 表示缓存未命中。
+    fn cached(&self, hash: &str) -> Option<Option<ApiToken>> {
+        let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .get(hash)
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, token)| token.clone())
+    }
+}
+
+/// 记录一次被拒绝的令牌校验。
+fn reject(reason: &str) {
+    metrics::counter_with_labels("api_token_rejected_total", &[("reason", reason)]).inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试权限范围的包含关系。
+    #[test]
+    fn test_scope_allows() {
+        assert!(Scope::Submit.allows(Scope::Submit));
+        assert!(!Scope::Submit.allows(Scope::Read));
+        assert!(!Scope::Read.allows(Scope::Submit));
+        assert!(!Scope::Read.allows(Scope::Admin));
+        assert!(Scope::Admin.allows(Scope::Submit));
+        assert!(Scope::Admin.allows(Scope::Read));
+    }
+
+    /// 测试令牌的生成、校验、吊销与过期，以及吊销后清空缓存立即生效。
+    #[tokio::test]
+    async fn test_authenticate() {
+        let db = crate::db::test_database().await;
+        let store = TokenStore::new(db.clone(), Duration::from_secs(60));
+
+        let (token, secret) =
+            ApiToken::generate("billing", Scope::Submit, Some("acme"), None).unwrap();
+        assert!(secret.starts_with(TOKEN_PREFIX));
+        assert_eq!(secret.len(), TOKEN_PREFIX.len() + 64);
+        db.insert_api_token(&token, &hash_secret(&secret))
+            .await
+            .unwrap();
+
+        let found = store.authenticate(&secret, Scope::Submit).await.unwrap();
+        assert_eq!(found.id, token.id);
+        assert_eq!(found.tenant.as_deref(), Some("acme"));
+        assert!(matches!(
+            store.authenticate(&secret, Scope::Read).await,
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            store.authenticate("wsk_unknown", Scope::Read).await,
+            Err(AppError::Unauthorized(_))
+        ));
+
+        let revoked = db
+            .revoke_api_token(&token.id, Utc::now())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(revoked.revoked_at.is_some());
+        // 缓存清空之前仍使用缓存的结果
+        assert!(store.authenticate(&secret, Scope::Submit).await.is_ok());
+        store.invalidate();
+        assert!(matches!(
+            store.authenticate(&secret, Scope::Submit).await,
+            Err(AppError::Unauthorized(_))
+        ));
+        assert_eq!(db.api_tokens().await.unwrap().len(), 1);
+        assert!(db
+            .revoke_api_token(&Uuid::new_v4(), Utc::now())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    /// 测试令牌的名称、租户与过期时间校验，以及过期判断。
+    #[test]
+    fn test_generate_and_check() {
+        assert!(ApiToken::generate(" ", Scope::Read, None, None).is_err());
+        assert!(
+            ApiToken::generate(&"n".repeat(MAX_NAME_LEN + 1), Scope::Read, None, None).is_err()
+        );
+        assert!(ApiToken::generate("ci", Scope::Read, Some("acme corp"), None).is_err());
+        let past = Utc::now() - chrono::Duration::seconds(1);
+        assert!(ApiToken::generate("ci", Scope::Read, None, Some(past)).is_err());
+
+        let future = Utc::now() + chrono::Duration::hours(1);
+        let (token, _) = ApiToken::generate("ci", Scope::Read, None, Some(future)).unwrap();
+        assert_eq!(token.check(Utc::now()), Ok(()));
+        assert_eq!(
+            token.check(future + chrono::Duration::seconds(1)),
+            Err("expired")
+        );
+    }
+
+    /// 测试摘要的格式。
+    #[test]
+    fn test_hash_secret() {
+        assert_eq!(
+            hash_secret("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use crate::db::Database;
+use crate::handler::{TaskHandler, TaskOutput};
+use crate::queue::Task;
+use crate::results::ResultStream;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::{stream, FutureExt, StreamExt};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+
+/// 内置的 JSON 转换任务的类型名。
+pub const TRANSFORM_TASK_TYPE: &str = "transform";
+/// 提交转换任务时省略 `priority` 使用的优先级。
+const DEFAULT_PRIORITY: u8 = 50;
+
+/// 将一个路径表达式转换为 JSON Pointer。
+///
+/// 支持两种写法：
+/// - JSON Pointer：`/order/items/0/sku`（`""` 表示整个输入）
+/// - 类 jq 路径：`.order.items[0].sku`、`.["key with.dot"]`（`.` 表示整个输入）
+pub fn to_pointer(expression: &str) -> Result<String, String> {
+    let expression = expression.trim();
+    if expression.is_empty() || expression.starts_with('/') {
+        return Ok(expression.to_string());
+    }
+    let Some(mut rest) = expression.strip_prefix('.') else {
+        return Err(format!("{}: 路径必须以 . 或 / 开头", expression));
+    };
+
+    let mut pointer = String::new();
+    let mut push = |segment: &str| {
+        pointer.push('/');
+        pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+    };
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("[\"") {
+            let end = after
+                .find("\"]")
+                .ok_or_else(|| format!("{}: 缺少 \"]", expression))?;
+            push(&after[..end]);
+            rest = &after[end + 2..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after
+                .find(']')
+                .ok_or_else(|| format!("{}: 缺少 ]", expression))?;
+            let index = &after[..end];
+            if index.parse::<usize>().is_err() {
+                return Err(format!("{}: 数组下标必须是非负整数: {}", expression, index));
+            }
+            push(index);
+            rest = &after[end + 1..];
+        } else {
+            let rest_key = rest.strip_prefix('.').unwrap_or(rest);
+            let end = rest_key.find(['.', '[']).unwrap_or(rest_key.len());
+            if end == 0 {
+                return Err(format!("{}: 字段名不能为空", expression));
+            }
+            push(&rest_key[..end]);
+            rest = &rest_key[end..];
+        }
+    }
+    Ok(pointer)
+}
+
+/// 按路径从输入中取值，路径不存在时为 `null`。
+fn select(input: &Value, expression: &str) -> Result<Value, String> {
+    let pointer = to_pointer(expression)?;
+    Ok(input.pointer(&pointer).cloned().unwrap_or(Value::Null))
+}
+
+/// 执行转换。
+///
+/// 载荷格式为 `{"type": "transform", "input": {...}, "expression": ".a.b"}`
+/// 或 `{"type": "transform", "input": {...}, "mapping": {"out": ".a.b", ...}}`：
+/// `expression` 选出输入中的一部分作为结果，`mapping` 按输出字段逐一选取后组成一个对象。
+pub fn apply(payload: &Value) -> Result<Value, String> {
+    let input = payload.get("input").unwrap_or(&Value::Null);
+    match (payload.get("expression"), payload.get("mapping")) {
+        (Some(Value::String(expression)), None) => select(input, expression),
+        (None, Some(Value::Object(mapping))) => mapping
+            .iter()
+            .map(|(key, expression)| {
+                let expression = expression
+                    .as_str()
+                    .ok_or_else(|| format!("mapping.{} 必须是路径字符串", key))?;
+                Ok((key.clone(), select(input, expression)?))
+            })
+            .collect::<Result<Map<String, Value>, String>>()
+            .map(Value::Object),
+        _ => Err("转换任务必须且只能包含 expression（字符串）或 mapping（对象）之一".to_string()),
+    }
+}
+
+/// 将转换结果渲染为 CSV：结果必须是对象数组，每个对象一行，列为所有对象中出现过的字段（按字段名排序）。
+///
+/// 字符串按原样输出，`null` 输出为空，其余值输出为 JSON；表头之后逐行生成，不会拼接出整个 CSV。
+pub fn to_csv(output: Value) -> Result<ResultStream, String> {
+    let Value::Array(rows) = output else {
+        return Err("CSV 格式要求转换结果是对象数组".to_string());
+    };
+    let mut columns = BTreeSet::new();
+    for row in &rows {
+        let Value::Object(fields) = row else {
+            return Err("CSV 格式要求转换结果是对象数组".to_string());
+        };
+        columns.extend(fields.keys().cloned());
+    }
+    let header = csv_line(columns.iter().map(|column| csv_field(column)));
+    let lines = rows.into_iter().map(move |row| {
+        let line = csv_line(columns.iter().map(|column| match row.get(column) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(text)) => csv_field(text),
+            Some(value) => csv_field(&value.to_string()),
+        }));
+        Ok(Bytes::from(line))
+    });
+    Ok(stream::once(async move { Ok(Bytes::from(header)) })
+        .chain(stream::iter(lines))
+        .boxed())
+}
+
+/// 按 RFC 4180 转义一个字段：包含逗号、引号或换行时加引号，引号写成两个。
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// 用逗号连接字段，以 CRLF 结尾。
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    let mut line = fields.collect::<Vec<_>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+/// `transform` 任务的处理器：转换输入并保存结果，不需要编写自定义的处理器。
+pub struct TransformHandler;
+
+impl TaskHandler for TransformHandler {
+    fn name(&self) -> &'static str {
+        TRANSFORM_TASK_TYPE
+    }
+
+    fn description(&self) -> &'static str {
+        "按类 jq 路径或 JSON Pointer 从 input 中选取数据并保存结果"
+    }
+
+    fn payload_schema(&self) -> Option<Value> {
+        Some(json!({
+            "type": "object",
+            "required": ["type", "input"],
+            "properties": {
+                "type": { "const": TRANSFORM_TASK_TYPE },
+                "input": {
+                    "description": "被转换的输入",
+                    "examples": [{ "order": { "id": 7, "items": [{ "sku": "A1" }] } }]
+                },
+                "expression": {
+                    "type": "string",
+                    "description": "选出输入的一部分作为结果，例如 .order.items 或 /order/items",
+                    "examples": [".order.items"]
+                },
+                "format": {
+                    "enum": ["json", "csv"],
+                    "description": "json（默认）将结果写入数据库；csv 要求结果是对象数组，作为任务结果保存，通过 GET /tasks/:id/result 下载"
+                },
+                "mapping": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "按输出字段逐一选取，值为路径表达式"
+                }
+            },
+            "oneOf": [
+                { "required": ["expression"] },
+                { "required": ["mapping"] }
+            ]
+        }))
+    }
+
+    fn default_priority(&self) -> Option<u8> {
+        Some(DEFAULT_PRIORITY)
+    }
+
+    fn run<'a>(
+        &'a self,
+        task: &'a Task,
+        db: &'a Database,
+    ) -> BoxFuture<'a, anyhow::Result<TaskOutput>> {
+        async move {
+            let output = apply(&task.payload).map_err(|e| anyhow::anyhow!("转换失败: {}", e))?;
+            tracing::info!(task_id = %task.id, "转换任务处理完成");
+            match task.payload.get("format").and_then(Value::as_str) {
+                None | Some("json") => {
+                    db.save_data(&output).await?;
+                    Ok(TaskOutput::Empty)
+                }
+                Some("csv") => Ok(TaskOutput::Stream {
+                    content_type: "text/csv; charset=utf-8".to_string(),
+                    body: to_csv(output).map_err(|e| anyhow::anyhow!("转换失败: {}", e))?,
+                }),
+                Some(other) => Err(anyhow::anyhow!("转换失败: 未知的结果格式 {}", other)),
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 测试两种路径写法得到相同的 JSON Pointer。
+    #[test]
+    fn test_to_pointer() {
+        assert_eq!(
+            to_pointer(".order.items[0].sku").unwrap(),
+            "/order/items/0/sku"
+        );
+        assert_eq!(
+            to_pointer("/order/items/0/sku").unwrap(),
+            "/order/items/0/sku"
+        );
+        assert_eq!(to_pointer(".").unwrap(), "");
+        assert_eq!(to_pointer(".[\"a.b\"].c/d").unwrap(), "/a.b/c~1d");
+        assert!(to_pointer("order").is_err());
+        assert!(to_pointer(".items[x]").is_err());
+        assert!(to_pointer(".a..b").is_err());
+    }
+
+    /// 测试按表达式与按映射转换。
+    #[test]
+    fn test_apply() {
+        let input = json!({ "order": { "id": 7, "items": [{ "sku": "A1" }] } });
+        let by_expression = json!({ "input": input, "expression": ".order.items[0]" });
+        assert_eq!(apply(&by_expression).unwrap(), json!({ "sku": "A1" }));
+
+        let by_mapping = json!({
+            "input": input,
+            "mapping": { "id": ".order.id", "sku": "/order/items/0/sku", "missing": ".nope" },
+        });
+        assert_eq!(
+            apply(&by_mapping).unwrap(),
+            json!({ "id": 7, "sku": "A1", "missing": null })
+        );
+
+        assert!(apply(&json!({ "input": input })).is_err());
+        assert!(apply(&json!({ "input": input, "mapping": { "id": 1 } })).is_err());
+    }
+
+    /// 测试按载荷 Schema 生成的示例是一个可以执行的转换任务。
+    #[test]
+    fn test_example_payload() {
+        let example = TransformHandler.example_payload().unwrap();
+        assert_eq!(example["type"], TRANSFORM_TASK_TYPE);
+        assert_eq!(apply(&example).unwrap(), json!([{ "sku": "A1" }]));
+    }
+
+    /// 测试对象数组渲染为 CSV，缺少的字段输出为空，特殊字符被转义。
+    #[tokio::test]
+    async fn test_to_csv() {
+        let output = json!([
+            { "sku": "A1", "qty": 2 },
+            { "sku": "B,2", "note": "say \"hi\"" },
+        ]);
+        let chunks: Vec<Bytes> = to_csv(output).unwrap().map(Result::unwrap).collect().await;
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(
+            chunks.concat(),
+            b"note,qty,sku\r\n,2,A1\r\n\"say \"\"hi\"\"\",,\"B,2\"\r\n"
+        );
+        assert!(to_csv(json!({ "sku": "A1" })).is_err());
+        assert!(to_csv(json!([1, 2])).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+/// 时长单位及其长度，按后缀长度从长到短排列，保证 `ms` 先于 `m` 匹配。
+const DURATION_UNITS: [(&str, Duration); 5] = [
+    ("ms", Duration::from_millis(1)),
+    ("s", Duration::from_secs(1)),
+    ("m", Duration::from_secs(60)),
+    ("h", Duration::from_secs(60 * 60)),
+    ("d", Duration::from_secs(24 * 60 * 60)),
+];
+
+/// 大小单位及其字节数，匹配时不区分大小写。
+const SIZE_UNITS: [(&str, u64); 7] = [
+    ("b", 1),
+    ("kb", 1000),
+    ("mb", 1000 * 1000),
+    ("gb", 1000 * 1000 * 1000),
+    ("kib", 1 << 10),
+    ("mib", 1 << 20),
+    ("gib", 1 << 30),
+];
+
+/// 解析人类可读的时长，例如 `500ms`、`30s`、`5m`、`1h`、`1h30m`。
+///
+/// 不带单位的整数按 `bare_unit` 解释，兼容原有以 `_MS`/`_SECS` 结尾、只接受整数的配置项。
+pub fn parse_duration(value: &str, bare_unit: Duration) -> Result<Duration, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err("时长不能为空".to_string());
+    }
+    if let Ok(bare) = value.parse::<u32>() {
+        return Ok(bare_unit * bare);
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(format!("{}: 每一段都必须以数字开头", value));
+        }
+        let amount: u32 = rest[..digits]
+            .parse()
+            .map_err(|_| format!("{}: 数值过大", value))?;
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = &rest[..unit_len];
+        let (_, length) = DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .ok_or_else(|| {
+                if unit.is_empty() {
+                    format!("{}: 缺少单位（ms/s/m/h/d）", value)
+                } else {
+                    format!("{}: 未知的时长单位 {}（可选 ms/s/m/h/d）", value, unit)
+                }
+            })?;
+        total = length
+            .checked_mul(amount)
+            .and_then(|part| total.checked_add(part))
+            .ok_or_else(|| format!("{}: 时长过长", value))?;
+        rest = &rest[unit_len..];
+    }
+    Ok(total)
+}
+
+/// 解析人类可读的字节数，例如 `4096`、`64KiB`、`10MiB`、`1GB`（不带单位时为字节）。
+///
+/// `KiB/MiB/GiB` 按 1024 进位，`KB/MB/GB` 按 1000 进位，单位不区分大小写。
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    if digits == 0 {
+        return Err(format!("{}: 大小必须以数字开头", value));
+    }
+    let amount: u64 = value[..digits]
+        .parse()
+        .map_err(|_| format!("{}: 数值过大", value))?;
+    let unit = value[digits..].trim().to_ascii_lowercase();
+    if unit.is_empty() {
+        return Ok(amount);
+    }
+    let (_, factor) = SIZE_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .ok_or_else(|| {
+            format!(
+                "{}: 未知的大小单位 {}（可选 B/KB/MB/GB/KiB/MiB/GiB）",
+                value, unit
+            )
+        })?;
+    amount
+        .checked_mul(*factor)
+        .ok_or_else(|| format!("{}: 大小过大", value))
+}
+
+/// 校验监听地址的格式为 `host:port`，IPv6 地址需要用方括号括起来，例如 `[::]:3000`。
+///
+/// 只检查格式，不解析主机名，主机名在绑定时才解析。
+pub fn validate_address(address: &str) -> Result<(), String> {
+    let (host, port) = address
+        .rsplit_once(':')
+        .ok_or_else(|| format!("{}: 缺少端口，格式应为 host:port", address))?;
+    if host.is_empty() {
+        return Err(format!("{}: 缺少主机，监听所有地址请使用 0.0.0.0", address));
+    }
+    if host.contains(':') && !(host.starts_with('[') && host.ends_with(']')) {
+        return Err(format!(
+            "{}: IPv6 地址需要用方括号括起来，例如 [::1]:3000",
+            address
+        ));
+    }
+    port.parse::<u16>()
+        .map_err(|_| format!("{}: 端口必须是 0–65535 的整数", address))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试带单位、组合与不带单位的时长。
+    #[test]
+    fn test_parse_duration() {
+        let secs = Duration::from_secs(1);
+        assert_eq!(parse_duration("30", secs), Ok(Duration::from_secs(30)));
+        assert_eq!(
+            parse_duration("500", Duration::from_millis(1)),
+            Ok(Duration::from_millis(500))
+        );
+        assert_eq!(
+            parse_duration("250ms", secs),
+            Ok(Duration::from_millis(250))
+        );
+        assert_eq!(parse_duration("5m", secs), Ok(Duration::from_secs(300)));
+        assert_eq!(
+            parse_duration(" 1h30m ", secs),
+            Ok(Duration::from_secs(5400))
+        );
+        assert!(parse_duration("", secs).is_err());
+        assert!(parse_duration("10x", secs)
+            .unwrap_err()
+            .contains("未知的时长单位"));
+        assert!(parse_duration("1h30", secs)
+            .unwrap_err()
+            .contains("缺少单位"));
+        assert!(parse_duration("-5s", secs).is_err());
+    }
+
+    /// 测试二进制与十进制的大小单位。
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("64KiB"), Ok(64 * 1024));
+        assert_eq!(parse_size("10 mib"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("1GB"), Ok(1_000_000_000));
+        assert!(parse_size("10MiBs").is_err());
+        assert!(parse_size("MiB").is_err());
+        assert!(parse_size("99999999999999GiB").is_err());
+    }
+
+    /// 测试监听地址的格式校验。
+    #[test]
+    fn test_validate_address() {
+        assert!(validate_address("0.0.0.0:3000").is_ok());
+        assert!(validate_address("[::]:3000").is_ok());
+        assert!(validate_address("localhost:8080").is_ok());
+        assert!(validate_address("0.0.0.0").is_err());
+        assert!(validate_address(":3000").is_err());
+        assert!(validate_address("::1:3000").is_err());
+        assert!(validate_address("0.0.0.0:70000").is_err());
+    }
+}
@@ -0,0 +1,437 @@
+use crate::i18n::Message;
+use crate::task_types::MAX_TIMEOUT_MS;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// 按 JSON Schema 校验所有任务类型的载荷，配置在 `PAYLOAD_SCHEMA_VALIDATION` 中。
+pub const ALL_TASK_TYPES: &str = "*";
+
+/// 一处字段的校验错误。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// 出错字段的路径，例如 `priority`、`payload.items[0].sku`。
+    pub field: String,
+    pub message: Message,
+}
+
+impl FieldError {
+    fn new(field: impl Into<String>, message: Message) -> Self {
+        Self {
+            field: field.into(),
+            message,
+        }
+    }
+}
+
+/// JSON Schema 校验失败时的处理方式，配置在 `PAYLOAD_SCHEMA_MODE` 中。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaMode {
+    /// 拒绝提交，返回 422（默认）。
+    #[default]
+    Strict,
+    /// 接受提交，但将任务隔离，等待运维人员审核。
+    Lenient,
+}
+
+impl FromStr for SchemaMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "strict" => Ok(SchemaMode::Strict),
+            "lenient" => Ok(SchemaMode::Lenient),
+            other => Err(format!(
+                "未知的 Schema 校验模式: {}（可选 strict/lenient）",
+                other
+            )),
+        }
+    }
+}
+
+/// 提交任务时的校验规则。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubmissionRules {
+    /// 允许的最低优先级。
+    pub min_priority: u8,
+    /// 允许的最高优先级。
+    pub max_priority: u8,
+    /// 提交时按处理器声明的 JSON Schema 校验载荷的任务类型，包含 `*` 时校验所有任务类型。
+    pub schema_types: HashSet<String>,
+    /// 载荷未通过 JSON Schema 校验时拒绝提交还是隔离任务。
+    pub schema_mode: SchemaMode,
+}
+
+impl Default for SubmissionRules {
+    fn default() -> Self {
+        Self {
+            min_priority: u8::MIN,
+            max_priority: u8::MAX,
+            schema_types: HashSet::new(),
+            schema_mode: SchemaMode::default(),
+        }
+    }
+}
+
+impl SubmissionRules {
+    /// 检查优先级是否在允许的范围内。
+    pub fn check_priority(&self, priority: u8) -> Option<FieldError> {
+        (priority < self.min_priority || priority > self.max_priority).then(|| {
+            FieldError::new(
+                "priority",
+                Message::new("PRIORITY_OUT_OF_RANGE")
+                    .arg("min", self.min_priority)
+                    .arg("max", self.max_priority),
+            )
+        })
+    }
+
+    /// 检查载荷中可选的 `timeout_ms`（单次执行的超时时间）是否为允许范围内的整数。
+    pub fn check_timeout(&self, payload: &Value) -> Option<FieldError> {
+        let timeout = payload.get("timeout_ms")?;
+        let valid = timeout
+            .as_u64()
+            .is_some_and(|ms| (1..=MAX_TIMEOUT_MS).contains(&ms));
+        (!valid).then(|| {
+            FieldError::new(
+                "payload.timeout_ms",
+                Message::new("TIMEOUT_OUT_OF_RANGE")
+                    .arg("min", 1)
+                    .arg("max", MAX_TIMEOUT_MS),
+            )
+        })
+    }
+
+    /// 是否需要按 JSON Schema 校验该任务类型的载荷。
+    pub fn validates_schema(&self, task_type: &str) -> bool {
+        self.schema_types.contains(ALL_TASK_TYPES) || self.schema_types.contains(task_type)
+    }
+}
+
+/// 按 JSON Schema 校验 `value`，错误追加到 `errors`，`path` 为 `value` 自身的字段路径。
+///
+/// 只支持处理器声明载荷时常用的关键字：`type`、`const`、`enum`、`required`、`properties`、
+/// `additionalProperties`、`items`、`minItems`/`maxItems`、`minLength`/`maxLength`、
+/// `minimum`/`maximum` 以及 `allOf`/`anyOf`/`oneOf`；其余关键字（如 `pattern`、`$ref`）被忽略。
+pub fn validate_schema(schema: &Value, value: &Value, path: &str, errors: &mut Vec<FieldError>) {
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            errors.push(FieldError::new(
+                path,
+                Message::new("SCHEMA_CONST").arg("expected", expected),
+            ));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            errors.push(FieldError::new(
+                path,
+                Message::new("SCHEMA_ENUM").arg("allowed", allowed.join(", ")),
+            ));
+            return;
+        }
+    }
+    if let Some(expected) = schema.get("type") {
+        let names: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !names.is_empty() && !names.iter().any(|name| has_type(value, name)) {
+            errors.push(FieldError::new(
+                path,
+                Message::new("SCHEMA_TYPE").arg("expected", names.join(" | ")),
+            ));
+            // 类型不对时其余关键字的错误没有意义
+            return;
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            for name in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !object.contains_key(name) {
+                    errors.push(FieldError::new(
+                        child_path(path, name),
+                        Message::new("SCHEMA_REQUIRED"),
+                    ));
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (name, child) in object {
+                let field = child_path(path, name);
+                match (
+                    properties.and_then(|p| p.get(name)),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(property), _) => validate_schema(property, child, &field, errors),
+                    (None, Some(Value::Bool(false))) => errors.push(FieldError::new(
+                        field,
+                        Message::new("SCHEMA_ADDITIONAL_PROPERTY"),
+                    )),
+                    (None, Some(additional @ Value::Object(_))) => {
+                        validate_schema(additional, child, &field, errors)
+                    }
+                    (None, _) => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    errors.push(FieldError::new(
+                        path,
+                        Message::new("SCHEMA_MIN_ITEMS").arg("min", min),
+                    ));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max {
+                    errors.push(FieldError::new(
+                        path,
+                        Message::new("SCHEMA_MAX_ITEMS").arg("max", max),
+                    ));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_schema(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if len < min {
+                    errors.push(FieldError::new(
+                        path,
+                        Message::new("SCHEMA_MIN_LENGTH").arg("min", min),
+                    ));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if len > max {
+                    errors.push(FieldError::new(
+                        path,
+                        Message::new("SCHEMA_MAX_LENGTH").arg("max", max),
+                    ));
+                }
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").filter(|m| m.is_number()) {
+                if n < min.as_f64().unwrap_or_default() {
+                    errors.push(FieldError::new(
+                        path,
+                        Message::new("SCHEMA_MINIMUM").arg("min", min),
+                    ));
+                }
+            }
+            if let Some(max) = schema.get("maximum").filter(|m| m.is_number()) {
+                if n > max.as_f64().unwrap_or_default() {
+                    errors.push(FieldError::new(
+                        path,
+                        Message::new("SCHEMA_MAXIMUM").arg("max", max),
+                    ));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    for branch in schema
+        .get("allOf")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        validate_schema(branch, value, path, errors);
+    }
+    if let Some(branches) = schema.get("anyOf").and_then(Value::as_array) {
+        if !branches.iter().any(|branch| matches_schema(branch, value)) {
+            errors.push(FieldError::new(path, Message::new("SCHEMA_ANY_OF")));
+        }
+    }
+    if let Some(branches) = schema.get("oneOf").and_then(Value::as_array) {
+        let matched = branches
+            .iter()
+            .filter(|branch| matches_schema(branch, value))
+            .count();
+        if matched != 1 {
+            errors.push(FieldError::new(
+                path,
+                Message::new("SCHEMA_ONE_OF").arg("matched", matched),
+            ));
+        }
+    }
+}
+
+/// `value` 是否符合 `schema`，用于判断 `anyOf`/`oneOf` 的分支。
+fn matches_schema(schema: &Value, value: &Value) -> bool {
+    let mut errors = Vec::new();
+    validate_schema(schema, value, "", &mut errors);
+    errors.is_empty()
+}
+
+/// `value` 是否是 JSON Schema 的 `name` 类型；`integer` 包括小数部分为零的数字。
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => true,
+    }
+}
+
+fn child_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn errors(schema: &Value, value: &Value) -> Vec<(String, &'static str)> {
+        let mut errors = Vec::new();
+        validate_schema(schema, value, "payload", &mut errors);
+        errors
+            .into_iter()
+            .map(|e| (e.field, e.message.code()))
+            .collect()
+    }
+
+    /// 测试优先级范围、载荷中的超时时间与需要校验 Schema 的任务类型。
+    #[test]
+    fn test_submission_rules() {
+        let rules = SubmissionRules {
+            min_priority: 10,
+            max_priority: 200,
+            schema_types: HashSet::from(["report".to_string()]),
+            ..Default::default()
+        };
+        assert!(rules.check_priority(10).is_none());
+        assert!(rules.check_priority(200).is_none());
+        let error = rules.check_priority(201).unwrap();
+        assert_eq!(error.field, "priority");
+        assert_eq!(error.message.code(), "PRIORITY_OUT_OF_RANGE");
+        assert!(rules.check_priority(9).is_some());
+        assert!(SubmissionRules::default().check_priority(255).is_none());
+        assert!(rules.check_timeout(&json!({ "timeout_ms": 500 })).is_none());
+        assert!(rules.check_timeout(&json!({})).is_none());
+        let error = rules.check_timeout(&json!({ "timeout_ms": 0 })).unwrap();
+        assert_eq!(error.field, "payload.timeout_ms");
+        assert_eq!(error.message.code(), "TIMEOUT_OUT_OF_RANGE");
+        assert!(rules
+            .check_timeout(&json!({ "timeout_ms": "1s" }))
+            .is_some());
+
+        assert!(rules.validates_schema("report"));
+        assert!(!rules.validates_schema("email"));
+        let all = SubmissionRules {
+            schema_types: HashSet::from([ALL_TASK_TYPES.to_string()]),
+            ..Default::default()
+        };
+        assert!(all.validates_schema("email"));
+        assert_eq!("Lenient".parse(), Ok(SchemaMode::Lenient));
+        assert!("loose".parse::<SchemaMode>().is_err());
+    }
+
+    /// 测试各关键字的校验结果与字段路径。
+    #[test]
+    fn test_validate_schema() {
+        let schema = json!({
+            "type": "object",
+            "required": ["type", "items"],
+            "additionalProperties": false,
+            "properties": {
+                "type": { "const": "report" },
+                "items": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "required": ["sku"],
+                        "properties": {
+                            "sku": { "type": "string", "minLength": 2 },
+                            "count": { "type": "integer", "minimum": 1, "maximum": 100 }
+                        }
+                    }
+                },
+                "format": { "enum": ["csv", "json"] }
+            }
+        });
+        let valid = json!({ "type": "report", "items": [{ "sku": "A1", "count": 3 }] });
+        assert!(errors(&schema, &valid).is_empty());
+
+        let invalid = json!({
+            "type": "email",
+            "items": [{ "sku": "A", "count": 0 }, { "count": 2.5 }],
+            "format": "xml",
+            "extra": true
+        });
+        assert_eq!(
+            errors(&schema, &invalid),
+            vec![
+                ("payload.extra".to_string(), "SCHEMA_ADDITIONAL_PROPERTY"),
+                ("payload.format".to_string(), "SCHEMA_ENUM"),
+                ("payload.items[0].count".to_string(), "SCHEMA_MINIMUM"),
+                ("payload.items[0].sku".to_string(), "SCHEMA_MIN_LENGTH"),
+                ("payload.items[1].sku".to_string(), "SCHEMA_REQUIRED"),
+                ("payload.items[1].count".to_string(), "SCHEMA_TYPE"),
+                ("payload.type".to_string(), "SCHEMA_CONST"),
+            ]
+        );
+        assert_eq!(
+            errors(&schema, &json!({ "type": "report", "items": [] })),
+            vec![("payload.items".to_string(), "SCHEMA_MIN_ITEMS")]
+        );
+        assert_eq!(
+            errors(&schema, &json!([1])),
+            vec![("payload".to_string(), "SCHEMA_TYPE")]
+        );
+    }
+
+    /// 测试 `oneOf` 要求恰好符合一个分支，`anyOf` 至少符合一个。
+    #[test]
+    fn test_validate_schema_branches() {
+        let schema = json!({
+            "type": "object",
+            "oneOf": [{ "required": ["expression"] }, { "required": ["mapping"] }],
+            "anyOf": [{ "required": ["input"] }, { "required": ["source"] }]
+        });
+        let value = json!({ "expression": ".a", "input": {} });
+        assert!(errors(&schema, &value).is_empty());
+        let value = json!({ "expression": ".a", "mapping": {}, "source": "s3" });
+        assert_eq!(
+            errors(&schema, &value),
+            vec![("payload".to_string(), "SCHEMA_ONE_OF")]
+        );
+        assert_eq!(
+            errors(&schema, &json!({})),
+            vec![
+                ("payload".to_string(), "SCHEMA_ANY_OF"),
+                ("payload".to_string(), "SCHEMA_ONE_OF"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::metrics;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 调度器的心跳。
+///
+/// 调度器每次循环迭代都调用 `beat`，看门狗通过 `age` 判断调度器是否卡住。
+/// 内部只保存相对于创建时刻的毫秒数，更新心跳无需加锁。
+#[derive(Clone)]
+pub struct Heartbeat {
+    origin: Instant,
+    last_beat_ms: Arc<AtomicU64>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heartbeat {
+    /// 创建一个新的心跳，创建时刻即视为第一次心跳。
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            last_beat_ms: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// 记录一次心跳。
+    pub fn beat(&self) {
+        let elapsed = self.origin.elapsed().as_millis() as u64;
+        self.last_beat_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// 距离上一次心跳过去的时间。
+    pub fn age(&self) -> Duration {
+        let now = self.origin.elapsed().as_millis() as u64;
+        let last = self.last_beat_ms.load(Ordering::Relaxed);
+        Duration::from_millis(now.saturating_sub(last))
+    }
+}
+
+/// 看门狗的配置。
+#[derive(Debug, Clone, Copy)]
+pub struct WatchdogSettings {
+    /// 心跳超过该时长未更新即视为调度器卡住。
+    pub stall_threshold: Duration,
+    /// 检测到卡住时是否直接终止进程，交由外部进程管理器重启。
+    pub abort_on_stall: bool,
+}
+
+/// 运行看门狗，定期检查调度器心跳。
+///
+/// 心跳过期时记录错误日志并更新指标；如果配置了 `abort_on_stall`，则直接终止进程。
+/// 同一次卡住只告警一次，恢复后记录一条恢复日志。
+pub async fn run_watchdog(heartbeat: Heartbeat, settings: WatchdogSettings) {
+    let check_interval = (settings.stall_threshold / 4).max(Duration::from_millis(100));
+    let mut ticker = tokio::time::interval(check_interval);
+    let mut stalled = false;
+
+    loop {
+        ticker.tick().await;
+        let age = heartbeat.age();
+        metrics::gauge("scheduler_heartbeat_age_seconds").set(age.as_secs_f64());
+
+        if age >= settings.stall_threshold {
+            if !stalled {
+                stalled = true;
+                metrics::counter("scheduler_stalls_total").inc();
+                metrics::gauge("scheduler_stalled").set(1.0);
+                tracing::error!(
+                    heartbeat_age_ms = age.as_millis() as u64,
+                    "调度器心跳已超过 {:?} 未更新，调度循环可能已卡住",
+                    settings.stall_threshold
+                );
+            }
+            if settings.abort_on_stall {
+                tracing::error!("已配置 WATCHDOG_ABORT，终止进程");
+                std::process::abort();
+            }
+        } else if stalled {
+            stalled = false;
+            metrics::gauge("scheduler_stalled").set(0.0);
+            tracing::info!("调度器心跳已恢复");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试心跳的更新与过期时长计算。
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat_age() {
+        let heartbeat = Heartbeat::new();
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(heartbeat.age() >= Duration::from_secs(5));
+
+        heartbeat.beat();
+        assert!(heartbeat.age() < Duration::from_secs(1));
+    }
+}
@@ -1,85 +1,1471 @@
+use crate::backpressure::Throughput;
+use crate::classifier::{self, SlowClassifier};
+use crate::config::Config;
+use crate::db::{self, Connectivity, Database, SchemaCheck};
+use crate::decompress;
 use crate::error::AppError;
-use crate::queue::{PriorityQueue, Task};
+use crate::events::{task_event_stream, EventBus};
+use crate::i18n::{self, Message};
+use crate::ids::{self, MakeRequestIdFromGenerator};
+use crate::jwt::{self, JwtVerifier};
+use crate::lifecycle::Lifecycle;
+use crate::metrics;
+use crate::policy::{PolicyEngine, PolicyInput, Subject, TaskMetadata};
+use crate::quarantine::{self, QuarantineReason};
+use crate::queue::{PriorityClass, QueueBackend, QueueError, Task, TaskKind};
+use crate::queue_migration::MigratingQueue;
+use crate::rate_limit::RateLimiter;
+use crate::response::{self, Fields};
+use crate::results::{self, ByteRange, ResultStore};
+use crate::scheduler::{Handlers, MAX_RETRIES};
+use crate::slo::SloTracker;
+use crate::status::{TaskIndex, TaskState, TenantStats};
+use crate::supervisor::Supervisor;
+use crate::task_types::TaskTypeConfigs;
+use crate::tokens::{self, Scope, TokenStore, TOKEN_PREFIX};
+use crate::validation::{self, FieldError, SchemaMode};
+use crate::watchdog::Heartbeat;
 use axum::{
-    extract::{Request, State},
-    http::{header, StatusCode},
+    body::{to_bytes, Body},
+    extract::{
+        ConnectInfo, DefaultBodyLimit, FromRequestParts, MatchedPath, OriginalUri, Path, Query,
+        RawPathParams, Request, State,
+    },
+    http::{header, HeaderMap, StatusCode},
     middleware::{self, Next},
-    response::Response,
-    routing::post,
-    Json, Router,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Extension, Json, Router,
 };
+use futures::{Stream, StreamExt};
 use serde::Deserialize;
-use sqlx::MySqlPool;
-use std::sync::Arc;
-use tower_http::request_id::{MakeRequestUuid, SetRequestIdLayer};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use tower_http::request_id::SetRequestIdLayer;
+use tracing::Instrument;
 use uuid::Uuid;
 
-/// 应用状态，包含数据库连接池和任务队列。
+/// 应用状态，包含配置、数据库、任务队列和后台任务监督者。
 /// `#[derive(Clone)]` 允许在多个 handler 之间安全地共享 `AppState`。
 #[derive(Clone)]
 pub struct AppState {
-    pub db_pool: MySqlPool,
-    pub queue: Arc<PriorityQueue>,
+    pub config: Arc<Config>,
+    pub db: Database,
+    pub queue: Arc<dyn QueueBackend>,
+    /// 任务状态索引，提交任务时同步写入。
+    pub tasks: TaskIndex,
+    /// 任务事件的广播通道，供事件流订阅。
+    pub events: EventBus,
+    /// 进程的生命周期状态，排空期间拒绝新任务。
+    pub lifecycle: Lifecycle,
+    /// 任务类型的快慢分类。
+    pub classifier: Arc<SlowClassifier>,
+    /// 调度器的出队速度。
+    pub throughput: Arc<Throughput>,
+    pub supervisor: Arc<Supervisor>,
+    pub heartbeat: Heartbeat,
+    /// 最近一次表结构兼容性检查的结果，不兼容时服务处于未就绪状态。
+    pub schema_check: Arc<RwLock<SchemaCheck>>,
+    /// 数据库的连接状态，`DB_CONNECT_LAZY` 时在后台连接成功之前为 `connecting`。
+    pub db_connectivity: Arc<Connectivity>,
+    /// API 令牌的校验器。
+    pub api_tokens: TokenStore,
+    /// JWT 的校验器，未配置 JWT 密钥时为 `None`。
+    pub jwt: Option<Arc<JwtVerifier>>,
+    /// 外部策略服务，未配置 `POLICY_URL` 时为 `None`。
+    pub policy: Option<Arc<PolicyEngine>>,
+    /// 按任务类型的调度配置，与调度器共用。
+    pub task_types: Arc<TaskTypeConfigs>,
+    /// 调度器使用的处理器注册表，用于列出已注册的任务类型与它们的默认优先级。
+    pub handlers: Handlers,
+    /// 按任务类型的端到端延迟目标的达标情况。
+    pub slo: Arc<SloTracker>,
+    /// 公开 API 按客户端的限流器。
+    pub rate_limiter: Arc<RateLimiter>,
+    /// 处理器返回的流式结果，通过 `GET /tasks/:id/result` 下载。
+    pub results: ResultStore,
+    /// 切换队列后端的迁移窗口，未设置 `QUEUE_MIGRATE_FROM` 时为 `None`。
+    pub queue_migration: Option<Arc<MigratingQueue>>,
 }
 
 /// 创建任务的请求体 (payload)。
 #[derive(Deserialize)]
 pub struct CreateTaskPayload {
     payload: serde_json::Value,
-    priority: u8,
+    /// 省略时使用任务类型的默认优先级（见 `GET /task-types`），没有默认优先级时返回 422。
+    #[serde(default)]
+    priority: Option<u8>,
+    /// 任务最早可以开始处理的时刻（RFC 3339），省略时立即可以处理。
+    #[serde(default)]
+    run_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// 执行方式：`quick`、`slow`、已注册的处理器名称，省略时为 `auto`（按历史耗时自动分类）。
+    #[serde(default)]
+    kind: TaskKind,
 }
 
 /// `POST /tasks` 的 handler。
 ///
 /// 从请求体中接收任务数据，创建一个 `Task` 并将其推入优先级队列。
+/// 任务状态在返回 202 之前同步写入状态索引，保证随后的状态查询一定能找到该任务。
+/// 响应体包含任务 ID、排在前面的任务数，以及根据近期处理速度估算的开始时间；
+/// `Location` 响应头指向该任务的状态查询地址（包含 `BASE_PATH` 前缀）。
+/// 队列达到 `QUEUE_CAPACITY` 时返回 429，并通过 `Retry-After` 给出建议的重试时间。
+/// 命中隔离检查的任务同样返回 202（`quarantined` 为 `true`），但不入队，而是写入隔离区等待审核。
 /// - `State(state)`: 提取共享的应用状态 `AppState`。
 /// - `Json(payload)`: 将请求体 JSON 反序列化为 `CreateTaskPayload`。
 async fn create_task(
     State(state): State<AppState>,
+    Extension(Tenant(tenant)): Extension<Tenant>,
+    headers: HeaderMap,
     Json(payload): Json<CreateTaskPayload>,
-) -> Result<StatusCode, AppError> {
+) -> Result<(StatusCode, [(header::HeaderName, String); 1], Json<Value>), AppError> {
+    let Submission {
+        tenant,
+        priority,
+        tasks_ahead,
+        estimated_start_at,
+        quarantine,
+    } = check_submission(&state, tenant, &payload).await?;
+
     let task = Task {
-        id: Uuid::new_v4(),
-        payload: payload.payload,
-        priority: payload.priority,
+        id: ids::generate(),
+        payload: Arc::new(payload.payload),
+        priority,
         retry_count: 0,
+        run_at: payload.run_at,
+        context: state.config.header_propagation.capture(&headers),
+        kind: payload.kind,
+        depends_on: Default::default(),
+        tenant: Some(tenant.clone()),
     };
 
-    // 将任务推入队列
-    state.queue.push(task).await;
+    let id = task.id;
+    let quarantined = !quarantine.is_empty();
+    if quarantined {
+        // 被隔离的任务不入队，写入隔离区等待运维人员放行或拒绝
+        state.tasks.insert_quarantined(id, task.priority, &tenant);
+        if let Err(e) = quarantine::hold(&state.db, &task, &tenant, quarantine.clone()).await {
+            state
+                .tasks
+                .set_state(&id, TaskState::Failed, 0, Some(e.to_string()));
+            return Err(e.into());
+        }
+        tracing::warn!(
+            audit = true,
+            task_id = %id,
+            tenant = %tenant,
+            reasons = ?quarantine,
+            "任务提交时被隔离"
+        );
+    } else {
+        // 先记录状态再入队：调度器可能在入队后立即开始处理，此时状态记录必须已经存在
+        state.tasks.insert_queued(id, task.priority, &tenant);
+        // 将任务推入队列，队列已满时不等待而是立即拒绝；
+        // 失败时状态记录也要反映出来，避免客户端查询到一个永远排队的任务
+        if let Err(e) = state.queue.try_push(task).await {
+            state
+                .tasks
+                .set_state(&id, TaskState::Failed, 0, Some(e.to_string()));
+            return Err(with_estimated_retry_after(&state, e).into());
+        }
+    }
 
     // 返回 202 Accepted 状态码，表示请求已被接受处理
-    Ok(StatusCode::ACCEPTED)
+    Ok((
+        StatusCode::ACCEPTED,
+        [(
+            header::LOCATION,
+            task_location(&state.config.base_path, &id),
+        )],
+        Json(json!({
+            "id": id,
+            "quarantined": quarantined,
+            "tasks_ahead": tasks_ahead,
+            "run_at": payload.run_at,
+            "estimated_start_at": (!quarantined).then_some(estimated_start_at).flatten(),
+        })),
+    ))
+}
+
+/// `POST /tasks/validate` 的 handler。
+///
+/// 对请求执行与 `POST /tasks` 完全相同的检查（租户、排空状态、载荷大小上限、队列容量），
+/// 但不入队，返回任务提交后会得到的处理方式，包括是否会被隔离（隔离原因只对运维人员可见）。检查失败时返回与 `POST /tasks` 相同的错误，
+/// 客户端开发者可以放心地对着生产配置测试集成。
+async fn validate_task(
+    State(state): State<AppState>,
+    Extension(Tenant(tenant)): Extension<Tenant>,
+    Json(payload): Json<CreateTaskPayload>,
+) -> Result<Json<Value>, AppError> {
+    let Submission {
+        tenant,
+        priority,
+        tasks_ahead,
+        estimated_start_at,
+        quarantine,
+    } = check_submission(&state, tenant, &payload).await?;
+
+    let draft = Task {
+        id: Uuid::nil(),
+        payload: Arc::new(payload.payload),
+        priority,
+        retry_count: 0,
+        run_at: payload.run_at,
+        context: Default::default(),
+        kind: payload.kind,
+        depends_on: Default::default(),
+        tenant: None,
+    };
+    Ok(Json(json!({
+        "valid": true,
+        "tenant": tenant,
+        "task_type": classifier::task_type(&draft),
+        "priority": draft.priority,
+        "priority_class": PriorityClass::from_priority(draft.priority).as_str(),
+        "kind": draft.kind,
+        "slow": state.classifier.is_slow(&draft),
+        "quarantined": !quarantine.is_empty(),
+        "tasks_ahead": tasks_ahead,
+        "run_at": payload.run_at,
+        "estimated_start_at": estimated_start_at,
+    })))
+}
+
+/// 一次事务提交中最多可以包含的任务数。
+const MAX_TRANSACTION_TASKS: usize = 100;
+
+/// 事务提交的请求体。
+#[derive(Deserialize)]
+pub struct CreateTransactionPayload {
+    tasks: Vec<TransactionTaskPayload>,
+}
+
+/// 事务中的一个任务：与 `POST /tasks` 的请求体相同，另外带有引用名与依赖。
+#[derive(Deserialize)]
+pub struct TransactionTaskPayload {
+    /// 任务在本次事务中的引用名，在事务内唯一，`depends_on` 通过它引用其他任务。
+    #[serde(rename = "ref")]
+    reference: String,
+    #[serde(flatten)]
+    task: CreateTaskPayload,
+    /// 本任务依赖的其他任务的引用名，它们全部成功之后本任务才会开始处理。
+    #[serde(default)]
+    depends_on: Vec<String>,
+}
+
+/// `POST /tasks/transaction` 的 handler。
+///
+/// 原子地提交一组任务：每个任务都要通过与 `POST /tasks` 相同的检查，依赖的引用名必须存在且不能成环，
+/// 任何一个任务未通过检查、会被隔离或整组放不进队列时，整组任务都不会入队。
+/// 队列按整组入队，持久化的后端在同一个数据库事务中写入整组任务。
+/// 响应体按提交顺序列出每个引用名对应的任务 ID。
+async fn create_transaction(
+    State(state): State<AppState>,
+    Extension(Tenant(tenant)): Extension<Tenant>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateTransactionPayload>,
+) -> Result<(StatusCode, Json<Value>), AppError> {
+    let dependencies = resolve_dependencies(&payload.tasks)?;
+    let mut priorities = Vec::with_capacity(payload.tasks.len());
+    for (index, item) in payload.tasks.iter().enumerate() {
+        let submission = check_submission(&state, tenant.clone(), &item.task)
+            .await
+            .map_err(|e| with_field_prefix(e, index))?;
+        // 被隔离的任务需要人工审核，不能保证与其他任务一起入队
+        if !submission.quarantine.is_empty() {
+            return Err(AppError::InvalidFields(vec![FieldError {
+                field: format!("tasks[{}]", index),
+                message: Message::new("TRANSACTION_TASK_QUARANTINED"),
+            }]));
+        }
+        priorities.push(submission.priority);
+    }
+
+    let ids: Vec<Uuid> = payload.tasks.iter().map(|_| ids::generate()).collect();
+    let context = state.config.header_propagation.capture(&headers);
+    let mut references = Vec::with_capacity(payload.tasks.len());
+    let mut tasks = Vec::with_capacity(payload.tasks.len());
+    for (index, item) in payload.tasks.into_iter().enumerate() {
+        references.push(json!({ "ref": item.reference, "id": ids[index] }));
+        tasks.push(Task {
+            id: ids[index],
+            payload: Arc::new(item.task.payload),
+            priority: priorities[index],
+            retry_count: 0,
+            run_at: item.task.run_at,
+            context: context.clone(),
+            kind: item.task.kind,
+            depends_on: dependencies[index].iter().map(|&i| ids[i]).collect(),
+            tenant: Some(tenant.clone()),
+        });
+    }
+
+    // 先记录状态再入队，与 `POST /tasks` 相同；整组入队失败时所有任务都标记为失败
+    for task in &tasks {
+        state.tasks.insert_queued(task.id, task.priority, &tenant);
+    }
+    if let Err(e) = state.queue.try_push_all(tasks).await {
+        for id in &ids {
+            state
+                .tasks
+                .set_state(id, TaskState::Failed, 0, Some(e.to_string()));
+        }
+        return Err(with_estimated_retry_after(&state, e).into());
+    }
+
+    Ok((StatusCode::ACCEPTED, Json(json!({ "tasks": references }))))
+}
+
+/// 检查事务中的引用名与依赖关系，返回每个任务依赖的任务在事务中的下标。
+///
+/// 引用名必须唯一，依赖的引用名必须存在，依赖关系不能成环（包括依赖自己）。
+fn resolve_dependencies(tasks: &[TransactionTaskPayload]) -> Result<Vec<Vec<usize>>, AppError> {
+    if tasks.is_empty() || tasks.len() > MAX_TRANSACTION_TASKS {
+        return Err(AppError::InvalidFields(vec![FieldError {
+            field: "tasks".to_string(),
+            message: Message::new("TRANSACTION_SIZE").arg("max", MAX_TRANSACTION_TASKS),
+        }]));
+    }
+    let mut errors = Vec::new();
+    let mut indices = HashMap::with_capacity(tasks.len());
+    for (index, item) in tasks.iter().enumerate() {
+        if indices.insert(item.reference.as_str(), index).is_some() {
+            errors.push(FieldError {
+                field: format!("tasks[{}].ref", index),
+                message: Message::new("DUPLICATE_TASK_REF").arg("ref", item.reference.as_str()),
+            });
+        }
+    }
+    let mut dependencies = Vec::with_capacity(tasks.len());
+    for (index, item) in tasks.iter().enumerate() {
+        let mut resolved = Vec::with_capacity(item.depends_on.len());
+        for reference in &item.depends_on {
+            match indices.get(reference.as_str()) {
+                Some(&dependency) if !resolved.contains(&dependency) => resolved.push(dependency),
+                Some(_) => {}
+                None => errors.push(FieldError {
+                    field: format!("tasks[{}].depends_on", index),
+                    message: Message::new("UNKNOWN_TASK_REF").arg("ref", reference.as_str()),
+                }),
+            }
+        }
+        dependencies.push(resolved);
+    }
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors));
+    }
+    // 按拓扑顺序逐个移除没有未决依赖的任务，最后剩下的任务都在环上或依赖环上的任务
+    let mut pending: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+    let mut ready: Vec<usize> = (0..tasks.len()).filter(|&i| pending[i] == 0).collect();
+    let mut resolved = 0;
+    while let Some(index) = ready.pop() {
+        resolved += 1;
+        for (dependent, deps) in dependencies.iter().enumerate() {
+            if deps.contains(&index) {
+                pending[dependent] -= 1;
+                if pending[dependent] == 0 {
+                    ready.push(dependent);
+                }
+            }
+        }
+    }
+    if resolved < tasks.len() {
+        if let Some(index) = (0..tasks.len()).find(|&i| pending[i] > 0) {
+            return Err(AppError::InvalidFields(vec![FieldError {
+                field: format!("tasks[{}].depends_on", index),
+                message: Message::new("DEPENDENCY_CYCLE")
+                    .arg("ref", tasks[index].reference.as_str()),
+            }]));
+        }
+    }
+    Ok(dependencies)
+}
+
+/// 为字段校验错误的字段路径加上事务中任务的下标，例如 `priority` 变为 `tasks[2].priority`。
+fn with_field_prefix(error: AppError, index: usize) -> AppError {
+    match error {
+        AppError::InvalidFields(errors) => AppError::InvalidFields(
+            errors
+                .into_iter()
+                .map(|e| FieldError {
+                    field: format!("tasks[{}].{}", index, e.field),
+                    message: e.message,
+                })
+                .collect(),
+        ),
+        error => error,
+    }
+}
+
+/// 通过提交检查的任务会得到的处理方式。
+struct Submission {
+    tenant: String,
+    /// 请求指定的优先级，省略时为任务类型的默认优先级。
+    priority: u8,
+    tasks_ahead: usize,
+    estimated_start_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// 隔离原因，为空时任务正常入队。
+    quarantine: Vec<QuarantineReason>,
+}
+
+/// 提交任务前的所有检查，`POST /tasks` 与 `POST /tasks/validate` 共用。
+async fn check_submission(
+    state: &AppState,
+    tenant: String,
+    payload: &CreateTaskPayload,
+) -> Result<Submission, AppError> {
+    // 停机时队列会被关闭，入队本身也会失败；这里提前拒绝，省去后面的检查
+    if state.lifecycle.is_draining() {
+        return Err(QueueError::Closed.into());
+    }
+    let task_type = classifier::kind_type(&payload.kind, &payload.payload);
+    let priority = match payload.priority {
+        Some(priority) => priority,
+        None => state.handlers.default_priority(task_type).ok_or_else(|| {
+            AppError::Validation(Message::new("MISSING_PRIORITY").arg("task_type", task_type))
+        })?,
+    };
+    let mut quarantine = check_fields(state, &payload.kind, priority, &payload.payload)?;
+    quarantine.extend(state.config.quarantine.scan(&payload.payload));
+    if let Err(e) = state
+        .config
+        .payload_limits
+        .check(&payload.payload, priority)
+    {
+        return Err(AppError::PayloadTooLarge(
+            Message::new("PAYLOAD_TOO_LARGE")
+                .arg("size", e.size)
+                .arg("source", e.source)
+                .arg("limit", e.limit),
+        ));
+    }
+    // 提前检查容量，队列已满时不必生成任务；入队时队列会在锁内再检查一次，不会超出容量
+    let capacity = state.config.queue_capacity;
+    if capacity > 0 {
+        let pending = state.queue.len().await;
+        if pending >= capacity {
+            let retry_after = state.throughput.retry_after(pending - capacity + 1);
+            return Err(QueueError::Full {
+                capacity,
+                retry_after,
+            }
+            .into());
+        }
+    }
+    if let Err(e) = state.queue.check_band_capacity(priority).await {
+        return Err(with_estimated_retry_after(state, e).into());
+    }
+    let tasks_ahead = state.queue.count_at_or_above(priority).await;
+    Ok(Submission {
+        tenant,
+        priority,
+        tasks_ahead,
+        estimated_start_at: estimate_start(&state.throughput, tasks_ahead, payload.run_at),
+        quarantine,
+    })
+}
+
+/// 检查优先级是否在允许的范围内，并按配置用任务类型的 JSON Schema 校验载荷，
+/// 所有出错的字段一并返回（422）。
+///
+/// `PAYLOAD_SCHEMA_MODE=lenient` 时 Schema 校验错误不拒绝提交，而是作为隔离原因返回。
+fn check_fields(
+    state: &AppState,
+    kind: &TaskKind,
+    priority: u8,
+    payload: &Value,
+) -> Result<Vec<QuarantineReason>, AppError> {
+    let rules = &state.config.submission_rules;
+    let task_type = classifier::kind_type(kind, payload);
+    let mut errors: Vec<_> = rules
+        .check_priority(priority)
+        .into_iter()
+        .chain(rules.check_timeout(payload))
+        .collect();
+    // 自定义执行方式必须对应已注册的处理器
+    if let TaskKind::Custom(name) = kind {
+        if !state.handlers.is_registered(name) {
+            errors.push(FieldError {
+                field: "kind".to_string(),
+                message: Message::new("UNKNOWN_TASK_KIND").arg("kind", name.as_str()),
+            });
+        }
+    }
+    let mut quarantine = Vec::new();
+    if rules.validates_schema(task_type) {
+        if let Some(schema) = state.handlers.payload_schema(task_type) {
+            match rules.schema_mode {
+                SchemaMode::Strict => {
+                    validation::validate_schema(&schema, payload, "payload", &mut errors);
+                }
+                SchemaMode::Lenient => {
+                    let mut schema_errors = Vec::new();
+                    validation::validate_schema(&schema, payload, "payload", &mut schema_errors);
+                    quarantine.extend(schema_errors.iter().map(QuarantineReason::schema));
+                }
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(quarantine)
+    } else {
+        metrics::counter("task_validation_failures_total").inc();
+        Err(AppError::InvalidFields(errors))
+    }
+}
+
+/// 队列已满时按当前处理速度估算 `Retry-After`，替换队列给出的默认值。
+pub(crate) fn with_estimated_retry_after(state: &AppState, error: QueueError) -> QueueError {
+    match error {
+        QueueError::Full { capacity, .. } => QueueError::Full {
+            capacity,
+            retry_after: state.throughput.retry_after(1),
+        },
+        QueueError::BandFull {
+            class, capacity, ..
+        } => QueueError::BandFull {
+            class,
+            capacity,
+            retry_after: state.throughput.retry_after(1),
+        },
+        error => error,
+    }
+}
+
+/// `GET /task-types` 的 handler。
+///
+/// 按名称列出已注册处理器的任务类型：说明、载荷的 JSON Schema 与按它生成的载荷示例、默认优先级，
+/// 以及当前生效的重试次数、超时时间、并发与速率限制（包括通过管理 API 修改的配置），
+/// 客户端可以据此了解能提交哪些任务。未注册的任务类型由默认的处理器处理，不在列表中。
+async fn list_task_types(State(state): State<AppState>) -> Json<Value> {
+    let mut task_types = Vec::new();
+    for (name, handler) in state.handlers.registered() {
+        let config = state.task_types.get(name).await;
+        task_types.push(json!({
+            "name": name,
+            "description": handler.description(),
+            "payload_schema": handler.payload_schema(),
+            "example_payload": handler.example_payload(),
+            "default_priority": handler.default_priority(),
+            "max_retries": config.max_retries.unwrap_or(MAX_RETRIES),
+            "timeout_ms": config.timeout_ms,
+            "max_concurrency": config.max_concurrency,
+            "rate_per_minute": config.rate_per_minute,
+        }));
+    }
+    Json(json!({ "task_types": task_types }))
+}
+
+/// 任务状态查询接口的路径，`base_path` 为规范化后的 `BASE_PATH`（空或以 `/` 开头、不以 `/` 结尾）。
+fn task_location(base_path: &str, id: &Uuid) -> String {
+    format!("{}/tasks/{}", base_path, id)
+}
+
+/// 根据调度器近期的处理速度，估算排在 `tasks_ahead` 个任务之后的任务何时开始处理。
+///
+/// 延迟任务不会早于执行时间 `run_at` 开始。
+fn estimate_start(
+    throughput: &Throughput,
+    tasks_ahead: usize,
+    run_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    let now = chrono::Utc::now();
+    let estimate = throughput
+        .estimate(tasks_ahead)
+        .and_then(|wait| chrono::Duration::from_std(wait).ok())
+        .map(|wait| now + wait);
+    match (estimate, run_at.filter(|run_at| *run_at > now)) {
+        (Some(estimate), Some(run_at)) => Some(estimate.max(run_at)),
+        (estimate, run_at) => estimate.or(run_at),
+    }
+}
+
+/// `GET /tasks/:id/position` 的 handler。
+///
+/// 返回排队任务前面还有多少个任务，以及根据近期处理速度估算的开始时间（没有处理速度数据时为 `null`）；
+/// 尚未到执行时间的延迟任务还会返回其 `run_at`。
+/// 任务已经开始处理或已结束时返回 404 及错误码 `TASK_NOT_QUEUED`。
+async fn task_position(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, AppError> {
+    if state.tasks.get(&id).is_none() {
+        return Err(AppError::NotFound(
+            Message::new("TASK_NOT_FOUND").arg("id", id),
+        ));
+    }
+    let position = state.queue.position(&id).await?;
+    Ok(Json(json!({
+        "id": id,
+        "priority": position.priority,
+        "tasks_ahead": position.tasks_ahead,
+        "run_at": position.run_at,
+        "estimated_start_at": estimate_start(&state.throughput, position.tasks_ahead, position.run_at),
+    })))
+}
+
+/// `GET /tasks/:id` 的查询参数。
+#[derive(Deserialize)]
+pub struct TaskQuery {
+    /// 逗号分隔的字段列表，只返回这些字段（见 `Fields`）。
+    fields: Option<String>,
+}
+
+/// `GET /tasks/:id` 的 handler。
+///
+/// 返回任务的当前状态。一致性保证：同一实例上，`POST /tasks` 返回 202 后立即查询
+/// 一定能读到该任务（read-your-writes），不会出现短暂的 404。
+/// 内存索引中查不到的任务从 `task_status` 表读取，进程重启后仍可查询；
+/// 该表由事件记录器异步写入，可能略落后于其他实例上的最新状态。
+///
+/// 响应带有 `ETag`；请求的 `If-None-Match` 与当前 ETag 匹配时返回 304，
+/// 不再序列化响应体，适合频繁轮询的看板。ETag 按完整状态计算，与 `fields` 无关。
+async fn get_task(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<TaskQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    // 索引中没有的任务（进程重启或已被淘汰）从 task_status 表读取
+    let record = match state.tasks.get(&id) {
+        Some(record) => record,
+        None => state
+            .db
+            .task_status(&id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(Message::new("TASK_NOT_FOUND").arg("id", id)))?,
+    };
+    let etag = record.etag();
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| etag_matches(value, &etag));
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        Json(Fields::parse(query.fields.as_deref()).apply(json!(record))).into_response()
+    };
+    if let Ok(value) = header::HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    Ok(response)
+}
+
+/// `GET /tasks/:id/result` 的 handler。
+///
+/// 以流的方式返回处理器保存的任务结果，`Content-Type` 为保存时指定的类型，结果不会被整体读入内存。
+/// 支持单个字节范围的 `Range` 请求（返回 206 与 `Content-Range`），中断的下载可以从断点继续；
+/// 范围超出结果长度时返回 416。任务没有结果（尚未完成，或处理器没有返回流式结果）时返回 404。
+///
+/// 响应带有 `ETag` 与 `Last-Modified`。续传时携带 `If-Range`，结果在两次请求之间被覆盖（例如任务重试）时
+/// 忽略 `Range` 返回完整的新结果，避免客户端把新旧两份结果拼接在一起。
+async fn task_result(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let (meta, mut file) = state
+        .results
+        .open(&id)
+        .await
+        .map_err(|e| anyhow::anyhow!("读取任务 {} 的结果失败: {}", id, e))?
+        .ok_or_else(|| AppError::NotFound(Message::new("RESULT_NOT_FOUND").arg("id", id)))?;
+    let header_value = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let range = match header_value(header::IF_RANGE) {
+        Some(if_range) if !meta.if_range_matches(if_range) => ByteRange::Full,
+        _ => results::byte_range(header_value(header::RANGE), meta.size),
+    };
+    let (start, len) = match range {
+        ByteRange::Full => (0, meta.size),
+        ByteRange::Partial { start, end } => (start, end - start + 1),
+        ByteRange::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", meta.size))],
+            )
+                .into_response());
+        }
+    };
+    file.seek(SeekFrom::Start(start))
+        .await
+        .map_err(|e| anyhow::anyhow!("读取任务 {} 的结果失败: {}", id, e))?;
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, &meta.content_type)
+        .header(header::CONTENT_LENGTH, len)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, meta.etag())
+        .header(header::LAST_MODIFIED, meta.last_modified());
+    let kind = if let ByteRange::Partial { start, end } = range {
+        response = response.status(StatusCode::PARTIAL_CONTENT).header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, meta.size),
+        );
+        "partial"
+    } else {
+        "full"
+    };
+    metrics::counter_with_labels("task_result_downloads_total", &[("kind", kind)]).inc();
+    let body = Body::from_stream(ReaderStream::new(file.take(len)));
+    Ok(response
+        .body(body)
+        .map_err(|e| anyhow::anyhow!("任务 {} 的结果无法返回: {}", id, e))?)
+}
+
+/// `If-None-Match` 的值（逗号分隔的 ETag 列表或 `*`）是否匹配 `etag`。
+///
+/// 按 RFC 9110 对 `If-None-Match` 使用弱比较，忽略 `W/` 前缀。
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+/// `GET /events` 的查询参数。
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// 与 `Last-Event-ID` 请求头含义相同，供无法设置请求头的客户端使用。
+    last_event_id: Option<i64>,
+}
+
+/// `GET /events` 的 handler。
+///
+/// 以 SSE 推送任务状态变化事件，每个事件的 `id` 为其在 `task_events` 表中的 ID。
+/// 客户端重连时携带 `Last-Event-ID`（浏览器的 `EventSource` 会自动携带），
+/// 服务端先回放之后的历史事件再接续实时事件，断线期间的状态变化不会丢失。
+async fn task_events(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, AppError> {
+    let last_event_id = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .ok_or_else(|| AppError::BadRequest(Message::new("INVALID_LAST_EVENT_ID")))?,
+        ),
+        None => query.last_event_id,
+    };
+    let stream = task_event_stream(state.db.clone(), &state.events, last_event_id).map(|event| {
+        Event::default()
+            .id(event.id.to_string())
+            .event("task")
+            .json_data(&event)
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// `GET /stats/starving` 的查询参数。
+#[derive(Deserialize)]
+pub struct StarvingQuery {
+    /// 最多返回的任务数量，默认 20。
+    limit: Option<usize>,
+    /// 逗号分隔的字段列表，每个任务只返回这些字段。
+    fields: Option<String>,
+}
+
+/// `GET /stats/starving` 的 handler。
+///
+/// 列出排队时间超过所属优先级档位阈值的任务，最严重的排在最前面。
+async fn starving_tasks(
+    State(state): State<AppState>,
+    Query(query): Query<StarvingQuery>,
+) -> Result<Json<Value>, AppError> {
+    let limit = query.limit.unwrap_or(20).min(1000);
+    let tasks = state
+        .queue
+        .starving(&state.config.starvation_thresholds, limit)
+        .await;
+    let tasks = Fields::parse(query.fields.as_deref()).apply(json!(tasks));
+    Ok(Json(json!({ "tasks": tasks })))
+}
+
+/// `GET /stats/slo` 的 handler。
+///
+/// 列出配置了延迟目标的任务类型在滚动窗口内的达标率、错误预算的消耗速率，以及是否处于快速消耗状态。
+async fn slo_stats(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "slos": state.slo.reports() }))
+}
+
+/// 标识调用方租户的请求头。
+const TENANT_HEADER: &str = "x-tenant-id";
+/// 未携带租户请求头的请求归入的租户。
+pub(crate) const DEFAULT_TENANT: &str = "default";
+
+/// 请求所属的租户，由鉴权中间件解析后放入请求的扩展中，见 `resolve_tenant`。
+#[derive(Debug, Clone)]
+pub(crate) struct Tenant(pub String);
+
+/// 租户名是否合法：只允许字母、数字、`-` 和 `_`，最长 64 个字符，避免被用作指标标签时产生异常的取值。
+pub(crate) fn is_valid_tenant(tenant: &str) -> bool {
+    !tenant.is_empty()
+        && tenant.len() <= 64
+        && tenant
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// 解析请求所属的租户。
+///
+/// 凭据绑定了租户（API 令牌的 `tenant` 或 JWT 的 `tenant` 声明）时以凭据为准，可以不携带 `X-Tenant-ID`，
+/// 携带的值与凭据不一致时返回 403；凭据没有绑定租户或未启用鉴权时由请求头声明，未携带时归入 `DEFAULT_TENANT`。
+pub(crate) fn resolve_tenant(
+    headers: &HeaderMap,
+    subject: Option<&Subject>,
+) -> Result<String, AppError> {
+    let declared = match headers.get(TENANT_HEADER) {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .map(str::trim)
+                .filter(|t| is_valid_tenant(t))
+                .ok_or_else(|| {
+                    AppError::BadRequest(
+                        Message::new("INVALID_TENANT").arg("header", TENANT_HEADER),
+                    )
+                })?,
+        ),
+        None => None,
+    };
+    match (subject.and_then(Subject::tenant), declared) {
+        (Some(bound), Some(declared)) if declared != bound => Err(AppError::Forbidden(
+            Message::new("TENANT_MISMATCH")
+                .arg("header", TENANT_HEADER)
+                .arg("declared", declared)
+                .arg("tenant", bound),
+        )),
+        (Some(bound), _) => Ok(bound.to_string()),
+        (None, declared) => Ok(declared.unwrap_or(DEFAULT_TENANT).to_string()),
+    }
+}
+
+/// `GET /stats/me` 的 handler。
+///
+/// 返回调用方租户（凭据绑定的租户或 `X-Tenant-ID`）的任务统计。
+async fn my_stats(
+    State(state): State<AppState>,
+    Extension(Tenant(tenant)): Extension<Tenant>,
+) -> Json<TenantStats> {
+    Json(state.tasks.tenant_stats_for(&tenant))
+}
+
+/// 就绪检查中探测数据库的超时，避免数据库卡住时探针请求长时间挂起。
+const READINESS_DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// `GET /healthz` 的 handler（存活探针）。
+///
+/// 只要进程还能处理请求就返回 200，不检查任何依赖，避免数据库故障时编排系统反复重启所有实例。
+async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// `GET /readyz` 的 handler（就绪探针）。
+///
+/// 依次检查数据库（一次轻量查询）、调度器（后台任务在运行且心跳未过期）、表结构与生命周期（未在排空），
+/// 全部通过时返回 200，否则返回 503；响应体包含每一项的状态，便于排查。
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let database = if !state.db_connectivity.is_connected() {
+        state.db_connectivity.describe()
+    } else {
+        match db::with_statement_timeout(Some(READINESS_DB_TIMEOUT), state.db.ping()).await {
+            Ok(latency) => {
+                json!({ "ok": true, "state": "connected", "latency_ms": latency.as_millis() as u64 })
+            }
+            Err(e) => json!({ "ok": false, "state": "connected", "error": e.to_string() }),
+        }
+    };
+    let scheduler = scheduler_readiness(
+        &state.supervisor,
+        &state.heartbeat,
+        state.config.scheduler_stall_threshold,
+    );
+    let schema_ok = state
+        .schema_check
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .ok;
+    let draining = state.lifecycle.is_draining();
+    let components = json!({
+        "database": database,
+        "scheduler": scheduler,
+        "schema": { "ok": schema_ok },
+        "lifecycle": { "ok": !draining, "draining": draining },
+    });
+    let ready = components
+        .as_object()
+        .is_some_and(|c| c.values().all(|v| v["ok"] == true));
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "components": components,
+        })),
+    )
+}
+
+/// 调度器的就绪状态：`scheduler` 后台任务正在运行，且心跳在 `stall_threshold` 内更新过。
+fn scheduler_readiness(
+    supervisor: &Supervisor,
+    heartbeat: &Heartbeat,
+    stall_threshold: Duration,
+) -> Value {
+    let running = supervisor
+        .status()
+        .children
+        .iter()
+        .any(|child| child.name == "scheduler" && child.running);
+    let age = heartbeat.age();
+    json!({
+        "ok": running && age < stall_threshold,
+        "running": running,
+        "heartbeat_age_ms": age.as_millis() as u64,
+    })
 }
 
 /// 创建并配置 API 路由。
 pub fn api_router(app_state: AppState) -> Router {
-    Router::new()
+    let base_path = app_state.config.base_path.clone();
+    let request_body = app_state.config.request_body;
+    let probe_state = app_state.clone();
+    let router = Router::new()
         // 定义 `/tasks` 路由，仅接受 POST 请求，并由 `create_task` handler 处理
-        .route("/tasks", post(create_task))
+        // 提交接口接受 gzip/zstd 压缩的请求体，解压后的大小受 `REQUEST_BODY_LIMIT_BYTES` 约束
+        .route(
+            "/tasks",
+            post(create_task)
+                .route_layer(middleware::from_fn_with_state(
+                    request_body,
+                    decompress::decompress_request,
+                ))
+                .layer(DefaultBodyLimit::max(request_body.limit)),
+        )
+        .route(
+            "/tasks/validate",
+            post(validate_task)
+                .route_layer(middleware::from_fn_with_state(
+                    request_body,
+                    decompress::decompress_request,
+                ))
+                .layer(DefaultBodyLimit::max(request_body.limit)),
+        )
+        .route(
+            "/tasks/transaction",
+            post(create_transaction)
+                .route_layer(middleware::from_fn_with_state(
+                    request_body,
+                    decompress::decompress_request,
+                ))
+                .layer(DefaultBodyLimit::max(request_body.limit)),
+        )
+        .route("/task-types", get(list_task_types))
+        .route("/tasks/:id", get(get_task))
+        .route("/tasks/:id/position", get(task_position))
+        .route("/tasks/:id/result", get(task_result))
+        .route("/events", get(task_events))
+        .route("/stats/starving", get(starving_tasks))
+        .route("/stats/me", get(my_stats))
+        .route("/stats/slo", get(slo_stats))
+        // 限流在鉴权之后执行，按令牌限流时令牌已经校验过，伪造的令牌不能绕过限流
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            limit_requests,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            require_api_token,
+        ))
         // 将应用状态 `app_state` 注入到所有路由的 handler 中
-        .with_state(app_state)
+        .with_state(app_state);
+    // 部署在网关的路径之后时，所有路由（包括事件流）都挂在 `BASE_PATH` 下。
+    // 先挂载再添加公共中间件，日志中记录的是包含前缀的完整路径。
+    let router = if base_path.is_empty() {
+        router
+    } else {
+        Router::new().nest(&base_path, router)
+    };
+    // 探针由编排系统直接访问实例，不经过网关，始终挂在根路径下
+    let probes = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(probe_state);
+    with_common_layers(router.merge(probes))
+}
+
+/// 公开 API 的鉴权中间件。
+///
+/// 配置了 `API_AUTH_REQUIRED` 或 JWT 密钥时，请求必须携带 `Authorization: Bearer <token>`：
+/// `GET` 请求需要 `read` 权限（JWT 的 `tasks:read`），提交任务需要 `submit` 权限（JWT 的 `tasks:write`），
+/// `admin` 可以访问所有接口。以 `wsk_` 开头的按 API 令牌校验，配置了 JWT 密钥时其余的按 JWT 校验，
+/// JWT 的声明记录在请求的日志 span 中。都未配置时公开 API 不做鉴权。探针不经过该中间件。
+///
+/// 配置了 `POLICY_URL` 时，通过鉴权（或未启用鉴权）的请求还要交给外部策略服务决定是否放行，见 `authorize`。
+async fn require_api_token(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let subject = authenticate(&state, request.method(), request.headers()).await?;
+    let tenant = resolve_tenant(request.headers(), subject.as_ref())?;
+    let mut request = match &state.policy {
+        Some(policy) => authorize(&state, policy, subject, &tenant, request).await?,
+        None => request,
+    };
+    request.extensions_mut().insert(Tenant(tenant));
+    Ok(next.run(request).await)
+}
+
+/// 校验请求携带的凭据，返回调用方；未启用鉴权时返回 `None`。
+async fn authenticate(
+    state: &AppState,
+    method: &axum::http::Method,
+    headers: &HeaderMap,
+) -> Result<Option<Subject>, AppError> {
+    if !state.config.api_auth_required && state.jwt.is_none() {
+        return Ok(None);
+    }
+    let required = if method == axum::http::Method::GET {
+        Scope::Read
+    } else {
+        Scope::Submit
+    };
+    let Some(secret) = tokens::bearer_token(headers) else {
+        return Err(AppError::Unauthorized(Message::new("MISSING_API_TOKEN")));
+    };
+    match &state.jwt {
+        Some(jwt) if !secret.starts_with(TOKEN_PREFIX) => {
+            let claims = jwt.verify(secret, required)?;
+            jwt::record_claims(&claims);
+            tracing::debug!("JWT 校验通过");
+            Ok(Some(Subject::Jwt {
+                sub: claims.sub,
+                iss: claims.iss,
+                scope: claims.scope,
+                tenant: claims.tenant,
+            }))
+        }
+        _ => {
+            let token = state.api_tokens.authenticate(secret, required).await?;
+            tracing::debug!(token_id = %token.id, token_name = %token.name, "API 令牌校验通过");
+            Ok(Some(Subject::ApiToken {
+                id: token.id,
+                name: token.name,
+                scope: token.scope.as_str(),
+                tenant: token.tenant,
+            }))
+        }
+    }
+}
+
+/// 将请求交给外部策略服务决定是否放行，返回（可能已解压请求体的）请求。
+///
+/// 策略的输入包括请求方法、路径、匹配的路由、租户、调用方与请求涉及的任务：
+/// 带任务 ID 的路由取状态索引中该任务的租户、优先级与状态；
+/// 提交任务（`POST`）时读取请求体，取其中的任务类型与优先级，压缩的请求体先在这里解压。
+/// 请求中按 `PROPAGATE_HEADERS` 选出的请求头随决策请求一起发给策略服务。
+async fn authorize(
+    state: &AppState,
+    policy: &PolicyEngine,
+    subject: Option<Subject>,
+    tenant: &str,
+    request: Request,
+) -> Result<Request, AppError> {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or_else(|| request.uri().path(), |uri| uri.path())
+        .to_string();
+    let tenant = Some(tenant.to_string());
+    let context = state.config.header_propagation.capture(request.headers());
+    let (mut parts, body) = request.into_parts();
+    let mut task = RawPathParams::from_request_parts(&mut parts, &())
+        .await
+        .ok()
+        .and_then(|params| {
+            params
+                .iter()
+                .find(|(name, _)| *name == "id")
+                .and_then(|(_, id)| id.parse::<Uuid>().ok())
+        })
+        .map(|id| match state.tasks.get(&id) {
+            Some(record) => TaskMetadata::from_record(&record),
+            None => TaskMetadata {
+                id: Some(id),
+                ..Default::default()
+            },
+        });
+    let method = parts.method.clone();
+    let mut request = Request::from_parts(parts, body);
+    if method == axum::http::Method::POST {
+        let limit = state.config.request_body.limit;
+        let (parts, body) = decompress::decompress(state.config.request_body, request)
+            .await?
+            .into_parts();
+        let bytes = to_bytes(body, limit).await.map_err(|_| {
+            AppError::PayloadTooLarge(Message::new("REQUEST_BODY_TOO_LARGE").arg("limit", limit))
+        })?;
+        // 请求体不是合法的 JSON 时不提供任务元数据，由 handler 返回相应的错误
+        if let Ok(submission) = serde_json::from_slice::<Value>(&bytes) {
+            task = Some(TaskMetadata {
+                task_type: Some(classifier::payload_type(&submission["payload"]).to_string()),
+                priority: submission["priority"]
+                    .as_u64()
+                    .and_then(|p| u8::try_from(p).ok()),
+                tenant: tenant.clone(),
+                ..Default::default()
+            });
+        }
+        request = Request::from_parts(parts, Body::from(bytes));
+    }
+    let input = PolicyInput {
+        method: method.to_string(),
+        path,
+        route,
+        tenant,
+        subject,
+        task,
+    };
+    policy.authorize(&input, &context).await?;
+    Ok(request)
+}
+
+/// 公开 API 按客户端的限流中间件。
+///
+/// 启用鉴权时按请求携带的令牌（API 令牌或 JWT 的摘要）限流，否则按对端 IP 地址限流；
+/// 超过 `RATE_LIMIT_PER_SECOND` 与 `RATE_LIMIT_BURST` 时返回 429，`Retry-After` 为补充一个令牌所需的秒数。
+/// 经过反向代理时对端地址是代理的地址，此时应启用鉴权按令牌限流。
+async fn limit_requests(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if !state.rate_limiter.is_enabled() {
+        return Ok(next.run(request).await);
+    }
+    let auth_enabled = state.config.api_auth_required || state.jwt.is_some();
+    let (kind, key) = match tokens::bearer_token(request.headers()).filter(|_| auth_enabled) {
+        Some(secret) => ("token", tokens::hash_secret(secret)),
+        None => (
+            "ip",
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map_or_else(|| "unknown".to_string(), |info| info.0.ip().to_string()),
+        ),
+    };
+    if let Err(wait) = state.rate_limiter.check(&format!("{}:{}", kind, key)) {
+        metrics::counter_with_labels("rate_limited_total", &[("kind", kind)]).inc();
+        return Err(AppError::RateLimited(Message::new("RATE_LIMITED"), wait));
+    }
+    Ok(next.run(request).await)
+}
+
+/// 为路由添加所有监听器共用的中间件。
+pub(crate) fn with_common_layers(router: Router) -> Router {
+    // 注意：后添加的 layer 位于外层、先执行。
+    // 请求ID必须先生成，日志中间件才能读取到它。
+    router
+        // 启用 `RESPONSE_ENVELOPE` 时包装 JSON 响应，位于请求 ID 中间件之内以便读取请求 ID
+        .layer(middleware::from_fn(response::envelope))
+        // 按 Accept-Language 选择错误消息的语言
+        .layer(middleware::from_fn(i18n::negotiate_locale))
+        // 添加自定义中间件，用于将请求ID集成到日志中
+        .layer(middleware::from_fn(request_id_middleware))
         // 添加中间件层，用于生成和设置请求ID
         .layer(SetRequestIdLayer::new(
             header::HeaderName::from_static("x-request-id"),
-            MakeRequestUuid,
+            MakeRequestIdFromGenerator,
         ))
-        // 添加自定义中间件，用于将请求ID集成到日志中
-        .layer(middleware::from_fn(request_id_middleware))
+}
+
+tokio::task_local! {
+    /// 当前请求的请求ID，由 `request_id_middleware` 设置，错误响应据此回显。
+    static REQUEST_ID: String;
+}
+
+/// 返回当前请求的请求ID，不在请求上下文中（例如后台任务）时返回 `None`。
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID
+        .try_with(|id| id.clone())
+        .ok()
+        .filter(|id| !id.is_empty())
 }
 
 /// 自定义中间件，用于从请求头中提取请求ID并将其添加到日志的 span 中。
+///
+/// 同时汇总该请求内所有数据库查询的耗时，记录到 span 的 `db_time_ms` 字段，
+/// 便于找出受数据库拖累的接口。
 async fn request_id_middleware(request: Request, next: Next) -> Response {
     // 从请求头 "x-request-id" 中获取请求ID，如果不存在则生成一个
     let request_id = request
         .headers()
         .get("x-request-id")
         .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
+        .unwrap_or_default()
+        .to_string();
     // 创建一个新的日志 span，并附带请求ID
-    let span = tracing::info_span!("http_request", request_id = %request_id);
-    // 进入 span，后续的日志都将包含此 span 的信息
-    let _enter = span.enter();
-    // 调用下一个中间件或 handler
-    next.run(request).await
+    let span = tracing::info_span!(
+        "http_request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        db_time_ms = tracing::field::Empty,
+        // 通过 JWT 鉴权的请求由鉴权中间件填写
+        auth_sub = tracing::field::Empty,
+        auth_iss = tracing::field::Empty,
+        auth_scope = tracing::field::Empty,
+        auth_tenant = tracing::field::Empty,
+    );
+    // 在 span 中调用下一个中间件或 handler，后续的日志都将包含此 span 的信息
+    let (response, db_time) = REQUEST_ID
+        .scope(
+            request_id,
+            db::track_request_db_time(next.run(request)).instrument(span.clone()),
+        )
+        .await;
+    span.record("db_time_ms", db_time.as_millis() as u64);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试租户请求头的解析与校验，以及凭据绑定的租户优先于请求头。
+    #[test]
+    fn test_resolve_tenant() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(resolve_tenant(&headers, None).unwrap(), DEFAULT_TENANT);
+
+        headers.insert(TENANT_HEADER, "acme_01".parse().unwrap());
+        assert_eq!(resolve_tenant(&headers, None).unwrap(), "acme_01");
+
+        headers.insert(TENANT_HEADER, "acme corp".parse().unwrap());
+        assert!(resolve_tenant(&headers, None).is_err());
+
+        let subject = |tenant: Option<&str>| Subject::ApiToken {
+            id: Uuid::new_v4(),
+            name: "ci".to_string(),
+            scope: "submit",
+            tenant: tenant.map(str::to_string),
+        };
+        let bound = subject(Some("acme"));
+        assert_eq!(
+            resolve_tenant(&HeaderMap::new(), Some(&bound)).unwrap(),
+            "acme"
+        );
+        headers.insert(TENANT_HEADER, "acme".parse().unwrap());
+        assert_eq!(resolve_tenant(&headers, Some(&bound)).unwrap(), "acme");
+        // 与凭据绑定的租户不一致的请求头被拒绝，不能借此读取或占用其他租户的配额
+        headers.insert(TENANT_HEADER, "globex".parse().unwrap());
+        assert!(matches!(
+            resolve_tenant(&headers, Some(&bound)),
+            Err(AppError::Forbidden(_))
+        ));
+        assert_eq!(
+            resolve_tenant(&headers, Some(&subject(None))).unwrap(),
+            "globex"
+        );
+    }
+
+    /// 测试错误响应的状态码、错误码与请求ID回显。
+    #[tokio::test]
+    async fn test_error_response_echoes_request_id() {
+        let response = REQUEST_ID
+            .scope("req-1".to_string(), async {
+                AppError::Validation(Message::new("EMPTY_FILTER")).into_response()
+            })
+            .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "EMPTY_FILTER");
+        assert_eq!(body["request_id"], "req-1");
+
+        // 请求上下文之外没有请求ID可以回显
+        let response = AppError::RateLimited(Message::new("RATE_LIMITED"), Duration::from_secs(1))
+            .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert!(body.get("request_id").is_none());
+    }
+
+    /// 测试字段校验错误的响应体逐一列出出错的字段。
+    #[tokio::test]
+    async fn test_invalid_fields_response() {
+        let rules = validation::SubmissionRules {
+            max_priority: 100,
+            ..Default::default()
+        };
+        let mut errors: Vec<_> = rules.check_priority(200).into_iter().collect();
+        validation::validate_schema(
+            &json!({ "required": ["input"] }),
+            &json!({}),
+            "payload",
+            &mut errors,
+        );
+        let response = AppError::InvalidFields(errors).into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "INVALID_FIELDS");
+        assert_eq!(body["details"][0]["field"], "priority");
+        assert_eq!(body["details"][0]["code"], "PRIORITY_OUT_OF_RANGE");
+        assert_eq!(body["details"][0]["error"], "优先级必须在 0 到 100 之间");
+        assert_eq!(body["details"][1]["field"], "payload.input");
+        assert_eq!(body["details"][1]["code"], "SCHEMA_REQUIRED");
+    }
+
+    /// 测试 `If-None-Match` 的匹配规则。
+    #[test]
+    fn test_etag_matches() {
+        let etag = "\"abc-2\"";
+        assert!(etag_matches("\"abc-2\"", etag));
+        assert!(etag_matches("\"abc-1\", W/\"abc-2\"", etag));
+        assert!(etag_matches("*", etag));
+        assert!(!etag_matches("\"abc-1\"", etag));
+        assert!(!etag_matches("abc-2", etag));
+    }
+
+    /// 测试调度器的就绪状态：后台任务在运行且心跳未过期时才算就绪。
+    #[tokio::test(start_paused = true)]
+    async fn test_scheduler_readiness() {
+        let supervisor = Supervisor::new(5);
+        let heartbeat = Heartbeat::new();
+        let threshold = Duration::from_secs(30);
+        assert_eq!(
+            scheduler_readiness(&supervisor, &heartbeat, threshold)["ok"],
+            false
+        );
+
+        supervisor
+            .spawn("scheduler", std::future::pending::<()>)
+            .await;
+        tokio::task::yield_now().await;
+        let status = scheduler_readiness(&supervisor, &heartbeat, threshold);
+        assert_eq!(status["ok"], true);
+        assert_eq!(status["running"], true);
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert_eq!(
+            scheduler_readiness(&supervisor, &heartbeat, threshold)["ok"],
+            false
+        );
+        heartbeat.beat();
+        assert_eq!(
+            scheduler_readiness(&supervisor, &heartbeat, threshold)["ok"],
+            true
+        );
+        supervisor.shutdown().await;
+    }
+
+    /// 测试 `Location` 响应头包含路径前缀。
+    #[test]
+    fn test_task_location() {
+        let id = Uuid::nil();
+        assert_eq!(
+            task_location("", &id),
+            "/tasks/00000000-0000-0000-0000-000000000000"
+        );
+        assert_eq!(
+            task_location("/api/v1", &id),
+            "/api/v1/tasks/00000000-0000-0000-0000-000000000000"
+        );
+    }
+
+    /// 测试事务中引用名与依赖关系的检查：重复或未知的引用名、成环的依赖都会被拒绝。
+    #[test]
+    fn test_resolve_dependencies() {
+        let tasks = |items: &[(&str, &[&str])]| -> Vec<TransactionTaskPayload> {
+            items
+                .iter()
+                .map(|(reference, depends_on)| {
+                    serde_json::from_value(json!({
+                        "ref": reference,
+                        "payload": {},
+                        "depends_on": depends_on,
+                    }))
+                    .unwrap()
+                })
+                .collect()
+        };
+        let fields = |error: AppError| match error {
+            AppError::InvalidFields(errors) => errors
+                .into_iter()
+                .map(|e| (e.field, e.message.code().to_string()))
+                .collect::<Vec<_>>(),
+            other => panic!("unexpected error: {other}"),
+        };
+
+        let resolved = resolve_dependencies(&tasks(&[
+            ("a", &[]),
+            ("b", &["a", "a"]),
+            ("c", &["a", "b"]),
+        ]))
+        .unwrap();
+        assert_eq!(resolved, vec![vec![], vec![0], vec![0, 1]]);
+
+        let error = resolve_dependencies(&tasks(&[])).unwrap_err();
+        assert_eq!(
+            fields(error),
+            [("tasks".to_string(), "TRANSACTION_SIZE".to_string())]
+        );
+        let error = resolve_dependencies(&tasks(&[("a", &[]), ("a", &["x"])])).unwrap_err();
+        assert_eq!(
+            fields(error),
+            [
+                ("tasks[1].ref".to_string(), "DUPLICATE_TASK_REF".to_string()),
+                (
+                    "tasks[1].depends_on".to_string(),
+                    "UNKNOWN_TASK_REF".to_string()
+                ),
+            ]
+        );
+        let error =
+            resolve_dependencies(&tasks(&[("a", &[]), ("b", &["c"]), ("c", &["b"])])).unwrap_err();
+        assert_eq!(
+            fields(error),
+            [(
+                "tasks[1].depends_on".to_string(),
+                "DEPENDENCY_CYCLE".to_string()
+            )]
+        );
+        let error = resolve_dependencies(&tasks(&[("a", &["a"])])).unwrap_err();
+        assert_eq!(
+            fields(error),
+            [(
+                "tasks[0].depends_on".to_string(),
+                "DEPENDENCY_CYCLE".to_string()
+            )]
+        );
+    }
 }